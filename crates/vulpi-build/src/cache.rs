@@ -0,0 +1,75 @@
+//! The cache of the modules of a crate. The namespace, the interface and the typed program of each
+//! module are stored in a directory after the crate is type checked without diagnostics, keyed by
+//! a hash of the sources of the module and of the ones that it uses. When the crate is compiled
+//! again, the modules whose sources didn't change are loaded instead of resolved and type checked.
+
+use std::{
+    collections::hash_map::DefaultHasher,
+    fs,
+    hash::{Hash, Hasher},
+    io,
+    path::PathBuf,
+};
+
+use serde::{Deserialize, Serialize};
+use vulpi_intern::Symbol;
+use vulpi_location::FileId;
+use vulpi_syntax::elaborated;
+use vulpi_typer::{module::Interface, real::Real, Type};
+use vulpi_vfs::path::Path;
+
+/// The directory of the cache inside of the directory of a project.
+pub const DIRECTORY: &str = ".vulpi-cache";
+
+/// Everything that the compilation of a module produces and that the other modules use.
+#[derive(Serialize, Deserialize)]
+pub struct Artifact {
+    pub namespace: vulpi_resolver::Module,
+
+    /// The interfaces of the type checker by the paths that they're in. A module has the ones of
+    /// its types and effects too, because their constructors and operations are stored in them.
+    pub interfaces: Vec<(Symbol, Interface)>,
+
+    pub program: elaborated::Program<Type<Real>>,
+
+    /// The modules that it imports, so the ones of a module without sources can be found too.
+    pub imports: Vec<Path>,
+}
+
+pub struct Cache {
+    directory: PathBuf,
+}
+
+impl Cache {
+    pub fn new(directory: PathBuf) -> Self {
+        Self { directory }
+    }
+
+    /// The key of a module. It receives the paths and the sources of the module and of all the
+    /// modules that it uses, in any order.
+    pub fn key(mut sources: Vec<(String, String)>) -> u64 {
+        sources.sort();
+
+        let mut hasher = DefaultHasher::new();
+        env!("CARGO_PKG_VERSION").hash(&mut hasher);
+        sources.hash(&mut hasher);
+        hasher.finish()
+    }
+
+    fn path(&self, key: u64) -> PathBuf {
+        self.directory.join(format!("{:016x}.bin", key))
+    }
+
+    /// Loads the artifact of a module. The spans are moved to the file that the module has now.
+    pub fn load(&self, key: u64, file: FileId) -> Option<Artifact> {
+        let bytes = fs::read(self.path(key)).ok()?;
+        vulpi_location::with_file(file, || bincode::deserialize(&bytes).ok())
+    }
+
+    pub fn store(&self, key: u64, artifact: &Artifact) -> io::Result<()> {
+        let bytes = bincode::serialize(artifact).map_err(io::Error::other)?;
+
+        fs::create_dir_all(&self.directory)?;
+        fs::write(self.path(key), bytes)
+    }
+}
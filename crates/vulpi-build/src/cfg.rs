@@ -0,0 +1,166 @@
+//! Conditional compilation. The `when` declarations are replaced by the declarations of the arms
+//! whose values hold for the compilation after a module is parsed, so the declarations of the
+//! other arms are never resolved. It's used to give a different implementation of an external to
+//! each backend:
+//!
+//! ```vulpi
+//! when target is
+//!   "js" where
+//!     pub external print : forall a. a -> Unit = "console.log"
+//!   "native" | "vm" where
+//!     pub external print : forall a. a -> Unit = "print"
+//! ```
+
+use vulpi_report::{Diagnostic, Report};
+use vulpi_syntax::concrete::top_level::{Program, TopLevel, WhenDecl};
+
+use crate::error::{BuildError, BuildErrorKind};
+
+/// The backend that the crate is compiled to.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub enum Target {
+    #[default]
+    Js,
+    Native,
+    Vm,
+}
+
+impl Target {
+    pub const ALL: [Target; 3] = [Target::Js, Target::Native, Target::Vm];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Target::Js => "js",
+            Target::Native => "native",
+            Target::Vm => "vm",
+        }
+    }
+}
+
+/// Removes the declarations whose conditions don't hold for the target.
+pub fn filter(reporter: &Report, target: Target, program: Program) -> Program {
+    Program {
+        top_levels: top_levels(reporter, target, program.top_levels),
+        eof: program.eof,
+    }
+}
+
+fn top_levels(reporter: &Report, target: Target, declarations: Vec<TopLevel>) -> Vec<TopLevel> {
+    let mut result = Vec::new();
+
+    for top_level in declarations {
+        match top_level {
+            TopLevel::When(decl) => {
+                for declarations in arms(reporter, target, *decl) {
+                    result.extend(top_levels(reporter, target, declarations));
+                }
+            }
+            TopLevel::Module(mut decl) => {
+                if let Some(part) = &mut decl.part {
+                    let declarations = std::mem::take(&mut part.top_levels);
+                    part.top_levels = top_levels(reporter, target, declarations);
+                }
+
+                result.push(TopLevel::Module(decl));
+            }
+            top_level => result.push(top_level),
+        }
+    }
+
+    result
+}
+
+/// The declarations of the arms that hold.
+fn arms(reporter: &Report, target: Target, decl: WhenDecl) -> Vec<Vec<TopLevel>> {
+    let condition = decl.condition.symbol();
+
+    if condition.get() != "target" {
+        reporter.report(Diagnostic::new(BuildError {
+            span: decl.condition.0.value.span.clone(),
+            kind: BuildErrorKind::UnknownCondition(condition),
+        }));
+
+        return vec![];
+    }
+
+    let mut result = Vec::new();
+
+    for arm in decl.arms {
+        let mut holds = false;
+
+        for (value, _) in &arm.values {
+            let name = value.symbol();
+
+            if !Target::ALL.iter().any(|x| x.name() == name.get()) {
+                reporter.report(Diagnostic::new(BuildError {
+                    span: value.value.span.clone(),
+                    kind: BuildErrorKind::UnknownTarget(name),
+                }));
+            } else if name.get() == target.name() {
+                holds = true;
+            }
+        }
+
+        if holds {
+            result.push(arm.top_levels);
+        }
+    }
+
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use vulpi_location::FileId;
+
+    const SOURCE: &str = "when target is
+  \"js\" where
+    let backend : String = \"js\"
+  \"vm\" | \"native\" where
+    let backend : String = \"machine\"
+    let fast : Int = 1
+
+when target is
+  \"wasm\" where
+    let other : Int = 2
+
+when platform is
+  \"linux\" where
+    let linux : Int = 3
+
+let main (x : ()) : () = ()
+";
+
+    /// The names of the let declarations that are kept for the target, with the codes of the
+    /// diagnostics.
+    fn kept(target: Target) -> (Vec<String>, Vec<Option<usize>>) {
+        let reporter = vulpi_report::hash_reporter();
+        let program = vulpi_parser::parse_with_tab_width(reporter.clone(), FileId(0), SOURCE, 2);
+        let program = filter(&reporter, target, program);
+
+        let names = program
+            .top_levels
+            .iter()
+            .filter_map(|x| match x {
+                TopLevel::Let(decl) => Some(decl.signature.name.symbol().get()),
+                _ => None,
+            })
+            .collect();
+
+        let diagnostics = reporter.all_diagnostics();
+        (names, diagnostics.iter().map(|x| x.code()).collect())
+    }
+
+    #[test]
+    fn keeps_the_declarations_of_the_arms_of_the_target() {
+        let (names, mut codes) = kept(Target::Js);
+        codes.sort();
+
+        assert_eq!(names, vec!["backend", "main"]);
+        assert_eq!(codes, vec![Some(703), Some(704)]);
+
+        assert_eq!(kept(Target::Vm).0, vec!["backend", "fast", "main"]);
+        assert_eq!(kept(Target::Native).0, vec!["backend", "fast", "main"]);
+    }
+}
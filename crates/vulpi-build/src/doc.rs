@@ -0,0 +1,885 @@
+//! Documentation of a crate as static HTML, with a page for each module and for each module that
+//! is declared inside of one. The pages show the public declarations with the comments that start
+//! with `---` in the lines right before them, and the names of the types link to their
+//! declarations.
+//!
+//! The types of the values are the ones that the type checker gives them. The type checker doesn't
+//! track effects, so the effects of a value are the ones of the operations and of the externals
+//! that its body can reach through other declarations, without the ones that it handles.
+
+use std::{
+    cell::RefCell,
+    collections::{BTreeMap, BTreeSet, HashMap, HashSet},
+    path::PathBuf,
+    rc::Rc,
+};
+
+use vulpi_intern::Symbol;
+use vulpi_location::Span;
+use vulpi_resolver::Module;
+use vulpi_syntax::{
+    elaborated::{self, ExprKind, Handler, PatternKind, SttmKind},
+    r#abstract::{
+        self, Constructor, EffectDecl, KindType, LetBinder, LetSignature, OperationKind, Program,
+        Qualified, TypeBinder, TypeDecl, TypeDef, TypeKind, Visibility,
+    },
+    tokens::{Token, TokenData},
+};
+use vulpi_typer::{real::Real, Env, Type};
+use vulpi_vfs::{path::Path, FileSystem};
+
+use crate::query::Database;
+
+const STYLE: &str = "body { font-family: sans-serif; max-width: 60em; margin: auto; padding: 1em }
+pre { background: #f4f4f4; padding: 0.5em; white-space: pre-wrap }
+a { color: #2a5db0; text-decoration: none }
+.item { margin-bottom: 1.5em }";
+
+/// The elaborated program of a module with the other trees that describe it.
+struct Source {
+    path: Path,
+    program: Program,
+    typed: Rc<elaborated::Program<Type<Real>>>,
+    namespace: Module,
+    tokens: Rc<Vec<Token>>,
+}
+
+/// The pages of the documentation of a crate by their file names, with an `index.html` that links
+/// to all of them.
+pub fn generate<FS: FileSystem<Path = PathBuf>>(db: &mut Database<FS>) -> BTreeMap<String, String> {
+    let mut sources = Vec::new();
+
+    for path in db.modules() {
+        let resolved = db.resolved(&path);
+        let file = db.file(&path);
+
+        let program = resolved
+            .programs
+            .iter()
+            .find(|(module, _)| *module == path)
+            .map(|(_, program)| program.clone())
+            .unwrap_or_default();
+
+        sources.push(Source {
+            program,
+            typed: db.typed(&path),
+            namespace: resolved.module.clone(),
+            tokens: db.tokens(&file),
+            path,
+        });
+    }
+
+    let documenter = Documenter::new(&sources);
+    let mut pages = BTreeMap::new();
+
+    for source in &sources {
+        let name = source.path.to_string();
+        documenter.pages(
+            source,
+            &name,
+            &source.program,
+            &source.namespace,
+            &mut pages,
+        );
+    }
+
+    let links: Vec<_> = pages
+        .keys()
+        .map(|name| format!("<li><a href=\"{0}.html\">{0}</a></li>", escape(name)))
+        .collect();
+
+    let index = format!(
+        "<h1>{}</h1>\n<ul>\n{}\n</ul>",
+        escape(&db.name.get()),
+        links.join("\n")
+    );
+    let mut files = BTreeMap::new();
+
+    files.insert("index.html".to_string(), page(&db.name.get(), &index));
+
+    for (name, body) in pages {
+        files.insert(format!("{}.html", name), page(&name, &body));
+    }
+
+    files
+}
+
+fn page(title: &str, body: &str) -> String {
+    format!(
+        "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>{}</title>\n<style>\n{}\n</style>\n</head>\n<body>\n<nav><a href=\"index.html\">Index</a></nav>\n{}\n</body>\n</html>\n",
+        escape(title),
+        STYLE,
+        body
+    )
+}
+
+struct Documenter {
+    /// The public types and effects, that have an anchor that their names link to.
+    documented: Rc<HashSet<Qualified>>,
+
+    effects: HashMap<Qualified, BTreeSet<Qualified>>,
+}
+
+impl Documenter {
+    fn new(sources: &[Source]) -> Self {
+        let mut documented = HashSet::new();
+
+        for source in sources {
+            public_types(&source.program, &mut documented);
+        }
+
+        Self {
+            documented: Rc::new(documented),
+            effects: effects(sources),
+        }
+    }
+
+    /// Adds the page of a module and the pages of the public modules that it declares.
+    fn pages(
+        &self,
+        source: &Source,
+        name: &str,
+        program: &Program,
+        namespace: &Module,
+        pages: &mut BTreeMap<String, String>,
+    ) {
+        let declared = namespace.declared();
+        let doc =
+            |keyword: Option<Span>| keyword.map(|x| doc(&source.tokens, &x)).unwrap_or_default();
+
+        let mut types = Vec::new();
+
+        for decl in program.types.iter().filter(|x| is_public(&x.visibility)) {
+            let keyword = declared
+                .types
+                .get(&decl.name.name)
+                .map(|x| x.keyword.clone());
+            types.push(item(&decl.name.name, self.type_decl(decl), doc(keyword)));
+        }
+
+        let mut effects = Vec::new();
+
+        for decl in program.effects.iter().filter(|x| is_public(&x.visibility)) {
+            let keyword = declared
+                .types
+                .get(&decl.name.name)
+                .map(|x| x.keyword.clone());
+            effects.push(item(&decl.name.name, self.effect_decl(decl), doc(keyword)));
+        }
+
+        let mut values = Vec::new();
+
+        for decl in &program.lets {
+            let signature = &decl.signature;
+
+            if !is_public(&signature.visibility) {
+                continue;
+            }
+
+            // The lets of the modules that are declared inside of a file are not type checked, so
+            // their types are the ones that they are annotated with.
+            let typ = match source.typed.lets.get(&signature.name) {
+                Some(decl) => self.typ(&decl.typ),
+                None => self.signature(signature),
+            };
+
+            let effects = self
+                .effects
+                .get(&signature.name)
+                .cloned()
+                .unwrap_or_default();
+
+            let code = format!(
+                "let <b>{}</b> : {}{}",
+                escape(&signature.name.name.get()),
+                typ,
+                self.effect_list(effects.iter())
+            );
+
+            let keyword = declared
+                .values
+                .get(&signature.name.name)
+                .map(|x| x.keyword.clone());
+            values.push(item(&signature.name.name, code, doc(keyword)));
+        }
+
+        for decl in program
+            .externals
+            .iter()
+            .filter(|x| is_public(&x.visibility))
+        {
+            let code = format!(
+                "external <b>{}</b> : {}{}",
+                escape(&decl.name.name.get()),
+                self.abstract_type(&decl.typ, Prec::Open),
+                self.effect_list(decl.effect.iter())
+            );
+
+            let keyword = declared
+                .values
+                .get(&decl.name.name)
+                .map(|x| x.keyword.clone());
+            values.push(item(&decl.name.name, code, doc(keyword)));
+        }
+
+        let mut modules = Vec::new();
+
+        for decl in program.modules.iter().filter(|x| is_public(&x.visibility)) {
+            let (Some(decls), Some(submodule)) =
+                (&decl.decls, namespace.submodules().get(&decl.name).cloned())
+            else {
+                continue;
+            };
+
+            let path = format!("{}.{}", name, decl.name.get());
+            let code = format!(
+                "mod <a href=\"{0}.html\">{1}</a>",
+                escape(&path),
+                escape(&decl.name.get())
+            );
+
+            modules.push(item(&decl.name, code, String::new()));
+            self.pages(source, &path, decls, &submodule, pages);
+        }
+
+        let mut body = format!("<h1>{}</h1>\n", escape(name));
+
+        for (title, items) in [
+            ("Types", types),
+            ("Effects", effects),
+            ("Values", values),
+            ("Modules", modules),
+        ] {
+            if !items.is_empty() {
+                body.push_str(&format!("<h2>{}</h2>\n{}\n", title, items.join("\n")));
+            }
+        }
+
+        pages.insert(name.to_string(), body);
+    }
+
+    fn type_decl(&self, decl: &TypeDecl) -> String {
+        let mut code = format!(
+            "type <b>{}</b>{}",
+            escape(&decl.name.name.get()),
+            type_binders(&decl.binders)
+        );
+
+        match &decl.def {
+            // The definition of an opaque type is not a part of the interface of its module.
+            _ if decl.attributes.opaque => (),
+            TypeDef::Sum(sum) => {
+                code.push_str(" =");
+
+                for constructor in &sum.constructors {
+                    code.push_str(&format!("\n  | {}", self.constructor(constructor)));
+                }
+            }
+            TypeDef::Record(record) => {
+                let fields: Vec<_> = record
+                    .fields
+                    .iter()
+                    .filter(|(_, _, visibility)| is_public(visibility))
+                    .map(|(name, typ, _)| {
+                        format!(
+                            "\n  {} : {}",
+                            escape(&name.name.get()),
+                            self.abstract_type(typ, Prec::Open)
+                        )
+                    })
+                    .collect();
+
+                code.push_str(&format!(" = {{{}\n}}", fields.join(",")));
+            }
+            TypeDef::Synonym(typ) => {
+                code.push_str(&format!(" = {}", self.abstract_type(typ, Prec::Open)));
+            }
+            TypeDef::Abstract => (),
+        }
+
+        code
+    }
+
+    fn constructor(&self, constructor: &Constructor) -> String {
+        let mut code = escape(&constructor.name.name.get());
+
+        for arg in &constructor.args {
+            code.push(' ');
+            code.push_str(&self.abstract_type(arg, Prec::Atom));
+        }
+
+        if let Some(typ) = &constructor.typ {
+            code.push_str(&format!(" : {}", self.abstract_type(typ, Prec::Open)));
+        }
+
+        code
+    }
+
+    fn effect_decl(&self, decl: &EffectDecl) -> String {
+        let mut code = format!(
+            "effect <b>{}</b>{} where",
+            escape(&decl.name.name.get()),
+            type_binders(&decl.binders)
+        );
+
+        for field in decl.fields.iter().filter(|x| is_public(&x.visibility)) {
+            let kind = match field.kind {
+                OperationKind::Ctl => "ctl",
+                OperationKind::Fun => "fun",
+            };
+
+            let mut code_field = format!("\n  {} {}", kind, escape(&field.name.name.get()));
+
+            for arg in &field.args {
+                code_field.push(' ');
+                code_field.push_str(&self.abstract_type(arg, Prec::Atom));
+            }
+
+            code_field.push_str(&format!(
+                " : {}",
+                self.abstract_type(&field.ret, Prec::Open)
+            ));
+            code.push_str(&code_field);
+        }
+
+        code
+    }
+
+    /// A type of the type checker with the names of the documented types linked. The names are
+    /// shown as their indices between null characters first, so the rest of the text is escaped
+    /// without the links.
+    fn typ(&self, typ: &Type<Real>) -> String {
+        let names = Rc::new(RefCell::new(Vec::new()));
+        let shown = names.clone();
+
+        let show = typ.show_with(&Env::default(), move |name| {
+            let mut names = shown.borrow_mut();
+            names.push(name.clone());
+            format!("\0{}\0", names.len() - 1)
+        });
+
+        let text = escape(&show.to_string());
+        let names = names.borrow();
+
+        text.split('\0')
+            .enumerate()
+            .map(|(i, part)| match i % 2 {
+                0 => part.to_string(),
+                _ => link(&self.documented, &names[part.parse::<usize>().unwrap()]),
+            })
+            .collect()
+    }
+
+    /// The type that a let declaration is annotated with, as the types of its binders before the
+    /// type that it returns.
+    fn signature(&self, signature: &LetSignature) -> String {
+        let Some(ret) = &signature.ret else {
+            return "?".to_string();
+        };
+
+        let mut code = String::new();
+
+        for binder in &signature.binders {
+            match binder {
+                LetBinder::Param(binder) => {
+                    code.push_str(&self.abstract_type(&binder.typ, Prec::Application));
+                    code.push_str(" -&gt; ");
+                }
+                LetBinder::Trait(typ) => {
+                    code.push_str(&self.abstract_type(typ, Prec::Application));
+                    code.push_str(" =&gt; ");
+                }
+            }
+        }
+
+        code.push_str(&self.abstract_type(ret, Prec::Binary));
+        code
+    }
+
+    /// A type of the abstract tree as it's written in the source, with the names of the documented
+    /// types linked.
+    fn abstract_type(&self, typ: &r#abstract::Type, prec: Prec) -> String {
+        let code = match &typ.data {
+            TypeKind::Arrow(pi) => format!(
+                "{} -&gt; {}",
+                self.abstract_type(&pi.left, Prec::Application),
+                self.abstract_type(&pi.right, Prec::Binary)
+            ),
+            TypeKind::Tuple(types) => {
+                let types: Vec<_> = types
+                    .iter()
+                    .map(|x| self.abstract_type(x, Prec::Open))
+                    .collect();
+
+                return format!("({})", types.join(", "));
+            }
+            TypeKind::Application(app) => {
+                let mut code = self.abstract_type(&app.func, Prec::Application);
+
+                for arg in &app.args {
+                    code.push(' ');
+                    code.push_str(&self.abstract_type(arg, Prec::Atom));
+                }
+
+                code
+            }
+            TypeKind::Forall(forall) => format!(
+                "forall{}. {}",
+                type_binders(&forall.params),
+                self.abstract_type(&forall.body, Prec::Open)
+            ),
+            TypeKind::TypeVariable(name) => return escape(&name.get()),
+            TypeKind::Type(name) => return link(&self.documented, name),
+            TypeKind::Unit => return "()".to_string(),
+            TypeKind::Error => return "?".to_string(),
+        };
+
+        if type_prec(typ) < prec {
+            format!("({})", code)
+        } else {
+            code
+        }
+    }
+
+    fn effect_list<'a>(&self, effects: impl Iterator<Item = &'a Qualified>) -> String {
+        let mut effects: Vec<_> = effects.collect();
+        effects.sort_by_key(|x| x.to_string());
+
+        if effects.is_empty() {
+            return String::new();
+        }
+
+        let effects: Vec<_> = effects.iter().map(|x| link(&self.documented, x)).collect();
+        format!(" / {}", effects.join(", "))
+    }
+}
+
+/// How tightly a type is bound. A type in a place that binds tighter than it is put inside of
+/// parenthesis.
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum Prec {
+    Open,
+    Binary,
+    Application,
+    Atom,
+}
+
+fn type_prec(typ: &r#abstract::Type) -> Prec {
+    match &typ.data {
+        TypeKind::Forall(_) => Prec::Open,
+        TypeKind::Arrow(_) => Prec::Binary,
+        TypeKind::Application(_) => Prec::Application,
+        _ => Prec::Atom,
+    }
+}
+
+fn type_binders(binders: &[TypeBinder]) -> String {
+    binders
+        .iter()
+        .map(|binder| match binder {
+            TypeBinder::Implicit(name) => format!(" {}", escape(&name.get())),
+            TypeBinder::Explicit(name, kind) => {
+                format!(" ({} : {})", escape(&name.get()), abstract_kind(kind))
+            }
+        })
+        .collect()
+}
+
+fn abstract_kind(kind: &r#abstract::Kind) -> String {
+    match &kind.data {
+        KindType::Star => "*".to_string(),
+        KindType::Constraint => "Constraint".to_string(),
+        KindType::Arrow(left, right) => match left.data {
+            KindType::Arrow(..) => {
+                format!("({}) -&gt; {}", abstract_kind(left), abstract_kind(right))
+            }
+            _ => format!("{} -&gt; {}", abstract_kind(left), abstract_kind(right)),
+        },
+        KindType::Error => "?".to_string(),
+    }
+}
+
+fn item(name: &Symbol, code: String, doc: String) -> String {
+    format!(
+        "<div class=\"item\" id=\"{}\">\n<pre>{}</pre>\n{}</div>",
+        escape(&name.get()),
+        code,
+        doc
+    )
+}
+
+/// The name of a type that links to its declaration if it's documented.
+fn link(documented: &HashSet<Qualified>, name: &Qualified) -> String {
+    if documented.contains(name) {
+        format!(
+            "<a href=\"{}.html#{}\">{}</a>",
+            escape(&name.path.get()),
+            escape(&name.name.get()),
+            escape(&name.name.get())
+        )
+    } else {
+        escape(&name.name.get())
+    }
+}
+
+fn is_public(visibility: &Visibility) -> bool {
+    matches!(visibility, Visibility::Public)
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+fn public_types(program: &Program, documented: &mut HashSet<Qualified>) {
+    let types = program.types.iter().map(|x| (&x.visibility, &x.name));
+    let effects = program.effects.iter().map(|x| (&x.visibility, &x.name));
+
+    for (visibility, name) in types.chain(effects) {
+        if is_public(visibility) {
+            documented.insert(name.clone());
+        }
+    }
+
+    for module in program.modules.iter().filter(|x| is_public(&x.visibility)) {
+        if let Some(decls) = &module.decls {
+            public_types(decls, documented);
+        }
+    }
+}
+
+/// The documentation of a declaration as paragraphs: the comments that start with `---` in the
+/// lines right before the first token of the declaration, that is its visibility or its keyword.
+fn doc(tokens: &[Token], keyword: &Span) -> String {
+    let Some(mut index) = tokens
+        .iter()
+        .position(|x| x.value.span.start == keyword.start)
+    else {
+        return String::new();
+    };
+
+    let lines_before = |whitespace: &Symbol| whitespace.get().matches('\n').count();
+
+    // The comments are in the token that comes after them, that can be a virtual token of the
+    // layout that ends the declaration before.
+    while index > 0
+        && tokens[index].comments.is_empty()
+        && lines_before(&tokens[index].whitespace.data) == 0
+        && matches!(
+            tokens[index - 1].kind,
+            TokenData::Pub | TokenData::Sep | TokenData::End
+        )
+    {
+        index -= 1;
+    }
+
+    let token = &tokens[index];
+
+    // A blank line between the comments and the declaration separates them.
+    if lines_before(&token.whitespace.data) > 1 {
+        return String::new();
+    }
+
+    let mut lines = Vec::new();
+
+    for comment in token.comments.iter().rev() {
+        let text = comment.comment.data.get();
+
+        let Some(line) = text.strip_prefix("---") else {
+            break;
+        };
+
+        lines.push(line.strip_prefix(' ').unwrap_or(line).to_string());
+
+        if lines_before(&comment.whitespace.data) > 1 {
+            break;
+        }
+    }
+
+    lines.reverse();
+
+    let paragraphs: Vec<_> = lines
+        .split(|line| line.trim().is_empty())
+        .filter(|lines| !lines.is_empty())
+        .map(|lines| format!("<p>{}</p>\n", escape(&lines.join("\n"))))
+        .collect();
+
+    paragraphs.concat()
+}
+
+/// The effects of the let declarations of the crate. They're computed again until none of them
+/// changes, because a declaration has the effects of the ones that it uses.
+fn effects(sources: &[Source]) -> HashMap<Qualified, BTreeSet<Qualified>> {
+    let mut effects: HashMap<Qualified, BTreeSet<Qualified>> = HashMap::new();
+    let mut operations = HashMap::new();
+
+    for source in sources {
+        for (name, decl) in &source.typed.types {
+            if let elaborated::TypeDecl::Effect(fields) = decl {
+                for (operation, _, _, _) in fields {
+                    operations.insert(operation.clone(), name.clone());
+                }
+            }
+        }
+
+        for external in &source.program.externals {
+            if let Some(effect) = &external.effect {
+                effects.insert(external.name.clone(), BTreeSet::from([effect.clone()]));
+            }
+        }
+    }
+
+    let mut walker = Effects {
+        operations,
+        effects,
+    };
+
+    loop {
+        let mut changed = false;
+
+        for source in sources {
+            for (name, decl) in &source.typed.lets {
+                let mut found = BTreeSet::new();
+
+                for arm in &decl.body {
+                    walker.arm(arm, &mut found);
+                }
+
+                if walker.effects.get(name) != Some(&found) {
+                    walker.effects.insert(name.clone(), found);
+                    changed = true;
+                }
+            }
+        }
+
+        if !changed {
+            return walker.effects;
+        }
+    }
+}
+
+struct Effects {
+    /// The effect of each operation.
+    operations: HashMap<Qualified, Qualified>,
+
+    /// The effects of the declarations that were found until now.
+    effects: HashMap<Qualified, BTreeSet<Qualified>>,
+}
+
+impl Effects {
+    fn arm(&self, arm: &elaborated::PatternArm<Type<Real>>, found: &mut BTreeSet<Qualified>) {
+        self.expr(&arm.expr, found);
+
+        if let Some(guard) = &arm.guard {
+            self.expr(guard, found);
+        }
+    }
+
+    fn expr(&self, expr: &elaborated::Expr<Type<Real>>, found: &mut BTreeSet<Qualified>) {
+        match &*expr.data {
+            ExprKind::Function(name, _) => match self.operations.get(name) {
+                Some(effect) => {
+                    found.insert(effect.clone());
+                }
+                None => found.extend(self.effects.get(name).into_iter().flatten().cloned()),
+            },
+            ExprKind::Lambda(lambda) => self.expr(&lambda.body, found),
+            ExprKind::Application(app) => {
+                self.expr(&app.func, found);
+                self.expr(&app.args, found);
+            }
+            ExprKind::Projection(projection) => self.expr(&projection.expr, found),
+            ExprKind::Let(let_expr) => {
+                self.expr(&let_expr.body, found);
+                self.expr(&let_expr.next, found);
+            }
+            ExprKind::LetRec(let_rec) => {
+                for (_, body) in &let_rec.bindings {
+                    self.expr(body, found);
+                }
+
+                self.expr(&let_rec.next, found);
+            }
+            ExprKind::When(when) => {
+                for scrutinee in &when.scrutinee {
+                    self.expr(scrutinee, found);
+                }
+
+                for arm in &when.arms {
+                    self.arm(arm, found);
+                }
+            }
+            ExprKind::Handler(handler) => {
+                let mut handled = BTreeSet::new();
+                self.expr(&handler.expr, &mut handled);
+
+                for effect in self.handled(&handler.handler) {
+                    handled.remove(&effect);
+                }
+
+                found.extend(handled);
+
+                match &handler.handler {
+                    Handler::Cases(arms) => {
+                        for arm in arms {
+                            self.arm(arm, found);
+                        }
+                    }
+                    Handler::Function(function) => self.expr(function, found),
+                }
+
+                if let Some(finally) = &handler.finally {
+                    self.expr(finally, found);
+                }
+            }
+            ExprKind::Mask(mask) => self.expr(&mask.expr, found),
+            ExprKind::Do(block) => {
+                for statement in block {
+                    match statement {
+                        SttmKind::Let(statement) => self.expr(&statement.expr, found),
+                        SttmKind::Expr(expr) => self.expr(expr, found),
+                        SttmKind::Error => (),
+                    }
+                }
+            }
+            ExprKind::RecordInstance(instance) => {
+                for (_, expr) in &instance.fields {
+                    self.expr(expr, found);
+                }
+            }
+            ExprKind::RecordUpdate(update) => {
+                self.expr(&update.expr, found);
+
+                for (_, expr) in &update.fields {
+                    self.expr(expr, found);
+                }
+            }
+            ExprKind::Tuple(tuple) => {
+                for expr in &tuple.exprs {
+                    self.expr(expr, found);
+                }
+            }
+            // Operations of a named handler are handled by it.
+            ExprKind::Operation(..)
+            | ExprKind::Variable(_)
+            | ExprKind::Constructor(..)
+            | ExprKind::Literal(_)
+            | ExprKind::Error => (),
+        }
+    }
+
+    /// The effects that a handler handles: the ones of the operations that its cases match, or
+    /// the effect of the requests that its function receives.
+    fn handled(&self, handler: &Handler<Type<Real>>) -> Vec<Qualified> {
+        match handler {
+            Handler::Cases(arms) => arms
+                .iter()
+                .flat_map(|arm| &arm.patterns)
+                .filter_map(|pattern| match &**pattern {
+                    PatternKind::Effect(effect) => self.operations.get(&effect.func).cloned(),
+                    _ => None,
+                })
+                .collect(),
+            Handler::Function(function) => {
+                let ExprKind::Function(_, typ) = &*function.data else {
+                    return Vec::new();
+                };
+
+                // The function receives a `Request (Effect args) a`.
+                let request = typ.force(0).arrow_spine().remove(0);
+                let effect = spine(&request).get(1).and_then(head);
+                effect.into_iter().collect()
+            }
+        }
+    }
+}
+
+/// The type that is applied and its arguments, after it.
+fn spine(typ: &Type<Real>) -> Vec<Type<Real>> {
+    let mut spine = Vec::new();
+    let mut current = typ.force(0);
+
+    while let vulpi_typer::TypeKind::Application(left, right) = current.as_ref() {
+        spine.push(right.force(0));
+        current = left.force(0);
+    }
+
+    spine.push(current);
+    spine.reverse();
+    spine
+}
+
+/// The name of the type that is applied in a type.
+fn head(typ: &Type<Real>) -> Option<Qualified> {
+    match spine(typ)[0].as_ref() {
+        vulpi_typer::TypeKind::Variable(name) => Some(name.clone()),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{cfg::Target, memory::MemoryFileSystem};
+
+    const MAIN: &str = "pub type Int
+
+--- Writes a line.
+pub effect Log where
+  pub log Int : ()
+
+-- Not documentation.
+
+--- The same value.
+---
+--- It does nothing else.
+pub let noisy (x : Int) : Int = do
+  Log.log x
+  x
+
+pub let quiet (x : Int) : Int =
+  handle noisy x with
+    cases
+      { Log.log y -> k } => k ()
+      other => other
+
+let private : Int -> Int = quiet
+";
+
+    #[test]
+    fn documents_the_public_declarations() {
+        let name = Symbol::intern("Proj");
+
+        let mut fs = MemoryFileSystem::new(name.clone());
+        fs.insert(PathBuf::from("Main.vp"), MAIN.to_string());
+
+        let mut db = Database::new(fs, name, PathBuf::from("Main.vp"), Target::Js);
+        assert!(db.diagnostics().is_empty());
+
+        let pages = generate(&mut db);
+        assert_eq!(
+            pages.keys().collect::<Vec<_>>(),
+            ["Proj.Main.html", "index.html"]
+        );
+
+        let main = &pages["Proj.Main.html"];
+
+        assert!(main.contains("<p>Writes a line.</p>"));
+        assert!(main.contains("<p>The same value.</p>\n<p>It does nothing else.</p>"));
+        assert!(!main.contains("Not documentation"));
+        assert!(!main.contains("private"));
+
+        // The effect of `noisy` is handled by `quiet`.
+        let int = "<a href=\"Proj.Main.html#Int\">Int</a>";
+        let log = "<a href=\"Proj.Main.html#Log\">Log</a>";
+
+        assert!(main.contains(&format!("ctl log {} : ()", int)));
+        assert!(main.contains(&format!(
+            "let <b>noisy</b> : {0} -&gt; {0} / {1}",
+            int, log
+        )));
+        assert!(main.contains(&format!("let <b>quiet</b> : {0} -&gt; {0}</pre>", int)));
+    }
+}
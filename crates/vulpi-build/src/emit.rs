@@ -0,0 +1,114 @@
+//! The intermediate representations that can be printed while a crate is compiled. Each stage is
+//! printed to the standard output as a tree, so the stages of two compilations can be compared to
+//! find where they differ.
+
+use vulpi_lexer::Lexer;
+use vulpi_location::FileId;
+use vulpi_pretty::{Pretty, WIDTH};
+use vulpi_show::Show;
+use vulpi_syntax::{
+    r#abstract::Program,
+    tokens::{Token, TokenData},
+};
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Stage {
+    /// The tokens of each module.
+    Tokens,
+
+    /// The concrete tree of each module, without the declarations of the conditions that don't
+    /// hold.
+    Cst,
+
+    /// The abstract tree of each module, with the names resolved.
+    Ast,
+
+    /// The namespace of each module: the names that it declares, imports and opens.
+    Resolved,
+
+    /// The elaborated program of each module, with the types inferred.
+    Typed,
+
+    /// The core language of the crate after it's optimized.
+    Core,
+
+    /// The bytecode of the crate. It's only produced when the crate runs in the virtual machine.
+    Bytecode,
+}
+
+impl Stage {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Stage::Tokens => "tokens",
+            Stage::Cst => "cst",
+            Stage::Ast => "ast",
+            Stage::Resolved => "resolved",
+            Stage::Typed => "typed",
+            Stage::Core => "core",
+            Stage::Bytecode => "bytecode",
+        }
+    }
+}
+
+/// How the trees of the stages are printed.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub enum Format {
+    /// An indented tree.
+    #[default]
+    Tree,
+
+    /// A JSON object for each node, that can be compared structurally.
+    Json,
+
+    /// A graph of Graphviz.
+    Dot,
+
+    /// The source of the abstract tree. The stages that aren't written in Vulpi are printed as
+    /// trees.
+    Source,
+}
+
+/// Prints a stage of a module, or of the whole crate.
+pub fn print(format: Format, stage: Stage, name: &str, value: &impl Show) {
+    let header = format!("[Emit]: {} of {}", stage.name(), name);
+
+    // The header is a comment of Graphviz, so the output can be given to `dot` as it is.
+    match format {
+        Format::Tree | Format::Source => println!("{}\n{}", header, value.show()),
+        Format::Json => println!("{}\n{}", header, value.show().to_json()),
+        Format::Dot => print!("// {}\n{}", header, value.show().to_dot()),
+    }
+}
+
+/// Prints the abstract tree of a module, that is printed as source in the format [Format::Source].
+pub fn print_ast(format: Format, name: &str, program: &Program) {
+    if format == Format::Source {
+        println!(
+            "-- [Emit]: {} of {}\n{}",
+            Stage::Ast.name(),
+            name,
+            program.to_source(WIDTH)
+        );
+    } else {
+        print(format, Stage::Ast, name, program)
+    }
+}
+
+/// The tokens of a file, up to the end of file, with tabs that go to the next multiple of
+/// `tab_width` columns. The errors of the lexer are not reported, because the parser reports them
+/// when it lexes the file again.
+pub fn tokens(file: FileId, source: &str, tab_width: usize) -> Vec<Token> {
+    let lexer = Lexer::new(source, file, vulpi_report::hash_reporter());
+    let mut lexer = lexer.with_tab_width(tab_width);
+    let mut tokens = Vec::new();
+
+    loop {
+        let token = lexer.bump();
+        let eof = token.kind == TokenData::Eof;
+        tokens.push(token);
+
+        if eof {
+            return tokens;
+        }
+    }
+}
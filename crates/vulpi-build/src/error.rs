@@ -0,0 +1,170 @@
+use std::path::PathBuf;
+
+use vulpi_intern::Symbol;
+use vulpi_location::Span;
+use vulpi_report::IntoDiagnostic;
+use vulpi_syntax::r#abstract::Qualified;
+use vulpi_vfs::path::Path;
+
+use crate::cfg::Target;
+
+pub enum BuildErrorKind {
+    /// An imported module has no file in any of the paths that were searched.
+    ModuleFileNotFound(Path, Vec<PathBuf>),
+
+    /// The interface file of a module without a source cannot be read.
+    InvalidInterface(PathBuf, String),
+
+    /// The interface file of a module cannot be written to the build directory.
+    CannotWriteInterface(PathBuf, String),
+
+    /// A `when` declaration uses a condition that doesn't exist.
+    UnknownCondition(Symbol),
+
+    /// A `when target` declaration uses a target that doesn't exist.
+    UnknownTarget(Symbol),
+
+    /// The formatter produced a source that is not the same program, which is a bug of it.
+    CannotFormat(String),
+
+    /// A name that is renamed is not declared in the crate, like the ones of its dependencies.
+    NotDeclared(String),
+
+    /// The new name of a declaration is not an identifier of the same case as the old one.
+    InvalidName(String, Symbol),
+
+    /// The new name of a declaration is the name of another one of the same namespace.
+    NameTaken(String),
+
+    /// A test evaluated to `False`.
+    TestFailed(Symbol),
+
+    /// A test stopped with an error of the virtual machine.
+    TestErrored(Symbol, String),
+
+    /// The entry point of a program is not declared.
+    MissingEntry(Qualified),
+
+    /// The entry point of a program has a type that the backends cannot call, which is shown.
+    InvalidEntry(Qualified, String),
+}
+
+pub struct BuildError {
+    pub span: Span,
+    pub kind: BuildErrorKind,
+}
+
+impl IntoDiagnostic for BuildError {
+    fn message(&self) -> vulpi_report::Text {
+        match &self.kind {
+            BuildErrorKind::ModuleFileNotFound(path, searched) => {
+                let searched = searched
+                    .iter()
+                    .map(|x| format!("'{}'", x.display()))
+                    .collect::<Vec<_>>();
+
+                format!(
+                    "cannot find the file of the module '{}', searched in {}",
+                    path,
+                    searched.join(", ")
+                )
+                .into()
+            }
+            BuildErrorKind::InvalidInterface(path, reason) => format!(
+                "cannot read the interface file '{}': {}",
+                path.display(),
+                reason
+            )
+            .into(),
+            BuildErrorKind::CannotWriteInterface(path, reason) => format!(
+                "cannot write the interface file '{}': {}",
+                path.display(),
+                reason
+            )
+            .into(),
+            BuildErrorKind::UnknownCondition(name) => format!(
+                "unknown condition '{}', the only one is 'target'",
+                name.get()
+            )
+            .into(),
+            BuildErrorKind::UnknownTarget(name) => format!(
+                "unknown target '{}', the targets are {}",
+                name.get(),
+                Target::ALL
+                    .iter()
+                    .map(|x| format!("'{}'", x.name()))
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            )
+            .into(),
+            BuildErrorKind::CannotFormat(reason) => {
+                format!("cannot format the file: {}", reason).into()
+            }
+            BuildErrorKind::NotDeclared(name) => {
+                format!("'{}' is not declared in this crate", name).into()
+            }
+            BuildErrorKind::InvalidName(name, old) => format!(
+                "'{}' is not a valid name to rename '{}' to",
+                name,
+                old.get()
+            )
+            .into(),
+            BuildErrorKind::NameTaken(name) => format!("'{}' is already declared", name).into(),
+            BuildErrorKind::TestFailed(name) => format!("the test '{}' failed", name.get()).into(),
+            BuildErrorKind::TestErrored(name, reason) => format!(
+                "the test '{}' stopped with an error: {}",
+                name.get(),
+                reason
+            )
+            .into(),
+            BuildErrorKind::MissingEntry(name) => {
+                format!("cannot find the entry point '{}'", name.to_string()).into()
+            }
+            BuildErrorKind::InvalidEntry(name, typ) => format!(
+                "the entry point '{}' has the type '{}', that is not one of the types of an entry point",
+                name.to_string(),
+                typ
+            )
+            .into(),
+        }
+    }
+
+    fn hint(&self) -> Option<vulpi_report::Text> {
+        match &self.kind {
+            BuildErrorKind::MissingEntry(_) => Some(
+                "declare a 'main' in the root module or choose another entry point with '--entry'"
+                    .into(),
+            ),
+            BuildErrorKind::InvalidEntry(_, _) => {
+                Some("an entry point has the type '()' or '() -> ()'".into())
+            }
+            _ => None,
+        }
+    }
+
+    fn code(&self) -> Option<usize> {
+        match &self.kind {
+            BuildErrorKind::ModuleFileNotFound(_, _) => Some(700),
+            BuildErrorKind::InvalidInterface(_, _) => Some(701),
+            BuildErrorKind::CannotWriteInterface(_, _) => Some(702),
+            BuildErrorKind::UnknownCondition(_) => Some(703),
+            BuildErrorKind::UnknownTarget(_) => Some(704),
+            BuildErrorKind::CannotFormat(_) => Some(705),
+            BuildErrorKind::NotDeclared(_) => Some(706),
+            BuildErrorKind::InvalidName(_, _) => Some(707),
+            BuildErrorKind::NameTaken(_) => Some(708),
+            BuildErrorKind::TestFailed(_) => Some(709),
+            BuildErrorKind::TestErrored(_, _) => Some(710),
+            BuildErrorKind::MissingEntry(_) => Some(711),
+            BuildErrorKind::InvalidEntry(_, _) => Some(712),
+        }
+    }
+
+    fn severity(&self) -> vulpi_report::Severity {
+        vulpi_report::Severity::Error
+    }
+
+    fn location(&self) -> Span {
+        self.span.clone()
+    }
+}
@@ -0,0 +1,70 @@
+//! Application of the suggestions of the diagnostics to the sources, for `vulpi fix`. Suggestions
+//! are applied from the start of a source to its end, and the ones that overlap a suggestion that
+//! was applied before them are left for the next run.
+
+use vulpi_report::Suggestion;
+
+/// Applies the suggestions of a source. Returns the new source and the number of suggestions that
+/// were applied.
+pub fn apply(source: &str, suggestions: Vec<Suggestion>) -> (String, usize) {
+    let mut edits = suggestions
+        .into_iter()
+        .map(|x| edit(source, x))
+        .collect::<Vec<_>>();
+
+    // The same suggestion is made by each use of a definition, like the one that makes it public.
+    edits.sort();
+    edits.dedup();
+
+    let mut result = String::new();
+    let mut cursor = 0;
+    let mut applied = 0;
+
+    for (start, end, replacement) in edits {
+        if start < cursor || end > source.len() {
+            continue;
+        }
+
+        result.push_str(&source[cursor..start]);
+        result.push_str(&replacement);
+        cursor = end;
+        applied += 1;
+    }
+
+    result.push_str(&source[cursor..]);
+
+    (result, applied)
+}
+
+/// The range of bytes of a suggestion and its replacement. Removals that leave a line with only
+/// whitespace remove the whole line.
+fn edit(source: &str, suggestion: Suggestion) -> (usize, usize, String) {
+    let Suggestion { span, replacement } = suggestion;
+
+    let (start, end) = (span.start.0, span.end.0);
+
+    if !replacement.is_empty() || start == end || end > source.len() {
+        return (start, end, replacement);
+    }
+
+    let line_start = source[..start].rfind('\n').map_or(0, |x| x + 1);
+    let line_end = source[end..]
+        .find('\n')
+        .map_or(source.len(), |x| end + x + 1);
+
+    let before = source[line_start..start].trim().is_empty();
+    let after = source[end..line_end].trim().is_empty();
+
+    if !before || !after {
+        return (start, end, replacement);
+    }
+
+    // A blank line that separated the declaration from the ones around it is removed too.
+    let separated = line_start == 0 || source[..line_start].ends_with("\n\n");
+
+    if separated && source[line_end..].starts_with('\n') {
+        (line_start, line_end + 1, replacement)
+    } else {
+        (line_start, line_end, replacement)
+    }
+}
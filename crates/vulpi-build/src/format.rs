@@ -0,0 +1,82 @@
+//! Formatting of the sources, for `vulpi fmt` and for the language server. The formatted source is
+//! parsed again before it's returned, so a source is never changed into one that doesn't compile
+//! or that lost some of its comments.
+
+use vulpi_location::{FileId, Span};
+use vulpi_pretty::{
+    concrete::{Printer, Trivia},
+    WIDTH,
+};
+use vulpi_report::Diagnostic;
+
+use crate::{
+    emit,
+    error::{BuildError, BuildErrorKind},
+};
+
+/// Formats a source whose tabs go to the next multiple of `tab_width` columns. The diagnostics are
+/// the syntax errors of the source, that can't be formatted.
+pub fn format(file: FileId, source: &str, tab_width: usize) -> Result<String, Vec<Diagnostic>> {
+    let reporter = vulpi_report::hash_reporter();
+    let program = vulpi_parser::parse_with_tab_width(reporter.clone(), file, source, tab_width);
+
+    if reporter.has_errors() {
+        return Err(reporter.all_diagnostics());
+    }
+
+    let trivia = Trivia::new(&emit::tokens(file, source, tab_width));
+    let comments = trivia.comments();
+
+    let formatted = Printer::new(source, trivia).program(&program).render(WIDTH);
+
+    let reporter = vulpi_report::hash_reporter();
+    vulpi_parser::parse_with_tab_width(reporter.clone(), file, &formatted, tab_width);
+
+    if reporter.has_errors() {
+        return Err(cannot_format(
+            file,
+            "the formatted source has syntax errors",
+        ));
+    }
+
+    if Trivia::new(&emit::tokens(file, &formatted, tab_width)).comments() != comments {
+        return Err(cannot_format(
+            file,
+            "the formatted source lost some of its comments",
+        ));
+    }
+
+    Ok(formatted)
+}
+
+fn cannot_format(file: FileId, reason: &str) -> Vec<Diagnostic> {
+    vec![Diagnostic::new(BuildError {
+        span: Span::from_usize(file, 0, 0),
+        kind: BuildErrorKind::CannotFormat(reason.to_string()),
+    })]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::TAB_WIDTH;
+
+    #[test]
+    fn formats_and_keeps_comments() {
+        let source = "use B\nuse A -- a\n\nlet main : Int = do\n    -- first\n    let x = add 1   2\n\n    x\n";
+        let expected =
+            "use A -- a\nuse B\n\nlet main : Int = do\n  -- first\n  let x = add 1 2\n\n  x\n";
+
+        let formatted = format(FileId(0), source, TAB_WIDTH).ok().unwrap();
+        assert_eq!(formatted, expected);
+        assert_eq!(format(FileId(0), &formatted, TAB_WIDTH).ok().unwrap(), expected);
+    }
+
+    #[test]
+    fn formats_the_clauses_of_handlers_out_of_their_blocks() {
+        let source = "let ticks (x : ()) : Int =\n  handle do\n    Tick.tick ()\n    3\n  with\n    cases\n      { Tick.tick u -> k } => k ()\n  finally print \"done\"\n\nlet main (x : ()) : Int = do\n  let y = handle do\n      Tick.tick ()\n    with cases\n      { Tick.tick u -> k } => k ()\n  y\n";
+
+        let formatted = format(FileId(0), source, TAB_WIDTH).ok().unwrap();
+        assert_eq!(format(FileId(0), &formatted, TAB_WIDTH).ok().unwrap(), formatted);
+    }
+}
@@ -0,0 +1,235 @@
+//! Interface files of modules. An interface file has everything that the other modules need to use
+//! a module without its source: the names that it declares, their types, the constructors of its
+//! types, the operations of its effects and its elaborated program, so its code is generated with
+//! the code of the crate that uses it. A crate can be distributed without its sources by putting
+//! its interface files in the build directory of the crates that use it.
+//!
+//! An interface file has a [SourceMap] too, with the places where the public items of the module
+//! are declared, so editors of the crates that use it can go to the sources of the crate that
+//! wrote it when they're available.
+
+use std::{
+    collections::BTreeMap,
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+use serde::{Deserialize, Serialize};
+use vulpi_location::{FileId, Span};
+use vulpi_resolver::Module;
+use vulpi_syntax::r#abstract::{Qualified, Visibility};
+
+use crate::cache::Artifact;
+
+/// The extension of the interface files.
+pub const EXTENSION: &str = "vpi";
+
+/// The start of an interface file. Elaborated programs change between the versions of the
+/// compiler, so an interface file can only be read by the version that wrote it.
+fn header() -> String {
+    format!("vulpi-interface {}\n", env!("CARGO_PKG_VERSION"))
+}
+
+/// The interface file of a module whose file would be the path.
+pub fn path(module: PathBuf) -> PathBuf {
+    module.with_extension(EXTENSION)
+}
+
+/// The name of a declaration in a file, by its bytes. The spans of an interface file are replaced
+/// when it's read, so the source map stores its locations without them.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Location {
+    pub file: PathBuf,
+    pub start: usize,
+    pub end: usize,
+}
+
+/// The locations of the public declarations of a module and of its submodules.
+#[derive(Clone, Default, Serialize, Deserialize)]
+pub struct SourceMap {
+    pub definitions: BTreeMap<Qualified, Location>,
+}
+
+impl SourceMap {
+    /// The source map of a module, with a function that gives the paths of its files.
+    pub fn new(module: &Module, path: impl Fn(FileId) -> Option<PathBuf>) -> Self {
+        let mut map = Self::default();
+        map.add(module, &path);
+        map
+    }
+
+    fn add(&mut self, module: &Module, path: &impl Fn(FileId) -> Option<PathBuf>) {
+        let namespace = module.name().symbol();
+        let declared = module.declared();
+
+        let definitions = [&declared.types, &declared.values, &declared.traits]
+            .into_iter()
+            .flatten()
+            .filter(|(_, definition)| definition.visibility == Visibility::Public);
+
+        for (name, definition) in definitions {
+            let Some(file) = path(definition.name.file) else {
+                continue;
+            };
+
+            let qualified = Qualified {
+                path: namespace.clone(),
+                name: name.clone(),
+            };
+
+            let location = Location {
+                file,
+                start: definition.name.start.0,
+                end: definition.name.end.0,
+            };
+
+            self.definitions.insert(qualified, location);
+        }
+
+        for submodule in module.submodules().values() {
+            self.add(submodule, path);
+        }
+    }
+}
+
+pub fn write(path: &Path, artifact: &Artifact, sources: &SourceMap) -> io::Result<()> {
+    let mut bytes = header().into_bytes();
+    bincode::serialize_into(&mut bytes, &(artifact, sources)).map_err(io::Error::other)?;
+
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+
+    fs::write(path, bytes)
+}
+
+/// Reads an interface file. The sources of the module are not available, so all of its spans are
+/// replaced by the span where it's imported.
+pub fn read(path: &Path, span: Span) -> io::Result<(Artifact, SourceMap)> {
+    let bytes = fs::read(path)?;
+
+    let Some(bytes) = bytes.strip_prefix(header().as_bytes()) else {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "it is not an interface file of this version of the compiler",
+        ));
+    };
+
+    vulpi_location::with_span(span, || bincode::deserialize(bytes))
+        .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use vulpi_intern::Symbol;
+    use vulpi_location::Byte;
+
+    #[test]
+    fn keeps_the_locations_of_the_declarations() {
+        let name = Qualified {
+            path: Symbol::intern("Dep.Util"),
+            name: Symbol::intern("unit"),
+        };
+
+        let location = Location {
+            file: PathBuf::from("/dep/src/Util.vp"),
+            start: 10,
+            end: 14,
+        };
+
+        let artifact = Artifact {
+            namespace: Module::new(vulpi_vfs::path::Path {
+                segments: vec![Symbol::intern("Dep"), Symbol::intern("Util")],
+            }),
+            interfaces: Vec::new(),
+            program: Default::default(),
+            imports: Vec::new(),
+        };
+
+        let sources = SourceMap {
+            definitions: BTreeMap::from([(name.clone(), location)]),
+        };
+
+        let file = std::env::temp_dir().join(format!("vulpi-{}.{}", std::process::id(), EXTENSION));
+        write(&file, &artifact, &sources).unwrap();
+
+        let span = Span::new(FileId(3), Byte(1), Byte(2));
+        let (_, read) = read(&file, span).unwrap();
+        fs::remove_file(&file).unwrap();
+
+        let location = &read.definitions[&name];
+        assert_eq!(location.file, PathBuf::from("/dep/src/Util.vp"));
+        assert_eq!((location.start, location.end), (10, 14));
+    }
+
+    #[test]
+    fn runs_the_code_of_interface_files_without_their_sources() {
+        use crate::{real::RealFileSystem, ProjectCompiler};
+        use vulpi_vm::machine::Machine;
+
+        let directory = std::env::temp_dir().join(format!("vulpi-runs-{}", std::process::id()));
+        let (library, app) = (directory.join("lib"), directory.join("app"));
+        let build = directory.join("build");
+
+        let shapes = "use Prelude
+
+pub type Shape = | Dot | Line String
+
+pub effect Draw where
+  pub draw String : ()
+
+pub let describe : Shape -> String
+  | Shape.Dot => \"dot\"
+  | Shape.Line name => name
+
+pub let both (x : Shape) (y : Shape) : () = do
+  Draw.draw (describe x)
+  Draw.draw (describe y)
+";
+
+        let main = "use Prelude
+use Shapes
+
+pub let main (x: ()) : () =
+  handle Shapes.both Shapes.Shape.Dot (Shapes.Shape.Line \"line\") with
+    cases
+      { Shapes.Draw.draw name -> k } => do
+        print name
+        k ()
+";
+
+        for project in [&library, &app] {
+            fs::create_dir_all(project).unwrap();
+            fs::write(project.join("Main.vp"), main).unwrap();
+        }
+
+        fs::write(library.join("Shapes.vp"), shapes).unwrap();
+
+        let compiler = |name: &str, root: &PathBuf| {
+            let name = Symbol::intern(name);
+            let fs = RealFileSystem::new(name.clone(), root.clone(), build.clone());
+            ProjectCompiler::new(name, fs)
+        };
+
+        let mut lib = compiler("Lib", &library);
+        lib.interfaces = true;
+        assert!(lib.check(lib.name.clone(), PathBuf::from("Main.vp")).is_some());
+
+        // The app only has the interface file of `Shapes`, that brings the code of its functions.
+        let mut app = compiler("App", &app);
+        let bytecode = app.bytecode(app.name.clone(), PathBuf::from("Main.vp"));
+        let entry = app.entry(app.name.clone());
+
+        fs::remove_dir_all(&directory).unwrap();
+
+        let bytecode = bytecode.unwrap();
+        let mut output = Vec::new();
+        let mut machine = Machine::with_output(&bytecode, Box::new(&mut output));
+        let result = machine.initialize(&entry).and_then(|_| machine.run(&entry));
+        drop(machine);
+
+        assert!(result.is_ok());
+        assert_eq!(String::from_utf8(output).unwrap(), "dot\nline\n");
+    }
+}
@@ -1,29 +1,81 @@
 //! Facilities to build a entire crate of vulpi files. This module is responsible for building the
 //! crate from the source files and resolving the modules.
 
-use std::{collections::HashMap, path::PathBuf, fs::File, rc::Rc, cell::RefCell};
+use std::{
+    cell::RefCell,
+    collections::{HashMap, HashSet},
+    path::PathBuf,
+    rc::Rc,
+    time::Instant,
+};
 
-use resw::Writer;
+use cache::{Artifact, Cache};
+use cfg::Target;
+use emit::Stage;
+use error::{BuildError, BuildErrorKind};
+use interface::SourceMap;
+use rayon::prelude::*;
+use timings::{Phase, Start, Timings};
 use vulpi_intern::Symbol;
-use vulpi_ir::{transform, inline, dead_code, uncurry};
-use vulpi_location::{FileId, Span};
-use vulpi_report::Report;
+use vulpi_codegen_js::codegen::Script;
+use vulpi_location::{FileId, Sources, Span};
+use vulpi_report::{Diagnostic, Report};
+use vulpi_show::Show;
 
 use vulpi_resolver::{
-    cycle::DepHolder,
     dependencies::{self, Dependencies},
     Context, Module,
 };
 
-use vulpi_show::Show;
-use vulpi_syntax::concrete::tree::Program;
-use vulpi_typer::declare::{Programs, Declare};
+use vulpi_syntax::{concrete::tree::Program, elaborated, r#abstract::Qualified};
+use vulpi_typer::{declare::{Programs, Declare}, real::Real, Type};
+use vulpi_vm::{
+    bytecode::Module as Bytecode,
+    machine::{Machine, RuntimeError},
+};
 use vulpi_vfs::{path::Path, FileSystem};
 
+pub mod cache;
+pub mod cfg;
+pub mod doc;
+pub mod emit;
+pub mod error;
+pub mod fix;
+pub mod format;
+pub mod interface;
+pub mod memory;
+pub mod query;
 pub mod real;
+pub mod rename;
+pub mod repl;
+pub mod runner;
+pub mod stdlib;
+pub mod timings;
+pub mod tree;
+
+pub use vulpi_core::{errors::UNUSED, primitive::Overflow};
+pub use vulpi_lexer::TAB_WIDTH;
+pub use vulpi_resolver::DEPRECATED;
+pub use vulpi_typer::ONE_SHOT;
+
+/// The lints that the user can allow or warn about.
+pub const LINTS: [&str; 3] = [UNUSED, ONE_SHOT, DEPRECATED];
+
+/// An error of a program that runs in the virtual machine, with the stack trace of the calls that
+/// were running.
+pub struct Crash {
+    pub error: RuntimeError,
+    pub trace: String,
+}
+
+impl std::fmt::Display for Crash {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}\n{}", self.error, self.trace.trim_end())
+    }
+}
 
 pub enum Interface {
-    Compiled(Module, Dependencies),
+    Compiled(Box<Artifact>),
     Uncompiled(Program),
 }
 
@@ -31,9 +83,88 @@ pub struct ProjectCompiler<FS: FileSystem> {
     pub name: Symbol,
     pub fs: FS,
     pub reporter: Report,
+
+    /// The level of the optimizer of the core language. Level 0 disables it.
+    pub optimization: usize,
+
+    /// What the operators of integer arithmetic do when the result doesn't fit in 64 bits.
+    pub overflow: Overflow,
+
+    /// Reports the private let declarations that are never used as warnings.
+    pub unused: bool,
+
+    /// The cache of the modules that were compiled before.
+    pub cache: Option<Cache>,
+
+    /// Writes the interface file of each module of the crate to the build directory.
+    pub interfaces: bool,
+
+    /// The backend whose `when target` declarations are kept when the crate is checked. The
+    /// methods that generate code use the target of their backend instead.
+    pub target: Target,
+
+    /// The intermediate representations that are printed while the crate is compiled.
+    pub emit: Vec<Stage>,
+
+    /// How the intermediate representations are printed.
+    pub emit_format: emit::Format,
+
+    /// Records the time, the memory and the nodes of each phase of the compilation.
+    pub timings: Option<Timings>,
+
+    /// The function that programs start from. Defaults to the `main` of the root module.
+    pub entry: Option<Qualified>,
+
+    /// The number of columns between two tab stops of the layout of the sources.
+    pub tab_width: usize,
 }
 
 impl<FS: FileSystem> ProjectCompiler<FS> {
+    /// A compiler of the crate with the default options. The optimizer, the cache, the lint of the
+    /// unused declarations and the printing of the intermediate representations are disabled.
+    pub fn new(name: Symbol, fs: FS) -> Self {
+        Self {
+            name,
+            fs,
+            reporter: vulpi_report::hash_reporter(),
+            optimization: 0,
+            overflow: Overflow::default(),
+            unused: false,
+            cache: None,
+            interfaces: false,
+            target: Target::default(),
+            emit: vec![],
+            emit_format: Default::default(),
+            timings: None,
+            entry: None,
+            tab_width: TAB_WIDTH,
+        }
+    }
+}
+
+impl<FS: FileSystem<Path = PathBuf>> ProjectCompiler<FS> {
+    fn emits(&self, stage: Stage) -> bool {
+        self.emit.contains(&stage)
+    }
+
+    /// Starts to measure a phase if the timings are recorded.
+    fn start(&self) -> Option<Start> {
+        self.timings.as_ref().map(|_| Start::now())
+    }
+
+    fn record(
+        &mut self,
+        phase: Phase,
+        module: Option<String>,
+        start: Option<Start>,
+        tree: Option<&dyn Show>,
+    ) {
+        if let Some((timings, start)) = self.timings.as_mut().zip(start) {
+            let nodes = tree.map(timings::nodes);
+            timings.record(phase, module, start, nodes);
+        }
+    }
+
     fn load(&mut self, _span: Span, path: FS::Path) -> Option<FileId> {
         if let Ok(id) = self.fs.load(path) {
             Some(id)
@@ -44,106 +175,1825 @@ impl<FS: FileSystem> ProjectCompiler<FS> {
 
     fn parse(&mut self, id: FileId) -> Program {
         let source = self.fs.read(id).unwrap();
-        vulpi_parser::parse(self.reporter.clone(), id, &source)
+        let reporter = self.reporter.clone();
+        let program = vulpi_parser::parse_with_tab_width(reporter, id, &source, self.tab_width);
+        cfg::filter(&self.reporter, self.target, program)
+    }
+
+    /// Checks if a module without a file can be declared inside of the file of one of its parents.
+    fn is_inline(&mut self, path: &Path) -> bool {
+        (1..path.segments.len()).any(|length| {
+            let parent = Path {
+                segments: path.segments[..length].to_vec(),
+            };
+
+            self.fs.load(self.fs.from_src_path(parent)).is_ok()
+        })
+    }
+
+    /// Loads the interface file of a module that has no source. Reports that the module cannot be
+    /// found if it has no interface file either.
+    fn interface(&mut self, path: &Path, span: Span) -> Option<Box<Artifact>> {
+        let file = interface::path(self.fs.from_cached_path(path.clone()));
+
+        if !file.is_file() {
+            self.reporter.report(Diagnostic::new(BuildError {
+                span,
+                kind: BuildErrorKind::ModuleFileNotFound(
+                    path.clone(),
+                    vec![self.fs.from_src_path(path.clone()), file],
+                ),
+            }));
+
+            return None;
+        }
+
+        match interface::read(&file, span.clone()) {
+            Ok((artifact, _)) => Some(Box::new(artifact)),
+            Err(err) => {
+                self.reporter.report(Diagnostic::new(BuildError {
+                    span,
+                    kind: BuildErrorKind::InvalidInterface(file, err.to_string()),
+                }));
+
+                None
+            }
+        }
     }
 
+    /// Loads and parses the modules that are imported, and the ones that they import. The modules
+    /// imported by the same wave of files are independent, so they're parsed in parallel, each
+    /// one with a report of its own that is merged into the report of the crate.
     pub fn find_dependencies(
         &mut self,
         bag: &mut HashMap<Path, (Interface, Dependencies)>,
         deps: Dependencies,
     ) {
-        for (path, span) in deps.imported {
-            if !bag.contains_key(&path) {
+        let mut pending = deps.imported;
+
+        while !pending.is_empty() {
+            let mut wave: Vec<(Path, FileId, String)> = Vec::new();
+
+            for (path, span) in std::mem::take(&mut pending) {
+                if bag.contains_key(&path) || wave.iter().any(|(other, _, _)| *other == path) {
+                    continue;
+                }
+
                 if let Some(id) = self.load(span.clone(), self.fs.from_src_path(path.clone())) {
-                    let program = self.parse(id);
-                    let deps = dependencies::dependencies(self.name.clone(), &program);
-                    bag.insert(path.clone(), (Interface::Uncompiled(program), deps.clone()));
-                    self.find_dependencies(bag, deps);
+                    let source = self.fs.read(id).unwrap();
+                    wave.push((path, id, source));
+                } else if self.is_inline(&path) {
+                    continue;
+                } else if let Some(artifact) = self.interface(&path, span.clone()) {
+                    let imported: Vec<_> = artifact
+                        .imports
+                        .iter()
+                        .map(|path| (path.clone(), span.clone()))
+                        .collect();
+
+                    pending.extend(imported.iter().cloned());
+
+                    let deps = Dependencies {
+                        declared: vec![],
+                        imported,
+                        opened: vec![],
+                    };
+
+                    bag.insert(path, (Interface::Compiled(artifact), deps));
+                }
+            }
+
+            let target = self.target;
+            let tab_width = self.tab_width;
+            let timed = self.timings.is_some();
+
+            let parsed: Vec<_> = wave
+                .into_par_iter()
+                .map(|(path, id, source)| {
+                    let start = Instant::now();
+                    let reporter = vulpi_report::hash_reporter();
+                    let program =
+                        vulpi_parser::parse_with_tab_width(reporter.clone(), id, &source, tab_width);
+                    let program = cfg::filter(&reporter, target, program);
+                    let time = start.elapsed();
+
+                    // The nodes are counted after the time is measured, because it's slow.
+                    let nodes = if timed { timings::nodes(&program) } else { 0 };
+
+                    (path, program, reporter.detach(), (time, nodes))
+                })
+                .collect();
+
+            for (path, program, diagnostics, (time, nodes)) in parsed {
+                self.reporter.merge(diagnostics);
+
+                if let Some(timings) = &mut self.timings {
+                    timings.record_parallel(Phase::Parse, path.to_string(), time, nodes);
                 }
+
+                let deps = dependencies::dependencies(self.name.clone(), &program);
+                pending.extend(deps.imported.iter().cloned());
+                bag.insert(path, (Interface::Uncompiled(program), deps));
             }
         }
     }
 
-    pub fn compile(&mut self, module: Symbol, path: FS::Path, output: PathBuf) {
+    /// Parses, resolves and type checks the crate that starts at the file. Returns the elaborated
+    /// programs if there are no errors.
+    pub fn check(&mut self, module: Symbol, path: FS::Path) -> Option<Vec<elaborated::Program<Type<Real>>>> {
         // TODO: Fix this error :( I can't now because it would require changes
         // to the vulpi-report module. Good luck Sofia from the future!
 
         let root = self.fs.load(path).unwrap();
-        let parsed = self.parse(root);
 
         let path = Path {
             segments: vec![module.clone(), Symbol::intern("Main")],
         };
 
+        let start = self.start();
+        let parsed = self.parse(root);
+        self.record(Phase::Parse, Some(path.to_string()), start, Some(&parsed));
+
         let mut bag = HashMap::new();
         let deps = dependencies::dependencies(self.name.clone(), &parsed);
         bag.insert(path.clone(), (Interface::Uncompiled(parsed), deps.clone()));
 
         self.find_dependencies(&mut bag, deps);
 
-        let mut modules = HashMap::new();
+        // Only the modules without sources are compiled at this point.
+        let libraries: HashSet<_> = bag
+            .iter()
+            .filter(|(_, (interface, _))| matches!(interface, Interface::Compiled(_)))
+            .map(|(path, _)| path.clone())
+            .collect();
+
+        let imports: HashMap<_, _> = bag
+            .iter()
+            .map(|(path, (_, deps))| {
+                let imported = deps.imported.iter().map(|(path, _)| path.clone());
+                (path.clone(), imported.collect::<Vec<_>>())
+            })
+            .collect();
+
+        // Modules are resolved in the order of their paths, so the namespaces and the programs
+        // don't depend on the order that the files were found.
+        let mut bag: Vec<_> = bag.into_iter().collect();
+        bag.sort_by_key(|(path, _)| path.to_string());
+
+        for (module, (interface, _)) in &bag {
+            let Interface::Uncompiled(program) = interface else {
+                continue;
+            };
+
+            let name = module.to_string();
+
+            if self.emits(Stage::Tokens) {
+                if let Some(file) = self.file(module, (&path, root)) {
+                    let source = self.fs.read(file).unwrap();
+                    let tokens = emit::tokens(file, &source, self.tab_width);
+                    emit::print(self.emit_format, Stage::Tokens, &name, &tokens);
+                }
+            }
+
+            if self.emits(Stage::Cst) {
+                emit::print(self.emit_format, Stage::Cst, &name, program);
+            }
+        }
+
+        let keys = self.keys(&bag, (&path, root));
+
+        // The modules that are loaded from the cache have no abstract trees to print.
+        let cache = self.cache.as_ref().filter(|_| !self.emits(Stage::Ast));
+
+        if let Some(cache) = cache {
+            for (path, (interface, _)) in bag.iter_mut() {
+                let loaded = keys.get(path).and_then(|(key, file)| cache.load(*key, *file));
 
+                if let Some(artifact) = loaded {
+                    *interface = Interface::Compiled(Box::new(artifact));
+                }
+            }
+        }
+
+        let mut modules = Vec::new();
+        let mut loaded = Vec::new();
+
+        // Only the parsing is parallel. The namespaces are shared by the resolvers of all the
+        // modules through `Rc`s, so the modules are resolved one after the other in this thread.
         let available: Rc<RefCell<HashMap<Path, Module>>> = Default::default();
 
         for (path, (program, deps)) in bag {
             match program {
-                Interface::Compiled(module, _) => {
-                    modules.insert(path, (module, None, deps));
+                Interface::Compiled(artifact) => {
+                    modules.push((artifact.namespace.clone(), None, deps));
+                    loaded.push(*artifact);
                 }
                 Interface::Uncompiled(parsed) => {
+                    let start = self.start();
                     let context = Context::new(available.clone(), path.clone(), self.reporter.clone());
                     let solved = vulpi_resolver::resolve(&context, parsed);
-                    modules.insert(
-                        path,
-                        (context.module.clone(), Some((context, solved)), deps),
-                    );
+                    self.record(Phase::Collect, Some(path.to_string()), start, None);
+                    modules.push((context.module.clone(), Some((context, solved)), deps));
                 }
             }
         }
 
-        for (module, _, _) in modules.values() {
-            let path = module.name().clone();
-            let mut borrow_mut = available.borrow_mut();
-            borrow_mut.insert(path, module.clone());
+        for (module, _, _) in &modules {
+            module.register(&mut available.borrow_mut());
         }
 
         let mut programs = vec![];
 
-        let mut dep = DepHolder::default();
-
-        for (_, ctx, _) in modules.into_values() {
+        for (module, ctx, _) in modules {
             if let Some((ctx, resolver)) = ctx {
+                let start = self.start();
                 let program = resolver.eval(ctx.clone());
-                dep.register(&program);
-                programs.push(program);
+                self.record(Phase::Resolve, Some(module.name().to_string()), start, Some(&program));
+
+                if self.emits(Stage::Ast) {
+                    emit::print_ast(self.emit_format, &module.name().to_string(), &program);
+                }
+
+                programs.push((module, program));
             }
         }
 
-        dep.report_cycles(self.reporter.clone());
+        if self.emits(Stage::Resolved) {
+            let available = available.borrow();
+            let mut namespaces: Vec<_> = available.iter().collect();
+            namespaces.sort_by_key(|(path, _)| path.to_string());
+
+            for (path, module) in namespaces {
+                emit::print(self.emit_format, Stage::Resolved, &path.to_string(), &*module.borrow());
+            }
+        }
 
         let mut ctx = vulpi_typer::Context::new(self.reporter.clone());
         let env = vulpi_typer::Env::default();
 
+        let mut elaborated = Vec::new();
+
+        for artifact in loaded {
+            let path = artifact.namespace.name().clone();
+
+            if self.interfaces && !libraries.contains(&path) {
+                self.write_interface(&path, &artifact);
+            }
+
+            for (path, interface) in artifact.interfaces {
+                ctx.modules.get(&path).merge(interface);
+            }
+
+            elaborated.push((artifact.namespace.name().symbol(), artifact.program));
+        }
+
+        let (namespaces, programs): (Vec<_>, Vec<_>) = programs.into_iter().unzip();
         let programs = Programs(programs);
-        println!("{}", programs.0[0].show());
 
+        let start = self.start();
         Declare::declare(&programs, (&mut ctx, env.clone()));
         let programs = Declare::define(&programs, (&mut ctx, env));
+        self.record(Phase::Typecheck, None, start, Some(&programs));
+
+        if self.reporter.has_errors() {
+            return None;
+        }
+
+        self.store(&keys, &imports, &namespaces, &programs, &mut ctx);
+
+        for (namespace, program) in namespaces.iter().zip(programs) {
+            elaborated.push((namespace.name().symbol(), program));
+        }
+
+        elaborated.sort_by_key(|(path, _)| path.get());
+
+        if self.emits(Stage::Typed) {
+            for (path, program) in &elaborated {
+                emit::print(self.emit_format, Stage::Typed, &path.get(), program);
+            }
+        }
+
+        Some(elaborated.into_iter().map(|(_, program)| program).collect())
+    }
+
+    /// The file of a module, if it has one. The root module is loaded from the path that the
+    /// crate is compiled from.
+    fn file(&mut self, path: &Path, root: (&Path, FileId)) -> Option<FileId> {
+        if path == root.0 {
+            Some(root.1)
+        } else {
+            self.fs.load(self.fs.from_src_path(path.clone())).ok()
+        }
+    }
+
+    /// The keys of the modules in the cache and their files. The key of a module depends on the
+    /// sources of all the modules that it uses, so it changes when the interface of one of them
+    /// changes.
+    fn keys(
+        &mut self,
+        bag: &[(Path, (Interface, Dependencies))],
+        root: (&Path, FileId),
+    ) -> HashMap<Path, (u64, FileId)> {
+        if self.cache.is_none() {
+            return HashMap::new();
+        }
+
+        let mut files = HashMap::new();
+
+        for (path, _) in bag {
+            if let Some(file) = self.file(path, root) {
+                files.insert(path.clone(), file);
+            }
+        }
+
+        let imports: HashMap<_, _> = bag
+            .iter()
+            .map(|(path, (_, deps))| (path.clone(), &deps.imported))
+            .collect();
+
+        let mut keys = HashMap::new();
+
+        for (path, file) in &files {
+            let mut visited = HashSet::new();
+            let mut pending = vec![path.clone()];
+            let mut sources = Vec::new();
+
+            while let Some(path) = pending.pop() {
+                if !visited.insert(path.clone()) {
+                    continue;
+                }
+
+                if let Some(file) = files.get(&path) {
+                    sources.push((path.to_string(), self.fs.read(*file).unwrap()));
+                }
+
+                if let Some(imported) = imports.get(&path) {
+                    pending.extend(imported.iter().map(|(path, _)| path.clone()));
+                }
+            }
+
+            // The declarations of a module depend on the target too, and its layout on the tab
+            // width. Modules that import each other, like the prelude and the operators, have the
+            // same sources, so the name of the module is part of its key.
+            sources.push((String::new(), self.target.name().to_string()));
+            sources.push((String::new(), self.tab_width.to_string()));
+            sources.push((String::new(), path.to_string()));
+
+            keys.insert(path.clone(), (Cache::key(sources), *file));
+        }
+
+        keys
+    }
+
+    /// Stores the modules that were type checked in the cache, if there are no diagnostics to show
+    /// again, and writes their interface files.
+    fn store(
+        &self,
+        keys: &HashMap<Path, (u64, FileId)>,
+        imports: &HashMap<Path, Vec<Path>>,
+        namespaces: &[Module],
+        programs: &[elaborated::Program<Type<Real>>],
+        ctx: &mut vulpi_typer::Context,
+    ) {
+        let cache = self
+            .cache
+            .as_ref()
+            .filter(|_| self.reporter.all_diagnostics().is_empty());
+
+        if cache.is_none() && !self.interfaces {
+            return;
+        }
+
+        let mut paths: Vec<_> = imports.keys().map(|x| x.symbol().get()).collect();
+
+        // Longer paths are tried first, so the interfaces of `A.B` are not given to `A`.
+        paths.sort_by_key(|x| std::cmp::Reverse(x.len()));
+
+        let owner = |name: &Symbol| {
+            let name = name.get();
+            paths
+                .iter()
+                .find(|path| name == **path || name.starts_with(&format!("{}.", path)))
+                .cloned()
+        };
+
+        for (namespace, program) in namespaces.iter().zip(programs) {
+            let path = namespace.name().clone();
+
+            // Types and effects are modules of their own in the type checker, because their
+            // constructors and operations are qualified by them.
+            let names: Vec<_> = ctx
+                .modules
+                .modules
+                .keys()
+                .filter(|name| owner(name) == Some(path.symbol().get()))
+                .cloned()
+                .collect();
+
+            let interfaces = names
+                .into_iter()
+                .map(|name| {
+                    let interface = ctx.modules.modules.remove(&name).unwrap();
+                    (name, interface)
+                })
+                .collect();
+
+            let artifact = Artifact {
+                namespace: namespace.clone(),
+                interfaces,
+                program: program.clone(),
+                imports: imports.get(&path).cloned().unwrap_or_default(),
+            };
+
+            if let Some((cache, (key, _))) = cache.zip(keys.get(&path)) {
+                // A cache that cannot be written only makes the next compilation slower.
+                let _ = cache.store(*key, &artifact);
+            }
+
+            if self.interfaces {
+                self.write_interface(&path, &artifact);
+            }
+        }
+    }
+
+    /// Writes the interface file of a module to the build directory.
+    fn write_interface(&self, path: &Path, artifact: &Artifact) {
+        let file = interface::path(self.fs.from_cached_path(path.clone()));
+        let sources = SourceMap::new(&artifact.namespace, |id| self.fs.path(id).ok().cloned());
+
+        if let Err(err) = interface::write(&file, artifact, &sources) {
+            self.reporter.report(Diagnostic::new(BuildError {
+                span: Span::ghost(),
+                kind: BuildErrorKind::CannotWriteInterface(file, err.to_string()),
+            }));
+        }
+    }
+
+    /// The entry point of the programs of the crate.
+    fn entry(&self, module: Symbol) -> Qualified {
+        self.entry.clone().unwrap_or_else(|| main(module))
+    }
+
+    /// Checks that the entry point is declared and that the backends can call it: it's a value of
+    /// type `()`, or a function from `()` to `()` that is called with the unit. The effects of a
+    /// function are not part of its type, so the ones that the entry point doesn't handle are
+    /// found only when they are performed. A missing entry point is reported only if it's
+    /// required. Returns whether the entry point can be called.
+    fn check_entry(
+        &self,
+        programs: &[elaborated::Program<Type<Real>>],
+        entry: &Qualified,
+        required: bool,
+    ) -> bool {
+        let Some(decl) = find(programs, entry) else {
+            if required {
+                self.reporter.report(Diagnostic::new(BuildError {
+                    span: Span::ghost(),
+                    kind: BuildErrorKind::MissingEntry(entry.clone()),
+                }));
+            }
+
+            return !required;
+        };
+
+        let unit = |typ: &vulpi_core::syntax::TypeKind| {
+            matches!(typ, vulpi_core::syntax::TypeKind::Tuple(types) if types.is_empty())
+        };
+
+        let valid = match *vulpi_core::lower::typ(&decl.typ, 0) {
+            vulpi_core::syntax::TypeKind::Arrow(param, ret) => unit(&param) && unit(&ret),
+            typ => unit(&typ),
+        };
+
+        if !valid {
+            let typ = decl.typ.show(&vulpi_typer::Env::default()).to_string();
+
+            self.reporter.report(Diagnostic::new(BuildError {
+                span: decl.span.clone(),
+                kind: BuildErrorKind::InvalidEntry(entry.clone(), typ),
+            }));
+        }
+
+        valid
+    }
+
+    /// Lowers the elaborated programs into the core language, removes the code that cannot be
+    /// reached from the entry point or from the public declarations, optimizes them and trims the
+    /// environments of their closures. The values are put in the order that they're initialized,
+    /// and nothing is returned if some of them need themselves.
+    fn lower(
+        &mut self,
+        programs: &[elaborated::Program<Type<Real>>],
+        entry: Option<&Qualified>,
+    ) -> Option<vulpi_core::syntax::Program> {
+        let start = self.start();
+        let mut core = vulpi_core::lower::lower(programs);
+        vulpi_core::primitive::overflow(&mut core, self.overflow);
+        let initializable = vulpi_core::init::check(self.reporter.clone(), &core);
+        let removed = vulpi_core::dead::eliminate(&mut core, entry);
+        self.record(Phase::Lower, None, start, Some(&core));
+
+        if self.unused {
+            vulpi_core::dead::report_unused(self.reporter.clone(), &removed);
+        }
+
+        let start = self.start();
+        vulpi_core::optimize::optimize(&mut core, self.optimization);
+
+        // Declarations that were inlined everywhere are not used anymore.
+        vulpi_core::dead::eliminate(&mut core, entry);
+        vulpi_core::usage::trim(&mut core);
+        vulpi_core::init::sort(&mut core, entry);
+        self.record(Phase::Optimize, None, start, Some(&core));
+
+        if self.emits(Stage::Core) {
+            emit::print(self.emit_format, Stage::Core, &self.name.get(), &core);
+        }
+
+        initializable.then_some(core)
+    }
+
+    /// Checks the crate and lowers it without generating any code, so the diagnostics of all the
+    /// phases before the backends are reported.
+    pub fn analyze(&mut self, module: Symbol, path: FS::Path) {
+        if let Some(programs) = self.check(module.clone(), path) {
+            // A crate without an entry point is a library, so only the type of one is checked.
+            let entry = self.entry(module);
+            self.check_entry(&programs, &entry, false);
+            self.lower(&programs, Some(&entry));
+        }
+    }
+
+    /// The sources of the files that the spans point to.
+    fn sources<'a>(&self, spans: impl IntoIterator<Item = &'a Span>) -> Sources {
+        let mut sources = Sources::default();
+
+        for span in spans {
+            if sources.get(span.file).is_some() {
+                continue;
+            }
+
+            if let (Ok(path), Ok(content)) = (self.fs.path(span.file), self.fs.read(span.file)) {
+                sources.insert(span.file, path.display().to_string(), content);
+            }
+        }
+
+        sources
+    }
+
+    /// Compiles the crate to a JavaScript file that calls the `main` of its root module, with its
+    /// source map next to it in a file with the `.map` extension.
+    pub fn compile(&mut self, module: Symbol, path: FS::Path, output: PathBuf) {
+        let Some(script) = self.script(module, path) else {
+            return;
+        };
+
+        let mut map = output.clone().into_os_string();
+        map.push(".map");
+        let map = PathBuf::from(map);
+
+        let file = |path: &std::path::Path| {
+            let name = path.file_name().unwrap_or_default();
+            name.to_string_lossy().to_string()
+        };
+
+        let sources = self.sources(script.mappings.iter().map(|x| &x.span));
+        let source_map = vulpi_codegen_js::source_map::source_map(&file(&output), &script, &sources);
+
+        let code = format!("{}//# sourceMappingURL={}\n", script.code, file(&map));
+
+        std::fs::write(output, code).unwrap();
+        std::fs::write(map, source_map).unwrap();
+    }
+
+    /// Compiles the crate to the JavaScript code that [Self::compile] writes, without the comment
+    /// that links it to its source map.
+    pub fn javascript(&mut self, module: Symbol, path: FS::Path) -> Option<String> {
+        self.script(module, path).map(|script| script.code)
+    }
+
+    fn script(&mut self, module: Symbol, path: FS::Path) -> Option<Script> {
+        self.target = Target::Js;
+
+        let programs = self.check(module.clone(), path)?;
+        let entry = self.entry(module);
+
+        if !self.check_entry(&programs, &entry, true) {
+            return None;
+        }
+
+        let mut core = self.lower(&programs, Some(&entry))?;
+
+        let start = self.start();
+        vulpi_core::effects::elaborate(&mut core, Some(&entry));
+        let script = vulpi_codegen_js::codegen::script(self.reporter.clone(), &core, Some(&entry));
+        self.record(Phase::Codegen, None, start, None);
+
+        script
+    }
+
+    /// Compiles the crate to bytecode and runs the `main` of its root module in the virtual
+    /// machine.
+    pub fn run(&mut self, module: Symbol, path: FS::Path) -> Result<(), Crash> {
+        self.target = Target::Vm;
+
+        let Some(programs) = self.check(module.clone(), path) else {
+            return Ok(());
+        };
+
+        let entry = self.entry(module);
+
+        if !self.check_entry(&programs, &entry, true) {
+            return Ok(());
+        }
+
+        let Some(core) = self.lower(&programs, Some(&entry)) else {
+            return Ok(());
+        };
+
+        let start = self.start();
+        let bytecode = vulpi_vm::compile::compile(&core);
+        self.record(Phase::Codegen, None, start, Some(&bytecode));
+
+        if self.emits(Stage::Bytecode) {
+            emit::print(self.emit_format, Stage::Bytecode, &self.name.get(), &bytecode);
+        }
+
+        let mut machine = Machine::new(&bytecode);
+
+        let result = machine
+            .initialize(&entry)
+            .and_then(|_| machine.run(&entry));
+
+        result.map_err(|error| {
+            let sources = self.sources(machine.trace().iter().map(|x| &x.span));
+            let trace = vulpi_vm::trace::render(machine.trace(), &sources);
+            Crash { error, trace }
+        })?;
+
+        Ok(())
+    }
+
+    /// Compiles the crate to bytecode with its tests and runs the ones whose descriptions contain
+    /// the filter. The tests that don't pass are reported as errors.
+    pub fn test(
+        &mut self,
+        module: Symbol,
+        path: FS::Path,
+        filter: Option<&str>,
+    ) -> Option<Vec<runner::Outcome>> {
+        self.target = Target::Vm;
+
+        let programs = self.check(module, path)?;
+        let outcomes = runner::run(&programs, filter);
+
+        for outcome in &outcomes {
+            let description = outcome.description.clone();
+
+            let kind = match &outcome.verdict {
+                runner::Verdict::Passed => continue,
+                runner::Verdict::Failed => BuildErrorKind::TestFailed(description),
+                runner::Verdict::Errored(err) => {
+                    BuildErrorKind::TestErrored(description, err.to_string())
+                }
+            };
+
+            self.reporter.report(Diagnostic::new(BuildError {
+                span: outcome.span.clone(),
+                kind,
+            }));
+        }
+
+        Some(outcomes)
+    }
+
+    /// Compiles the crate to bytecode without an entry point. Only the public declarations and the
+    /// ones that they use are kept, so applications can call them without a `main`.
+    pub fn bytecode(&mut self, module: Symbol, path: FS::Path) -> Option<Bytecode> {
+        self.target = Target::Vm;
+
+        let programs = self.check(module, path)?;
+        let core = self.lower(&programs, None)?;
+        let start = self.start();
+        let bytecode = vulpi_vm::compile::compile(&core);
+        self.record(Phase::Codegen, None, start, Some(&bytecode));
+
+        if self.emits(Stage::Bytecode) {
+            emit::print(self.emit_format, Stage::Bytecode, &self.name.get(), &bytecode);
+        }
+
+        Some(bytecode)
+    }
+
+    /// Compiles the crate to an executable with the native backend. The `main` of its root module
+    /// is called when the executable starts.
+    #[cfg(feature = "native")]
+    pub fn build_native(
+        &mut self,
+        module: Symbol,
+        path: FS::Path,
+        output: PathBuf,
+    ) -> std::io::Result<()> {
+        match self.native_object(module, path) {
+            Some(object) => vulpi_codegen_native::link::link(&object, &output),
+            None => Ok(()),
+        }
+    }
+
+    /// Compiles the crate to the object file that [Self::build_native] links.
+    #[cfg(feature = "native")]
+    pub fn native_object(&mut self, module: Symbol, path: FS::Path) -> Option<Vec<u8>> {
+        self.target = Target::Native;
+
+        let programs = self.check(module.clone(), path)?;
+        let entry = self.entry(module);
+
+        if !self.check_entry(&programs, &entry, true) {
+            return None;
+        }
+
+        let core = self.lower(&programs, Some(&entry))?;
+
+        let mut spans = Vec::new();
+
+        for decl in &core.lets {
+            spans.push(decl.span.clone());
+            vulpi_core::syntax::visit_spans(&decl.body, &mut |span| spans.push(span.clone()));
+        }
+
+        let sources = self.sources(&spans);
+
+        let start = self.start();
+        let mut core = vulpi_core::monomorphize::monomorphize(self.reporter.clone(), &core)?;
+        vulpi_core::effects::elaborate(&mut core, Some(&entry));
+        let object =
+            vulpi_codegen_native::codegen::compile(self.reporter.clone(), &core, &entry, &sources);
+        self.record(Phase::Codegen, None, start, None);
+
+        object
+    }
+}
+
+/// The `main` function of the root module of a crate.
+fn main(module: Symbol) -> Qualified {
+    Qualified {
+        path: Path {
+            segments: vec![module, Symbol::intern("Main")],
+        }
+        .symbol(),
+        name: Symbol::intern("main"),
+    }
+}
+
+/// Finds a let declaration in the programs and in the modules declared inside of them.
+fn find<'a, T>(
+    programs: impl IntoIterator<Item = &'a elaborated::Program<T>>,
+    name: &Qualified,
+) -> Option<&'a elaborated::LetDecl<T>>
+where
+    T: 'a,
+{
+    programs.into_iter().find_map(|program| {
+        program
+            .lets
+            .get(name)
+            .or_else(|| find(program.modules.values(), name))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::memory::MemoryFileSystem;
+    use vulpi_core::syntax::{free_variables, Atom, TermKind, Value};
+
+    const MAIN: &str = "pub let main (x: ()) (y: ()) : () = ()
+
+pub let start (x: ()) : () = ()
+";
+
+    /// The prelude of the tests that compile programs, with the externals that they print with.
+    const PRELUDE: &str = "pub type Int
+
+pub type String
+
+pub type Bool = | False | True
+
+pub external add : Int -> Int -> Int = \"add\"
+
+pub external sub : Int -> Int -> Int = \"sub\"
+
+pub external wrapping : Int -> Int -> Int = \"wrapping_add\"
+
+pub external log : Int -> () = \"print\"
+
+pub external print : String -> () = \"print\"
+";
+
+    fn compiler(files: &[(&str, &str)], entry: Option<&str>) -> ProjectCompiler<MemoryFileSystem> {
+        let name = Symbol::intern("Proj");
+
+        let mut fs = MemoryFileSystem::new(name.clone());
+
+        for (file, source) in files {
+            fs.insert(PathBuf::from(file), source.to_string());
+        }
+
+        let mut compiler = ProjectCompiler::new(name, fs);
+
+        compiler.entry = entry.map(|entry| Qualified {
+            path: Symbol::intern("Proj.Main"),
+            name: Symbol::intern(entry),
+        });
+
+        compiler
+    }
+
+    /// A compiler of a crate whose main module uses the [PRELUDE].
+    fn with_prelude(main: &str) -> ProjectCompiler<MemoryFileSystem> {
+        compiler(&[("Prelude.vp", PRELUDE), ("Main.vp", main)], None)
+    }
+
+    fn codes(entry: Option<&str>) -> Vec<Option<usize>> {
+        let mut compiler = compiler(&[("Main.vp", MAIN)], entry);
+        let name = compiler.name.clone();
+
+        assert!(compiler.run(name, PathBuf::from("Main.vp")).is_ok());
+
+        let diagnostics = compiler.reporter.all_diagnostics();
+        diagnostics.iter().map(|x| x.code()).collect()
+    }
+
+    #[test]
+    fn checks_the_type_of_the_entry_point() {
+        assert_eq!(codes(None), vec![Some(712)]);
+        assert_eq!(codes(Some("start")), vec![]);
+        assert_eq!(codes(Some("stop")), vec![Some(711)]);
+    }
+
+    #[test]
+    fn lays_out_the_tabs_with_the_tab_width() {
+        let main = "pub let main (x: ()) : () = do\n    ()\n\t()\n";
+
+        let codes = |tab_width| {
+            let mut compiler = compiler(&[("Main.vp", main)], None);
+            compiler.tab_width = tab_width;
+
+            let name = compiler.name.clone();
+            let _ = compiler.run(name, PathBuf::from("Main.vp"));
+
+            let diagnostics = compiler.reporter.all_diagnostics();
+            diagnostics.iter().map(|x| x.code()).collect::<Vec<_>>()
+        };
+
+        // The tab is in the block only when it goes to the column of the spaces, and both widths
+        // warn that the block mixes them.
+        assert_eq!(codes(4), vec![Some(3)]);
+        assert_eq!(codes(8), vec![Some(3), Some(310)]);
+    }
+
+    #[test]
+    fn folds_the_constants_of_other_declarations() {
+        let main = "use Prelude
+
+let size : Int = add 1024 1024
+
+let twice (x: Int) : Int = add x x
+
+pub let main (x: ()) : () = log (twice size)
+";
+
+        let mut compiler = with_prelude(main);
+        compiler.optimization = 1;
+
+        let name = compiler.name.clone();
+        let script = compiler.javascript(name, PathBuf::from("Main.vp")).unwrap();
+
+        assert!(script.contains("4096"));
+        assert!(!script.contains("1024"));
+    }
+
+    /// The variables that the lambdas of a term capture, in the order that the lambdas appear.
+    fn captures(term: &TermKind, found: &mut Vec<Vec<Symbol>>) {
+        match term {
+            TermKind::Let(_, Value::Lambda(params, body), rest) => {
+                found.push(free_variables(params, body));
+                captures(body, found);
+                captures(rest, found);
+            }
+            TermKind::Let(_, _, rest) => captures(rest, found),
+            TermKind::Join(_, _, body, rest) => {
+                captures(body, found);
+                captures(rest, found);
+            }
+            TermKind::Match(_, alts, default) => {
+                alts.iter().for_each(|x| captures(&x.body, found));
+                default.iter().for_each(|x| captures(x, found));
+            }
+            TermKind::Jump(..)
+            | TermKind::Tail(..)
+            | TermKind::Return(_)
+            | TermKind::Unreachable => (),
+        }
+    }
+
+    #[test]
+    fn closures_bind_the_literals_again() {
+        let main = "use Prelude
+use Prelude.Bool
+
+let adder (x: ()) : Int -> Int = do
+  let step = 10
+  let fast = True
+  let scale = add step step
+  \\y => when fast is
+    True => add (add y step) scale
+    False => y
+
+pub let main (x: ()) : () = log (adder () 1)
+";
+
+        let mut compiler = with_prelude(main);
+        let name = compiler.name.clone();
+        let programs = compiler.check(name.clone(), PathBuf::from("Main.vp")).unwrap();
+        let entry = compiler.entry(name);
+        let core = compiler.lower(&programs, Some(&entry)).unwrap();
+
+        let adder = core.lets.iter().find(|x| x.name.name.get() == "adder").unwrap();
+
+        let mut found = Vec::new();
+        captures(&adder.body, &mut found);
+
+        let mut applications = Vec::new();
+        let mut term = &*adder.body;
+
+        while let TermKind::Let(binder, value, rest) = term {
+            if matches!(value, Value::Application(..)) {
+                applications.push(binder.name.clone());
+            }
+            term = rest;
+        }
+
+        // The literal and the tag are built again inside of the lambda, but the result of the
+        // application is only computed once, so it stays in the environment.
+        assert_eq!(found, vec![applications]);
+    }
+
+    /// Runs the `main` of the crate in the virtual machine, giving whether it finished and what it
+    /// printed.
+    fn output(compiler: &mut ProjectCompiler<MemoryFileSystem>) -> (bool, String) {
+        let name = compiler.name.clone();
+        let bytecode = compiler.bytecode(name, PathBuf::from("Main.vp")).unwrap();
+        let entry = compiler.entry(compiler.name.clone());
+
+        let mut buffer = Vec::new();
+        let mut machine = Machine::with_output(&bytecode, Box::new(&mut buffer));
+        let result = machine.initialize(&entry).and_then(|_| machine.run(&entry));
+        drop(machine);
+
+        (result.is_ok(), String::from_utf8(buffer).unwrap())
+    }
+
+    #[test]
+    fn the_virtual_machine_runs_closures_constructors_and_handlers() {
+        let main = "use Prelude
+
+type List a = | Nil | Cons a (List a)
+
+pub effect Ask where
+  pub ask () : Int
+
+let sum : List Int -> Int
+  | List.Nil => 0
+  | List.Cons x xs => add x (sum xs)
+
+let adder (n : Int) : Int -> Int = \\x => add x n
+
+let answer (x : ()) : Int =
+  handle add (Ask.ask ()) 1 with
+    cases
+      { Ask.ask u -> k } => k 41
+
+pub let twice (x : Int) : Int = add x x
+
+pub let main (x: ()) : () = do
+  log (sum (List.Cons 1 (List.Cons 2 (List.Cons 3 List.Nil))))
+  log (adder 10 5)
+  log (answer ())
+";
+
+        assert_eq!(output(&mut with_prelude(main)), (true, "6\n15\n42\n".to_string()));
+
+        let mut compiler = with_prelude(main);
+        let name = compiler.name.clone();
+        let bytecode = compiler.bytecode(name, PathBuf::from("Main.vp")).unwrap();
+
+        let twice = Qualified {
+            path: Symbol::intern("Proj.Main"),
+            name: Symbol::intern("twice"),
+        };
+
+        // Applications call the public functions with values of their own.
+        let mut machine = Machine::new(&bytecode);
+        let result = machine.apply(&twice, vec![vulpi_vm::value::Value::Int(21)]);
+        assert!(matches!(result, Ok(vulpi_vm::value::Value::Int(42))));
+    }
+
+    /// Runs the JavaScript of the crate with node, giving whether it finished and what it
+    /// printed. Gives nothing where node is not installed.
+    fn node_output(script: &str) -> Option<(bool, String)> {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+
+        static SCRIPTS: AtomicUsize = AtomicUsize::new(0);
+
+        let script_id = SCRIPTS.fetch_add(1, Ordering::Relaxed);
+        let path = std::env::temp_dir().join(format!(
+            "vulpi-script-{}-{}.js",
+            std::process::id(),
+            script_id
+        ));
+
+        std::fs::write(&path, script).unwrap();
+        let run = std::process::Command::new("node").arg(&path).output();
+        std::fs::remove_file(&path).unwrap();
+
+        let run = run.ok()?;
+        Some((run.status.success(), String::from_utf8_lossy(&run.stdout).to_string()))
+    }
+
+    #[test]
+    fn javascript_names_come_from_the_modules_and_tail_calls_are_trampolined() {
+        let main = "use Prelude
+use Prelude.Bool
+
+let isEven : Int -> Bool
+  | 0 => True
+  | n => isOdd (sub n 1)
+
+let isOdd : Int -> Bool
+  | 0 => False
+  | n => isEven (sub n 1)
+
+pub let main (x: ()) : () =
+  when isEven 1000000 is
+    True => print \"even\"
+    False => print \"odd\"
+";
+
+        let mut compiler = with_prelude(main);
+        let name = compiler.name.clone();
+        let script = compiler.javascript(name, PathBuf::from("Main.vp")).unwrap();
+
+        assert!(script.contains("Proj$Main$isEven"));
+        assert!(script.contains("Proj$Main$isOdd"));
+
+        // The mutual recursion is deeper than the stack of node.
+        if let Some(output) = node_output(&script) {
+            assert_eq!(output, (true, "even\n".to_string()));
+        }
+    }
+
+    #[test]
+    fn lowers_records_and_tuples_to_constructors_and_fields() {
+        let main = "use Prelude
+
+type Point = { x : Int, y : Int }
+
+let swap (p : Point) : (Int, Int) = (p.y, p.x)
+
+pub let main (x: ()) : () =
+  when swap (Point { x = 1, y = 2 }) is
+    (a, b) => log (add a (add b b))
+";
+
+        let mut compiler = with_prelude(main);
+        let name = compiler.name.clone();
+        let programs = compiler.check(name.clone(), PathBuf::from("Main.vp")).unwrap();
+        let entry = compiler.entry(name);
+        let core = compiler.lower(&programs, Some(&entry)).unwrap();
+
+        let swap = core.lets.iter().find(|x| x.name.name.get() == "swap").unwrap();
+
+        let mut values = Vec::new();
+        let mut term = &*swap.body;
+
+        while let TermKind::Let(_, value, rest) = term {
+            values.push(value);
+            term = rest;
+        }
+
+        // Every field is read into a variable before the tuple is built from them.
+        assert!(matches!(values[..], [Value::Field(..), Value::Field(..), Value::Constructor(..)]));
+        assert!(matches!(term, TermKind::Return(_)));
+
+        assert_eq!(output(&mut with_prelude(main)), (true, "4\n".to_string()));
+    }
+
+    #[test]
+    fn specializes_functions_and_reports_polymorphic_recursion() {
+        let main = "use Prelude
+
+let id (x : a) : a = x
+
+pub let main (x: ()) : () = do
+  log (id 1)
+  log (id 2)
+  print (id \"three\")
+";
+
+        let mut compiler = with_prelude(main);
+        let name = compiler.name.clone();
+        let programs = compiler.check(name.clone(), PathBuf::from("Main.vp")).unwrap();
+        let entry = compiler.entry(name);
+        let core = compiler.lower(&programs, Some(&entry)).unwrap();
+
+        let reporter = compiler.reporter.clone();
+        let specialized = vulpi_core::monomorphize::monomorphize(reporter, &core).unwrap();
+
+        let mut copies: Vec<_> = specialized
+            .lets
+            .iter()
+            .map(|x| x.name.name.get())
+            .filter(|x| x.starts_with("id"))
+            .collect();
+
+        copies.sort();
+
+        // The two calls with integers share the same copy.
+        assert_eq!(copies, vec!["id.0", "id.1"]);
+        assert!(specialized.lets.iter().all(|x| x.typ.binders() == 0));
+
+        let main = "use Prelude
+
+let nest (n : Int) (x : a) : Int =
+  when n is
+    0 => 0
+    _ => nest (sub n 1) (x, x)
+
+pub let main (x: ()) : () = log (nest 3 1)
+";
+
+        let mut compiler = with_prelude(main);
+        let name = compiler.name.clone();
+        let programs = compiler.check(name.clone(), PathBuf::from("Main.vp")).unwrap();
+        let entry = compiler.entry(name);
+        let core = compiler.lower(&programs, Some(&entry)).unwrap();
+
+        let reporter = compiler.reporter.clone();
+        assert!(vulpi_core::monomorphize::monomorphize(reporter, &core).is_none());
+
+        let diagnostics = compiler.reporter.all_diagnostics();
+        let codes: Vec<_> = diagnostics.iter().map(|x| x.code()).collect();
+        assert_eq!(codes, vec![Some(400)]);
+    }
+
+    /// The names of the functions that a term calls.
+    fn callees(term: &TermKind, found: &mut HashSet<String>) {
+        let mut callee = |atom: &Atom| {
+            if let Atom::Function(name, _) = atom {
+                found.insert(name.name.get());
+            }
+        };
+
+        match term {
+            TermKind::Let(_, value, rest) => {
+                match value {
+                    Value::Application(func, _, _) => callee(func),
+                    Value::Lambda(_, body) => callees(body, found),
+                    _ => (),
+                }
+                callees(rest, found);
+            }
+            TermKind::Tail(func, _, _) => callee(func),
+            TermKind::Join(_, _, body, rest) => {
+                callees(body, found);
+                callees(rest, found);
+            }
+            TermKind::Match(_, alts, default) => {
+                alts.iter().for_each(|x| callees(&x.body, found));
+                default.iter().for_each(|x| callees(x, found));
+            }
+            TermKind::Jump(..) | TermKind::Return(_) | TermKind::Unreachable => (),
+        }
+    }
+
+    #[test]
+    fn inlines_the_small_functions_that_dont_call_themselves() {
+        let main = "use Prelude
+use Prelude.Bool
+
+let double (x : Int) : Int = add x x
+
+let pick : Bool -> Int
+  | True => 1
+  | False => 2
+
+let count : Int -> Int
+  | 0 => 0
+  | n => add 1 (count (sub n 1))
+
+pub let compute (y : Int) : Int = add (double y) (add (pick True) (count y))
+
+pub let main (x: ()) : () = log (compute 3)
+";
+
+        let called = |optimization| {
+            let mut compiler = with_prelude(main);
+            compiler.optimization = optimization;
+
+            let name = compiler.name.clone();
+            let programs = compiler.check(name.clone(), PathBuf::from("Main.vp")).unwrap();
+            let entry = compiler.entry(name);
+            let core = compiler.lower(&programs, Some(&entry)).unwrap();
+
+            let compute = core.lets.iter().find(|x| x.name.name.get() == "compute").unwrap();
+
+            let mut found = HashSet::new();
+            callees(&compute.body, &mut found);
+
+            let mut found: Vec<_> = found.into_iter().collect();
+            found.sort();
+            found
+        };
+
+        assert_eq!(called(0), vec!["add", "count", "double", "pick"]);
+
+        // `double` and `pick` are small enough to be inlined, but `count` calls itself.
+        assert_eq!(called(1), vec!["add", "count"]);
+    }
+
+    #[test]
+    fn removes_the_declarations_that_main_doesnt_reach() {
+        let main = "use Prelude
+
+let used (x : Int) : Int = add x 1
+
+let unused (x : Int) : Int = used (sub x 1)
+
+pub let exported (x : Int) : Int = x
+
+pub let main (x: ()) : () = log (used 1)
+";
+
+        let script = |unused| {
+            let mut compiler = with_prelude(main);
+            compiler.unused = unused;
+
+            let name = compiler.name.clone();
+            let script = compiler.javascript(name, PathBuf::from("Main.vp")).unwrap();
+
+            let diagnostics = compiler.reporter.all_diagnostics();
+            let codes: Vec<_> = diagnostics.iter().map(|x| x.code()).collect();
+            (script, codes)
+        };
+
+        let (removed, codes) = script(false);
+        assert!(removed.contains("Proj$Main$used"));
+        assert!(removed.contains("Proj$Main$exported"));
+        assert!(!removed.contains("Proj$Main$unused"));
+        assert_eq!(codes, vec![]);
+
+        // The public declarations are kept, so only the private one is reported.
+        let (_, codes) = script(true);
+        assert_eq!(codes, vec![Some(401)]);
+    }
+
+    #[test]
+    fn orders_the_values_and_reports_their_cycles() {
+        let main = "use Prelude
+
+let late : Int = add early 1
+
+let early : Int = do
+  log 5
+  2
+
+pub let main (x: ()) : () = log late
+";
+
+        let mut ordered = with_prelude(main);
+        let name = ordered.name.clone();
+        let script = ordered.javascript(name, PathBuf::from("Main.vp")).unwrap();
+
+        assert!(script.find("early =").unwrap() < script.find("late =").unwrap());
+
+        let main = "use Prelude
+
+let first : Int = add (second ()) 1
+
+let second (x: ()) : Int = first
+
+pub let main (x: ()) : () = log first
+";
+
+        let mut compiler = with_prelude(main);
+        let name = compiler.name.clone();
+
+        assert!(compiler.javascript(name, PathBuf::from("Main.vp")).is_none());
+
+        let diagnostics = compiler.reporter.all_diagnostics();
+        let codes: Vec<_> = diagnostics.iter().map(|x| x.code()).collect();
+        assert_eq!(codes, vec![Some(402)]);
+    }
+
+    #[test]
+    fn modules_parsed_in_parallel_are_resolved_together() {
+        let modules: Vec<_> = (0..16)
+            .map(|i| {
+                let source = if i % 5 == 4 {
+                    format!("use Prelude\n\npub let value{i} : Int = (\n")
+                } else {
+                    format!("use Prelude\n\npub let value{i} : Int = {i}\n")
+                };
+
+                (format!("Part{i}.vp"), source)
+            })
+            .collect();
+
+        let uses: String = (0..16).map(|i| format!("use Part{i}\n")).collect();
+        let sum = (0..16).fold("0".to_string(), |sum, i| format!("(add Part{i}.value{i} {sum})"));
+        let main = format!("use Prelude\n{uses}\npub let main (x: ()) : () = log {sum}\n");
+
+        let diagnostics = || {
+            let mut files = vec![("Prelude.vp", PRELUDE), ("Main.vp", main.as_str())];
+            files.extend(modules.iter().map(|(file, source)| (file.as_str(), source.as_str())));
+
+            let mut compiler = compiler(&files, None);
+            let name = compiler.name.clone();
+            let checked = compiler.check(name, PathBuf::from("Main.vp")).is_some();
+
+            let diagnostics = compiler.reporter.all_diagnostics();
+            let mut locations: Vec<_> = diagnostics
+                .iter()
+                .map(|x| (x.location().file, x.location().start.0, x.code()))
+                .collect();
+
+            locations.sort();
+            (checked, locations)
+        };
+
+        // Each broken module reports its syntax error once, whatever the thread that parsed it,
+        // and the main module can't find the values that they don't declare.
+        let (checked, first) = diagnostics();
+        assert!(!checked);
+        assert_eq!(first.iter().filter(|x| x.2 == Some(100)).count(), 3);
+        assert_eq!(first.iter().filter(|x| x.2 == Some(200)).count(), 3);
+
+        for _ in 0..4 {
+            assert_eq!(diagnostics(), (false, first.clone()));
+        }
+    }
+
+    #[test]
+    fn constructors_of_cached_modules_are_found() {
+        let prelude = "pub type Bool = | True | False
+
+pub external log : Bool -> () = \"print\"
+";
+
+        let directory = std::env::temp_dir().join(format!("vulpi-cache-{}", std::process::id()));
+
+        let check = |main: &str| {
+            let main = format!(
+                "use Prelude\nuse Prelude.Bool\n\npub let main (x: ()) : () = log {main}\n"
+            );
+            let mut compiler = compiler(&[("Prelude.vp", prelude), ("Main.vp", &main)], None);
+            compiler.cache = Some(Cache::new(directory.clone()));
+
+            let name = compiler.name.clone();
+            let checked = compiler.check(name, PathBuf::from("Main.vp")).is_some();
+            (checked, compiler.reporter.all_diagnostics().len())
+        };
+
+        assert_eq!(check("False"), (true, 0));
+
+        // The prelude didn't change, so it's loaded from the cache this time.
+        assert_eq!(check("True"), (true, 0));
+
+        std::fs::remove_dir_all(&directory).unwrap();
+    }
+
+    #[cfg(feature = "native")]
+    #[test]
+    fn native_tail_calls_dont_grow_the_stack() {
+        let main = "use Prelude
+use Prelude.Bool
+
+let isEven : Int -> Bool
+  | 0 => True
+  | n => isOdd (sub n 1)
+
+let isOdd : Int -> Bool
+  | 0 => False
+  | n => isEven (sub n 1)
+
+pub let main (x: ()) : () =
+  when isEven 1000000 is
+    True => print \"even\"
+    False => print \"odd\"
+";
+
+        let directory = std::env::temp_dir().join(format!("vulpi-native-{}", std::process::id()));
+        std::fs::create_dir_all(&directory).unwrap();
+        let output = directory.join("main");
+
+        let mut compiler = with_prelude(main);
+        let name = compiler.name.clone();
+        compiler.build_native(name, PathBuf::from("Main.vp"), output.clone()).unwrap();
+        assert!(!compiler.reporter.has_errors());
+
+        let run = std::process::Command::new(&output).output().unwrap();
+        std::fs::remove_dir_all(&directory).unwrap();
+
+        assert!(run.status.success());
+        assert_eq!(String::from_utf8_lossy(&run.stdout).trim(), "even");
+    }
+
+    /// A program whose handlers resume more than once, are named, are masked and have a `finally`.
+    const HANDLERS: &str = "use Prelude
+use Prelude.Bool
+
+pub effect Choice where
+  pub choose () : Bool
+
+pub effect Log where
+  pub log Int : ()
+
+let pick (x : ()) : Int =
+  when Choice.choose () is
+    True => 1
+    False => 2
+
+let both (x : ()) : Int =
+  handle add (pick ()) (pick ()) with
+    cases
+      { Choice.choose u -> k } => add (k True) (k False)
+
+let logs (x : Int) : Int = do
+  Log.log x
+  mask<Log> Log.log (add x 1)
+  add x 2
+
+let masked (x : ()) : Int =
+  handle handle logs 10 with
+      cases
+        { Log.log y -> k } => do
+          log y
+          k ()
+    with
+      cases
+        { Log.log y -> k } => do
+          print \"outer\"
+          log y
+          k ()
+    finally print \"done\"
+
+let named (x : ()) : Int =
+  handle h =
+    cases
+      { Log.log y -> k } => add y (k ())
+  in do
+    h.log 5
+    h.log 6
+    7
+
+pub let main (x : ()) : () = do
+  log (both ())
+  log (masked ())
+  log (named ())
+";
+
+    const HANDLED: &str = "12\n10\nouter\n11\ndone\n12\n18\n";
+
+    #[test]
+    fn handlers_run_in_the_virtual_machine_and_in_javascript() {
+        let mut compiler = with_prelude(HANDLERS);
+        let name = compiler.name.clone();
+        let bytecode = compiler.bytecode(name, PathBuf::from("Main.vp")).unwrap();
+        let entry = compiler.entry(compiler.name.clone());
+
+        let mut buffer = Vec::new();
+        let mut machine = Machine::with_output(&bytecode, Box::new(&mut buffer));
+        let result = machine.initialize(&entry).and_then(|_| machine.run(&entry));
+        drop(machine);
+
+        assert!(result.is_ok());
+        assert_eq!(String::from_utf8(buffer).unwrap(), HANDLED);
+
+        let mut compiler = with_prelude(HANDLERS);
+        let name = compiler.name.clone();
+        let script = compiler.javascript(name, PathBuf::from("Main.vp")).unwrap();
+
+        let path = std::env::temp_dir().join(format!("vulpi-handlers-{}.js", std::process::id()));
+        std::fs::write(&path, script).unwrap();
+        let run = std::process::Command::new("node").arg(&path).output();
+        std::fs::remove_file(&path).unwrap();
+
+        // The script is only run where node is installed.
+        if let Ok(run) = run {
+            assert!(run.status.success());
+            assert_eq!(String::from_utf8_lossy(&run.stdout), HANDLED);
+        }
+    }
+
+    #[cfg(feature = "native")]
+    #[test]
+    fn native_handlers_resume_their_continuations() {
+        let directory = std::env::temp_dir().join(format!("vulpi-handlers-{}", std::process::id()));
+        std::fs::create_dir_all(&directory).unwrap();
+        let output = directory.join("main");
+
+        let mut compiler = with_prelude(HANDLERS);
+        let name = compiler.name.clone();
+        compiler.build_native(name, PathBuf::from("Main.vp"), output.clone()).unwrap();
+        assert!(!compiler.reporter.has_errors());
+
+        let run = std::process::Command::new(&output).output().unwrap();
+        std::fs::remove_dir_all(&directory).unwrap();
+
+        assert!(run.status.success());
+        assert_eq!(String::from_utf8_lossy(&run.stdout), HANDLED);
+    }
+
+    /// Builds the crate with the native backend and runs the executable, giving whether it
+    /// finished and what it printed.
+    #[cfg(feature = "native")]
+    fn native_output(mut compiler: ProjectCompiler<MemoryFileSystem>) -> (bool, String) {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+
+        // The tests run at the same time, so each executable gets a directory of its own.
+        static BUILDS: AtomicUsize = AtomicUsize::new(0);
+
+        let build = BUILDS.fetch_add(1, Ordering::Relaxed);
+        let directory = std::env::temp_dir().join(format!(
+            "vulpi-native-{}-{}",
+            std::process::id(),
+            build
+        ));
+
+        std::fs::create_dir_all(&directory).unwrap();
+        let output = directory.join("main");
+
+        let name = compiler.name.clone();
+        compiler.build_native(name, PathBuf::from("Main.vp"), output.clone()).unwrap();
+        assert!(!compiler.reporter.has_errors());
+
+        // The address space of the executables is limited to 100 MB, so the ones that don't
+        // free their garbage fail.
+        let run = std::process::Command::new("sh")
+            .args(["-c", "ulimit -v 100000 && exec \"$0\""])
+            .arg(&output)
+            .output()
+            .unwrap();
+
+        std::fs::remove_dir_all(&directory).unwrap();
+
+        (run.status.success(), String::from_utf8_lossy(&run.stdout).to_string())
+    }
+
+    #[cfg(feature = "native")]
+    #[test]
+    fn native_programs_collect_their_garbage() {
+        let main = "use Prelude
+
+type List a = | Nil | Cons a (List a)
+
+let build (n : Int) (acc : List Int) : List Int =
+  when n is
+    0 => acc
+    _ => build (sub n 1) (List.Cons n acc)
+
+let total : List Int -> Int -> Int
+  | List.Nil, acc => acc
+  | List.Cons x xs, acc => total xs (add acc x)
+
+let repeat (n : Int) (last : Int) : Int =
+  when n is
+    0 => last
+    _ => repeat (sub n 1) (total (build 1000 List.Nil) 0)
+
+pub let main (x: ()) : () = log (repeat 20000 0)
+";
+
+        // The lists take hundreds of megabytes together, but only one of them is alive at a time.
+        assert_eq!(native_output(with_prelude(main)), (true, "500500\n".to_string()));
+    }
+
+    #[test]
+    fn c_functions_are_called_by_their_symbols_or_by_the_host() {
+        let main = "use Prelude
+
+external \"c\" labs : Int -> Int = \"labs\"
+
+external \"c\" strlen : String -> Int = \"strlen\"
+
+pub let main (x: ()) : () = do
+  log (labs (sub 0 42))
+  log (strlen \"héllo\")
+";
+
+        let expected = "42\n6\n";
+
+        let mut compiler = with_prelude(main);
+        let name = compiler.name.clone();
+        let bytecode = compiler.bytecode(name, PathBuf::from("Main.vp")).unwrap();
+        let entry = compiler.entry(compiler.name.clone());
+
+        let run = |register: bool| {
+            let mut buffer = Vec::new();
+            let mut machine = Machine::with_output(&bytecode, Box::new(&mut buffer));
+
+            if register {
+                machine.host().register1("labs", |x: i64| x.abs());
+                machine.host().register1("strlen", |x: String| x.len() as i64);
+            }
+
+            let result = machine.initialize(&entry).and_then(|_| machine.run(&entry));
+            drop(machine);
+
+            (result.is_ok(), String::from_utf8(buffer).unwrap())
+        };
+
+        // The virtual machine calls the Rust versions that the application registers.
+        assert_eq!(run(false), (false, String::new()));
+        assert_eq!(run(true), (true, expected.to_string()));
+
+        #[cfg(feature = "native")]
+        assert_eq!(native_output(with_prelude(main)), (true, expected.to_string()));
+    }
+
+    #[test]
+    fn primitives_give_the_same_results_in_every_backend() {
+        let prelude = format!(
+            "{PRELUDE}
+pub type Char
+
+pub external concat : String -> String -> String = \"concat\"
+
+pub external length : String -> Int = \"length\"
+
+pub external index : String -> Int -> Char = \"index\"
+
+pub external div : Int -> Int -> Int = \"div\"
+
+pub external rem : Int -> Int -> Int = \"rem\"
+
+pub external lt : Int -> Int -> Bool = \"lt\"
+
+pub external ord : Char -> Int = \"ord\"
+
+pub external chr : Int -> Char = \"chr\"
+
+pub external showInt : Int -> String = \"int_to_string\"
+
+pub external showChar : Char -> String = \"char_to_string\"
+"
+        );
+
+        let main = "use Prelude
+use Prelude.Bool
+
+pub let main (x: ()) : () = do
+  print (concat \"héllo\" \" world\")
+  log (length \"héllo\")
+  print (showChar (index \"héllo\" 1))
+  log (div (sub 0 7) 2)
+  log (rem (sub 0 7) 2)
+  log (ord (chr 955))
+  print (showInt (add 40 2))
+  when lt 1 2 is
+    True => print \"less\"
+    False => print \"more\"
+";
+
+        let files = [("Prelude.vp", prelude.as_str()), ("Main.vp", main)];
+        let expected = "héllo world\n5\né\n-3\n-1\n955\n42\nless\n";
+
+        let mut vm = compiler(&files, None);
+        assert_eq!(output(&mut vm), (true, expected.to_string()));
+
+        let mut js = compiler(&files, None);
+        let name = js.name.clone();
+        let script = js.javascript(name, PathBuf::from("Main.vp")).unwrap();
+
+        if let Some(output) = node_output(&script) {
+            assert_eq!(output, (true, expected.to_string()));
+        }
+
+        #[cfg(feature = "native")]
+        assert_eq!(native_output(compiler(&files, None)), (true, expected.to_string()));
+    }
+
+    #[cfg(feature = "native")]
+    #[test]
+    fn native_programs_print_what_the_virtual_machine_prints() {
+        let main = "use Prelude
+
+type Shape = | Circle Int | Square Int Int
+
+let area : Shape -> Int
+  | Shape.Circle r => add r (add r r)
+  | Shape.Square w h => add w h
+
+let compose (f : Int -> Int) (g : Int -> Int) : Int -> Int = \\x => f (g x)
+
+pub let main (x: ()) : () = do
+  log (area (Shape.Circle 2))
+  log (area (Shape.Square 3 4))
+  log (compose (add 1) (sub 10) 4)
+  print \"done\"
+";
+
+        let expected = (true, "6\n7\n7\ndone\n".to_string());
+
+        assert_eq!(output(&mut with_prelude(main)), expected);
+        assert_eq!(native_output(with_prelude(main)), expected);
+    }
+
+    #[test]
+    fn integer_arithmetic_overflows_by_the_configuration() {
+        let main = "use Prelude
+
+pub let main (x: ()) : () = do
+  log (wrapping 9223372036854775807 1)
+  log (sub (sub 0 9223372036854775807) 2)
+  log (add 9223372036854775807 1)
+";
+
+        let output = |overflow| {
+            let mut compiler = with_prelude(main);
+            compiler.overflow = overflow;
+
+            let name = compiler.name.clone();
+            let bytecode = compiler.bytecode(name, PathBuf::from("Main.vp")).unwrap();
+            let entry = compiler.entry(compiler.name.clone());
+
+            let mut buffer = Vec::new();
+            let mut machine = Machine::with_output(&bytecode, Box::new(&mut buffer));
+            let result = machine.initialize(&entry).and_then(|_| machine.run(&entry));
+            drop(machine);
+
+            (result.is_ok(), String::from_utf8(buffer).unwrap())
+        };
+
+        let min = "-9223372036854775808";
+        let max = "9223372036854775807";
+
+        let wrap = format!("{min}\n{max}\n{min}\n");
+        assert_eq!(output(Overflow::Wrap), (true, wrap));
+
+        let check = format!("{min}\n");
+        assert_eq!(output(Overflow::Check), (false, check));
+
+        let saturate = format!("{min}\n{min}\n{max}\n");
+        assert_eq!(output(Overflow::Saturate), (true, saturate));
+    }
+
+    #[test]
+    fn resolved_programs_and_typed_interfaces_are_serialized() {
+        let source = format!(
+            "{PRELUDE}
+pub type Pair a = | Pair a a
+
+pub let swap (pair : Pair a) : Pair a =
+  when pair is
+    Pair.Pair x y => Pair.Pair y x
+
+pub let double (x : Int) : Int = add x x
+"
+        );
+
+        let reporter = vulpi_report::hash_reporter();
+        let parsed = vulpi_parser::parse(reporter.clone(), FileId(0), &source);
+
+        let available: Rc<RefCell<HashMap<Path, Module>>> = Default::default();
+        let path = Path {
+            segments: vec![Symbol::intern("Prelude")],
+        };
+
+        let context = Context::new(available.clone(), path, reporter.clone());
+        let solver = vulpi_resolver::resolve(&context, parsed);
+        context.module.register(&mut available.borrow_mut());
+        let program = solver.eval(context);
+
+        // The tree that is read back is the same one, with the same spans.
+        let json = serde_json::to_string(&program).unwrap();
+        let read: vulpi_syntax::r#abstract::Program = serde_json::from_str(&json).unwrap();
+
+        assert_eq!(read.show().to_string(), program.show().to_string());
+        assert!(json.contains("\"swap\""));
+
+        let mut ctx = vulpi_typer::Context::new(reporter.clone());
+        let programs = Programs(vec![program]);
+
+        Declare::declare(&programs, (&mut ctx, vulpi_typer::Env::default()));
+        Declare::define(&programs, (&mut ctx, vulpi_typer::Env::default()));
+        assert!(!reporter.has_errors());
+
+        let json = serde_json::to_string(&ctx.modules).unwrap();
+        let read: vulpi_typer::module::Modules = serde_json::from_str(&json).unwrap();
 
-        
-        if !self.reporter.has_errors() {
-            let mut res = transform::Transform::transform(&vulpi_ir::transform::Programs(programs), &mut Default::default());
-            
-            uncurry::uncurry(&mut res);
-            inline::inline(&mut res);
-            dead_code::dead_code_remove(&mut res);
-            
-            let js = vulpi_js::Transform::transform(vulpi_js::Programs(res), &mut Default::default());
-            let f = File::create(output).unwrap();
-            let mut w = Writer::new(f);
-
-            w.write_program(&js).unwrap();
-        }
-        
+        assert_eq!(serde_json::to_string(&read).unwrap(), json);
+        assert!(json.contains("\"double\""));
     }
 }
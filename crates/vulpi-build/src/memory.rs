@@ -0,0 +1,131 @@
+//! A file system that keeps the sources in memory. It's used to compile programs that don't come
+//! from a directory, like the scripts of applications that embed the language. Paths are relative
+//! and are created the same way as in the [crate::real::RealFileSystem], so the module `A.B` of
+//! the crate is in `A/B.vp`.
+
+use std::{collections::HashMap, path::PathBuf};
+
+use filetime::FileTime;
+use vulpi_intern::Symbol;
+use vulpi_location::FileId;
+use vulpi_vfs::{path::Path, Error};
+
+use super::FileSystem;
+
+#[derive(Clone)]
+pub struct MemoryFileSystem {
+    root: Symbol,
+    sources: HashMap<PathBuf, String>,
+    file_map: HashMap<FileId, (PathBuf, String)>,
+    path_map: HashMap<PathBuf, FileId>,
+    counter: usize,
+}
+
+impl MemoryFileSystem {
+    pub fn new(root: Symbol) -> Self {
+        Self {
+            root,
+            sources: HashMap::new(),
+            file_map: HashMap::new(),
+            path_map: HashMap::new(),
+            counter: 0,
+        }
+    }
+
+    /// Adds a source file that can be loaded later.
+    pub fn insert(&mut self, path: PathBuf, content: String) {
+        self.sources.insert(path, content);
+    }
+}
+
+impl FileSystem for MemoryFileSystem {
+    type Path = PathBuf;
+
+    fn load(&mut self, path: PathBuf) -> Result<FileId, Error> {
+        if let Some(id) = self.path_map.get(&path) {
+            return Ok(*id);
+        }
+
+        let content = self
+            .sources
+            .get(&path)
+            .cloned()
+            .ok_or_else(|| Error::NotFound(path.clone()))?;
+
+        let id = FileId(self.counter);
+        self.counter += 1;
+
+        self.file_map.insert(id, (path.clone(), content));
+        self.path_map.insert(path, id);
+
+        Ok(id)
+    }
+
+    fn unload(&mut self, id: FileId) -> Result<(), Error> {
+        self.file_map.remove(&id).ok_or(Error::NotFoundId)?;
+        Ok(())
+    }
+
+    fn store(&mut self, id: FileId, content: String) -> Result<(), Error> {
+        let file = self.file_map.get_mut(&id).ok_or(Error::NotFoundId)?;
+        file.1 = content;
+        Ok(())
+    }
+
+    fn read(&self, id: FileId) -> Result<String, Error> {
+        let file = self.file_map.get(&id).ok_or(Error::NotFoundId)?;
+        Ok(file.1.clone())
+    }
+
+    fn create(&mut self, path: PathBuf) -> Result<FileId, Error> {
+        if self.sources.contains_key(&path) || self.path_map.contains_key(&path) {
+            return Err(Error::AlreadyExists);
+        }
+
+        let id = FileId(self.counter);
+        self.counter += 1;
+
+        self.file_map.insert(id, (path.clone(), String::new()));
+        self.path_map.insert(path, id);
+
+        Ok(id)
+    }
+
+    fn write(&mut self, id: FileId) -> Result<(), Error> {
+        let (path, content) = self.file_map.get(&id).ok_or(Error::NotFoundId)?;
+        self.sources.insert(path.clone(), content.clone());
+        Ok(())
+    }
+
+    fn delete(&mut self, id: FileId) -> Result<(), Error> {
+        let (path, _) = self.file_map.get(&id).ok_or(Error::NotFoundId)?;
+        self.sources.remove(path);
+        Ok(())
+    }
+
+    fn path(&self, id: FileId) -> Result<&PathBuf, Error> {
+        let file = self.file_map.get(&id).ok_or(Error::NotFoundId)?;
+        Ok(&file.0)
+    }
+
+    /// Files in memory never change after they're added, so all of them have the same time.
+    fn modification_time(&self, path: PathBuf) -> Result<FileTime, Error> {
+        if self.sources.contains_key(&path) {
+            Ok(FileTime::zero())
+        } else {
+            Err(Error::NotFound(path))
+        }
+    }
+
+    fn from_cached_path(&self, path: Path) -> Self::Path {
+        path.to_pathbuf(PathBuf::from("build"))
+    }
+
+    fn from_src_path(&self, path: Path) -> Self::Path {
+        if self.root == path.segments[0] {
+            path.shift().to_pathbuf(PathBuf::new())
+        } else {
+            path.to_pathbuf(PathBuf::new())
+        }
+    }
+}
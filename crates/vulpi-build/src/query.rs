@@ -0,0 +1,851 @@
+//! Incremental compilation with queries. Every step of the pipeline is a query of a module or of a
+//! file, like its tokens, its concrete tree or its typed program, and the results are kept in a
+//! [Database] with the queries that were read to compute them. When a source changes, a result is
+//! only computed again if one of the queries that it read changed since it was verified.
+//!
+//! Results that are computed again but are equal to the old ones keep their old revision, so the
+//! queries that read them don't change (early cutoff). Modules read the [Database::namespace] of
+//! the ones that they import, that doesn't contain the bodies of the let declarations, so editing
+//! a body only type checks its own module again.
+//!
+//! Modules without sources are loaded from their interface files in the build directory, like the
+//! ones of the crates that the crate depends on.
+
+use std::{
+    cell::RefCell,
+    collections::{hash_map::DefaultHasher, HashMap, HashSet},
+    hash::{Hash, Hasher},
+    path::{Path as FilePath, PathBuf},
+    rc::Rc,
+};
+
+use vulpi_intern::Symbol;
+use vulpi_location::{Byte, FileId, Span};
+use vulpi_report::{Detached, Diagnostic, Report};
+use vulpi_resolver::{dependencies, Context};
+use vulpi_syntax::{
+    concrete::tree::{LetMode, Program, TopLevel},
+    elaborated, r#abstract,
+    tokens::Token,
+};
+use vulpi_typer::{declare::Programs, real::Real, Type};
+use vulpi_vfs::{path::Path, FileSystem};
+
+use crate::{
+    cache::Artifact,
+    cfg::{self, Target},
+    emit,
+    error::{BuildError, BuildErrorKind},
+    interface::{self, SourceMap},
+};
+
+/// The number of changes made to the sources of a [Database].
+pub type Revision = usize;
+
+/// A module after it's resolved.
+pub struct Resolved {
+    /// The programs of the module and of the modules with sources that it uses, sorted by their
+    /// paths.
+    pub programs: Vec<(Path, r#abstract::Program)>,
+
+    /// The namespace of the module, with the names that refer to the declarations that it uses.
+    pub module: vulpi_resolver::Module,
+}
+
+/// A module that is loaded from its interface file.
+pub struct Library {
+    pub artifact: Artifact,
+    pub sources: SourceMap,
+}
+
+#[derive(Clone, PartialEq, Eq, Hash)]
+pub enum Key {
+    Source(PathBuf),
+    Library(Path),
+    Tokens(PathBuf),
+    Cst(PathBuf),
+    Imports(Path),
+    Namespace(Path),
+    Resolved(Path),
+    Typed(Path),
+}
+
+#[derive(Clone)]
+enum Value {
+    Source(Option<(FileId, Rc<String>)>),
+    Library(Option<Result<Rc<Library>, String>>),
+    Tokens(Rc<Vec<Token>>),
+    Cst(Rc<Program>),
+    Imports(Rc<Vec<Path>>),
+    Namespace(Rc<String>),
+    Resolved(Rc<Resolved>),
+    Typed(Rc<elaborated::Program<Type<Real>>>),
+}
+
+impl Value {
+    /// A hash of the result that is compared with the old one to know if it changed. Results
+    /// without one always change when they're computed again.
+    fn fingerprint(&self) -> Option<u64> {
+        let mut hasher = DefaultHasher::new();
+
+        match self {
+            Value::Source(source) => source.as_ref().map(|x| &x.1).hash(&mut hasher),
+            Value::Tokens(tokens) => {
+                for token in tokens.iter() {
+                    for comment in &token.comments {
+                        comment.comment.data.hash(&mut hasher);
+                    }
+                    token.whitespace.data.hash(&mut hasher);
+                    token.value.data.hash(&mut hasher);
+                }
+            }
+            Value::Imports(imports) => imports.hash(&mut hasher),
+            Value::Namespace(namespace) => namespace.hash(&mut hasher),
+            Value::Library(_) | Value::Cst(_) | Value::Resolved(_) | Value::Typed(_) => {
+                return None
+            }
+        }
+
+        Some(hasher.finish())
+    }
+}
+
+struct Memo {
+    value: Value,
+    fingerprint: Option<u64>,
+    diagnostics: Rc<Vec<Detached>>,
+    dependencies: Vec<Key>,
+
+    /// The revision in which the value last changed.
+    changed_at: Revision,
+
+    /// The last revision in which the value was known to be up to date.
+    verified_at: Revision,
+}
+
+/// The results of the queries of a crate. The sources are read from the file system the first time
+/// that they're needed and are changed with [Database::set_source].
+pub struct Database<FS: FileSystem> {
+    pub fs: FS,
+    pub name: Symbol,
+
+    /// The file of the root module of the crate.
+    pub root: PathBuf,
+
+    /// The backend whose `when target` declarations are kept.
+    target: Target,
+
+    /// The number of columns between two tab stops of the layout of the sources.
+    tab_width: usize,
+
+    revision: Revision,
+    memos: HashMap<Key, Memo>,
+
+    /// The queries read by each of the queries that are being computed.
+    stack: Vec<Vec<Key>>,
+}
+
+impl<FS: FileSystem<Path = PathBuf>> Database<FS> {
+    pub fn new(fs: FS, name: Symbol, root: PathBuf, target: Target) -> Self {
+        Self {
+            fs,
+            name,
+            root,
+            target,
+            tab_width: vulpi_lexer::TAB_WIDTH,
+            revision: 0,
+            memos: HashMap::new(),
+            stack: Vec::new(),
+        }
+    }
+
+    /// Sets the number of columns between two tab stops of the layout of the sources.
+    pub fn with_tab_width(mut self, width: usize) -> Self {
+        self.tab_width = width;
+        self
+    }
+
+    pub fn revision(&self) -> Revision {
+        self.revision
+    }
+
+    /// The path of the root module of the crate.
+    pub fn root_module(&self) -> Path {
+        Path {
+            segments: vec![self.name.clone(), Symbol::intern("Main")],
+        }
+    }
+
+    /// The file of a module of the crate.
+    pub fn file(&self, module: &Path) -> PathBuf {
+        if *module == self.root_module() {
+            self.root.clone()
+        } else {
+            self.fs.from_src_path(module.clone())
+        }
+    }
+
+    /// Changes the source of a file that is in the file system. It starts a new revision if the
+    /// source is different from the old one.
+    pub fn set_source(&mut self, path: PathBuf, source: String) -> Result<(), vulpi_vfs::Error> {
+        let id = self.fs.load(path.clone())?;
+        self.fs.store(id, source.clone())?;
+
+        self.set_input(path, Value::Source(Some((id, Rc::new(source)))));
+
+        Ok(())
+    }
+
+    /// Marks a file as deleted, so the modules that import it cannot find it anymore.
+    pub fn remove_source(&mut self, path: PathBuf) {
+        self.set_input(path, Value::Source(None));
+    }
+
+    fn set_input(&mut self, path: PathBuf, value: Value) {
+        let key = Key::Source(path);
+        let fingerprint = value.fingerprint();
+
+        if let Some(memo) = self.memos.get(&key) {
+            if memo.fingerprint == fingerprint {
+                return;
+            }
+        }
+
+        self.revision += 1;
+
+        self.memos.insert(
+            key,
+            Memo {
+                value,
+                fingerprint,
+                diagnostics: Default::default(),
+                dependencies: Vec::new(),
+                changed_at: self.revision,
+                verified_at: self.revision,
+            },
+        );
+    }
+
+    /// The contents of a file, if it exists.
+    pub fn source(&mut self, file: &FilePath) -> Option<(FileId, Rc<String>)> {
+        match self.query(Key::Source(file.to_path_buf()), true) {
+            Value::Source(source) => source,
+            _ => unreachable!(),
+        }
+    }
+
+    /// The interface file of a module, if it exists, or the reason why it cannot be read.
+    pub fn library(&mut self, module: &Path) -> Option<Result<Rc<Library>, String>> {
+        match self.query(Key::Library(module.clone()), true) {
+            Value::Library(library) => library,
+            _ => unreachable!(),
+        }
+    }
+
+    /// The tokens of a file, without the layout ones that the parser creates.
+    pub fn tokens(&mut self, file: &FilePath) -> Rc<Vec<Token>> {
+        match self.query(Key::Tokens(file.to_path_buf()), true) {
+            Value::Tokens(tokens) => tokens,
+            _ => unreachable!(),
+        }
+    }
+
+    /// The concrete syntax tree of a file.
+    pub fn cst(&mut self, file: &FilePath) -> Rc<Program> {
+        match self.query(Key::Cst(file.to_path_buf()), true) {
+            Value::Cst(program) => program,
+            _ => unreachable!(),
+        }
+    }
+
+    /// The modules with files that a module imports. Modules that are declared inside of the file
+    /// of another one are replaced by it.
+    pub fn imports(&mut self, module: &Path) -> Rc<Vec<Path>> {
+        match self.query(Key::Imports(module.clone()), true) {
+            Value::Imports(imports) => imports,
+            _ => unreachable!(),
+        }
+    }
+
+    /// The declarations of a module without the bodies of its let declarations. It's made of the
+    /// text of the tokens, so spaces and comments don't change it.
+    pub fn namespace(&mut self, module: &Path) -> Rc<String> {
+        match self.query(Key::Namespace(module.clone()), true) {
+            Value::Namespace(namespace) => namespace,
+            _ => unreachable!(),
+        }
+    }
+
+    /// A module after it's resolved with the modules that it uses. Only the diagnostics of the
+    /// module itself are reported.
+    pub fn resolved(&mut self, module: &Path) -> Rc<Resolved> {
+        match self.query(Key::Resolved(module.clone()), true) {
+            Value::Resolved(programs) => programs,
+            _ => unreachable!(),
+        }
+    }
+
+    /// The elaborated program of a module after it's resolved and type checked.
+    pub fn typed(&mut self, module: &Path) -> Rc<elaborated::Program<Type<Real>>> {
+        match self.query(Key::Typed(module.clone()), true) {
+            Value::Typed(program) => program,
+            _ => unreachable!(),
+        }
+    }
+
+    /// The declaration of a name in the modules of the crate, as the span of its name.
+    pub fn definition(&mut self, qualified: &r#abstract::Qualified) -> Option<(FileId, Span)> {
+        self.modules().into_iter().find_map(|module| {
+            let resolved = self.resolved(&module);
+            let definitions = resolved.module.definitions();
+
+            definitions
+                .into_iter()
+                .find(|(name, _)| name == qualified)
+                .map(|(_, span)| (span.file, span))
+        })
+    }
+
+    /// The declaration of a name as the span of its name, in the modules of the crate or in the
+    /// sources of a module that is loaded from its interface file. The files of the sources of
+    /// these modules are loaded into the file system.
+    pub fn declaration(&mut self, qualified: &r#abstract::Qualified) -> Option<Span> {
+        if let Some((_, span)) = self.definition(qualified) {
+            return Some(span);
+        }
+
+        let location = self.libraries().into_iter().find_map(|module| {
+            let library = self.library(&module)?.ok()?;
+            library.sources.definitions.get(qualified).cloned()
+        })?;
+
+        let file = self.fs.load(location.file).ok()?;
+        Some(Span::new(file, Byte(location.start), Byte(location.end)))
+    }
+
+    /// The names in the modules of the crate that refer to a declaration, sorted by their files
+    /// and their positions.
+    pub fn references(&mut self, qualified: &r#abstract::Qualified) -> Vec<(FileId, Span)> {
+        let mut references = Vec::new();
+
+        for module in self.modules() {
+            let resolved = self.resolved(&module);
+
+            if let Some(spans) = resolved.module.references().remove(qualified) {
+                references.extend(spans.into_iter().map(|span| (span.file, span)));
+            }
+        }
+
+        references.sort();
+        references.dedup();
+        references
+    }
+
+    /// The declaration that the name at a byte of a file declares or refers to.
+    pub fn name_at(&mut self, file: &FilePath, byte: usize) -> Option<r#abstract::Qualified> {
+        let module = self
+            .modules()
+            .into_iter()
+            .find(|module| self.file(module) == file)?;
+
+        let resolved = self.resolved(&module);
+        let contains = |span: &Span| span.start.0 <= byte && byte <= span.end.0;
+
+        let references = resolved
+            .module
+            .references()
+            .into_iter()
+            .flat_map(|(name, spans)| spans.into_iter().map(move |span| (name.clone(), span)));
+
+        resolved
+            .module
+            .definitions()
+            .into_iter()
+            .chain(references)
+            .find(|(_, span)| contains(span))
+            .map(|(name, _)| name)
+    }
+
+    /// The modules with files that the root module uses, sorted by their paths.
+    pub fn modules(&mut self) -> Vec<Path> {
+        let mut modules = self.cone(&self.root_module());
+        modules.retain(|module| self.has_source(module));
+        modules.sort_by_key(|x| x.to_string());
+        modules
+    }
+
+    /// The modules without files that the root module uses, that are loaded from their interface
+    /// files, sorted by their paths.
+    pub fn libraries(&mut self) -> Vec<Path> {
+        let mut libraries = self.cone(&self.root_module());
+        libraries.retain(|module| !self.has_source(module));
+        libraries.sort_by_key(|x| x.to_string());
+        libraries
+    }
+
+    /// Checks if a module has a file. The read is not tracked, so a query that checks it doesn't
+    /// change with the source, but it's only used by queries that read the imports that find it.
+    fn has_source(&mut self, module: &Path) -> bool {
+        let file = self.file(module);
+        matches!(self.query(Key::Source(file), false), Value::Source(Some(_)))
+    }
+
+    /// Type checks all the modules of the crate and returns their diagnostics. The cycles between
+    /// values are not checked, because they're a property of the entire crate.
+    pub fn diagnostics(&mut self) -> Vec<Detached> {
+        self.collect(true)
+    }
+
+    /// Resolves all the modules of the crate without type checking them, and returns the
+    /// diagnostics of the parser and of the resolver.
+    pub fn resolution_diagnostics(&mut self) -> Vec<Detached> {
+        self.collect(false)
+    }
+
+    fn collect(&mut self, typed: bool) -> Vec<Detached> {
+        let mut diagnostics = Vec::new();
+
+        for module in self.modules() {
+            let file = self.file(&module);
+
+            let mut keys = vec![
+                Key::Cst(file),
+                Key::Imports(module.clone()),
+                Key::Resolved(module.clone()),
+            ];
+
+            if typed {
+                keys.push(Key::Typed(module));
+            }
+
+            for key in keys {
+                self.update(&key);
+                diagnostics.extend(self.memos[&key].diagnostics.iter().cloned());
+            }
+        }
+
+        diagnostics
+    }
+
+    /// The modules that a module uses, including itself.
+    fn cone(&mut self, module: &Path) -> Vec<Path> {
+        let mut visited = HashSet::new();
+        let mut pending = vec![module.clone()];
+        let mut modules = Vec::new();
+
+        while let Some(module) = pending.pop() {
+            if visited.insert(module.clone()) {
+                pending.extend(self.imports(&module).iter().cloned());
+                modules.push(module);
+            }
+        }
+
+        modules
+    }
+
+    /// Reads the result of a query, computing it if it's outdated. Untracked reads are not
+    /// recorded as dependencies of the query that is being computed.
+    fn query(&mut self, key: Key, tracked: bool) -> Value {
+        if tracked {
+            if let Some(frame) = self.stack.last_mut() {
+                frame.push(key.clone());
+            }
+        }
+
+        self.update(&key);
+        self.memos[&key].value.clone()
+    }
+
+    /// Brings the result of a query up to date and returns the revision in which it last changed.
+    fn update(&mut self, key: &Key) -> Revision {
+        if let Some(memo) = self.memos.get(key) {
+            if memo.verified_at == self.revision {
+                return memo.changed_at;
+            }
+
+            let verified_at = memo.verified_at;
+            let dependencies = memo.dependencies.clone();
+
+            // Sources only change when they're set, so they're always up to date. Interface files
+            // are only read again when the database is created.
+            let unchanged = matches!(key, Key::Source(_) | Key::Library(_))
+                || dependencies
+                    .iter()
+                    .all(|dependency| self.update(dependency) <= verified_at);
+
+            if unchanged {
+                let memo = self.memos.get_mut(key).unwrap();
+                memo.verified_at = self.revision;
+                return memo.changed_at;
+            }
+        }
+
+        let reporter = vulpi_report::hash_reporter();
+
+        self.stack.push(Vec::new());
+        let value = self.compute(key, reporter.clone());
+        let dependencies = self.stack.pop().unwrap();
+
+        let fingerprint = value.fingerprint();
+
+        let changed_at = match self.memos.get(key) {
+            Some(old) if fingerprint.is_some() && old.fingerprint == fingerprint => old.changed_at,
+            _ => self.revision,
+        };
+
+        self.memos.insert(
+            key.clone(),
+            Memo {
+                value,
+                fingerprint,
+                diagnostics: Rc::new(reporter.detach()),
+                dependencies,
+                changed_at,
+                verified_at: self.revision,
+            },
+        );
+
+        changed_at
+    }
+
+    fn compute(&mut self, key: &Key, reporter: Report) -> Value {
+        match key {
+            Key::Source(file) => {
+                let source = self.fs.load(file.clone()).ok().map(|id| {
+                    let source = self.fs.read(id).unwrap();
+                    (id, Rc::new(source))
+                });
+
+                Value::Source(source)
+            }
+            Key::Library(module) => {
+                let file = interface::path(self.fs.from_cached_path(module.clone()));
+
+                let library = file.is_file().then(|| {
+                    interface::read(&file, Span::ghost())
+                        .map(|(artifact, sources)| Rc::new(Library { artifact, sources }))
+                        .map_err(|err| err.to_string())
+                });
+
+                Value::Library(library)
+            }
+            Key::Tokens(file) => {
+                let tokens = self
+                    .source(file)
+                    .map(|(id, source)| emit::tokens(id, &source, self.tab_width))
+                    .unwrap_or_default();
+
+                Value::Tokens(Rc::new(tokens))
+            }
+            Key::Cst(file) => {
+                let (id, source) = self
+                    .source(file)
+                    .expect("modules without files have no tree");
+                let program =
+                    vulpi_parser::parse_with_tab_width(reporter.clone(), id, &source, self.tab_width);
+                Value::Cst(Rc::new(cfg::filter(&reporter, self.target, program)))
+            }
+            Key::Imports(module) => Value::Imports(Rc::new(self.compute_imports(module, reporter))),
+            Key::Namespace(module) => Value::Namespace(Rc::new(self.compute_namespace(module))),
+            Key::Resolved(module) => {
+                Value::Resolved(Rc::new(self.compute_resolved(module, reporter)))
+            }
+            Key::Typed(module) => Value::Typed(Rc::new(self.compute_typed(module, reporter))),
+        }
+    }
+
+    fn compute_imports(&mut self, module: &Path, reporter: Report) -> Vec<Path> {
+        let file = self.file(module);
+
+        // Interface files have the paths that their modules import, without their spans.
+        let imported = if self.source(&file).is_some() {
+            let program = self.cst(&file);
+            dependencies::dependencies(self.name.clone(), &program).imported
+        } else {
+            match self.library(module) {
+                Some(Ok(library)) => {
+                    let imports = library.artifact.imports.iter();
+                    imports.map(|path| (path.clone(), Span::ghost())).collect()
+                }
+                _ => Vec::new(),
+            }
+        };
+
+        let mut imports = Vec::new();
+
+        for (path, span) in imported {
+            let parent = (1..=path.segments.len()).rev().find_map(|length| {
+                let parent = Path {
+                    segments: path.segments[..length].to_vec(),
+                };
+
+                let file = self.file(&parent);
+
+                if self.source(&file).is_some() {
+                    Some(Ok(parent))
+                } else {
+                    let library = self.library(&parent)?;
+                    Some(library.map(|_| parent.clone()).map_err(|x| (parent, x)))
+                }
+            });
+
+            match parent {
+                Some(Ok(parent)) if parent != *module => imports.push(parent),
+                Some(Ok(_)) => (),
+                Some(Err((parent, reason))) => reporter.report(Diagnostic::new(BuildError {
+                    span,
+                    kind: BuildErrorKind::InvalidInterface(
+                        interface::path(self.fs.from_cached_path(parent)),
+                        reason,
+                    ),
+                })),
+                None => reporter.report(Diagnostic::new(BuildError {
+                    span,
+                    kind: BuildErrorKind::ModuleFileNotFound(
+                        path.clone(),
+                        vec![
+                            self.fs.from_src_path(path.clone()),
+                            interface::path(self.fs.from_cached_path(path)),
+                        ],
+                    ),
+                })),
+            }
+        }
+
+        imports.sort_by_key(|x| x.to_string());
+        imports.dedup();
+        imports
+    }
+
+    fn compute_namespace(&mut self, module: &Path) -> String {
+        let file = self.file(module);
+        let program = self.cst(&file);
+        let tokens = self.tokens(&file);
+
+        // The first token of the body of each let declaration.
+        let bodies: HashSet<_> = program
+            .top_levels
+            .iter()
+            .filter_map(|top_level| match top_level {
+                TopLevel::Let(decl) => match &decl.body {
+                    LetMode::Body(eq, _) => Some(eq.value.span.start.0),
+                    LetMode::Cases(cases) => cases.first().map(|x| x.pipe.value.span.start.0),
+                },
+                TopLevel::Pattern(decl) => Some(decl.eq.value.span.start.0),
+                _ => None,
+            })
+            .collect();
+
+        let mut namespace = String::new();
+        let mut in_body = false;
+
+        for token in tokens.iter() {
+            let start = token.value.span.start.0;
+
+            // Top level declarations start at the first column, so a body ends before them.
+            let first_column = start == 0 || token.whitespace.data.get().ends_with('\n');
+
+            if bodies.contains(&start) {
+                in_body = true;
+            } else if first_column {
+                in_body = false;
+            }
+
+            if !in_body {
+                namespace.push_str(&token.value.data.get());
+                namespace.push(' ');
+            }
+        }
+
+        namespace
+    }
+
+    /// Resolves a module with the modules that it uses. It reads the namespaces of the others, so
+    /// it's only computed again when their declarations change, and reads their trees without
+    /// tracking them. The modules of interface files are already resolved.
+    fn compute_resolved(&mut self, module: &Path, reporter: Report) -> Resolved {
+        let mut cone = self.cone(module);
+        cone.sort_by_key(|x| x.to_string());
+
+        let sink = vulpi_report::hash_reporter();
+        let available: Rc<RefCell<HashMap<Path, vulpi_resolver::Module>>> = Default::default();
+
+        let mut resolved = Vec::new();
+
+        for path in cone {
+            let file = self.file(&path);
+
+            if !self.has_source(&path) {
+                if let Some(Ok(library)) = self.library(&path) {
+                    let namespace = &library.artifact.namespace;
+                    namespace.register(&mut available.borrow_mut());
+                }
+
+                continue;
+            }
+
+            let (program, reporter) = if path == *module {
+                (self.cst(&file), reporter.clone())
+            } else {
+                self.namespace(&path);
+
+                let Value::Cst(program) = self.query(Key::Cst(file), false) else {
+                    unreachable!()
+                };
+
+                (program, sink.clone())
+            };
+
+            let context = Context::new(available.clone(), path.clone(), reporter);
+            let solved = vulpi_resolver::resolve(&context, (*program).clone());
+
+            available
+                .borrow_mut()
+                .insert(context.module.name().clone(), context.module.clone());
+
+            resolved.push((path, context, solved));
+        }
+
+        let index = resolved.iter().position(|x| x.0 == *module).unwrap();
+        let module = resolved[index].1.module.clone();
+
+        let programs = resolved
+            .into_iter()
+            .map(|(path, context, solved)| (path, solved.eval(context)))
+            .collect();
+
+        Resolved { programs, module }
+    }
+
+    /// Type checks a resolved module with the modules that it uses.
+    fn compute_typed(
+        &mut self,
+        module: &Path,
+        reporter: Report,
+    ) -> elaborated::Program<Type<Real>> {
+        let resolved = self.resolved(module);
+
+        let index = resolved
+            .programs
+            .iter()
+            .position(|(path, _)| path == module)
+            .unwrap();
+        let programs = resolved
+            .programs
+            .iter()
+            .map(|(_, program)| program.clone())
+            .collect();
+
+        let mut ctx = vulpi_typer::Context::new(reporter);
+
+        // The build directory can have interface files of the modules of the crate too.
+        for path in self.cone(module) {
+            if self.has_source(&path) {
+                continue;
+            }
+
+            if let Some(Ok(library)) = self.library(&path) {
+                for (name, interface) in &library.artifact.interfaces {
+                    ctx.modules.get(name).merge(interface.clone());
+                }
+            }
+        }
+
+        Programs(programs).check_one(index, (&mut ctx, vulpi_typer::Env::default()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{memory::MemoryFileSystem, real::RealFileSystem};
+
+    const UTIL: &str = "pub type Unit = | Unit\n\npub let unit : Unit = Unit.Unit\n";
+
+    fn database() -> Database<MemoryFileSystem> {
+        let name = Symbol::intern("Proj");
+
+        let mut fs = MemoryFileSystem::new(name.clone());
+        fs.insert(PathBuf::from("Util.vp"), UTIL.to_string());
+        fs.insert(
+            PathBuf::from("Main.vp"),
+            "use Proj.Util\n\nlet main : Unit = unit\n".to_string(),
+        );
+
+        Database::new(fs, name, PathBuf::from("Main.vp"), Target::Js)
+    }
+
+    #[test]
+    fn reuses_modules_when_a_body_changes() {
+        let mut db = database();
+        let main = db.root_module();
+        let util = Path {
+            segments: vec![Symbol::intern("Proj"), Symbol::intern("Util")],
+        };
+
+        assert!(db.diagnostics().is_empty());
+
+        let typed_main = db.typed(&main);
+        let typed_util = db.typed(&util);
+
+        let body = UTIL.replace("= Unit.Unit", "=\n  (Unit.Unit)");
+        db.set_source(PathBuf::from("Util.vp"), body).unwrap();
+
+        assert!(db.diagnostics().is_empty());
+        assert!(Rc::ptr_eq(&typed_main, &db.typed(&main)));
+        assert!(!Rc::ptr_eq(&typed_util, &db.typed(&util)));
+
+        let signature = UTIL.replace(
+            "pub let unit",
+            "pub let other : Unit = Unit.Unit\n\nlet unit",
+        );
+        db.set_source(PathBuf::from("Util.vp"), signature).unwrap();
+
+        assert!(!db.diagnostics().is_empty());
+        assert!(!Rc::ptr_eq(&typed_main, &db.typed(&main)));
+    }
+
+    #[test]
+    fn uses_the_constructors_of_interface_files() {
+        let directory = std::env::temp_dir().join(format!("vulpi-libs-{}", std::process::id()));
+        let library = directory.join("lib");
+        let (app, build) = (directory.join("app"), directory.join("build"));
+
+        let shapes = "pub type Color = | Red | Green\n\npub let name (x: Color) : () = ()\n";
+        let main = "use Shapes\n\npub let main (x: ()) : () = Shapes.name Shapes.Color.Green\n";
+
+        for project in [&library, &app] {
+            std::fs::create_dir_all(project).unwrap();
+            std::fs::write(project.join("Main.vp"), main).unwrap();
+        }
+
+        std::fs::write(library.join("Shapes.vp"), shapes).unwrap();
+
+        let compiler = |name: &str, root: &PathBuf, interfaces: bool| {
+            let name = Symbol::intern(name);
+
+            let fs = RealFileSystem::new(name.clone(), root.clone(), build.clone());
+
+            let mut compiler = crate::ProjectCompiler::new(name, fs);
+            compiler.interfaces = interfaces;
+            compiler
+        };
+
+        // The library writes the interface files to the build directory that the app uses, so
+        // the app has no sources of `Shapes`.
+        let mut lib = compiler("Lib", &library, true);
+        assert!(lib.check(lib.name.clone(), PathBuf::from("Main.vp")).is_some());
+
+        let mut compiled = compiler("App", &app, false);
+        let checked = compiled.check(compiled.name.clone(), PathBuf::from("Main.vp"));
+
+        let fs = RealFileSystem::new(Symbol::intern("App"), app.clone(), build.clone());
+        let mut db = Database::new(fs, Symbol::intern("App"), PathBuf::from("Main.vp"), Target::Vm);
+        let diagnostics = db.diagnostics();
+
+        std::fs::remove_dir_all(&directory).unwrap();
+
+        assert!(checked.is_some());
+        assert!(diagnostics.is_empty());
+    }
+}
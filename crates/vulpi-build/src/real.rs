@@ -5,10 +5,14 @@ use vulpi_intern::Symbol;
 use vulpi_location::FileId;
 use vulpi_vfs::{path::Path, Error};
 
-use super::FileSystem;
+use super::{stdlib, tree::ModuleTree, FileSystem};
 
 pub struct RealFileSystem {
     project_root: PathBuf,
+    tree: ModuleTree,
+
+    /// The modules of the standard library, that are found when the crate has no file for them.
+    library: ModuleTree,
     build_root: PathBuf,
     root: Symbol,
     file_map: HashMap<FileId, (PathBuf, String)>,
@@ -17,9 +21,14 @@ pub struct RealFileSystem {
 }
 
 impl RealFileSystem {
+    /// Creates the file system of a crate whose modules are in the project root. The modules are
+    /// discovered when it's created, so their files are found without guessing. The standard
+    /// library is mounted below them.
     pub fn new(root: Symbol, project_root: PathBuf, build: PathBuf) -> Self {
         Self {
             root,
+            tree: ModuleTree::discover(&project_root).unwrap_or_default(),
+            library: stdlib::tree(),
             project_root,
             build_root: build,
             file_map: HashMap::new(),
@@ -33,20 +42,32 @@ impl RealFileSystem {
         path.canonicalize()
             .map_err(|_| Error::NotFound(path.clone()))
     }
+
+    /// The modules of the crate that have files.
+    pub fn tree(&self) -> &ModuleTree {
+        &self.tree
+    }
 }
 
 impl FileSystem for RealFileSystem {
     type Path = PathBuf;
 
     fn load(&mut self, path: PathBuf) -> Result<FileId, Error> {
-        let path = self.get_path(path)?;
+        let embedded = stdlib::source(&path);
+
+        let path = match embedded {
+            Some(_) => path,
+            None => self.get_path(path)?,
+        };
 
         if let Some(id) = self.path_map.get(&path) {
             return Ok(*id);
         }
 
-        let content =
-            fs::read_to_string(path.clone()).map_err(|_| Error::NotFound(path.clone()))?;
+        let content = match embedded {
+            Some(source) => source.to_string(),
+            None => fs::read_to_string(path.clone()).map_err(|_| Error::NotFound(path.clone()))?,
+        };
 
         let id = FileId(self.counter);
         self.counter += 1;
@@ -64,8 +85,11 @@ impl FileSystem for RealFileSystem {
         Ok(())
     }
 
-    fn store(&mut self, _id: FileId, _content: String) -> Result<(), Error> {
-        todo!()
+    /// Replaces the contents of a loaded file. The file in the disk only changes when it's written.
+    fn store(&mut self, id: FileId, content: String) -> Result<(), Error> {
+        let file = self.file_map.get_mut(&id).ok_or(Error::NotFoundId)?;
+        file.1 = content;
+        Ok(())
     }
 
     fn read(&self, id: FileId) -> Result<String, Error> {
@@ -123,10 +147,15 @@ impl FileSystem for RealFileSystem {
     }
 
     fn from_src_path(&self, path: Path) -> Self::Path {
-        if self.root == path.segments[0] {
-            path.shift().to_pathbuf(self.project_root.clone())
+        let path = if self.root == path.segments[0] {
+            path.shift()
         } else {
-            path.to_pathbuf(self.project_root.clone())
+            path
+        };
+
+        match self.tree.file(&path).or_else(|| self.library.file(&path)) {
+            Some(file) => file.clone(),
+            None => path.to_pathbuf(self.project_root.clone()),
         }
     }
 }
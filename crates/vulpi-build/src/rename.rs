@@ -0,0 +1,130 @@
+//! Renaming of declarations, for `vulpi rename` and for the language server. The name in the
+//! declaration and the names that the resolver found to refer to it are replaced, so names that
+//! only look the same, like a local variable that shadows a function, are left alone.
+
+use std::{collections::BTreeMap, path::PathBuf};
+
+use vulpi_intern::Symbol;
+use vulpi_location::{FileId, Span};
+use vulpi_report::Suggestion;
+use vulpi_syntax::{r#abstract::Qualified, tokens::TokenData};
+use vulpi_vfs::FileSystem;
+
+use crate::{
+    emit,
+    error::{BuildError, BuildErrorKind},
+    query::Database,
+};
+
+/// The edits of each file that rename a declaration of the crate and the names that refer to it.
+pub fn rename<FS: FileSystem<Path = PathBuf>>(
+    db: &mut Database<FS>,
+    qualified: &Qualified,
+    name: &str,
+) -> Result<BTreeMap<FileId, Vec<Suggestion>>, BuildError> {
+    let Some((_, definition)) = db.definition(qualified) else {
+        return Err(BuildError {
+            span: Span::default(),
+            kind: BuildErrorKind::NotDeclared(qualified.to_string()),
+        });
+    };
+
+    // The case of a name says what it can be, so a value cannot be renamed to a constructor.
+    if identifier(name).is_none() || identifier(name) != identifier(&qualified.name.get()) {
+        return Err(BuildError {
+            span: definition,
+            kind: BuildErrorKind::InvalidName(name.to_string(), qualified.name.clone()),
+        });
+    }
+
+    let taken = Qualified {
+        path: qualified.path.clone(),
+        name: Symbol::intern(name),
+    };
+
+    if db.definition(&taken).is_some() {
+        return Err(BuildError {
+            span: definition,
+            kind: BuildErrorKind::NameTaken(taken.to_string()),
+        });
+    }
+
+    let mut edits: BTreeMap<FileId, Vec<Suggestion>> = BTreeMap::new();
+
+    for (file, span) in db
+        .references(qualified)
+        .into_iter()
+        .chain([(definition.file, definition)])
+    {
+        edits.entry(file).or_default().push(Suggestion {
+            span,
+            replacement: name.to_string(),
+        });
+    }
+
+    Ok(edits)
+}
+
+/// The kind of identifier that a name is, if it's a single one.
+fn identifier(name: &str) -> Option<TokenData> {
+    let tokens = emit::tokens(FileId(0), name, vulpi_lexer::TAB_WIDTH);
+
+    match tokens.as_slice() {
+        [token, eof] if eof.is(TokenData::Eof) && token.data() == name => match token.kind {
+            TokenData::UpperIdent | TokenData::LowerIdent => Some(token.kind),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use vulpi_vfs::path::Path;
+
+    use super::*;
+    use crate::{cfg::Target, fix, memory::MemoryFileSystem};
+
+    #[test]
+    fn renames_the_uses_in_every_module() {
+        let name = Symbol::intern("Proj");
+
+        let util = "pub type Unit = | Unit\n\npub let unit : Unit = Unit.Unit\n";
+        let main =
+            "use Proj.Util\n\nlet main : Unit = unit\n\nlet other (unit : Unit) : Unit = unit\n";
+
+        let mut fs = MemoryFileSystem::new(name.clone());
+        fs.insert(PathBuf::from("Util.vp"), util.to_string());
+        fs.insert(PathBuf::from("Main.vp"), main.to_string());
+
+        let mut db = Database::new(fs, name, PathBuf::from("Main.vp"), Target::Js);
+
+        let unit = Qualified {
+            path: Path {
+                segments: vec![Symbol::intern("Proj"), Symbol::intern("Util")],
+            }
+            .symbol(),
+            name: Symbol::intern("unit"),
+        };
+
+        assert!(rename(&mut db, &unit, "Done").is_err());
+
+        let mut sources = Vec::new();
+
+        for (file, edits) in rename(&mut db, &unit, "done").ok().unwrap() {
+            let source = db.fs.read(file).unwrap();
+            sources.push(fix::apply(&source, edits).0);
+        }
+
+        sources.sort();
+
+        // The parameter of `other` shadows the function, so it keeps its name.
+        assert_eq!(
+            sources,
+            [
+                "pub type Unit = | Unit\n\npub let done : Unit = Unit.Unit\n",
+                "use Proj.Util\n\nlet main : Unit = done\n\nlet other (unit : Unit) : Unit = unit\n",
+            ]
+        );
+    }
+}
@@ -0,0 +1,386 @@
+//! The interactive loop of `vulpi repl`. The declarations that are entered are kept in a module
+//! that takes the place of the root module of the crate, and each input is checked by a [Database]
+//! after them, so the modules of the crate that they use are only checked again when they change.
+//!
+//! Expressions, and the arguments of the commands, are checked as a declaration named [PROBE] at
+//! the end of the module, that is thrown away after the input is answered.
+
+use std::path::PathBuf;
+
+use vulpi_intern::Symbol;
+use vulpi_location::FileId;
+use vulpi_report::{Detached, IntoDiagnostic, Severity};
+use vulpi_resolver::Module;
+use vulpi_syntax::{
+    elaborated,
+    r#abstract::{Qualified, Visibility},
+};
+use vulpi_typer::{declare::Programs, real::Real, Env, Type};
+use vulpi_vfs::{path::Path, FileSystem};
+use vulpi_vm::machine::{Machine, RuntimeError};
+
+use crate::query::Database;
+
+/// The name of the declaration that an input is checked as. It cannot be declared by the user,
+/// because an identifier cannot start with a quote.
+pub const PROBE: &str = "repl'";
+
+/// The commands, without the colon that starts them.
+pub const COMMANDS: [&str; 3] = ["type", "kind", "browse"];
+
+pub enum Failure {
+    /// The input, or one of the modules that it uses, has errors.
+    Diagnostics(Vec<Detached>),
+
+    /// The evaluation of an expression stopped with an error of the virtual machine.
+    Runtime(RuntimeError),
+
+    /// A command that doesn't exist, without its colon.
+    UnknownCommand(String),
+}
+
+pub struct Repl<FS: FileSystem> {
+    pub db: Database<FS>,
+
+    /// The source of the declarations that were accepted, one in each line.
+    declarations: String,
+}
+
+impl<FS: FileSystem<Path = PathBuf>> Repl<FS> {
+    /// Starts a loop over a crate whose root module has a file. The source of the file is replaced
+    /// by the declarations, so the other modules are only available through `use`.
+    pub fn new(db: Database<FS>) -> Self {
+        Self {
+            db,
+            declarations: String::new(),
+        }
+    }
+
+    /// Answers a line: a command, a declaration that is added to the module or an expression that
+    /// is evaluated. Returns the text to show, if there is any.
+    pub fn eval(&mut self, line: &str) -> Result<Option<String>, Failure> {
+        let line = line.trim();
+
+        if line.is_empty() {
+            return Ok(None);
+        }
+
+        if let Some(command) = line.strip_prefix(':') {
+            let (name, argument) = command
+                .split_once(char::is_whitespace)
+                .unwrap_or((command, ""));
+
+            let argument = argument.trim();
+
+            return match name {
+                "type" => self.typ(argument).map(Some),
+                "kind" => self.kind(argument).map(Some),
+                "browse" => self.browse(argument).map(Some),
+                _ => Err(Failure::UnknownCommand(name.to_string())),
+            };
+        }
+
+        if is_declaration(line) {
+            let source = format!("{}{}\n", self.declarations, line);
+            self.check(source.clone(), true)?;
+            self.declarations = source;
+            Ok(None)
+        } else {
+            self.evaluate(line).map(Some)
+        }
+    }
+
+    /// Evaluates an expression in the virtual machine and shows its value.
+    fn evaluate(&mut self, expr: &str) -> Result<String, Failure> {
+        self.check(self.probe("let", expr), true)?;
+
+        let mut programs = Vec::new();
+
+        for module in self.db.modules() {
+            programs.push((*self.db.typed(&module)).clone());
+        }
+
+        for module in self.db.libraries() {
+            if let Some(Ok(library)) = self.db.library(&module) {
+                programs.push(library.artifact.program.clone());
+            }
+        }
+
+        let entry = self.name(PROBE);
+
+        let mut core = vulpi_core::lower::lower(&programs);
+        vulpi_core::dead::eliminate(&mut core, Some(&entry));
+        let bytecode = vulpi_vm::compile::compile(&core);
+
+        let mut machine = Machine::new(&bytecode);
+        let value = machine.apply(&entry, vec![]).map_err(Failure::Runtime)?;
+
+        Ok(machine.inspect(&value))
+    }
+
+    /// The type of an expression, after it's generalized.
+    fn typ(&mut self, expr: &str) -> Result<String, Failure> {
+        self.check(self.probe("let", expr), true)?;
+
+        let root = self.db.root_module();
+        let typed = self.db.typed(&root);
+        let decl = &typed.lets[&self.name(PROBE)];
+
+        Ok(decl.typ.show(&Env::default()).to_string())
+    }
+
+    /// The kind of a type. The type is resolved as the type of an external declaration, that is
+    /// not type checked because the kind of the type of a value must be `Type`.
+    fn kind(&mut self, typ: &str) -> Result<String, Failure> {
+        let source = format!("{}external {} : {} = \"\"\n", self.declarations, PROBE, typ);
+        self.check(source, false)?;
+
+        let root = self.db.root_module();
+        let resolved = self.db.resolved(&root);
+        let name = self.name(PROBE);
+
+        let external = resolved
+            .programs
+            .iter()
+            .flat_map(|(_, program)| &program.externals)
+            .find(|external| external.name == name)
+            .expect("the probe is declared in the root module");
+
+        let programs = resolved.programs.iter().map(|x| x.1.clone()).collect();
+
+        let reporter = vulpi_report::hash_reporter();
+        let mut ctx = vulpi_typer::Context::new(reporter.clone());
+        let kind = Programs(programs).kind(&external.typ, (&mut ctx, Env::default()));
+
+        if reporter.has_errors() {
+            return Err(Failure::Diagnostics(reporter.detach()));
+        }
+
+        Ok(kind.show(&Env::default()).to_string())
+    }
+
+    /// The names that a module declares, with the types of its values. The module is written the
+    /// same way as in `use`, and it's the one of the loop if there is none.
+    fn browse(&mut self, path: &str) -> Result<String, Failure> {
+        let root = self.db.root_module();
+
+        let path = if path.is_empty() {
+            self.check(self.declarations.clone(), false)?;
+            root
+        } else {
+            self.check(format!("{}use {}\n", self.declarations, path), false)?;
+            Path {
+                segments: path.split('.').map(Symbol::intern).collect(),
+            }
+        };
+
+        // Modules that are declared inside of the file of another one are found from it.
+        let file = self
+            .db
+            .modules()
+            .into_iter()
+            .filter(|module| path.segments.starts_with(&module.segments))
+            .max_by_key(|module| module.segments.len())
+            .expect("the module was imported");
+
+        let mut module = self.db.resolved(&file).module.clone();
+
+        // The resolver reports the paths of `use` that are not modules.
+        for segment in &path.segments[file.segments.len()..] {
+            let submodule = module.submodules().get(segment).cloned();
+            module = submodule.expect("the module was resolved");
+        }
+
+        let typed = self.db.typed(&file);
+        Ok(names(&module, &typed).join("\n"))
+    }
+
+    /// Replaces the source of the root module and fails if it has errors. The type checker only
+    /// runs if it's asked to.
+    fn check(&mut self, source: String, typed: bool) -> Result<(), Failure> {
+        let root = self.db.root.clone();
+
+        self.db
+            .set_source(root, source)
+            .expect("the root module of the crate has a file");
+
+        let diagnostics = if typed {
+            self.db.diagnostics()
+        } else {
+            self.db.resolution_diagnostics()
+        };
+
+        if diagnostics.iter().any(|x| x.severity() == Severity::Error) {
+            Err(Failure::Diagnostics(diagnostics))
+        } else {
+            Ok(())
+        }
+    }
+
+    /// The declarations followed by the one of the probe.
+    fn probe(&self, keyword: &str, body: &str) -> String {
+        format!("{}{} {} = {}\n", self.declarations, keyword, PROBE, body)
+    }
+
+    /// A name declared in the root module.
+    fn name(&self, name: &str) -> Qualified {
+        Qualified {
+            path: self.db.root_module().symbol(),
+            name: Symbol::intern(name),
+        }
+    }
+}
+
+/// Checks if a line is a declaration, by parsing it as a program. Expressions are not programs.
+fn is_declaration(line: &str) -> bool {
+    let reporter = vulpi_report::hash_reporter();
+    let program = vulpi_parser::parse(reporter.clone(), FileId(0), line);
+    !program.top_levels.is_empty() && !reporter.has_errors()
+}
+
+/// The lines that describe the types, the traits, the values and the submodules of a module,
+/// each group sorted by the names.
+fn names(module: &Module, typed: &elaborated::Program<Type<Real>>) -> Vec<String> {
+    let declared = module.declared();
+    let namespace = module.name().symbol();
+
+    let sorted = |names: Vec<(String, String)>| {
+        let mut names = names;
+        names.sort();
+        names.into_iter().map(|x| x.1)
+    };
+
+    let types = declared.types.iter().map(|(name, definition)| {
+        let line = format!("{}type {}", visibility(&definition.visibility), name.get());
+        (name.get(), line)
+    });
+
+    let traits = declared.traits.iter().map(|(name, definition)| {
+        let line = format!("{}trait {}", visibility(&definition.visibility), name.get());
+        (name.get(), line)
+    });
+
+    let values = declared.values.iter().map(|(name, definition)| {
+        let qualified = Qualified {
+            path: namespace.clone(),
+            name: name.clone(),
+        };
+
+        let visibility = visibility(&definition.visibility);
+
+        // Constructors and operations are values without a declaration of their own.
+        let line = match signature(typed, &qualified) {
+            Some(typ) => format!("{}let {} : {}", visibility, name.get(), typ),
+            None => format!("{}{}", visibility, name.get()),
+        };
+
+        (name.get(), line)
+    });
+
+    // Types and effects have submodules of their own, with their constructors and operations.
+    let submodules: Vec<_> = module
+        .submodules()
+        .keys()
+        .filter(|name| !declared.types.contains_key(name))
+        .map(|name| (name.get(), format!("mod {}", name.get())))
+        .collect();
+
+    sorted(types.collect())
+        .chain(sorted(traits.collect()))
+        .chain(sorted(values.collect()))
+        .chain(sorted(submodules))
+        .collect()
+}
+
+fn visibility(visibility: &Visibility) -> &'static str {
+    match visibility {
+        Visibility::Public => "pub ",
+        Visibility::Super | Visibility::Private => "",
+    }
+}
+
+/// The type of a let or external declaration of a program or of its submodules.
+fn signature(program: &elaborated::Program<Type<Real>>, name: &Qualified) -> Option<String> {
+    let typ = program
+        .lets
+        .get(name)
+        .map(|decl| &decl.typ)
+        .or_else(|| program.externals.get(name).map(|decl| &decl.typ));
+
+    match typ {
+        Some(typ) => Some(typ.show(&Env::default()).to_string()),
+        None => program.modules.values().find_map(|x| signature(x, name)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{cfg::Target, memory::MemoryFileSystem};
+
+    const PRELUDE: &str = "pub type Int\n\npub external add : Int -> Int -> Int = \"add\"\n";
+
+    fn repl() -> Repl<MemoryFileSystem> {
+        let name = Symbol::intern("Proj");
+
+        let mut fs = MemoryFileSystem::new(name.clone());
+        fs.insert(PathBuf::from("Prelude.vp"), PRELUDE.to_string());
+        fs.insert(PathBuf::from("Main.vp"), String::new());
+
+        Repl::new(Database::new(
+            fs,
+            name,
+            PathBuf::from("Main.vp"),
+            Target::Vm,
+        ))
+    }
+
+    fn answer(repl: &mut Repl<MemoryFileSystem>, line: &str) -> Option<String> {
+        repl.eval(line).ok().unwrap()
+    }
+
+    #[test]
+    fn evaluates_with_the_declarations_before() {
+        let mut repl = repl();
+
+        assert_eq!(answer(&mut repl, "use Prelude"), None);
+        assert_eq!(answer(&mut repl, "let two = add 1 1"), None);
+        assert_eq!(answer(&mut repl, "add two 3"), Some("5".to_string()));
+
+        // Declarations with errors are not kept, so the module can still be used.
+        assert!(repl.eval("let three : Int = \"three\"").is_err());
+        assert!(repl.eval("three").is_err());
+        assert_eq!(answer(&mut repl, "two"), Some("2".to_string()));
+
+        assert_eq!(
+            answer(&mut repl, ":type add two"),
+            Some("Int -> Int".to_string())
+        );
+        assert_eq!(answer(&mut repl, ":kind Int"), Some("Type".to_string()));
+        assert_eq!(
+            answer(&mut repl, ":browse"),
+            Some("let two : Int".to_string())
+        );
+        assert!(repl.eval(":unknown").is_err());
+    }
+
+    #[test]
+    fn shows_the_kinds_of_type_constructors() {
+        let mut repl = repl();
+
+        assert_eq!(answer(&mut repl, "use Prelude"), None);
+        assert_eq!(answer(&mut repl, "type Maybe a = | Just a | Nothing"), None);
+        assert_eq!(answer(&mut repl, "type Proxy a = | Proxy"), None);
+        assert_eq!(answer(&mut repl, "type Wrap f = | Wrap (f Int)"), None);
+
+        let kind = |repl: &mut Repl<_>, typ: &str| answer(repl, &format!(":kind {typ}"));
+
+        assert_eq!(kind(&mut repl, "Maybe"), Some("Type -> Type".to_string()));
+        assert_eq!(kind(&mut repl, "Maybe Int"), Some("Type".to_string()));
+        assert_eq!(kind(&mut repl, "Proxy"), Some("Type -> Type".to_string()));
+        assert_eq!(
+            kind(&mut repl, "Wrap"),
+            Some("(Type -> Type) -> Type".to_string())
+        );
+    }
+}
@@ -0,0 +1,167 @@
+//! The runner of `vulpi test`. Each test is compiled as a private declaration without parameters
+//! in the program of its module, and runs in a machine of its own, so a test cannot change the
+//! state that the others see. A test passes if its body evaluates to `True`.
+
+use vulpi_intern::Symbol;
+use vulpi_location::Span;
+use vulpi_syntax::{
+    elaborated::{LetDecl, PatternArm, Program, TestDecl},
+    r#abstract::Visibility,
+};
+use vulpi_typer::{real::Real, Type};
+use vulpi_vm::machine::{Machine, RuntimeError};
+
+pub enum Verdict {
+    Passed,
+
+    /// The test evaluated to `False`.
+    Failed,
+
+    /// The evaluation of the test stopped with an error of the virtual machine.
+    Errored(RuntimeError),
+}
+
+pub struct Outcome {
+    pub description: Symbol,
+    pub span: Span,
+    pub verdict: Verdict,
+}
+
+impl Outcome {
+    pub fn passed(&self) -> bool {
+        matches!(self.verdict, Verdict::Passed)
+    }
+}
+
+/// Runs the tests of the programs whose descriptions contain the filter, in the order that they
+/// are declared in each module.
+pub fn run(programs: &[Program<Type<Real>>], filter: Option<&str>) -> Vec<Outcome> {
+    let mut programs = programs.to_vec();
+    let mut tests = Vec::new();
+
+    for program in &mut programs {
+        collect(program, filter, &mut tests);
+    }
+
+    if tests.is_empty() {
+        return Vec::new();
+    }
+
+    // The tests are private and nothing uses them, so the dead code is not eliminated.
+    let core = vulpi_core::lower::lower(&programs);
+    let bytecode = vulpi_vm::compile::compile(&core);
+
+    tests
+        .into_iter()
+        .map(|test| {
+            let mut machine = Machine::new(&bytecode);
+
+            let verdict = match machine.apply(&test.name, vec![]) {
+                Ok(value) if machine.is_true(&value) => Verdict::Passed,
+                Ok(_) => Verdict::Failed,
+                Err(err) => Verdict::Errored(err),
+            };
+
+            Outcome {
+                description: test.description,
+                span: test.span,
+                verdict,
+            }
+        })
+        .collect()
+}
+
+/// Moves the tests that are selected to the declarations of their programs.
+fn collect(
+    program: &mut Program<Type<Real>>,
+    filter: Option<&str>,
+    tests: &mut Vec<TestDecl<Type<Real>>>,
+) {
+    for test in std::mem::take(&mut program.tests) {
+        if filter.is_none_or(|filter| test.description.get().contains(filter)) {
+            program.lets.insert(test.name.clone(), declaration(&test));
+            tests.push(test);
+        }
+    }
+
+    for module in program.modules.values_mut() {
+        collect(module, filter, tests);
+    }
+}
+
+fn declaration(test: &TestDecl<Type<Real>>) -> LetDecl<Type<Real>> {
+    LetDecl {
+        name: test.name.clone(),
+        span: test.span.clone(),
+        visibility: Visibility::Private,
+        typ: test.typ.clone(),
+        binders: Vec::new(),
+        body: vec![PatternArm {
+            patterns: Vec::new(),
+            expr: test.body.clone(),
+            guard: None,
+        }],
+        constants: None,
+        declaration: test.span.clone(),
+        allow: Vec::new(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use super::*;
+    use crate::{cfg::Target, memory::MemoryFileSystem, query::Database};
+
+    const PRELUDE: &str = "pub type Int\n\npub type Bool = | True | False\n\npub external eq : Int -> Int -> Bool = \"eq\"\n\npub external div : Int -> Int -> Int = \"div\"\n";
+
+    const MAIN: &str = "use Prelude\n\ntest \"one is one\" = eq 1 1\n\ntest \"one is two\" = eq 1 2\n\ntest \"one by zero\" = eq (div 1 0) 1\n";
+
+    fn verdicts(filter: Option<&str>) -> Vec<(String, &'static str)> {
+        let name = Symbol::intern("Proj");
+
+        let mut fs = MemoryFileSystem::new(name.clone());
+        fs.insert(PathBuf::from("Prelude.vp"), PRELUDE.to_string());
+        fs.insert(PathBuf::from("Main.vp"), MAIN.to_string());
+
+        let mut db = Database::new(fs, name, PathBuf::from("Main.vp"), Target::Vm);
+        assert!(db.diagnostics().is_empty());
+
+        let programs: Vec<_> = db
+            .modules()
+            .iter()
+            .map(|module| (*db.typed(module)).clone())
+            .collect();
+
+        run(&programs, filter)
+            .into_iter()
+            .map(|outcome| {
+                let verdict = match outcome.verdict {
+                    Verdict::Passed => "passed",
+                    Verdict::Failed => "failed",
+                    Verdict::Errored(_) => "errored",
+                };
+
+                (outcome.description.get(), verdict)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn runs_the_tests_that_match_the_filter() {
+        assert_eq!(
+            verdicts(None),
+            vec![
+                ("one is one".to_string(), "passed"),
+                ("one is two".to_string(), "failed"),
+                ("one by zero".to_string(), "errored"),
+            ]
+        );
+
+        assert_eq!(
+            verdicts(Some("is two")),
+            vec![("one is two".to_string(), "failed")]
+        );
+    }
+}
@@ -0,0 +1,124 @@
+//! The standard library that is shipped with the compiler. Its modules are embedded in the binary,
+//! unless the `VULPI_STD` variable names a directory with other sources for them. The modules of a
+//! project take the place of the ones of the standard library with the same names, so projects
+//! that bring a prelude of their own keep using it.
+
+use std::path::{Path as FilePath, PathBuf};
+
+use vulpi_intern::Symbol;
+
+use crate::tree::{ModuleTree, EXTENSION};
+
+/// The variable with the directory of the sources of the standard library.
+pub const VARIABLE: &str = "VULPI_STD";
+
+/// The directory of the embedded modules. It's not in the disk, so their files are found by their
+/// names.
+pub const DIRECTORY: &str = "<std>";
+
+/// The embedded modules with their sources.
+pub const MODULES: [(&str, &str); 11] = [
+    ("Prelude", include_str!("../../../std/Prelude.vp")),
+    ("Operator", include_str!("../../../std/Operator.vp")),
+    ("Bool", include_str!("../../../std/Bool.vp")),
+    ("Int", include_str!("../../../std/Int.vp")),
+    ("Float", include_str!("../../../std/Float.vp")),
+    ("String", include_str!("../../../std/String.vp")),
+    ("Option", include_str!("../../../std/Option.vp")),
+    ("Result", include_str!("../../../std/Result.vp")),
+    ("List", include_str!("../../../std/List.vp")),
+    ("Channel", include_str!("../../../std/Channel.vp")),
+    ("Mutex", include_str!("../../../std/Mutex.vp")),
+];
+
+/// The tree of the modules of the standard library.
+pub fn tree() -> ModuleTree {
+    if let Some(directory) = std::env::var_os(VARIABLE) {
+        return ModuleTree::discover(FilePath::new(&directory)).unwrap_or_default();
+    }
+
+    let mut tree = ModuleTree::default();
+
+    for (name, _) in MODULES {
+        let file = PathBuf::from(DIRECTORY).join(format!("{}.{}", name, EXTENSION));
+        tree.children.entry(Symbol::intern(name)).or_default().file = Some(file);
+    }
+
+    tree
+}
+
+/// The source of the file of an embedded module.
+pub fn source(file: &FilePath) -> Option<&'static str> {
+    let name = file.strip_prefix(DIRECTORY).ok()?.file_stem()?.to_str()?;
+
+    MODULES
+        .iter()
+        .find(|(module, _)| *module == name)
+        .map(|(_, source)| *source)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{cfg::Target, memory::MemoryFileSystem, query::Database, runner};
+
+    const MAIN: &str = "use Prelude
+use List
+use Option
+use Result
+use String
+use Int
+use Float
+use Channel
+
+test \"operators\" = ((2 + 3 * 4) == 14) && Bool.not (1 == 2)
+
+test \"lists\" = 6 == List.foldl (\\acc x => acc + x) 0 (List.reverse [1, 2, 3])
+
+test \"options\" = Option.withDefault 0 (List.head [4, 5]) == 4
+
+test \"results\" = Result.withDefault 1 (Result.Err \"no\") == 1
+
+test \"strings\" = String.length (\"a\" ++ Int.toString 42) == 3
+
+test \"conversions\" = (Int.toString 42 ++ String.fromChar 'x') == \"42x\"
+
+test \"whole floats\" = Float.toString 2 == Float.toString 2.0
+
+test \"channels\" = do
+  let channel = Channel.new 0
+  let sender = Async.spawn (\\_ => Channel.send channel 7)
+  Channel.receive channel == 7
+";
+
+    #[test]
+    fn the_modules_check_and_run() {
+        let name = Symbol::intern("Proj");
+
+        let mut fs = MemoryFileSystem::new(name.clone());
+
+        for (module, source) in MODULES {
+            let file = PathBuf::from(format!("{}.{}", module, EXTENSION));
+            fs.insert(file, source.to_string());
+        }
+
+        fs.insert(PathBuf::from("Main.vp"), MAIN.to_string());
+
+        let mut db = Database::new(fs, name, PathBuf::from("Main.vp"), Target::Vm);
+        assert!(db.diagnostics().is_empty());
+
+        let programs: Vec<_> = db
+            .modules()
+            .iter()
+            .map(|module| (*db.typed(module)).clone())
+            .collect();
+
+        let outcomes = runner::run(&programs, None);
+        assert_eq!(outcomes.len(), 8);
+
+        for outcome in outcomes {
+            let passed = matches!(outcome.verdict, runner::Verdict::Passed);
+            assert!(passed, "{}", outcome.description.get());
+        }
+    }
+}
@@ -0,0 +1,322 @@
+//! Timings of the phases of a compilation. Each phase records its wall time, the peak of the memory
+//! that it allocated and the number of nodes of the tree that it produced, for each module when the
+//! phase works on the modules one by one. They're used to find where a change made the compiler
+//! slower, so they're shown as a table or as JSON that can be compared between compilations.
+
+use std::{
+    alloc::{GlobalAlloc, Layout, System},
+    fmt::Write,
+    sync::atomic::{AtomicBool, AtomicUsize, Ordering},
+    time::{Duration, Instant},
+};
+
+use vulpi_show::{Show, TreeDisplay};
+
+static INSTALLED: AtomicBool = AtomicBool::new(false);
+static ALLOCATED: AtomicUsize = AtomicUsize::new(0);
+static PEAK: AtomicUsize = AtomicUsize::new(0);
+
+/// An allocator that counts the bytes that are allocated. The peaks of memory are only recorded
+/// when the binary uses it as its global allocator:
+///
+/// ```ignore
+/// #[global_allocator]
+/// static ALLOCATOR: vulpi_build::timings::Allocator = vulpi_build::timings::Allocator;
+/// ```
+pub struct Allocator;
+
+unsafe impl GlobalAlloc for Allocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let ptr = System.alloc(layout);
+
+        if !ptr.is_null() {
+            allocated(layout.size());
+        }
+
+        ptr
+    }
+
+    unsafe fn alloc_zeroed(&self, layout: Layout) -> *mut u8 {
+        let ptr = System.alloc_zeroed(layout);
+
+        if !ptr.is_null() {
+            allocated(layout.size());
+        }
+
+        ptr
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout);
+        ALLOCATED.fetch_sub(layout.size(), Ordering::Relaxed);
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        let new = System.realloc(ptr, layout, new_size);
+
+        if !new.is_null() {
+            ALLOCATED.fetch_sub(layout.size(), Ordering::Relaxed);
+            allocated(new_size);
+        }
+
+        new
+    }
+}
+
+fn allocated(size: usize) {
+    INSTALLED.store(true, Ordering::Relaxed);
+    let current = ALLOCATED.fetch_add(size, Ordering::Relaxed) + size;
+    PEAK.fetch_max(current, Ordering::Relaxed);
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Phase {
+    /// Lexing and parsing of each module.
+    Parse,
+
+    /// Collection of the names that each module declares.
+    Collect,
+
+    /// Resolution of the names of each module into its abstract tree.
+    Resolve,
+
+    /// Type checking and elaboration of the whole crate.
+    Typecheck,
+
+    /// Lowering into the core language and removal of the unreachable declarations.
+    Lower,
+
+    /// Optimization of the core language.
+    Optimize,
+
+    /// Generation of the code of the backend.
+    Codegen,
+}
+
+impl Phase {
+    pub const ALL: [Phase; 7] = [
+        Phase::Parse,
+        Phase::Collect,
+        Phase::Resolve,
+        Phase::Typecheck,
+        Phase::Lower,
+        Phase::Optimize,
+        Phase::Codegen,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Phase::Parse => "parse",
+            Phase::Collect => "collect",
+            Phase::Resolve => "resolve",
+            Phase::Typecheck => "typecheck",
+            Phase::Lower => "lower",
+            Phase::Optimize => "optimize",
+            Phase::Codegen => "codegen",
+        }
+    }
+}
+
+/// The start of a measurement. Measurements must not overlap, because each one resets the peak of
+/// the memory that was allocated.
+pub struct Start {
+    instant: Instant,
+    allocated: usize,
+}
+
+impl Start {
+    pub fn now() -> Self {
+        let allocated = ALLOCATED.load(Ordering::Relaxed);
+        PEAK.store(allocated, Ordering::Relaxed);
+
+        Self {
+            instant: Instant::now(),
+            allocated,
+        }
+    }
+}
+
+pub struct Entry {
+    pub phase: Phase,
+
+    /// The module that the phase worked on, or none if it worked on the whole crate.
+    pub module: Option<String>,
+
+    pub time: Duration,
+
+    /// The bytes allocated over the ones at the start, at the highest point of the phase. It's
+    /// unknown when the allocator is not installed or when the modules run in parallel.
+    pub peak: Option<usize>,
+
+    /// The nodes of the tree that the phase produced.
+    pub nodes: Option<usize>,
+}
+
+#[derive(Default)]
+pub struct Timings {
+    pub entries: Vec<Entry>,
+}
+
+impl Timings {
+    /// Records a phase that started at the start.
+    pub fn record(
+        &mut self,
+        phase: Phase,
+        module: Option<String>,
+        start: Start,
+        nodes: Option<usize>,
+    ) {
+        let time = start.instant.elapsed();
+
+        let peak = INSTALLED
+            .load(Ordering::Relaxed)
+            .then(|| PEAK.load(Ordering::Relaxed).saturating_sub(start.allocated));
+
+        self.entries.push(Entry {
+            phase,
+            module,
+            time,
+            peak,
+            nodes,
+        });
+    }
+
+    /// Records a phase of a module that was measured in another thread, so its peak is unknown.
+    pub fn record_parallel(&mut self, phase: Phase, module: String, time: Duration, nodes: usize) {
+        self.entries.push(Entry {
+            phase,
+            module: Some(module),
+            time,
+            peak: None,
+            nodes: Some(nodes),
+        });
+    }
+
+    /// The sum of the entries of each phase that ran. The peak is the highest of its entries.
+    pub fn totals(&self) -> Vec<Entry> {
+        let mut totals = Vec::new();
+
+        for phase in Phase::ALL {
+            let entries: Vec<_> = self.entries.iter().filter(|x| x.phase == phase).collect();
+
+            if entries.is_empty() {
+                continue;
+            }
+
+            totals.push(Entry {
+                phase,
+                module: None,
+                time: entries.iter().map(|x| x.time).sum(),
+                peak: entries.iter().filter_map(|x| x.peak).max(),
+                nodes: entries.iter().filter_map(|x| x.nodes).reduce(|x, y| x + y),
+            });
+        }
+
+        totals
+    }
+
+    pub fn table(&self) -> String {
+        let mut rows = vec![[
+            "phase".to_string(),
+            "module".to_string(),
+            "time".to_string(),
+            "peak".to_string(),
+            "nodes".to_string(),
+        ]];
+
+        let totals = self.totals();
+        let separator = rows.len() + self.entries.len();
+
+        let entries = self
+            .entries
+            .iter()
+            .map(|x| (x, x.module.as_deref().unwrap_or("crate")));
+        let totals = totals.iter().map(|x| (x, "total"));
+
+        for (entry, module) in entries.chain(totals) {
+            rows.push([
+                entry.phase.name().to_string(),
+                module.to_string(),
+                format!("{:.3}ms", entry.time.as_secs_f64() * 1000.0),
+                entry.peak.map_or_else(|| "-".to_string(), bytes),
+                entry
+                    .nodes
+                    .map_or_else(|| "-".to_string(), |x| x.to_string()),
+            ]);
+        }
+
+        let mut widths = [0; 5];
+
+        for row in &rows {
+            for (width, cell) in widths.iter_mut().zip(row) {
+                *width = (*width).max(cell.chars().count());
+            }
+        }
+
+        let mut table = String::new();
+
+        for (index, row) in rows.iter().enumerate() {
+            if index == 1 || index == separator {
+                let total = widths.iter().sum::<usize>() + 2 * (widths.len() - 1);
+                writeln!(table, "{}", "-".repeat(total)).unwrap();
+            }
+
+            // The phases and the modules are aligned to the left and the numbers to the right.
+            let line = format!(
+                "{:<a$}  {:<b$}  {:>c$}  {:>d$}  {:>e$}",
+                row[0],
+                row[1],
+                row[2],
+                row[3],
+                row[4],
+                a = widths[0],
+                b = widths[1],
+                c = widths[2],
+                d = widths[3],
+                e = widths[4],
+            );
+
+            writeln!(table, "{}", line.trim_end()).unwrap();
+        }
+
+        table
+    }
+
+    pub fn json(&self) -> String {
+        fn entry(entry: &Entry) -> serde_json::Value {
+            serde_json::json!({
+                "phase": entry.phase.name(),
+                "module": entry.module,
+                "time_us": entry.time.as_micros() as u64,
+                "peak_bytes": entry.peak,
+                "nodes": entry.nodes,
+            })
+        }
+
+        let json = serde_json::json!({
+            "entries": self.entries.iter().map(entry).collect::<Vec<_>>(),
+            "totals": self.totals().iter().map(entry).collect::<Vec<_>>(),
+        });
+
+        serde_json::to_string_pretty(&json).unwrap()
+    }
+}
+
+fn bytes(bytes: usize) -> String {
+    if bytes < 1024 {
+        format!("{}B", bytes)
+    } else if bytes < 1024 * 1024 {
+        format!("{:.1}KiB", bytes as f64 / 1024.0)
+    } else {
+        format!("{:.1}MiB", bytes as f64 / (1024.0 * 1024.0))
+    }
+}
+
+/// The number of nodes of a tree.
+pub fn nodes(value: &dyn Show) -> usize {
+    fn count(tree: &TreeDisplay) -> usize {
+        1 + tree.children.iter().map(count).sum::<usize>()
+    }
+
+    count(&value.show())
+}
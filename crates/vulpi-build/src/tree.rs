@@ -0,0 +1,78 @@
+//! The tree of the modules of a crate that have files. The module `Data.List` of a crate is in the
+//! file `Data/List.vp` of its source directory, so the tree is found by walking the directory. Only
+//! the paths are collected, the files are parsed later when a module imports them.
+
+use std::{
+    collections::HashMap,
+    fs, io,
+    path::{Path as FilePath, PathBuf},
+};
+
+use vulpi_intern::Symbol;
+use vulpi_vfs::path::Path;
+
+/// The extension of the source files.
+pub const EXTENSION: &str = "vp";
+
+#[derive(Default, Clone)]
+pub struct ModuleTree {
+    /// The file of the module. Directories that only have submodules don't have one.
+    pub file: Option<PathBuf>,
+    pub children: HashMap<Symbol, ModuleTree>,
+}
+
+impl ModuleTree {
+    /// Finds the source files in the directory and in its subdirectories.
+    pub fn discover(directory: &FilePath) -> io::Result<Self> {
+        let mut tree = ModuleTree::default();
+
+        for entry in fs::read_dir(directory)? {
+            let path = entry?.path();
+
+            let Some(name) = path.file_stem().and_then(|x| x.to_str()) else {
+                continue;
+            };
+
+            let name = Symbol::intern(name);
+
+            if path.is_dir() {
+                let subtree = ModuleTree::discover(&path)?;
+                let child = tree.children.entry(name).or_default();
+                child.children = subtree.children;
+            } else if path.extension().is_some_and(|x| x == EXTENSION) {
+                tree.children.entry(name).or_default().file = Some(path);
+            }
+        }
+
+        Ok(tree)
+    }
+
+    pub fn get(&self, path: &Path) -> Option<&ModuleTree> {
+        path.segments
+            .iter()
+            .try_fold(self, |tree, segment| tree.children.get(segment))
+    }
+
+    /// The file of a module by its path relative to the crate.
+    pub fn file(&self, path: &Path) -> Option<&PathBuf> {
+        self.get(path)?.file.as_ref()
+    }
+
+    /// The paths of all the modules that have files, sorted by their names.
+    pub fn modules(&self) -> Vec<Path> {
+        let mut modules = Vec::new();
+        self.collect(Path { segments: vec![] }, &mut modules);
+        modules.sort_by_key(|x| x.to_string());
+        modules
+    }
+
+    fn collect(&self, path: Path, modules: &mut Vec<Path>) {
+        if self.file.is_some() {
+            modules.push(path.clone());
+        }
+
+        for (name, child) in &self.children {
+            child.collect(path.with(name.clone()), modules);
+        }
+    }
+}
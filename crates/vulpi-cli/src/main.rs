@@ -1,30 +1,848 @@
-#![feature(panic_info_message)]
-#![feature(panic_can_unwind)]
+use std::{
+    backtrace::Backtrace,
+    collections::BTreeMap,
+    io::{self, Write},
+    panic,
+    path::{Path, PathBuf},
+    process,
+};
 
-use std::{backtrace::Backtrace, env, panic, path::PathBuf};
-
-use vulpi_build::real::RealFileSystem;
+use vulpi_build::{
+    cache::{self, Cache},
+    cfg,
+    emit::{self, Stage},
+    query::Database,
+    real::RealFileSystem,
+    repl::{Failure, Repl, COMMANDS},
+    runner::Verdict,
+    timings::{self, Timings},
+    ProjectCompiler,
+};
 use vulpi_intern::Symbol;
-use vulpi_report::renderer::classic::Classic;
+use vulpi_location::FileId;
+use vulpi_report::{
+    renderer::{classic::Classic, json::Json},
+    IntoDiagnostic, Level, Lints, Severity, Suggestion,
+};
+use vulpi_syntax::r#abstract::Qualified;
+use vulpi_vfs::FileSystem;
+
+use clap::{Args, Parser, ValueEnum};
 
-use clap::Parser;
+mod watch;
+
+/// Counts the allocations, so the timings can show the memory that each phase used.
+#[global_allocator]
+static ALLOCATOR: timings::Allocator = timings::Allocator;
 
 #[derive(Parser)]
+#[clap(name = "vulpi")]
 enum Cli {
-    Compile {
-        package: String,
-        file_name: String,
+    /// Parses, resolves and type checks the project without generating any code.
+    Check {
+        #[clap(flatten)]
+        project: Project,
+
+        /// Checks the project again when its files change. Only the modules that are affected by
+        /// a change are checked again.
+        #[clap(short, long)]
+        watch: bool,
+
+        /// The backend whose `when target` declarations are checked.
+        #[clap(short, long, value_enum, default_value_t = Target::Js)]
+        target: Target,
+    },
+
+    /// Compiles the project with one of the backends.
+    Build {
+        #[clap(flatten)]
+        project: Project,
+
+        #[clap(short, long, value_enum, default_value_t = Backend::Js)]
+        backend: Backend,
+
+        /// The file to write. Defaults to the name of the package.
+        #[clap(short, long)]
+        output: Option<PathBuf>,
+
+        /// Compiles the project twice before and fails if the outputs are not the same.
+        #[clap(long)]
+        verify_deterministic: bool,
+
+        /// Shows the time, the peak of memory and the nodes of each phase and module, as a table
+        /// in the standard error or as JSON in the standard output.
+        #[clap(long, value_enum, num_args = 0..=1, default_missing_value = "table")]
+        timings: Option<Format>,
+    },
+
+    /// Compiles the project to bytecode and runs its `main` in the virtual machine.
+    Run(Project),
+
+    /// Runs the `test` declarations of the project in the virtual machine, and fails if any of
+    /// them doesn't evaluate to `True`.
+    Test {
+        #[clap(flatten)]
+        project: Project,
 
+        /// Runs only the tests whose descriptions contain the text.
+        #[clap(long)]
+        filter: Option<String>,
+    },
+
+    /// Applies the fixes that the diagnostics suggest to the sources of the project, like making a
+    /// definition public or importing the module that declares a name. The unused declarations are
+    /// removed with `-W unused`.
+    Fix(Project),
+
+    /// Formats the sources of the project: blocks are indented by two spaces, long lines are
+    /// broken and the imports are sorted. The comments are kept.
+    Fmt {
+        #[clap(flatten)]
+        project: Project,
+
+        /// Lists the files that are not formatted instead of writing them, and fails if there is
+        /// any.
+        #[clap(long)]
+        check: bool,
+    },
+
+    /// Renames a declaration and the names that refer to it in all the modules of the project.
+    Rename {
+        #[clap(flatten)]
+        project: Project,
+
+        /// The declaration after the path of its module, like `Data.List.map` for the `map` of
+        /// `src/Data/List.vp`.
+        #[clap(long)]
+        name: String,
+
+        /// The new name of the declaration.
+        #[clap(long)]
+        to: String,
+    },
+
+    /// Writes the documentation of the public declarations of the project as HTML, with a page for
+    /// each module.
+    Doc {
+        #[clap(flatten)]
+        project: Project,
+
+        /// The directory to write the pages in. Defaults to `build/doc` in the project.
         #[clap(short, long)]
-        output: Option<String>,
+        output: Option<PathBuf>,
     },
+
+    /// Starts the language server of the project, that talks with an editor through the standard
+    /// input and output.
+    Lsp(Project),
+
+    /// Starts an interactive loop that evaluates expressions in the virtual machine. The
+    /// declarations that are entered take the place of the root module, so the other modules of
+    /// the project are used with `use`. `:type`, `:kind` and `:browse` show the type of an
+    /// expression, the kind of a type and the names of a module.
+    Repl(Project),
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum Backend {
+    /// A JavaScript file that calls `main` when it's loaded.
+    Js,
+
+    /// An executable compiled with Cranelift.
+    #[cfg(feature = "native")]
+    Native,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum Format {
+    Table,
+    Json,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum Target {
+    Js,
+    Native,
+    Vm,
+}
+
+impl From<Target> for cfg::Target {
+    fn from(target: Target) -> Self {
+        match target {
+            Target::Js => cfg::Target::Js,
+            Target::Native => cfg::Target::Native,
+            Target::Vm => cfg::Target::Vm,
+        }
+    }
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum Overflow {
+    /// Keeps the lower 64 bits of the result.
+    Wrap,
+
+    /// Stops the program with an error.
+    Check,
+
+    /// Gives the minimum or the maximum integer.
+    Saturate,
+}
+
+impl From<Overflow> for vulpi_build::Overflow {
+    fn from(overflow: Overflow) -> Self {
+        match overflow {
+            Overflow::Wrap => vulpi_build::Overflow::Wrap,
+            Overflow::Check => vulpi_build::Overflow::Check,
+            Overflow::Saturate => vulpi_build::Overflow::Saturate,
+        }
+    }
+}
+
+#[derive(Args)]
+struct Project {
+    /// The directory of the project. The module `Data.List` is in `src/Data/List.vp` and the root
+    /// module is `src/Main.vp`. It can be a file too, and then it's the root module of a project
+    /// whose modules are in the same directory.
+    #[clap(default_value = ".")]
+    path: PathBuf,
+
+    /// The name of the package. Defaults to the name of the directory or of the file, starting
+    /// with an upper case letter.
+    #[clap(short, long)]
+    package: Option<String>,
+
+    /// The level of the optimizer, 0 disables it.
+    #[clap(short = 'O', default_value_t = 0)]
+    optimization: usize,
+
+    /// What integer arithmetic does when the result doesn't fit in 64 bits. Defaults to check
+    /// without optimizations and to wrap with them.
+    #[clap(long, value_enum)]
+    overflow: Option<Overflow>,
+
+    /// Warns about the private let declarations that are never used. Same as `-W unused`.
+    #[clap(long)]
+    warn_unused: bool,
+
+    /// Reports the diagnostics of a lint as warnings. It can be given many times.
+    #[clap(short = 'W', value_name = "LINT", value_parser = lint)]
+    warn: Vec<&'static str>,
+
+    /// Doesn't report the diagnostics of a lint. It can be given many times.
+    #[clap(short = 'A', value_name = "LINT", value_parser = lint)]
+    allow: Vec<&'static str>,
+
+    /// Makes the warnings errors, so the compilation fails if there is any.
+    #[clap(long)]
+    deny_warnings: bool,
+
+    /// Resolves and type checks all the modules instead of loading the unchanged ones from the
+    /// cache of the project.
+    #[clap(long)]
+    no_cache: bool,
+
+    /// Writes the interface file of each module to the build directory. Other projects can use
+    /// the modules without their sources by putting the interface files in their build directory.
+    #[clap(long)]
+    emit_interfaces: bool,
+
+    /// Prints an intermediate representation of the compiler. It can be given many times.
+    #[clap(long, value_enum)]
+    emit: Vec<Emit>,
+
+    /// How the intermediate representations are printed.
+    #[clap(long, value_enum, default_value_t = EmitFormat::Tree)]
+    emit_format: EmitFormat,
+
+    /// How the diagnostics are shown.
+    #[clap(long, value_enum, default_value_t = MessageFormat::Human)]
+    message_format: MessageFormat,
+
+    /// Shows only the first N errors and the number of the other ones. All of them are shown as
+    /// JSON.
+    #[clap(long, value_name = "N")]
+    error_limit: Option<usize>,
+
+    /// The function that the program starts from, after the path of its module, like
+    /// `Server.start` for the `start` of `src/Server.vp`. Defaults to `Main.main`.
+    #[clap(long, value_name = "FUNCTION")]
+    entry: Option<String>,
+
+    /// The number of columns between two tab stops of the indentation of the sources.
+    #[clap(long, value_name = "N", default_value_t = vulpi_build::TAB_WIDTH)]
+    tab_width: usize,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum MessageFormat {
+    /// Code frames in the standard error.
+    Human,
+
+    /// An object for each diagnostic in a line of the standard output, for editors and other
+    /// tools.
+    Json,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum Emit {
+    /// The tokens of each module.
+    Tokens,
+
+    /// The concrete tree of each module.
+    Cst,
+
+    /// The abstract tree of each module, with the names resolved.
+    Ast,
+
+    /// The names that each module declares, imports and opens.
+    Resolved,
+
+    /// The elaborated tree of each module, with the types inferred.
+    Typed,
+
+    /// The optimized core language of the project.
+    Core,
+
+    /// The bytecode of the project, when it runs in the virtual machine.
+    Bytecode,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum EmitFormat {
+    /// An indented tree.
+    Tree,
+
+    /// A JSON object for each node, to compare the trees of two compilations structurally.
+    Json,
+
+    /// A graph of Graphviz, to see the trees with `dot`.
+    Dot,
+
+    /// Source of Vulpi for the abstract tree, and an indented tree for the other stages.
+    Source,
+}
+
+impl From<EmitFormat> for emit::Format {
+    fn from(format: EmitFormat) -> Self {
+        match format {
+            EmitFormat::Tree => emit::Format::Tree,
+            EmitFormat::Json => emit::Format::Json,
+            EmitFormat::Dot => emit::Format::Dot,
+            EmitFormat::Source => emit::Format::Source,
+        }
+    }
+}
+
+impl From<Emit> for Stage {
+    fn from(emit: Emit) -> Self {
+        match emit {
+            Emit::Tokens => Stage::Tokens,
+            Emit::Cst => Stage::Cst,
+            Emit::Ast => Stage::Ast,
+            Emit::Resolved => Stage::Resolved,
+            Emit::Typed => Stage::Typed,
+            Emit::Core => Stage::Core,
+            Emit::Bytecode => Stage::Bytecode,
+        }
+    }
+}
+
+fn lint(name: &str) -> Result<&'static str, String> {
+    vulpi_build::LINTS
+        .into_iter()
+        .find(|lint| *lint == name)
+        .ok_or_else(|| format!("the lints are {}", vulpi_build::LINTS.join(", ")))
+}
+
+/// A project that is being compiled: the compiler of its crate, its directory and its root module
+/// relative to the directory of the sources.
+struct Compilation {
+    name: Symbol,
+    directory: PathBuf,
+    sources: PathBuf,
+    root: PathBuf,
+    format: MessageFormat,
+    limit: Option<usize>,
+    compiler: ProjectCompiler<RealFileSystem>,
+}
+
+impl Project {
+    /// Builds without optimizations are the ones for debugging, so they catch overflows.
+    fn overflow(&self) -> vulpi_build::Overflow {
+        match self.overflow {
+            Some(overflow) => overflow.into(),
+            None if self.optimization == 0 => vulpi_build::Overflow::Check,
+            None => vulpi_build::Overflow::Wrap,
+        }
+    }
+
+    fn open(&self) -> Compilation {
+        let path = self.path.canonicalize().unwrap_or_else(|_| {
+            fail(&format!("cannot find '{}'", self.path.display()));
+        });
+
+        let (directory, sources, root) = if path.is_dir() {
+            (path.clone(), path.join("src"), PathBuf::from("Main.vp"))
+        } else {
+            let directory = path.parent().map(Path::to_path_buf).unwrap_or_default();
+            let root = PathBuf::from(path.file_name().unwrap());
+            (directory.clone(), directory, root)
+        };
+
+        if !sources.join(&root).is_file() {
+            fail(&format!(
+                "cannot find the root module '{}'",
+                sources.join(&root).display()
+            ));
+        }
+
+        let package = self.package.clone().unwrap_or_else(|| {
+            let stem = if path.is_dir() {
+                path.file_name()
+            } else {
+                path.file_stem()
+            };
+            let stem = stem.and_then(|x| x.to_str()).unwrap_or("Main");
+
+            // Modules of the crate are imported by a path that starts with its name, and paths
+            // are made of upper case identifiers.
+            let mut chars = stem.chars();
+            chars
+                .next()
+                .map_or_else(String::new, |x| x.to_uppercase().chain(chars).collect())
+        });
+
+        let name = Symbol::intern(&package);
+
+        let mut lints = Lints::default();
+
+        if self.warn_unused {
+            lints.set(vulpi_build::UNUSED, Level::Warn);
+        }
+
+        for lint in &self.warn {
+            lints.set(lint, Level::Warn);
+        }
+
+        for lint in &self.allow {
+            lints.set(lint, Level::Allow);
+        }
+
+        if self.deny_warnings {
+            lints.deny_warnings();
+        }
+
+        let reporter = vulpi_report::hash_reporter();
+        reporter.set_lints(lints.clone());
+
+        let entry = self.entry.as_ref().map(|entry| {
+            let Some((path, last)) = entry.rsplit_once('.') else {
+                fail(&format!("'{}' doesn't have the path of its module", entry));
+            };
+
+            Qualified {
+                path: Symbol::intern(&format!("{}.{}", name.get(), path)),
+                name: Symbol::intern(last),
+            }
+        });
+
+        let compiler = ProjectCompiler {
+            fs: RealFileSystem::new(name.clone(), sources.clone(), directory.join("build")),
+            reporter,
+            name: name.clone(),
+            optimization: self.optimization,
+            overflow: self.overflow(),
+            unused: lints.level(vulpi_build::UNUSED) == Some(Level::Warn),
+            cache: (!self.no_cache).then(|| Cache::new(directory.join(cache::DIRECTORY))),
+            interfaces: self.emit_interfaces,
+            target: cfg::Target::default(),
+            emit: self.emit.iter().map(|x| Stage::from(*x)).collect(),
+            emit_format: self.emit_format.into(),
+            timings: None,
+            entry,
+            tab_width: self.tab_width,
+        };
+
+        Compilation {
+            name,
+            directory,
+            sources,
+            root,
+            format: self.message_format,
+            limit: self.error_limit,
+            compiler,
+        }
+    }
+}
+
+impl Compilation {
+    /// Compiles the project to the contents of the file that the backend writes, or of the object
+    /// file that it links.
+    fn output(&mut self, backend: Backend) -> Option<Vec<u8>> {
+        let name = self.name.clone();
+        let root = self.root.clone();
+
+        match backend {
+            Backend::Js => self.compiler.javascript(name, root).map(String::into_bytes),
+            #[cfg(feature = "native")]
+            Backend::Native => self.compiler.native_object(name, root),
+        }
+    }
+
+    /// Shows the diagnostics and exits if any of them is an error.
+    fn report(&self) {
+        match self.format {
+            MessageFormat::Human => {
+                let ctx = Classic::new(&self.compiler.fs, self.directory.clone());
+                self.compiler.reporter.to_stderr(ctx, self.limit);
+            }
+            MessageFormat::Json => {
+                let ctx = Json::new(&self.compiler.fs, self.directory.clone());
+                self.compiler.reporter.to_stdout_json(ctx);
+            }
+        }
+
+        if self.compiler.reporter.has_errors() {
+            process::exit(1);
+        }
+    }
+}
+
+/// Compiles the project twice without the cache and fails if the outputs are not the same.
+fn verify(project: &Project, backend: Backend) {
+    let mut outputs = Vec::new();
+
+    for _ in 0..2 {
+        let mut compilation = project.open();
+        compilation.compiler.cache = None;
+
+        let output = compilation.output(backend);
+        compilation.report();
+        outputs.push(output.unwrap_or_default());
+    }
+
+    let (first, second) = (&outputs[0], &outputs[1]);
+
+    if first != second {
+        let position = first.iter().zip(second).take_while(|(x, y)| x == y).count();
+
+        fail(&format!(
+            "the project was compiled twice to different outputs, they differ at byte {}",
+            position
+        ));
+    }
+}
+
+/// Applies the suggestions of the diagnostics of the project to its files.
+fn fix(compilation: &Compilation) {
+    let mut files: BTreeMap<FileId, Vec<Suggestion>> = BTreeMap::new();
+
+    for diagnostic in compilation.compiler.reporter.all_diagnostics() {
+        for suggestion in diagnostic.suggestions() {
+            files
+                .entry(suggestion.span.file)
+                .or_default()
+                .push(suggestion);
+        }
+    }
+
+    let mut total = 0;
+
+    for (file, suggestions) in files {
+        let fs = &compilation.compiler.fs;
+
+        let (Ok(path), Ok(source)) = (fs.path(file), fs.read(file)) else {
+            continue;
+        };
+
+        let (fixed, applied) = vulpi_build::fix::apply(&source, suggestions);
+
+        if let Err(err) = std::fs::write(path, fixed) {
+            fail(&format!("cannot write '{}': {}", path.display(), err));
+        }
+
+        let relative = path.strip_prefix(&compilation.directory).unwrap_or(path);
+        let plural = if applied == 1 { "" } else { "es" };
+        eprintln!(
+            "[Fixed]: {} with {} fix{}",
+            relative.display(),
+            applied,
+            plural
+        );
+
+        total += applied;
+    }
+
+    if total == 0 {
+        eprintln!("[Fixed]: there is nothing to fix");
+    }
+}
+
+/// Formats the files of the project, or lists the ones that are not formatted.
+fn format_sources(compilation: &mut Compilation, check: bool) {
+    let tree = compilation.compiler.fs.tree();
+
+    let paths = tree
+        .modules()
+        .iter()
+        .filter_map(|x| tree.file(x).cloned())
+        .collect::<Vec<_>>();
+
+    let tab_width = compilation.compiler.tab_width;
+    let mut unformatted = Vec::new();
+
+    for path in paths {
+        let fs = &mut compilation.compiler.fs;
+
+        let (Ok(file), Ok(source)) = (fs.load(path.clone()), std::fs::read_to_string(&path)) else {
+            fail(&format!("cannot read '{}'", path.display()));
+        };
+
+        let formatted = match vulpi_build::format::format(file, &source, tab_width) {
+            Ok(formatted) => formatted,
+            Err(diagnostics) => {
+                for diagnostic in diagnostics {
+                    compilation.compiler.reporter.report(diagnostic);
+                }
+
+                continue;
+            }
+        };
+
+        if formatted == source {
+            continue;
+        }
+
+        let relative = path.strip_prefix(&compilation.directory).unwrap_or(&path);
+
+        if check {
+            unformatted.push(relative.to_path_buf());
+            continue;
+        }
+
+        if let Err(err) = std::fs::write(&path, formatted) {
+            fail(&format!("cannot write '{}': {}", path.display(), err));
+        }
+
+        eprintln!("[Formatted]: {}", relative.display());
+    }
+
+    compilation.report();
+
+    for path in &unformatted {
+        eprintln!("[Unformatted]: {}", path.display());
+    }
+
+    if !unformatted.is_empty() {
+        process::exit(1);
+    }
+}
+
+fn rename(compilation: Compilation, name: &str, to: &str) {
+    let root = compilation.sources.join(&compilation.root);
+    let target = compilation.compiler.target;
+    let tab_width = compilation.compiler.tab_width;
+    let mut db = Database::new(
+        compilation.compiler.fs,
+        compilation.name.clone(),
+        root,
+        target,
+    )
+    .with_tab_width(tab_width);
+
+    // The names of a module that doesn't resolve are not known to refer to the declaration.
+    let diagnostics = db.diagnostics();
+
+    if diagnostics.iter().any(|x| x.severity() == Severity::Error) {
+        fail("the project has errors, they must be fixed before renaming");
+    }
+
+    let Some((path, last)) = name.rsplit_once('.') else {
+        fail(&format!("'{}' doesn't have the path of its module", name));
+    };
+
+    let qualified = Qualified {
+        path: Symbol::intern(&format!("{}.{}", compilation.name.get(), path)),
+        name: Symbol::intern(last),
+    };
+
+    let edits = vulpi_build::rename::rename(&mut db, &qualified, to)
+        .unwrap_or_else(|err| fail(&err.message().plain()));
+
+    for (file, suggestions) in edits {
+        let (Ok(path), Ok(source)) = (db.fs.path(file), db.fs.read(file)) else {
+            continue;
+        };
+
+        let (renamed, applied) = vulpi_build::fix::apply(&source, suggestions);
+
+        if let Err(err) = std::fs::write(path, renamed) {
+            fail(&format!("cannot write '{}': {}", path.display(), err));
+        }
+
+        let relative = path.strip_prefix(&compilation.directory).unwrap_or(path);
+        let plural = if applied == 1 { "" } else { "s" };
+        eprintln!(
+            "[Renamed]: {} with {} name{}",
+            relative.display(),
+            applied,
+            plural
+        );
+    }
+}
+
+fn doc(compilation: Compilation, output: Option<PathBuf>) {
+    let root = compilation.sources.join(&compilation.root);
+    let target = compilation.compiler.target;
+    let tab_width = compilation.compiler.tab_width;
+    let mut db = Database::new(
+        compilation.compiler.fs,
+        compilation.name.clone(),
+        root,
+        target,
+    )
+    .with_tab_width(tab_width);
+
+    let diagnostics = db.diagnostics();
+
+    if diagnostics.iter().any(|x| x.severity() == Severity::Error) {
+        fail("the project has errors, they must be fixed before documenting");
+    }
+
+    let output = output.unwrap_or_else(|| compilation.directory.join("build").join("doc"));
+    let pages = vulpi_build::doc::generate(&mut db);
+
+    if let Err(err) = std::fs::create_dir_all(&output) {
+        fail(&format!("cannot create '{}': {}", output.display(), err));
+    }
+
+    for (name, page) in &pages {
+        let path = output.join(name);
+
+        if let Err(err) = std::fs::write(&path, page) {
+            fail(&format!("cannot write '{}': {}", path.display(), err));
+        }
+    }
+
+    // The index is not a module.
+    let modules = pages.len() - 1;
+    let plural = if modules == 1 { "" } else { "s" };
+
+    println!(
+        "[Documented]: {} module{} in {}",
+        modules,
+        plural,
+        output.display()
+    );
+}
+
+/// Reads the lines of the standard input until it ends, and answers each of them.
+fn repl(compilation: Compilation) {
+    let root = compilation.sources.join(&compilation.root);
+    let tab_width = compilation.compiler.tab_width;
+    let db = Database::new(
+        compilation.compiler.fs,
+        compilation.name.clone(),
+        root,
+        cfg::Target::Vm,
+    )
+    .with_tab_width(tab_width);
+
+    let mut repl = Repl::new(db);
+    let mut line = String::new();
+
+    loop {
+        print!("> ");
+        let _ = io::stdout().flush();
+
+        line.clear();
+
+        match io::stdin().read_line(&mut line) {
+            Ok(0) | Err(_) => break,
+            Ok(_) => (),
+        }
+
+        match repl.eval(&line) {
+            Ok(Some(answer)) => println!("{}", answer),
+            Ok(None) => (),
+            Err(Failure::Diagnostics(diagnostics)) => {
+                let reporter = vulpi_report::hash_reporter();
+                reporter.merge(diagnostics);
+
+                match compilation.format {
+                    MessageFormat::Human => {
+                        let ctx = Classic::new(&repl.db.fs, compilation.directory.clone());
+                        reporter.to_stderr(ctx, compilation.limit);
+                    }
+                    MessageFormat::Json => {
+                        let ctx = Json::new(&repl.db.fs, compilation.directory.clone());
+                        reporter.to_stdout_json(ctx);
+                    }
+                }
+            }
+            Err(Failure::Runtime(err)) => eprintln!("[Error]: {}", err),
+            Err(Failure::UnknownCommand(name)) => {
+                let commands: Vec<_> = COMMANDS.iter().map(|x| format!(":{}", x)).collect();
+                eprintln!(
+                    "[Error]: unknown command ':{}', the commands are {}",
+                    name,
+                    commands.join(", ")
+                );
+            }
+        }
+    }
+}
+
+/// Runs the tests of the project and shows the verdict of each one. The tests that don't pass are
+/// shown as diagnostics too, with their spans.
+fn test(mut compilation: Compilation, filter: Option<String>) {
+    let name = compilation.name.clone();
+    let root = compilation.root.clone();
+
+    let Some(outcomes) = compilation.compiler.test(name, root, filter.as_deref()) else {
+        compilation.report();
+        return;
+    };
+
+    for outcome in &outcomes {
+        let verdict = match outcome.verdict {
+            Verdict::Passed => "Passed",
+            Verdict::Failed => "Failed",
+            Verdict::Errored(_) => "Errored",
+        };
+
+        eprintln!("[{}]: {}", verdict, outcome.description.get());
+    }
+
+    let passed = outcomes.iter().filter(|x| x.passed()).count();
+
+    eprintln!(
+        "[Tested]: {} passed, {} failed",
+        passed,
+        outcomes.len() - passed
+    );
+
+    compilation.report();
+}
+
+fn fail(message: &str) -> ! {
+    eprintln!("\n[Error]: {}", message);
+    process::exit(1)
 }
 
 fn main() {
     panic::set_hook(Box::new(|e| {
+        let payload = e.payload();
+
+        let message = payload
+            .downcast_ref::<&str>()
+            .map(|x| x.to_string())
+            .or_else(|| payload.downcast_ref::<String>().cloned())
+            .unwrap_or_default();
+
         eprintln!(
-            "\n[Error]: internal compiler error '{:?}' at {}",
-            e.message().unwrap(),
+            "\n[Error]: internal compiler error '{}' at {}",
+            message,
             e.location().unwrap()
         );
         eprintln!("-  It should not occur. Please submit an issue to the Vulpi repository:)");
@@ -37,36 +855,123 @@ fn main() {
         }
     }));
 
-    let result = Cli::parse();
+    match Cli::parse() {
+        Cli::Check {
+            project,
+            watch,
+            target,
+        } => {
+            let mut compilation = project.open();
+            compilation.compiler.target = target.into();
+
+            if watch {
+                watch::watch(compilation);
+            }
+
+            compilation
+                .compiler
+                .check(compilation.name.clone(), compilation.root.clone());
 
-    match result {
-        Cli::Compile {
-            file_name,
-            package,
+            compilation.report();
+        }
+        Cli::Build {
+            project,
+            backend,
             output,
+            verify_deterministic,
+            timings,
         } => {
-            let cwd = env::current_dir().unwrap();
+            if verify_deterministic {
+                verify(&project, backend);
+            }
 
-            let name = Symbol::intern(&package);
+            let mut compilation = project.open();
+            compilation.compiler.timings = timings.map(|_| Timings::default());
 
-            let output = output.unwrap_or_else(|| {
-                format!("{}.js", file_name.split(".").next().unwrap().to_string())
-            });
+            let name = compilation.name.clone();
+            let root = compilation.root.clone();
 
-            let mut compiler = vulpi_build::ProjectCompiler {
-                fs: RealFileSystem::new(name.clone(), cwd.clone(), cwd.clone().join("build")),
-                reporter: vulpi_report::hash_reporter(),
-                name: name.clone(),
+            let result: std::io::Result<()> = match backend {
+                Backend::Js => {
+                    let output =
+                        output.unwrap_or_else(|| PathBuf::from(format!("{}.js", name.get())));
+                    compilation.compiler.compile(name, root, output);
+                    Ok(())
+                }
+                #[cfg(feature = "native")]
+                Backend::Native => {
+                    let output = output.unwrap_or_else(|| PathBuf::from(name.get()));
+                    compilation.compiler.build_native(name, root, output)
+                }
             };
 
-            compiler.compile(
-                name.clone(),
-                PathBuf::from(file_name),
-                PathBuf::from(output),
-            );
+            compilation.report();
+
+            if let Some((timings, format)) = compilation.compiler.timings.zip(timings) {
+                match format {
+                    Format::Table => eprint!("\n{}", timings.table()),
+                    Format::Json => println!("{}", timings.json()),
+                }
+            }
+
+            if let Err(err) = result {
+                fail(&err.to_string());
+            }
+        }
+        Cli::Fix(project) => {
+            let mut compilation = project.open();
+
+            compilation
+                .compiler
+                .analyze(compilation.name.clone(), compilation.root.clone());
+
+            fix(&compilation);
+        }
+        Cli::Fmt { project, check } => {
+            let mut compilation = project.open();
+            format_sources(&mut compilation, check);
+        }
+        Cli::Rename { project, name, to } => {
+            let compilation = project.open();
+            rename(compilation, &name, &to);
+        }
+        Cli::Doc { project, output } => {
+            let compilation = project.open();
+            doc(compilation, output);
+        }
+        Cli::Lsp(project) => {
+            let compilation = project.open();
+
+            let root = compilation.sources.join(&compilation.root);
+            let target = compilation.compiler.target;
+            let tab_width = compilation.compiler.tab_width;
+            let db = Database::new(compilation.compiler.fs, compilation.name, root, target)
+                .with_tab_width(tab_width);
+
+            if let Err(err) = vulpi_lsp::serve(db) {
+                fail(&format!("the language server stopped: {}", err));
+            }
+        }
+        Cli::Repl(project) => {
+            let compilation = project.open();
+            repl(compilation);
+        }
+        Cli::Test { project, filter } => {
+            let compilation = project.open();
+            test(compilation, filter);
+        }
+        Cli::Run(project) => {
+            let mut compilation = project.open();
+
+            let result = compilation
+                .compiler
+                .run(compilation.name.clone(), compilation.root.clone());
+
+            compilation.report();
 
-            let ctx = Classic::new(&compiler.fs, cwd.clone());
-            compiler.reporter.to_stderr(ctx)
+            if let Err(err) = result {
+                fail(&err.to_string());
+            }
         }
     }
 }
@@ -0,0 +1,174 @@
+//! The watch mode of the `check` command. The modules are kept in a query database between the
+//! changes, so only the modules that are affected by a change are checked again, and only the files
+//! whose diagnostics changed are shown again.
+
+use std::{
+    collections::{BTreeMap, HashSet},
+    fs,
+    path::PathBuf,
+    sync::mpsc,
+    time::{Duration, Instant},
+};
+
+use notify::{RecursiveMode, Watcher};
+use vulpi_build::{query::Database, real::RealFileSystem, tree::EXTENSION};
+use vulpi_report::{
+    renderer::{classic::Classic, json::Json, Renderer},
+    Diagnostic, Lints, Severity,
+};
+use vulpi_vfs::FileSystem;
+
+use crate::{fail, Compilation, MessageFormat};
+
+/// Changes that arrive close to each other are checked together, like the ones of an editor that
+/// saves many files at once.
+const DEBOUNCE: Duration = Duration::from_millis(50);
+
+pub fn watch(compilation: Compilation) -> ! {
+    let root = compilation.sources.join(&compilation.root);
+    let target = compilation.compiler.target;
+    let lints = compilation.compiler.reporter.lints();
+    let mut db = Database::new(compilation.compiler.fs, compilation.name, root, target);
+
+    let (sender, receiver) = mpsc::channel();
+
+    let mut watcher = notify::recommended_watcher(sender)
+        .unwrap_or_else(|err| fail(&format!("cannot watch the files: {}", err)));
+
+    watcher
+        .watch(&compilation.sources, RecursiveMode::Recursive)
+        .unwrap_or_else(|err| fail(&format!("cannot watch the files: {}", err)));
+
+    let mut shown = BTreeMap::new();
+
+    loop {
+        let start = Instant::now();
+        show(
+            &mut db,
+            &lints,
+            compilation.format,
+            compilation.limit,
+            &compilation.directory,
+            &mut shown,
+        );
+
+        eprintln!(
+            "\n[Watching]: checked in {}ms, waiting for changes",
+            start.elapsed().as_millis()
+        );
+
+        let mut changed = HashSet::new();
+
+        let Ok(event) = receiver.recv() else {
+            fail("the watcher of the files stopped");
+        };
+
+        let mut events = vec![event];
+
+        while let Ok(event) = receiver.recv_timeout(DEBOUNCE) {
+            events.push(event);
+        }
+
+        for event in events.into_iter().flatten() {
+            changed.extend(
+                event
+                    .paths
+                    .into_iter()
+                    .filter(|path| path.extension().is_some_and(|x| x == EXTENSION)),
+            );
+        }
+
+        for path in changed {
+            match fs::read_to_string(&path) {
+                Ok(source) => {
+                    let _ = db.set_source(path, source);
+                }
+                Err(_) => db.remove_source(path),
+            }
+        }
+    }
+}
+
+/// Shows the diagnostics of the files that changed since the last time that they were shown. As
+/// JSON, they're printed to the standard output and the names of the files to the standard error.
+/// The errors over the limit are only shown as JSON.
+fn show(
+    db: &mut Database<RealFileSystem>,
+    lints: &Lints,
+    format: MessageFormat,
+    limit: Option<usize>,
+    directory: &PathBuf,
+    shown: &mut BTreeMap<PathBuf, String>,
+) {
+    let diagnostics = db.diagnostics();
+
+    let classic = Classic::new(&db.fs, directory.clone());
+    let json = Json::new(&db.fs, directory.clone());
+
+    let mut rendered: BTreeMap<PathBuf, Vec<u8>> = shown
+        .keys()
+        .map(|file| (file.clone(), Vec::new()))
+        .collect();
+
+    let mut errors = 0;
+
+    for diagnostic in diagnostics {
+        let Some(diagnostic) = lints.apply(Diagnostic::new(diagnostic)) else {
+            continue;
+        };
+
+        if let Severity::Error = diagnostic.severity() {
+            errors += 1;
+
+            let over = limit.is_some_and(|limit| errors > limit);
+
+            if over && format == MessageFormat::Human {
+                continue;
+            }
+        }
+
+        let Ok(file) = db.fs.path(diagnostic.location().file) else {
+            continue;
+        };
+
+        let output = rendered.entry(file.clone()).or_default();
+
+        match format {
+            MessageFormat::Human => diagnostic.render(&classic, output).unwrap(),
+            MessageFormat::Json => diagnostic.render(&json, output).unwrap(),
+        }
+    }
+
+    for (file, output) in rendered {
+        let output = String::from_utf8_lossy(&output).to_string();
+
+        if shown.get(&file) == Some(&output) {
+            continue;
+        }
+
+        let relative = file.strip_prefix(directory).unwrap_or(&file);
+
+        if output.is_empty() {
+            eprintln!("\n[Checked]: {} has no problems", relative.display());
+        } else {
+            eprintln!("\n[Checked]: {}", relative.display());
+
+            match format {
+                MessageFormat::Human => eprint!("{}", output),
+                MessageFormat::Json => print!("{}", output),
+            }
+        }
+
+        shown.insert(file, output);
+    }
+
+    if let Some(limit) = limit.filter(|_| format == MessageFormat::Human) {
+        if errors > limit {
+            eprintln!("\n  and {} more errors", errors - limit);
+        }
+    }
+
+    if errors != 0 {
+        eprintln!("\n[Error]: {} errors", errors);
+    }
+}
@@ -0,0 +1,522 @@
+//! Tests of the commands of the driver. They run the binary on projects in temporary directories.
+
+use std::{
+    fs,
+    io::{BufRead, BufReader},
+    path::PathBuf,
+    process::{Command, Output, Stdio},
+    sync::mpsc,
+    time::Duration,
+};
+
+/// A project in a temporary directory that is removed when it's dropped. Its files are relative
+/// to the `src` directory.
+struct Project {
+    directory: PathBuf,
+}
+
+impl Project {
+    fn new(name: &str, files: &[(&str, &str)]) -> Self {
+        let directory = std::env::temp_dir()
+            .join(format!("vulpi-cli-{}", std::process::id()))
+            .join(name);
+
+        for (file, source) in files {
+            let path = directory.join("src").join(file);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, source).unwrap();
+        }
+
+        Self { directory }
+    }
+
+    fn vulpi(&self, args: &[&str]) -> Output {
+        Command::new(env!("CARGO_BIN_EXE_vulpi"))
+            .args(args)
+            .current_dir(&self.directory)
+            .output()
+            .unwrap()
+    }
+}
+
+impl Drop for Project {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.directory);
+    }
+}
+
+fn stdout(output: &Output) -> String {
+    String::from_utf8_lossy(&output.stdout).to_string()
+}
+
+fn stderr(output: &Output) -> String {
+    String::from_utf8_lossy(&output.stderr).to_string()
+}
+
+/// The text without the escape sequences of its colors and the spaces at the end of its lines.
+fn plain(text: &str) -> String {
+    let mut plain = String::new();
+    let mut chars = text.chars();
+
+    while let Some(char) = chars.next() {
+        if char == '\x1b' {
+            chars.by_ref().find(|x| *x == 'm');
+        } else {
+            plain.push(char);
+        }
+    }
+
+    plain
+        .lines()
+        .map(str::trim_end)
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+#[test]
+fn check_build_and_run_stop_at_their_phases() {
+    let project = Project::new(
+        "driver",
+        &[(
+            "Main.vp",
+            "use Prelude\n\nlet main (x : ()) : () = print \"hi\"\n",
+        )],
+    );
+
+    assert!(project.vulpi(&["check"]).status.success());
+
+    let run = project.vulpi(&["run"]);
+    assert!(run.status.success());
+    assert_eq!(stdout(&run), "hi\n");
+
+    assert!(project.vulpi(&["build"]).status.success());
+    assert!(project.directory.join("Driver.js").exists());
+
+    let broken = Project::new(
+        "broken",
+        &[(
+            "Main.vp",
+            "use Prelude\n\nlet main (x : ()) : () = print 1\n",
+        )],
+    );
+
+    // The type error is found by `check`, so nothing is built or run.
+    let check = broken.vulpi(&["check"]);
+    assert!(!check.status.success());
+    assert!(stderr(&check).contains("E0302"));
+
+    let run = broken.vulpi(&["run"]);
+    assert!(!run.status.success());
+    assert_eq!(stdout(&run), "");
+
+    assert!(!broken.vulpi(&["build"]).status.success());
+    assert!(!broken.directory.join("Broken.js").exists());
+}
+
+#[test]
+fn modules_are_found_by_their_paths_when_they_are_imported() {
+    let main = "use Prelude\nuse Data.Text\n\nlet main (x : ()) : () = print Data.Text.greeting\n";
+    let text = "use Prelude\n\npub let greeting : String = \"from text\"\n";
+
+    // The module that nothing imports is never parsed, so its syntax error is not reported.
+    let project = Project::new(
+        "modules",
+        &[
+            ("Main.vp", main),
+            ("Data/Text.vp", text),
+            ("Unused.vp", "let = =\n"),
+        ],
+    );
+
+    let run = project.vulpi(&["run"]);
+    assert!(run.status.success());
+    assert_eq!(stdout(&run), "from text\n");
+
+    let main = "use Prelude\nuse Data.Missing\n\nlet main (x : ()) : () = ()\n";
+    let project = Project::new("missing", &[("Main.vp", main)]);
+
+    let check = project.vulpi(&["check"]);
+    let searched = project.directory.join("src/Data/Missing.vp");
+
+    assert!(!check.status.success());
+    assert!(stderr(&check).contains("E0700"));
+    assert!(stderr(&check).contains(&searched.display().to_string()));
+}
+
+/// Reads the lines of the watch mode until it waits for changes again, giving what it showed.
+fn checked(lines: &mpsc::Receiver<String>) -> String {
+    let mut shown = String::new();
+
+    loop {
+        let line = lines.recv_timeout(Duration::from_secs(30)).unwrap();
+
+        if line.starts_with("[Watching]") {
+            return shown;
+        }
+
+        shown.push_str(&line);
+        shown.push('\n');
+    }
+}
+
+#[test]
+fn watch_mode_shows_again_only_the_files_that_changed() {
+    let main = "use Prelude\nuse Other\n\nlet main (x : ()) : () = print Other.name\n";
+    let other = "use Prelude\n\npub let name : String = \"a\"\n";
+    let project = Project::new("watch", &[("Main.vp", main), ("Other.vp", other)]);
+
+    let mut child = Command::new(env!("CARGO_BIN_EXE_vulpi"))
+        .args(["check", "--watch"])
+        .current_dir(&project.directory)
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+
+    let (sender, lines) = mpsc::channel();
+    let stderr = BufReader::new(child.stderr.take().unwrap());
+
+    std::thread::spawn(move || {
+        for line in stderr.lines().map_while(Result::ok) {
+            if sender.send(line).is_err() {
+                break;
+            }
+        }
+    });
+
+    assert_eq!(checked(&lines).trim(), "");
+
+    let path = project.directory.join("src/Other.vp");
+    fs::write(&path, "use Prelude\n\npub let name : String = 1\n").unwrap();
+
+    let broken = checked(&lines);
+    assert!(broken.contains("[Checked]: src/Other.vp"));
+    assert!(broken.contains("E0302"));
+    assert!(!broken.contains("src/Main.vp"));
+
+    fs::write(&path, other).unwrap();
+
+    let fixed = checked(&lines);
+    assert!(fixed.contains("[Checked]: src/Other.vp has no problems"));
+    assert!(!fixed.contains("src/Main.vp"));
+
+    child.kill().unwrap();
+    child.wait().unwrap();
+}
+
+#[test]
+fn emit_prints_each_representation_before_the_program_runs() {
+    let project = Project::new(
+        "emit",
+        &[(
+            "Main.vp",
+            "use Prelude\n\nlet main (x : ()) : () = print \"hi\"\n",
+        )],
+    );
+
+    let run = project.vulpi(&["run", "--emit", "tokens", "--emit", "bytecode"]);
+    let printed = stdout(&run);
+
+    // The stages of the front end are printed for each module and the later ones for the crate.
+    assert!(run.status.success());
+    assert!(printed.contains("[Emit]: tokens of Emit.Main"));
+    assert!(printed.contains("[Emit]: tokens of Prelude"));
+    assert!(printed.contains("[Emit]: bytecode of Emit"));
+    assert!(!printed.contains("[Emit]: core of"));
+    assert!(printed.ends_with("hi\n"));
+
+    let run = project.vulpi(&["run", "--emit", "core", "--emit-format", "json"]);
+
+    assert!(run.status.success());
+    assert!(stdout(&run)
+        .lines()
+        .any(|line| line.starts_with("{\"label\":\"Program\"")));
+}
+
+#[test]
+fn builds_and_diagnostics_are_the_same_every_time() {
+    let modules: Vec<_> = (0..8)
+        .map(|i| {
+            (
+                format!("Part{i}.vp"),
+                format!("use Prelude\n\npub let value{i} : Int = \"{i}\"\n"),
+            )
+        })
+        .collect();
+
+    let uses: String = (0..8).map(|i| format!("use Part{i}\n")).collect();
+    let main = format!("use Prelude\n{uses}\nlet main (x : ()) : () = print \"hi\"\n");
+
+    let mut files = vec![("Main.vp", main.as_str())];
+    files.extend(
+        modules
+            .iter()
+            .map(|(file, source)| (file.as_str(), source.as_str())),
+    );
+
+    // The type errors of the modules come out in the same order whatever their hashes.
+    let broken = Project::new("unordered", &files);
+    let first = stderr(&broken.vulpi(&["check", "--no-cache"]));
+    assert_eq!(first.matches("E0302").count(), 8);
+
+    for _ in 0..3 {
+        assert_eq!(stderr(&broken.vulpi(&["check", "--no-cache"])), first);
+    }
+
+    let project = Project::new(
+        "deterministic",
+        &[(
+            "Main.vp",
+            "use Prelude\n\nlet main (x : ()) : () = print \"hi\"\n",
+        )],
+    );
+
+    let build = project.vulpi(&["build", "--verify-deterministic"]);
+    assert!(build.status.success(), "{}", stderr(&build));
+
+    let script = project.directory.join("Deterministic.js");
+    let first = fs::read(&script).unwrap();
+
+    assert!(project.vulpi(&["build", "--no-cache"]).status.success());
+    assert_eq!(fs::read(&script).unwrap(), first);
+}
+
+#[test]
+fn timings_are_recorded_for_each_phase_and_module() {
+    let project = Project::new(
+        "timings",
+        &[(
+            "Main.vp",
+            "use Prelude\n\nlet main (x : ()) : () = print \"hi\"\n",
+        )],
+    );
+
+    let build = project.vulpi(&["build", "--no-cache", "--timings", "json"]);
+    assert!(build.status.success());
+
+    let report: serde_json::Value = serde_json::from_str(&stdout(&build)).unwrap();
+    let entries = report["entries"].as_array().unwrap();
+
+    // Each module is parsed on its own, and the later phases work on the whole crate.
+    let parsed: Vec<_> = entries
+        .iter()
+        .filter(|x| x["phase"] == "parse")
+        .map(|x| x["module"].as_str().unwrap())
+        .collect();
+
+    assert!(parsed.contains(&"Timings.Main"));
+    assert!(parsed.contains(&"Prelude"));
+
+    for phase in ["typecheck", "lower", "optimize", "codegen"] {
+        let entry = entries.iter().find(|x| x["phase"] == phase).unwrap();
+        assert!(entry["module"].is_null());
+        assert!(entry["time_us"].is_u64());
+    }
+
+    let build = project.vulpi(&["build", "--no-cache", "--timings"]);
+    let table = stderr(&build);
+
+    assert!(table.contains("phase"));
+    assert!(table.contains("parse      Timings.Main"));
+}
+
+#[test]
+fn lints_are_allowed_warned_or_denied_by_the_flags() {
+    let project = Project::new(
+        "lints",
+        &[(
+            "Main.vp",
+            "use Prelude\n\nlet helper : Int = 1\n\nlet main (x : ()) : () = print \"hi\"\n",
+        )],
+    );
+
+    let build = project.vulpi(&["build", "--no-cache"]);
+    assert!(build.status.success());
+    assert!(!stderr(&build).contains("E0401"));
+
+    // A warning is shown but doesn't make the build fail unless warnings are denied.
+    let build = project.vulpi(&["build", "--no-cache", "-W", "unused"]);
+    let warned = plain(&stderr(&build));
+
+    assert!(build.status.success());
+    assert!(warned.contains("WARNING  [E0401] [unused] 'helper' is never used"));
+
+    let build = project.vulpi(&["build", "--no-cache", "-W", "unused", "--deny-warnings"]);
+    assert!(!build.status.success());
+    assert!(plain(&stderr(&build)).contains("ERROR  [E0401]"));
+
+    let build = project.vulpi(&["build", "--no-cache", "--warn-unused", "-A", "unused"]);
+    assert!(build.status.success());
+    assert_eq!(stderr(&build), "");
+
+    let build = project.vulpi(&["build", "-W", "unknown"]);
+    assert!(!build.status.success());
+    assert!(stderr(&build).contains("the lints are unused, one_shot, deprecated"));
+}
+
+#[test]
+fn diagnostics_show_the_lines_of_their_spans() {
+    let main = "use Prelude\n\nlet first (x : (Int, Int)) : Int =\n  when x is\n    (a, a) => a\n";
+    let project = Project::new("frames", &[("Main.vp", main)]);
+
+    let check = project.vulpi(&["check"]);
+    let shown = stderr(&check);
+
+    let lines = plain(&shown);
+
+    // The primary span is underlined with carets and the secondary one with its label.
+    let frame = "
+      ┌─> src/Main.vp:5:9
+      │
+    4 │   when x is
+    5 │     (a, a) => a
+      │      -  ^
+      │      first bound here
+";
+
+    assert!(!check.status.success());
+    assert!(shown.contains("\x1b["));
+    assert!(lines.contains("ERROR  [E0203] duplicate pattern: a"));
+    assert!(lines.contains(frame));
+}
+
+#[test]
+fn diagnostics_are_printed_as_json_objects() {
+    let main = "use Prelude\nuse Other\n\nlet main (x : ()) : () = print secret\n";
+    let other = "use Prelude\n\nlet secret : String = \"s\"\n";
+    let project = Project::new("json", &[("Main.vp", main), ("Other.vp", other)]);
+
+    let check = project.vulpi(&["check", "--message-format", "json"]);
+    assert!(!check.status.success());
+    assert_eq!(stderr(&check), "");
+
+    let printed = stdout(&check);
+    let diagnostics: Vec<serde_json::Value> = printed
+        .lines()
+        .map(|line| serde_json::from_str(line).unwrap())
+        .collect();
+
+    let [diagnostic] = diagnostics.as_slice() else {
+        panic!("expected one diagnostic, found {printed}");
+    };
+
+    assert_eq!(diagnostic["code"], "E0204");
+    assert_eq!(diagnostic["severity"], "error");
+    assert_eq!(diagnostic["message"], "private definition");
+
+    let span = &diagnostic["spans"][0];
+    assert_eq!(span["file"], "src/Main.vp");
+    assert_eq!(span["byte_start"], 54);
+    assert_eq!(span["byte_end"], 60);
+    assert_eq!(span["line_start"], 4);
+    assert_eq!(span["column_start"], 32);
+    assert_eq!(span["label"], "used here");
+
+    let child = &diagnostic["children"][0];
+    assert_eq!(child["severity"], "note");
+    assert_eq!(child["spans"][0]["file"], "src/Other.vp");
+
+    let fix = &diagnostic["fixes"][0];
+    assert_eq!(fix["replacement"], "pub ");
+    assert_eq!(fix["span"]["byte_start"], 13);
+}
+
+#[test]
+fn fix_applies_the_suggestions_of_the_diagnostics() {
+    let main = "use Prelude\nuse Other\n\nlet main (x : ()) : () = print secret\n";
+    let other = "use Prelude\n\nlet secret : String = \"s\"\n";
+    let project = Project::new("fix", &[("Main.vp", main), ("Other.vp", other)]);
+
+    assert!(!project.vulpi(&["check"]).status.success());
+
+    // The definition is made public where it's declared, and the module that uses it is kept.
+    let fix = project.vulpi(&["fix"]);
+    assert!(fix.status.success());
+    assert!(stderr(&fix).contains("[Fixed]: src/Other.vp with 1 fix"));
+
+    let source = |file| fs::read_to_string(project.directory.join("src").join(file)).unwrap();
+    assert_eq!(
+        source("Other.vp"),
+        "use Prelude\n\npub let secret : String = \"s\"\n"
+    );
+    assert_eq!(source("Main.vp"), main);
+
+    let run = project.vulpi(&["run"]);
+    assert!(run.status.success());
+    assert_eq!(stdout(&run), "s\n");
+
+    let fix = project.vulpi(&["fix"]);
+    assert!(stderr(&fix).contains("[Fixed]: there is nothing to fix"));
+}
+
+#[test]
+fn privacy_errors_point_at_the_use_and_at_the_declaration() {
+    let main = "use Prelude\nuse Other\n\nlet main (x : ()) : () = print secret\n";
+    let other = "use Prelude\n\nlet secret : String = \"s\"\n";
+    let project = Project::new("privacy", &[("Main.vp", main), ("Other.vp", other)]);
+
+    let check = project.vulpi(&["check"]);
+    let lines = plain(&stderr(&check));
+
+    let used = "
+      ┌─> src/Main.vp:4:32
+      │
+    3 │
+    4 │ let main (x : ()) : () = print secret
+      │                                ^^^^^^ used here
+";
+
+    let declared = "
+      ┌─> src/Other.vp:3:5
+      │
+    2 │
+    3 │ let secret : String = \"s\"
+      │ --- ------ declared private here
+      │ without `pub`
+";
+
+    assert!(!check.status.success());
+    assert!(lines.contains("ERROR  [E0204] private definition"));
+    assert!(lines.contains(used));
+    assert!(lines.contains(declared));
+}
+
+#[test]
+fn names_that_are_not_found_dont_cause_type_errors() {
+    let main =
+        "use Prelude\n\nlet main (x : ()) : () = print (concat missing (concat missing \"a\"))\n";
+    let project = Project::new("cascade", &[("Main.vp", main)]);
+
+    let check = project.vulpi(&["check"]);
+    let shown = plain(&stderr(&check));
+
+    // Each use of the name is reported, but the types of its uses are not checked.
+    assert!(!check.status.success());
+    assert_eq!(shown.matches("[E0200] cannot find 'missing'").count(), 2);
+    assert!(!shown.contains("E0302"));
+}
+
+#[test]
+fn error_limit_hides_the_other_errors_but_not_from_json() {
+    let values: String = (0..12)
+        .map(|i| format!("let value{i} : Int = \"{i}\"\n"))
+        .collect();
+
+    let main = format!("use Prelude\n\n{values}\nlet main (x : ()) : () = ()\n");
+    let project = Project::new("limit", &[("Main.vp", main.as_str())]);
+
+    let check = project.vulpi(&["check", "--error-limit", "5"]);
+    let shown = plain(&stderr(&check));
+
+    assert!(!check.status.success());
+    assert_eq!(shown.matches("[E0302]").count(), 5);
+    assert!(shown.contains("and 7 more errors"));
+
+    let check = project.vulpi(&["check", "--error-limit", "5", "--message-format", "json"]);
+    assert_eq!(stdout(&check).lines().count(), 12);
+
+    let check = project.vulpi(&["check"]);
+    let shown = plain(&stderr(&check));
+
+    assert_eq!(shown.matches("[E0302]").count(), 12);
+    assert!(!shown.contains("more error"));
+}
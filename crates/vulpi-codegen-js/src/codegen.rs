@@ -0,0 +1,1097 @@
+//! Printer of JavaScript from the core language. Let declarations with parameters become function
+//! declarations and the ones without parameters become constants, named by mangling their
+//! [Qualified] names. Constructors become objects with a `tag` field and their fields at numeric
+//! keys, except for types where no constructor has fields, whose values are just the tags.
+//!
+//! Externals whose binding is a [Primitive] are printed as JavaScript operators, or as calls to
+//! small helpers for the ones that can fail, so programs don't need a prelude with their
+//! bindings. Integers are JavaScript numbers, so they're exact only up to 2^53 and they don't
+//! wrap around like in the other backends. The checked arithmetic fails and the saturating one
+//! stops when the result is not exact anymore. Strings are encoded to UTF-8 by the helpers that
+//! use the offsets of their bytes, and arrays are JavaScript arrays. The rest of the externals
+//! are spliced as they are written and called in curried style, like the bindings of the prelude
+//! expect. Join points become labeled blocks that are left with `break`, and the ones that jump
+//! to themselves are followed by a labeled `for` loop that is repeated with `continue`.
+//!
+//! JavaScript engines do not eliminate tail calls, so tail calls between functions that can call
+//! each other back are trampolined: the function returns a `$Tail` with the next call and a
+//! wrapper with the original name runs the calls until a value comes back. Self tail calls are
+//! already loops in the core language.
+//!
+//! The lines with calls and the headers of the declarations are mapped to the spans that they come
+//! from, so a [Script] can be described by a source map.
+
+use std::collections::{BTreeSet, HashMap, HashSet};
+
+use vulpi_intern::Symbol;
+use vulpi_location::Span;
+use vulpi_report::{Diagnostic, Report};
+use vulpi_syntax::{elaborated::LiteralKind, r#abstract::Qualified};
+
+use vulpi_core::{
+    primitive::{Number, Operand, Primitive},
+    syntax::{
+        visit_term, Atom, Binder, Case, ExternalDecl, LetDecl, Program, TermKind, Type, Value,
+    },
+};
+
+use crate::errors::{JsError, JsErrorKind};
+
+#[rustfmt::skip]
+const RESERVED: &[&str] = &[
+    "arguments", "await", "break", "case", "catch", "class", "const", "continue", "debugger",
+    "default", "delete", "do", "else", "enum", "eval", "export", "extends", "false", "finally",
+    "for", "function", "if", "implements", "import", "in", "instanceof", "interface", "let", "new",
+    "null", "package", "private", "protected", "public", "return", "static", "super", "switch",
+    "this", "throw", "true", "try", "typeof", "undefined", "var", "void", "while", "with", "yield",
+];
+
+const APPLY: &str = "const $apply = (f, args) => {
+  for (;;) {
+    const arity = f.length || 1;
+    if (args.length === arity) return f(...args);
+    if (args.length < arity) {
+      const partial = (...rest) => $apply(f, args.concat(rest));
+      return Object.defineProperty(partial, \"length\", { value: arity - args.length });
+    }
+    f = f(...args.slice(0, arity));
+    args = args.slice(arity);
+  }
+};
+";
+
+const TAIL: &str = "class $Tail {
+  constructor(f, args) {
+    this.f = f;
+    this.args = args;
+  }
+}
+
+const $run = (result) => {
+  while (result instanceof $Tail) result = result.f(...result.args);
+  return result;
+};
+";
+
+/// The helpers of the primitives that cannot be written as a single operator. The comparisons of
+/// values whose type is not known compare them by structure.
+fn helper(primitive: Primitive) -> &'static str {
+    match primitive {
+        Primitive::Div => {
+            "const $div = (x, y) => {
+  if (y === 0) throw new Error(\"division by zero\");
+  return Math.trunc(x / y);
+};
+"
+        }
+        Primitive::Rem => {
+            "const $rem = (x, y) => {
+  if (y === 0) throw new Error(\"division by zero\");
+  return x % y;
+};
+"
+        }
+        Primitive::CheckedAdd => {
+            "const $checked = (x) => {
+  if (!Number.isSafeInteger(x)) throw new Error(\"integer overflow\");
+  return x;
+};
+"
+        }
+        Primitive::SaturatingAdd => {
+            "const $saturate = (x) =>
+  Math.min(Math.max(x, Number.MIN_SAFE_INTEGER), Number.MAX_SAFE_INTEGER);
+"
+        }
+        Primitive::Convert(_) => {
+            "const $toInt = (x, min, max) => {
+  if (Number.isNaN(x)) return 0;
+  return Math.min(Math.max(Math.trunc(x), min), max);
+};
+"
+        }
+        Primitive::Index => {
+            "const $index = (s, i) => {
+  const c = [...s][i];
+  if (c === undefined) throw new Error(`the index ${i} is outside of the string`);
+  return c;
+};
+"
+        }
+        Primitive::ByteLength => {
+            "const $bytes = (s) => new TextEncoder().encode(s);
+
+const $boundary = (bytes, i) => {
+  if (i < 0 || i > bytes.length) throw new Error(`the index ${i} is outside of the string`);
+  if ((bytes[i] & 0xc0) === 0x80) throw new Error(`the offset ${i} is inside of a character`);
+};
+"
+        }
+        Primitive::Slice => {
+            "const $slice = (s, start, end) => {
+  const bytes = $bytes(s);
+  $boundary(bytes, start);
+  $boundary(bytes, end);
+  if (end < start) throw new Error(`the index ${end} is outside of the string`);
+  return new TextDecoder().decode(bytes.subarray(start, end));
+};
+"
+        }
+        Primitive::CharAt => {
+            "const $charAt = (s, i) => {
+  const bytes = $bytes(s);
+  $boundary(bytes, i);
+  if (i === bytes.length) throw new Error(`the index ${i} is outside of the string`);
+  return [...new TextDecoder().decode(bytes.subarray(i, i + 4))][0];
+};
+"
+        }
+        Primitive::CharWidth => {
+            "const $width = (c) => {
+  const x = c.codePointAt(0);
+  return x < 0x80 ? 1 : x < 0x800 ? 2 : x < 0x10000 ? 3 : 4;
+};
+"
+        }
+        Primitive::IntToChar => {
+            "const $chr = (x) => {
+  if (x < 0 || x > 0x10ffff || (x >= 0xd800 && x <= 0xdfff))
+    throw new Error(`${x} is not the code point of a character`);
+  return String.fromCodePoint(x);
+};
+"
+        }
+        Primitive::ArrayMake => {
+            "const $make = (n, x) => {
+  if (n < 0) throw new Error(`the length ${n} of an array is negative`);
+  return new Array(n).fill(x);
+};
+"
+        }
+        Primitive::ArrayGet => {
+            "const $element = (a, i) => {
+  if (!(i >= 0 && i < a.length))
+    throw new Error(`the index ${i} is outside of an array of length ${a.length}`);
+  return i;
+};
+"
+        }
+        _ => {
+            "const $equal = (x, y) => {
+  if (x === y) return true;
+  if (typeof x !== \"object\" || typeof y !== \"object\" || x === null || y === null) return false;
+  const keys = Object.keys(x);
+  if (keys.length !== Object.keys(y).length) return false;
+  return keys.every((key) => $equal(x[key], y[key]));
+};
+"
+        }
+    }
+}
+
+fn bool(name: &str) -> Qualified {
+    Qualified {
+        path: Symbol::intern("Prelude.Bool"),
+        name: Symbol::intern(name),
+    }
+}
+
+fn identifier(name: &str) -> String {
+    let name: String = name
+        .chars()
+        .map(|c| {
+            if c.is_alphanumeric() || c == '_' || c == '$' {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect();
+
+    if RESERVED.contains(&name.as_str()) {
+        format!("{}$", name)
+    } else {
+        name
+    }
+}
+
+fn local(name: &Symbol) -> String {
+    identifier(&name.get())
+}
+
+fn global(name: &Qualified) -> String {
+    identifier(&name.mangle())
+}
+
+fn string(value: &str) -> String {
+    let mut result = String::from("\"");
+
+    for c in value.chars() {
+        match c {
+            '"' => result.push_str("\\\""),
+            '\\' => result.push_str("\\\\"),
+            '\n' => result.push_str("\\n"),
+            '\r' => result.push_str("\\r"),
+            '\t' => result.push_str("\\t"),
+            c if c.is_control() => result.push_str(&format!("\\u{:04x}", c as u32)),
+            c => result.push(c),
+        }
+    }
+
+    result.push('"');
+    result
+}
+
+fn literal(literal: &LiteralKind) -> String {
+    match literal {
+        LiteralKind::String(x) => string(&x.get()),
+        LiteralKind::Integer(x) | LiteralKind::Float(x) => x.get().replace('_', ""),
+        LiteralKind::Char(x) => string(&x.get()),
+        LiteralKind::Unit => "0".to_string(),
+    }
+}
+
+/// The condition of a range. Characters are strings, so they're compared by their code points.
+fn within(scrutinee: &str, start: &LiteralKind, end: &LiteralKind) -> String {
+    let (value, start, end) = match start {
+        LiteralKind::Char(_) => (
+            format!("{}.codePointAt(0)", scrutinee),
+            start.ordinal().unwrap_or_default().to_string(),
+            end.ordinal().unwrap_or_default().to_string(),
+        ),
+        _ => (scrutinee.to_string(), literal(start), literal(end)),
+    };
+
+    format!("{} <= {} && {} <= {}", start, value, value, end)
+}
+
+#[derive(Clone, Copy)]
+struct Constructor {
+    tag: usize,
+    siblings: usize,
+
+    /// The type has no constructor with fields, so the value is just the tag.
+    enumeration: bool,
+}
+
+/// A position of the script, with the line and the column starting at zero, that comes from the
+/// span.
+#[derive(Clone, Debug)]
+pub struct Mapping {
+    pub line: usize,
+    pub column: usize,
+    pub span: Span,
+}
+
+/// A script with the positions of the sources that its lines come from, in the order of the
+/// lines.
+pub struct Script {
+    pub code: String,
+    pub mappings: Vec<Mapping>,
+}
+
+/// A let declaration that is trampolined has its body in a function with this suffix.
+const STEP: &str = "$step";
+
+struct Generator<'a> {
+    reporter: Report,
+    lets: HashMap<Qualified, &'a LetDecl>,
+    externals: HashMap<Qualified, &'a ExternalDecl>,
+    constructors: HashMap<Qualified, Constructor>,
+
+    /// The let declarations that can be reached from each let declaration by calls.
+    reaches: HashMap<Qualified, HashSet<Qualified>>,
+
+    /// The let declarations that have trampolined tail calls.
+    trampolined: HashSet<Qualified>,
+
+    out: String,
+    indent: usize,
+    lines: usize,
+
+    /// The span of the next line, if it comes from one.
+    mark: Option<Span>,
+    mappings: Vec<Mapping>,
+
+    /// The top level function whose body is being printed. It's none inside of lambdas.
+    function: Option<Qualified>,
+    joins: HashMap<Symbol, Vec<String>>,
+
+    /// The join points whose loop contains the term that is being printed.
+    loops: HashSet<Symbol>,
+    arities: HashMap<Symbol, usize>,
+
+    uses_apply: bool,
+    uses_tail: bool,
+
+    /// The primitives whose helpers are used, in the order that they're printed.
+    helpers: BTreeSet<Primitive>,
+    span: Span,
+}
+
+impl<'a> Generator<'a> {
+    fn error(&mut self, kind: JsErrorKind) {
+        self.reporter.report(Diagnostic::new(JsError {
+            span: self.span.clone(),
+            kind,
+        }));
+    }
+
+    fn line(&mut self, text: &str) {
+        if let Some(span) = self.mark.take() {
+            self.mappings.push(Mapping {
+                line: self.lines,
+                column: self.indent * 2,
+                span,
+            });
+        }
+
+        self.lines += 1;
+
+        for _ in 0..self.indent {
+            self.out.push_str("  ");
+        }
+
+        self.out.push_str(text);
+        self.out.push('\n');
+    }
+
+    fn indented(&mut self, f: impl FnOnce(&mut Self)) {
+        self.indent += 1;
+        f(self);
+        self.indent -= 1;
+    }
+
+    fn constructor(&self, name: &Qualified) -> Constructor {
+        self.constructors.get(name).copied().unwrap_or(Constructor {
+            tag: 0,
+            siblings: 1,
+            enumeration: false,
+        })
+    }
+
+    fn arity(&self, name: &Qualified) -> Option<usize> {
+        self.lets
+            .get(name)
+            .map(|x| x.params.len())
+            .filter(|x| *x > 0)
+    }
+
+    fn atom(&mut self, atom: &Atom) -> String {
+        match atom {
+            Atom::Variable(name) => local(name),
+            Atom::Function(name, types) => match self.externals.get(name).copied() {
+                Some(external) if external.is_foreign() => {
+                    self.error(JsErrorKind::Unsupported("C functions"));
+                    "undefined".to_string()
+                }
+                Some(external) => match Primitive::from_binding(&external.binding.get()) {
+                    Some(primitive) => {
+                        let params: Vec<_> =
+                            (0..primitive.arity()).map(|i| format!("${}", i)).collect();
+                        let body = self.primitive(external, primitive, types, &params);
+                        let params: String =
+                            params.iter().map(|x| format!("({}) => ", x)).collect();
+                        format!("({}{})", params, body)
+                    }
+                    None => external.binding.get(),
+                },
+                None => global(name),
+            },
+            Atom::Literal(lit) => literal(lit),
+        }
+    }
+
+    fn atoms(&mut self, atoms: &[Atom]) -> Vec<String> {
+        atoms.iter().map(|x| self.atom(x)).collect()
+    }
+
+    fn apply(&mut self, function: String, args: &[String]) -> String {
+        if args.is_empty() {
+            return function;
+        }
+
+        self.uses_apply = true;
+        format!("$apply({}, [{}])", function, args.join(", "))
+    }
+
+    fn application(&mut self, function: &Atom, args: &[Atom]) -> String {
+        let args = self.atoms(args);
+        let callee = self.atom(function);
+
+        let arity = match function {
+            Atom::Function(name, types) if self.externals.contains_key(name) => {
+                let external = self.externals[name];
+                let mut callee = callee;
+                let mut args = args.as_slice();
+
+                if let Some(primitive) = Primitive::from_binding(&external.binding.get()) {
+                    if args.len() >= primitive.arity() {
+                        let (now, rest) = args.split_at(primitive.arity());
+                        callee = self.primitive(external, primitive, types, now);
+                        args = rest;
+                    }
+                }
+
+                let calls: String = args.iter().map(|x| format!("({})", x)).collect();
+                return format!("{}{}", callee, calls);
+            }
+            Atom::Function(name, _) => self.arity(name),
+            Atom::Variable(name) => self.arities.get(name).copied(),
+            Atom::Literal(_) => None,
+        };
+
+        match arity {
+            Some(arity) if args.len() >= arity => {
+                let call = format!("{}({})", callee, args[..arity].join(", "));
+                self.apply(call, &args[arity..])
+            }
+            _ => self.apply(callee, &args),
+        }
+    }
+
+    /// The value of a boolean condition, as a constructor of `Prelude.Bool`.
+    fn boolean(&self, condition: String) -> String {
+        let tag = |name| self.constructors.get(&bool(name)).map(|x| x.tag);
+        let yes = tag("True").unwrap_or(1);
+        let no = tag("False").unwrap_or(0);
+        format!("({} ? {} : {})", condition, yes, no)
+    }
+
+    /// Prints a primitive applied to all of its arguments. The operands are chosen by the types
+    /// that the external is instantiated to.
+    fn primitive(
+        &mut self,
+        external: &ExternalDecl,
+        primitive: Primitive,
+        types: &[Type],
+        args: &[String],
+    ) -> String {
+        let (params, _) = external.typ.instantiate(types).arrow_spine();
+        let operand = params
+            .first()
+            .map(|x| Operand::of(x))
+            .unwrap_or(Operand::Unit);
+
+        let operator = match primitive {
+            Primitive::Add | Primitive::CheckedAdd | Primitive::SaturatingAdd => "+",
+            Primitive::Sub | Primitive::CheckedSub | Primitive::SaturatingSub => "-",
+            Primitive::Mul | Primitive::CheckedMul | Primitive::SaturatingMul => "*",
+            Primitive::Concat => "+",
+            Primitive::Div => "/",
+            Primitive::Rem => "%",
+            Primitive::Eq => "===",
+            Primitive::Neq => "!==",
+            Primitive::Lt => "<",
+            Primitive::Gt => ">",
+            Primitive::Le => "<=",
+            _ => ">=",
+        };
+
+        match primitive {
+            Primitive::Div | Primitive::Rem if operand != Operand::Float => {
+                self.helpers.insert(primitive);
+                let name = if primitive == Primitive::Div {
+                    "$div"
+                } else {
+                    "$rem"
+                };
+                format!("{}({}, {})", name, args[0], args[1])
+            }
+            Primitive::CheckedAdd | Primitive::CheckedSub | Primitive::CheckedMul => {
+                self.helpers.insert(Primitive::CheckedAdd);
+                format!("$checked({} {} {})", args[0], operator, args[1])
+            }
+            Primitive::SaturatingAdd | Primitive::SaturatingSub | Primitive::SaturatingMul => {
+                self.helpers.insert(Primitive::SaturatingAdd);
+                format!("$saturate({} {} {})", args[0], operator, args[1])
+            }
+            Primitive::Eq | Primitive::Neq if operand == Operand::Other => {
+                self.helpers.insert(Primitive::Eq);
+                let not = if primitive == Primitive::Neq { "!" } else { "" };
+                self.boolean(format!("{}$equal({}, {})", not, args[0], args[1]))
+            }
+            _ if primitive.is_comparison() => {
+                self.boolean(format!("{} {} {}", args[0], operator, args[1]))
+            }
+            Primitive::Length => format!("[...{}].length", args[0]),
+            Primitive::Index => {
+                self.helpers.insert(primitive);
+                format!("$index({}, {})", args[0], args[1])
+            }
+            Primitive::ByteLength => {
+                self.helpers.insert(primitive);
+                format!("$bytes({}).length", args[0])
+            }
+            Primitive::Slice => {
+                self.helpers.extend([Primitive::ByteLength, primitive]);
+                format!("$slice({}, {}, {})", args[0], args[1], args[2])
+            }
+            Primitive::CharAt => {
+                self.helpers.extend([Primitive::ByteLength, primitive]);
+                format!("$charAt({}, {})", args[0], args[1])
+            }
+            Primitive::CharWidth => {
+                self.helpers.insert(primitive);
+                format!("$width({})", args[0])
+            }
+            Primitive::Convert(number) => self.convert(operand, number, &args[0]),
+            Primitive::CharToInt => format!("{}.codePointAt(0)", args[0]),
+            Primitive::IntToChar => {
+                self.helpers.insert(primitive);
+                format!("$chr({})", args[0])
+            }
+            Primitive::Identity => args[0].clone(),
+            Primitive::Show => match operand {
+                Operand::Char => args[0].clone(),
+                _ => format!("String({})", args[0]),
+            },
+            Primitive::RefNew => format!("{{ value: {} }}", args[0]),
+            Primitive::RefGet => format!("{}.value", args[0]),
+            Primitive::RefSet => format!("({}.value = {}, 0)", args[0], args[1]),
+            Primitive::ArrayEmpty => "[]".to_string(),
+            Primitive::ArrayMake => {
+                self.helpers.insert(primitive);
+                format!("$make({}, {})", args[0], args[1])
+            }
+            Primitive::ArrayGet => {
+                self.helpers.insert(primitive);
+                format!("{}[$element({}, {})]", args[0], args[0], args[1])
+            }
+            Primitive::ArraySet => {
+                self.helpers.insert(Primitive::ArrayGet);
+                let element = format!("{}[$element({}, {})]", args[0], args[0], args[1]);
+                format!("({} = {}, 0)", element, args[2])
+            }
+            Primitive::ArrayLength => format!("{}.length", args[0]),
+            Primitive::ArrayPush => format!("({}.push({}), 0)", args[0], args[1]),
+            Primitive::Print => format!("console.log({})", args[0]),
+            Primitive::PrintError => format!("console.error({})", args[0]),
+            _ => format!("({} {} {})", args[0], operator, args[1]),
+        }
+    }
+
+    /// Converts a number to a numeric type. The integers that are smaller than 32 bits keep their
+    /// lower bits with the bitwise operators, and floats become integers by saturating.
+    fn convert(&mut self, operand: Operand, number: Number, arg: &str) -> String {
+        match number {
+            Number::Int { .. } if operand == Operand::Float => {
+                // Integers of 64 bits are kept in doubles, so they stop at the safe integers.
+                let safe = (1i64 << 53) - 1;
+                let (min, max) = number.range().unwrap();
+                let (min, max) = (min.max(-safe), max.min(safe));
+                self.helpers.insert(Primitive::Convert(Number::INT));
+                format!("$toInt({}, {}, {})", arg, min, max)
+            }
+            Number::Int { bits: 64, .. } | Number::Float { bits: 64 } => arg.to_string(),
+            Number::Int { bits: 32, signed } => {
+                format!("({} {})", arg, if signed { "| 0" } else { ">>> 0" })
+            }
+            Number::Int { bits, signed: true } => {
+                format!("({} << {} >> {})", arg, 32 - bits, 32 - bits)
+            }
+            Number::Int { bits, .. } => format!("({} & {})", arg, (1u32 << bits) - 1),
+            Number::Float { .. } => format!("Math.fround({})", arg),
+        }
+    }
+
+    fn value(&mut self, value: &'a Value) -> String {
+        match value {
+            Value::Atom(atom) => self.atom(atom),
+            Value::Lambda(..) => unreachable!("lambdas are printed as statements"),
+            Value::Application(function, args, _) => self.application(function, args),
+            Value::Constructor(name, args) => {
+                let args = self.atoms(args);
+
+                match self.constructors.get(name).copied() {
+                    None => format!("[{}]", args.join(", ")),
+                    Some(constructor) if constructor.enumeration => {
+                        format!("{} /* {} */", constructor.tag, name.name.get())
+                    }
+                    Some(constructor) => {
+                        let mut fields = vec![format!("tag: {}", constructor.tag)];
+                        fields.extend(
+                            args.iter()
+                                .enumerate()
+                                .map(|(i, x)| format!("{}: {}", i, x)),
+                        );
+                        format!("{{ {} }}", fields.join(", "))
+                    }
+                }
+            }
+            Value::Field(_, atom, index) => format!("{}[{}]", self.atom(atom), index),
+            Value::Perform(..) | Value::Handle(..) | Value::Mask(..) => {
+                unreachable!("the effects are elaborated by vulpi_core::effects")
+            }
+        }
+    }
+
+    /// Finds a call that is in tail position and goes to a function that can call the current one
+    /// back. These calls are the ones that would make the stack grow without a bound.
+    fn tail_call(&self, function: &Atom, args: &[Atom]) -> Option<Qualified> {
+        let current = self.function.as_ref()?;
+
+        let Atom::Function(name, _) = function else {
+            return None;
+        };
+
+        if self.arity(name) != Some(args.len()) {
+            return None;
+        }
+
+        let reaches = self.reaches.get(name)?;
+
+        if reaches.contains(current) {
+            Some(name.clone())
+        } else {
+            None
+        }
+    }
+
+    fn lambda(&mut self, name: &Symbol, params: &[Binder], body: &'a TermKind) {
+        let params: Vec<_> = params.iter().map(|x| local(&x.name)).collect();
+        self.line(&format!(
+            "const {} = ({}) => {{",
+            local(name),
+            params.join(", ")
+        ));
+
+        let function = self.function.take();
+        let joins = std::mem::take(&mut self.joins);
+
+        self.indented(|ctx| ctx.term(body));
+
+        self.function = function;
+        self.joins = joins;
+
+        self.line("};");
+    }
+
+    fn term(&mut self, term: &'a TermKind) {
+        match term {
+            TermKind::Let(binder, Value::Lambda(params, body), rest) => {
+                self.arities.insert(binder.name.clone(), params.len());
+                self.lambda(&binder.name, params, body);
+                self.term(rest);
+            }
+            TermKind::Let(binder, value, rest) => {
+                if let Value::Application(.., span) | Value::Perform(.., span) = value {
+                    self.mark = Some(span.clone());
+                }
+
+                let value = self.value(value);
+                self.line(&format!("const {} = {};", local(&binder.name), value));
+                self.term(rest);
+            }
+            TermKind::Join(label, params, body, rest) => {
+                let params: Vec<_> = params.iter().map(|x| local(&x.name)).collect();
+
+                if !params.is_empty() {
+                    self.line(&format!("let {};", params.join(", ")));
+                }
+
+                self.joins.insert(label.clone(), params);
+
+                self.line(&format!("{}: {{", local(label)));
+                self.indented(|ctx| ctx.term(rest));
+                self.line("}");
+
+                if jumps_to(label, body) {
+                    self.line(&format!("{}: for (;;) {{", local(label)));
+                    self.loops.insert(label.clone());
+                    self.indented(|ctx| ctx.term(body));
+                    self.loops.remove(label);
+                    self.line("}");
+                } else {
+                    self.term(body);
+                }
+            }
+            TermKind::Jump(label, args) => {
+                let args = self.atoms(args);
+                let params = self.joins.get(label).cloned().unwrap_or_default();
+
+                // The arguments of a loop can use the parameters, so they're assigned all at once
+                // if one of them would be read after it's changed.
+                let overwritten = args
+                    .iter()
+                    .enumerate()
+                    .any(|(i, arg)| params.iter().take(i).any(|x| x == arg));
+
+                if overwritten {
+                    self.line(&format!("[{}] = [{}];", params.join(", "), args.join(", ")));
+                } else {
+                    for (param, arg) in params.iter().zip(args) {
+                        if *param != arg {
+                            self.line(&format!("{} = {};", param, arg));
+                        }
+                    }
+                }
+
+                if self.loops.contains(label) {
+                    self.line(&format!("continue {};", local(label)));
+                } else {
+                    self.line(&format!("break {};", local(label)));
+                }
+            }
+            TermKind::Tail(function, args, span) => match self.tail_call(function, args) {
+                Some(target) => {
+                    let args = self.atoms(args);
+
+                    let function = if self.trampolined.contains(&target) {
+                        format!("{}{}", global(&target), STEP)
+                    } else {
+                        global(&target)
+                    };
+
+                    self.uses_tail = true;
+                    self.mark = Some(span.clone());
+                    self.line(&format!(
+                        "return new $Tail({}, [{}]);",
+                        function,
+                        args.join(", ")
+                    ));
+                }
+                None => {
+                    let call = self.application(function, args);
+                    self.mark = Some(span.clone());
+                    self.line(&format!("return {};", call));
+                }
+            },
+            TermKind::Match(atom, alts, default) => {
+                let scrutinee = self.atom(atom);
+
+                // Types with only one constructor need no test.
+                if let [alt] = alts.as_slice() {
+                    if let Case::Constructor(name) = &alt.case {
+                        if self.constructor(name).siblings == 1 {
+                            self.alt(&scrutinee, &alt.binders, &alt.body);
+                            return;
+                        }
+                    }
+                }
+
+                // Ranges are conditions, so the cases of a match with them are tested in order
+                // by switching on true.
+                let ranges = alts.iter().any(|x| matches!(x.case, Case::Range(_, _)));
+
+                let discriminant = match alts.first().map(|x| &x.case) {
+                    _ if ranges => "true".to_string(),
+                    Some(Case::Constructor(name)) if !self.constructor(name).enumeration => {
+                        format!("{}.tag", scrutinee)
+                    }
+                    _ => scrutinee.clone(),
+                };
+
+                self.line(&format!("switch ({}) {{", discriminant));
+
+                self.indented(|ctx| {
+                    for alt in alts {
+                        let case = match &alt.case {
+                            Case::Constructor(name) => {
+                                format!("{} /* {} */", ctx.constructor(name).tag, name.name.get())
+                            }
+                            Case::Literal(lit) if ranges => {
+                                format!("{} === {}", scrutinee, literal(lit))
+                            }
+                            Case::Literal(lit) => literal(lit),
+                            Case::Range(start, end) => within(&scrutinee, start, end),
+                        };
+
+                        ctx.line(&format!("case {}: {{", case));
+                        ctx.indented(|ctx| ctx.alt(&scrutinee, &alt.binders, &alt.body));
+                        ctx.line("}");
+                    }
+
+                    if let Some(default) = default {
+                        ctx.line("default: {");
+                        ctx.indented(|ctx| ctx.term(default));
+                        ctx.line("}");
+                    }
+                });
+
+                self.line("}");
+            }
+            TermKind::Return(atom) => {
+                let atom = self.atom(atom);
+                self.line(&format!("return {};", atom));
+            }
+            TermKind::Unreachable => self.line("throw new Error(\"unreachable\");"),
+        }
+    }
+
+    fn alt(&mut self, scrutinee: &str, binders: &[Binder], body: &'a TermKind) {
+        for (i, binder) in binders.iter().enumerate() {
+            self.line(&format!(
+                "const {} = {}[{}];",
+                local(&binder.name),
+                scrutinee,
+                i
+            ));
+        }
+
+        self.term(body);
+    }
+
+    fn let_decl(&mut self, decl: &'a LetDecl) {
+        self.span = decl.span.clone();
+        self.mark = Some(decl.span.clone());
+        self.joins.clear();
+        self.arities.clear();
+
+        let name = global(&decl.name);
+
+        if decl.params.is_empty() {
+            if let TermKind::Return(atom) = &*decl.body {
+                let atom = self.atom(atom);
+                self.line(&format!("const {} = {};", name, atom));
+            } else {
+                self.function = None;
+                self.line(&format!("const {} = (() => {{", name));
+                self.indented(|ctx| ctx.term(&decl.body));
+                self.line("})();");
+            }
+
+            self.line("");
+            return;
+        }
+
+        let params: Vec<_> = decl.params.iter().map(|x| local(&x.name)).collect();
+        let params = params.join(", ");
+
+        self.function = Some(decl.name.clone());
+
+        if self.trampolined.contains(&decl.name) {
+            self.line(&format!("function {}{}({}) {{", name, STEP, params));
+            self.indented(|ctx| ctx.term(&decl.body));
+            self.line("}");
+            self.line("");
+
+            self.line(&format!("function {}({}) {{", name, params));
+            self.indented(|ctx| ctx.line(&format!("return $run({}{}({}));", name, STEP, params)));
+            self.line("}");
+        } else {
+            self.line(&format!("function {}({}) {{", name, params));
+            self.indented(|ctx| ctx.term(&decl.body));
+            self.line("}");
+        }
+
+        self.line("");
+        self.function = None;
+    }
+
+    /// Finds the let declarations that have trampolined tail calls by looking at the tail
+    /// positions of their bodies.
+    fn find_trampolined(&mut self, program: &'a Program) {
+        fn tails(term: &TermKind, f: &mut dyn FnMut(&Atom, &[Atom])) {
+            match term {
+                TermKind::Tail(function, args, _) => f(function, args),
+                TermKind::Let(_, _, rest) => tails(rest, f),
+                TermKind::Join(_, _, body, rest) => {
+                    tails(body, f);
+                    tails(rest, f);
+                }
+                TermKind::Match(_, alts, default) => {
+                    for alt in alts {
+                        tails(&alt.body, f);
+                    }
+
+                    if let Some(default) = default {
+                        tails(default, f);
+                    }
+                }
+                _ => (),
+            }
+        }
+
+        for decl in program.lets.iter().filter(|x| !x.params.is_empty()) {
+            self.function = Some(decl.name.clone());
+
+            let mut found = false;
+            tails(&decl.body, &mut |function, args| {
+                found |= self.tail_call(function, args).is_some();
+            });
+
+            if found {
+                self.trampolined.insert(decl.name.clone());
+            }
+        }
+
+        self.function = None;
+    }
+}
+
+/// Checks if the term jumps to the join point, without looking inside of lambdas.
+fn jumps_to(label: &Symbol, term: &TermKind) -> bool {
+    match term {
+        TermKind::Jump(target, _) => target == label,
+        TermKind::Let(_, _, rest) => jumps_to(label, rest),
+        TermKind::Join(_, _, body, rest) => jumps_to(label, body) || jumps_to(label, rest),
+        TermKind::Match(_, alts, default) => {
+            alts.iter().any(|alt| jumps_to(label, &alt.body))
+                || default.as_ref().is_some_and(|x| jumps_to(label, x))
+        }
+        _ => false,
+    }
+}
+
+/// The let declarations that each declaration uses directly.
+fn references(program: &Program) -> HashMap<Qualified, Vec<Qualified>> {
+    let lets: HashSet<_> = program.lets.iter().map(|x| x.name.clone()).collect();
+    let mut references = HashMap::new();
+
+    for decl in &program.lets {
+        let mut used = Vec::new();
+
+        visit_term(&decl.body, &mut |atom| {
+            if let Atom::Function(name, _) = atom {
+                if lets.contains(name) && !used.contains(name) {
+                    used.push(name.clone());
+                }
+            }
+        });
+
+        references.insert(decl.name.clone(), used);
+    }
+
+    references
+}
+
+fn reaches(
+    references: &HashMap<Qualified, Vec<Qualified>>,
+) -> HashMap<Qualified, HashSet<Qualified>> {
+    let mut reaches = HashMap::new();
+
+    for name in references.keys() {
+        let mut visited = HashSet::new();
+        let mut stack: Vec<_> = references[name].clone();
+
+        while let Some(current) = stack.pop() {
+            if visited.insert(current.clone()) {
+                stack.extend(references.get(&current).into_iter().flatten().cloned());
+            }
+        }
+
+        reaches.insert(name.clone(), visited);
+    }
+
+    reaches
+}
+
+/// Prints the program as a JavaScript script. The text of `#javascript` commands is put before
+/// the declarations and the entry point is called at the end if it's a function. Returns nothing
+/// if some part of the program cannot be compiled.
+pub fn generate(reporter: Report, program: &Program, entry: Option<&Qualified>) -> Option<String> {
+    script(reporter, program, entry).map(|script| script.code)
+}
+
+/// Prints the program like [generate], keeping the spans that the lines come from.
+pub fn script(reporter: Report, program: &Program, entry: Option<&Qualified>) -> Option<Script> {
+    let references = references(program);
+
+    let mut ctx = Generator {
+        reporter: reporter.clone(),
+        lets: program.lets.iter().map(|x| (x.name.clone(), x)).collect(),
+        externals: program
+            .externals
+            .iter()
+            .map(|x| (x.name.clone(), x))
+            .collect(),
+        constructors: HashMap::new(),
+        reaches: reaches(&references),
+        trampolined: HashSet::new(),
+        out: String::new(),
+        indent: 0,
+        lines: 0,
+        mark: None,
+        mappings: Vec::new(),
+        function: None,
+        joins: HashMap::new(),
+        loops: HashSet::new(),
+        arities: HashMap::new(),
+        uses_apply: false,
+        uses_tail: false,
+        helpers: BTreeSet::new(),
+        span: Span::default(),
+    };
+
+    for typ in &program.types {
+        let enumeration = typ.constructors.iter().all(|(_, arity)| *arity == 0);
+
+        for (tag, (name, _)) in typ.constructors.iter().enumerate() {
+            let constructor = Constructor {
+                tag,
+                siblings: typ.constructors.len(),
+                enumeration,
+            };
+
+            ctx.constructors.insert(name.clone(), constructor);
+        }
+    }
+
+    ctx.find_trampolined(program);
+
+    // The values are computed in the order of the declarations, so it's the one of their
+    // initialization.
+    for decl in &program.lets {
+        ctx.let_decl(decl);
+    }
+
+    if let Some(decl) = entry.and_then(|x| ctx.lets.get(x).copied()) {
+        let name = global(&decl.name);
+
+        if !decl.params.is_empty() {
+            ctx.line(&format!("{}(0);", name));
+        } else if !decl.typ.arrow_spine().0.is_empty() {
+            let call = ctx.apply(name, &["0".to_string()]);
+            ctx.line(&format!("{};", call));
+        }
+    }
+
+    if reporter.has_errors() {
+        return None;
+    }
+
+    let mut out = String::new();
+
+    if ctx.uses_apply {
+        out.push_str(APPLY);
+        out.push('\n');
+    }
+
+    if ctx.uses_tail {
+        out.push_str(TAIL);
+        out.push('\n');
+    }
+
+    for primitive in &ctx.helpers {
+        out.push_str(helper(*primitive));
+        out.push('\n');
+    }
+
+    for (text, command) in &program.commands {
+        if command.get() == "javascript" {
+            out.push_str(text.get().trim());
+            out.push_str("\n\n");
+        }
+    }
+
+    // The declarations are printed after the helpers and the commands.
+    let offset = out.matches('\n').count();
+
+    let mappings = ctx
+        .mappings
+        .into_iter()
+        .map(|mapping| Mapping {
+            line: mapping.line + offset,
+            ..mapping
+        })
+        .collect();
+
+    out.push_str(ctx.out.trim_end());
+    out.push('\n');
+
+    Some(Script {
+        code: out,
+        mappings,
+    })
+}
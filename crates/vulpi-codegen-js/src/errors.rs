@@ -0,0 +1,47 @@
+//! Errors that can occur while generating JavaScript.
+
+use vulpi_location::Span;
+use vulpi_report::{IntoDiagnostic, Severity, Text};
+
+pub enum JsErrorKind {
+    /// A feature of the language that the JavaScript backend cannot compile yet.
+    Unsupported(&'static str),
+}
+
+pub struct JsError {
+    pub span: Span,
+    pub kind: JsErrorKind,
+}
+
+impl IntoDiagnostic for JsError {
+    fn message(&self) -> Text {
+        match &self.kind {
+            JsErrorKind::Unsupported(feature) => Text::from(format!(
+                "{} are not supported by the JavaScript backend",
+                feature
+            )),
+        }
+    }
+
+    fn hint(&self) -> Option<Text> {
+        match &self.kind {
+            JsErrorKind::Unsupported(_) => Some(Text::from(
+                "use `vulpi run` to run the program in the virtual machine",
+            )),
+        }
+    }
+
+    fn code(&self) -> Option<usize> {
+        match &self.kind {
+            JsErrorKind::Unsupported(_) => Some(500),
+        }
+    }
+
+    fn severity(&self) -> Severity {
+        Severity::Error
+    }
+
+    fn location(&self) -> Span {
+        self.span.clone()
+    }
+}
@@ -0,0 +1,6 @@
+//! A backend that prints readable JavaScript from the core language, so Vulpi code can be used
+//! inside of web applications.
+
+pub mod codegen;
+pub mod errors;
+pub mod source_map;
@@ -0,0 +1,135 @@
+//! Source maps of the scripts, in the third version of the format that browsers and Node read.
+//! The map has the paths and the contents of the sources, so they don't need to be served next to
+//! the script, and the positions of the script that come from them as segments of variable length
+//! quantities in base 64.
+
+use vulpi_location::{Encoding, FileId, Sources};
+
+use crate::codegen::Script;
+
+const BASE64: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+/// Pushes a number as a variable length quantity. The sign is the lowest bit of the first digit
+/// and each digit has five bits, with the sixth one set if another digit follows.
+fn vlq(out: &mut String, value: i64) {
+    let mut rest = if value < 0 {
+        (-value << 1) | 1
+    } else {
+        value << 1
+    };
+
+    loop {
+        let mut digit = rest & 0b11111;
+        rest >>= 5;
+
+        if rest > 0 {
+            digit |= 0b100000;
+        }
+
+        out.push(BASE64[digit as usize] as char);
+
+        if rest == 0 {
+            break;
+        }
+    }
+}
+
+/// The source map of a script whose file is named `file`. Mappings whose spans are not in the
+/// sources are left out. Columns of the sources are counted in UTF-16 code units, like JavaScript
+/// counts them.
+pub fn source_map(file: &str, script: &Script, sources: &Sources) -> String {
+    let mut files: Vec<FileId> = Vec::new();
+    let mut mappings = String::new();
+
+    // Each field of a segment is relative to the one of the segment before. The column of the
+    // script starts again at every line.
+    let mut previous = [0i64; 4];
+    let mut line = 0;
+    let mut first = true;
+
+    for mapping in &script.mappings {
+        let Some((_, position)) = sources.locate(&mapping.span, Encoding::Utf16) else {
+            continue;
+        };
+
+        let index = match files.iter().position(|x| *x == mapping.span.file) {
+            Some(index) => index,
+            None => {
+                files.push(mapping.span.file);
+                files.len() - 1
+            }
+        };
+
+        if mapping.line > line {
+            for _ in line..mapping.line {
+                mappings.push(';');
+            }
+
+            line = mapping.line;
+            previous[0] = 0;
+        } else if !first {
+            mappings.push(',');
+        }
+
+        first = false;
+
+        let segment = [
+            mapping.column as i64,
+            index as i64,
+            position.line as i64,
+            position.column as i64,
+        ];
+
+        for (field, previous) in segment.iter().zip(&mut previous) {
+            vlq(&mut mappings, field - *previous);
+            *previous = *field;
+        }
+    }
+
+    let files: Vec<_> = files.iter().filter_map(|x| sources.get(*x)).collect();
+
+    serde_json::json!({
+        "version": 3,
+        "file": file,
+        "sources": files.iter().map(|x| &x.path).collect::<Vec<_>>(),
+        "sourcesContent": files.iter().map(|x| &x.content).collect::<Vec<_>>(),
+        "names": [],
+        "mappings": mappings,
+    })
+    .to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use vulpi_location::{FileId, Span};
+
+    use super::*;
+    use crate::codegen::Mapping;
+
+    #[test]
+    fn encodes_the_segments_relative_to_the_ones_before() {
+        let mut sources = Sources::default();
+        let source = "let main = print \"a\"\n\nlet other = main\n".to_string();
+        sources.insert(FileId(1), "Main.vp", source);
+
+        let mapping = |line, column, start| Mapping {
+            line,
+            column,
+            span: Span::from_usize(FileId(1), start, start + 1),
+        };
+
+        let script = Script {
+            code: String::new(),
+            mappings: vec![mapping(0, 0, 0), mapping(1, 2, 11), mapping(40, 0, 22)],
+        };
+
+        let map: serde_json::Value =
+            serde_json::from_str(&source_map("main.js", &script, &sources)).unwrap();
+
+        assert_eq!(map["sources"][0], "Main.vp");
+
+        // The last segment is two lines of the sources after the second one and 11 columns before.
+        let separators = ";".repeat(39);
+        assert_eq!(map["mappings"], format!("AAAA;EAAW{}AAEX", separators));
+    }
+}
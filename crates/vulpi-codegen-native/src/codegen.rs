@@ -0,0 +1,1371 @@
+//! Compiler from the core language to Cranelift. Every value is a 64 bit word: integers,
+//! characters and the unit are stored directly, floats are stored by their bits, strings are
+//! pointers to null terminated bytes and closures are pointers to blocks.
+//!
+//! Constructors are stored by their [Layout]. A boxed constructor is a pointer to a block that
+//! starts with its tag followed by its fields, and the boxed constructors without fields are
+//! allocated once in the data section. Unboxed constructors are their only field and the ones of
+//! types without fields are their tags. A closure block starts with
+//! the address of its code and its arity followed by the captured variables. Every function
+//! receives the closure that it was called through and a pointer to its arguments, so calls to
+//! unknown functions and partial applications are handled by `vulpi_apply` in the runtime.
+//!
+//! The cells of `Ref` are blocks with a single field. Arrays are blocks of the runtime with their
+//! length and a pointer to the block of their elements, which is replaced when they grow.
+//!
+//! Blocks are allocated in the heap of the collector of [vulpi_runtime]. It finds the pointers
+//! on the stack by itself, but the cells of the let declarations without parameters are in the
+//! data section, so their addresses are listed in the table of roots.
+//!
+//! The program must be monomorphized before, because primitives are chosen by the types that
+//! they're instantiated to.
+//!
+//! The code of every function is compiled with the tail calling convention of Cranelift, and it
+//! receives its closure and its arguments directly. The closures point to an entry that loads the
+//! arguments and calls that code, because the runtime calls them with the convention of C. The
+//! tail calls to let declarations with all of their arguments are `return_call`s to their code, so
+//! they don't grow the stack. The other tail calls go through `vulpi_apply`, that can call any
+//! closure, and are ordinary calls followed by a return.
+//!
+//! The instructions are located at the calls that they come from, or at their declaration, and the
+//! object has the line tables of [crate::debug] for them.
+
+use std::collections::HashMap;
+
+use cranelift_codegen::{
+    gimli::RunTimeEndian,
+    ir::{
+        condcodes::{FloatCC, IntCC},
+        types::{F32, F64, I32, I64},
+        AbiParam, ArgumentExtension, Block, InstBuilder, MemFlags, Signature, StackSlotData,
+        StackSlotKind, TrapCode, Value as Word,
+    },
+    isa::CallConv,
+    settings::{self, Configurable},
+};
+use cranelift_frontend::{FunctionBuilder, FunctionBuilderContext, Switch, Variable};
+use cranelift_module::{DataDescription, DataId, FuncId, Linkage, Module};
+use cranelift_object::{ObjectBuilder, ObjectModule};
+
+use vulpi_intern::Symbol;
+use vulpi_location::{Sources, Span};
+use vulpi_report::{Diagnostic, Report};
+use vulpi_syntax::{elaborated::LiteralKind, r#abstract::Qualified};
+
+use vulpi_core::{
+    layout::{Layout, Layouts},
+    primitive::{self, Number, Operand, Primitive},
+    syntax::{
+        free_variables, Atom, Binder, Case, ExternalDecl, Program, TermKind, Type, TypeKind, Value,
+    },
+};
+
+use crate::{
+    debug::{self, Lines, Locations},
+    errors::{NativeError, NativeErrorKind},
+};
+
+/// The code for [TrapCode] of the terms that cannot be reached.
+const UNREACHABLE: u8 = 1;
+
+#[derive(Clone, Copy)]
+enum Global<'a> {
+    /// A let declaration with parameters, its code and the closure that is used when it's not
+    /// called directly.
+    Function {
+        direct: FuncId,
+        arity: usize,
+        closure: DataId,
+    },
+
+    /// A let declaration without parameters. The getter computes it the first time that it's used.
+    Value {
+        getter: FuncId,
+    },
+
+    External(&'a ExternalDecl),
+}
+
+/// A function that was declared while compiling another one and still needs a body.
+enum Pending<'a> {
+    Lambda {
+        function: FuncId,
+        direct: FuncId,
+        captures: Vec<Symbol>,
+        params: &'a [Binder],
+        body: &'a TermKind,
+    },
+
+    /// A closure for an external that is used without all of its arguments.
+    External {
+        function: FuncId,
+        external: &'a ExternalDecl,
+        primitive: Option<Primitive>,
+        types: Vec<Type>,
+    },
+
+    /// The code that initializes a let declaration without parameters.
+    Value {
+        getter: FuncId,
+        init: FuncId,
+        cell: DataId,
+    },
+}
+
+fn display(typ: &TypeKind) -> String {
+    match typ {
+        TypeKind::Constructor(name) => name.name.get(),
+        TypeKind::Bound(index) => format!("t{}", index),
+        TypeKind::Arrow(param, body) => format!("({} -> {})", display(param), display(body)),
+        TypeKind::Tuple(types) => {
+            let types: Vec<_> = types.iter().map(|x| display(x)).collect();
+            format!("({})", types.join(", "))
+        }
+        TypeKind::Application(func, args) => {
+            let args: Vec<_> = args.iter().map(|x| display(x)).collect();
+            format!("({} {})", display(func), args.join(" "))
+        }
+        TypeKind::Forall(name, body) => format!("(forall {}. {})", name.get(), display(body)),
+        TypeKind::Unknown => "_".to_string(),
+    }
+}
+
+/// The parameter of C for a value of the type, if it's passed.
+fn abi(typ: &TypeKind) -> Option<AbiParam> {
+    let param = match (primitive::number(typ), Operand::of(typ)) {
+        (_, Operand::Unit) => return None,
+        (Some(Number::Float { bits: 32 }), _) => AbiParam::new(F32),
+        (_, Operand::Float) => AbiParam::new(F64),
+        (Some(Number::Int { bits, signed }), _) if bits < 64 => {
+            let param = AbiParam::new(integer(bits));
+            if signed {
+                param.sext()
+            } else {
+                param.uext()
+            }
+        }
+        (_, Operand::Char) => AbiParam::new(I32).uext(),
+        _ => AbiParam::new(I64),
+    };
+
+    Some(param)
+}
+
+/// The type of Cranelift for an integer with the number of bits.
+fn integer(bits: u32) -> cranelift_codegen::ir::Type {
+    cranelift_codegen::ir::Type::int(bits as u16).unwrap()
+}
+
+fn bool(name: &str) -> Qualified {
+    Qualified {
+        path: Symbol::intern("Prelude.Bool"),
+        name: Symbol::intern(name),
+    }
+}
+
+/// The function that is being compiled.
+struct Emitter<'b> {
+    builder: FunctionBuilder<'b>,
+    variables: HashMap<Symbol, Variable>,
+    joins: HashMap<Symbol, (Block, Vec<Variable>)>,
+}
+
+impl<'b> Emitter<'b> {
+    fn variable(&mut self, name: &Symbol) -> Variable {
+        if let Some(variable) = self.variables.get(name) {
+            return *variable;
+        }
+
+        let variable = Variable::from_u32(self.variables.len() as u32);
+        self.builder.declare_var(variable, I64);
+        self.variables.insert(name.clone(), variable);
+        variable
+    }
+
+    fn bind(&mut self, name: &Symbol, value: Word) {
+        let variable = self.variable(name);
+        self.builder.def_var(variable, value);
+    }
+
+    fn int(&mut self, value: i64) -> Word {
+        self.builder.ins().iconst(I64, value)
+    }
+
+    fn load(&mut self, block: Word, index: usize) -> Word {
+        self.builder
+            .ins()
+            .load(I64, MemFlags::trusted(), block, (index * 8) as i32)
+    }
+
+    fn store(&mut self, value: Word, block: Word, index: usize) {
+        self.builder
+            .ins()
+            .store(MemFlags::trusted(), value, block, (index * 8) as i32);
+    }
+
+    /// Stores the arguments of a call in the stack and returns the pointer to them.
+    fn arguments(&mut self, args: &[Word]) -> Word {
+        let size = (args.len().max(1) * 8) as u32;
+        let slot = StackSlotData::new(StackSlotKind::ExplicitSlot, size, 3);
+        let slot = self.builder.create_sized_stack_slot(slot);
+
+        for (i, arg) in args.iter().enumerate() {
+            self.builder.ins().stack_store(*arg, slot, (i * 8) as i32);
+        }
+
+        self.builder.ins().stack_addr(I64, slot, 0)
+    }
+
+    fn float(&mut self, value: Word) -> Word {
+        self.builder.ins().bitcast(F64, MemFlags::new(), value)
+    }
+
+    fn word(&mut self, value: Word) -> Word {
+        self.builder.ins().bitcast(I64, MemFlags::new(), value)
+    }
+
+    /// The value of C for a word, with the type of the parameter.
+    fn foreign_value(&mut self, param: AbiParam, value: Word) -> Word {
+        match param.value_type {
+            F64 => self.float(value),
+            F32 => {
+                let value = self.float(value);
+                self.builder.ins().fdemote(F32, value)
+            }
+            I64 => value,
+            typ => self.builder.ins().ireduce(typ, value),
+        }
+    }
+
+    /// The word of a value of C, with the type of the parameter.
+    fn foreign_word(&mut self, param: AbiParam, value: Word) -> Word {
+        match param.value_type {
+            F64 => self.word(value),
+            F32 => {
+                let value = self.builder.ins().fpromote(F64, value);
+                self.word(value)
+            }
+            I64 => value,
+            _ if param.extension == ArgumentExtension::Sext => {
+                self.builder.ins().sextend(I64, value)
+            }
+            _ => self.builder.ins().uextend(I64, value),
+        }
+    }
+
+    fn unreachable(&mut self) {
+        self.builder.ins().trap(TrapCode::unwrap_user(UNREACHABLE));
+    }
+}
+
+pub struct Codegen<'a> {
+    module: ObjectModule,
+    reporter: Report,
+    globals: HashMap<Qualified, Global<'a>>,
+    layouts: Layouts,
+
+    /// The blocks of the boxed constructors without fields.
+    nullaries: HashMap<Qualified, DataId>,
+    externals: HashMap<(Qualified, Vec<Type>), DataId>,
+    strings: HashMap<Symbol, DataId>,
+
+    /// The cells of the let declarations without parameters, which are roots of the collector.
+    cells: Vec<DataId>,
+    pending: Vec<Pending<'a>>,
+    count: usize,
+
+    /// The declaration that is being compiled, used to locate errors.
+    span: Span,
+
+    locations: Locations,
+    lines: Vec<Lines>,
+}
+
+impl<'a> Codegen<'a> {
+    fn error(&mut self, kind: NativeErrorKind) {
+        self.reporter.report(Diagnostic::new(NativeError {
+            span: self.span.clone(),
+            kind,
+        }));
+    }
+
+    fn fresh(&mut self, prefix: &str) -> String {
+        self.count += 1;
+        format!("{}.{}", prefix, self.count)
+    }
+
+    fn signature(&self, params: usize) -> Signature {
+        let mut signature = self.module.make_signature();
+        signature
+            .params
+            .extend((0..params).map(|_| AbiParam::new(I64)));
+        signature.returns.push(AbiParam::new(I64));
+        signature
+    }
+
+    /// Declares a function with the signature that every closure has.
+    fn declare(&mut self, name: &str) -> FuncId {
+        let signature = self.signature(2);
+        self.module
+            .declare_function(name, Linkage::Local, &signature)
+            .unwrap()
+    }
+
+    /// The signature of the code of a function, that receives its closure and its arguments.
+    fn direct_signature(&self, arity: usize) -> Signature {
+        let mut signature = self.signature(arity + 1);
+        signature.call_conv = CallConv::Tail;
+        signature
+    }
+
+    /// Declares the code of a function, that is called by the entry of its closure.
+    fn declare_direct(&mut self, name: &str, arity: usize) -> FuncId {
+        let signature = self.direct_signature(arity);
+        self.module
+            .declare_function(&format!("{}.direct", name), Linkage::Local, &signature)
+            .unwrap()
+    }
+
+    fn bytes(&self, value: i64) -> [u8; 8] {
+        match self.module.isa().endianness() {
+            cranelift_codegen::ir::Endianness::Little => value.to_le_bytes(),
+            cranelift_codegen::ir::Endianness::Big => value.to_be_bytes(),
+        }
+    }
+
+    fn data(&mut self, name: &str, contents: Vec<u8>, writable: bool) -> DataId {
+        let id = self
+            .module
+            .declare_data(name, Linkage::Local, writable, false)
+            .unwrap();
+
+        let mut description = DataDescription::new();
+        description.define(contents.into_boxed_slice());
+        description.set_align(8);
+        self.module.define_data(id, &description).unwrap();
+
+        id
+    }
+
+    /// A closure in the data section for a function that captures nothing.
+    fn static_closure(&mut self, name: &str, function: FuncId, arity: usize) -> DataId {
+        let id = self
+            .module
+            .declare_data(name, Linkage::Local, false, false)
+            .unwrap();
+
+        let mut contents = vec![0; 8];
+        contents.extend(self.bytes(arity as i64));
+
+        let mut description = DataDescription::new();
+        description.define(contents.into_boxed_slice());
+        description.set_align(8);
+
+        let function = self.module.declare_func_in_data(function, &mut description);
+        description.write_function_addr(0, function);
+
+        self.module.define_data(id, &description).unwrap();
+
+        id
+    }
+
+    fn address(&mut self, e: &mut Emitter, data: DataId) -> Word {
+        let global = self.module.declare_data_in_func(data, e.builder.func);
+        e.builder.ins().symbol_value(I64, global)
+    }
+
+    fn call(&mut self, e: &mut Emitter, function: FuncId, args: &[Word]) -> Word {
+        let function = self.module.declare_func_in_func(function, e.builder.func);
+        let call = e.builder.ins().call(function, args);
+        e.builder.inst_results(call)[0]
+    }
+
+    /// Calls a function of the runtime. Every one of them receives and returns words.
+    fn runtime(&mut self, e: &mut Emitter, name: &str, args: &[Word]) -> Word {
+        let signature = self.signature(args.len());
+        let function = self
+            .module
+            .declare_function(name, Linkage::Import, &signature)
+            .unwrap();
+
+        self.call(e, function, args)
+    }
+
+    fn apply(&mut self, e: &mut Emitter, function: Word, args: &[Word]) -> Word {
+        if args.is_empty() {
+            return function;
+        }
+
+        let argc = e.int(args.len() as i64);
+        let argv = e.arguments(args);
+        self.runtime(e, "vulpi_apply", &[function, argc, argv])
+    }
+
+    fn string(&mut self, e: &mut Emitter, string: &Symbol) -> Word {
+        let data = match self.strings.get(string) {
+            Some(data) => *data,
+            None => {
+                let name = self.fresh("string");
+                let mut contents = string.get().into_bytes();
+                contents.push(0);
+
+                let data = self.data(&name, contents, false);
+                self.strings.insert(string.clone(), data);
+                data
+            }
+        };
+
+        self.address(e, data)
+    }
+
+    fn nullary(&mut self, e: &mut Emitter, name: &Qualified, tag: usize) -> Word {
+        let data = match self.nullaries.get(name) {
+            Some(data) => *data,
+            None => {
+                let contents = self.bytes(tag as i64).to_vec();
+                let data = self.data(&name.to_string(), contents, false);
+                self.nullaries.insert(name.clone(), data);
+                data
+            }
+        };
+
+        self.address(e, data)
+    }
+
+    fn construct(&mut self, e: &mut Emitter, name: &Qualified, args: Vec<Word>) -> Word {
+        match self.layouts.get(name) {
+            Layout::Unboxed => args[0],
+            Layout::Tag(tag) => e.int(tag as i64),
+            Layout::Boxed(tag) if args.is_empty() => self.nullary(e, name, tag),
+            Layout::Boxed(tag) => {
+                let tag = e.int(tag as i64);
+                let fields = e.int(args.len() as i64);
+                let block = self.runtime(e, vulpi_runtime::ALLOC_CONSTRUCTOR, &[tag, fields]);
+
+                for (i, arg) in args.into_iter().enumerate() {
+                    e.store(arg, block, i + 1);
+                }
+
+                block
+            }
+        }
+    }
+
+    /// The tag of a value of the type of the constructor.
+    fn tag(&mut self, e: &mut Emitter, name: &Qualified, value: Word) -> Word {
+        match self.layouts.get(name) {
+            Layout::Boxed(_) => e.load(value, 0),
+            Layout::Tag(_) => value,
+            Layout::Unboxed => e.int(0),
+        }
+    }
+
+    fn field(&mut self, e: &mut Emitter, name: &Qualified, value: Word, index: usize) -> Word {
+        match self.layouts.get(name) {
+            Layout::Unboxed => value,
+            _ => e.load(value, index + 1),
+        }
+    }
+
+    fn boolean(&mut self, e: &mut Emitter, condition: Word) -> Word {
+        let true_ = self.construct(e, &bool("True"), vec![]);
+        let false_ = self.construct(e, &bool("False"), vec![]);
+        e.builder.ins().select(condition, true_, false_)
+    }
+
+    fn literal(&mut self, e: &mut Emitter, literal: &LiteralKind) -> Word {
+        match literal {
+            LiteralKind::String(x) => self.string(e, x),
+            LiteralKind::Integer(x) => e.int(x.get().replace('_', "").parse().unwrap_or(0)),
+            LiteralKind::Float(x) => {
+                let value: f64 = x.get().replace('_', "").parse().unwrap_or(0.0);
+                e.int(value.to_bits() as i64)
+            }
+            LiteralKind::Char(x) => e.int(x.get().chars().next().unwrap_or_default() as i64),
+            LiteralKind::Unit => e.int(0),
+        }
+    }
+
+    fn atom(&mut self, e: &mut Emitter, atom: &Atom) -> Word {
+        match atom {
+            Atom::Variable(name) => match e.variables.get(name) {
+                Some(variable) => e.builder.use_var(*variable),
+                None => e.int(0),
+            },
+            Atom::Function(name, types) => match self.globals.get(name).copied() {
+                Some(Global::Function { closure, .. }) => self.address(e, closure),
+                Some(Global::Value { getter }) => {
+                    let null = e.int(0);
+                    self.call(e, getter, &[null, null])
+                }
+                Some(Global::External(external)) => {
+                    let closure = self.external(external, types);
+                    self.address(e, closure)
+                }
+                None => e.int(0),
+            },
+            Atom::Literal(literal) => self.literal(e, literal),
+        }
+    }
+
+    fn atoms(&mut self, e: &mut Emitter, atoms: &[Atom]) -> Vec<Word> {
+        atoms.iter().map(|x| self.atom(e, x)).collect()
+    }
+
+    /// The closure of an external with the types that it's instantiated to.
+    fn external(&mut self, external: &'a ExternalDecl, types: &[Type]) -> DataId {
+        let key = (external.name.clone(), types.to_vec());
+
+        if let Some(closure) = self.externals.get(&key) {
+            return *closure;
+        }
+
+        let primitive = Primitive::from_binding(&external.binding.get());
+
+        let arity = match primitive {
+            Some(primitive) => primitive.arity(),
+            None => external.typ.arrow_spine().0.len().max(1),
+        };
+
+        let name = self.fresh(&external.name.to_string());
+        let function = self.declare(&name);
+        let closure = self.static_closure(&format!("{}.closure", name), function, arity);
+
+        self.pending.push(Pending::External {
+            function,
+            external,
+            primitive,
+            types: types.to_vec(),
+        });
+
+        self.externals.insert(key, closure);
+        closure
+    }
+
+    fn primitive(
+        &mut self,
+        e: &mut Emitter,
+        external: &ExternalDecl,
+        primitive: Primitive,
+        types: &[Type],
+        args: &[Word],
+    ) -> Word {
+        let (params, _) = external.typ.instantiate(types).arrow_spine();
+        let operand = params
+            .first()
+            .map(|x| Operand::of(x))
+            .unwrap_or(Operand::Unit);
+        let unsupported = || params.first().map(|x| display(x)).unwrap_or_default();
+
+        match primitive {
+            Primitive::Rem if operand == Operand::Float => {
+                self.runtime(e, "vulpi_float_rem", &[args[0], args[1]])
+            }
+            Primitive::Add | Primitive::Sub | Primitive::Mul | Primitive::Div
+                if operand == Operand::Float =>
+            {
+                let l = e.float(args[0]);
+                let r = e.float(args[1]);
+
+                let result = match primitive {
+                    Primitive::Add => e.builder.ins().fadd(l, r),
+                    Primitive::Sub => e.builder.ins().fsub(l, r),
+                    Primitive::Mul => e.builder.ins().fmul(l, r),
+                    _ => e.builder.ins().fdiv(l, r),
+                };
+
+                e.word(result)
+            }
+            Primitive::Add => e.builder.ins().iadd(args[0], args[1]),
+            Primitive::Sub => e.builder.ins().isub(args[0], args[1]),
+            Primitive::Mul => e.builder.ins().imul(args[0], args[1]),
+            Primitive::CheckedAdd => self.runtime(e, "vulpi_int_checked_add", &[args[0], args[1]]),
+            Primitive::CheckedSub => self.runtime(e, "vulpi_int_checked_sub", &[args[0], args[1]]),
+            Primitive::CheckedMul => self.runtime(e, "vulpi_int_checked_mul", &[args[0], args[1]]),
+            Primitive::SaturatingAdd => {
+                self.runtime(e, "vulpi_int_saturating_add", &[args[0], args[1]])
+            }
+            Primitive::SaturatingSub => {
+                self.runtime(e, "vulpi_int_saturating_sub", &[args[0], args[1]])
+            }
+            Primitive::SaturatingMul => {
+                self.runtime(e, "vulpi_int_saturating_mul", &[args[0], args[1]])
+            }
+
+            // The instructions trap on a zero divisor and on the overflow of the minimum integer
+            // divided by minus one, so the runtime checks both.
+            Primitive::Div => self.runtime(e, "vulpi_int_div", &[args[0], args[1]]),
+            Primitive::Rem => self.runtime(e, "vulpi_int_rem", &[args[0], args[1]]),
+            Primitive::Eq
+            | Primitive::Neq
+            | Primitive::Lt
+            | Primitive::Gt
+            | Primitive::Le
+            | Primitive::Ge => {
+                let (int, float) = match primitive {
+                    Primitive::Eq => (IntCC::Equal, FloatCC::Equal),
+                    Primitive::Neq => (IntCC::NotEqual, FloatCC::NotEqual),
+                    Primitive::Lt => (IntCC::SignedLessThan, FloatCC::LessThan),
+                    Primitive::Gt => (IntCC::SignedGreaterThan, FloatCC::GreaterThan),
+                    Primitive::Le => (IntCC::SignedLessThanOrEqual, FloatCC::LessThanOrEqual),
+                    _ => (IntCC::SignedGreaterThanOrEqual, FloatCC::GreaterThanOrEqual),
+                };
+
+                let condition = match operand {
+                    Operand::Int | Operand::Char | Operand::Unit => {
+                        e.builder.ins().icmp(int, args[0], args[1])
+                    }
+                    Operand::Float => {
+                        let l = e.float(args[0]);
+                        let r = e.float(args[1]);
+                        e.builder.ins().fcmp(float, l, r)
+                    }
+                    Operand::String => {
+                        let order = self.runtime(e, "vulpi_string_compare", &[args[0], args[1]]);
+                        e.builder.ins().icmp_imm(int, order, 0)
+                    }
+                    Operand::Bool => {
+                        let l = self.tag(e, &bool("True"), args[0]);
+                        let r = self.tag(e, &bool("True"), args[1]);
+                        e.builder.ins().icmp(int, l, r)
+                    }
+                    Operand::Other => {
+                        self.error(NativeErrorKind::UnsupportedType(
+                            "comparison",
+                            unsupported(),
+                        ));
+                        e.builder.ins().iconst(cranelift_codegen::ir::types::I8, 0)
+                    }
+                };
+
+                self.boolean(e, condition)
+            }
+            Primitive::Concat => self.runtime(e, "vulpi_string_concat", &[args[0], args[1]]),
+            Primitive::Length => self.runtime(e, "vulpi_string_length", &[args[0]]),
+            Primitive::Index => self.runtime(e, "vulpi_string_index", &[args[0], args[1]]),
+            Primitive::ByteLength => self.runtime(e, "vulpi_string_byte_length", &[args[0]]),
+            Primitive::Slice => self.runtime(e, "vulpi_string_slice", &args[..3]),
+            Primitive::CharAt => self.runtime(e, "vulpi_string_char_at", &[args[0], args[1]]),
+            Primitive::CharWidth => self.runtime(e, "vulpi_char_width", &[args[0]]),
+            Primitive::Convert(number) => self.convert(e, operand, number, args[0]),
+            Primitive::CharToInt => args[0],
+            Primitive::IntToChar => self.runtime(e, "vulpi_int_to_char", &[args[0]]),
+
+            // A cell is a block with a single field that is replaced in place.
+            Primitive::RefNew => {
+                let tag = e.int(0);
+                let fields = e.int(1);
+                let block = self.runtime(e, vulpi_runtime::ALLOC_CONSTRUCTOR, &[tag, fields]);
+                e.store(args[0], block, 1);
+                block
+            }
+            Primitive::RefGet => e.load(args[0], 1),
+            Primitive::RefSet => {
+                e.store(args[1], args[0], 1);
+                e.int(0)
+            }
+
+            // An array is a block with its tag, its length, its capacity and a pointer to the
+            // block of its elements, so the length is its first field.
+            Primitive::ArrayEmpty => {
+                let (length, element) = (e.int(0), e.int(0));
+                self.runtime(e, "vulpi_array_make", &[length, element])
+            }
+            Primitive::ArrayMake => self.runtime(e, "vulpi_array_make", &[args[0], args[1]]),
+            Primitive::ArrayGet => self.runtime(e, "vulpi_array_get", &[args[0], args[1]]),
+            Primitive::ArraySet => self.runtime(e, "vulpi_array_set", &args[..3]),
+            Primitive::ArrayLength => e.load(args[0], 1),
+            Primitive::ArrayPush => self.runtime(e, "vulpi_array_push", &[args[0], args[1]]),
+            Primitive::Identity => args[0],
+            Primitive::Show => match operand {
+                Operand::Float => self.runtime(e, "vulpi_float_to_string", &[args[0]]),
+                Operand::Char => self.runtime(e, "vulpi_char_to_string", &[args[0]]),
+                _ => self.runtime(e, "vulpi_int_to_string", &[args[0]]),
+            },
+            Primitive::Print | Primitive::PrintError => {
+                let stream = e.int(if primitive == Primitive::Print { 1 } else { 2 });
+
+                let (function, value) = match operand {
+                    Operand::Int => ("vulpi_print_int", args[0]),
+                    Operand::Float => ("vulpi_print_float", args[0]),
+                    Operand::Char => ("vulpi_print_char", args[0]),
+                    Operand::String => ("vulpi_print_string", args[0]),
+                    Operand::Unit => ("vulpi_print_unit", args[0]),
+                    Operand::Bool => {
+                        let tag = self.tag(e, &bool("True"), args[0]);
+                        let true_ = self.layouts.get(&bool("True")).tag() as i64;
+                        let condition = e.builder.ins().icmp_imm(IntCC::Equal, tag, true_);
+                        let yes = self.string(e, &Symbol::intern("True"));
+                        let no = self.string(e, &Symbol::intern("False"));
+                        let name = e.builder.ins().select(condition, yes, no);
+                        ("vulpi_print_string", name)
+                    }
+                    Operand::Other => {
+                        self.error(NativeErrorKind::UnsupportedType("printing", unsupported()));
+                        return e.int(0);
+                    }
+                };
+
+                self.runtime(e, function, &[value, stream])
+            }
+        }
+    }
+
+    /// Converts a number to a numeric type. Integers keep the lower bits that fit in the type and
+    /// floats are rounded to its precision, while the conversion of floats to integers saturates
+    /// in the runtime.
+    fn convert(&mut self, e: &mut Emitter, operand: Operand, number: Number, value: Word) -> Word {
+        match number {
+            Number::Int { .. } if operand == Operand::Float => {
+                let (min, max) = number.range().unwrap();
+                let min = e.int(min);
+                let max = e.int(max);
+                self.runtime(e, "vulpi_float_to_int", &[value, min, max])
+            }
+            Number::Int { bits: 64, .. } => value,
+            Number::Int { bits, signed } => {
+                let small = e.builder.ins().ireduce(integer(bits), value);
+
+                if signed {
+                    e.builder.ins().sextend(I64, small)
+                } else {
+                    e.builder.ins().uextend(I64, small)
+                }
+            }
+            Number::Float { bits } => {
+                let mut value = if operand == Operand::Float {
+                    e.float(value)
+                } else {
+                    e.builder.ins().fcvt_from_sint(F64, value)
+                };
+
+                if bits == 32 {
+                    let single = e.builder.ins().fdemote(F32, value);
+                    value = e.builder.ins().fpromote(F64, single);
+                }
+
+                e.word(value)
+            }
+        }
+    }
+
+    /// Calls a C function with the calling convention of the machine. Numbers are passed as the
+    /// C types of their sizes, like `int8_t` for `Int8` and `float` for `Float32`, characters as
+    /// `uint32_t` and strings as pointers to their bytes. Unit parameters are not passed and a
+    /// unit result is `void`. Values of other types are passed as their words, so C code can keep
+    /// them as opaque pointers.
+    fn foreign(
+        &mut self,
+        e: &mut Emitter,
+        external: &ExternalDecl,
+        types: &[Type],
+        args: &[Word],
+    ) -> Word {
+        let (params, ret) = external.typ.instantiate(types).arrow_spine();
+        let mut signature = self.module.make_signature();
+        let mut values = Vec::new();
+
+        for (typ, arg) in params.iter().zip(args) {
+            if let Some(param) = abi(typ) {
+                signature.params.push(param);
+                values.push(e.foreign_value(param, *arg));
+            }
+        }
+
+        let result = abi(&ret);
+        signature.returns.extend(result);
+
+        let binding = external.binding.get();
+
+        let Ok(function) =
+            self.module
+                .declare_function(binding.trim(), Linkage::Import, &signature)
+        else {
+            self.error(NativeErrorKind::ForeignSignature(binding));
+            return e.int(0);
+        };
+
+        let function = self.module.declare_func_in_func(function, e.builder.func);
+        let call = e.builder.ins().call(function, &values);
+
+        match (result, e.builder.inst_results(call).first().copied()) {
+            (Some(param), Some(value)) => e.foreign_word(param, value),
+            _ => e.int(0),
+        }
+    }
+
+    fn application(&mut self, e: &mut Emitter, function: &Atom, args: &[Atom]) -> Word {
+        let args = self.atoms(e, args);
+
+        if let Atom::Function(name, types) = function {
+            match self.globals.get(name).copied() {
+                Some(Global::Function { direct, arity, .. }) if args.len() >= arity => {
+                    let mut words = vec![e.int(0)];
+                    words.extend(&args[..arity]);
+                    let result = self.call(e, direct, &words);
+                    return self.apply(e, result, &args[arity..]);
+                }
+                Some(Global::External(external)) => {
+                    if let Some(primitive) = Primitive::from_binding(&external.binding.get()) {
+                        let arity = primitive.arity();
+
+                        if args.len() >= arity {
+                            let result =
+                                self.primitive(e, external, primitive, types, &args[..arity]);
+                            return self.apply(e, result, &args[arity..]);
+                        }
+                    } else if external.is_foreign() {
+                        let arity = external.typ.arrow_spine().0.len().max(1);
+
+                        if args.len() >= arity {
+                            let result = self.foreign(e, external, types, &args[..arity]);
+                            return self.apply(e, result, &args[arity..]);
+                        }
+                    }
+                }
+                _ => (),
+            }
+        }
+
+        let function = self.atom(e, function);
+        self.apply(e, function, &args)
+    }
+
+    fn lambda(&mut self, e: &mut Emitter, params: &'a [Binder], body: &'a TermKind) -> Word {
+        let captures: Vec<_> = free_variables(params, body)
+            .into_iter()
+            .filter(|x| e.variables.contains_key(x))
+            .collect();
+
+        let name = self.fresh("lambda");
+        let function = self.declare(&name);
+        let direct = self.declare_direct(&name, params.len());
+
+        let code = self.module.declare_func_in_func(function, e.builder.func);
+        let code = e.builder.ins().func_addr(I64, code);
+        let arity = e.int(params.len() as i64);
+        let count = e.int(captures.len() as i64);
+        let closure = self.runtime(e, vulpi_runtime::ALLOC_CLOSURE, &[code, arity, count]);
+
+        for (i, capture) in captures.iter().enumerate() {
+            let value = e.builder.use_var(e.variables[capture]);
+            e.store(value, closure, i + 2);
+        }
+
+        self.pending.push(Pending::Lambda {
+            function,
+            direct,
+            captures,
+            params,
+            body,
+        });
+
+        closure
+    }
+
+    fn value(&mut self, e: &mut Emitter, value: &'a Value) -> Word {
+        match value {
+            Value::Atom(atom) => self.atom(e, atom),
+            Value::Lambda(params, body) => self.lambda(e, params, body),
+            Value::Application(function, args, span) => {
+                e.builder.set_srcloc(self.locations.get(span));
+                self.application(e, function, args)
+            }
+            Value::Constructor(name, args) => {
+                let args = self.atoms(e, args);
+                self.construct(e, name, args)
+            }
+            Value::Field(name, atom, index) => {
+                let value = self.atom(e, atom);
+                self.field(e, name, value, *index)
+            }
+            Value::Perform(..) | Value::Handle(..) | Value::Mask(..) => {
+                unreachable!("the effects are elaborated by vulpi_core::effects")
+            }
+        }
+    }
+
+    fn equals(&mut self, e: &mut Emitter, scrutinee: Word, literal: &LiteralKind) -> Word {
+        let value = self.literal(e, literal);
+
+        match literal {
+            LiteralKind::String(_) => {
+                let order = self.runtime(e, "vulpi_string_compare", &[scrutinee, value]);
+                e.builder.ins().icmp_imm(IntCC::Equal, order, 0)
+            }
+            LiteralKind::Float(_) => {
+                let l = e.float(scrutinee);
+                let r = e.float(value);
+                e.builder.ins().fcmp(FloatCC::Equal, l, r)
+            }
+            _ => e.builder.ins().icmp(IntCC::Equal, scrutinee, value),
+        }
+    }
+
+    /// Checks if an integer or a character is inside of a range. Every integer is kept in a word
+    /// with a value of its type, so they are compared as signed words.
+    fn within(
+        &mut self,
+        e: &mut Emitter,
+        scrutinee: Word,
+        start: &LiteralKind,
+        end: &LiteralKind,
+    ) -> Word {
+        let start = self.literal(e, start);
+        let end = self.literal(e, end);
+        let above = e
+            .builder
+            .ins()
+            .icmp(IntCC::SignedGreaterThanOrEqual, scrutinee, start);
+        let below = e
+            .builder
+            .ins()
+            .icmp(IntCC::SignedLessThanOrEqual, scrutinee, end);
+        e.builder.ins().band(above, below)
+    }
+
+    fn term(&mut self, e: &mut Emitter, term: &'a TermKind) {
+        match term {
+            TermKind::Let(binder, value, rest) => {
+                let value = self.value(e, value);
+                e.bind(&binder.name, value);
+                self.term(e, rest);
+            }
+            TermKind::Join(label, params, body, rest) => {
+                let block = e.builder.create_block();
+                let params = params.iter().map(|x| e.variable(&x.name)).collect();
+                e.joins.insert(label.clone(), (block, params));
+
+                self.term(e, rest);
+
+                e.builder.switch_to_block(block);
+                self.term(e, body);
+            }
+            TermKind::Jump(label, args) => {
+                let args = self.atoms(e, args);
+                let (block, params) = e.joins[label].clone();
+
+                for (param, arg) in params.into_iter().zip(args) {
+                    e.builder.def_var(param, arg);
+                }
+
+                e.builder.ins().jump(block, &[]);
+            }
+            TermKind::Tail(function, args, span) => {
+                e.builder.set_srcloc(self.locations.get(span));
+
+                if let Atom::Function(name, _) = function {
+                    if let Some(Global::Function { direct, arity, .. }) = self.globals.get(name) {
+                        if args.len() == *arity {
+                            let direct = self.module.declare_func_in_func(*direct, e.builder.func);
+                            let mut words = vec![e.int(0)];
+                            words.extend(self.atoms(e, args));
+                            e.builder.ins().return_call(direct, &words);
+                            return;
+                        }
+                    }
+                }
+
+                let value = self.application(e, function, args);
+                e.builder.ins().return_(&[value]);
+            }
+            TermKind::Match(atom, alts, default) => {
+                let scrutinee = self.atom(e, atom);
+                let fallback = e.builder.create_block();
+                let blocks: Vec<_> = alts.iter().map(|_| e.builder.create_block()).collect();
+
+                let constructors = alts.iter().all(|x| matches!(x.case, Case::Constructor(_)));
+
+                if constructors {
+                    // Every alternative has a constructor of the same type, so the first one
+                    // tells how to find the tag.
+                    let tag = match alts.first().map(|x| &x.case) {
+                        Some(Case::Constructor(name)) => self.tag(e, name, scrutinee),
+                        _ => e.int(0),
+                    };
+
+                    let mut switch = Switch::new();
+
+                    for (alt, block) in alts.iter().zip(&blocks) {
+                        if let Case::Constructor(name) = &alt.case {
+                            let tag = self.layouts.get(name).tag();
+                            switch.set_entry(tag as u128, *block);
+                        }
+                    }
+
+                    switch.emit(&mut e.builder, tag, fallback);
+                } else {
+                    for (alt, block) in alts.iter().zip(&blocks) {
+                        let next = e.builder.create_block();
+
+                        let condition = match &alt.case {
+                            Case::Literal(literal) => self.equals(e, scrutinee, literal),
+                            Case::Range(start, end) => self.within(e, scrutinee, start, end),
+                            Case::Constructor(_) => {
+                                e.builder.ins().iconst(cranelift_codegen::ir::types::I8, 0)
+                            }
+                        };
+
+                        e.builder.ins().brif(condition, *block, &[], next, &[]);
+                        e.builder.switch_to_block(next);
+                    }
+
+                    e.builder.ins().jump(fallback, &[]);
+                }
+
+                for (alt, block) in alts.iter().zip(blocks) {
+                    e.builder.switch_to_block(block);
+
+                    if let Case::Constructor(name) = &alt.case {
+                        for (i, binder) in alt.binders.iter().enumerate() {
+                            let field = self.field(e, name, scrutinee, i);
+                            e.bind(&binder.name, field);
+                        }
+                    }
+
+                    self.term(e, &alt.body);
+                }
+
+                e.builder.switch_to_block(fallback);
+
+                match default {
+                    Some(default) => self.term(e, default),
+                    None => e.unreachable(),
+                }
+            }
+            TermKind::Return(atom) => {
+                let value = self.atom(e, atom);
+                e.builder.ins().return_(&[value]);
+            }
+            TermKind::Unreachable => e.unreachable(),
+        }
+    }
+
+    /// Defines a function with the signature of closures. The body receives the emitter, the
+    /// closure and the pointer to the arguments.
+    fn define(&mut self, id: FuncId, body: impl FnOnce(&mut Self, &mut Emitter, Word, Word)) {
+        let signature = self.signature(2);
+        self.define_with(id, signature, |ctx, e, params| body(ctx, e, params[0], params[1]))
+    }
+
+    /// Defines a function with a signature. The body receives the emitter and the parameters.
+    fn define_with(
+        &mut self,
+        id: FuncId,
+        signature: Signature,
+        body: impl FnOnce(&mut Self, &mut Emitter, &[Word]),
+    ) {
+        let mut context = self.module.make_context();
+        context.func.signature = signature;
+
+        let mut builder_context = FunctionBuilderContext::new();
+
+        let mut e = Emitter {
+            builder: FunctionBuilder::new(&mut context.func, &mut builder_context),
+            variables: HashMap::new(),
+            joins: HashMap::new(),
+        };
+
+        let entry = e.builder.create_block();
+        e.builder.append_block_params_for_function_params(entry);
+        e.builder.switch_to_block(entry);
+        e.builder.set_srcloc(self.locations.get(&self.span));
+
+        let params = e.builder.block_params(entry).to_vec();
+        body(self, &mut e, &params);
+
+        e.builder.seal_all_blocks();
+        e.builder.finalize();
+
+        self.module.define_function(id, &mut context).unwrap();
+        self.lines.extend(Lines::new(id, &context));
+    }
+
+    /// Defines the code of a function and the entry of its closure, that calls the code with the
+    /// arguments that it loads.
+    fn function(
+        &mut self,
+        (id, direct): (FuncId, FuncId),
+        captures: &[Symbol],
+        params: &'a [Binder],
+        body: &'a TermKind,
+    ) {
+        self.define(id, |ctx, e, closure, args| {
+            let mut words = vec![closure];
+            words.extend((0..params.len()).map(|i| e.load(args, i)));
+
+            let result = ctx.call(e, direct, &words);
+            e.builder.ins().return_(&[result]);
+        });
+
+        let signature = self.direct_signature(params.len());
+
+        self.define_with(direct, signature, |ctx, e, words| {
+            for (i, capture) in captures.iter().enumerate() {
+                let value = e.load(words[0], i + 2);
+                e.bind(capture, value);
+            }
+
+            for (param, value) in params.iter().zip(&words[1..]) {
+                e.bind(&param.name, *value);
+            }
+
+            ctx.term(e, body);
+        })
+    }
+
+    fn pending(&mut self, pending: Pending<'a>) {
+        match pending {
+            Pending::Lambda {
+                function,
+                direct,
+                captures,
+                params,
+                body,
+            } => self.function((function, direct), &captures, params, body),
+            Pending::External {
+                function,
+                external,
+                primitive,
+                types,
+            } => self.define(function, |ctx, e, _, args| {
+                let result = match primitive {
+                    Some(primitive) => {
+                        let args: Vec<_> =
+                            (0..primitive.arity()).map(|i| e.load(args, i)).collect();
+                        ctx.primitive(e, external, primitive, &types, &args)
+                    }
+                    None if external.is_foreign() => {
+                        let arity = external.typ.arrow_spine().0.len().max(1);
+                        let args: Vec<_> = (0..arity).map(|i| e.load(args, i)).collect();
+                        ctx.foreign(e, external, &types, &args)
+                    }
+                    None => {
+                        if let Some(convention) = &external.convention {
+                            ctx.error(NativeErrorKind::UnknownConvention(convention.get()));
+                        }
+
+                        let name = ctx.string(e, &external.binding);
+                        ctx.runtime(e, "vulpi_unknown_external", &[name])
+                    }
+                };
+
+                e.builder.ins().return_(&[result]);
+            }),
+            Pending::Value { getter, init, cell } => self.define(getter, |ctx, e, _, _| {
+                let cell = ctx.address(e, cell);
+                let initialized = e.load(cell, 0);
+
+                let ready = e.builder.create_block();
+                let compute = e.builder.create_block();
+
+                e.builder.ins().brif(initialized, ready, &[], compute, &[]);
+
+                e.builder.switch_to_block(compute);
+                let null = e.int(0);
+                let value = ctx.call(e, init, &[null, null]);
+                e.store(value, cell, 1);
+                let one = e.int(1);
+                e.store(one, cell, 0);
+                e.builder.ins().return_(&[value]);
+
+                e.builder.switch_to_block(ready);
+                let value = e.load(cell, 1);
+                e.builder.ins().return_(&[value]);
+            }),
+        }
+    }
+
+    /// The table of the addresses of the values in the cells, so the collector can scan them.
+    fn roots(&mut self) {
+        let id = self
+            .module
+            .declare_data(vulpi_runtime::ROOTS, Linkage::Export, false, false)
+            .unwrap();
+
+        let mut contents = self.bytes(self.cells.len() as i64).to_vec();
+        contents.resize((self.cells.len() + 1) * 8, 0);
+
+        let mut description = DataDescription::new();
+        description.define(contents.into_boxed_slice());
+        description.set_align(8);
+
+        for (i, cell) in self.cells.clone().into_iter().enumerate() {
+            let cell = self.module.declare_data_in_data(cell, &mut description);
+            description.write_data_addr(((i + 1) * 8) as u32, cell, 8);
+        }
+
+        self.module.define_data(id, &description).unwrap();
+    }
+
+    /// Defines the `vulpi_main` function that the runtime calls. It initializes the values and
+    /// then the entry point, that is called with the unit if it's a function.
+    fn entry(&mut self, program: &Program, entry: &Qualified) {
+        let signature = self.signature(0);
+        let id = self
+            .module
+            .declare_function("vulpi_main", Linkage::Export, &signature)
+            .unwrap();
+
+        let decl = program.lets.iter().find(|x| &x.name == entry);
+
+        let Some(decl) = decl else {
+            self.span = Span::default();
+            self.error(NativeErrorKind::MissingEntry(entry.clone()));
+            return;
+        };
+
+        let mut context = self.module.make_context();
+        context.func.signature = signature;
+
+        let mut builder_context = FunctionBuilderContext::new();
+
+        let mut e = Emitter {
+            builder: FunctionBuilder::new(&mut context.func, &mut builder_context),
+            variables: HashMap::new(),
+            joins: HashMap::new(),
+        };
+
+        let block = e.builder.create_block();
+        e.builder.switch_to_block(block);
+        e.builder.set_srcloc(self.locations.get(&decl.span));
+
+        // The values are in the order of their initialization.
+        for value in program.lets.iter().filter(|x| x.params.is_empty()) {
+            if &value.name != entry {
+                self.atom(&mut e, &Atom::Function(value.name.clone(), vec![]));
+            }
+        }
+
+        let value = self.atom(&mut e, &Atom::Function(entry.clone(), vec![]));
+
+        let (params, _) = decl.typ.arrow_spine();
+
+        if !params.is_empty() {
+            let unit = e.int(0);
+            self.apply(&mut e, value, &[unit]);
+        }
+
+        let zero = e.int(0);
+        e.builder.ins().return_(&[zero]);
+
+        e.builder.seal_all_blocks();
+        e.builder.finalize();
+
+        self.module.define_function(id, &mut context).unwrap();
+        self.lines.extend(Lines::new(id, &context));
+    }
+}
+
+/// Compiles a monomorphized program into an object file for the machine that the compiler runs
+/// on, with `vulpi_main` calling the entry point. The line tables name the files of the sources.
+/// Returns nothing if some part of the program cannot be compiled.
+pub fn compile(
+    reporter: Report,
+    program: &Program,
+    entry: &Qualified,
+    sources: &Sources,
+) -> Option<Vec<u8>> {
+    let mut flags = settings::builder();
+    flags.set("is_pic", "true").unwrap();
+    flags.set("opt_level", "speed").unwrap();
+
+    // The tail calls of Cranelift need the frame pointers.
+    flags.set("preserve_frame_pointers", "true").unwrap();
+
+    let isa = cranelift_native::builder()
+        .unwrap()
+        .finish(settings::Flags::new(flags))
+        .unwrap();
+
+    let builder = ObjectBuilder::new(isa, "vulpi", cranelift_module::default_libcall_names());
+
+    let mut ctx = Codegen {
+        module: ObjectModule::new(builder.unwrap()),
+        reporter: reporter.clone(),
+        globals: HashMap::new(),
+        layouts: Layouts::new(program),
+        nullaries: HashMap::new(),
+        externals: HashMap::new(),
+        strings: HashMap::new(),
+        cells: Vec::new(),
+        pending: Vec::new(),
+        count: 0,
+        span: Span::default(),
+        locations: Locations::default(),
+        lines: Vec::new(),
+    };
+
+    for external in &program.externals {
+        ctx.globals
+            .insert(external.name.clone(), Global::External(external));
+    }
+
+    // Globals are declared before compiling any function so they can refer to each other.
+    let mut functions = Vec::new();
+
+    for decl in &program.lets {
+        let name = decl.name.to_string();
+        let function = ctx.declare(&name);
+        let direct = ctx.declare_direct(&name, decl.params.len());
+
+        let global = if decl.params.is_empty() {
+            let getter = ctx.declare(&format!("{}.get", name));
+            let cell = ctx.data(&format!("{}.cell", name), vec![0; 16], true);
+            ctx.cells.push(cell);
+
+            ctx.pending.push(Pending::Value {
+                getter,
+                init: function,
+                cell,
+            });
+
+            Global::Value { getter }
+        } else {
+            let arity = decl.params.len();
+            let closure = ctx.static_closure(&format!("{}.closure", name), function, arity);
+
+            Global::Function {
+                direct,
+                arity,
+                closure,
+            }
+        };
+
+        ctx.globals.insert(decl.name.clone(), global);
+        functions.push((function, direct));
+    }
+
+    for (decl, function) in program.lets.iter().zip(functions) {
+        ctx.span = decl.span.clone();
+        ctx.function(function, &[], &decl.params, &decl.body);
+
+        while let Some(pending) = ctx.pending.pop() {
+            ctx.pending(pending);
+        }
+    }
+
+    while let Some(pending) = ctx.pending.pop() {
+        ctx.pending(pending);
+    }
+
+    ctx.entry(program, entry);
+    ctx.roots();
+
+    if reporter.has_errors() {
+        return None;
+    }
+
+    let endian = match ctx.module.isa().endianness() {
+        cranelift_codegen::ir::Endianness::Little => RunTimeEndian::Little,
+        cranelift_codegen::ir::Endianness::Big => RunTimeEndian::Big,
+    };
+
+    let mut product = ctx.module.finish();
+
+    // The object is still usable without the line tables, so the formats whose relocations
+    // cannot describe them are left without them.
+    let _ = debug::write(&mut product, endian, &ctx.lines, &ctx.locations, sources);
+
+    Some(product.emit().unwrap())
+}
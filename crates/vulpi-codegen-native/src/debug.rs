@@ -0,0 +1,262 @@
+//! Line tables of DWARF, so debuggers and profilers show the lines of the sources that the machine
+//! code comes from. The source location of each instruction of Cranelift is the index of a span in
+//! the [Locations], and the ranges of the code of each function become the rows of a sequence of
+//! the line program. The unit of the object has no entries besides its root, that points to the
+//! line program and has the ranges of the functions.
+
+use std::collections::HashMap;
+
+use cranelift_codegen::{
+    gimli::{
+        self,
+        write::{
+            Address, AttributeValue, DwarfUnit, EndianVec, LineProgram, LineString, Range,
+            RangeList, RelocateWriter, Relocation, RelocationTarget, Sections,
+        },
+        RunTimeEndian,
+    },
+    ir::SourceLoc,
+    Context,
+};
+use cranelift_module::FuncId;
+use cranelift_object::{
+    object::{
+        write::{self, SectionId, StandardSegment, SymbolId},
+        RelocationEncoding, RelocationFlags, RelocationKind, SectionKind,
+    },
+    ObjectProduct,
+};
+use vulpi_location::{Encoding, Sources, Span};
+
+/// The spans that the source locations of the instructions point to.
+#[derive(Default)]
+pub struct Locations {
+    spans: Vec<Span>,
+    indexes: HashMap<Span, u32>,
+}
+
+impl Locations {
+    pub fn get(&mut self, span: &Span) -> SourceLoc {
+        if let Some(index) = self.indexes.get(span) {
+            return SourceLoc::new(*index);
+        }
+
+        let index = self.spans.len() as u32;
+        self.spans.push(span.clone());
+        self.indexes.insert(span.clone(), index);
+        SourceLoc::new(index)
+    }
+
+    fn span(&self, location: SourceLoc) -> Option<&Span> {
+        if location.is_default() {
+            None
+        } else {
+            self.spans.get(location.bits() as usize)
+        }
+    }
+}
+
+/// The ranges of the code of a function that was compiled, by their offsets and locations.
+pub struct Lines {
+    function: FuncId,
+    size: u32,
+    ranges: Vec<(u32, SourceLoc)>,
+}
+
+impl Lines {
+    pub fn new(function: FuncId, context: &Context) -> Option<Self> {
+        let code = context.compiled_code()?;
+
+        let ranges = code
+            .buffer
+            .get_srclocs_sorted()
+            .iter()
+            .map(|x| (x.start, x.loc))
+            .collect();
+
+        Some(Self {
+            function,
+            size: code.buffer.total_size(),
+            ranges,
+        })
+    }
+}
+
+/// A section of DWARF with the relocations of the addresses of the functions and of the offsets
+/// into the other sections.
+#[derive(Clone)]
+struct Section {
+    writer: EndianVec<RunTimeEndian>,
+    relocations: Vec<Relocation>,
+}
+
+impl RelocateWriter for Section {
+    type Writer = EndianVec<RunTimeEndian>;
+
+    fn writer(&self) -> &Self::Writer {
+        &self.writer
+    }
+
+    fn writer_mut(&mut self) -> &mut Self::Writer {
+        &mut self.writer
+    }
+
+    fn relocate(&mut self, relocation: Relocation) {
+        self.relocations.push(relocation);
+    }
+}
+
+/// Adds the sections of DWARF to the object. The symbols of the relocations are the positions of
+/// the functions in the list.
+pub fn write(
+    product: &mut ObjectProduct,
+    endian: RunTimeEndian,
+    functions: &[Lines],
+    locations: &Locations,
+    sources: &Sources,
+) -> Result<(), String> {
+    let encoding = gimli::Encoding {
+        format: gimli::Format::Dwarf32,
+        version: 4,
+        address_size: 8,
+    };
+
+    let name = sources
+        .iter()
+        .next()
+        .map(|(_, source)| source.path.clone())
+        .unwrap_or_default();
+
+    let mut program = LineProgram::new(
+        encoding,
+        Default::default(),
+        LineString::String(Vec::new()),
+        LineString::String(name.clone().into_bytes()),
+        None,
+    );
+
+    let directory = program.default_directory();
+    let mut files = HashMap::new();
+    let mut symbols = Vec::new();
+    let mut ranges = Vec::new();
+
+    for lines in functions.iter().filter(|x| x.size > 0) {
+        let symbol = Address::Symbol {
+            symbol: symbols.len(),
+            addend: 0,
+        };
+
+        symbols.push(product.function_symbol(lines.function));
+
+        program.begin_sequence(Some(symbol));
+
+        for (offset, location) in &lines.ranges {
+            let Some(span) = locations.span(*location) else {
+                continue;
+            };
+
+            let Some((source, position)) = sources.locate(span, Encoding::Utf8) else {
+                continue;
+            };
+
+            let file = *files.entry(span.file).or_insert_with(|| {
+                let path = LineString::String(source.path.clone().into_bytes());
+                program.add_file(path, directory, None)
+            });
+
+            let row = program.row();
+            row.address_offset = *offset as u64;
+            row.file = file;
+            row.line = position.line as u64 + 1;
+            row.column = position.column as u64 + 1;
+
+            program.generate_row();
+        }
+
+        program.end_sequence(lines.size as u64);
+
+        ranges.push(Range::StartLength {
+            begin: symbol,
+            length: lines.size as u64,
+        });
+    }
+
+    let mut dwarf = DwarfUnit::new(encoding);
+    dwarf.unit.line_program = program;
+
+    let ranges = dwarf.unit.ranges.add(RangeList(ranges));
+    let root = dwarf.unit.root();
+    let entry = dwarf.unit.get_mut(root);
+
+    entry.set(
+        gimli::DW_AT_producer,
+        AttributeValue::String(b"vulpi".to_vec()),
+    );
+    entry.set(gimli::DW_AT_name, AttributeValue::String(name.into_bytes()));
+    entry.set(
+        gimli::DW_AT_low_pc,
+        AttributeValue::Address(Address::Constant(0)),
+    );
+    entry.set(gimli::DW_AT_ranges, AttributeValue::RangeListRef(ranges));
+
+    let mut sections = Sections::new(Section {
+        writer: EndianVec::new(endian),
+        relocations: Vec::new(),
+    });
+
+    dwarf.write(&mut sections).map_err(|err| err.to_string())?;
+
+    // The sections are added before the relocations, because they can point to each other.
+    let mut ids: HashMap<gimli::SectionId, SectionId> = HashMap::new();
+
+    sections.for_each(|id, section| -> Result<(), String> {
+        if section.writer.slice().is_empty() {
+            return Ok(());
+        }
+
+        let object = &mut product.object;
+        let segment = object.segment_name(StandardSegment::Debug).to_vec();
+        let index = object.add_section(segment, id.name().as_bytes().to_vec(), SectionKind::Debug);
+        object.set_section_data(index, section.writer.slice().to_vec(), 1);
+        ids.insert(id, index);
+
+        Ok(())
+    })?;
+
+    sections.for_each(|id, section| -> Result<(), String> {
+        let Some(index) = ids.get(&id).copied() else {
+            return Ok(());
+        };
+
+        for relocation in &section.relocations {
+            // Offsets into sections that are empty are zero, like the value that is written.
+            let symbol: SymbolId = match relocation.target {
+                RelocationTarget::Symbol(symbol) => symbols[symbol],
+                RelocationTarget::Section(target) => match ids.get(&target) {
+                    Some(target) => product.object.section_symbol(*target),
+                    None => continue,
+                },
+            };
+
+            let flags = RelocationFlags::Generic {
+                kind: RelocationKind::Absolute,
+                encoding: RelocationEncoding::Generic,
+                size: relocation.size * 8,
+            };
+
+            let relocation = write::Relocation {
+                offset: relocation.offset as u64,
+                symbol,
+                addend: relocation.addend,
+                flags,
+            };
+
+            product
+                .object
+                .add_relocation(index, relocation)
+                .map_err(|err| err.to_string())?;
+        }
+
+        Ok(())
+    })
+}
@@ -0,0 +1,83 @@
+//! Errors that can occur while compiling to native code.
+
+use vulpi_location::Span;
+use vulpi_report::{IntoDiagnostic, Severity, Text};
+use vulpi_syntax::r#abstract::Qualified;
+
+pub enum NativeErrorKind {
+    /// A feature of the language that the native backend cannot compile yet.
+    Unsupported(&'static str),
+
+    /// A primitive used with a type that it has no native implementation for.
+    UnsupportedType(&'static str, String),
+
+    MissingEntry(Qualified),
+
+    /// An external with a calling convention that the native backend doesn't know.
+    UnknownConvention(String),
+
+    /// A C function that is declared with signatures that don't agree, because it's used by
+    /// externals with different types.
+    ForeignSignature(String),
+}
+
+pub struct NativeError {
+    pub span: Span,
+    pub kind: NativeErrorKind,
+}
+
+impl IntoDiagnostic for NativeError {
+    fn message(&self) -> Text {
+        match &self.kind {
+            NativeErrorKind::Unsupported(feature) => Text::from(format!(
+                "{} are not supported by the native backend",
+                feature
+            )),
+            NativeErrorKind::UnsupportedType(operation, typ) => Text::from(format!(
+                "{} of values of type '{}' is not supported by the native backend",
+                operation, typ
+            )),
+            NativeErrorKind::MissingEntry(name) => Text::from(format!(
+                "cannot find the entry point '{}'",
+                name.to_string()
+            )),
+            NativeErrorKind::UnknownConvention(convention) => {
+                Text::from(format!("unknown calling convention '{}'", convention))
+            }
+            NativeErrorKind::ForeignSignature(binding) => Text::from(format!(
+                "the C function '{}' is used with different types",
+                binding
+            )),
+        }
+    }
+
+    fn hint(&self) -> Option<Text> {
+        match &self.kind {
+            NativeErrorKind::Unsupported(_) | NativeErrorKind::UnsupportedType(_, _) => Some(
+                Text::from("use `vulpi run` to run the program in the virtual machine"),
+            ),
+            NativeErrorKind::UnknownConvention(_) => {
+                Some(Text::from("the only calling convention is \"c\""))
+            }
+            NativeErrorKind::MissingEntry(_) | NativeErrorKind::ForeignSignature(_) => None,
+        }
+    }
+
+    fn code(&self) -> Option<usize> {
+        match &self.kind {
+            NativeErrorKind::Unsupported(_) => Some(600),
+            NativeErrorKind::UnsupportedType(_, _) => Some(601),
+            NativeErrorKind::MissingEntry(_) => Some(602),
+            NativeErrorKind::UnknownConvention(_) => Some(603),
+            NativeErrorKind::ForeignSignature(_) => Some(604),
+        }
+    }
+
+    fn severity(&self) -> Severity {
+        Severity::Error
+    }
+
+    fn location(&self) -> Span {
+        self.span.clone()
+    }
+}
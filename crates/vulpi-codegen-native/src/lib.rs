@@ -0,0 +1,8 @@
+//! A native backend that compiles the core language to machine code with Cranelift. The whole
+//! program is compiled into a single object file that is linked with the runtime of
+//! [vulpi_runtime] to produce an executable.
+
+pub mod codegen;
+pub mod debug;
+pub mod errors;
+pub mod link;
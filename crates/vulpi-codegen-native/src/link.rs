@@ -0,0 +1,34 @@
+//! Linking of the object files into executables. The runtime is compiled together with the object
+//! by the C compiler of the system, that is `cc` or the one in the `CC` environment variable.
+
+use std::{fs, io, path::Path, process::Command};
+
+/// Writes the object and the runtime next to the output and links them into an executable.
+pub fn link(object: &[u8], output: &Path) -> io::Result<()> {
+    let object_path = output.with_extension("o");
+    let runtime_path = output.with_extension("runtime");
+
+    fs::write(&object_path, object)?;
+    let sources = vulpi_runtime::write(&runtime_path)?;
+
+    let compiler = std::env::var("CC").unwrap_or_else(|_| "cc".to_string());
+
+    let status = Command::new(compiler)
+        .arg(&object_path)
+        .args(&sources)
+        .arg("-lm")
+        .arg("-o")
+        .arg(output)
+        .status()?;
+
+    fs::remove_dir_all(&runtime_path)?;
+
+    if status.success() {
+        Ok(())
+    } else {
+        Err(io::Error::other(format!(
+            "the linker failed with {}",
+            status
+        )))
+    }
+}
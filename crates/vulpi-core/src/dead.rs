@@ -0,0 +1,186 @@
+//! Dead code elimination. The let declarations that are reachable from the entry point and from
+//! the public declarations are kept, together with the externals, types and effect operations
+//! that they use. Everything else is removed before the program reaches the backends.
+
+use std::collections::{HashMap, HashSet};
+
+use vulpi_report::{Diagnostic, Report};
+use vulpi_syntax::r#abstract::{Qualified, Visibility};
+
+use crate::{
+    errors::{CoreError, CoreErrorKind, UNUSED},
+    syntax::*,
+};
+
+/// The names that are used by the reachable let declarations.
+#[derive(Default)]
+struct Uses {
+    functions: HashSet<Qualified>,
+    constructors: HashSet<Qualified>,
+    operations: HashSet<Qualified>,
+    pending: Vec<Qualified>,
+}
+
+impl Uses {
+    fn atom(&mut self, atom: &Atom) {
+        if let Atom::Function(name, _) = atom {
+            if self.functions.insert(name.clone()) {
+                self.pending.push(name.clone());
+            }
+        }
+    }
+
+    fn value(&mut self, value: &Value) {
+        match value {
+            Value::Constructor(name, _) => {
+                self.constructors.insert(name.clone());
+            }
+            Value::Perform(_, name, _, _) => {
+                self.operations.insert(name.clone());
+            }
+            Value::Handle(_, clauses, _, _) => {
+                for (name, _, _) in clauses {
+                    self.operations.insert(name.clone());
+                }
+            }
+            Value::Lambda(_, body) => {
+                self.term(body);
+                return;
+            }
+            _ => (),
+        }
+
+        visit_value(value, &mut |atom| self.atom(atom));
+    }
+
+    fn term(&mut self, term: &TermKind) {
+        match term {
+            TermKind::Let(_, value, rest) => {
+                self.value(value);
+                self.term(rest);
+            }
+            TermKind::Join(_, _, body, rest) => {
+                self.term(body);
+                self.term(rest);
+            }
+            TermKind::Match(atom, alts, default) => {
+                self.atom(atom);
+
+                for alt in alts {
+                    if let Case::Constructor(name) = &alt.case {
+                        self.constructors.insert(name.clone());
+                    }
+
+                    self.term(&alt.body);
+                }
+
+                if let Some(default) = default {
+                    self.term(default);
+                }
+            }
+            TermKind::Jump(_, args) => args.iter().for_each(|x| self.atom(x)),
+            TermKind::Tail(func, args, _) => {
+                self.atom(func);
+                args.iter().for_each(|x| self.atom(x));
+            }
+            TermKind::Return(atom) => self.atom(atom),
+            TermKind::Unreachable => (),
+        }
+    }
+}
+
+/// The names of the types that occur in the type.
+fn mentions(typ: &TypeKind, names: &mut HashSet<Qualified>) {
+    match typ {
+        TypeKind::Constructor(name) => {
+            names.insert(name.clone());
+        }
+        TypeKind::Arrow(param, body) => {
+            mentions(param, names);
+            mentions(body, names);
+        }
+        TypeKind::Tuple(types) => types.iter().for_each(|x| mentions(x, names)),
+        TypeKind::Application(func, args) => {
+            mentions(func, names);
+            args.iter().for_each(|x| mentions(x, names));
+        }
+        TypeKind::Forall(_, body) => mentions(body, names),
+        TypeKind::Bound(_) | TypeKind::Unknown => (),
+    }
+}
+
+/// Removes the let declarations that cannot be reached from the entry point or from the public
+/// declarations, and the externals, types and effect operations that only they used. Returns the
+/// let declarations that were removed.
+pub fn eliminate(program: &mut Program, entry: Option<&Qualified>) -> Vec<LetDecl> {
+    let lets: HashMap<_, _> = program.lets.iter().map(|x| (x.name.clone(), x)).collect();
+    let mut uses = Uses::default();
+
+    for decl in &program.lets {
+        if decl.visibility == Visibility::Public || Some(&decl.name) == entry {
+            uses.atom(&Atom::Function(decl.name.clone(), vec![]));
+        }
+    }
+
+    while let Some(name) = uses.pending.pop() {
+        if let Some(decl) = lets.get(&name) {
+            uses.term(&decl.body);
+        }
+    }
+
+    let (lets, removed) = std::mem::take(&mut program.lets)
+        .into_iter()
+        .partition(|x| uses.functions.contains(&x.name));
+
+    program.lets = lets;
+    program
+        .externals
+        .retain(|x| uses.functions.contains(&x.name));
+
+    // Values of a type can be created by externals too, so the types that they mention are kept
+    // even if none of their constructors is used. A type is kept or removed as a whole because
+    // the backends number the constructors by their position in it.
+    let mut mentioned = HashSet::new();
+
+    for external in &program.externals {
+        mentions(&external.typ, &mut mentioned);
+    }
+
+    program.types.retain(|typ| {
+        typ.constructors.is_empty()
+            || mentioned.contains(&typ.name)
+            || typ
+                .constructors
+                .iter()
+                .any(|(name, _)| uses.constructors.contains(name))
+    });
+
+    for effect in &mut program.effects {
+        effect
+            .operations
+            .retain(|(name, _, _)| uses.operations.contains(name));
+    }
+
+    removed
+}
+
+/// Reports a warning for each private let declaration that was removed because it's never used.
+pub fn report_unused(reporter: Report, removed: &[LetDecl]) {
+    for decl in removed {
+        // The default implementations of operations are named after them and the values of the
+        // declarations of patterns after their positions. They are not written as let
+        // declarations, so they are not reported.
+        if decl.name.name.get().contains(['.', '\'']) {
+            continue;
+        }
+
+        if decl.allow.iter().any(|x| x.get() == UNUSED) {
+            continue;
+        }
+
+        reporter.report(Diagnostic::new(CoreError {
+            span: decl.span.clone(),
+            kind: CoreErrorKind::Unused(decl.name.clone(), decl.declaration.clone()),
+        }));
+    }
+}
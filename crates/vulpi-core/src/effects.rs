@@ -0,0 +1,1310 @@
+//! Elaboration of the effects for the backends that don't run handlers by themselves. It removes
+//! [Value::Perform], [Value::Handle] and [Value::Mask] from the program, so the native and the
+//! JavaScript backends only see calls, closures and matches.
+//!
+//! Operations are yielded like in the evidence passing of Koka: a perform stores the request of
+//! the operation in a global cell and returns, and every call that can reach a perform checks the
+//! cell after it. While there's a request, the callers add the rest of their computation to its
+//! continuation and return too, until a handler of the operation takes it and calls its clause
+//! with the continuation. The code that doesn't perform runs in direct style, and the calls that
+//! cannot reach a perform are not checked at all.
+//!
+//! The rest of a computation after a call is lifted into a let declaration that receives the
+//! variables that it uses, and the join points that it jumps to are lifted in the same way, so the
+//! continuations are closures of them. The lifted rest only runs when a continuation is resumed,
+//! so the body of the function keeps the rest inline. A call that yields returns a closure that
+//! adds the arguments that it's applied to to the continuation, so the calls with more arguments
+//! than their function receives keep them.
+//!
+//! Handlers are deep, so resuming a continuation installs the handler around it again. A request
+//! skips one handler of its operation for each mask of it that it goes through, and a `finally`
+//! runs when its handler gives its result or, if the continuation of an outer `ctl` clause that
+//! has it is never resumed, when that clause returns. The operations of `Prelude.Async` have no
+//! scheduler here, so a request that no handler takes stops the program like in the virtual
+//! machine.
+
+use std::collections::{HashMap, HashSet};
+
+use vulpi_intern::Symbol;
+use vulpi_location::Span;
+use vulpi_syntax::{
+    elaborated::LiteralKind,
+    r#abstract::{OperationKind, Qualified, Visibility},
+};
+
+use crate::syntax::*;
+
+/// The positions of the fields of a request in the constructor `Effect$.Yielding`.
+const OPERATION: usize = 0;
+const INSTANCE: usize = 1;
+const SKIP: usize = 2;
+const APPLY: usize = 3;
+const CONT: usize = 4;
+const FINALS: usize = 5;
+const CLEANUP: usize = 6;
+const MESSAGE: usize = 7;
+
+/// The declarations that the elaborated program uses to yield and to handle the requests.
+struct Runtime {
+    state: Qualified,
+    running: Qualified,
+    yielding: Qualified,
+
+    /// The cell with the request that is yielded, or `Effect$.Running` if there's none.
+    cell: Qualified,
+
+    /// The cell with the last instance of a handler that was installed.
+    counter: Qualified,
+
+    ref_new: Qualified,
+    ref_get: Qualified,
+    ref_set: Qualified,
+    add: Qualified,
+    sub: Qualified,
+    print_error: Qualified,
+
+    bind: Qualified,
+    compose: Qualified,
+    pending: Qualified,
+    perform: Qualified,
+    fresh: Qualified,
+    then: Qualified,
+    both: Qualified,
+    unwind: Qualified,
+    protect: Qualified,
+}
+
+fn runtime(name: &str) -> Qualified {
+    Qualified {
+        path: Symbol::intern("Effect$"),
+        name: Symbol::intern(name),
+    }
+}
+
+impl Runtime {
+    fn new() -> Self {
+        Self {
+            state: runtime("State"),
+            running: runtime("Running"),
+            yielding: runtime("Yielding"),
+            cell: runtime("cell"),
+            counter: runtime("counter"),
+            ref_new: runtime("refNew"),
+            ref_get: runtime("refGet"),
+            ref_set: runtime("refSet"),
+            add: runtime("add"),
+            sub: runtime("sub"),
+            print_error: runtime("printError"),
+            bind: runtime("bind"),
+            compose: runtime("compose"),
+            pending: runtime("pending"),
+            perform: runtime("perform"),
+            fresh: runtime("fresh"),
+            then: runtime("then"),
+            both: runtime("both"),
+            unwind: runtime("unwind"),
+            protect: runtime("protect"),
+        }
+    }
+}
+
+fn prelude(name: &str) -> Type {
+    Box::new(TypeKind::Constructor(Qualified {
+        path: Symbol::intern("Prelude"),
+        name: Symbol::intern(name),
+    }))
+}
+
+fn unit_type() -> Type {
+    Box::new(TypeKind::Tuple(vec![]))
+}
+
+fn arrow(params: &[Type], ret: Type) -> Type {
+    params.iter().rev().fold(ret, |body, param| {
+        Box::new(TypeKind::Arrow(param.clone(), body))
+    })
+}
+
+fn int(value: usize) -> Atom {
+    Atom::Literal(Box::new(LiteralKind::Integer(Symbol::intern(
+        &value.to_string(),
+    ))))
+}
+
+fn zero() -> Case {
+    Case::Literal(Box::new(LiteralKind::Integer(Symbol::intern("0"))))
+}
+
+fn unit() -> Atom {
+    Atom::Literal(Box::new(LiteralKind::Unit))
+}
+
+fn var(binder: &Binder) -> Atom {
+    Atom::Variable(binder.name.clone())
+}
+
+fn function(name: &Qualified) -> Atom {
+    Atom::Function(name.clone(), vec![])
+}
+
+fn define(binder: Binder, value: Value, rest: Term) -> Term {
+    Box::new(TermKind::Let(binder, value, rest))
+}
+
+fn returns(atom: Atom) -> Term {
+    Box::new(TermKind::Return(atom))
+}
+
+fn jump(label: &Symbol, args: Vec<Atom>) -> Term {
+    Box::new(TermKind::Jump(label.clone(), args))
+}
+
+fn join(label: &Symbol, params: Vec<Binder>, body: Term, rest: Term) -> Term {
+    Box::new(TermKind::Join(label.clone(), params, body, rest))
+}
+
+/// Matches the atom against zero.
+fn if_zero(atom: Atom, then: Term, otherwise: Term) -> Term {
+    let alt = Alt {
+        case: zero(),
+        binders: vec![],
+        body: then,
+    };
+
+    Box::new(TermKind::Match(atom, vec![alt], Some(otherwise)))
+}
+
+/// Checks if the term has an effect that this pass removes, without looking inside of lambdas.
+fn has_effects(term: &TermKind) -> bool {
+    match term {
+        TermKind::Let(_, Value::Perform(..) | Value::Handle(..) | Value::Mask(..), _) => true,
+        TermKind::Let(_, Value::Lambda(_, body), rest) => has_effects(body) || has_effects(rest),
+        TermKind::Let(_, _, rest) => has_effects(rest),
+        TermKind::Join(_, _, body, rest) => has_effects(body) || has_effects(rest),
+        TermKind::Match(_, alts, default) => {
+            alts.iter().any(|alt| has_effects(&alt.body))
+                || default.as_ref().is_some_and(|x| has_effects(x))
+        }
+        TermKind::Jump(..) | TermKind::Tail(..) | TermKind::Return(_) | TermKind::Unreachable => {
+            false
+        }
+    }
+}
+
+/// Collects the labels that the term jumps to and the ones of the join points inside of it.
+fn labels(term: &TermKind, jumps: &mut Vec<Symbol>, defined: &mut HashSet<Symbol>) {
+    match term {
+        TermKind::Let(_, _, rest) => labels(rest, jumps, defined),
+        TermKind::Join(label, _, body, rest) => {
+            defined.insert(label.clone());
+            labels(body, jumps, defined);
+            labels(rest, jumps, defined);
+        }
+        TermKind::Jump(label, _) => jumps.push(label.clone()),
+        TermKind::Match(_, alts, default) => {
+            for alt in alts {
+                labels(&alt.body, jumps, defined);
+            }
+
+            if let Some(default) = default {
+                labels(default, jumps, defined);
+            }
+        }
+        TermKind::Tail(..) | TermKind::Return(_) | TermKind::Unreachable => (),
+    }
+}
+
+struct Elaborator {
+    runtime: Runtime,
+    names: usize,
+
+    /// The types of the binders of the program and the number of parameters of the variables
+    /// that are bound to lambdas, before the thunks get a parameter.
+    types: HashMap<Symbol, Type>,
+    lambdas: HashMap<Symbol, usize>,
+
+    /// The number of parameters of the let declarations and of the externals.
+    arities: HashMap<Qualified, usize>,
+    externals: HashSet<Qualified>,
+
+    /// The let declarations whose calls can reach a perform.
+    yields: HashSet<Qualified>,
+
+    /// The tags of the operations and their kinds.
+    operations: HashMap<Qualified, (usize, OperationKind)>,
+
+    /// The lifted join points and the rests of the computations after the calls, with the
+    /// variables that they receive before their own parameters. The rests are found by their
+    /// addresses in the program that is elaborated, that doesn't change during the pass.
+    joins: HashMap<Symbol, (Qualified, Vec<Symbol>)>,
+    rests: HashMap<*const TermKind, (Qualified, Vec<Symbol>)>,
+
+    lifted: Vec<LetDecl>,
+
+    /// The declaration that is elaborated, whose module and spans the lifted declarations get.
+    path: Symbol,
+    span: Span,
+    declaration: Span,
+}
+
+impl Elaborator {
+    fn fresh(&mut self, name: &str) -> Symbol {
+        let id = self.names;
+        self.names += 1;
+        Symbol::intern(&format!("{}${}", name, id))
+    }
+
+    fn binder(&mut self, name: &str, typ: Type) -> Binder {
+        Binder {
+            name: self.fresh(name),
+            typ,
+        }
+    }
+
+    fn binders(&self, names: &[Symbol]) -> Vec<Binder> {
+        names
+            .iter()
+            .map(|name| Binder {
+                name: name.clone(),
+                typ: self
+                    .types
+                    .get(name)
+                    .cloned()
+                    .unwrap_or_else(TypeKind::unknown),
+            })
+            .collect()
+    }
+
+    fn lifted_name(&mut self, name: &str) -> Qualified {
+        Qualified {
+            path: self.path.clone(),
+            name: self.fresh(name),
+        }
+    }
+
+    fn declare(&mut self, name: Qualified, params: Vec<Binder>, body: Term) {
+        let types: Vec<_> = params.iter().map(|x| x.typ.clone()).collect();
+
+        self.lifted.push(LetDecl {
+            name,
+            span: self.span.clone(),
+            visibility: Visibility::Private,
+            typ: arrow(&types, TypeKind::unknown()),
+            params,
+            body,
+            declaration: self.declaration.clone(),
+            allow: vec![],
+        });
+    }
+
+    fn call(&self, func: &Qualified, args: Vec<Atom>) -> Value {
+        Value::Application(function(func), args, self.span.clone())
+    }
+
+    fn apply(&self, func: Atom, args: Vec<Atom>) -> Value {
+        Value::Application(func, args, self.span.clone())
+    }
+
+    fn tail(&self, func: Atom, args: Vec<Atom>) -> Term {
+        Box::new(TermKind::Tail(func, args, self.span.clone()))
+    }
+
+    fn operation(&mut self, name: &Qualified) -> (usize, OperationKind) {
+        let tag = self.operations.len();
+
+        *self
+            .operations
+            .entry(name.clone())
+            .or_insert((tag, OperationKind::Fun))
+    }
+
+    /// Checks if calling the atom with the number of arguments can reach a perform. Calls with
+    /// more arguments than the function receives call the closure that it gives too.
+    fn callee_yields(&self, func: &Atom, args: usize) -> bool {
+        match func {
+            _ if args == 0 => false,
+            Atom::Variable(_) => true,
+            Atom::Function(name, _) => match self.arities.get(name) {
+                Some(arity) if args < *arity => false,
+                Some(arity) if args == *arity => {
+                    !self.externals.contains(name) && self.yields.contains(name)
+                }
+                _ => true,
+            },
+            Atom::Literal(_) => false,
+        }
+    }
+
+    fn value_yields(&self, value: &Value) -> bool {
+        match value {
+            Value::Perform(..) | Value::Handle(..) | Value::Mask(..) => true,
+            Value::Application(func, args, _) => self.callee_yields(func, args.len()),
+            _ => false,
+        }
+    }
+
+    /// Checks if the term has a call that can reach a perform, without looking inside of lambdas.
+    /// The calls in tail position only count with `tails`, because their requests are checked by
+    /// the caller.
+    fn term_yields(&self, term: &TermKind, tails: bool) -> bool {
+        match term {
+            TermKind::Let(_, value, rest) => {
+                self.value_yields(value) || self.term_yields(rest, tails)
+            }
+            TermKind::Join(_, _, body, rest) => {
+                self.term_yields(body, tails) || self.term_yields(rest, tails)
+            }
+            TermKind::Tail(func, args, _) => tails && self.callee_yields(func, args.len()),
+            TermKind::Match(_, alts, default) => {
+                alts.iter().any(|alt| self.term_yields(&alt.body, tails))
+                    || default.as_ref().is_some_and(|x| self.term_yields(x, tails))
+            }
+            TermKind::Jump(..) | TermKind::Return(_) | TermKind::Unreachable => false,
+        }
+    }
+
+    /// The variables that the term uses and that are not bound inside of it or by the parameters,
+    /// including the ones that the lifted join points that it jumps to receive.
+    fn free(&self, params: &[Binder], term: &TermKind) -> Vec<Symbol> {
+        let mut free = free_variables(params, term);
+
+        let mut jumps = Vec::new();
+        let mut defined = HashSet::new();
+        labels(term, &mut jumps, &mut defined);
+
+        for label in jumps.iter().filter(|x| !defined.contains(*x)) {
+            let Some((_, variables)) = self.joins.get(label) else {
+                continue;
+            };
+
+            for variable in variables {
+                if !free.contains(variable) && params.iter().all(|x| &x.name != variable) {
+                    free.push(variable.clone());
+                }
+            }
+        }
+
+        free
+    }
+
+    /// Matches on the cell of the requests, running the first term with the fields of the request
+    /// if there's one.
+    fn state(
+        &mut self,
+        yielding: impl FnOnce(&mut Self, &[Binder]) -> Term,
+        otherwise: Term,
+    ) -> Term {
+        let state = self.binder("s", TypeKind::unknown());
+
+        let types = [
+            prelude("Int"),
+            prelude("Int"),
+            prelude("Int"),
+            TypeKind::unknown(),
+            TypeKind::unknown(),
+            prelude("Int"),
+            TypeKind::unknown(),
+            prelude("String"),
+        ];
+
+        let fields: Vec<_> = types.into_iter().map(|typ| self.binder("f", typ)).collect();
+
+        let body = yielding(self, &fields);
+
+        let alt = Alt {
+            case: Case::Constructor(self.runtime.yielding.clone()),
+            binders: fields,
+            body,
+        };
+
+        let value = self.call(&self.runtime.ref_get, vec![function(&self.runtime.cell)]);
+        let scrutinee = var(&state);
+
+        define(
+            state,
+            value,
+            Box::new(TermKind::Match(scrutinee, vec![alt], Some(otherwise))),
+        )
+    }
+
+    /// A request with the fields of another one, except for the changed ones.
+    fn request(&self, fields: &[Binder], changes: &[(usize, Atom)]) -> Value {
+        let atoms = fields
+            .iter()
+            .enumerate()
+            .map(|(i, field)| match changes.iter().find(|(at, _)| *at == i) {
+                Some((_, atom)) => atom.clone(),
+                None => var(field),
+            })
+            .collect();
+
+        Value::Constructor(self.runtime.yielding.clone(), atoms)
+    }
+
+    /// Stores the state in the cell of the requests before the rest.
+    fn store(&mut self, state: Value, rest: Term) -> Term {
+        let binder = self.binder("s", TypeKind::unknown());
+        let ignored = self.binder("u", unit_type());
+        let value = self.call(
+            &self.runtime.ref_set,
+            vec![function(&self.runtime.cell), var(&binder)],
+        );
+
+        define(binder, state, define(ignored, value, rest))
+    }
+
+    /// Calls the value and runs the term after it with the result. If the call yields, the term
+    /// becomes part of the continuation of the request instead.
+    fn sequence(&mut self, call: Value, after: impl Fn(&mut Self, Atom) -> Term) -> Term {
+        let result = self.binder("x", TypeKind::unknown());
+        let later = self.binder("y", TypeKind::unknown());
+
+        let now = after(self, var(&result));
+        let resume = after(self, var(&later));
+
+        let cont = self.binder("k", TypeKind::unknown());
+        let ignored = self.binder("u", unit_type());
+        let value = self.call(&self.runtime.bind, vec![var(&cont)]);
+
+        let yielded = define(
+            cont,
+            Value::Lambda(vec![later], resume),
+            define(ignored, value, returns(var(&result))),
+        );
+
+        let checked = self.state(|_, _| yielded, now);
+        define(result, call, checked)
+    }
+
+    /// A continuation that resumes the one of the request inside of the function again, that gets
+    /// the arguments followed by a thunk of the resumed continuation.
+    fn reinstall(&mut self, cont: &Binder, func: &Qualified, args: Vec<Atom>) -> (Binder, Value) {
+        let param = self.binder("y", TypeKind::unknown());
+        let ignored = self.binder("u", unit_type());
+        let thunk = self.binder("t", TypeKind::unknown());
+
+        let resumed = Value::Lambda(vec![ignored], self.tail(var(cont), vec![var(&param)]));
+
+        let mut args = args;
+        args.push(var(&thunk));
+
+        let body = define(thunk, resumed, self.tail(function(func), args));
+        let binder = self.binder("r", TypeKind::unknown());
+
+        (binder, Value::Lambda(vec![param], body))
+    }
+
+    /// Elaborates a term. Inside of a lifted rest, the rests after the calls are jumped to instead
+    /// of kept inline, so the code of each rest is not copied into the ones before it.
+    fn term(&mut self, term: &TermKind, lifted: bool) -> Term {
+        match term {
+            TermKind::Let(binder, Value::Lambda(params, body), rest) => {
+                let mut params = params.clone();
+
+                // The thunks get a unit, because the backends call closures with arguments.
+                if params.is_empty() {
+                    params.push(self.binder("u", unit_type()));
+                }
+
+                let body = self.term(body, false);
+                let rest = self.term(rest, lifted);
+                define(binder.clone(), Value::Lambda(params, body), rest)
+            }
+            TermKind::Let(binder, value, rest) if self.value_yields(value) => {
+                let mut before = Vec::new();
+                let call = self.effect(value, &mut before);
+                let split = self.split(binder, call, rest, lifted);
+
+                before
+                    .into_iter()
+                    .rev()
+                    .fold(split, |rest, (binder, value)| define(binder, value, rest))
+            }
+            TermKind::Let(binder, value, rest) => {
+                define(binder.clone(), value.clone(), self.term(rest, lifted))
+            }
+            TermKind::Join(label, params, body, rest)
+                if self.term_yields(body, false) || self.term_yields(rest, false) =>
+            {
+                if !self.joins.contains_key(label) {
+                    let free = self.free(params, body);
+                    let name = self.lifted_name("join");
+                    self.joins
+                        .insert(label.clone(), (name.clone(), free.clone()));
+
+                    let body = self.term(body, false);
+                    let mut all = self.binders(&free);
+                    all.extend(params.iter().cloned());
+                    self.declare(name, all, body);
+                }
+
+                self.term(rest, lifted)
+            }
+            TermKind::Join(label, params, body, rest) => join(
+                label,
+                params.clone(),
+                self.term(body, lifted),
+                self.term(rest, lifted),
+            ),
+            TermKind::Jump(label, args) => match self.joins.get(label) {
+                Some((name, free)) => {
+                    let mut all: Vec<_> = free.iter().map(|x| Atom::Variable(x.clone())).collect();
+                    all.extend(args.iter().cloned());
+                    self.tail(function(name), all)
+                }
+                None => Box::new(term.clone()),
+            },
+            TermKind::Match(atom, alts, default) => {
+                let alts = alts
+                    .iter()
+                    .map(|alt| Alt {
+                        case: alt.case.clone(),
+                        binders: alt.binders.clone(),
+                        body: self.term(&alt.body, lifted),
+                    })
+                    .collect();
+
+                let default = default.as_ref().map(|x| self.term(x, lifted));
+                Box::new(TermKind::Match(atom.clone(), alts, default))
+            }
+            TermKind::Tail(..) | TermKind::Return(_) | TermKind::Unreachable => {
+                Box::new(term.clone())
+            }
+        }
+    }
+
+    /// Binds the result of a call that can yield and checks the cell of the requests after it.
+    fn split(&mut self, binder: &Binder, call: Value, rest: &TermKind, lifted: bool) -> Term {
+        let (name, free) = self.lift(binder, rest);
+        let mut args: Vec<_> = free.iter().map(|x| Atom::Variable(x.clone())).collect();
+
+        let now = if lifted {
+            let mut args = args.clone();
+            args.push(var(binder));
+            self.tail(function(&name), args)
+        } else {
+            self.term(rest, false)
+        };
+
+        let param = self.binder("y", binder.typ.clone());
+        args.push(var(&param));
+
+        let cont = self.binder("k", TypeKind::unknown());
+        let resume = Value::Lambda(vec![param], self.tail(function(&name), args));
+        let ignored = self.binder("u", unit_type());
+        let value = self.call(&self.runtime.bind, vec![var(&cont)]);
+
+        let yielded = define(cont, resume, define(ignored, value, returns(var(binder))));
+
+        let checked = self.state(|_, _| yielded, now);
+        define(binder.clone(), call, checked)
+    }
+
+    /// The let declaration with the rest of the computation after a call, that receives the
+    /// variables that the rest uses and then the result of the call.
+    fn lift(&mut self, binder: &Binder, rest: &TermKind) -> (Qualified, Vec<Symbol>) {
+        let key = rest as *const TermKind;
+
+        if let Some(found) = self.rests.get(&key) {
+            return found.clone();
+        }
+
+        let free = self.free(std::slice::from_ref(binder), rest);
+        let name = self.lifted_name("rest");
+        self.rests.insert(key, (name.clone(), free.clone()));
+
+        let body = self.term(rest, true);
+        let mut params = self.binders(&free);
+        params.push(binder.clone());
+        self.declare(name.clone(), params, body);
+
+        (name, free)
+    }
+
+    /// Turns an effect into a call of the runtime, binding the closures that it receives before.
+    fn effect(&mut self, value: &Value, before: &mut Vec<(Binder, Value)>) -> Value {
+        match value {
+            Value::Perform(instance, operation, args, span) => {
+                let (tag, kind) = self.operation(operation);
+
+                let clause = self.binder("c", TypeKind::unknown());
+                let mut params = vec![clause.clone()];
+                let mut args = args.clone();
+
+                // The clauses of operations without arguments are thunks that get a unit too.
+                if args.is_empty() && kind == OperationKind::Fun {
+                    args.push(unit());
+                }
+
+                if kind == OperationKind::Ctl {
+                    let cont = self.binder("k", TypeKind::unknown());
+                    args.push(var(&cont));
+                    params.push(cont);
+                }
+
+                let apply = self.binder("a", TypeKind::unknown());
+                let body = self.tail(var(&clause), args);
+                before.push((apply.clone(), Value::Lambda(params, body)));
+
+                let message = format!("unhandled operation '{}'", operation.to_string());
+                let message =
+                    Atom::Literal(Box::new(LiteralKind::String(Symbol::intern(&message))));
+                let instance = instance.clone().unwrap_or_else(|| int(0));
+
+                Value::Application(
+                    function(&self.runtime.perform),
+                    vec![int(tag), instance, var(&apply), message],
+                    span.clone(),
+                )
+            }
+            Value::Handle(thunk, clauses, ret, finally) => {
+                let id = self.binder("i", prelude("Int"));
+                before.push((id.clone(), self.call(&self.runtime.fresh, vec![unit()])));
+
+                // A named handler gives its instance to the thunk.
+                let named = match thunk {
+                    Atom::Variable(name) => self.lambdas.get(name) == Some(&1),
+                    _ => false,
+                };
+
+                let ignored = self.binder("u", unit_type());
+                let arg = if named { var(&id) } else { unit() };
+                let run = self.binder("t", TypeKind::unknown());
+                let body = self.tail(thunk.clone(), vec![arg]);
+                before.push((run.clone(), Value::Lambda(vec![ignored], body)));
+
+                let handler = self.handler(clauses);
+
+                let mut args = vec![var(&id)];
+                args.extend(clauses.iter().map(|(_, _, clause)| clause.clone()));
+                args.push(ret.clone());
+                args.push(var(&run));
+
+                let Some(finally) = finally else {
+                    return self.call(&handler, args);
+                };
+
+                let ignored = self.binder("u", unit_type());
+                let handled = self.binder("h", TypeKind::unknown());
+                let body = self.tail(function(&handler), args);
+                before.push((handled.clone(), Value::Lambda(vec![ignored], body)));
+
+                self.call(&self.runtime.protect, vec![finally.clone(), var(&handled)])
+            }
+            Value::Mask(operations, thunk) => {
+                let ignored = self.binder("u", unit_type());
+                let run = self.binder("t", TypeKind::unknown());
+                let body = self.tail(thunk.clone(), vec![unit()]);
+                before.push((run.clone(), Value::Lambda(vec![ignored], body)));
+
+                let mask = self.mask(operations);
+                self.call(&mask, vec![var(&run)])
+            }
+            value => value.clone(),
+        }
+    }
+
+    /// The function of a handler with the clauses. It gets the instance, the clauses, the return
+    /// clause and the thunk that it runs, and takes the requests of its operations that are not
+    /// masked and that are not performed on another instance.
+    fn handler(&mut self, clauses: &[(Qualified, OperationKind, Atom)]) -> Qualified {
+        let name = runtime(&self.fresh("handler").get());
+
+        let id = self.binder("i", prelude("Int"));
+        let params: Vec<_> = clauses
+            .iter()
+            .map(|_| self.binder("c", TypeKind::unknown()))
+            .collect();
+        let ret = self.binder("r", TypeKind::unknown());
+        let run = self.binder("t", TypeKind::unknown());
+        let result = self.binder("x", TypeKind::unknown());
+
+        let mut prefix = vec![var(&id)];
+        prefix.extend(params.iter().map(var));
+        prefix.push(var(&ret));
+
+        let yielding = |this: &mut Self, fields: &[Binder]| {
+            let (resume, value) = this.reinstall(&fields[CONT], &name, prefix);
+
+            let bubble = this.fresh("bubble");
+            let request = this.request(fields, &[(CONT, var(&resume))]);
+            let bubbled = this.store(request, returns(var(&result)));
+
+            let mut alts = Vec::new();
+
+            for ((operation, kind, _), clause) in clauses.iter().zip(&params) {
+                let (tag, _) = this.operation(operation);
+
+                let take = this.fresh("take");
+                let taken = this.take(*kind, fields, clause, &resume);
+
+                // A request that is masked skips the handler.
+                let skip = this.binder("n", prelude("Int"));
+                let value = this.call(&this.runtime.sub, vec![var(&fields[SKIP]), int(1)]);
+                let request = this.request(fields, &[(CONT, var(&resume)), (SKIP, var(&skip))]);
+                let skipped = define(skip, value, this.store(request, returns(var(&result))));
+                let unmasked = if_zero(var(&fields[SKIP]), jump(&take, vec![]), skipped);
+
+                let difference = this.binder("d", prelude("Int"));
+                let value = this.call(&this.runtime.sub, vec![var(&fields[INSTANCE]), var(&id)]);
+                let compared =
+                    if_zero(var(&difference), jump(&take, vec![]), jump(&bubble, vec![]));
+                let instance = define(difference, value, compared);
+
+                let check = if_zero(var(&fields[INSTANCE]), unmasked, instance);
+
+                alts.push(Alt {
+                    case: Case::Literal(Box::new(LiteralKind::Integer(Symbol::intern(
+                        &tag.to_string(),
+                    )))),
+                    binders: vec![],
+                    body: join(&take, vec![], taken, check),
+                });
+            }
+
+            let dispatch = Box::new(TermKind::Match(
+                var(&fields[OPERATION]),
+                alts,
+                Some(jump(&bubble, vec![])),
+            ));
+
+            define(resume, value, join(&bubble, vec![], bubbled, dispatch))
+        };
+
+        let returned = self.tail(var(&ret), vec![var(&result)]);
+        let checked = self.state(yielding, returned);
+        let value = self.apply(var(&run), vec![unit()]);
+        let body = define(result, value, checked);
+
+        let mut all = vec![id];
+        all.extend(params);
+        all.push(ret);
+        all.push(run);
+        self.declare(name.clone(), all, body);
+
+        name
+    }
+
+    /// Takes the request, calling the clause with the continuation that resumes it. The clauses of
+    /// `fun` operations resume it with their results, and the `finally`s of a continuation that a
+    /// `ctl` clause doesn't resume run after the clause.
+    fn take(
+        &mut self,
+        kind: OperationKind,
+        fields: &[Binder],
+        clause: &Binder,
+        resume: &Binder,
+    ) -> Term {
+        let state = Value::Constructor(self.runtime.running.clone(), vec![]);
+        let apply = var(&fields[APPLY]);
+
+        let taken = match kind {
+            OperationKind::Fun => {
+                let call = self.apply(apply, vec![var(clause)]);
+                let resume = var(resume);
+                self.sequence(call, |this, value| this.tail(resume.clone(), vec![value]))
+            }
+            OperationKind::Ctl => {
+                let direct = self.tail(apply.clone(), vec![var(clause), var(resume)]);
+
+                let flag = self.binder("g", TypeKind::unknown());
+                let value = self.call(&self.runtime.ref_new, vec![int(0)]);
+
+                let param = self.binder("y", TypeKind::unknown());
+                let ignored = self.binder("u", unit_type());
+                let set = self.call(&self.runtime.ref_set, vec![var(&flag), int(1)]);
+                let body = define(ignored, set, self.tail(var(resume), vec![var(&param)]));
+                let cont = self.binder("k", TypeKind::unknown());
+
+                let call = self.apply(apply, vec![var(clause), var(&cont)]);
+                let unwind = function(&self.runtime.unwind);
+                let args = [var(&flag), var(&fields[CLEANUP])];
+                let called = self.sequence(call, |this, value| {
+                    let mut args = args.to_vec();
+                    args.push(value);
+                    this.tail(unwind.clone(), args)
+                });
+
+                let tracked = define(
+                    flag,
+                    value,
+                    define(cont, Value::Lambda(vec![param], body), called),
+                );
+
+                if_zero(var(&fields[FINALS]), direct, tracked)
+            }
+        };
+
+        self.store(state, taken)
+    }
+
+    /// The function of a mask of the operations, that gets the thunk that it runs.
+    fn mask(&mut self, operations: &[Qualified]) -> Qualified {
+        let name = runtime(&self.fresh("mask").get());
+
+        let run = self.binder("t", TypeKind::unknown());
+        let result = self.binder("x", TypeKind::unknown());
+
+        let yielding = |this: &mut Self, fields: &[Binder]| {
+            let (resume, value) = this.reinstall(&fields[CONT], &name, vec![]);
+
+            let set = this.fresh("set");
+            let skip = this.binder("n", prelude("Int"));
+            let request = this.request(fields, &[(CONT, var(&resume)), (SKIP, var(&skip))]);
+            let stored = this.store(request, returns(var(&result)));
+
+            let same = || jump(&set, vec![var(&fields[SKIP])]);
+            let mut alts = Vec::new();
+
+            for operation in operations {
+                let (tag, _) = this.operation(operation);
+                let more = this.binder("n", prelude("Int"));
+                let value = this.call(&this.runtime.add, vec![var(&fields[SKIP]), int(1)]);
+
+                alts.push(Alt {
+                    case: Case::Literal(Box::new(LiteralKind::Integer(Symbol::intern(
+                        &tag.to_string(),
+                    )))),
+                    binders: vec![],
+                    body: define(more.clone(), value, jump(&set, vec![var(&more)])),
+                });
+            }
+
+            // The masks don't change the handler of a request that is performed on an instance.
+            let masked = Box::new(TermKind::Match(var(&fields[OPERATION]), alts, Some(same())));
+            let check = if_zero(var(&fields[INSTANCE]), masked, same());
+
+            define(resume, value, join(&set, vec![skip], stored, check))
+        };
+
+        let checked = self.state(yielding, returns(var(&result)));
+        let value = self.apply(var(&run), vec![unit()]);
+        let body = define(result, value, checked);
+
+        self.declare(name.clone(), vec![run], body);
+        name
+    }
+}
+
+impl Elaborator {
+    /// Records the types of the binders of the term and the parameters of its lambdas.
+    fn collect(&mut self, term: &TermKind) {
+        let bind = |this: &mut Self, binders: &[Binder]| {
+            for binder in binders {
+                this.types.insert(binder.name.clone(), binder.typ.clone());
+            }
+        };
+
+        match term {
+            TermKind::Let(binder, value, rest) => {
+                bind(self, std::slice::from_ref(binder));
+
+                if let Value::Lambda(params, body) = value {
+                    self.lambdas.insert(binder.name.clone(), params.len());
+                    bind(self, params);
+                    self.collect(body);
+                }
+
+                self.collect(rest);
+            }
+            TermKind::Join(_, params, body, rest) => {
+                bind(self, params);
+                self.collect(body);
+                self.collect(rest);
+            }
+            TermKind::Match(_, alts, default) => {
+                for alt in alts {
+                    bind(self, &alt.binders);
+                    self.collect(&alt.body);
+                }
+
+                if let Some(default) = default {
+                    self.collect(default);
+                }
+            }
+            TermKind::Jump(..)
+            | TermKind::Tail(..)
+            | TermKind::Return(_)
+            | TermKind::Unreachable => {}
+        }
+    }
+
+    /// Adds the type of the requests, the externals and the functions of the runtime to the
+    /// program. The cells are returned, because they're initialized before the other values.
+    fn declare_runtime(&mut self, program: &mut Program) -> Vec<LetDecl> {
+        let rt = Runtime::new();
+        let int_type = prelude("Int");
+
+        program.types.push(TypeDecl {
+            name: rt.state.clone(),
+            constructors: vec![(rt.running.clone(), 0), (rt.yielding.clone(), 8)],
+        });
+
+        let unknown = TypeKind::unknown;
+
+        let externals = [
+            (&rt.ref_new, arrow(&[unknown()], unknown()), "ref_new"),
+            (&rt.ref_get, arrow(&[unknown()], unknown()), "ref_get"),
+            (
+                &rt.ref_set,
+                arrow(&[unknown(), unknown()], unit_type()),
+                "ref_set",
+            ),
+            (
+                &rt.add,
+                arrow(&[int_type.clone(), int_type.clone()], int_type.clone()),
+                "add",
+            ),
+            (
+                &rt.sub,
+                arrow(&[int_type.clone(), int_type.clone()], int_type.clone()),
+                "sub",
+            ),
+            (
+                &rt.print_error,
+                arrow(&[prelude("String")], unit_type()),
+                "console.error",
+            ),
+        ];
+
+        for (name, typ, binding) in externals {
+            program.externals.push(ExternalDecl {
+                name: name.clone(),
+                typ,
+                effect: runtime("IO"),
+                convention: None,
+                binding: Symbol::intern(binding),
+            });
+        }
+
+        let value = |name: &Qualified, body: Term| LetDecl {
+            name: name.clone(),
+            span: Span::default(),
+            visibility: Visibility::Private,
+            typ: TypeKind::unknown(),
+            params: vec![],
+            body,
+            declaration: Span::default(),
+            allow: vec![],
+        };
+
+        let state = self.binder("s", TypeKind::unknown());
+        let cell = self.binder("c", TypeKind::unknown());
+        let running = Value::Constructor(rt.running.clone(), vec![]);
+        let created = self.call(&rt.ref_new, vec![var(&state)]);
+        let body = define(
+            state,
+            running,
+            define(cell.clone(), created, returns(var(&cell))),
+        );
+        let cells = vec![value(&rt.cell, body)];
+
+        let counter = self.binder("c", TypeKind::unknown());
+        let created = self.call(&rt.ref_new, vec![int(0)]);
+        let body = define(counter.clone(), created, returns(var(&counter)));
+        let cells = [cells, vec![value(&rt.counter, body)]].concat();
+
+        // Adds a function to the continuation of the request.
+        let cont = self.binder("k", TypeKind::unknown());
+        let body = self.state(
+            |this, fields| {
+                let composed = this.binder("c", TypeKind::unknown());
+                let param = this.binder("y", TypeKind::unknown());
+                let args = vec![var(&fields[CONT]), var(&cont), var(&param)];
+                let body = this.tail(function(&this.runtime.compose), args);
+                let request = this.request(fields, &[(CONT, var(&composed))]);
+                let stored = this.store(request, returns(unit()));
+                define(composed, Value::Lambda(vec![param], body), stored)
+            },
+            returns(unit()),
+        );
+        self.declare(rt.bind.clone(), vec![cont], body);
+
+        let first = self.binder("f", TypeKind::unknown());
+        let second = self.binder("g", TypeKind::unknown());
+        let param = self.binder("y", TypeKind::unknown());
+        let call = self.apply(var(&first), vec![var(&param)]);
+        let then = var(&second);
+        let body = self.sequence(call, |this, value| this.tail(then.clone(), vec![value]));
+        self.declare(rt.compose.clone(), vec![first, second, param], body);
+
+        // The result of the calls that yield, that adds the arguments that it's applied to to the
+        // continuation.
+        let arg = self.binder("a", TypeKind::unknown());
+        let applied = self.binder("g", TypeKind::unknown());
+        let param = self.binder("v", TypeKind::unknown());
+        let ignored = self.binder("u", unit_type());
+        let body = self.tail(var(&param), vec![var(&arg)]);
+        let bound = self.call(&rt.bind, vec![var(&applied)]);
+        let body = define(
+            applied,
+            Value::Lambda(vec![param], body),
+            define(ignored, bound, returns(function(&rt.pending))),
+        );
+        self.declare(rt.pending.clone(), vec![arg], body);
+
+        let params: Vec<_> = [
+            int_type.clone(),
+            int_type.clone(),
+            unknown(),
+            prelude("String"),
+        ]
+        .into_iter()
+        .map(|typ| self.binder("p", typ))
+        .collect();
+        let identity = self.binder("c", TypeKind::unknown());
+        let param = self.binder("y", TypeKind::unknown());
+        let cleanup = self.binder("l", TypeKind::unknown());
+        let ignored = self.binder("u", unit_type());
+        let request = Value::Constructor(
+            rt.yielding.clone(),
+            vec![
+                var(&params[0]),
+                var(&params[1]),
+                int(0),
+                var(&params[2]),
+                var(&identity),
+                int(0),
+                var(&cleanup),
+                var(&params[3]),
+            ],
+        );
+        let stored = self.store(request, returns(function(&rt.pending)));
+        let body = define(
+            identity,
+            Value::Lambda(vec![param.clone()], returns(var(&param))),
+            define(
+                cleanup,
+                Value::Lambda(vec![ignored], returns(unit())),
+                stored,
+            ),
+        );
+        self.declare(rt.perform.clone(), params, body);
+
+        let ignored = self.binder("u", unit_type());
+        let last = self.binder("n", int_type.clone());
+        let next = self.binder("m", int_type.clone());
+        let stored = self.binder("u", unit_type());
+        let get = self.call(&rt.ref_get, vec![function(&rt.counter)]);
+        let add = self.call(&rt.add, vec![var(&last), int(1)]);
+        let set = self.call(&rt.ref_set, vec![function(&rt.counter), var(&next)]);
+        let body = define(
+            last,
+            get,
+            define(next.clone(), add, define(stored, set, returns(var(&next)))),
+        );
+        self.declare(rt.fresh.clone(), vec![ignored], body);
+
+        // Calls the function with the argument and gives the value, even if the call yields.
+        let func = self.binder("f", TypeKind::unknown());
+        let arg = self.binder("a", TypeKind::unknown());
+        let result = self.binder("v", TypeKind::unknown());
+        let call = self.apply(var(&func), vec![var(&arg)]);
+        let given = var(&result);
+        let body = self.sequence(call, |_, _| returns(given.clone()));
+        self.declare(rt.then.clone(), vec![func, arg, result], body);
+
+        let first = self.binder("f", TypeKind::unknown());
+        let second = self.binder("g", TypeKind::unknown());
+        let arg = self.binder("u", unit_type());
+        let call = self.apply(var(&first), vec![var(&arg)]);
+        let (then, given) = (var(&second), var(&arg));
+        let body = self.sequence(call, |this, _| this.tail(then.clone(), vec![given.clone()]));
+        self.declare(rt.both.clone(), vec![first, second, arg], body);
+
+        // Runs the `finally`s of a continuation that a `ctl` clause didn't resume.
+        let flag = self.binder("g", TypeKind::unknown());
+        let cleanup = self.binder("l", TypeKind::unknown());
+        let result = self.binder("v", TypeKind::unknown());
+        let resumed = self.binder("n", int_type.clone());
+        let get = self.call(&rt.ref_get, vec![var(&flag)]);
+        let args = vec![var(&cleanup), unit(), var(&result)];
+        let cleaned = self.tail(function(&rt.then), args);
+        let body = define(
+            resumed.clone(),
+            get,
+            if_zero(var(&resumed), cleaned, returns(var(&result))),
+        );
+        self.declare(rt.unwind.clone(), vec![flag, cleanup, result], body);
+
+        // Runs the thunk of a handler with a `finally`, that runs when the handler gives its
+        // result or when a continuation that has it is not resumed.
+        let finally = self.binder("f", TypeKind::unknown());
+        let run = self.binder("t", TypeKind::unknown());
+        let result = self.binder("x", TypeKind::unknown());
+        let protect = rt.protect.clone();
+        let body = self.state(
+            |this, fields| {
+                let (resume, value) = this.reinstall(&fields[CONT], &protect, vec![var(&finally)]);
+
+                let cleanup = this.binder("l", TypeKind::unknown());
+                let ignored = this.binder("u", unit_type());
+                let args = vec![var(&fields[CLEANUP]), var(&finally), var(&ignored)];
+                let body = this.tail(function(&this.runtime.both), args);
+
+                let count = this.binder("n", prelude("Int"));
+                let add = this.call(&this.runtime.add, vec![var(&fields[FINALS]), int(1)]);
+
+                let request = this.request(
+                    fields,
+                    &[
+                        (CONT, var(&resume)),
+                        (FINALS, var(&count)),
+                        (CLEANUP, var(&cleanup)),
+                    ],
+                );
+
+                let stored = this.store(request, returns(var(&result)));
+
+                define(
+                    resume,
+                    value,
+                    define(
+                        cleanup,
+                        Value::Lambda(vec![ignored], body),
+                        define(count, add, stored),
+                    ),
+                )
+            },
+            self.tail(
+                function(&rt.then),
+                vec![var(&finally), unit(), var(&result)],
+            ),
+        );
+        let value = self.apply(var(&run), vec![unit()]);
+        let body = define(result, value, body);
+        self.declare(rt.protect.clone(), vec![finally, run], body);
+
+        cells
+    }
+
+    /// Moves the body of the entry point into another declaration that it calls, stopping the
+    /// program if a request comes out of it.
+    fn entry(&mut self, decl: &mut LetDecl) {
+        if decl.params.is_empty() {
+            return;
+        }
+
+        let inner = self.lifted_name("main");
+        let body = std::mem::replace(&mut decl.body, Box::new(TermKind::Unreachable));
+
+        self.lifted.push(LetDecl {
+            name: inner.clone(),
+            visibility: Visibility::Private,
+            body,
+            ..decl.clone()
+        });
+
+        let result = self.binder("x", TypeKind::unknown());
+        let args = decl.params.iter().map(var).collect();
+        let call = self.call(&inner, args);
+
+        let checked = self.state(
+            |this, fields| {
+                let ignored = this.binder("u", unit_type());
+                let message = var(&fields[MESSAGE]);
+                let value = this.call(&this.runtime.print_error, vec![message]);
+                define(ignored, value, Box::new(TermKind::Unreachable))
+            },
+            returns(var(&result)),
+        );
+
+        decl.body = define(result, call, checked);
+    }
+}
+
+/// Removes the effects of the program, adding the declarations of the runtime that runs them.
+/// Programs without effects are not changed.
+pub fn elaborate(program: &mut Program, entry: Option<&Qualified>) {
+    if !program.lets.iter().any(|x| has_effects(&x.body)) {
+        return;
+    }
+
+    let lets = std::mem::take(&mut program.lets);
+
+    let mut ctx = Elaborator {
+        runtime: Runtime::new(),
+        names: program.names,
+        types: HashMap::new(),
+        lambdas: HashMap::new(),
+        arities: HashMap::new(),
+        externals: HashSet::new(),
+        yields: HashSet::new(),
+        operations: HashMap::new(),
+        joins: HashMap::new(),
+        rests: HashMap::new(),
+        lifted: Vec::new(),
+        path: Symbol::intern("Effect$"),
+        span: Span::default(),
+        declaration: Span::default(),
+    };
+
+    for effect in &program.effects {
+        for (name, _, kind) in &effect.operations {
+            let tag = ctx.operations.len();
+            ctx.operations.insert(name.clone(), (tag, *kind));
+        }
+    }
+
+    for external in &program.externals {
+        let arity = external.typ.arrow_spine().0.len().max(1);
+        ctx.arities.insert(external.name.clone(), arity);
+        ctx.externals.insert(external.name.clone());
+    }
+
+    for decl in &lets {
+        ctx.arities.insert(decl.name.clone(), decl.params.len());
+
+        for param in &decl.params {
+            ctx.types.insert(param.name.clone(), param.typ.clone());
+        }
+
+        ctx.collect(&decl.body);
+    }
+
+    // A declaration can reach a perform if it calls one that can, so they're found until no
+    // other one is.
+    loop {
+        let found: Vec<_> = lets
+            .iter()
+            .filter(|x| !ctx.yields.contains(&x.name) && ctx.term_yields(&x.body, true))
+            .map(|x| x.name.clone())
+            .collect();
+
+        if found.is_empty() {
+            break;
+        }
+
+        ctx.yields.extend(found);
+    }
+
+    let mut elaborated = Vec::new();
+
+    for decl in &lets {
+        ctx.path = decl.name.path.clone();
+        ctx.span = decl.span.clone();
+        ctx.declaration = decl.declaration.clone();
+
+        let mut decl = LetDecl {
+            body: ctx.term(&decl.body, false),
+            ..decl.clone()
+        };
+
+        if Some(&decl.name) == entry {
+            ctx.entry(&mut decl);
+        }
+
+        elaborated.push(decl);
+    }
+
+    ctx.path = Symbol::intern("Effect$");
+    ctx.span = Span::default();
+    ctx.declaration = Span::default();
+
+    let cells = ctx.declare_runtime(program);
+
+    program.lets = cells;
+    program.lets.extend(elaborated);
+    program.lets.extend(ctx.lifted);
+    program.names = ctx.names;
+}
@@ -0,0 +1,107 @@
+//! Errors that can occur while transforming the core language.
+
+use vulpi_location::Span;
+use vulpi_report::{IntoDiagnostic, Severity, Suggestion, Text};
+use vulpi_syntax::r#abstract::Qualified;
+
+/// The lint of the private declarations that are never used.
+pub const UNUSED: &str = "unused";
+
+pub enum CoreErrorKind {
+    /// A function calls itself, directly or through other functions, with a type that grows at
+    /// each call, so it would need an infinite number of specializations.
+    PolymorphicRecursion(Qualified),
+
+    /// A private let declaration that cannot be reached from the entry point or from the public
+    /// declarations, with the span of the whole declaration. The declaration of a variable of a
+    /// pattern is just the variable, that is replaced with a wildcard instead of removed.
+    Unused(Qualified, Span),
+
+    /// A value that needs itself to be computed, with the declarations of the cycle from it back
+    /// to itself.
+    InitializationCycle(Vec<Qualified>),
+}
+
+pub struct CoreError {
+    pub span: Span,
+    pub kind: CoreErrorKind,
+}
+
+impl IntoDiagnostic for CoreError {
+    fn message(&self) -> Text {
+        match &self.kind {
+            CoreErrorKind::PolymorphicRecursion(name) => Text::from(format!(
+                "polymorphic recursion in '{}' cannot be monomorphized",
+                name.name.get()
+            )),
+            CoreErrorKind::Unused(name, _) => {
+                Text::from(format!("'{}' is never used", name.name.get()))
+            }
+            CoreErrorKind::InitializationCycle(cycle) => {
+                let names: Vec<_> = cycle.iter().map(|x| x.name.get()).collect();
+
+                Text::from(format!(
+                    "the value '{}' needs itself to be initialized: {}",
+                    cycle[0].name.get(),
+                    names.join(" -> ")
+                ))
+            }
+        }
+    }
+
+    fn hint(&self) -> Option<Text> {
+        match &self.kind {
+            CoreErrorKind::PolymorphicRecursion(_) => Some(Text::from(
+                "the recursive calls must use the same type arguments as the function",
+            )),
+            CoreErrorKind::Unused(_, _) => None,
+            CoreErrorKind::InitializationCycle(_) => Some(Text::from(
+                "values are computed before the entry point runs, so one of them must be a function",
+            )),
+        }
+    }
+
+    fn code(&self) -> Option<usize> {
+        match &self.kind {
+            CoreErrorKind::PolymorphicRecursion(_) => Some(400),
+            CoreErrorKind::Unused(_, _) => Some(401),
+            CoreErrorKind::InitializationCycle(_) => Some(402),
+        }
+    }
+
+    fn lint(&self) -> Option<&'static str> {
+        match &self.kind {
+            CoreErrorKind::PolymorphicRecursion(_) | CoreErrorKind::InitializationCycle(_) => None,
+            CoreErrorKind::Unused(_, _) => Some(UNUSED),
+        }
+    }
+
+    fn suggestions(&self) -> Vec<Suggestion> {
+        match &self.kind {
+            CoreErrorKind::PolymorphicRecursion(_) | CoreErrorKind::InitializationCycle(_) => {
+                vec![]
+            }
+            CoreErrorKind::Unused(_, declaration) => vec![Suggestion {
+                span: declaration.clone(),
+                replacement: if *declaration == self.span {
+                    "_".to_string()
+                } else {
+                    String::new()
+                },
+            }],
+        }
+    }
+
+    fn severity(&self) -> Severity {
+        match &self.kind {
+            CoreErrorKind::PolymorphicRecursion(_) | CoreErrorKind::InitializationCycle(_) => {
+                Severity::Error
+            }
+            CoreErrorKind::Unused(_, _) => Severity::Warning,
+        }
+    }
+
+    fn location(&self) -> Span {
+        self.span.clone()
+    }
+}
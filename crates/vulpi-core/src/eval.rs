@@ -0,0 +1,251 @@
+//! Evaluator of the core language at compile time. It runs the let declarations without parameters
+//! whose values only depend on literals, constructors, the primitives that the optimizer folds and
+//! calls to other functions of the program, so their values are known before the program runs.
+//! Anything else, like lambdas, effects and externals, stops the evaluation of the declaration.
+//!
+//! Each declaration is evaluated with a limited number of steps, so a constant that loops or takes
+//! too long is left for the program to compute.
+
+use std::collections::HashMap;
+
+use vulpi_intern::Symbol;
+use vulpi_syntax::{
+    elaborated::{Literal, LiteralKind},
+    r#abstract::Qualified,
+};
+
+use crate::{
+    optimize::{self, Foldable, Folded},
+    syntax::*,
+};
+
+/// The number of steps that the evaluation of a declaration can take.
+const FUEL: usize = 1000;
+
+/// A value that is known at compile time.
+#[derive(Clone)]
+pub enum Static {
+    Literal(Literal),
+    Constructor(Qualified, Vec<Static>),
+}
+
+impl Static {
+    /// The literal of the value if it's a literal. Floats are not literals here, because each
+    /// backend prints them differently.
+    pub fn literal(&self) -> Option<&Literal> {
+        match self {
+            Static::Literal(literal) if !matches!(**literal, LiteralKind::Float(_)) => {
+                Some(literal)
+            }
+            _ => None,
+        }
+    }
+}
+
+struct Evaluator<'a> {
+    lets: HashMap<Qualified, &'a LetDecl>,
+    operators: HashMap<Qualified, Foldable>,
+
+    /// The values of the declarations without parameters. Declarations that are being evaluated
+    /// are `None`, so the ones that refer to themselves are not constants.
+    globals: HashMap<Qualified, Option<Static>>,
+    fuel: usize,
+}
+
+/// The variables and join points of a call.
+#[derive(Default)]
+struct Frame<'a> {
+    variables: HashMap<Symbol, Static>,
+    joins: HashMap<Symbol, (&'a [Binder], &'a TermKind)>,
+}
+
+impl<'a> Evaluator<'a> {
+    fn step(&mut self) -> Option<()> {
+        self.fuel = self.fuel.checked_sub(1)?;
+        Some(())
+    }
+
+    fn global(&mut self, name: &Qualified) -> Option<Static> {
+        if let Some(value) = self.globals.get(name) {
+            return value.clone();
+        }
+
+        let decl = *self.lets.get(name)?;
+
+        if !decl.params.is_empty() {
+            return None;
+        }
+
+        self.globals.insert(name.clone(), None);
+        let value = self.term(&decl.body, &mut Frame::default());
+
+        // A declaration that ran out of steps can still be a constant when it's evaluated alone.
+        if value.is_some() || self.fuel > 0 {
+            self.globals.insert(name.clone(), value.clone());
+        } else {
+            self.globals.remove(name);
+        }
+
+        value
+    }
+
+    fn atom(&mut self, atom: &Atom, frame: &Frame) -> Option<Static> {
+        match atom {
+            Atom::Variable(name) => frame.variables.get(name).cloned(),
+            Atom::Function(name, _) => self.global(name),
+            Atom::Literal(literal) => Some(Static::Literal(literal.clone())),
+        }
+    }
+
+    fn atoms(&mut self, atoms: &[Atom], frame: &Frame) -> Option<Vec<Static>> {
+        atoms.iter().map(|x| self.atom(x, frame)).collect()
+    }
+
+    fn apply(&mut self, func: &Atom, args: Vec<Static>) -> Option<Static> {
+        let Atom::Function(name, _) = func else {
+            return None;
+        };
+
+        if let Some(foldable) = self.operators.get(name) {
+            if args.len() != foldable.primitive.arity() {
+                return None;
+            }
+
+            let args = args
+                .iter()
+                .map(|x| optimize::constant(&Atom::Literal(x.literal()?.clone())))
+                .collect::<Option<Vec<_>>>()?;
+
+            return match optimize::fold(foldable.primitive, &args)? {
+                Folded::Literal(literal) => Some(Static::Literal(Box::new(literal))),
+                Folded::Bool(value) => {
+                    let bools = foldable.bools.as_ref()?;
+                    Some(Static::Constructor(bools[value as usize].clone(), vec![]))
+                }
+            };
+        }
+
+        let decl = *self.lets.get(name)?;
+
+        if decl.params.is_empty() || decl.params.len() != args.len() {
+            return None;
+        }
+
+        let mut frame = Frame::default();
+
+        for (param, arg) in decl.params.iter().zip(args) {
+            frame.variables.insert(param.name.clone(), arg);
+        }
+
+        self.term(&decl.body, &mut frame)
+    }
+
+    fn value(&mut self, value: &Value, frame: &Frame) -> Option<Static> {
+        match value {
+            Value::Atom(atom) => self.atom(atom, frame),
+            Value::Application(func, args, _) => {
+                let args = self.atoms(args, frame)?;
+                self.apply(func, args)
+            }
+            Value::Constructor(name, args) => {
+                Some(Static::Constructor(name.clone(), self.atoms(args, frame)?))
+            }
+            Value::Field(name, atom, index) => match self.atom(atom, frame)? {
+                Static::Constructor(constructor, mut fields)
+                    if &constructor == name && *index < fields.len() =>
+                {
+                    Some(fields.swap_remove(*index))
+                }
+                _ => None,
+            },
+            Value::Lambda(..) | Value::Perform(..) | Value::Handle(..) | Value::Mask(..) => None,
+        }
+    }
+
+    fn term(&mut self, term: &'a TermKind, frame: &mut Frame<'a>) -> Option<Static> {
+        self.step()?;
+
+        match term {
+            TermKind::Let(binder, value, rest) => {
+                let value = self.value(value, frame)?;
+                frame.variables.insert(binder.name.clone(), value);
+                self.term(rest, frame)
+            }
+            TermKind::Join(label, params, body, rest) => {
+                frame.joins.insert(label.clone(), (params, body));
+                self.term(rest, frame)
+            }
+            TermKind::Jump(label, args) => {
+                let args = self.atoms(args, frame)?;
+                let (params, body) = *frame.joins.get(label)?;
+
+                for (param, arg) in params.iter().zip(args) {
+                    frame.variables.insert(param.name.clone(), arg);
+                }
+
+                self.term(body, frame)
+            }
+            TermKind::Tail(func, args, _) => {
+                let args = self.atoms(args, frame)?;
+                self.apply(func, args)
+            }
+            TermKind::Match(atom, alts, default) => {
+                let (alt, fields) = match self.atom(atom, frame)? {
+                    Static::Constructor(name, fields) => {
+                        let alt = alts.iter().find(|alt| {
+                            alt.case == Case::Constructor(name.clone())
+                                && alt.binders.len() == fields.len()
+                        });
+
+                        (alt, fields)
+                    }
+                    Static::Literal(literal) => {
+                        // Floats are compared by the backend.
+                        let scrutinee = optimize::constant(&Atom::Literal(literal))?;
+
+                        let alt = alts
+                            .iter()
+                            .find(|alt| optimize::selects(&alt.case, &scrutinee) == Some(true));
+
+                        (alt, vec![])
+                    }
+                };
+
+                let Some(alt) = alt else {
+                    return self.term(default.as_ref()?, frame);
+                };
+
+                for (binder, field) in alt.binders.iter().zip(fields) {
+                    frame.variables.insert(binder.name.clone(), field);
+                }
+
+                self.term(&alt.body, frame)
+            }
+            TermKind::Return(atom) => self.atom(atom, frame),
+            TermKind::Unreachable => None,
+        }
+    }
+}
+
+/// The values of the let declarations without parameters that can be computed at compile time.
+pub fn constants(program: &Program) -> HashMap<Qualified, Static> {
+    let mut evaluator = Evaluator {
+        lets: program.lets.iter().map(|x| (x.name.clone(), x)).collect(),
+        operators: optimize::operators(program),
+        globals: HashMap::new(),
+        fuel: 0,
+    };
+
+    for decl in &program.lets {
+        if decl.params.is_empty() {
+            evaluator.fuel = FUEL;
+            evaluator.global(&decl.name);
+        }
+    }
+
+    evaluator
+        .globals
+        .into_iter()
+        .filter_map(|(name, value)| Some((name, value?)))
+        .collect()
+}
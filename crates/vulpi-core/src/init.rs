@@ -0,0 +1,219 @@
+//! The initialization of the values of the program, that are the let declarations without
+//! parameters. They're computed before the entry point runs, in an order where each value comes
+//! after the values that it needs.
+//!
+//! Computing a value runs its body and calling a function runs the body of the function, so a
+//! value needs the values that its body uses and the ones that the functions it uses need, even
+//! inside of lambdas, because they may be called. A value that is just a lambda runs nothing when
+//! it's computed. A value that needs itself cannot be computed, so it's an error.
+
+use std::collections::{HashMap, VecDeque};
+
+use vulpi_report::{Diagnostic, Report};
+use vulpi_syntax::r#abstract::Qualified;
+
+use crate::{
+    errors::{CoreError, CoreErrorKind},
+    syntax::*,
+};
+
+/// The graph of the initialization. Each let declaration has two nodes: the one of computing its
+/// value, at `2 * index`, and the one of calling it, at `2 * index + 1`.
+struct Graph {
+    edges: Vec<Vec<usize>>,
+}
+
+fn compute(index: usize) -> usize {
+    2 * index
+}
+
+fn call(index: usize) -> usize {
+    2 * index + 1
+}
+
+/// Checks if the body of a declaration is a lambda that it returns.
+fn is_lambda(body: &TermKind) -> bool {
+    match body {
+        TermKind::Let(binder, Value::Lambda(..), rest) => {
+            matches!(&**rest, TermKind::Return(Atom::Variable(x)) if *x == binder.name)
+        }
+        _ => false,
+    }
+}
+
+impl Graph {
+    fn new(lets: &[LetDecl]) -> Self {
+        let indices: HashMap<_, _> = lets.iter().enumerate().map(|(i, x)| (&x.name, i)).collect();
+        let mut edges = vec![Vec::new(); lets.len() * 2];
+
+        for (i, decl) in lets.iter().enumerate() {
+            let mut used = Vec::new();
+
+            visit_term(&decl.body, &mut |atom| {
+                if let Atom::Function(name, _) = atom {
+                    used.extend(indices.get(name).copied());
+                }
+            });
+
+            used.sort();
+            used.dedup();
+
+            let mut targets = Vec::new();
+
+            for used in used {
+                if lets[used].params.is_empty() {
+                    targets.push(compute(used));
+                }
+
+                targets.push(call(used));
+            }
+
+            if decl.params.is_empty() && !is_lambda(&decl.body) {
+                edges[compute(i)] = targets.clone();
+            }
+
+            edges[call(i)] = targets;
+        }
+
+        Self { edges }
+    }
+
+    /// The shortest path from a node back to itself, if there's one.
+    fn cycle(&self, start: usize) -> Option<Vec<usize>> {
+        let mut parents = vec![None; self.edges.len()];
+        let mut queue = VecDeque::from([start]);
+
+        while let Some(node) = queue.pop_front() {
+            for &next in &self.edges[node] {
+                if next == start {
+                    let mut path = vec![node];
+
+                    while let Some(parent) = parents[*path.last().unwrap()] {
+                        path.push(parent);
+                    }
+
+                    path.reverse();
+                    path.push(start);
+                    return Some(path);
+                }
+
+                if parents[next].is_none() {
+                    parents[next] = Some(node);
+                    queue.push_back(next);
+                }
+            }
+        }
+
+        None
+    }
+}
+
+/// Reports the values that need themselves to be computed, with the declarations that they go
+/// through until they get back to themselves. Returns whether all the values can be computed.
+pub fn check(reporter: Report, program: &Program) -> bool {
+    let graph = Graph::new(&program.lets);
+    let mut reported = vec![false; program.lets.len()];
+
+    for (i, decl) in program.lets.iter().enumerate() {
+        if !decl.params.is_empty() || reported[i] {
+            continue;
+        }
+
+        let Some(path) = graph.cycle(compute(i)) else {
+            continue;
+        };
+
+        let mut cycle: Vec<usize> = path.into_iter().map(|x| x / 2).collect();
+        cycle.dedup();
+
+        // A value that uses itself directly is a cycle of a single declaration.
+        if cycle.len() == 1 {
+            cycle.push(i);
+        }
+
+        for &index in &cycle {
+            reported[index] |= program.lets[index].params.is_empty();
+        }
+
+        let cycle = cycle
+            .into_iter()
+            .map(|x| program.lets[x].name.clone())
+            .collect();
+
+        reporter.report(Diagnostic::new(CoreError {
+            span: decl.span.clone(),
+            kind: CoreErrorKind::InitializationCycle(cycle),
+        }));
+    }
+
+    !reported.contains(&true)
+}
+
+/// Orders the values of the program so each one comes after the ones that it needs and the entry
+/// point comes after all of them, unless some value needs it. Functions keep their places.
+pub fn sort(program: &mut Program, entry: Option<&Qualified>) {
+    let graph = Graph::new(&program.lets);
+    let mut visited = vec![false; graph.edges.len()];
+    let mut order = Vec::new();
+
+    let values = || {
+        program
+            .lets
+            .iter()
+            .enumerate()
+            .filter(|(_, x)| x.params.is_empty())
+    };
+
+    let is_entry = |decl: &LetDecl| Some(&decl.name) == entry;
+
+    let roots: Vec<_> = values()
+        .filter(|(_, x)| !is_entry(x))
+        .chain(values().filter(|(_, x)| is_entry(x)))
+        .map(|(i, _)| compute(i))
+        .collect();
+
+    // The values are put in the order that their nodes finish in a depth first search.
+    for root in roots {
+        if visited[root] {
+            continue;
+        }
+
+        visited[root] = true;
+        let mut stack = vec![(root, 0)];
+
+        while let Some((node, next)) = stack.last_mut() {
+            let node = *node;
+
+            if let Some(&target) = graph.edges[node].get(*next) {
+                *next += 1;
+
+                if !visited[target] {
+                    visited[target] = true;
+                    stack.push((target, 0));
+                }
+            } else {
+                stack.pop();
+
+                if node % 2 == 0 {
+                    order.push(node / 2);
+                }
+            }
+        }
+    }
+
+    let mut lets: Vec<_> = std::mem::take(&mut program.lets)
+        .into_iter()
+        .map(Some)
+        .collect();
+
+    let mut values = order.into_iter();
+
+    for i in 0..lets.len() {
+        let index = match &lets[i] {
+            Some(decl) if !decl.params.is_empty() => i,
+            _ => values.next().unwrap(),
+        };
+
+        program.lets.push(lets[index].take().unwrap());
+    }
+}
@@ -0,0 +1,113 @@
+//! Representation selection for constructors. Every constructor gets a layout that says how the
+//! backends store its values: the only constructor of a type with a single field, like the one of
+//! a newtype, is represented by its field, the constructors of types without fields are just their
+//! tags and the rest are blocks that start with the tag.
+
+use std::collections::HashMap;
+
+use vulpi_syntax::r#abstract::Qualified;
+
+use crate::syntax::{Program, TypeDecl};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Layout {
+    /// A block with the tag followed by the fields. The tag is the position of the constructor in
+    /// its type.
+    Boxed(usize),
+
+    /// The value of the only field, without any block around it.
+    Unboxed,
+
+    /// The tag of a constructor whose type has no fields at all.
+    Tag(usize),
+}
+
+impl Layout {
+    fn select(typ: &TypeDecl, tag: usize) -> Layout {
+        match typ.constructors.as_slice() {
+            [(_, 1)] => Layout::Unboxed,
+            constructors if constructors.iter().all(|(_, arity)| *arity == 0) => Layout::Tag(tag),
+            _ => Layout::Boxed(tag),
+        }
+    }
+
+    /// The tag that tells the constructor apart from the other ones of its type.
+    pub fn tag(&self) -> usize {
+        match self {
+            Layout::Boxed(tag) | Layout::Tag(tag) => *tag,
+            Layout::Unboxed => 0,
+        }
+    }
+}
+
+/// The layouts of all the constructors of a program.
+#[derive(Clone, Debug, Default)]
+pub struct Layouts {
+    constructors: HashMap<Qualified, Layout>,
+}
+
+impl Layouts {
+    pub fn new(program: &Program) -> Self {
+        let mut constructors = HashMap::new();
+
+        for typ in &program.types {
+            for (tag, (name, _)) in typ.constructors.iter().enumerate() {
+                constructors.insert(name.clone(), Layout::select(typ, tag));
+            }
+        }
+
+        Self { constructors }
+    }
+
+    /// The layout of a constructor. Constructors that are not declared by the program are boxed
+    /// and alone in their type.
+    pub fn get(&self, name: &Qualified) -> Layout {
+        self.constructors
+            .get(name)
+            .copied()
+            .unwrap_or(Layout::Boxed(0))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use vulpi_intern::Symbol;
+
+    use super::*;
+
+    fn name(name: &str) -> Qualified {
+        Qualified {
+            path: Symbol::intern("Main"),
+            name: Symbol::intern(name),
+        }
+    }
+
+    fn typ(typ: &str, constructors: &[(&str, usize)]) -> TypeDecl {
+        TypeDecl {
+            name: name(typ),
+            constructors: constructors.iter().map(|(x, arity)| (name(x), *arity)).collect(),
+        }
+    }
+
+    #[test]
+    fn selects_the_layouts_by_the_fields_of_the_types() {
+        let program = Program {
+            types: vec![
+                typ("Meters", &[("Meters", 1)]),
+                typ("Color", &[("Red", 0), ("Green", 0), ("Blue", 0)]),
+                typ("Maybe", &[("Nothing", 0), ("Just", 1)]),
+                typ("Pair", &[("Pair", 2)]),
+            ],
+            ..Program::default()
+        };
+
+        let layouts = Layouts::new(&program);
+
+        assert_eq!(layouts.get(&name("Meters")), Layout::Unboxed);
+        assert_eq!(layouts.get(&name("Blue")), Layout::Tag(2));
+        assert_eq!(layouts.get(&name("Nothing")), Layout::Boxed(0));
+        assert_eq!(layouts.get(&name("Just")), Layout::Boxed(1));
+        assert_eq!(layouts.get(&name("Pair")), Layout::Boxed(0));
+        assert_eq!(layouts.get(&name("Unknown")), Layout::Boxed(0));
+    }
+}
@@ -0,0 +1,18 @@
+//! This is the core language of the compiler. It's a small explicitly typed language in A-normal
+//! form that is lowered from the elaborated tree, so optimizations and backends have a target
+//! that does not change every time the surface syntax changes.
+
+pub mod dead;
+pub mod effects;
+pub mod errors;
+pub mod eval;
+pub mod init;
+pub mod layout;
+pub mod lower;
+pub mod monomorphize;
+pub mod optimize;
+pub mod pattern;
+pub mod primitive;
+pub mod syntax;
+pub mod tail;
+pub mod usage;
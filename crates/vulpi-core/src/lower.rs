@@ -0,0 +1,1618 @@
+//! Lowering of the elaborated tree into the core language. Expressions are flattened into A-normal
+//! form, `when` and the patterns of declarations become decision trees, `do` blocks become lets,
+//! and records and tuples become constructors.
+
+use std::collections::{BTreeSet, HashMap};
+
+use vulpi_intern::Symbol;
+use vulpi_location::Span;
+use vulpi_syntax::{
+    elaborated::{self, Handler, LiteralKind, PatEffect, PatternKind},
+    r#abstract::{OperationKind, Qualified},
+};
+use vulpi_typer::{real::Real, TypeKind as RealKind};
+
+use crate::{
+    pattern::{self, Tree},
+    syntax::*,
+};
+
+type Expr = elaborated::Expr<vulpi_typer::Type<Real>>;
+type PatternArm = elaborated::PatternArm<vulpi_typer::Type<Real>>;
+
+/// Converts a type of the type checker into a type of the core language. The depth is the number
+/// of type binders that are in scope.
+pub fn typ(typ: &vulpi_typer::Type<Real>, depth: usize) -> Type {
+    let typ = typ.force(depth);
+
+    Box::new(match typ.as_ref() {
+        RealKind::Variable(name) => TypeKind::Constructor(name.clone()),
+        RealKind::Bound(index) => TypeKind::Bound(index.0),
+        RealKind::Arrow(arrow) => {
+            TypeKind::Arrow(self::typ(&arrow.typ, depth), self::typ(&arrow.body, depth))
+        }
+        RealKind::Forall(forall) => {
+            TypeKind::Forall(forall.name.clone(), self::typ(&forall.body, depth + 1))
+        }
+        RealKind::Tuple(types) => {
+            TypeKind::Tuple(types.iter().map(|x| self::typ(x, depth)).collect())
+        }
+        RealKind::Application(func, arg) => {
+            let arg = self::typ(arg, depth);
+
+            match *self::typ(func, depth) {
+                TypeKind::Application(head, mut args) => {
+                    args.push(arg);
+                    TypeKind::Application(head, args)
+                }
+                func => TypeKind::Application(Box::new(func), vec![arg]),
+            }
+        }
+        RealKind::Qualified(_, typ) => return self::typ(typ, depth),
+        _ => TypeKind::Unknown,
+    })
+}
+
+fn literal_type(literal: &LiteralKind) -> Type {
+    let name = match literal {
+        LiteralKind::String(_) => "String",
+        LiteralKind::Integer(_) => "Int",
+        LiteralKind::Float(_) => "Float",
+        LiteralKind::Char(_) => "Char",
+        LiteralKind::Unit => return Box::new(TypeKind::Tuple(vec![])),
+    };
+
+    Box::new(TypeKind::Constructor(Qualified {
+        path: Symbol::intern("Prelude"),
+        name: Symbol::intern(name),
+    }))
+}
+
+fn unit() -> (Atom, Type) {
+    (
+        Atom::Literal(Box::new(LiteralKind::Unit)),
+        literal_type(&LiteralKind::Unit),
+    )
+}
+
+fn true_case() -> Case {
+    Case::Constructor(Qualified {
+        path: Symbol::intern("Prelude.Bool"),
+        name: Symbol::intern("True"),
+    })
+}
+
+/// Something that can be called with a fixed number of arguments and that is not a function.
+enum Callee {
+    Constructor(Qualified),
+    Perform(Option<Atom>, Qualified),
+}
+
+/// A binding that is waiting for the rest of the term.
+enum Frame {
+    Let(Binder, Value),
+
+    /// A join point whose body is the rest of the term and that is jumped to by the term.
+    Join(Symbol, Vec<Binder>, Term),
+}
+
+/// The context of the lowering. It contains the bindings of the term that is being built and
+/// information about the declarations that is needed to desugar expressions.
+#[derive(Default)]
+pub struct Context {
+    counter: usize,
+    frames: Vec<Frame>,
+    vars: im_rc::HashMap<Symbol, Binder>,
+    constructors: HashMap<Qualified, (usize, usize)>,
+    records: HashMap<Qualified, Vec<Qualified>>,
+    fields: HashMap<Qualified, (Qualified, usize)>,
+    operations: HashMap<Qualified, (usize, OperationKind)>,
+    tuples: BTreeSet<usize>,
+    schemes: HashMap<Qualified, Type>,
+    depth: usize,
+
+    /// The functions of recursive groups that are join points, with their labels and the number
+    /// of their parameters.
+    loops: HashMap<Symbol, (Symbol, usize)>,
+
+    /// The span of the expression that is being lowered. The calls that it becomes are located
+    /// there.
+    span: Span,
+}
+
+impl Context {
+    pub fn fresh(&mut self, name: &str) -> Symbol {
+        let id = self.counter;
+        self.counter += 1;
+        Symbol::intern(&format!("{}${}", name, id))
+    }
+
+    /// The number of constructors of the type that the constructor belongs to.
+    pub fn siblings(&self, name: &Qualified) -> usize {
+        self.constructors.get(name).map(|x| x.1).unwrap_or(1)
+    }
+
+    fn arity(&self, callee: &Callee) -> usize {
+        match callee {
+            Callee::Constructor(name) => self.constructors.get(name).map(|x| x.0).unwrap_or(0),
+            Callee::Perform(_, name) => self.operations.get(name).map(|x| x.0).unwrap_or(0),
+        }
+    }
+
+    fn convert(&self, typ: &vulpi_typer::Type<Real>) -> Type {
+        self::typ(typ, self.depth)
+    }
+
+    /// A reference to a function that is instantiated to the type. The arguments of the
+    /// quantifiers are found by matching the type against the type of the declaration.
+    fn function(&self, name: &Qualified, typ: &Type) -> Atom {
+        let args = match self.schemes.get(name) {
+            Some(scheme) => {
+                let binders = scheme.binders();
+                let mut args = vec![None; binders];
+
+                let mut body = &**scheme;
+                while let TypeKind::Forall(_, inner) = body {
+                    body = inner;
+                }
+
+                arguments(body, typ, 0, &mut args);
+
+                args.into_iter()
+                    .map(|x| x.unwrap_or_else(TypeKind::unknown))
+                    .collect()
+            }
+            None => vec![],
+        };
+
+        Atom::Function(name.clone(), args)
+    }
+
+    fn tuple(&mut self, size: usize) -> Qualified {
+        self.tuples.insert(size);
+        self.constructors.insert(tuple(size), (size, 1));
+        tuple(size)
+    }
+
+    fn define(&mut self, name: Symbol, binder: Binder) {
+        self.vars.insert(name, binder);
+    }
+
+    fn binder(&mut self, name: &str, typ: Type) -> Binder {
+        Binder {
+            name: self.fresh(name),
+            typ,
+        }
+    }
+
+    /// A call located at the expression that is being lowered.
+    fn application_of(&self, func: Atom, args: Vec<Atom>) -> Value {
+        Value::Application(func, args, self.span.clone())
+    }
+
+    /// Binds the value to a new name, unless it's already an atom.
+    fn bind(&mut self, name: &str, typ: Type, value: Value) -> Atom {
+        match value {
+            Value::Atom(atom) => atom,
+            value => self.bind_value(name, typ, value),
+        }
+    }
+
+    fn bind_value(&mut self, name: &str, typ: Type, value: Value) -> Atom {
+        let binder = self.binder(name, typ);
+        let atom = Atom::Variable(binder.name.clone());
+        self.frames.push(Frame::Let(binder, value));
+        atom
+    }
+
+    /// Turns an atom into an occurrence that patterns can match on.
+    fn occurrence(&mut self, atom: Atom, typ: Type) -> Binder {
+        match atom {
+            Atom::Variable(name) => Binder { name, typ },
+            atom => {
+                let binder = self.binder("v", typ);
+                let value = Value::Atom(atom);
+                self.frames.push(Frame::Let(binder.clone(), value));
+                binder
+            }
+        }
+    }
+
+    /// Builds a term with its own bindings and variables, closing the bindings with the term that
+    /// is returned by the function.
+    fn block(&mut self, f: impl FnOnce(&mut Self) -> Term) -> Term {
+        let frames = std::mem::take(&mut self.frames);
+        let vars = self.vars.clone();
+
+        let term = f(self);
+
+        let inner = std::mem::replace(&mut self.frames, frames);
+        self.vars = vars;
+
+        inner.into_iter().rfold(term, |rest, frame| match frame {
+            Frame::Let(binder, value) => Box::new(TermKind::Let(binder, value, rest)),
+            Frame::Join(label, params, term) => Box::new(TermKind::Join(label, params, rest, term)),
+        })
+    }
+
+    fn scoped<T>(&mut self, f: impl FnOnce(&mut Self) -> T) -> T {
+        let vars = self.vars.clone();
+        let result = f(self);
+        self.vars = vars;
+        result
+    }
+
+    /// Binds the variables of an irrefutable pattern to the fields of the atom.
+    fn destructure(&mut self, atom: Atom, typ: Type, pattern: &elaborated::Pattern) {
+        match &**pattern {
+            PatternKind::Variable(name) => {
+                let binder = match atom {
+                    Atom::Variable(var) => Binder { name: var, typ },
+                    atom => {
+                        let binder = self.binder(&name.get(), typ);
+                        let value = Value::Atom(atom);
+                        self.frames.push(Frame::Let(binder.clone(), value));
+                        binder
+                    }
+                };
+
+                self.define(name.clone(), binder);
+            }
+            PatternKind::Tuple(parts) => {
+                let types = match *typ {
+                    TypeKind::Tuple(types) if types.len() == parts.len() => types,
+                    _ => vec![TypeKind::unknown(); parts.len()],
+                };
+
+                let name = self.tuple(parts.len());
+                self.destructure_fields(&name, atom, types, parts);
+            }
+            PatternKind::Application(app) => {
+                let types = vec![TypeKind::unknown(); app.args.len()];
+                self.destructure_fields(&app.func, atom, types, &app.args);
+            }
+            PatternKind::As(name, inner) => {
+                let scrutinee = self.occurrence(atom, typ.clone());
+                self.define(name.clone(), scrutinee.clone());
+                self.destructure(Atom::Variable(scrutinee.name), typ, inner);
+            }
+            PatternKind::Or(alternatives) => {
+                // The alternatives are matched like the arms of a `when` whose actions jump to
+                // the rest of the block with the variables.
+                let mut variables = Vec::new();
+                pattern::variables(pattern, &mut variables);
+
+                let label = self.fresh("j");
+                let params: Vec<_> = variables
+                    .iter()
+                    .map(|x| self.binder(&x.get(), TypeKind::unknown()))
+                    .collect();
+
+                let scrutinee = self.occurrence(atom, typ);
+                let rows = alternatives.iter().map(|x| vec![x.clone()]).collect();
+                let guards = vec![None; alternatives.len()];
+
+                let term = self.block(|this| {
+                    this.matching(vec![scrutinee], rows, &guards, &mut |this, _| {
+                        let args = variables.iter().map(|x| this.variable(x).0).collect();
+                        Box::new(TermKind::Jump(label.clone(), args))
+                    })
+                });
+
+                self.frames.push(Frame::Join(label, params.clone(), term));
+
+                for (name, param) in variables.iter().zip(params) {
+                    self.define(name.clone(), param);
+                }
+            }
+            _ => (),
+        }
+    }
+
+    fn destructure_fields(
+        &mut self,
+        name: &Qualified,
+        atom: Atom,
+        types: Vec<Type>,
+        parts: &[elaborated::Pattern],
+    ) {
+        for (i, (part, typ)) in parts.iter().zip(types).enumerate() {
+            let mut variables = Vec::new();
+            pattern::variables(part, &mut variables);
+
+            if !variables.is_empty() {
+                let value = Value::Field(name.clone(), atom.clone(), i);
+                let field = self.bind_value("v", typ.clone(), value);
+                self.destructure(field, typ, part);
+            }
+        }
+    }
+
+    /// Lowers an expression that is not in tail position, returning the atom that holds its
+    /// result.
+    pub fn expr(&mut self, expr: &Expr) -> (Atom, Type) {
+        let span = std::mem::replace(&mut self.span, expr.span.clone());
+        let result = self.expr_kind(expr);
+        self.span = span;
+        result
+    }
+
+    fn expr_kind(&mut self, expr: &Expr) -> (Atom, Type) {
+        match &*expr.data {
+            elaborated::ExprKind::Lambda(_) => {
+                let value = self.lambda(expr);
+                (
+                    self.bind("f", TypeKind::unknown(), value),
+                    TypeKind::unknown(),
+                )
+            }
+            elaborated::ExprKind::Application(_) => self.application(expr),
+            elaborated::ExprKind::Variable(name) => self.variable(name),
+            elaborated::ExprKind::Constructor(_, name) => self.call(
+                Callee::Constructor(name.clone()),
+                vec![],
+                TypeKind::unknown(),
+            ),
+            elaborated::ExprKind::Function(name, typ) => {
+                if self.operations.contains_key(name) {
+                    let callee = Callee::Perform(None, name.clone());
+                    self.call(callee, vec![], TypeKind::unknown())
+                } else {
+                    let typ = self.convert(typ);
+                    (self.function(name, &typ), typ)
+                }
+            }
+            elaborated::ExprKind::Operation(handler, name) => {
+                let (instance, _) = self.variable(handler);
+                let callee = Callee::Perform(Some(instance), name.clone());
+                self.call(callee, vec![], TypeKind::unknown())
+            }
+            elaborated::ExprKind::Projection(projection) => {
+                let (atom, _) = self.expr(&projection.expr);
+                let (record, index) = self
+                    .fields
+                    .get(&projection.field)
+                    .cloned()
+                    .unwrap_or_else(|| (projection.field.clone(), 0));
+                let value = Value::Field(record, atom, index);
+                (
+                    self.bind("p", TypeKind::unknown(), value),
+                    TypeKind::unknown(),
+                )
+            }
+            elaborated::ExprKind::Let(let_expr) => self.scoped(|this| {
+                let (atom, typ) = this.expr(&let_expr.body);
+                this.destructure(atom, typ, &let_expr.pattern);
+                this.expr(&let_expr.next)
+            }),
+            elaborated::ExprKind::LetRec(let_rec) if is_loop(let_rec) => {
+                let label = self.fresh("j");
+
+                let mut term = self.block(|this| this.loop_join(let_rec));
+                exits(&mut term, &label);
+
+                let binder = self.binder("r", TypeKind::unknown());
+                let atom = Atom::Variable(binder.name.clone());
+
+                self.frames.push(Frame::Join(label, vec![binder], term));
+
+                (atom, TypeKind::unknown())
+            }
+            elaborated::ExprKind::LetRec(let_rec) => self.scoped(|this| {
+                this.let_rec(&let_rec.bindings);
+                this.expr(&let_rec.next)
+            }),
+            elaborated::ExprKind::When(when) => {
+                let scrutinee = self.scrutinee(&when.scrutinee);
+
+                let label = self.fresh("j");
+                let mut result = None;
+
+                let term = self.when(scrutinee, &when.arms, &mut |this, expr| {
+                    let (atom, typ) = this.expr(expr);
+                    result.get_or_insert(typ);
+                    Box::new(TermKind::Jump(label.clone(), vec![atom]))
+                });
+
+                let binder = self.binder("r", result.unwrap_or_else(TypeKind::unknown));
+                let atom = Atom::Variable(binder.name.clone());
+                let typ = binder.typ.clone();
+
+                self.frames.push(Frame::Join(label, vec![binder], term));
+
+                (atom, typ)
+            }
+            elaborated::ExprKind::Handler(handler) => self.handler(handler),
+            elaborated::ExprKind::Mask(mask) => {
+                let body = self.block(|this| this.tail(&mask.expr));
+                let thunk = self.bind_value("t", TypeKind::unknown(), Value::Lambda(vec![], body));
+                let value = Value::Mask(mask.operations.clone(), thunk);
+                (
+                    self.bind("m", TypeKind::unknown(), value),
+                    TypeKind::unknown(),
+                )
+            }
+            elaborated::ExprKind::Do(block) => self.scoped(|this| {
+                let mut result = unit();
+
+                for statement in block {
+                    result = this.statement(statement);
+                }
+
+                result
+            }),
+            elaborated::ExprKind::Literal(literal) => {
+                (Atom::Literal(literal.clone()), literal_type(literal))
+            }
+            elaborated::ExprKind::RecordInstance(instance) => {
+                let fields = self.record_fields(&instance.fields);
+                let order = self
+                    .records
+                    .get(&instance.name)
+                    .cloned()
+                    .unwrap_or_default();
+
+                let atoms = order
+                    .iter()
+                    .map(|field| {
+                        fields
+                            .iter()
+                            .find(|(name, _)| *name == field.name)
+                            .map(|(_, atom)| atom.clone())
+                            .unwrap_or_else(|| unit().0)
+                    })
+                    .collect();
+
+                let value = Value::Constructor(instance.name.clone(), atoms);
+                (
+                    self.bind("r", TypeKind::unknown(), value),
+                    TypeKind::unknown(),
+                )
+            }
+            elaborated::ExprKind::RecordUpdate(update) => {
+                let (record, typ) = self.expr(&update.expr);
+                let fields = self.record_fields(&update.fields);
+                let order = self.records.get(&update.name).cloned().unwrap_or_default();
+
+                let mut atoms = Vec::new();
+
+                for (i, field) in order.iter().enumerate() {
+                    let atom = match fields.iter().find(|(name, _)| *name == field.name) {
+                        Some((_, atom)) => atom.clone(),
+                        None => {
+                            let value = Value::Field(update.name.clone(), record.clone(), i);
+                            self.bind("v", TypeKind::unknown(), value)
+                        }
+                    };
+
+                    atoms.push(atom);
+                }
+
+                let value = Value::Constructor(update.name.clone(), atoms);
+                (self.bind("r", typ.clone(), value), typ)
+            }
+            elaborated::ExprKind::Tuple(tuple) => {
+                let (atoms, types): (Vec<_>, Vec<_>) =
+                    tuple.exprs.iter().map(|x| self.expr(x)).unzip();
+
+                let name = self.tuple(atoms.len());
+                let typ = Box::new(TypeKind::Tuple(types));
+
+                (
+                    self.bind("t", typ.clone(), Value::Constructor(name, atoms)),
+                    typ,
+                )
+            }
+            elaborated::ExprKind::Error => unreachable!(),
+        }
+    }
+
+    /// Lowers an expression in tail position into the term that finishes the current block.
+    fn tail(&mut self, expr: &Expr) -> Term {
+        match &*expr.data {
+            elaborated::ExprKind::Let(let_expr) => {
+                let (atom, typ) = self.expr(&let_expr.body);
+                self.destructure(atom, typ, &let_expr.pattern);
+                self.tail(&let_expr.next)
+            }
+            elaborated::ExprKind::LetRec(let_rec) if is_loop(let_rec) => self.loop_join(let_rec),
+            elaborated::ExprKind::LetRec(let_rec) => {
+                self.let_rec(&let_rec.bindings);
+                self.tail(&let_rec.next)
+            }
+            elaborated::ExprKind::Application(_) if self.loop_call(expr).is_some() => {
+                let (label, args) = self.loop_call(expr).unwrap();
+                let args = args.into_iter().map(|x| self.expr(x).0).collect();
+                Box::new(TermKind::Jump(label, args))
+            }
+            elaborated::ExprKind::When(when) => {
+                let scrutinee = self.scrutinee(&when.scrutinee);
+                self.when(scrutinee, &when.arms, &mut |this, expr| this.tail(expr))
+            }
+            elaborated::ExprKind::Do(block) if !block.is_empty() => {
+                for statement in &block[..block.len() - 1] {
+                    self.statement(statement);
+                }
+
+                match block.last().unwrap() {
+                    elaborated::SttmKind::Expr(expr) => self.tail(expr),
+                    statement => {
+                        self.statement(statement);
+                        Box::new(TermKind::Return(unit().0))
+                    }
+                }
+            }
+            _ => {
+                let (atom, _) = self.expr(expr);
+                Box::new(TermKind::Return(atom))
+            }
+        }
+    }
+
+    fn statement(
+        &mut self,
+        statement: &elaborated::Statement<vulpi_typer::Type<Real>>,
+    ) -> (Atom, Type) {
+        match statement {
+            elaborated::SttmKind::Let(let_) => {
+                let (atom, typ) = self.expr(&let_.expr);
+                self.destructure(atom, typ, &let_.pattern);
+                unit()
+            }
+            elaborated::SttmKind::Expr(expr) => self.expr(expr),
+            elaborated::SttmKind::Error => unreachable!(),
+        }
+    }
+
+    fn variable(&mut self, name: &Symbol) -> (Atom, Type) {
+        match self.vars.get(name) {
+            Some(binder) => (Atom::Variable(binder.name.clone()), binder.typ.clone()),
+            None => (Atom::Variable(name.clone()), TypeKind::unknown()),
+        }
+    }
+
+    fn scrutinee(&mut self, scrutinee: &[Expr]) -> Vec<Binder> {
+        scrutinee
+            .iter()
+            .map(|expr| {
+                let (atom, typ) = self.expr(expr);
+                self.occurrence(atom, typ)
+            })
+            .collect()
+    }
+
+    fn record_fields(&mut self, fields: &[(Symbol, Expr)]) -> Vec<(Symbol, Atom)> {
+        fields
+            .iter()
+            .map(|(name, expr)| (name.clone(), self.expr(expr).0))
+            .collect()
+    }
+
+    /// Lowers a lambda, merging the lambdas that are nested right inside of it into a single
+    /// lambda with many parameters.
+    fn lambda(&mut self, expr: &Expr) -> Value {
+        self.lambda_in(expr, &[])
+    }
+
+    /// Lowers a lambda of a recursive group. Core has no recursive bindings, so the lambda takes
+    /// the lambdas of the group before its own parameters and binds the names of the group by
+    /// giving them the group again, which is just a partial application.
+    fn lambda_in(&mut self, expr: &Expr, group: &[Symbol]) -> Value {
+        let knots: Vec<_> = group
+            .iter()
+            .map(|name| self.binder(&name.get(), TypeKind::unknown()))
+            .collect();
+
+        let (lambdas, current) = lambdas(expr);
+
+        let params: Vec<_> = lambdas
+            .iter()
+            .map(|param| match &***param {
+                PatternKind::Variable(name) => self.binder(&name.get(), TypeKind::unknown()),
+                _ => self.binder("x", TypeKind::unknown()),
+            })
+            .collect();
+
+        let body = self.block(|this| {
+            let group_atoms: Vec<_> = knots
+                .iter()
+                .map(|x| Atom::Variable(x.name.clone()))
+                .collect();
+
+            for (name, knot) in group.iter().zip(&knots) {
+                let func = Atom::Variable(knot.name.clone());
+                let value = this.application_of(func, group_atoms.clone());
+                this.bind_recursive(name, value);
+            }
+
+            for (param, pattern) in params.iter().zip(lambdas) {
+                let atom = Atom::Variable(param.name.clone());
+                this.destructure(atom, param.typ.clone(), pattern);
+            }
+
+            this.tail(current)
+        });
+
+        Value::Lambda(
+            knots.into_iter().chain(params).collect(),
+            unused(body, group.len()),
+        )
+    }
+
+    fn bind_recursive(&mut self, name: &Symbol, value: Value) {
+        let binder = self.binder(&name.get(), TypeKind::unknown());
+        self.frames.push(Frame::Let(binder.clone(), value));
+        self.define(name.clone(), binder);
+    }
+
+    /// Binds the functions of a recursive group, whose lambdas take the group before their own
+    /// parameters.
+    fn let_rec(&mut self, bindings: &[(Symbol, Expr)]) {
+        let group: Vec<_> = bindings.iter().map(|(name, _)| name.clone()).collect();
+
+        let lambdas: Vec<_> = bindings
+            .iter()
+            .map(|(name, body)| {
+                let value = self.lambda_in(body, &group);
+                self.bind_value(&name.get(), TypeKind::unknown(), value)
+            })
+            .collect();
+
+        for (name, lambda) in group.iter().zip(&lambdas) {
+            let value = self.application_of(lambda.clone(), lambdas.clone());
+            self.bind_recursive(name, value);
+        }
+    }
+
+    /// Lowers a group that [is_loop] into a join point that jumps to itself, which finishes the
+    /// current block.
+    fn loop_join(&mut self, let_rec: &elaborated::LetRecExpr<vulpi_typer::Type<Real>>) -> Term {
+        let (name, function) = &let_rec.bindings[0];
+        let (patterns, body) = lambdas(function);
+
+        let label = self.fresh("loop");
+        let entry = (label.clone(), patterns.len());
+        let previous = self.loops.insert(name.clone(), entry);
+
+        let params: Vec<_> = patterns
+            .iter()
+            .map(|param| match &***param {
+                PatternKind::Variable(name) => self.binder(&name.get(), TypeKind::unknown()),
+                _ => self.binder("x", TypeKind::unknown()),
+            })
+            .collect();
+
+        let body = self.block(|this| {
+            for (param, pattern) in params.iter().zip(patterns) {
+                let atom = Atom::Variable(param.name.clone());
+                this.destructure(atom, param.typ.clone(), pattern);
+            }
+
+            this.tail(body)
+        });
+
+        let rest = self.block(|this| this.tail(&let_rec.next));
+
+        match previous {
+            Some(previous) => self.loops.insert(name.clone(), previous),
+            None => self.loops.remove(name),
+        };
+
+        Box::new(TermKind::Join(label, params, body, rest))
+    }
+
+    /// The label and the arguments of a call to a function that is a join point.
+    fn loop_call<'a>(&self, expr: &'a Expr) -> Option<(Symbol, Vec<&'a Expr>)> {
+        let (func, args) = spine(expr);
+
+        match &*func.data {
+            elaborated::ExprKind::Variable(name) => {
+                let (label, arity) = self.loops.get(name)?;
+                (args.len() == *arity).then(|| (label.clone(), args))
+            }
+            _ => None,
+        }
+    }
+
+    fn application(&mut self, expr: &Expr) -> (Atom, Type) {
+        let typ = match &*expr.data {
+            elaborated::ExprKind::Application(app) => self.convert(&app.typ),
+            _ => TypeKind::unknown(),
+        };
+
+        let (current, args) = spine(expr);
+
+        let callee = match &*current.data {
+            elaborated::ExprKind::Constructor(_, name) => Some(Callee::Constructor(name.clone())),
+            elaborated::ExprKind::Function(name, _) if self.operations.contains_key(name) => {
+                Some(Callee::Perform(None, name.clone()))
+            }
+            elaborated::ExprKind::Operation(handler, name) => {
+                let (instance, _) = self.variable(handler);
+                Some(Callee::Perform(Some(instance), name.clone()))
+            }
+            _ => None,
+        };
+
+        match callee {
+            Some(callee) => {
+                let args = args.into_iter().map(|x| self.expr(x).0).collect();
+                self.call(callee, args, typ)
+            }
+            None => {
+                let (func, _) = self.expr(current);
+                let args = args.into_iter().map(|x| self.expr(x).0).collect();
+                (
+                    self.bind("a", typ.clone(), self.application_of(func, args)),
+                    typ,
+                )
+            }
+        }
+    }
+
+    /// Calls a constructor or an operation. Calls with less arguments than needed become lambdas
+    /// that wait for the rest of them.
+    fn call(&mut self, callee: Callee, mut args: Vec<Atom>, typ: Type) -> (Atom, Type) {
+        let arity = self.arity(&callee);
+
+        if args.len() < arity {
+            let params: Vec<_> = (args.len()..arity)
+                .map(|_| self.binder("a", TypeKind::unknown()))
+                .collect();
+
+            args.extend(params.iter().map(|x| Atom::Variable(x.name.clone())));
+
+            let body = self.block(|this| {
+                let (atom, _) = this.call(callee, args, typ);
+                Box::new(TermKind::Return(atom))
+            });
+
+            let value = Value::Lambda(params, body);
+            return (
+                self.bind("f", TypeKind::unknown(), value),
+                TypeKind::unknown(),
+            );
+        }
+
+        let rest = args.split_off(arity);
+
+        let value = match callee {
+            Callee::Constructor(name) => Value::Constructor(name, args),
+            Callee::Perform(instance, name) => {
+                Value::Perform(instance, name, args, self.span.clone())
+            }
+        };
+
+        if rest.is_empty() {
+            (self.bind("c", typ.clone(), value), typ)
+        } else {
+            let func = self.bind("c", TypeKind::unknown(), value);
+            (
+                self.bind("a", typ.clone(), self.application_of(func, rest)),
+                typ,
+            )
+        }
+    }
+
+    /// Lowers the arms of a `when` that match on the occurrences.
+    fn when(
+        &mut self,
+        scrutinee: Vec<Binder>,
+        arms: &[PatternArm],
+        action: &mut dyn FnMut(&mut Self, &Expr) -> Term,
+    ) -> Term {
+        let rows = arms.iter().map(|x| x.patterns.clone()).collect();
+        let guards: Vec<_> = arms.iter().map(|x| x.guard.as_ref()).collect();
+
+        self.matching(scrutinee, rows, &guards, &mut |this, i| {
+            action(this, &arms[i].expr)
+        })
+    }
+
+    /// Compiles a pattern match into a decision tree. Actions that are reachable from more than
+    /// one leaf of the tree become join points so their code is not duplicated.
+    fn matching(
+        &mut self,
+        scrutinee: Vec<Binder>,
+        rows: Vec<Vec<elaborated::Pattern>>,
+        guards: &[Option<&Expr>],
+        action: &mut dyn FnMut(&mut Self, usize) -> Term,
+    ) -> Term {
+        let has_guard: Vec<_> = guards.iter().map(|x| x.is_some()).collect();
+        let tree = pattern::compile(self, scrutinee, rows.clone(), &has_guard);
+
+        let mut counts = vec![0; rows.len()];
+        tree.count(&mut counts);
+
+        let mut joins = HashMap::new();
+        let mut bodies = Vec::new();
+
+        for (i, row) in rows.iter().enumerate() {
+            if counts[i] > 1 {
+                let mut variables = Vec::new();
+
+                for pattern in row {
+                    pattern::variables(pattern, &mut variables);
+                }
+
+                let params: Vec<_> = variables
+                    .iter()
+                    .map(|x| self.binder(&x.get(), TypeKind::unknown()))
+                    .collect();
+
+                let body = self.block(|this| {
+                    for (name, param) in variables.iter().zip(&params) {
+                        this.define(name.clone(), param.clone());
+                    }
+
+                    action(this, i)
+                });
+
+                let label = self.fresh("j");
+                joins.insert(i, (label.clone(), variables));
+                bodies.push((label, params, body));
+            }
+        }
+
+        let term = self.emit(tree, guards, &joins, action);
+
+        bodies
+            .into_iter()
+            .rfold(term, |rest, (label, params, body)| {
+                Box::new(TermKind::Join(label, params, body, rest))
+            })
+    }
+
+    fn emit(
+        &mut self,
+        tree: Tree,
+        guards: &[Option<&Expr>],
+        joins: &HashMap<usize, (Symbol, Vec<Symbol>)>,
+        action: &mut dyn FnMut(&mut Self, usize) -> Term,
+    ) -> Term {
+        match tree {
+            Tree::Fail => Box::new(TermKind::Unreachable),
+            Tree::Leaf(i, bindings) => self.leaf(i, bindings, joins, action),
+            Tree::Guard(i, bindings, otherwise) => {
+                let otherwise = self.emit(*otherwise, guards, joins, action);
+
+                self.block(|this| {
+                    for (name, binder) in &bindings {
+                        this.define(name.clone(), binder.clone());
+                    }
+
+                    let (guard, _) = this.expr(guards[i].unwrap());
+                    let body = this.leaf(i, bindings, joins, action);
+
+                    let alt = Alt {
+                        case: true_case(),
+                        binders: vec![],
+                        body,
+                    };
+
+                    Box::new(TermKind::Match(guard, vec![alt], Some(otherwise)))
+                })
+            }
+            Tree::Switch(occurrence, cases, default) => {
+                let alts = cases
+                    .into_iter()
+                    .map(|(case, binders, tree)| Alt {
+                        case,
+                        binders,
+                        body: self.emit(tree, guards, joins, action),
+                    })
+                    .collect();
+
+                let default = default.map(|x| self.emit(*x, guards, joins, action));
+
+                Box::new(TermKind::Match(
+                    Atom::Variable(occurrence.name),
+                    alts,
+                    default,
+                ))
+            }
+        }
+    }
+
+    fn leaf(
+        &mut self,
+        i: usize,
+        bindings: Vec<(Symbol, Binder)>,
+        joins: &HashMap<usize, (Symbol, Vec<Symbol>)>,
+        action: &mut dyn FnMut(&mut Self, usize) -> Term,
+    ) -> Term {
+        match joins.get(&i) {
+            Some((label, variables)) => {
+                let args = variables
+                    .iter()
+                    .map(|var| {
+                        let (_, binder) = bindings.iter().find(|(name, _)| name == var).unwrap();
+                        Atom::Variable(binder.name.clone())
+                    })
+                    .collect();
+
+                Box::new(TermKind::Jump(label.clone(), args))
+            }
+            None => self.block(|this| {
+                for (name, binder) in bindings {
+                    this.define(name, binder);
+                }
+
+                action(this, i)
+            }),
+        }
+    }
+
+    /// Lowers a handler expression. Just like in the Lambda IR, the handled expression becomes a
+    /// thunk and each operation gets a clause that receives its arguments. The `finally` becomes a
+    /// thunk without parameters that the backend runs when the handler is left.
+    fn handler(
+        &mut self,
+        handler: &elaborated::HandlerExpr<vulpi_typer::Type<Real>>,
+    ) -> (Atom, Type) {
+        let finally = handler.finally.as_ref().map(|finally| {
+            let body = self.block(|this| this.tail(finally));
+            self.bind_value("f", TypeKind::unknown(), Value::Lambda(vec![], body))
+        });
+
+        let arms = match &handler.handler {
+            Handler::Function(func) if finally.is_none() => {
+                let (func, _) = self.expr(func);
+                let (expr, _) = self.expr(&handler.expr);
+                let value = self.application_of(func, vec![expr]);
+                return (
+                    self.bind("h", TypeKind::unknown(), value),
+                    TypeKind::unknown(),
+                );
+            }
+            Handler::Function(func) => {
+                // The function runs inside of a handler without clauses, so the `finally` runs
+                // even when an operation that it doesn't handle aborts it.
+                let (func, _) = self.expr(func);
+
+                let body = self.block(|this| {
+                    let (expr, _) = this.expr(&handler.expr);
+                    let value = this.application_of(func, vec![expr]);
+                    let result = this.bind("h", TypeKind::unknown(), value);
+                    Box::new(TermKind::Return(result))
+                });
+
+                let thunk = self.bind_value("t", TypeKind::unknown(), Value::Lambda(vec![], body));
+                let ret = self.return_clause(vec![]);
+
+                let value = Value::Handle(thunk, vec![], ret, finally);
+                return (
+                    self.bind("h", TypeKind::unknown(), value),
+                    TypeKind::unknown(),
+                );
+            }
+            Handler::Cases(arms) => arms,
+        };
+
+        let params: Vec<_> = handler
+            .name
+            .iter()
+            .map(|name| (name.clone(), self.binder(&name.get(), TypeKind::unknown())))
+            .collect();
+
+        let body = self.block(|this| {
+            for (name, param) in &params {
+                this.define(name.clone(), param.clone());
+            }
+
+            this.tail(&handler.expr)
+        });
+
+        let params = params.into_iter().map(|x| x.1).collect();
+        let thunk = self.bind_value("t", TypeKind::unknown(), Value::Lambda(params, body));
+
+        let mut operations: Vec<(&PatEffect, Vec<&PatternArm>)> = vec![];
+        let mut returns = vec![];
+
+        for arm in arms {
+            match &*arm.patterns[0] {
+                PatternKind::Effect(eff) => {
+                    match operations.iter_mut().find(|(op, _)| op.func == eff.func) {
+                        Some((_, arms)) => arms.push(arm),
+                        None => operations.push((eff, vec![arm])),
+                    }
+                }
+                _ => returns.push(arm),
+            }
+        }
+
+        let mut clauses = Vec::new();
+
+        for (eff, arms) in operations {
+            let default = default_of(handler, &eff.func);
+            clauses.push(self.clause(&eff.func, arms, default));
+        }
+
+        // Operations that are not matched by any arm are handled by their default.
+        for (func, default) in &handler.defaults {
+            if clauses.iter().all(|(name, _, _)| name != func) {
+                clauses.push(self.clause(func, vec![], Some(default)));
+            }
+        }
+
+        let ret = self.return_clause(returns);
+
+        let value = Value::Handle(thunk, clauses, ret, finally);
+        (
+            self.bind("h", TypeKind::unknown(), value),
+            TypeKind::unknown(),
+        )
+    }
+
+    /// Compiles the arms that match on the same operation into a lambda that receives the
+    /// arguments of the operation and, for `ctl` operations, the continuation.
+    fn clause(
+        &mut self,
+        func: &Qualified,
+        arms: Vec<&PatternArm>,
+        default: Option<&Qualified>,
+    ) -> (Qualified, OperationKind, Atom) {
+        let (arity, kind) = self.operations.get(func).cloned().unwrap();
+
+        let mut params: Vec<_> = (0..arity)
+            .map(|_| self.binder("a", TypeKind::unknown()))
+            .collect();
+
+        let cont = match kind {
+            OperationKind::Ctl => Some(self.binder("k", TypeKind::unknown())),
+            OperationKind::Fun => None,
+        };
+
+        params.extend(cont.clone());
+
+        let mut rows = Vec::new();
+
+        for arm in &arms {
+            let PatternKind::Effect(eff) = &*arm.patterns[0] else {
+                unreachable!()
+            };
+
+            let mut row = eff.args.clone();
+
+            if cont.is_some() {
+                row.push(Box::new(match &eff.cont {
+                    Some(name) => PatternKind::Variable(name.clone()),
+                    None => PatternKind::Wildcard,
+                }));
+            }
+
+            rows.push(row);
+        }
+
+        // Requests that are not matched by any arm go to the default implementation of the
+        // operation or, if there is none, are performed again.
+        rows.push(vec![Box::new(PatternKind::Wildcard); params.len()]);
+
+        let mut guards: Vec<_> = arms.iter().map(|x| x.guard.as_ref()).collect();
+        guards.push(None);
+
+        let args: Vec<_> = params[..arity]
+            .iter()
+            .map(|x| Atom::Variable(x.name.clone()))
+            .collect();
+
+        let body = self.block(|this| {
+            this.matching(params.clone(), rows, &guards, &mut |this, i| {
+                if let Some(arm) = arms.get(i) {
+                    return this.tail(&arm.expr);
+                }
+
+                let result = match default {
+                    Some(default) if args.is_empty() => {
+                        this.function(default, &TypeKind::unknown())
+                    }
+                    Some(default) => {
+                        let func = this.function(default, &TypeKind::unknown());
+                        let value = this.application_of(func, args.clone());
+                        this.bind("r", TypeKind::unknown(), value)
+                    }
+                    None => {
+                        let value =
+                            Value::Perform(None, func.clone(), args.clone(), this.span.clone());
+                        this.bind("r", TypeKind::unknown(), value)
+                    }
+                };
+
+                let result = match &cont {
+                    Some(cont) => {
+                        let cont = Atom::Variable(cont.name.clone());
+                        let value = this.application_of(cont, vec![result]);
+                        this.bind("r", TypeKind::unknown(), value)
+                    }
+                    None => result,
+                };
+
+                Box::new(TermKind::Return(result))
+            })
+        });
+
+        let clause = self.bind_value("c", TypeKind::unknown(), Value::Lambda(params, body));
+
+        (func.clone(), kind, clause)
+    }
+
+    /// Compiles the arms that match on the final value of the handled computation. Values that
+    /// are not matched go through the handler untouched.
+    fn return_clause(&mut self, arms: Vec<&PatternArm>) -> Atom {
+        let value = self.binder("v", TypeKind::unknown());
+
+        let mut rows: Vec<_> = arms.iter().map(|x| x.patterns.clone()).collect();
+        rows.push(vec![Box::new(PatternKind::Wildcard)]);
+
+        let mut guards: Vec<_> = arms.iter().map(|x| x.guard.as_ref()).collect();
+        guards.push(None);
+
+        let body = self.block(|this| {
+            this.matching(
+                vec![value.clone()],
+                rows,
+                &guards,
+                &mut |this, i| match arms.get(i) {
+                    Some(arm) => this.tail(&arm.expr),
+                    None => Box::new(TermKind::Return(Atom::Variable(value.name.clone()))),
+                },
+            )
+        });
+
+        let lambda = Value::Lambda(vec![value.clone()], body);
+        self.bind_value("r", TypeKind::unknown(), lambda)
+    }
+
+    fn declare_type(
+        &mut self,
+        name: &Qualified,
+        decl: &elaborated::TypeDecl,
+        program: &mut Program,
+    ) {
+        match decl {
+            elaborated::TypeDecl::Abstract => program.types.push(TypeDecl {
+                name: name.clone(),
+                constructors: vec![],
+            }),
+            elaborated::TypeDecl::Enum(constructors) => {
+                for (constructor, arity) in constructors {
+                    let siblings = constructors.len();
+                    self.constructors
+                        .insert(constructor.clone(), (*arity, siblings));
+                }
+
+                program.types.push(TypeDecl {
+                    name: name.clone(),
+                    constructors: constructors.clone(),
+                })
+            }
+            elaborated::TypeDecl::Record(fields) => {
+                for (i, field) in fields.iter().enumerate() {
+                    self.fields.insert(field.clone(), (name.clone(), i));
+                }
+
+                self.records.insert(name.clone(), fields.clone());
+                self.constructors.insert(name.clone(), (fields.len(), 1));
+
+                program.types.push(TypeDecl {
+                    name: name.clone(),
+                    constructors: vec![(name.clone(), fields.len())],
+                })
+            }
+            elaborated::TypeDecl::Effect(operations) => {
+                for (operation, arity, kind, _) in operations {
+                    self.operations.insert(operation.clone(), (*arity, *kind));
+                }
+
+                program.effects.push(EffectDecl {
+                    name: name.clone(),
+                    operations: operations
+                        .iter()
+                        .map(|(name, arity, kind, _)| (name.clone(), *arity, *kind))
+                        .collect(),
+                })
+            }
+        }
+    }
+
+    fn let_decl(&mut self, decl: &elaborated::LetDecl<vulpi_typer::Type<Real>>) -> LetDecl {
+        let typ = self::typ(&decl.typ, 0);
+        let (types, _) = typ.arrow_spine();
+
+        self.depth = typ.binders();
+        self.span = decl.span.clone();
+
+        let mut types = types.into_iter();
+        let mut next_type = || types.next().unwrap_or_else(TypeKind::unknown);
+
+        let binders: Vec<_> = decl
+            .binders
+            .iter()
+            .map(|(pattern, _)| {
+                let typ = next_type();
+                match &**pattern {
+                    PatternKind::Variable(name) => self.binder(&name.get(), typ),
+                    _ => self.binder("p", typ),
+                }
+            })
+            .collect();
+
+        let columns: Vec<_> = decl.body[0]
+            .patterns
+            .iter()
+            .map(|pattern| {
+                let typ = next_type();
+                match &**pattern {
+                    PatternKind::Variable(name) if decl.body.len() == 1 => {
+                        self.binder(&name.get(), typ)
+                    }
+                    _ => self.binder("v", typ),
+                }
+            })
+            .collect();
+
+        let body = self.block(|this| {
+            for (binder, (pattern, _)) in binders.iter().zip(&decl.binders) {
+                let atom = Atom::Variable(binder.name.clone());
+                this.destructure(atom, binder.typ.clone(), pattern);
+            }
+
+            if columns.is_empty() {
+                this.tail(&decl.body[0].expr)
+            } else {
+                this.when(columns.clone(), &decl.body, &mut |this, expr| {
+                    this.tail(expr)
+                })
+            }
+        });
+
+        let mut params = binders;
+        params.extend(columns);
+
+        LetDecl {
+            name: decl.name.clone(),
+            span: decl.span.clone(),
+            visibility: decl.visibility.clone(),
+            typ,
+            params,
+            body,
+            declaration: decl.declaration.clone(),
+            allow: decl.allow.clone(),
+        }
+    }
+}
+
+/// The function and the arguments of an application.
+fn spine(expr: &Expr) -> (&Expr, Vec<&Expr>) {
+    let mut args = Vec::new();
+    let mut current = expr;
+
+    while let elaborated::ExprKind::Application(app) = &*current.data {
+        args.push(&app.args);
+        current = &app.func;
+    }
+
+    args.reverse();
+    (current, args)
+}
+
+/// The parameters and the body of a function.
+fn lambdas(expr: &Expr) -> (Vec<&elaborated::Pattern>, &Expr) {
+    let mut params = Vec::new();
+    let mut current = expr;
+
+    while let elaborated::ExprKind::Lambda(lambda) = &*current.data {
+        params.push(&lambda.param);
+        current = &lambda.body;
+    }
+
+    (params, current)
+}
+
+fn binds(pattern: &PatternKind, name: &Symbol) -> bool {
+    match pattern {
+        PatternKind::Variable(x) => x == name,
+        PatternKind::Application(app) => app.args.iter().any(|x| binds(x, name)),
+        PatternKind::Effect(effect) => {
+            effect.cont.as_ref() == Some(name) || effect.args.iter().any(|x| binds(x, name))
+        }
+        PatternKind::Tuple(args) | PatternKind::Or(args) => args.iter().any(|x| binds(x, name)),
+        PatternKind::As(x, pattern) => x == name || binds(pattern, name),
+        PatternKind::Wildcard
+        | PatternKind::Literal(_)
+        | PatternKind::Range(_, _)
+        | PatternKind::Error => false,
+    }
+}
+
+/// Checks if the only uses of the name in the expression are calls in tail position with all of
+/// the parameters, and that nothing binds the name again. Outside of tail position, it checks
+/// that the expression doesn't use the name at all.
+fn tail_calls(expr: &Expr, name: &Symbol, arity: usize, tail: bool) -> bool {
+    let unused = |expr: &Expr| tail_calls(expr, name, arity, false);
+
+    match &*expr.data {
+        elaborated::ExprKind::Application(_) => {
+            let (func, args) = spine(expr);
+
+            let call = match &*func.data {
+                elaborated::ExprKind::Variable(x) if x == name => tail && args.len() == arity,
+                _ => unused(func),
+            };
+
+            call && args.into_iter().all(unused)
+        }
+        elaborated::ExprKind::Variable(x) => x != name,
+        elaborated::ExprKind::Lambda(lambda) => !binds(&lambda.param, name) && unused(&lambda.body),
+        elaborated::ExprKind::Let(let_expr) => {
+            !binds(&let_expr.pattern, name)
+                && unused(&let_expr.body)
+                && tail_calls(&let_expr.next, name, arity, tail)
+        }
+        elaborated::ExprKind::LetRec(let_rec) => {
+            let_rec
+                .bindings
+                .iter()
+                .all(|(x, body)| x != name && unused(body))
+                && tail_calls(&let_rec.next, name, arity, tail)
+        }
+        elaborated::ExprKind::When(when) => {
+            when.scrutinee.iter().all(unused)
+                && when.arms.iter().all(|arm| {
+                    !arm.patterns.iter().any(|x| binds(x, name))
+                        && arm.guard.iter().all(unused)
+                        && tail_calls(&arm.expr, name, arity, tail)
+                })
+        }
+        elaborated::ExprKind::Do(block) => block.iter().enumerate().all(|(i, statement)| {
+            let last = i + 1 == block.len();
+
+            match statement {
+                elaborated::SttmKind::Let(let_) => {
+                    !binds(&let_.pattern, name) && unused(&let_.expr)
+                }
+                elaborated::SttmKind::Expr(expr) => tail_calls(expr, name, arity, tail && last),
+                elaborated::SttmKind::Error => true,
+            }
+        }),
+        elaborated::ExprKind::Handler(handler) => {
+            let handled = match &handler.handler {
+                Handler::Cases(arms) => arms.iter().all(|arm| {
+                    !arm.patterns.iter().any(|x| binds(x, name))
+                        && arm.guard.iter().all(unused)
+                        && unused(&arm.expr)
+                }),
+                Handler::Function(function) => unused(function),
+            };
+
+            handler.name.as_ref() != Some(name)
+                && handled
+                && unused(&handler.expr)
+                && handler.finally.iter().all(unused)
+        }
+        elaborated::ExprKind::Mask(mask) => unused(&mask.expr),
+        elaborated::ExprKind::Projection(projection) => unused(&projection.expr),
+        elaborated::ExprKind::RecordInstance(instance) => {
+            instance.fields.iter().all(|(_, x)| unused(x))
+        }
+        elaborated::ExprKind::RecordUpdate(update) => {
+            unused(&update.expr) && update.fields.iter().all(|(_, x)| unused(x))
+        }
+        elaborated::ExprKind::Tuple(tuple) => tuple.exprs.iter().all(unused),
+        elaborated::ExprKind::Operation(handler, _) => handler != name,
+        elaborated::ExprKind::Constructor(..)
+        | elaborated::ExprKind::Function(..)
+        | elaborated::ExprKind::Literal(_)
+        | elaborated::ExprKind::Error => true,
+    }
+}
+
+/// Checks if a recursive group is a single function that is only called in tail position with all
+/// of its parameters, like the functions of loops. Its calls can be jumps to a join point instead
+/// of calls to a closure, so the loop doesn't use the stack.
+fn is_loop(let_rec: &elaborated::LetRecExpr<vulpi_typer::Type<Real>>) -> bool {
+    let [(name, function)] = &let_rec.bindings[..] else {
+        return false;
+    };
+
+    let (params, body) = lambdas(function);
+
+    !params.is_empty()
+        && !params.iter().any(|x| binds(x, name))
+        && tail_calls(body, name, params.len(), true)
+        && tail_calls(&let_rec.next, name, params.len(), true)
+}
+
+/// Replaces the returns of a term by jumps to the label, so the term gives its result to the
+/// join point instead of returning from the function. The lambdas return from themselves.
+fn exits(term: &mut TermKind, label: &Symbol) {
+    match term {
+        TermKind::Let(_, _, rest) => exits(rest, label),
+        TermKind::Join(_, _, body, rest) => {
+            exits(body, label);
+            exits(rest, label);
+        }
+        TermKind::Match(_, alts, default) => {
+            alts.iter_mut().for_each(|x| exits(&mut x.body, label));
+            default.iter_mut().for_each(|x| exits(x, label));
+        }
+        TermKind::Return(atom) => {
+            let atom = atom.clone();
+            *term = TermKind::Jump(label.clone(), vec![atom]);
+        }
+        TermKind::Jump(..) | TermKind::Tail(..) | TermKind::Unreachable => (),
+    }
+}
+
+/// Removes the bindings of the names of a recursive group that start the body of one of its
+/// lambdas when the body doesn't use them.
+fn unused(mut body: Term, count: usize) -> Term {
+    let mut bindings = Vec::new();
+
+    for _ in 0..count {
+        match *body {
+            TermKind::Let(binder, value, rest) => {
+                bindings.push((binder, value));
+                body = rest;
+            }
+            _ => unreachable!(),
+        }
+    }
+
+    bindings.into_iter().rfold(body, |rest, (binder, value)| {
+        let mut used = false;
+
+        visit_term(&rest, &mut |atom| {
+            used |= matches!(atom, Atom::Variable(x) if *x == binder.name);
+        });
+
+        if used {
+            Box::new(TermKind::Let(binder, value, rest))
+        } else {
+            rest
+        }
+    })
+}
+
+/// Finds the types that the variables bound outside of the pattern must have for it to be equal
+/// to the type. The offset is the number of binders that were crossed.
+fn arguments(pattern: &TypeKind, typ: &TypeKind, offset: usize, args: &mut [Option<Type>]) {
+    match (pattern, typ) {
+        (TypeKind::Bound(index), _) if *index >= offset && *index - offset < args.len() => {
+            let position = args.len() - 1 - (*index - offset);
+            args[position].get_or_insert_with(|| Box::new(typ.clone()));
+        }
+        (TypeKind::Arrow(l_param, l_body), TypeKind::Arrow(r_param, r_body)) => {
+            arguments(l_param, r_param, offset, args);
+            arguments(l_body, r_body, offset, args);
+        }
+        (TypeKind::Tuple(l), TypeKind::Tuple(r)) if l.len() == r.len() => {
+            for (l, r) in l.iter().zip(r) {
+                arguments(l, r, offset, args);
+            }
+        }
+        (TypeKind::Application(l_func, l), TypeKind::Application(r_func, r))
+            if l.len() == r.len() =>
+        {
+            arguments(l_func, r_func, offset, args);
+
+            for (l, r) in l.iter().zip(r) {
+                arguments(l, r, offset, args);
+            }
+        }
+        (TypeKind::Forall(_, l), TypeKind::Forall(_, r)) => arguments(l, r, offset + 1, args),
+        _ => (),
+    }
+}
+
+fn default_of<'a>(
+    handler: &'a elaborated::HandlerExpr<vulpi_typer::Type<Real>>,
+    func: &Qualified,
+) -> Option<&'a Qualified> {
+    handler
+        .defaults
+        .iter()
+        .find(|(name, _)| name == func)
+        .map(|(_, default)| default)
+}
+
+fn collect<'a>(
+    programs: &'a [elaborated::Program<vulpi_typer::Type<Real>>],
+    result: &mut Vec<&'a elaborated::Program<vulpi_typer::Type<Real>>>,
+) {
+    for program in programs {
+        result.push(program);
+
+        for module in program.modules.values() {
+            collect(std::slice::from_ref(module), result);
+        }
+    }
+}
+
+/// Lowers the elaborated programs of all the modules into a single core program. The declarations
+/// are lowered in the order of their names, so the output does not depend on the order that the
+/// modules were checked in.
+pub fn lower(programs: &[elaborated::Program<vulpi_typer::Type<Real>>]) -> Program {
+    let mut programs_ = Vec::new();
+    collect(programs, &mut programs_);
+
+    let mut ctx = Context::default();
+    let mut program = Program::default();
+
+    for elab in &programs_ {
+        for (name, decl) in &elab.types {
+            ctx.declare_type(name, decl, &mut program);
+        }
+    }
+
+    for elab in &programs_ {
+        for (name, external) in &elab.externals {
+            program.externals.push(ExternalDecl {
+                name: name.clone(),
+                typ: typ(&external.typ, 0),
+                effect: external.effect.clone(),
+                convention: external.convention.clone(),
+                binding: external.binding.clone(),
+            });
+        }
+
+        program.commands.extend(elab.commands.iter().cloned());
+    }
+
+    // The operations of the primitive effects are externals bound to primitives of the backends.
+    for (effect, name, real, binding) in vulpi_typer::primitive_operations() {
+        ctx.schemes.insert(name.clone(), typ(&real, 0));
+
+        program.externals.push(ExternalDecl {
+            name,
+            typ: typ(&real, 0),
+            effect,
+            convention: None,
+            binding: Symbol::intern(binding),
+        });
+    }
+
+    // The operations of the `Async` effect are performed like the ones of the declared effects.
+    let operations = vulpi_typer::async_operations()
+        .into_iter()
+        .map(|(name, real)| {
+            let arity = typ(&real, 0).arrow_spine().0.len();
+            (name, arity, OperationKind::Ctl, None)
+        })
+        .collect();
+
+    let task = elaborated::TypeDecl::Abstract;
+    ctx.declare_type(&vulpi_typer::task_type(), &task, &mut program);
+
+    let effect = elaborated::TypeDecl::Effect(operations);
+    ctx.declare_type(&vulpi_typer::async_effect(), &effect, &mut program);
+
+    for elab in &programs_ {
+        for (name, decl) in &elab.lets {
+            ctx.schemes.insert(name.clone(), typ(&decl.typ, 0));
+        }
+
+        for (name, external) in &elab.externals {
+            ctx.schemes.insert(name.clone(), typ(&external.typ, 0));
+        }
+    }
+
+    for elab in &programs_ {
+        for decl in elab.lets.values() {
+            let decl = ctx.let_decl(decl);
+            program.lets.push(decl);
+        }
+    }
+
+    for size in &ctx.tuples {
+        program.types.push(TypeDecl {
+            name: tuple(*size),
+            constructors: vec![(tuple(*size), *size)],
+        });
+    }
+
+    program.names = ctx.counter;
+    crate::tail::optimize(&mut program);
+
+    program
+}
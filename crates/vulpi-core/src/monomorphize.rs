@@ -0,0 +1,321 @@
+//! Specialization of polymorphic functions. Every function that is reachable from a monomorphic
+//! declaration gets one copy for each list of types that it's instantiated to, so the resulting
+//! program has no quantified let declarations and backends can choose representations by type.
+//!
+//! Polymorphic recursion, where a function calls itself with a type that grows at each call,
+//! would need an infinite number of copies. It's detected before specializing anything by looking
+//! for cycles between the type parameters of the functions.
+
+use std::collections::{HashMap, HashSet, VecDeque};
+
+use vulpi_intern::Symbol;
+use vulpi_report::{Diagnostic, Report};
+use vulpi_syntax::r#abstract::Qualified;
+
+use crate::{
+    errors::{CoreError, CoreErrorKind},
+    syntax::*,
+};
+
+/// The variables bound outside of the type that occur in it. The offset is the number of binders
+/// that were crossed.
+fn free_variables(typ: &TypeKind, offset: usize, variables: &mut Vec<usize>) {
+    match typ {
+        TypeKind::Bound(index) if *index >= offset => variables.push(*index - offset),
+        TypeKind::Arrow(param, body) => {
+            free_variables(param, offset, variables);
+            free_variables(body, offset, variables);
+        }
+        TypeKind::Tuple(types) => {
+            for typ in types {
+                free_variables(typ, offset, variables);
+            }
+        }
+        TypeKind::Application(func, args) => {
+            free_variables(func, offset, variables);
+
+            for arg in args {
+                free_variables(arg, offset, variables);
+            }
+        }
+        TypeKind::Forall(_, body) => free_variables(body, offset + 1, variables),
+        _ => (),
+    }
+}
+
+/// A type parameter of a let declaration, numbered from the outermost quantifier.
+type Parameter = (Qualified, usize);
+
+/// Finds the let declarations that have polymorphic recursion. The graph has an edge from a type
+/// parameter of a function to each type parameter of a callee whose argument mentions it, and the
+/// edge grows when the argument is not just the parameter. A cycle that goes through a growing
+/// edge instantiates the functions with bigger and bigger types.
+fn polymorphic_recursion(program: &Program) -> Vec<&LetDecl> {
+    let lets: HashMap<_, _> = program.lets.iter().map(|x| (x.name.clone(), x)).collect();
+    let mut edges: HashMap<Parameter, Vec<(Parameter, bool)>> = HashMap::new();
+
+    for decl in &program.lets {
+        let binders = decl.typ.binders();
+
+        if binders == 0 {
+            continue;
+        }
+
+        visit_term(&decl.body, &mut |atom| {
+            let Atom::Function(name, args) = atom else {
+                return;
+            };
+
+            if !lets.contains_key(name) {
+                return;
+            }
+
+            for (j, arg) in args.iter().enumerate() {
+                let mut variables = Vec::new();
+                free_variables(arg, 0, &mut variables);
+
+                for index in variables.into_iter().filter(|x| *x < binders) {
+                    let from = (decl.name.clone(), binders - 1 - index);
+                    let grows = **arg != TypeKind::Bound(index);
+                    edges
+                        .entry(from)
+                        .or_default()
+                        .push(((name.clone(), j), grows));
+                }
+            }
+        });
+    }
+
+    let reaches = |from: &Parameter, to: &Parameter| {
+        let mut visited = HashSet::new();
+        let mut stack = vec![from.clone()];
+
+        while let Some(current) = stack.pop() {
+            if &current == to {
+                return true;
+            }
+
+            if visited.insert(current.clone()) {
+                for (next, _) in edges.get(&current).into_iter().flatten() {
+                    stack.push(next.clone());
+                }
+            }
+        }
+
+        false
+    };
+
+    program
+        .lets
+        .iter()
+        .filter(|decl| {
+            (0..decl.typ.binders()).any(|i| {
+                let from = (decl.name.clone(), i);
+                edges
+                    .get(&from)
+                    .into_iter()
+                    .flatten()
+                    .any(|(to, grows)| *grows && reaches(to, &from))
+            })
+        })
+        .collect()
+}
+
+struct Monomorphizer<'a> {
+    lets: HashMap<Qualified, &'a LetDecl>,
+    instances: HashMap<(Qualified, Vec<Type>), Qualified>,
+    counts: HashMap<Qualified, usize>,
+    queue: VecDeque<(&'a LetDecl, Vec<Type>, Qualified)>,
+}
+
+impl<'a> Monomorphizer<'a> {
+    /// The name of the copy of the function for the types. Instantiations with the same types
+    /// share the same copy.
+    fn instance(&mut self, name: &Qualified, args: Vec<Type>) -> Qualified {
+        let Some(decl) = self.lets.get(name).cloned() else {
+            return name.clone();
+        };
+
+        if args.is_empty() {
+            return name.clone();
+        }
+
+        let key = (name.clone(), args);
+
+        if let Some(instance) = self.instances.get(&key) {
+            return instance.clone();
+        }
+
+        let count = self.counts.entry(name.clone()).or_default();
+        let instance = Qualified {
+            path: name.path.clone(),
+            name: Symbol::intern(&format!("{}.{}", name.name.get(), count)),
+        };
+
+        *count += 1;
+
+        self.instances.insert(key.clone(), instance.clone());
+        self.queue.push_back((decl, key.1, instance.clone()));
+
+        instance
+    }
+
+    fn binder(&mut self, binder: &Binder, args: &[Type]) -> Binder {
+        Binder {
+            name: binder.name.clone(),
+            typ: binder.typ.substitute(args, 0),
+        }
+    }
+
+    fn atom(&mut self, atom: &Atom, args: &[Type]) -> Atom {
+        match atom {
+            Atom::Function(name, types) => {
+                let types: Vec<_> = types.iter().map(|x| x.substitute(args, 0)).collect();
+
+                if self.lets.contains_key(name) {
+                    Atom::Function(self.instance(name, types), vec![])
+                } else {
+                    Atom::Function(name.clone(), types)
+                }
+            }
+            atom => atom.clone(),
+        }
+    }
+
+    fn atoms(&mut self, atoms: &[Atom], args: &[Type]) -> Vec<Atom> {
+        atoms.iter().map(|x| self.atom(x, args)).collect()
+    }
+
+    fn value(&mut self, value: &Value, args: &[Type]) -> Value {
+        match value {
+            Value::Atom(atom) => Value::Atom(self.atom(atom, args)),
+            Value::Lambda(params, body) => Value::Lambda(
+                params.iter().map(|x| self.binder(x, args)).collect(),
+                self.term(body, args),
+            ),
+            Value::Application(func, params, span) => Value::Application(
+                self.atom(func, args),
+                self.atoms(params, args),
+                span.clone(),
+            ),
+            Value::Constructor(name, params) => {
+                Value::Constructor(name.clone(), self.atoms(params, args))
+            }
+            Value::Field(name, atom, index) => {
+                Value::Field(name.clone(), self.atom(atom, args), *index)
+            }
+            Value::Perform(instance, name, params, span) => Value::Perform(
+                instance.as_ref().map(|x| self.atom(x, args)),
+                name.clone(),
+                self.atoms(params, args),
+                span.clone(),
+            ),
+            Value::Handle(thunk, clauses, ret, finally) => Value::Handle(
+                self.atom(thunk, args),
+                clauses
+                    .iter()
+                    .map(|(name, kind, clause)| (name.clone(), *kind, self.atom(clause, args)))
+                    .collect(),
+                self.atom(ret, args),
+                finally.as_ref().map(|x| self.atom(x, args)),
+            ),
+            Value::Mask(operations, thunk) => {
+                Value::Mask(operations.clone(), self.atom(thunk, args))
+            }
+        }
+    }
+
+    fn term(&mut self, term: &TermKind, args: &[Type]) -> Term {
+        Box::new(match term {
+            TermKind::Let(binder, value, rest) => TermKind::Let(
+                self.binder(binder, args),
+                self.value(value, args),
+                self.term(rest, args),
+            ),
+            TermKind::Join(label, params, body, rest) => TermKind::Join(
+                label.clone(),
+                params.iter().map(|x| self.binder(x, args)).collect(),
+                self.term(body, args),
+                self.term(rest, args),
+            ),
+            TermKind::Jump(label, params) => {
+                TermKind::Jump(label.clone(), self.atoms(params, args))
+            }
+            TermKind::Tail(func, params, span) => TermKind::Tail(
+                self.atom(func, args),
+                self.atoms(params, args),
+                span.clone(),
+            ),
+            TermKind::Match(atom, alts, default) => TermKind::Match(
+                self.atom(atom, args),
+                alts.iter()
+                    .map(|alt| Alt {
+                        case: alt.case.clone(),
+                        binders: alt.binders.iter().map(|x| self.binder(x, args)).collect(),
+                        body: self.term(&alt.body, args),
+                    })
+                    .collect(),
+                default.as_ref().map(|x| self.term(x, args)),
+            ),
+            TermKind::Return(atom) => TermKind::Return(self.atom(atom, args)),
+            TermKind::Unreachable => TermKind::Unreachable,
+        })
+    }
+
+    fn let_decl(&mut self, decl: &LetDecl, args: &[Type], name: Qualified) -> LetDecl {
+        LetDecl {
+            name,
+            span: decl.span.clone(),
+            visibility: decl.visibility.clone(),
+            typ: decl.typ.instantiate(args),
+            params: decl.params.iter().map(|x| self.binder(x, args)).collect(),
+            body: self.term(&decl.body, args),
+            declaration: decl.declaration.clone(),
+            allow: decl.allow.clone(),
+        }
+    }
+}
+
+/// Specializes the polymorphic functions of the program for each of their instantiations. The
+/// declarations without quantifiers are the roots, so polymorphic functions that are never used
+/// by them are removed. Returns nothing if some function has polymorphic recursion.
+pub fn monomorphize(reporter: Report, program: &Program) -> Option<Program> {
+    let recursive = polymorphic_recursion(program);
+
+    for decl in &recursive {
+        reporter.report(Diagnostic::new(CoreError {
+            span: decl.span.clone(),
+            kind: CoreErrorKind::PolymorphicRecursion(decl.name.clone()),
+        }));
+    }
+
+    if !recursive.is_empty() {
+        return None;
+    }
+
+    let mut ctx = Monomorphizer {
+        lets: program.lets.iter().map(|x| (x.name.clone(), x)).collect(),
+        instances: HashMap::new(),
+        counts: HashMap::new(),
+        queue: VecDeque::new(),
+    };
+
+    let mut lets = Vec::new();
+
+    for decl in program.lets.iter().filter(|x| x.typ.binders() == 0) {
+        lets.push(ctx.let_decl(decl, &[], decl.name.clone()));
+    }
+
+    while let Some((decl, args, name)) = ctx.queue.pop_front() {
+        lets.push(ctx.let_decl(decl, &args, name));
+    }
+
+    Some(Program {
+        types: program.types.clone(),
+        effects: program.effects.clone(),
+        externals: program.externals.clone(),
+        lets,
+        commands: program.commands.clone(),
+        names: program.names,
+    })
+}
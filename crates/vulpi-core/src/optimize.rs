@@ -0,0 +1,1011 @@
+//! Optimizer of the core language. It simplifies the let declarations a few times: variables that
+//! are bound to atoms are replaced by the atoms, primitives applied to literals are folded, matches
+//! on known constructors and literals choose their alternative, small functions and lambdas that
+//! are used once are inlined and lets of pure values that are never used are removed. The
+//! declarations without parameters whose values are computed by [crate::eval] are replaced by
+//! their values where they are used.
+//!
+//! The level says how much is done. Level 0 does nothing and the higher levels inline bigger
+//! functions. Functions that can call themselves back are never inlined, and the amount of code
+//! that is inlined into each declaration is bounded, so the program cannot grow without a bound.
+
+use std::collections::{HashMap, HashSet};
+
+use vulpi_intern::Symbol;
+use vulpi_syntax::{elaborated::LiteralKind, r#abstract::Qualified};
+use vulpi_typer::number::Number;
+
+use crate::{
+    eval::{self, Static},
+    primitive::Primitive,
+    syntax::*,
+};
+
+/// The number of times that the simplifier runs over the program.
+const ROUNDS: usize = 3;
+
+/// The biggest size of a function that is inlined at the level.
+fn threshold(level: usize) -> usize {
+    match level {
+        0 => 0,
+        1 => 8,
+        _ => 32,
+    }
+}
+
+#[derive(Clone, PartialEq)]
+pub(crate) enum Constant {
+    Int(i64),
+    String(Symbol),
+    Char(Symbol),
+}
+
+/// The constant of an atom. Floats are not folded because each backend prints them differently.
+pub(crate) fn constant(atom: &Atom) -> Option<Constant> {
+    let Atom::Literal(literal) = atom else {
+        return None;
+    };
+
+    match &**literal {
+        LiteralKind::Integer(x) => x.get().replace('_', "").parse().ok().map(Constant::Int),
+        LiteralKind::String(x) => Some(Constant::String(x.clone())),
+        LiteralKind::Char(x) => Some(Constant::Char(x.clone())),
+        _ => None,
+    }
+}
+
+/// Checks if the constant is one of the values of a case of literals, or nothing if the case
+/// can't be compared at compile time.
+pub(crate) fn selects(case: &Case, scrutinee: &Constant) -> Option<bool> {
+    match case {
+        Case::Literal(literal) => Some(constant(&Atom::Literal(literal.clone()))? == *scrutinee),
+        Case::Range(start, end) => {
+            let value = match scrutinee {
+                Constant::Int(x) => *x,
+                Constant::Char(x) => x.get().chars().next()? as i64,
+                Constant::String(_) => return None,
+            };
+
+            Some(start.ordinal()? <= value && value <= end.ordinal()?)
+        }
+        Case::Constructor(_) => None,
+    }
+}
+
+pub(crate) enum Folded {
+    Literal(LiteralKind),
+    Bool(bool),
+}
+
+/// Computes the primitive on constants. Nothing is folded when the result could depend on the
+/// backend, like overflows and divisions that are not exact, or when the primitive would fail.
+pub(crate) fn fold(primitive: Primitive, args: &[Constant]) -> Option<Folded> {
+    let int = |x: Option<i64>| {
+        x.map(|x| Folded::Literal(LiteralKind::Integer(Symbol::intern(&x.to_string()))))
+    };
+
+    let char = |x: char| Folded::Literal(LiteralKind::Char(Symbol::intern(&x.to_string())));
+
+    if let (Primitive::Convert(number @ Number::Int { .. }), [Constant::Int(value)]) =
+        (primitive, args)
+    {
+        return int(Some(number.wrap(*value)));
+    }
+
+    match args {
+        [Constant::Int(l), Constant::Int(r)] => {
+            let (l, r) = (*l, *r);
+
+            // The versions of the arithmetic only differ when the result overflows.
+            let primitive = primitive.overflow().map_or(primitive, |(x, _)| x);
+
+            match primitive {
+                Primitive::Add => int(l.checked_add(r)),
+                Primitive::Sub => int(l.checked_sub(r)),
+                Primitive::Mul => int(l.checked_mul(r)),
+                Primitive::Div if r != 0 && l % r == 0 => int(l.checked_div(r)),
+                Primitive::Rem if r != 0 => int(l.checked_rem(r)),
+                Primitive::Eq => Some(Folded::Bool(l == r)),
+                Primitive::Neq => Some(Folded::Bool(l != r)),
+                Primitive::Lt => Some(Folded::Bool(l < r)),
+                Primitive::Gt => Some(Folded::Bool(l > r)),
+                Primitive::Le => Some(Folded::Bool(l <= r)),
+                Primitive::Ge => Some(Folded::Bool(l >= r)),
+                _ => None,
+            }
+        }
+        [Constant::String(l), Constant::String(r)] => match primitive {
+            Primitive::Concat => Some(Folded::Literal(LiteralKind::String(Symbol::intern(
+                &format!("{}{}", l.get(), r.get()),
+            )))),
+            Primitive::Eq => Some(Folded::Bool(l == r)),
+            Primitive::Neq => Some(Folded::Bool(l != r)),
+            _ => None,
+        },
+        [Constant::Char(l), Constant::Char(r)] => match primitive {
+            Primitive::Eq => Some(Folded::Bool(l == r)),
+            Primitive::Neq => Some(Folded::Bool(l != r)),
+            _ => None,
+        },
+        [Constant::String(string), Constant::Int(index)] if primitive == Primitive::Index => {
+            let index = usize::try_from(*index).ok()?;
+            string.get().chars().nth(index).map(char)
+        }
+        [Constant::String(string), Constant::Int(offset)] if primitive == Primitive::CharAt => {
+            let offset = usize::try_from(*offset).ok()?;
+            string.get().get(offset..)?.chars().next().map(char)
+        }
+        [Constant::String(string), Constant::Int(start), Constant::Int(end)]
+            if primitive == Primitive::Slice =>
+        {
+            let range = usize::try_from(*start).ok()?..usize::try_from(*end).ok()?;
+            let slice = Symbol::intern(string.get().get(range)?);
+            Some(Folded::Literal(LiteralKind::String(slice)))
+        }
+        [Constant::String(string)] if primitive == Primitive::Length => {
+            int(i64::try_from(string.get().chars().count()).ok())
+        }
+        [Constant::String(string)] if primitive == Primitive::ByteLength => {
+            int(i64::try_from(string.get().len()).ok())
+        }
+        [Constant::Char(c)] if primitive == Primitive::CharToInt => {
+            int(c.get().chars().next().map(|x| x as i64))
+        }
+        [Constant::Char(c)] if primitive == Primitive::CharWidth => {
+            int(c.get().chars().next().map(|x| x.len_utf8() as i64))
+        }
+        [Constant::Int(code)] if primitive == Primitive::IntToChar => {
+            u32::try_from(*code).ok().and_then(char::from_u32).map(char)
+        }
+        _ => None,
+    }
+}
+
+/// The size of a term, that is used to choose the functions that are inlined.
+fn size(term: &TermKind) -> usize {
+    match term {
+        TermKind::Let(_, Value::Lambda(_, body), rest) => 1 + size(body) + size(rest),
+        TermKind::Let(_, _, rest) => 1 + size(rest),
+        TermKind::Join(_, _, body, rest) => 1 + size(body) + size(rest),
+        TermKind::Match(_, alts, default) => {
+            1 + alts.iter().map(|x| size(&x.body)).sum::<usize>()
+                + default.as_ref().map(|x| size(x)).unwrap_or(0)
+        }
+        _ => 1,
+    }
+}
+
+/// The number of places where the term returns, without looking inside of lambdas.
+fn exits(term: &TermKind) -> usize {
+    match term {
+        TermKind::Let(_, _, rest) => exits(rest),
+        TermKind::Join(_, _, body, rest) => exits(body) + exits(rest),
+        TermKind::Match(_, alts, default) => {
+            alts.iter().map(|x| exits(&x.body)).sum::<usize>()
+                + default.as_ref().map(|x| exits(x)).unwrap_or(0)
+        }
+        TermKind::Return(_) | TermKind::Tail(..) => 1,
+        TermKind::Jump(..) | TermKind::Unreachable => 0,
+    }
+}
+
+/// Replaces the places where the term returns by the result of the function.
+fn replace_exits(term: &mut TermKind, f: &mut dyn FnMut(TermKind) -> TermKind) {
+    match term {
+        TermKind::Let(_, _, rest) => replace_exits(rest, f),
+        TermKind::Join(_, _, body, rest) => {
+            replace_exits(body, f);
+            replace_exits(rest, f);
+        }
+        TermKind::Match(_, alts, default) => {
+            for alt in alts {
+                replace_exits(&mut alt.body, f);
+            }
+
+            if let Some(default) = default {
+                replace_exits(default, f);
+            }
+        }
+        TermKind::Return(_) | TermKind::Tail(..) => {
+            let exit = std::mem::replace(term, TermKind::Unreachable);
+            *term = f(exit);
+        }
+        TermKind::Jump(..) | TermKind::Unreachable => (),
+    }
+}
+
+/// The number of jumps to the join point, without looking inside of lambdas.
+fn jumps(label: &Symbol, term: &TermKind) -> usize {
+    match term {
+        TermKind::Let(_, _, rest) => jumps(label, rest),
+        TermKind::Join(_, _, body, rest) => jumps(label, body) + jumps(label, rest),
+        TermKind::Match(_, alts, default) => {
+            alts.iter().map(|x| jumps(label, &x.body)).sum::<usize>()
+                + default.as_ref().map(|x| jumps(label, x)).unwrap_or(0)
+        }
+        TermKind::Jump(target, _) => (target == label) as usize,
+        TermKind::Tail(..) | TermKind::Return(_) | TermKind::Unreachable => 0,
+    }
+}
+
+/// Replaces the jumps to the join point by the result of the function on their arguments.
+fn replace_jump(term: &mut TermKind, label: &Symbol, f: &mut dyn FnMut(Vec<Atom>) -> TermKind) {
+    match term {
+        TermKind::Let(_, _, rest) => replace_jump(rest, label, f),
+        TermKind::Join(_, _, body, rest) => {
+            replace_jump(body, label, f);
+            replace_jump(rest, label, f);
+        }
+        TermKind::Match(_, alts, default) => {
+            for alt in alts {
+                replace_jump(&mut alt.body, label, f);
+            }
+
+            if let Some(default) = default {
+                replace_jump(default, label, f);
+            }
+        }
+        TermKind::Jump(target, args) if target == label => {
+            let args = std::mem::take(args);
+            *term = f(args);
+        }
+        _ => (),
+    }
+}
+
+fn is_pure(value: &Value, operators: &HashMap<Qualified, Foldable>) -> bool {
+    match value {
+        Value::Atom(_) | Value::Lambda(..) | Value::Constructor(..) | Value::Field(..) => true,
+        Value::Application(Atom::Function(name, _), _, _) => operators
+            .get(name)
+            .map(|x| x.primitive.is_pure())
+            .unwrap_or(false),
+        _ => false,
+    }
+}
+
+/// Removes the lets of pure values and the join points that are not used. The term is visited
+/// from the end, so the variables that are used are known when a let is found.
+fn sweep(
+    term: &mut TermKind,
+    used: &mut HashSet<Symbol>,
+    operators: &HashMap<Qualified, Foldable>,
+) {
+    let atom = |atom: &Atom, used: &mut HashSet<Symbol>| {
+        if let Atom::Variable(name) = atom {
+            used.insert(name.clone());
+        }
+    };
+
+    match term {
+        TermKind::Let(binder, value, rest) => {
+            sweep(rest, used, operators);
+
+            if !used.contains(&binder.name) && is_pure(value, operators) {
+                let TermKind::Let(_, _, rest) = std::mem::replace(term, TermKind::Unreachable)
+                else {
+                    unreachable!()
+                };
+
+                *term = *rest;
+                return;
+            }
+
+            if let Value::Lambda(_, body) = value {
+                sweep(body, used, operators);
+            }
+
+            visit_value(value, &mut |x| atom(x, used));
+        }
+        TermKind::Join(label, _, body, rest) => {
+            sweep(rest, used, operators);
+
+            if !used.contains(label) {
+                let TermKind::Join(_, _, _, rest) = std::mem::replace(term, TermKind::Unreachable)
+                else {
+                    unreachable!()
+                };
+
+                *term = *rest;
+                return;
+            }
+
+            sweep(body, used, operators);
+        }
+        TermKind::Jump(label, args) => {
+            used.insert(label.clone());
+            args.iter().for_each(|x| atom(x, used));
+        }
+        TermKind::Match(scrutinee, alts, default) => {
+            atom(scrutinee, used);
+
+            for alt in alts {
+                sweep(&mut alt.body, used, operators);
+            }
+
+            if let Some(default) = default {
+                sweep(default, used, operators);
+            }
+        }
+        TermKind::Tail(..) | TermKind::Return(_) => visit_term(term, &mut |x| atom(x, used)),
+        TermKind::Unreachable => (),
+    }
+}
+
+/// An external that is bound to a primitive.
+pub(crate) struct Foldable {
+    pub primitive: Primitive,
+    pub result: Type,
+
+    /// The `False` and `True` constructors of the result, if it's a boolean.
+    pub bools: Option<[Qualified; 2]>,
+}
+
+/// The externals of the program that are bound to primitives.
+pub(crate) fn operators(program: &Program) -> HashMap<Qualified, Foldable> {
+    let types: HashMap<_, _> = program.types.iter().map(|x| (x.name.clone(), x)).collect();
+
+    program
+        .externals
+        .iter()
+        .filter_map(|external| {
+            let primitive = Primitive::from_binding(&external.binding.get())?;
+            let result = external.typ.arrow_spine().1;
+
+            let bools = match *result {
+                TypeKind::Constructor(ref name) => types.get(name).and_then(|typ| {
+                    let find = |name: &str| {
+                        typ.constructors
+                            .iter()
+                            .find(|(x, _)| x.name.get() == name)
+                            .map(|(x, _)| x.clone())
+                    };
+
+                    Some([find("False")?, find("True")?])
+                }),
+                _ => None,
+            };
+
+            let foldable = Foldable {
+                primitive,
+                result,
+                bools,
+            };
+
+            Some((external.name.clone(), foldable))
+        })
+        .collect()
+}
+
+/// What is known about the value of a variable.
+#[derive(Clone)]
+enum Known {
+    Constructor(Qualified, Vec<Atom>),
+    Lambda(Vec<Binder>, Term),
+}
+
+/// The new names of the variables and join points of a term that is copied, and the types that
+/// the quantifiers of the function are instantiated to.
+#[derive(Default)]
+struct Renaming {
+    atoms: HashMap<Symbol, Atom>,
+    labels: HashMap<Symbol, Symbol>,
+    types: Vec<Type>,
+}
+
+struct Optimizer {
+    names: usize,
+    operators: HashMap<Qualified, Foldable>,
+
+    /// The let declarations that are small enough to be inlined and do not call themselves back.
+    inline: HashMap<Qualified, LetDecl>,
+    threshold: usize,
+
+    /// The values of the declarations without parameters that are known at compile time.
+    constants: HashMap<Qualified, Static>,
+
+    /// The size of the code that can still be inlined into the current declaration.
+    fuel: usize,
+    uses: HashMap<Symbol, usize>,
+    substitution: HashMap<Symbol, Atom>,
+    known: HashMap<Symbol, Known>,
+}
+
+impl Optimizer {
+    fn fresh(&mut self, name: &Symbol) -> Symbol {
+        let name = name.get();
+        let prefix = name.split('$').next().unwrap_or("x");
+        let id = self.names;
+        self.names += 1;
+        Symbol::intern(&format!("{}${}", prefix, id))
+    }
+
+    fn copy_binder(&mut self, binder: &Binder, renaming: &mut Renaming) -> Binder {
+        let name = self.fresh(&binder.name);
+
+        renaming
+            .atoms
+            .insert(binder.name.clone(), Atom::Variable(name.clone()));
+
+        Binder {
+            name,
+            typ: binder.typ.substitute(&renaming.types, 0),
+        }
+    }
+
+    fn copy_atom(&mut self, atom: &Atom, renaming: &Renaming) -> Atom {
+        match atom {
+            Atom::Variable(name) => renaming.atoms.get(name).cloned().unwrap_or(atom.clone()),
+            Atom::Function(name, types) => Atom::Function(
+                name.clone(),
+                types
+                    .iter()
+                    .map(|x| x.substitute(&renaming.types, 0))
+                    .collect(),
+            ),
+            Atom::Literal(_) => atom.clone(),
+        }
+    }
+
+    fn copy_atoms(&mut self, atoms: &[Atom], renaming: &Renaming) -> Vec<Atom> {
+        atoms.iter().map(|x| self.copy_atom(x, renaming)).collect()
+    }
+
+    fn copy_value(&mut self, value: &Value, renaming: &mut Renaming) -> Value {
+        match value {
+            Value::Atom(atom) => Value::Atom(self.copy_atom(atom, renaming)),
+            Value::Lambda(params, body) => {
+                let params = params
+                    .iter()
+                    .map(|x| self.copy_binder(x, renaming))
+                    .collect();
+
+                Value::Lambda(params, self.copy_term(body, renaming))
+            }
+            Value::Application(func, args, span) => Value::Application(
+                self.copy_atom(func, renaming),
+                self.copy_atoms(args, renaming),
+                span.clone(),
+            ),
+            Value::Constructor(name, args) => {
+                Value::Constructor(name.clone(), self.copy_atoms(args, renaming))
+            }
+            Value::Field(name, atom, index) => {
+                Value::Field(name.clone(), self.copy_atom(atom, renaming), *index)
+            }
+            Value::Perform(instance, name, args, span) => Value::Perform(
+                instance.as_ref().map(|x| self.copy_atom(x, renaming)),
+                name.clone(),
+                self.copy_atoms(args, renaming),
+                span.clone(),
+            ),
+            Value::Handle(thunk, clauses, ret, finally) => Value::Handle(
+                self.copy_atom(thunk, renaming),
+                clauses
+                    .iter()
+                    .map(|(name, kind, clause)| {
+                        (name.clone(), *kind, self.copy_atom(clause, renaming))
+                    })
+                    .collect(),
+                self.copy_atom(ret, renaming),
+                finally.as_ref().map(|x| self.copy_atom(x, renaming)),
+            ),
+            Value::Mask(operations, thunk) => {
+                Value::Mask(operations.clone(), self.copy_atom(thunk, renaming))
+            }
+        }
+    }
+
+    /// Copies the term giving new names to everything that it binds, so it can be put in a place
+    /// where the old names are already used.
+    fn copy_term(&mut self, term: &TermKind, renaming: &mut Renaming) -> Term {
+        Box::new(match term {
+            TermKind::Let(binder, value, rest) => {
+                let value = self.copy_value(value, renaming);
+                let binder = self.copy_binder(binder, renaming);
+                TermKind::Let(binder, value, self.copy_term(rest, renaming))
+            }
+            TermKind::Join(label, params, body, rest) => {
+                let new = self.fresh(label);
+                renaming.labels.insert(label.clone(), new.clone());
+
+                let params = params
+                    .iter()
+                    .map(|x| self.copy_binder(x, renaming))
+                    .collect();
+
+                let body = self.copy_term(body, renaming);
+                TermKind::Join(new, params, body, self.copy_term(rest, renaming))
+            }
+            TermKind::Jump(label, args) => TermKind::Jump(
+                renaming.labels.get(label).cloned().unwrap_or(label.clone()),
+                self.copy_atoms(args, renaming),
+            ),
+            TermKind::Tail(func, args, span) => TermKind::Tail(
+                self.copy_atom(func, renaming),
+                self.copy_atoms(args, renaming),
+                span.clone(),
+            ),
+            TermKind::Match(atom, alts, default) => TermKind::Match(
+                self.copy_atom(atom, renaming),
+                alts.iter()
+                    .map(|alt| Alt {
+                        case: alt.case.clone(),
+                        binders: alt
+                            .binders
+                            .iter()
+                            .map(|x| self.copy_binder(x, renaming))
+                            .collect(),
+                        body: self.copy_term(&alt.body, renaming),
+                    })
+                    .collect(),
+                default.as_ref().map(|x| self.copy_term(x, renaming)),
+            ),
+            TermKind::Return(atom) => TermKind::Return(self.copy_atom(atom, renaming)),
+            TermKind::Unreachable => TermKind::Unreachable,
+        })
+    }
+
+    fn atom(&self, atom: &Atom) -> Atom {
+        match atom {
+            Atom::Variable(name) => self.substitution.get(name).cloned().unwrap_or(atom.clone()),
+            Atom::Function(name, _) => match self.constants.get(name).and_then(Static::literal) {
+                Some(literal) => Atom::Literal(literal.clone()),
+                None => atom.clone(),
+            },
+            _ => atom.clone(),
+        }
+    }
+
+    fn atoms(&self, atoms: &[Atom]) -> Vec<Atom> {
+        atoms.iter().map(|x| self.atom(x)).collect()
+    }
+
+    /// The copy of the body of the function with the parameters replaced by the arguments, if
+    /// the function is known and it's worth inlining it.
+    fn inline(&mut self, func: &Atom, args: &[Atom]) -> Option<Term> {
+        let (params, body, types) = match func {
+            Atom::Function(name, types) => {
+                let decl = self.inline.get(name)?;
+                (decl.params.clone(), decl.body.clone(), types.clone())
+            }
+            Atom::Variable(name) => match self.known.get(name)? {
+                Known::Lambda(params, body) => {
+                    let once = self.uses.get(name) == Some(&1);
+
+                    if !once && size(body) > self.threshold {
+                        return None;
+                    }
+
+                    (params.clone(), body.clone(), vec![])
+                }
+                Known::Constructor(..) => return None,
+            },
+            Atom::Literal(_) => return None,
+        };
+
+        let cost = size(&body);
+
+        if params.len() != args.len() || cost > self.fuel {
+            return None;
+        }
+
+        self.fuel -= cost;
+
+        let mut renaming = Renaming {
+            types,
+            ..Default::default()
+        };
+
+        for (param, arg) in params.iter().zip(args) {
+            renaming.atoms.insert(param.name.clone(), arg.clone());
+        }
+
+        Some(self.copy_term(&body, &mut renaming))
+    }
+
+    /// Puts the inlined body in the place of a let whose value is a call, so the rest is run with
+    /// the result of the body bound to the binder.
+    fn splice(&mut self, mut body: Term, binder: &Binder, rest: &TermKind) -> TermKind {
+        if exits(&body) == 1 {
+            let mut rest = Some(Box::new(rest.clone()));
+
+            replace_exits(&mut body, &mut |exit| {
+                let rest = rest.take().unwrap();
+
+                match exit {
+                    TermKind::Return(atom) => {
+                        TermKind::Let(binder.clone(), Value::Atom(atom), rest)
+                    }
+                    TermKind::Tail(func, args, span) => {
+                        TermKind::Let(binder.clone(), Value::Application(func, args, span), rest)
+                    }
+                    _ => unreachable!(),
+                }
+            });
+
+            return *body;
+        }
+
+        let label = self.fresh(&Symbol::intern("j"));
+
+        replace_exits(&mut body, &mut |exit| match exit {
+            TermKind::Return(atom) => TermKind::Jump(label.clone(), vec![atom]),
+            TermKind::Tail(func, args, span) => {
+                let result = Binder {
+                    name: self.fresh(&binder.name),
+                    typ: binder.typ.clone(),
+                };
+
+                let jump = TermKind::Jump(label.clone(), vec![Atom::Variable(result.name.clone())]);
+                let value = Value::Application(func, args, span);
+                TermKind::Let(result, value, Box::new(jump))
+            }
+            _ => unreachable!(),
+        });
+
+        TermKind::Join(label, vec![binder.clone()], Box::new(rest.clone()), body)
+    }
+
+    /// Folds the application of a primitive to literals.
+    fn fold(&self, func: &Atom, args: &[Atom]) -> Option<Value> {
+        let Atom::Function(name, _) = func else {
+            return None;
+        };
+
+        let foldable = self.operators.get(name)?;
+
+        if args.len() != foldable.primitive.arity() {
+            return None;
+        }
+
+        let args = args.iter().map(constant).collect::<Option<Vec<_>>>()?;
+
+        match fold(foldable.primitive, &args)? {
+            Folded::Literal(literal) => Some(Value::Atom(Atom::Literal(Box::new(literal)))),
+            Folded::Bool(value) => {
+                let bools = foldable.bools.as_ref()?;
+                Some(Value::Constructor(bools[value as usize].clone(), vec![]))
+            }
+        }
+    }
+
+    fn value(&mut self, value: &Value) -> Value {
+        match value {
+            Value::Atom(atom) => Value::Atom(self.atom(atom)),
+            Value::Lambda(params, body) => Value::Lambda(params.clone(), self.term(body)),
+            Value::Application(func, args, span) => {
+                Value::Application(self.atom(func), self.atoms(args), span.clone())
+            }
+            Value::Constructor(name, args) => Value::Constructor(name.clone(), self.atoms(args)),
+            Value::Field(name, atom, index) => Value::Field(name.clone(), self.atom(atom), *index),
+            Value::Perform(instance, name, args, span) => Value::Perform(
+                instance.as_ref().map(|x| self.atom(x)),
+                name.clone(),
+                self.atoms(args),
+                span.clone(),
+            ),
+            Value::Handle(thunk, clauses, ret, finally) => Value::Handle(
+                self.atom(thunk),
+                clauses
+                    .iter()
+                    .map(|(name, kind, clause)| (name.clone(), *kind, self.atom(clause)))
+                    .collect(),
+                self.atom(ret),
+                finally.as_ref().map(|x| self.atom(x)),
+            ),
+            Value::Mask(operations, thunk) => Value::Mask(operations.clone(), self.atom(thunk)),
+        }
+    }
+
+    fn known_constructor(&self, atom: &Atom) -> Option<(Qualified, Vec<Atom>)> {
+        match atom {
+            Atom::Variable(name) => match self.known.get(name)? {
+                Known::Constructor(name, args) => Some((name.clone(), args.clone())),
+                Known::Lambda(..) => None,
+            },
+            Atom::Function(name, _) => match self.constants.get(name)? {
+                Static::Constructor(name, fields) => {
+                    let args = fields
+                        .iter()
+                        .map(|x| Some(Atom::Literal(x.literal()?.clone())))
+                        .collect::<Option<_>>()?;
+
+                    Some((name.clone(), args))
+                }
+                Static::Literal(_) => None,
+            },
+            _ => None,
+        }
+    }
+
+    fn term(&mut self, term: &TermKind) -> Term {
+        Box::new(match term {
+            TermKind::Let(binder, value, rest) => {
+                let mut value = self.value(value);
+
+                if let Value::Application(func, args, _) = &value {
+                    if let Some(folded) = self.fold(func, args) {
+                        value = folded;
+                    }
+                }
+
+                let value = match value {
+                    Value::Atom(atom) => {
+                        self.substitution.insert(binder.name.clone(), atom);
+                        return self.term(rest);
+                    }
+                    Value::Field(name, atom, index) => match self.known_constructor(&atom) {
+                        Some((known, args)) if known == name && index < args.len() => {
+                            self.substitution
+                                .insert(binder.name.clone(), args[index].clone());
+                            return self.term(rest);
+                        }
+                        _ => Value::Field(name, atom, index),
+                    },
+                    Value::Application(func, args, span) => {
+                        if let Some(body) = self.inline(&func, &args) {
+                            let spliced = self.splice(body, binder, rest);
+                            return self.term(&spliced);
+                        }
+
+                        Value::Application(func, args, span)
+                    }
+                    value => value,
+                };
+
+                match &value {
+                    Value::Constructor(name, args) => {
+                        let known = Known::Constructor(name.clone(), args.clone());
+                        self.known.insert(binder.name.clone(), known);
+                    }
+                    Value::Lambda(params, body) => {
+                        let known = Known::Lambda(params.clone(), body.clone());
+                        self.known.insert(binder.name.clone(), known);
+                    }
+                    _ => (),
+                }
+
+                TermKind::Let(binder.clone(), value, self.term(rest))
+            }
+            TermKind::Join(label, params, body, rest) => {
+                let mut rest = self.term(rest);
+
+                // A join point that is jumped to once is put in the place of the jump.
+                if jumps(label, body) == 0 && jumps(label, &rest) == 1 {
+                    replace_jump(&mut rest, label, &mut |args| {
+                        for (param, arg) in params.iter().zip(args) {
+                            self.substitution.insert(param.name.clone(), arg);
+                        }
+
+                        *self.term(body)
+                    });
+
+                    return rest;
+                }
+
+                TermKind::Join(label.clone(), params.clone(), self.term(body), rest)
+            }
+            TermKind::Jump(label, args) => TermKind::Jump(label.clone(), self.atoms(args)),
+            TermKind::Tail(func, args, span) => {
+                let (func, args) = (self.atom(func), self.atoms(args));
+
+                if let Some(value) = self.fold(&func, &args) {
+                    let Atom::Function(name, _) = &func else {
+                        unreachable!()
+                    };
+
+                    let binder = Binder {
+                        name: self.fresh(&Symbol::intern("c")),
+                        typ: self.operators[name].result.clone(),
+                    };
+
+                    let result = Box::new(TermKind::Return(Atom::Variable(binder.name.clone())));
+                    return self.term(&TermKind::Let(binder, value, result));
+                }
+
+                if let Some(body) = self.inline(&func, &args) {
+                    return self.term(&body);
+                }
+
+                TermKind::Tail(func, args, span.clone())
+            }
+            TermKind::Match(atom, alts, default) => {
+                let atom = self.atom(atom);
+
+                if let Some(term) = self.known_match(&atom, alts, default) {
+                    return term;
+                }
+
+                let alts = alts
+                    .iter()
+                    .map(|alt| {
+                        // Inside of the alternative the scrutinee is known to be the constructor.
+                        let previous = match (&atom, &alt.case) {
+                            (Atom::Variable(name), Case::Constructor(constructor)) => {
+                                let args = alt
+                                    .binders
+                                    .iter()
+                                    .map(|x| Atom::Variable(x.name.clone()))
+                                    .collect();
+
+                                let known = Known::Constructor(constructor.clone(), args);
+                                Some((name.clone(), self.known.insert(name.clone(), known)))
+                            }
+                            _ => None,
+                        };
+
+                        let body = self.term(&alt.body);
+
+                        if let Some((name, previous)) = previous {
+                            match previous {
+                                Some(previous) => self.known.insert(name, previous),
+                                None => self.known.remove(&name),
+                            };
+                        }
+
+                        Alt {
+                            case: alt.case.clone(),
+                            binders: alt.binders.clone(),
+                            body,
+                        }
+                    })
+                    .collect();
+
+                let default = default.as_ref().map(|x| self.term(x));
+                TermKind::Match(atom, alts, default)
+            }
+            TermKind::Return(atom) => TermKind::Return(self.atom(atom)),
+            TermKind::Unreachable => TermKind::Unreachable,
+        })
+    }
+
+    /// Chooses the alternative of a match on a literal or on a known constructor.
+    fn known_match(&mut self, atom: &Atom, alts: &[Alt], default: &Option<Term>) -> Option<Term> {
+        let fallback = |this: &mut Self| match default {
+            Some(default) => this.term(default),
+            None => Box::new(TermKind::Unreachable),
+        };
+
+        if let Some((name, args)) = self.known_constructor(atom) {
+            let Some(alt) = alts
+                .iter()
+                .find(|x| x.case == Case::Constructor(name.clone()))
+            else {
+                return Some(fallback(self));
+            };
+
+            if alt.binders.len() != args.len() {
+                return None;
+            }
+
+            for (binder, arg) in alt.binders.iter().zip(args) {
+                self.substitution.insert(binder.name.clone(), arg);
+            }
+
+            return Some(self.term(&alt.body));
+        }
+
+        let scrutinee = constant(atom)?;
+        let mut cases = Vec::new();
+
+        for alt in alts {
+            cases.push((selects(&alt.case, &scrutinee)?, alt));
+        }
+
+        match cases.into_iter().find(|(selected, _)| *selected) {
+            Some((_, alt)) => Some(self.term(&alt.body)),
+            None => Some(fallback(self)),
+        }
+    }
+
+    fn let_decl(&mut self, decl: &LetDecl) -> LetDecl {
+        self.fuel = self.threshold * 8;
+        self.substitution.clear();
+        self.known.clear();
+        self.uses.clear();
+
+        visit_term(&decl.body, &mut |atom| {
+            if let Atom::Variable(name) = atom {
+                *self.uses.entry(name.clone()).or_default() += 1;
+            }
+        });
+
+        let literal = self.constants.get(&decl.name).and_then(Static::literal);
+
+        let mut body = match literal {
+            Some(literal) => Box::new(TermKind::Return(Atom::Literal(literal.clone()))),
+            None => self.term(&decl.body),
+        };
+
+        sweep(&mut body, &mut HashSet::new(), &self.operators);
+
+        LetDecl {
+            name: decl.name.clone(),
+            span: decl.span.clone(),
+            visibility: decl.visibility.clone(),
+            typ: decl.typ.clone(),
+            params: decl.params.clone(),
+            body,
+            declaration: decl.declaration.clone(),
+            allow: decl.allow.clone(),
+        }
+    }
+}
+
+/// The let declarations that can call themselves back, directly or through other declarations.
+fn recursive(program: &Program) -> HashSet<Qualified> {
+    let references: HashMap<_, _> = program
+        .lets
+        .iter()
+        .map(|decl| {
+            let mut used = HashSet::new();
+
+            visit_term(&decl.body, &mut |atom| {
+                if let Atom::Function(name, _) = atom {
+                    used.insert(name.clone());
+                }
+            });
+
+            (decl.name.clone(), used)
+        })
+        .collect();
+
+    let mut recursive = HashSet::new();
+
+    for decl in &program.lets {
+        let mut visited = HashSet::new();
+        let mut stack: Vec<_> = references[&decl.name].iter().cloned().collect();
+
+        while let Some(current) = stack.pop() {
+            if current == decl.name {
+                recursive.insert(current);
+                break;
+            }
+
+            if visited.insert(current.clone()) {
+                stack.extend(references.get(&current).into_iter().flatten().cloned());
+            }
+        }
+    }
+
+    recursive
+}
+
+/// Optimizes the program at the level. The tail calls are marked again at the end because
+/// inlining can put calls in tail position.
+pub fn optimize(program: &mut Program, level: usize) {
+    if level == 0 {
+        return;
+    }
+
+    let mut optimizer = Optimizer {
+        names: program.names,
+        operators: operators(program),
+        inline: HashMap::new(),
+        threshold: threshold(level),
+        constants: HashMap::new(),
+        fuel: 0,
+        uses: HashMap::new(),
+        substitution: HashMap::new(),
+        known: HashMap::new(),
+    };
+
+    for _ in 0..ROUNDS {
+        let recursive = recursive(program);
+        optimizer.constants = eval::constants(program);
+
+        optimizer.inline = program
+            .lets
+            .iter()
+            .filter(|x| !x.params.is_empty() && !recursive.contains(&x.name))
+            .filter(|x| size(&x.body) <= optimizer.threshold)
+            .map(|x| (x.name.clone(), x.clone()))
+            .collect();
+
+        program.lets = program.lets.iter().map(|x| optimizer.let_decl(x)).collect();
+    }
+
+    program.names = optimizer.names;
+    crate::tail::optimize(program);
+}
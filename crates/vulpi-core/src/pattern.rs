@@ -0,0 +1,421 @@
+//! Compilation of pattern matching into decision trees. Every test in a tree looks at a single
+//! occurrence, so each node of the tree becomes one [crate::syntax::TermKind::Match].
+
+use vulpi_intern::Symbol;
+use vulpi_syntax::{
+    elaborated::{Literal, LiteralKind, Pattern, PatternKind},
+    r#abstract::Qualified,
+};
+
+use crate::{
+    lower::Context,
+    syntax::{tuple, Binder, Case, TypeKind},
+};
+
+#[derive(Clone)]
+enum Head {
+    Constructor(Qualified, usize),
+    Literal(Literal),
+    Range(Literal, Literal),
+}
+
+impl Head {
+    fn case(&self) -> Case {
+        match self {
+            Head::Constructor(name, _) => Case::Constructor(name.clone()),
+            Head::Literal(literal) => Case::Literal(literal.clone()),
+            Head::Range(start, end) => Case::Range(start.clone(), end.clone()),
+        }
+    }
+
+    fn arity(&self) -> usize {
+        match self {
+            Head::Constructor(_, arity) => *arity,
+            Head::Literal(_) | Head::Range(_, _) => 0,
+        }
+    }
+
+    /// Checks if the values of the head are matched by a literal or by a range. The heads of a
+    /// column with ranges never overlap partially with its patterns, so it only looks at the
+    /// first value.
+    fn inside(&self, pattern: &Pattern) -> bool {
+        let first = match self {
+            Head::Literal(literal) | Head::Range(literal, _) => literal.ordinal(),
+            Head::Constructor(_, _) => None,
+        };
+
+        match (first, bounds(pattern)) {
+            (Some(first), Some((start, end))) => start <= first && first <= end,
+            _ => false,
+        }
+    }
+}
+
+pub enum Tree {
+    Fail,
+
+    /// Runs the action with the variables of its patterns bound to the occurrences.
+    Leaf(usize, Vec<(Symbol, Binder)>),
+
+    /// Runs the action if its guard holds and the tree otherwise.
+    Guard(usize, Vec<(Symbol, Binder)>, Box<Tree>),
+
+    /// Tests the occurrence against each case, binding the fields of the case to new occurrences.
+    Switch(Binder, Vec<(Case, Vec<Binder>, Tree)>, Option<Box<Tree>>),
+}
+
+impl Tree {
+    /// Counts how many times each action is reachable from the tree.
+    pub fn count(&self, counts: &mut [usize]) {
+        match self {
+            Tree::Fail => (),
+            Tree::Leaf(action, _) => counts[*action] += 1,
+            Tree::Guard(action, _, otherwise) => {
+                counts[*action] += 1;
+                otherwise.count(counts);
+            }
+            Tree::Switch(_, cases, default) => {
+                for (_, _, tree) in cases {
+                    tree.count(counts);
+                }
+
+                if let Some(default) = default {
+                    default.count(counts);
+                }
+            }
+        }
+    }
+}
+
+#[derive(Clone)]
+struct Row {
+    patterns: Vec<Pattern>,
+    action: usize,
+    bindings: Vec<(Symbol, Binder)>,
+}
+
+fn is_refutable(pattern: &Pattern) -> bool {
+    matches!(
+        &**pattern,
+        PatternKind::Literal(_)
+            | PatternKind::Range(_, _)
+            | PatternKind::Application(_)
+            | PatternKind::Tuple(_)
+    )
+}
+
+/// The first and the last position of the values matched by an integer, a character or a range.
+fn bounds(pattern: &Pattern) -> Option<(i64, i64)> {
+    match &**pattern {
+        PatternKind::Literal(literal) => literal.ordinal().map(|x| (x, x)),
+        PatternKind::Range(start, end) => Some((start.ordinal()?, end.ordinal()?)),
+        _ => None,
+    }
+}
+
+/// The heads of a column with ranges. The positions where the patterns start or stop split the
+/// values into intervals that are inside or outside of each pattern, so a value is tested with
+/// comparisons against the intervals that some pattern matches, in order.
+fn intervals(patterns: &[&Pattern]) -> Vec<Head> {
+    let mut like = None;
+    let mut cuts = Vec::new();
+
+    for pattern in patterns {
+        if let (Some((start, end)), PatternKind::Literal(lit) | PatternKind::Range(lit, _)) =
+            (bounds(pattern), &***pattern)
+        {
+            like.get_or_insert(lit);
+            cuts.push(start);
+            cuts.extend(end.checked_add(1));
+        }
+    }
+
+    cuts.sort();
+    cuts.dedup();
+
+    let Some(like) = like else {
+        return vec![];
+    };
+
+    let mut heads = Vec::new();
+
+    for (i, start) in cuts.iter().enumerate() {
+        let end = cuts.get(i + 1).map_or(i64::MAX, |x| x - 1);
+
+        let covered = patterns
+            .iter()
+            .any(|x| bounds(x).is_some_and(|(lo, hi)| lo <= *start && *start <= hi));
+
+        // Characters skip the surrogates, that aren't values of them.
+        let start = match &**like {
+            LiteralKind::Char(_) if (0xD800..=0xDFFF).contains(start) => 0xE000,
+            _ => *start,
+        };
+
+        if !covered || start > end {
+            continue;
+        }
+
+        let head = if start == end {
+            Head::Literal(Box::new(like.with_ordinal(start)))
+        } else {
+            let start = Box::new(like.with_ordinal(start));
+            Head::Range(start, Box::new(like.with_ordinal(end)))
+        };
+
+        heads.push(head);
+    }
+
+    heads
+}
+
+fn head(pattern: &Pattern) -> Option<Head> {
+    match &**pattern {
+        PatternKind::Literal(literal) => Some(Head::Literal(literal.clone())),
+        PatternKind::Application(app) => Some(Head::Constructor(app.func.clone(), app.args.len())),
+        PatternKind::Tuple(parts) => Some(Head::Constructor(tuple(parts.len()), parts.len())),
+        _ => None,
+    }
+}
+
+/// Compiles the rows of patterns that match on the occurrences. Each row runs the action with the
+/// same index and the rows with a guard fall through to the next rows if the guard fails.
+pub fn compile(
+    ctx: &mut Context,
+    occurrences: Vec<Binder>,
+    rows: Vec<Vec<Pattern>>,
+    guards: &[bool],
+) -> Tree {
+    let rows = rows
+        .into_iter()
+        .enumerate()
+        .map(|(action, patterns)| Row {
+            patterns,
+            action,
+            bindings: vec![],
+        })
+        .collect();
+
+    compile_rows(ctx, &occurrences, rows, guards)
+}
+
+fn compile_rows(
+    ctx: &mut Context,
+    occurrences: &[Binder],
+    rows: Vec<Row>,
+    guards: &[bool],
+) -> Tree {
+    let rows = expand(occurrences, rows);
+
+    let Some(first) = rows.first() else {
+        return Tree::Fail;
+    };
+
+    let Some(column) = first.patterns.iter().position(is_refutable) else {
+        let mut bindings = first.bindings.clone();
+
+        for (pattern, occurrence) in first.patterns.iter().zip(occurrences) {
+            if let PatternKind::Variable(name) = &**pattern {
+                bindings.push((name.clone(), occurrence.clone()));
+            }
+        }
+
+        return if guards[first.action] {
+            let action = first.action;
+            let otherwise = compile_rows(ctx, occurrences, rows[1..].to_vec(), guards);
+            Tree::Guard(action, bindings, Box::new(otherwise))
+        } else {
+            Tree::Leaf(first.action, bindings)
+        };
+    };
+
+    let occurrence = occurrences[column].clone();
+
+    let mut rest = occurrences.to_vec();
+    rest.remove(column);
+
+    let mut heads: Vec<Head> = Vec::new();
+
+    let patterns: Vec<_> = rows.iter().map(|row| &row.patterns[column]).collect();
+
+    if patterns
+        .iter()
+        .any(|x| matches!(&***x, PatternKind::Range(_, _)))
+    {
+        heads = intervals(&patterns);
+    } else {
+        for pattern in patterns {
+            if let Some(head) = head(pattern) {
+                if !heads.iter().any(|x| x.case() == head.case()) {
+                    heads.push(head);
+                }
+            }
+        }
+    }
+
+    let mut cases = Vec::new();
+
+    for head in &heads {
+        let arity = head.arity();
+
+        let types = match (&*occurrence.typ, head) {
+            (TypeKind::Tuple(types), Head::Constructor(_, _)) if types.len() == arity => {
+                types.clone()
+            }
+            _ => vec![TypeKind::unknown(); arity],
+        };
+
+        let fields: Vec<_> = types
+            .into_iter()
+            .map(|typ| Binder {
+                name: ctx.fresh("v"),
+                typ,
+            })
+            .collect();
+
+        let mut specialized = Vec::new();
+
+        for row in &rows {
+            let pattern = &row.patterns[column];
+
+            let args = match &**pattern {
+                PatternKind::Application(app)
+                    if head.case() == Case::Constructor(app.func.clone()) =>
+                {
+                    app.args.clone()
+                }
+                PatternKind::Tuple(parts)
+                    if head.case() == Case::Constructor(tuple(parts.len())) =>
+                {
+                    parts.clone()
+                }
+                PatternKind::Literal(literal) if head.case() == Case::Literal(literal.clone()) => {
+                    vec![]
+                }
+                PatternKind::Literal(_) | PatternKind::Range(_, _) if head.inside(pattern) => {
+                    vec![]
+                }
+                PatternKind::Wildcard | PatternKind::Variable(_) => {
+                    vec![Box::new(PatternKind::Wildcard); arity]
+                }
+                _ => continue,
+            };
+
+            specialized.push(specialize(row, column, &occurrence, args));
+        }
+
+        let mut occurrences = fields.clone();
+        occurrences.extend(rest.iter().cloned());
+
+        let tree = compile_rows(ctx, &occurrences, specialized, guards);
+
+        cases.push((head.case(), fields, tree));
+    }
+
+    let exhaustive = match heads.first() {
+        Some(Head::Constructor(name, _)) => ctx.siblings(name) == heads.len(),
+        _ => false,
+    };
+
+    let default = if exhaustive {
+        None
+    } else {
+        let defaults = rows
+            .iter()
+            .filter(|row| !is_refutable(&row.patterns[column]))
+            .map(|row| specialize(row, column, &occurrence, vec![]))
+            .collect();
+
+        Some(Box::new(compile_rows(ctx, &rest, defaults, guards)))
+    };
+
+    Tree::Switch(occurrence, cases, default)
+}
+
+/// Splits the rows with or-patterns into a row for each alternative, with the same action, and
+/// binds the names of as-patterns to their occurrences so the value is never computed again. The
+/// patterns that are inside of other patterns are handled when their occurrences are tested.
+fn expand(occurrences: &[Binder], rows: Vec<Row>) -> Vec<Row> {
+    let mut expanded = Vec::new();
+
+    for mut row in rows {
+        for (pattern, occurrence) in row.patterns.iter_mut().zip(occurrences) {
+            while let PatternKind::As(name, inner) = &**pattern {
+                row.bindings.push((name.clone(), occurrence.clone()));
+                *pattern = inner.clone();
+            }
+        }
+
+        let or = row
+            .patterns
+            .iter()
+            .enumerate()
+            .find_map(|(i, x)| match &**x {
+                PatternKind::Or(alternatives) => Some((i, alternatives.clone())),
+                _ => None,
+            });
+
+        match or {
+            Some((column, alternatives)) => {
+                let rows = alternatives.into_iter().map(|alternative| {
+                    let mut row = row.clone();
+                    row.patterns[column] = alternative;
+                    row
+                });
+
+                expanded.extend(expand(occurrences, rows.collect()));
+            }
+            None => expanded.push(row),
+        }
+    }
+
+    expanded
+}
+
+/// Replaces the pattern in the column by the patterns of its fields, binding the pattern to the
+/// occurrence if it's a variable.
+fn specialize(row: &Row, column: usize, occurrence: &Binder, fields: Vec<Pattern>) -> Row {
+    let mut bindings = row.bindings.clone();
+
+    if let PatternKind::Variable(name) = &*row.patterns[column] {
+        bindings.push((name.clone(), occurrence.clone()));
+    }
+
+    let mut patterns = fields;
+    patterns.extend(
+        row.patterns
+            .iter()
+            .enumerate()
+            .filter(|(i, _)| *i != column)
+            .map(|(_, x)| x.clone()),
+    );
+
+    Row {
+        patterns,
+        action: row.action,
+        bindings,
+    }
+}
+
+/// The variables that are bound by a pattern in the order that they appear.
+pub fn variables(pattern: &Pattern, variables: &mut Vec<Symbol>) {
+    match &**pattern {
+        PatternKind::Variable(name) => variables.push(name.clone()),
+        PatternKind::Application(app) => {
+            for arg in &app.args {
+                self::variables(arg, variables);
+            }
+        }
+        PatternKind::Tuple(parts) => {
+            for part in parts {
+                self::variables(part, variables);
+            }
+        }
+        PatternKind::Or(alternatives) => self::variables(&alternatives[0], variables),
+        PatternKind::As(name, pattern) => {
+            variables.push(name.clone());
+            self::variables(pattern, variables)
+        }
+        _ => (),
+    }
+}
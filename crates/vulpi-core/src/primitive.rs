@@ -0,0 +1,345 @@
+//! Primitive operations that externals can be bound to. They're found by the binding of the
+//! external, and the bindings are the same ones that the prelude uses for the JavaScript backend,
+//! so the operators of the prelude work in every backend.
+//!
+//! Integers are 64 bit. The operators `add`, `sub` and `mul` wrap around on overflow, and each one
+//! has a checked version that fails and a saturating one that stops at the minimum or the maximum
+//! integer. The build chooses the version that the operators use with [Overflow]. Division rounds
+//! toward zero, fails when the divisor is zero and wraps around when the minimum integer is divided
+//! by minus one. Characters are Unicode scalar values, so converting an integer that is not one of
+//! them fails.
+//!
+//! Strings are encoded in UTF-8. [Primitive::Length] and [Primitive::Index] count characters, and
+//! the other primitives of strings use the offsets of bytes, so programs go through a string with
+//! [Primitive::CharAt] and [Primitive::CharWidth] without counting it from the start each time. An
+//! offset that falls inside of a character is an error, so a string is never split in the middle
+//! of one.
+//!
+//! The sized numbers are converted to each other with [Primitive::Convert], that is bound to the
+//! name of the type that it converts to, like `to_int8` or `to_float32`.
+//!
+//! The operations of the primitive `Ref` effect are primitives too, and the cells that they work on
+//! are mutable boxes of a single value in every backend. The same goes for the `Array` effect,
+//! whose arrays are indexed from zero and fail on the indices that are outside of them.
+
+use vulpi_intern::Symbol;
+pub use vulpi_typer::number::Number;
+
+use crate::syntax::{Program, TypeKind};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum Primitive {
+    Add,
+    Sub,
+    Mul,
+
+    /// Integer arithmetic that fails when the result doesn't fit in 64 bits.
+    CheckedAdd,
+    CheckedSub,
+    CheckedMul,
+
+    /// Integer arithmetic that gives the nearest integer when the result doesn't fit in 64 bits.
+    SaturatingAdd,
+    SaturatingSub,
+    SaturatingMul,
+
+    Div,
+    Rem,
+    Eq,
+    Neq,
+    Lt,
+    Gt,
+    Le,
+    Ge,
+    Concat,
+
+    /// The number of characters of a string.
+    Length,
+
+    /// The character at a position of a string. It fails if the position is outside of it.
+    Index,
+
+    /// The number of bytes of a string.
+    ByteLength,
+
+    /// The part of a string between two offsets. It fails if an offset is outside of the string
+    /// or inside of a character, or if the end comes before the start.
+    Slice,
+
+    /// The character that starts at an offset of a string. It fails if the offset is not the
+    /// start of a character.
+    CharAt,
+
+    /// The number of bytes of a character, that is the offset of the next one.
+    CharWidth,
+
+    /// Converts a number to a numeric type. Integers keep their lower bits, floats are truncated
+    /// to the nearest integer of the type and lose the precision that the type doesn't have.
+    Convert(Number),
+
+    /// The code point of a character.
+    CharToInt,
+
+    /// The character of a code point.
+    IntToChar,
+
+    Identity,
+
+    /// The text of a number or of a character, in the way that it's printed.
+    Show,
+
+    Print,
+    PrintError,
+
+    /// A new cell with a value.
+    RefNew,
+
+    /// The value of a cell.
+    RefGet,
+
+    /// Replaces the value of a cell, returning unit.
+    RefSet,
+
+    /// A new array without elements. It receives a unit.
+    ArrayEmpty,
+
+    /// A new array with a length and the value of every element. It fails if the length is
+    /// negative.
+    ArrayMake,
+
+    /// The element at an index of an array. It fails if the index is outside of it.
+    ArrayGet,
+
+    /// Replaces the element at an index of an array, returning unit. It fails if the index is
+    /// outside of it.
+    ArraySet,
+
+    /// The number of elements of an array.
+    ArrayLength,
+
+    /// Adds an element to the end of an array, returning unit.
+    ArrayPush,
+}
+
+impl Primitive {
+    /// Finds the primitive of the binding of an external declaration.
+    pub fn from_binding(binding: &str) -> Option<Primitive> {
+        let primitive = match binding.trim() {
+            "add" => Primitive::Add,
+            "sub" => Primitive::Sub,
+            "mul" => Primitive::Mul,
+            "wrapping_add" => Primitive::Add,
+            "wrapping_sub" => Primitive::Sub,
+            "wrapping_mul" => Primitive::Mul,
+            "checked_add" => Primitive::CheckedAdd,
+            "checked_sub" => Primitive::CheckedSub,
+            "checked_mul" => Primitive::CheckedMul,
+            "saturating_add" => Primitive::SaturatingAdd,
+            "saturating_sub" => Primitive::SaturatingSub,
+            "saturating_mul" => Primitive::SaturatingMul,
+            "div" => Primitive::Div,
+            "rem" => Primitive::Rem,
+            "eq" => Primitive::Eq,
+            "neq" | "1 - eq" => Primitive::Neq,
+            "lt" => Primitive::Lt,
+            "gt" => Primitive::Gt,
+            "le" => Primitive::Le,
+            "ge" => Primitive::Ge,
+            "concat" => Primitive::Concat,
+            "length" => Primitive::Length,
+            "index" => Primitive::Index,
+            "byte_length" => Primitive::ByteLength,
+            "slice" => Primitive::Slice,
+            "char_at" => Primitive::CharAt,
+            "char_width" => Primitive::CharWidth,
+            "ord" => Primitive::CharToInt,
+            "to_int" => Primitive::Convert(Number::INT),
+            "to_int8" => Primitive::Convert(Number::of("Int8")?),
+            "to_int16" => Primitive::Convert(Number::of("Int16")?),
+            "to_int32" => Primitive::Convert(Number::of("Int32")?),
+            "to_int64" => Primitive::Convert(Number::of("Int64")?),
+            "to_uint8" => Primitive::Convert(Number::of("UInt8")?),
+            "to_uint16" => Primitive::Convert(Number::of("UInt16")?),
+            "to_uint32" => Primitive::Convert(Number::of("UInt32")?),
+            "to_float" => Primitive::Convert(Number::FLOAT),
+            "to_float32" => Primitive::Convert(Number::of("Float32")?),
+            "chr" => Primitive::IntToChar,
+            "id" => Primitive::Identity,
+            "int_to_string" | "float_to_string" | "char_to_string" => Primitive::Show,
+            "console.log" | "print" => Primitive::Print,
+            "console.warn" | "console.error" => Primitive::PrintError,
+            "ref_new" => Primitive::RefNew,
+            "ref_get" => Primitive::RefGet,
+            "ref_set" => Primitive::RefSet,
+            "array_empty" => Primitive::ArrayEmpty,
+            "array_make" => Primitive::ArrayMake,
+            "array_get" => Primitive::ArrayGet,
+            "array_set" => Primitive::ArraySet,
+            "array_length" => Primitive::ArrayLength,
+            "array_push" => Primitive::ArrayPush,
+            _ => return None,
+        };
+
+        Some(primitive)
+    }
+
+    pub fn arity(&self) -> usize {
+        match self {
+            Primitive::Length
+            | Primitive::ByteLength
+            | Primitive::CharWidth
+            | Primitive::Convert(_)
+            | Primitive::CharToInt
+            | Primitive::IntToChar
+            | Primitive::Identity
+            | Primitive::Show
+            | Primitive::Print
+            | Primitive::PrintError
+            | Primitive::RefNew
+            | Primitive::RefGet
+            | Primitive::ArrayEmpty
+            | Primitive::ArrayLength => 1,
+            Primitive::Slice | Primitive::ArraySet => 3,
+            _ => 2,
+        }
+    }
+
+    /// The wrapping operation of an integer arithmetic primitive and what it does on overflow.
+    pub fn overflow(&self) -> Option<(Primitive, Overflow)> {
+        let overflow = match self {
+            Primitive::Add | Primitive::Sub | Primitive::Mul => (*self, Overflow::Wrap),
+            Primitive::CheckedAdd => (Primitive::Add, Overflow::Check),
+            Primitive::CheckedSub => (Primitive::Sub, Overflow::Check),
+            Primitive::CheckedMul => (Primitive::Mul, Overflow::Check),
+            Primitive::SaturatingAdd => (Primitive::Add, Overflow::Saturate),
+            Primitive::SaturatingSub => (Primitive::Sub, Overflow::Saturate),
+            Primitive::SaturatingMul => (Primitive::Mul, Overflow::Saturate),
+            _ => return None,
+        };
+
+        Some(overflow)
+    }
+
+    /// Primitives that return a boolean, as a constructor of `Prelude.Bool`.
+    pub fn is_comparison(&self) -> bool {
+        matches!(
+            self,
+            Primitive::Eq
+                | Primitive::Neq
+                | Primitive::Lt
+                | Primitive::Gt
+                | Primitive::Le
+                | Primitive::Ge
+        )
+    }
+
+    /// Primitives that cannot fail and have no effects, so their applications can be removed if
+    /// the result is not used.
+    pub fn is_pure(&self) -> bool {
+        !matches!(
+            self,
+            Primitive::CheckedAdd
+                | Primitive::CheckedSub
+                | Primitive::CheckedMul
+                | Primitive::Div
+                | Primitive::Rem
+                | Primitive::Index
+                | Primitive::Slice
+                | Primitive::CharAt
+                | Primitive::IntToChar
+                | Primitive::Print
+                | Primitive::PrintError
+                | Primitive::RefSet
+                | Primitive::ArrayMake
+                | Primitive::ArrayGet
+                | Primitive::ArraySet
+                | Primitive::ArrayPush
+        )
+    }
+}
+
+/// What the operators of integer arithmetic do when the result doesn't fit in 64 bits.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Overflow {
+    /// Wraps around, keeping the lower 64 bits of the result.
+    #[default]
+    Wrap,
+
+    /// Fails with an error.
+    Check,
+
+    /// Gives the minimum or the maximum integer.
+    Saturate,
+}
+
+impl Overflow {
+    fn prefix(&self) -> &'static str {
+        match self {
+            Overflow::Wrap => "wrapping",
+            Overflow::Check => "checked",
+            Overflow::Saturate => "saturating",
+        }
+    }
+}
+
+/// Binds the externals of the operators `add`, `sub` and `mul` on integers to the versions that
+/// do what the overflow says. The externals bound to a version by name keep it.
+pub fn overflow(program: &mut Program, overflow: Overflow) {
+    for external in &mut program.externals {
+        let binding = external.binding.get();
+        let binding = binding.trim();
+
+        if !matches!(binding, "add" | "sub" | "mul") {
+            continue;
+        }
+
+        let (params, _) = external.typ.arrow_spine();
+
+        if params.first().and_then(|x| number(x)) == Some(Number::INT) {
+            let binding = format!("{}_{}", overflow.prefix(), binding);
+            external.binding = Symbol::intern(&binding);
+        }
+    }
+}
+
+/// The numeric type of the prelude that a type is, if it's one.
+pub fn number(typ: &TypeKind) -> Option<Number> {
+    match typ {
+        TypeKind::Constructor(name) if name.path.get() == "Prelude" => Number::of(&name.name.get()),
+        _ => None,
+    }
+}
+
+/// The representation of the values that a primitive works on. Primitives like [Primitive::Add]
+/// work on more than one of them, so backends choose the code by the types of the arguments.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Operand {
+    Int,
+    Float,
+    Char,
+    String,
+    Unit,
+    Bool,
+    Other,
+}
+
+impl Operand {
+    /// The sized numbers are kept like the integers and the floats of 64 bits, so they're the
+    /// same operands.
+    pub fn of(typ: &TypeKind) -> Operand {
+        match (number(typ), typ) {
+            (Some(Number::Int { .. }), _) => Operand::Int,
+            (Some(Number::Float { .. }), _) => Operand::Float,
+            (None, TypeKind::Constructor(name)) if name.path.get() == "Prelude" => {
+                match name.name.get().as_str() {
+                    "Char" => Operand::Char,
+                    "String" => Operand::String,
+                    "Bool" => Operand::Bool,
+                    _ => Operand::Other,
+                }
+            }
+            (None, TypeKind::Tuple(types)) if types.is_empty() => Operand::Unit,
+            _ => Operand::Other,
+        }
+    }
+}
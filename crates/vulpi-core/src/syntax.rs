@@ -0,0 +1,451 @@
+//! The syntax of the core language. It's a small language in A-normal form, so every intermediate
+//! result is bound to a name and the arguments of every operation are atoms. Control flow only
+//! happens through [TermKind::Match] and join points, and every binder carries its type.
+
+use std::collections::HashSet;
+
+use vulpi_intern::Symbol;
+use vulpi_location::Span;
+use vulpi_macros::Show;
+use vulpi_syntax::{
+    elaborated::Literal,
+    r#abstract::{OperationKind, Qualified, Visibility},
+};
+
+#[derive(Show, Clone, PartialEq, Eq, Hash)]
+pub enum TypeKind {
+    /// A type that is defined by the user or a primitive type like `Prelude.Int`.
+    Constructor(Qualified),
+
+    /// A type variable that is bound by a [TypeKind::Forall] as a De Bruijn index.
+    Bound(usize),
+
+    Arrow(Type, Type),
+    Tuple(Vec<Type>),
+    Application(Type, Vec<Type>),
+    Forall(Symbol, Type),
+
+    /// A type that was not recorded by the type checker.
+    Unknown,
+}
+
+pub type Type = Box<TypeKind>;
+
+impl TypeKind {
+    pub fn unknown() -> Type {
+        Box::new(TypeKind::Unknown)
+    }
+
+    /// Returns the types of the parameters and the return type, skipping the quantifiers at the
+    /// start of the type.
+    pub fn arrow_spine(&self) -> (Vec<Type>, Type) {
+        let mut current = self;
+
+        while let TypeKind::Forall(_, body) = current {
+            current = body;
+        }
+
+        let mut params = Vec::new();
+
+        while let TypeKind::Arrow(param, body) = current {
+            params.push(param.clone());
+            current = body;
+        }
+
+        (params, Box::new(current.clone()))
+    }
+
+    /// The number of quantifiers at the start of the type.
+    pub fn binders(&self) -> usize {
+        match self {
+            TypeKind::Forall(_, body) => 1 + body.binders(),
+            _ => 0,
+        }
+    }
+
+    /// Replaces the quantifiers at the start of the type by the arguments, in the order that the
+    /// quantifiers appear.
+    pub fn instantiate(&self, args: &[Type]) -> Type {
+        let mut current = self;
+
+        for _ in args {
+            match current {
+                TypeKind::Forall(_, body) => current = body,
+                _ => break,
+            }
+        }
+
+        current.substitute(args, 0)
+    }
+
+    /// Replaces the variables bound outside of the type by the arguments. The first argument is
+    /// the outermost binder and the offset is the number of binders that were crossed.
+    pub fn substitute(&self, args: &[Type], offset: usize) -> Type {
+        Box::new(match self {
+            TypeKind::Bound(index) if *index >= offset && *index - offset < args.len() => {
+                return args[args.len() - 1 - (*index - offset)].clone()
+            }
+            TypeKind::Arrow(param, body) => TypeKind::Arrow(
+                param.substitute(args, offset),
+                body.substitute(args, offset),
+            ),
+            TypeKind::Tuple(types) => {
+                TypeKind::Tuple(types.iter().map(|x| x.substitute(args, offset)).collect())
+            }
+            TypeKind::Application(func, types) => TypeKind::Application(
+                func.substitute(args, offset),
+                types.iter().map(|x| x.substitute(args, offset)).collect(),
+            ),
+            TypeKind::Forall(name, body) => {
+                TypeKind::Forall(name.clone(), body.substitute(args, offset + 1))
+            }
+            typ => typ.clone(),
+        })
+    }
+}
+
+/// The name of the constructor of tuples with the given size. Tuples are desugared into
+/// constructors so backends only need to know about one kind of product.
+pub fn tuple(size: usize) -> Qualified {
+    Qualified {
+        path: Symbol::intern("Tuple"),
+        name: Symbol::intern(&size.to_string()),
+    }
+}
+
+#[derive(Show, Clone)]
+pub struct Binder {
+    pub name: Symbol,
+    pub typ: Type,
+}
+
+#[derive(Show, Clone)]
+pub enum Atom {
+    Variable(Symbol),
+
+    /// A let declaration or external with the types that its quantifiers are instantiated to.
+    Function(Qualified, Vec<Type>),
+
+    Literal(Literal),
+}
+
+#[derive(Show, Clone)]
+pub enum Value {
+    Atom(Atom),
+
+    /// A function that receives all the parameters at once. Partial application of it has the
+    /// same meaning as applying a curried function.
+    Lambda(Vec<Binder>, Term),
+
+    /// Calls the function with the arguments. The span is the one of the expression that the call
+    /// comes from, so backends can locate the call in the sources.
+    Application(Atom, Vec<Atom>, Span),
+
+    /// A saturated constructor. Records and tuples are constructors too.
+    Constructor(Qualified, Vec<Atom>),
+
+    /// Accesses a field of a value built by the constructor by its position.
+    Field(Qualified, Atom, usize),
+
+    /// Performs an operation, giving control to the closest handler of that operation or to the
+    /// handler instance given in the first field.
+    Perform(Option<Atom>, Qualified, Vec<Atom>, Span),
+
+    /// Runs the thunk with the handler installed. It has the same meaning as the one of the Lambda
+    /// IR, so each clause receives the arguments of the operation followed by the continuation
+    /// for `ctl` operations and the third atom receives the final value. The last one is the thunk
+    /// of the `finally`, that runs when the handler is left, even by an operation that aborts.
+    Handle(
+        Atom,
+        Vec<(Qualified, OperationKind, Atom)>,
+        Atom,
+        Option<Atom>,
+    ),
+
+    /// Runs the thunk with the operations masked, so performing one of them skips the closest
+    /// handler of it.
+    Mask(Vec<Qualified>, Atom),
+}
+
+#[derive(Show, Clone, PartialEq, Eq)]
+pub enum Case {
+    Constructor(Qualified),
+    Literal(Literal),
+
+    /// An inclusive range of integers or of characters.
+    Range(Literal, Literal),
+}
+
+#[derive(Show, Clone)]
+pub struct Alt {
+    pub case: Case,
+    pub binders: Vec<Binder>,
+    pub body: Term,
+}
+
+#[derive(Show, Clone)]
+pub enum TermKind {
+    Let(Binder, Value, Term),
+
+    /// Declares a join point with its parameters and body that can be jumped to from the last
+    /// term. It's used to avoid duplicating the code that comes after a match. The body can jump
+    /// to its own join point too, which makes it a loop.
+    Join(Symbol, Vec<Binder>, Term, Term),
+
+    Jump(Symbol, Vec<Atom>),
+
+    /// Calls the function and returns its result. The caller has nothing left to do after the
+    /// call, so backends can run it without keeping the frame of the caller.
+    Tail(Atom, Vec<Atom>, Span),
+
+    /// Matches the atom against the alternatives, running the last term if none of them match.
+    Match(Atom, Vec<Alt>, Option<Term>),
+
+    Return(Atom),
+
+    /// A term that cannot be reached because the pattern matching is exhaustive.
+    Unreachable,
+}
+
+pub type Term = Box<TermKind>;
+
+#[derive(Show, Clone)]
+pub struct LetDecl {
+    pub name: Qualified,
+    pub span: Span,
+    pub visibility: Visibility,
+    pub typ: Type,
+    pub params: Vec<Binder>,
+    pub body: Term,
+
+    /// The span of the whole declaration, from its visibility to the end of its body.
+    pub declaration: Span,
+
+    /// The lints that are allowed in the declaration.
+    pub allow: Vec<Symbol>,
+}
+
+#[derive(Show, Clone)]
+pub struct TypeDecl {
+    pub name: Qualified,
+    pub constructors: Vec<(Qualified, usize)>,
+}
+
+#[derive(Show, Clone)]
+pub struct EffectDecl {
+    pub name: Qualified,
+    pub operations: Vec<(Qualified, usize, OperationKind)>,
+}
+
+#[derive(Show, Clone)]
+pub struct ExternalDecl {
+    pub name: Qualified,
+    pub typ: Type,
+    pub effect: Qualified,
+    pub convention: Option<Symbol>,
+    pub binding: Symbol,
+}
+
+impl ExternalDecl {
+    /// Externals with the `"c"` convention are bound to a function of C by its symbol, and the
+    /// backends that can call C functions marshal the arguments by their types.
+    pub fn is_foreign(&self) -> bool {
+        self.convention.as_ref().is_some_and(|x| x.get() == "c")
+    }
+}
+
+#[derive(Show, Clone, Default)]
+pub struct Program {
+    pub types: Vec<TypeDecl>,
+    pub effects: Vec<EffectDecl>,
+    pub externals: Vec<ExternalDecl>,
+    pub lets: Vec<LetDecl>,
+    pub commands: Vec<(Symbol, Symbol)>,
+
+    /// The number of names that were generated, so the passes that run after the lowering can
+    /// create new names that do not clash with the existing ones.
+    pub names: usize,
+}
+
+impl Program {
+    pub fn fresh(&mut self, name: &str) -> Symbol {
+        let id = self.names;
+        self.names += 1;
+        Symbol::intern(&format!("{}${}", name, id))
+    }
+}
+
+/// The variables that are used by the term but are not bound inside of it, in the order that
+/// they're used.
+pub fn free_variables(params: &[Binder], body: &TermKind) -> Vec<Symbol> {
+    let mut bound: HashSet<_> = params.iter().map(|x| x.name.clone()).collect();
+    let mut used = Vec::new();
+
+    bound_term(body, &mut bound, &mut used);
+
+    let mut free = Vec::new();
+
+    for name in used {
+        if !bound.contains(&name) && !free.contains(&name) {
+            free.push(name);
+        }
+    }
+
+    free
+}
+
+fn bound_atom(atom: &Atom, used: &mut Vec<Symbol>) {
+    if let Atom::Variable(name) = atom {
+        used.push(name.clone());
+    }
+}
+
+fn bound_value(value: &Value, bound: &mut HashSet<Symbol>, used: &mut Vec<Symbol>) {
+    match value {
+        Value::Atom(atom) | Value::Field(_, atom, _) => bound_atom(atom, used),
+        Value::Lambda(params, body) => {
+            bound.extend(params.iter().map(|x| x.name.clone()));
+            bound_term(body, bound, used);
+        }
+        Value::Application(func, args, _) => {
+            bound_atom(func, used);
+            args.iter().for_each(|x| bound_atom(x, used));
+        }
+        Value::Constructor(_, args) => args.iter().for_each(|x| bound_atom(x, used)),
+        Value::Perform(instance, _, args, _) => {
+            instance.iter().for_each(|x| bound_atom(x, used));
+            args.iter().for_each(|x| bound_atom(x, used));
+        }
+        Value::Handle(thunk, clauses, ret, finally) => {
+            bound_atom(thunk, used);
+            clauses.iter().for_each(|(_, _, x)| bound_atom(x, used));
+            bound_atom(ret, used);
+            finally.iter().for_each(|x| bound_atom(x, used));
+        }
+        Value::Mask(_, thunk) => bound_atom(thunk, used),
+    }
+}
+
+fn bound_term(term: &TermKind, bound: &mut HashSet<Symbol>, used: &mut Vec<Symbol>) {
+    match term {
+        TermKind::Let(binder, value, rest) => {
+            bound.insert(binder.name.clone());
+            bound_value(value, bound, used);
+            bound_term(rest, bound, used);
+        }
+        TermKind::Join(_, params, body, rest) => {
+            bound.extend(params.iter().map(|x| x.name.clone()));
+            bound_term(body, bound, used);
+            bound_term(rest, bound, used);
+        }
+        TermKind::Jump(_, args) => args.iter().for_each(|x| bound_atom(x, used)),
+        TermKind::Tail(func, args, _) => {
+            bound_atom(func, used);
+            args.iter().for_each(|x| bound_atom(x, used));
+        }
+        TermKind::Match(atom, alts, default) => {
+            bound_atom(atom, used);
+
+            for alt in alts {
+                bound.extend(alt.binders.iter().map(|x| x.name.clone()));
+                bound_term(&alt.body, bound, used);
+            }
+
+            if let Some(default) = default {
+                bound_term(default, bound, used);
+            }
+        }
+        TermKind::Return(atom) => bound_atom(atom, used),
+        TermKind::Unreachable => (),
+    }
+}
+
+/// Calls the function on every atom of the term, including the ones inside of lambdas.
+pub fn visit_term(term: &TermKind, f: &mut dyn FnMut(&Atom)) {
+    match term {
+        TermKind::Let(_, value, rest) => {
+            visit_value(value, f);
+            visit_term(rest, f);
+        }
+        TermKind::Join(_, _, body, rest) => {
+            visit_term(body, f);
+            visit_term(rest, f);
+        }
+        TermKind::Jump(_, args) => args.iter().for_each(f),
+        TermKind::Tail(func, args, _) => {
+            f(func);
+            args.iter().for_each(f);
+        }
+        TermKind::Match(atom, alts, default) => {
+            f(atom);
+
+            for alt in alts {
+                visit_term(&alt.body, f);
+            }
+
+            if let Some(default) = default {
+                visit_term(default, f);
+            }
+        }
+        TermKind::Return(atom) => f(atom),
+        TermKind::Unreachable => (),
+    }
+}
+
+pub fn visit_value(value: &Value, f: &mut dyn FnMut(&Atom)) {
+    match value {
+        Value::Atom(atom) | Value::Field(_, atom, _) => f(atom),
+        Value::Lambda(_, body) => visit_term(body, f),
+        Value::Application(func, args, _) => {
+            f(func);
+            args.iter().for_each(f);
+        }
+        Value::Constructor(_, args) | Value::Perform(None, _, args, _) => args.iter().for_each(f),
+        Value::Perform(Some(instance), _, args, _) => {
+            f(instance);
+            args.iter().for_each(f);
+        }
+        Value::Handle(thunk, clauses, ret, finally) => {
+            f(thunk);
+
+            for (_, _, clause) in clauses {
+                f(clause);
+            }
+
+            f(ret);
+            finally.iter().for_each(f);
+        }
+        Value::Mask(_, thunk) => f(thunk),
+    }
+}
+
+/// Calls the function on the span of every call and perform of the term, including the ones
+/// inside of lambdas.
+pub fn visit_spans(term: &TermKind, f: &mut dyn FnMut(&Span)) {
+    match term {
+        TermKind::Let(_, value, rest) => {
+            match value {
+                Value::Lambda(_, body) => visit_spans(body, f),
+                Value::Application(.., span) | Value::Perform(.., span) => f(span),
+                _ => (),
+            }
+
+            visit_spans(rest, f);
+        }
+        TermKind::Join(_, _, body, rest) => {
+            visit_spans(body, f);
+            visit_spans(rest, f);
+        }
+        TermKind::Tail(.., span) => f(span),
+        TermKind::Match(_, alts, default) => {
+            for alt in alts {
+                visit_spans(&alt.body, f);
+            }
+
+            if let Some(default) = default {
+                visit_spans(default, f);
+            }
+        }
+        TermKind::Jump(..) | TermKind::Return(_) | TermKind::Unreachable => (),
+    }
+}
@@ -0,0 +1,144 @@
+//! Tail calls. Applications whose result is returned right away become [TermKind::Tail], so the
+//! backends can run them without keeping the frame of the caller. A let declaration that calls
+//! itself in tail position with all of its parameters and the same type arguments becomes a loop:
+//! its body is wrapped in a join point and these calls become jumps to it.
+
+use vulpi_intern::Symbol;
+
+use crate::syntax::*;
+
+/// Rewrites the tail calls of every let declaration of the program.
+pub fn optimize(program: &mut Program) {
+    let mut lets = std::mem::take(&mut program.lets);
+
+    for decl in &mut lets {
+        tail(&mut decl.body);
+
+        if !decl.params.is_empty() {
+            self_loop(program, decl);
+        }
+    }
+
+    program.lets = lets;
+}
+
+/// Turns the applications in tail position of the term and of the lambdas inside of it into
+/// [TermKind::Tail].
+fn tail(term: &mut TermKind) {
+    match term {
+        TermKind::Let(binder, value, rest) => {
+            if let Value::Lambda(_, body) = value {
+                tail(body);
+            }
+
+            let returned = matches!(
+                &**rest,
+                TermKind::Return(Atom::Variable(name)) if *name == binder.name
+            );
+
+            match value {
+                Value::Application(func, args, span) if returned => {
+                    *term = TermKind::Tail(func.clone(), std::mem::take(args), span.clone());
+                }
+                _ => tail(rest),
+            }
+        }
+        TermKind::Join(_, _, body, rest) => {
+            tail(body);
+            tail(rest);
+        }
+        TermKind::Match(_, alts, default) => {
+            for alt in alts {
+                tail(&mut alt.body);
+            }
+
+            if let Some(default) = default {
+                tail(default);
+            }
+        }
+        TermKind::Jump(..) | TermKind::Tail(..) | TermKind::Return(_) | TermKind::Unreachable => (),
+    }
+}
+
+/// Checks if the type arguments instantiate each quantifier of a declaration with itself, that is
+/// the case for recursive calls that do not change the types.
+fn same_types(args: &[Type], binders: usize) -> bool {
+    args.len() == binders
+        && args
+            .iter()
+            .enumerate()
+            .all(|(i, arg)| matches!(**arg, TypeKind::Bound(index) if index == binders - 1 - i))
+}
+
+/// Replaces the tail calls to the declaration by jumps to the label. Lambdas are not visited
+/// because their tail calls leave the frame of the lambda and not the one of the declaration.
+fn jumps(decl: &LetDecl, label: &Symbol, term: &mut TermKind) -> bool {
+    match term {
+        TermKind::Tail(Atom::Function(name, types), args, _)
+            if *name == decl.name
+                && args.len() == decl.params.len()
+                && same_types(types, decl.typ.binders()) =>
+        {
+            *term = TermKind::Jump(label.clone(), std::mem::take(args));
+            true
+        }
+        TermKind::Let(_, _, rest) => jumps(decl, label, rest),
+        TermKind::Join(_, _, body, rest) => {
+            let body = jumps(decl, label, body);
+            jumps(decl, label, rest) || body
+        }
+        TermKind::Match(_, alts, default) => {
+            let mut found = false;
+
+            for alt in alts {
+                found |= jumps(decl, label, &mut alt.body);
+            }
+
+            if let Some(default) = default {
+                found |= jumps(decl, label, default);
+            }
+
+            found
+        }
+        _ => false,
+    }
+}
+
+fn self_loop(program: &mut Program, decl: &mut LetDecl) {
+    let label = program.fresh("loop");
+    let mut body = std::mem::replace(&mut decl.body, Box::new(TermKind::Unreachable));
+
+    if !jumps(decl, &label, &mut body) {
+        decl.body = body;
+        return;
+    }
+
+    // The parameters of the declaration get new names and the old ones become the parameters of
+    // the loop, so the body does not need to be renamed.
+    let params: Vec<_> = decl
+        .params
+        .iter()
+        .map(|param| {
+            let name = param.name.get();
+            let prefix = name.split('$').next().unwrap_or("p");
+
+            Binder {
+                name: program.fresh(prefix),
+                typ: param.typ.clone(),
+            }
+        })
+        .collect();
+
+    let args = params
+        .iter()
+        .map(|x| Atom::Variable(x.name.clone()))
+        .collect();
+    let loop_params = std::mem::replace(&mut decl.params, params);
+
+    *decl.body = TermKind::Join(
+        label.clone(),
+        loop_params,
+        body,
+        Box::new(TermKind::Jump(label, args)),
+    );
+}
@@ -0,0 +1,328 @@
+//! Usage analysis of the core language and the trimming of the environments of closures that
+//! uses it. The analysis counts the uses of each variable and finds the ones that are strict in a
+//! term, that is, used on every path that returns from it.
+//!
+//! Closure conversion captures the free variables of each lambda. A free variable that is bound to
+//! a literal or to a constructor that is just a tag is cheaper to build again than to keep in the
+//! environment, so the lambda binds it again by itself. The new binding is put in the smallest
+//! part of the body that has all of its uses, so the branches that don't use the variable don't
+//! build it, and the old one is removed if nothing else uses it.
+
+use std::collections::{HashMap, HashSet};
+
+use vulpi_intern::Symbol;
+
+use crate::{
+    layout::{Layout, Layouts},
+    syntax::*,
+};
+
+/// How a variable is used by a term.
+#[derive(Clone, Copy, Default)]
+pub struct Usage {
+    /// The number of atoms that refer to the variable, including the ones inside of lambdas.
+    pub uses: usize,
+
+    /// Whether the variable is used on every path of the term, without counting the uses inside
+    /// of lambdas that may never be called.
+    pub strict: bool,
+}
+
+/// The variables that are used on every path of the term. `None` means all of them, which is the
+/// case of the paths that cannot be reached.
+fn strict(term: &TermKind) -> Option<HashSet<Symbol>> {
+    let atoms = |atoms: &mut dyn Iterator<Item = &Atom>| -> HashSet<Symbol> {
+        atoms
+            .filter_map(|atom| match atom {
+                Atom::Variable(name) => Some(name.clone()),
+                _ => None,
+            })
+            .collect()
+    };
+
+    let union = |mut left: HashSet<Symbol>, right: Option<HashSet<Symbol>>| {
+        left.extend(right?);
+        Some(left)
+    };
+
+    match term {
+        TermKind::Let(_, value, rest) => {
+            let used = match value {
+                Value::Lambda(..) => HashSet::new(),
+                value => {
+                    let mut used = Vec::new();
+                    visit_value(value, &mut |atom| used.push(atom.clone()));
+                    atoms(&mut used.iter())
+                }
+            };
+
+            union(used, strict(rest))
+        }
+        // The body of the join point runs only when it's jumped to, so only the rest counts.
+        TermKind::Join(_, _, _, rest) => strict(rest),
+        TermKind::Jump(_, args) => Some(atoms(&mut args.iter())),
+        TermKind::Tail(func, args, _) => Some(atoms(&mut std::iter::once(func).chain(args))),
+        TermKind::Match(atom, alts, default) => {
+            let mut common: Option<HashSet<Symbol>> = None;
+
+            let branches = alts
+                .iter()
+                .map(|alt| &alt.body)
+                .chain(default.iter())
+                .filter_map(|x| strict(x));
+
+            for branch in branches {
+                common = Some(match common {
+                    Some(common) => common.intersection(&branch).cloned().collect(),
+                    None => branch,
+                });
+            }
+
+            match common {
+                Some(common) => union(atoms(&mut std::iter::once(atom)), Some(common)),
+                None => None,
+            }
+        }
+        TermKind::Return(atom) => Some(atoms(&mut std::iter::once(atom))),
+        TermKind::Unreachable => None,
+    }
+}
+
+/// The usage of the variables that the term uses.
+pub fn usage(term: &TermKind) -> HashMap<Symbol, Usage> {
+    let mut usage: HashMap<Symbol, Usage> = HashMap::new();
+
+    visit_term(term, &mut |atom| {
+        if let Atom::Variable(name) = atom {
+            usage.entry(name.clone()).or_default().uses += 1;
+        }
+    });
+
+    if let Some(strict) = strict(term) {
+        for name in strict {
+            if let Some(usage) = usage.get_mut(&name) {
+                usage.strict = true;
+            }
+        }
+    } else {
+        usage.values_mut().for_each(|x| x.strict = true);
+    }
+
+    usage
+}
+
+fn uses(term: &TermKind, name: &Symbol) -> bool {
+    let mut found = false;
+
+    visit_term(term, &mut |atom| {
+        found |= matches!(atom, Atom::Variable(x) if x == name);
+    });
+
+    found
+}
+
+fn value_uses(value: &Value, name: &Symbol) -> bool {
+    let mut found = false;
+
+    visit_value(value, &mut |atom| {
+        found |= matches!(atom, Atom::Variable(x) if x == name);
+    });
+
+    found
+}
+
+fn rename_atom(atom: &mut Atom, from: &Symbol, to: &Symbol) {
+    if matches!(atom, Atom::Variable(x) if x == from) {
+        *atom = Atom::Variable(to.clone());
+    }
+}
+
+fn rename_atoms(atoms: &mut [Atom], from: &Symbol, to: &Symbol) {
+    atoms.iter_mut().for_each(|x| rename_atom(x, from, to));
+}
+
+fn rename_value(value: &mut Value, from: &Symbol, to: &Symbol) {
+    match value {
+        Value::Atom(atom) | Value::Field(_, atom, _) => rename_atom(atom, from, to),
+        Value::Lambda(_, body) => rename(body, from, to),
+        Value::Application(func, args, _) => {
+            rename_atom(func, from, to);
+            rename_atoms(args, from, to);
+        }
+        Value::Constructor(_, args) => rename_atoms(args, from, to),
+        Value::Perform(instance, _, args, _) => {
+            instance.iter_mut().for_each(|x| rename_atom(x, from, to));
+            rename_atoms(args, from, to);
+        }
+        Value::Handle(thunk, clauses, ret, finally) => {
+            rename_atom(thunk, from, to);
+            clauses
+                .iter_mut()
+                .for_each(|x| rename_atom(&mut x.2, from, to));
+            rename_atom(ret, from, to);
+            finally.iter_mut().for_each(|x| rename_atom(x, from, to));
+        }
+        Value::Mask(_, thunk) => rename_atom(thunk, from, to),
+    }
+}
+
+/// Renames the uses of a variable in the term, including the ones inside of lambdas.
+fn rename(term: &mut TermKind, from: &Symbol, to: &Symbol) {
+    match term {
+        TermKind::Let(_, value, rest) => {
+            rename_value(value, from, to);
+            rename(rest, from, to);
+        }
+        TermKind::Join(_, _, body, rest) => {
+            rename(body, from, to);
+            rename(rest, from, to);
+        }
+        TermKind::Jump(_, args) => rename_atoms(args, from, to),
+        TermKind::Tail(func, args, _) => {
+            rename_atom(func, from, to);
+            rename_atoms(args, from, to);
+        }
+        TermKind::Match(atom, alts, default) => {
+            rename_atom(atom, from, to);
+            alts.iter_mut().for_each(|x| rename(&mut x.body, from, to));
+            default.iter_mut().for_each(|x| rename(x, from, to));
+        }
+        TermKind::Return(atom) => rename_atom(atom, from, to),
+        TermKind::Unreachable => (),
+    }
+}
+
+/// Binds the variable in the smallest part of the term that has all of its uses. A variable that
+/// is strict in the term is bound at its start.
+fn sink(term: &mut Term, binder: Binder, value: Value) {
+    let strict = usage(term).get(&binder.name).is_some_and(|x| x.strict);
+    let scrutinee = matches!(&**term, TermKind::Match(Atom::Variable(x), ..) if *x == binder.name);
+
+    let inner: Option<&mut Term> = match &mut **term {
+        _ if strict => None,
+        TermKind::Let(_, bound, rest) if !value_uses(bound, &binder.name) => Some(rest),
+        TermKind::Join(_, _, body, rest) => {
+            match (uses(body, &binder.name), uses(rest, &binder.name)) {
+                (true, false) => Some(body),
+                (false, true) => Some(rest),
+                _ => None,
+            }
+        }
+        TermKind::Match(_, alts, default) if !scrutinee => {
+            let mut branches = alts
+                .iter_mut()
+                .map(|x| &mut x.body)
+                .chain(default.iter_mut())
+                .filter(|x| uses(x, &binder.name));
+
+            match (branches.next(), branches.next()) {
+                (Some(branch), None) => Some(branch),
+                _ => None,
+            }
+        }
+        _ => None,
+    };
+
+    match inner {
+        Some(inner) => sink(inner, binder, value),
+        None => {
+            let rest = std::mem::replace(term, Box::new(TermKind::Unreachable));
+            **term = TermKind::Let(binder, value, rest);
+        }
+    }
+}
+
+struct Trimmer<'a> {
+    program: &'a mut Program,
+    layouts: Layouts,
+
+    /// The variables in scope that are bound to values that are cheap to build again.
+    cheap: HashMap<Symbol, (Type, Value)>,
+}
+
+impl Trimmer<'_> {
+    fn is_cheap(&self, value: &Value) -> bool {
+        match value {
+            Value::Atom(Atom::Literal(_)) => true,
+            Value::Constructor(name, args) => {
+                args.is_empty() && matches!(self.layouts.get(name), Layout::Tag(_))
+            }
+            _ => false,
+        }
+    }
+
+    fn lambda(&mut self, params: &[Binder], body: &mut Term) {
+        self.term(body);
+
+        for name in free_variables(params, body) {
+            let Some((typ, value)) = self.cheap.get(&name).cloned() else {
+                continue;
+            };
+
+            let binder = Binder {
+                name: self
+                    .program
+                    .fresh(name.get().split('$').next().unwrap_or("x")),
+                typ,
+            };
+
+            rename(body, &name, &binder.name);
+            sink(body, binder, value);
+        }
+    }
+
+    fn term(&mut self, term: &mut Term) {
+        match &mut **term {
+            TermKind::Let(binder, value, rest) => {
+                if let Value::Lambda(params, body) = value {
+                    self.lambda(params, body);
+                }
+
+                let cheap = self.is_cheap(value);
+
+                if cheap {
+                    let cheap = (binder.typ.clone(), value.clone());
+                    self.cheap.insert(binder.name.clone(), cheap);
+                }
+
+                self.term(rest);
+
+                // The closures that used the variable bind it by themselves now.
+                if cheap && !uses(rest, &binder.name) {
+                    let rest = std::mem::replace(rest, Box::new(TermKind::Unreachable));
+                    *term = rest;
+                }
+            }
+            TermKind::Join(_, _, body, rest) => {
+                self.term(body);
+                self.term(rest);
+            }
+            TermKind::Match(_, alts, default) => {
+                alts.iter_mut().for_each(|x| self.term(&mut x.body));
+                default.iter_mut().for_each(|x| self.term(x));
+            }
+            TermKind::Jump(..)
+            | TermKind::Tail(..)
+            | TermKind::Return(_)
+            | TermKind::Unreachable => (),
+        }
+    }
+}
+
+/// Trims the environments of the closures of the program.
+pub fn trim(program: &mut Program) {
+    let mut lets = std::mem::take(&mut program.lets);
+
+    let mut trimmer = Trimmer {
+        layouts: Layouts::new(program),
+        program,
+        cheap: HashMap::new(),
+    };
+
+    for decl in &mut lets {
+        trimmer.cheap.clear();
+        trimmer.term(&mut decl.body);
+    }
+
+    program.lets = lets;
+}
@@ -1,19 +1,35 @@
 //! A simple string interner with no reference counting so it lives until the end of the program.
+//! It's shared by all the threads, so symbols interned while parsing modules in parallel are the
+//! same as the ones of the thread that resolves them. The strings are split in shards by their
+//! hashes, and both the shards and the table of the ids are changed with atomic operations, so no
+//! thread waits for another one to intern or to read a string.
 
+use lazy_static::lazy_static;
+use serde::{Deserialize, Serialize};
 use vulpi_show::Show;
 
-use std::cell::RefCell;
-use std::collections::HashMap;
-use std::sync::atomic::{AtomicUsize, Ordering};
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+use std::ptr;
+use std::sync::atomic::{AtomicPtr, AtomicUsize, Ordering};
 
-thread_local! {
-    static INTERNER: Interner = Interner::default();
+/// The number of shards of the interner. Each one is a list of the strings whose hashes fall in
+/// it.
+const SHARDS: usize = 1 << 14;
+
+/// The number of ids in each segment of the table of the strings, and the number of segments, so
+/// the interner holds up to 16 million strings.
+const SEGMENT: usize = 1 << 12;
+const SEGMENTS: usize = 1 << 12;
+
+lazy_static! {
+    static ref INTERNER: Interner = Interner::default();
 }
 
 /// A symbol is a reference to a string inside the interner. It is used to compare strings by
 /// comparing their ids instead of comparing their content because it is more efficient (it makes
 /// the comparison an integer comparison instead of a string comparison).
-#[derive(Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[derive(Clone, PartialEq, Eq, Hash)]
 pub enum Symbol {
     Generated(usize),
     Interned(usize),
@@ -27,22 +43,54 @@ impl std::fmt::Debug for Symbol {
 
 impl Symbol {
     pub fn intern(string: &str) -> Self {
-        INTERNER.with(|i| i.intern(string))
+        INTERNER.intern(string)
     }
 
     pub fn get(&self) -> String {
-        INTERNER.with(|i| i.get(self).unwrap())
+        INTERNER.get(self).unwrap()
     }
 
     pub fn get_static(&self) -> &'static str {
-        INTERNER.with(|i| match self {
+        match self {
             Symbol::Generated(_) => todo!(),
-            Symbol::Interned(id) => {
-                let id_to_string = i.id_to_string.borrow();
-                let string = id_to_string.get(*id).unwrap();
-                Box::leak(string.clone().into_boxed_str())
-            },
-        })
+            Symbol::Interned(id) => INTERNER.string(*id).unwrap(),
+        }
+    }
+}
+
+/// Symbols are ordered by their strings instead of their ids, because the ids depend on the order
+/// that the threads interned the strings in, and the ordered collections of the compiler must be
+/// iterated in the same order in every compilation.
+impl Ord for Symbol {
+    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+        match (self, other) {
+            (Symbol::Interned(x), Symbol::Interned(y)) if x == y => std::cmp::Ordering::Equal,
+            (Symbol::Interned(_), Symbol::Interned(_)) => self.get_static().cmp(other.get_static()),
+            (Symbol::Generated(x), Symbol::Generated(y)) => x.cmp(y),
+            (Symbol::Generated(_), Symbol::Interned(_)) => std::cmp::Ordering::Less,
+            (Symbol::Interned(_), Symbol::Generated(_)) => std::cmp::Ordering::Greater,
+        }
+    }
+}
+
+impl PartialOrd for Symbol {
+    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+/// Symbols are serialized as their strings, because the ids depend on the order that the strings
+/// were interned in.
+impl serde::Serialize for Symbol {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&self.get())
+    }
+}
+
+impl<'de> serde::Deserialize<'de> for Symbol {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let string = String::deserialize(deserializer)?;
+        Ok(Symbol::intern(&string))
     }
 }
 
@@ -51,33 +99,254 @@ impl Show for Symbol {
         vulpi_show::TreeDisplay::label(&format!("Symbol: {}", self.get()))
     }
 }
-#[derive(Default)]
+/// A string of the interner in the list of its shard. Entries are never freed once they are in a
+/// shard or in the table, so they're borrowed for the rest of the program.
+struct Entry {
+    string: &'static str,
+    id: usize,
+    next: AtomicPtr<Entry>,
+}
+
+/// The entry that a pointer of the interner points to, if it's not null.
+fn entry(pointer: *mut Entry) -> Option<&'static Entry> {
+    // SAFETY: The pointers of the shards and of the table are either null or leaked entries.
+    unsafe { pointer.as_ref() }
+}
+
 struct Interner {
-    id_to_string: RefCell<Vec<String>>,
-    string_to_id: RefCell<HashMap<String, Symbol>>,
-    counter: AtomicUsize,
+    shards: Box<[AtomicPtr<Entry>]>,
+
+    /// The segments of the table from the ids to the entries, that are allocated when an id in
+    /// them is given.
+    segments: Box<[AtomicPtr<[AtomicPtr<Entry>; SEGMENT]>]>,
+
+    /// The number of ids that were given.
+    count: AtomicUsize,
+}
+
+impl Default for Interner {
+    fn default() -> Self {
+        Self {
+            shards: (0..SHARDS).map(|_| AtomicPtr::default()).collect(),
+            segments: (0..SEGMENTS).map(|_| AtomicPtr::default()).collect(),
+            count: AtomicUsize::new(0),
+        }
+    }
 }
 
 impl Interner {
+    fn shard(string: &str) -> usize {
+        let mut hasher = DefaultHasher::new();
+        string.hash(&mut hasher);
+        hasher.finish() as usize % SHARDS
+    }
+
+    /// Searches a string in the entries of a list, from the first one until the last one.
+    fn find(first: *mut Entry, last: *mut Entry, string: &str) -> Option<usize> {
+        let mut current = first;
+
+        while current != last {
+            let entry = entry(current)?;
+
+            if entry.string == string {
+                return Some(entry.id);
+            }
+
+            current = entry.next.load(Ordering::Acquire);
+        }
+
+        None
+    }
+
+    /// The place of an id in the table, allocating its segment if it's needed.
+    fn slot(&self, id: usize) -> &AtomicPtr<Entry> {
+        let segment = &self.segments[id / SEGMENT];
+        let mut pointer = segment.load(Ordering::Acquire);
+
+        if pointer.is_null() {
+            let new = Box::into_raw(Box::new(std::array::from_fn(|_| AtomicPtr::default())));
+
+            pointer = match segment.compare_exchange(
+                ptr::null_mut(),
+                new,
+                Ordering::AcqRel,
+                Ordering::Acquire,
+            ) {
+                Ok(_) => new,
+                Err(current) => {
+                    // SAFETY: The segment lost the race, so no other thread has seen it.
+                    drop(unsafe { Box::from_raw(new) });
+                    current
+                }
+            };
+        }
+
+        // SAFETY: The segments are never freed once they're in the table.
+        unsafe { &(*pointer)[id % SEGMENT] }
+    }
+
     fn intern(&self, string: &str) -> Symbol {
-        if let Some(id) = self.string_to_id.borrow().get(string) {
-            return id.clone();
+        let shard = &self.shards[Self::shard(string)];
+        let mut first = shard.load(Ordering::Acquire);
+
+        if let Some(id) = Self::find(first, ptr::null_mut(), string) {
+            return Symbol::Interned(id);
         }
 
-        let mut id_to_string = self.id_to_string.borrow_mut();
-        let mut string_to_id = self.string_to_id.borrow_mut();
+        // The id is in the table before the entry is in the shard, so the ids of the symbols are
+        // always found. If another thread puts the same string in the shard first, the id is
+        // never given and the table keeps the entry.
+        let id = self.count.fetch_add(1, Ordering::Relaxed);
+
+        let new = Box::leak(Box::new(Entry {
+            string: Box::leak(string.to_owned().into_boxed_str()),
+            id,
+            next: AtomicPtr::new(first),
+        }));
+
+        self.slot(id).store(new, Ordering::Release);
+
+        loop {
+            match shard.compare_exchange(first, new, Ordering::AcqRel, Ordering::Acquire) {
+                Ok(_) => return Symbol::Interned(id),
+                Err(current) => {
+                    // Only the entries that were put before the first one that we saw are new.
+                    if let Some(id) = Self::find(current, first, string) {
+                        return Symbol::Interned(id);
+                    }
 
-        let id = Symbol::Interned(self.counter.fetch_add(1, Ordering::SeqCst));
-        id_to_string.push(string.to_owned());
-        string_to_id.insert(string.to_owned(), id.clone());
+                    new.next.store(current, Ordering::Relaxed);
+                    first = current;
+                }
+            }
+        }
+    }
+
+    fn string(&self, id: usize) -> Option<&'static str> {
+        let segment = self.segments.get(id / SEGMENT)?.load(Ordering::Acquire);
 
-        id
+        // SAFETY: The segments are never freed once they're in the table.
+        let slot = unsafe { segment.as_ref() }?;
+        entry(slot[id % SEGMENT].load(Ordering::Acquire)).map(|x| x.string)
     }
 
     fn get(&self, id: &Symbol) -> Option<String> {
         match id {
             Symbol::Generated(n) => Some(format!("%{n}")),
-            Symbol::Interned(id) => self.id_to_string.borrow().get(*id).cloned(),
+            Symbol::Interned(id) => self.string(*id).map(str::to_owned),
+        }
+    }
+
+    fn snapshot(&self) -> Snapshot {
+        let count = self.count.load(Ordering::Acquire);
+
+        let strings = (0..count).map(|id| {
+            let string = self.string(id)?;
+            let first = self.shards[Self::shard(string)].load(Ordering::Acquire);
+            let same = Self::find(first, ptr::null_mut(), string) == Some(id);
+            same.then(|| string.to_owned())
+        });
+
+        Snapshot(strings.collect())
+    }
+
+    fn restore(&self, snapshot: &Snapshot) {
+        for (id, string) in snapshot.0.iter().enumerate() {
+            match string {
+                Some(string) => {
+                    self.intern(string);
+                }
+                None => {
+                    // The ids that were never given are skipped too, so the next ones are the
+                    // same if nothing was interned before.
+                    let _ = self.count.compare_exchange(
+                        id,
+                        id + 1,
+                        Ordering::AcqRel,
+                        Ordering::Relaxed,
+                    );
+                }
+            }
         }
     }
 }
+
+/// The strings of the interner, in the order of their ids, without the ids that were never given.
+/// A compilation that restores the snapshot of another one before interning anything gives the
+/// same ids to the same strings.
+#[derive(Clone, Default, Serialize, Deserialize)]
+pub struct Snapshot(Vec<Option<String>>);
+
+/// Takes a snapshot of all the strings that were interned.
+pub fn snapshot() -> Snapshot {
+    INTERNER.snapshot()
+}
+
+/// Interns the strings of a snapshot. Symbols are stored as their strings, so the ones that were
+/// interned before are still the same symbols.
+pub fn restore(snapshot: &Snapshot) {
+    INTERNER.restore(snapshot)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn threads_intern_the_same_ids() {
+        let interner = Interner::default();
+        let strings: Vec<_> = (0..2000).map(|x| format!("name{}", x % 500)).collect();
+
+        let ids: Vec<Vec<_>> = std::thread::scope(|scope| {
+            let threads: Vec<_> = (0..8)
+                .map(|thread| {
+                    let (interner, strings) = (&interner, &strings);
+
+                    scope.spawn(move || {
+                        // Every thread starts from another string, so they race for all of them.
+                        let mut ids = vec![None; strings.len()];
+
+                        for i in 0..strings.len() {
+                            let i = (i + thread * 250) % strings.len();
+                            ids[i] = Some(interner.intern(&strings[i]));
+                        }
+
+                        ids.into_iter().map(Option::unwrap).collect()
+                    })
+                })
+                .collect();
+
+            threads.into_iter().map(|x| x.join().unwrap()).collect()
+        });
+
+        for (i, string) in strings.iter().enumerate() {
+            let symbol = interner.intern(string);
+            assert!(ids.iter().all(|x| x[i] == symbol));
+            assert_eq!(interner.get(&symbol).as_deref(), Some(string.as_str()));
+        }
+
+        let distinct: std::collections::HashSet<_> = ids[0][..500].iter().collect();
+        assert_eq!(distinct.len(), 500);
+    }
+
+    #[test]
+    fn restores_the_ids_of_a_snapshot() {
+        let interner = Interner::default();
+        let strings = ["Main", "main", "Prelude", "Int", "add"];
+        let symbols: Vec<_> = strings.iter().map(|x| interner.intern(x)).collect();
+
+        // A hole of an id that was never given.
+        interner.count.fetch_add(1, Ordering::Relaxed);
+        let last = interner.intern("last");
+
+        let restored = Interner::default();
+        restored.restore(&interner.snapshot());
+
+        for (string, symbol) in strings.iter().zip(symbols) {
+            assert_eq!(restored.intern(string), symbol);
+        }
+
+        assert_eq!(restored.intern("last"), last);
+        assert_eq!(restored.intern("new"), interner.intern("new"));
+    }
+}
@@ -91,6 +91,21 @@ impl Check for lambda::ExprKind {
                     action.check(ctx);
                 }
             }
+            lambda::ExprKind::Perform(instance, _, args) => {
+                if let Some(instance) = instance {
+                    instance.check(ctx);
+                }
+                for arg in args {
+                    arg.check(ctx);
+                }
+            }
+            lambda::ExprKind::Handle(body, clauses, ret) => {
+                body.check(ctx);
+                for (_, _, clause) in clauses {
+                    clause.check(ctx);
+                }
+                ret.check(ctx);
+            }
         }
     }
 }
@@ -144,6 +159,8 @@ pub fn has_no_side_effects(expr: &lambda::Expr) -> bool {
         lambda::ExprKind::RecordUpdate(_, _, _) => false,
         lambda::ExprKind::Tuple(args) => args.iter().all(has_no_side_effects),
         lambda::ExprKind::Switch(_, _, _) => false,
+        lambda::ExprKind::Perform(_, _, _) => false,
+        lambda::ExprKind::Handle(_, _, _) => false,
     }
 }
 
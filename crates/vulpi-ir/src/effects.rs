@@ -0,0 +1,231 @@
+//! Lowering of effects into the delimited control primitives of the Lambda IR. Operations become
+//! [lambda::ExprKind::Perform] and handlers become [lambda::ExprKind::Handle] with one clause for
+//! each operation, so the backends only need to implement these two primitives instead of
+//! understanding effect patterns.
+
+use vulpi_syntax::{
+    elaborated::*,
+    lambda::{self, Stmt},
+    r#abstract::{OperationKind, Qualified},
+};
+use vulpi_typer::{real::Real, Type};
+
+use crate::transform::{compile_match_with_actions, Context, Transform};
+
+/// Lowers a reference to an operation into a curried function that performs it. Operations
+/// without arguments are performed right away. The instance is the named handler that the
+/// operation is performed on, if any.
+pub fn perform(
+    context: &mut Context,
+    instance: Option<lambda::Expr>,
+    name: Qualified,
+    arity: usize,
+) -> lambda::Expr {
+    let params: Vec<_> = (0..arity)
+        .map(|_| context.new_var("a".to_string()))
+        .collect();
+
+    let args = params
+        .iter()
+        .map(|x| Box::new(lambda::ExprKind::Variable(x.clone())))
+        .collect();
+
+    let body = Box::new(lambda::ExprKind::Perform(instance, name, args));
+
+    params.into_iter().rfold(body, |acc, name| {
+        Box::new(lambda::ExprKind::Lambda(vec![name], acc))
+    })
+}
+
+/// Lowers a handler expression. The handled expression is delayed inside of a thunk so the backend
+/// can install the handler before running it.
+pub fn handler(context: &mut Context, handler: &HandlerExpr<Type<Real>>) -> lambda::Expr {
+    let handled = handle(context, handler);
+
+    let Some(finally) = &handler.finally else {
+        return handled;
+    };
+
+    // The Lambda IR has no unwinding, so the `finally` only runs after the handler returns.
+    let result = context.new_var("r".to_string());
+    context.add_upwards(Stmt::Let(result.clone(), handled));
+
+    let finally = finally.transform(context);
+    context.add_upwards(Stmt::Expr(finally));
+
+    Box::new(lambda::ExprKind::Variable(result))
+}
+
+fn handle(context: &mut Context, handler: &HandlerExpr<Type<Real>>) -> lambda::Expr {
+    match &handler.handler {
+        Handler::Function(func) => {
+            let func = func.transform(context);
+            let expr = handler.expr.transform(context);
+            Box::new(lambda::ExprKind::Application(func, vec![expr]))
+        }
+        Handler::Cases(arms) => {
+            let body = context.scope(|context| {
+                let params = handler.name.iter().map(|x| context.add_var(x.clone())).collect();
+                let body = handler.expr.transform(context);
+                Box::new(lambda::ExprKind::Lambda(params, with_upwards(context, body)))
+            });
+
+            let mut operations: Vec<(&PatEffect, Vec<&PatternArm<Type<Real>>>)> = vec![];
+            let mut returns = vec![];
+
+            for arm in arms {
+                match &*arm.patterns[0] {
+                    PatternKind::Effect(eff) => {
+                        match operations.iter_mut().find(|(op, _)| op.func == eff.func) {
+                            Some((_, arms)) => arms.push(arm),
+                            None => operations.push((eff, vec![arm])),
+                        }
+                    }
+                    _ => returns.push(arm),
+                }
+            }
+
+            let mut clauses: Vec<_> = operations
+                .into_iter()
+                .map(|(eff, arms)| {
+                    let (arity, kind) = context.get_operation(&eff.func).unwrap();
+                    let default = default_of(handler, &eff.func);
+                    let clause = clause(context, &eff.func, arity, kind, arms, default);
+                    (eff.func.clone(), kind, clause)
+                })
+                .collect();
+
+            // Operations that are not matched by any arm are handled by their default.
+            for (func, default) in &handler.defaults {
+                if clauses.iter().all(|(name, _, _)| name != func) {
+                    let (arity, kind) = context.get_operation(func).unwrap();
+                    let clause = clause(context, func, arity, kind, vec![], Some(default));
+                    clauses.push((func.clone(), kind, clause));
+                }
+            }
+
+            let ret = return_clause(context, returns);
+
+            Box::new(lambda::ExprKind::Handle(body, clauses, ret))
+        }
+    }
+}
+
+fn default_of<'a>(handler: &'a HandlerExpr<Type<Real>>, func: &Qualified) -> Option<&'a Qualified> {
+    handler
+        .defaults
+        .iter()
+        .find(|(name, _)| name == func)
+        .map(|(_, default)| default)
+}
+
+/// Compiles all the arms that match on the same operation into a single function that receives
+/// the arguments of the operation and, for `ctl` operations, the continuation.
+fn clause(
+    context: &mut Context,
+    func: &Qualified,
+    arity: usize,
+    kind: OperationKind,
+    arms: Vec<&PatternArm<Type<Real>>>,
+    default: Option<&Qualified>,
+) -> lambda::Expr {
+    context.scope(|context| {
+        let mut params: Vec<_> = (0..arity)
+            .map(|_| context.new_var("a".to_string()))
+            .collect();
+
+        let cont = match kind {
+            OperationKind::Ctl => Some(context.new_var("k".to_string())),
+            OperationKind::Fun => None,
+        };
+
+        params.extend(cont.clone());
+
+        let mut rows = vec![];
+
+        for arm in &arms {
+            let PatternKind::Effect(eff) = &*arm.patterns[0] else {
+                unreachable!()
+            };
+
+            let mut row = eff.args.clone();
+
+            if cont.is_some() {
+                row.push(Box::new(match &eff.cont {
+                    Some(name) => PatternKind::Variable(name.clone()),
+                    None => PatternKind::Wildcard,
+                }));
+            }
+
+            rows.push(row);
+        }
+
+        // Requests that are not matched by any arm go to the default implementation of the
+        // operation or, if there is none, are performed again. The result is given back to the
+        // handled computation.
+        rows.push(vec![Box::new(PatternKind::Wildcard); params.len()]);
+
+        let args = params[..arity]
+            .iter()
+            .map(|x| Box::new(lambda::ExprKind::Variable(x.clone())));
+
+        let result = match default {
+            Some(default) => args.fold(
+                Box::new(lambda::ExprKind::Function(default.clone())),
+                |func, arg| Box::new(lambda::ExprKind::Application(func, vec![arg])),
+            ),
+            None => Box::new(lambda::ExprKind::Perform(None, func.clone(), args.collect())),
+        };
+
+        let forward = match cont {
+            Some(cont) => Box::new(lambda::ExprKind::Application(
+                Box::new(lambda::ExprKind::Variable(cont)),
+                vec![result],
+            )),
+            None => result,
+        };
+
+        let body = compile_match_with_actions(context, params.clone(), rows, |context, i| {
+            match arms.get(i) {
+                Some(arm) => arm.expr.transform(context),
+                None => forward.clone(),
+            }
+        });
+
+        Box::new(lambda::ExprKind::Lambda(params, with_upwards(context, body)))
+    })
+}
+
+/// Compiles the arms that match on the final value of the computation. Values that are not matched
+/// go through the handler untouched.
+fn return_clause(context: &mut Context, arms: Vec<&PatternArm<Type<Real>>>) -> lambda::Expr {
+    context.scope(|context| {
+        let value = context.new_var("v".to_string());
+
+        let mut rows: Vec<_> = arms.iter().map(|x| x.patterns.clone()).collect();
+        rows.push(vec![Box::new(PatternKind::Wildcard)]);
+
+        let body = compile_match_with_actions(context, vec![value.clone()], rows, |context, i| {
+            match arms.get(i) {
+                Some(arm) => arm.expr.transform(context),
+                None => Box::new(lambda::ExprKind::Variable(value.clone())),
+            }
+        });
+
+        Box::new(lambda::ExprKind::Lambda(
+            vec![value],
+            with_upwards(context, body),
+        ))
+    })
+}
+
+fn with_upwards(context: &mut Context, expr: lambda::Expr) -> lambda::Expr {
+    let mut upwards = context.drain_upwards();
+
+    if upwards.is_empty() {
+        expr
+    } else {
+        upwards.push(Stmt::Expr(expr));
+        Box::new(lambda::ExprKind::Block(upwards))
+    }
+}
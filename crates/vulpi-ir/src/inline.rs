@@ -95,6 +95,21 @@ impl Transform for lambda::ExprKind {
                     action.transform(ctx);
                 }
             }
+            lambda::ExprKind::Perform(instance, _, args) => {
+                if let Some(instance) = instance {
+                    instance.transform(ctx);
+                }
+                for arg in args {
+                    arg.transform(ctx);
+                }
+            }
+            lambda::ExprKind::Handle(body, clauses, ret) => {
+                body.transform(ctx);
+                for (_, _, clause) in clauses {
+                    clause.transform(ctx);
+                }
+                ret.transform(ctx);
+            }
         }
     }
 }
@@ -188,7 +203,21 @@ pub fn traverse<F: Fn(&mut lambda::ExprKind) -> ()>(expr: &mut lambda::ExprKind,
                 traverse(action, f.clone());
             }
         }
-    
+        lambda::ExprKind::Perform(instance, _, args) => {
+            if let Some(instance) = instance {
+                traverse(instance, f.clone());
+            }
+            for arg in args {
+                traverse(arg, f.clone());
+            }
+        }
+        lambda::ExprKind::Handle(body, clauses, ret) => {
+            traverse(body, f.clone());
+            for (_, _, clause) in clauses {
+                traverse(clause, f.clone());
+            }
+            traverse(ret, f.clone());
+        }
     }
 }
 
@@ -219,6 +248,9 @@ pub fn is_complex(expr: &lambda::ExprKind) -> bool {
         
         lambda::ExprKind::Block(_) => true,
         lambda::ExprKind::Switch(_, _, _) => false,
+
+        lambda::ExprKind::Perform(_, _, _) => true,
+        lambda::ExprKind::Handle(_, _, _) => true,
     }
 }
 
@@ -312,6 +344,21 @@ pub fn substitute(expr: &mut lambda::ExprKind, mut subs: im_rc::HashMap<Symbol,
                 substitute(action, subs.clone());
             }
         }
+        lambda::ExprKind::Perform(instance, _, args) => {
+            if let Some(instance) = instance {
+                substitute(instance, subs.clone());
+            }
+            for arg in args {
+                substitute(arg, subs.clone());
+            }
+        }
+        lambda::ExprKind::Handle(body, clauses, ret) => {
+            substitute(body, subs.clone());
+            for (_, _, clause) in clauses {
+                substitute(clause, subs.clone());
+            }
+            substitute(ret, subs);
+        }
     }
 }
 
@@ -339,6 +386,9 @@ pub fn should_inline(expr: &lambda::ExprKind) -> bool {
         
         lambda::ExprKind::Block(_) => false,
         lambda::ExprKind::Switch(_, _, _) => false,
+
+        lambda::ExprKind::Perform(_, _, _) => false,
+        lambda::ExprKind::Handle(_, _, _) => false,
     }
 }
 
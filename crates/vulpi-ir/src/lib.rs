@@ -2,6 +2,7 @@
 //! a form that is easier to work with for code generation.
 
 pub mod transform;
+pub mod effects;
 pub mod pattern;
 pub mod inline;
 pub mod dead_code;
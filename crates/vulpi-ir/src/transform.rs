@@ -8,10 +8,10 @@ use vulpi_intern::Symbol;
 use vulpi_syntax::{
     elaborated::*,
     lambda::{self, Case, ConsDef, Stmt, TagType},
-    r#abstract::Qualified,
+    r#abstract::{OperationKind, Qualified},
 };
 
-use crate::pattern;
+use crate::{effects, pattern};
 use vulpi_typer::{real::Real, Type};
 
 #[derive(Clone)]
@@ -22,6 +22,7 @@ pub enum TypeDef {
     Tuple,
     Abstract,
     Record,
+    Effect,
 }
 
 pub enum Flag {
@@ -37,6 +38,7 @@ pub struct Context {
     upwards: Rc<RefCell<Vec<lambda::Stmt>>>,
     scoped: Rc<RefCell<Vec<usize>>>,
     constructors: Rc<RefCell<HashMap<Qualified, (ConsDef, usize)>>>,
+    operations: Rc<RefCell<HashMap<Qualified, (usize, OperationKind)>>>,
     vars: im_rc::HashMap<Symbol, usize>,
     types: im_rc::HashMap<Qualified, TypeDef>,
 }
@@ -106,6 +108,14 @@ impl Context {
         self.constructors.borrow().get(name).cloned().unwrap().0
     }
 
+    pub fn add_operation(&mut self, name: Qualified, arity: usize, kind: OperationKind) {
+        self.operations.borrow_mut().insert(name, (arity, kind));
+    }
+
+    pub fn get_operation(&self, name: &Qualified) -> Option<(usize, OperationKind)> {
+        self.operations.borrow().get(name).cloned()
+    }
+
     pub fn is_newtype(&self, name: &Qualified) -> bool {
         if let Some(cons) = self.constructors.borrow().get(name) {
             matches!(cons, (ConsDef::NewType, 1))
@@ -241,11 +251,24 @@ fn compile_match_with_names(
     scrutinee_names: Vec<Symbol>,
     arms: Vec<Vec<Pattern>>,
     actions: Vec<Expr<Type<Real>>>,
+) -> lambda::Expr {
+    compile_match_with_actions(context, scrutinee_names, arms, |context, i| {
+        actions[i].transform(context)
+    })
+}
+
+/// Compiles a pattern match over variables where the action of each arm is produced by `lower`,
+/// so arms that do not come from the source code can be added to the match.
+pub(crate) fn compile_match_with_actions(
+    context: &mut Context,
+    scrutinee_names: Vec<Symbol>,
+    arms: Vec<Vec<Pattern>>,
+    mut lower: impl FnMut(&mut Context, usize) -> lambda::Expr,
 ) -> lambda::Expr {
     // Vector of all the result actions with let bindings.
     let mut result_actions = vec![];
 
-    for (patterns, action) in arms.iter().zip(actions) {
+    for (i, patterns) in arms.iter().enumerate() {
         let mut statements = vec![];
 
         for (scrutinee, pat) in scrutinee_names.iter().zip(patterns) {
@@ -260,7 +283,7 @@ fn compile_match_with_names(
             }
         }
 
-        let action = action.transform(context);
+        let action = lower(context, i);
 
         statements.extend(context.drain_upwards());
 
@@ -380,7 +403,10 @@ impl Transform for Expr<Type<Real>> {
                 Box::new(lambda::ExprKind::Variable(context.find_var(var.clone())))
             }
             ExprKind::Constructor(_, name) => Box::new(lambda::ExprKind::Constructor(name.clone())),
-            ExprKind::Function(name, _) => Box::new(lambda::ExprKind::Function(name.clone())),
+            ExprKind::Function(name, _) => match context.get_operation(name) {
+                Some((arity, _)) => effects::perform(context, None, name.clone(), arity),
+                None => Box::new(lambda::ExprKind::Function(name.clone())),
+            },
 
             ExprKind::Projection(field) => Box::new(lambda::ExprKind::Projection(
                 field.field.clone(),
@@ -393,6 +419,22 @@ impl Transform for Expr<Type<Real>> {
                 compile_binders(context, scrutinee, arms);
                 let_expr.next.transform(context)
             }
+            ExprKind::LetRec(let_rec) => {
+                // The functions of JavaScript see the names that are declared after them, so the
+                // bindings of the group can just be declared one after the other.
+                let names: Vec<_> = let_rec
+                    .bindings
+                    .iter()
+                    .map(|(name, _)| context.add_var(name.clone()))
+                    .collect();
+
+                for (name, (_, body)) in names.into_iter().zip(&let_rec.bindings) {
+                    let body = body.transform(context);
+                    context.add_upwards(Stmt::Let(name, body));
+                }
+
+                let_rec.next.transform(context)
+            }
             ExprKind::When(when_expr) => {
                 let (actions, patterns): (Vec<_>, Vec<_>) = when_expr
                     .arms
@@ -403,6 +445,14 @@ impl Transform for Expr<Type<Real>> {
 
                 compile_match(context, when_expr.scrutinee.clone(), patterns, actions)
             }
+            ExprKind::Handler(handler) => effects::handler(context, handler),
+            // The Lambda IR has no masks, so the operations go to the closest handler.
+            ExprKind::Mask(mask) => mask.expr.transform(context),
+            ExprKind::Operation(handler, name) => {
+                let instance = lambda::ExprKind::Variable(context.find_var(handler.clone()));
+                let (arity, _) = context.get_operation(name).unwrap();
+                effects::perform(context, Some(Box::new(instance)), name.clone(), arity)
+            }
             ExprKind::Do(sttms) => context.scope(|context| {
                 sttms.into_iter().for_each(|x| x.transform(context));
                 let statements = context.drain_upwards();
@@ -570,6 +620,13 @@ impl Transform for (Qualified, TypeDecl) {
                     TypeDef::Record
                 }
             }
+            TypeDecl::Effect(operations) => {
+                for (name, arity, kind, _) in operations {
+                    context.add_operation(name.clone(), *arity, *kind);
+                }
+
+                TypeDef::Effect
+            }
         };
 
         context.types.insert(self.0.clone(), classification);
@@ -639,6 +696,10 @@ impl Transform for Programs {
                 programs[i].definitions = definitions;
             }
 
+            for (name, (arity, kind)) in contexts[i].operations.borrow().iter() {
+                context.add_operation(name.clone(), *arity, *kind);
+            }
+
             for (name, (def, size)) in programs[i].definitions.clone() {
                 context.add_constructor(name.clone(), def.clone(), size);
 
@@ -10,7 +10,11 @@ use resast::{
 };
 use vulpi_intern::Symbol;
 use vulpi_location::Span;
-use vulpi_syntax::{elaborated::LiteralKind, lambda, r#abstract::Qualified};
+use vulpi_syntax::{
+    elaborated::LiteralKind,
+    lambda,
+    r#abstract::{OperationKind, Qualified},
+};
 
 /// The context used to generate new variable names and other things.
 #[derive(Default, Clone)]
@@ -341,6 +345,46 @@ impl Transform for lambda::ExprKind {
 
                 Expr::Ident(Ident::new(name.get()))
             }
+            lambda::ExprKind::Perform(instance, op, args) => Expr::Call(CallExpr {
+                callee: Box::new(Expr::Ident(Ident::new("perform".to_string()))),
+                arguments: vec![
+                    instance.map_or(Expr::Lit(Lit::Null), |x| *x.transform(ctx)),
+                    Expr::Lit(Lit::String(StringLit::Double(Cow::Owned(op.mangle())))),
+                    Expr::Array(args.transform(ctx).into_iter().map(|x| Some(*x)).collect()),
+                ],
+            }),
+            lambda::ExprKind::Handle(body, clauses, ret) => {
+                let body = *body.transform(ctx);
+
+                // Clauses of `fun` operations are called directly by the runtime, so they are kept
+                // apart from the ones that need the continuation.
+                let mut ctls = vec![];
+                let mut funs = vec![];
+
+                for (op, kind, clause) in clauses {
+                    let prop = ObjProp::Prop(Prop {
+                        key: PropKey::Lit(Lit::String(StringLit::Double(Cow::Owned(
+                            op.mangle(),
+                        )))),
+                        value: PropValue::Expr(*clause.transform(ctx)),
+                        kind: resast::PropKind::Init,
+                        method: false,
+                        computed: false,
+                        short_hand: false,
+                        is_static: false,
+                    });
+
+                    match kind {
+                        OperationKind::Ctl => ctls.push(prop),
+                        OperationKind::Fun => funs.push(prop),
+                    }
+                }
+
+                Expr::Call(CallExpr {
+                    callee: Box::new(Expr::Ident(Ident::new("handle".to_string()))),
+                    arguments: vec![body, Expr::Obj(ctls), Expr::Obj(funs), *ret.transform(ctx)],
+                })
+            }
         }
     }
 }
@@ -6,6 +6,8 @@ use vulpi_report::IntoDiagnostic;
 /// The kind of lexing error.
 pub enum ErrorKind {
     UnfinishedString,
+    InvalidChar,
+    MixedIndentation,
 }
 
 /// A lexing error.
@@ -18,11 +20,28 @@ impl IntoDiagnostic for Error {
     fn message(&self) -> vulpi_report::Text {
         match self.message {
             ErrorKind::UnfinishedString => vulpi_report::Text::from("unfinished string literal"),
+            ErrorKind::InvalidChar => {
+                vulpi_report::Text::from("a char literal must have exactly one character")
+            }
+            ErrorKind::MixedIndentation => vulpi_report::Text::from(
+                "this indentation mixes tabs and spaces, so the layout depends on the tab width",
+            ),
+        }
+    }
+
+    fn code(&self) -> Option<usize> {
+        match self.message {
+            ErrorKind::UnfinishedString => Some(1),
+            ErrorKind::InvalidChar => Some(2),
+            ErrorKind::MixedIndentation => Some(3),
         }
     }
 
     fn severity(&self) -> vulpi_report::Severity {
-        vulpi_report::Severity::Error
+        match self.message {
+            ErrorKind::MixedIndentation => vulpi_report::Severity::Warning,
+            _ => vulpi_report::Severity::Error,
+        }
     }
 
     fn location(&self) -> Span {
@@ -38,9 +38,20 @@
 //!     - Equal: We emit a semicolon
 //!     - Less: We emit a block end
 //!
+//! A tab goes to the next multiple of the tab width, that is [TAB_WIDTH] unless the lexer is
+//! configured with another one, so the layout of a file that mixes tabs and spaces in the
+//! indentation of a line, or of the lines of a block, depends on that width, and the lexer warns
+//! about it. Line breaks can be a line
+//! feed, a carriage return followed by a line feed or a carriage return alone.
+//!
+//! Tools that only need the words of a file can use [lex], that gives the tokens without the
+//! layout ones.
 
 pub mod error;
 mod literals;
+pub mod stream;
+
+pub use stream::{lex, Tokens};
 
 use std::{iter::Peekable, str::Chars};
 
@@ -54,6 +65,9 @@ fn is_identifier_char(char: &char) -> bool {
     char.is_alphanumeric() || matches!(char, |'_'| '!' | '?' | '\'')
 }
 
+/// The default number of columns between two tab stops, as in the layout rule of Haskell.
+pub const TAB_WIDTH: usize = 8;
+
 /// Checks if a char is a whitespace, tab or something like that.
 fn is_whitespace(char: &char) -> bool {
     matches!(char, '\t' | '\x0C' | '\r' | ' ')
@@ -75,6 +89,42 @@ enum LexState {
     Common,
     PushLayout,
 }
+
+/// The kinds of whitespace of the indentation of a line, from the byte where the line starts.
+#[derive(Clone, Copy)]
+struct Indentation {
+    start: usize,
+    tabs: bool,
+    spaces: bool,
+}
+
+impl Indentation {
+    /// The whitespace of the indentation, if the line is indented.
+    fn whitespace(&self) -> Option<Whitespace> {
+        match (self.tabs, self.spaces) {
+            (true, true) => Some(Whitespace::Mixed),
+            (true, false) => Some(Whitespace::Tabs),
+            (false, true) => Some(Whitespace::Spaces),
+            (false, false) => None,
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Whitespace {
+    Tabs,
+    Spaces,
+    Mixed,
+}
+
+/// A layout block, with the column of its lines and the whitespace that indents them. The
+/// whitespace is the one of the first indented line of the block.
+#[derive(Clone, Copy)]
+struct Block {
+    column: usize,
+    whitespace: Option<Whitespace>,
+}
+
 /// A state that can be stored and recovered further in the lexing process.
 #[derive(Clone)]
 pub struct State {
@@ -83,9 +133,13 @@ pub struct State {
     column: usize,
     line: usize,
     file: FileId,
-    layout: Vec<usize>,
+    layout: Vec<Block>,
     lex_state: LexState,
     reporter: Report,
+    tab_width: usize,
+
+    /// The indentation of the current line while no token of it was lexed.
+    indentation: Option<Indentation>,
 }
 
 /// The lexer struct that contains the input and the current state. This struct is the entry point
@@ -111,6 +165,12 @@ impl<'a> Lexer<'a> {
                 layout: vec![],
                 lex_state: LexState::Common,
                 reporter,
+                tab_width: TAB_WIDTH,
+                indentation: Some(Indentation {
+                    start: 0,
+                    tabs: false,
+                    spaces: false,
+                }),
             },
         }
     }
@@ -123,15 +183,53 @@ impl<'a> Lexer<'a> {
         }
     }
 
+    /// Sets the number of columns between two tab stops.
+    pub fn with_tab_width(mut self, width: usize) -> Self {
+        self.state.tab_width = width.max(1);
+        self
+    }
+
+    /// The file of the spans of the tokens.
+    pub fn file(&self) -> FileId {
+        self.state.file
+    }
+
     fn advance(&mut self) -> Option<char> {
         let char = self.peekable.next()?;
         self.state.index += char.len_utf8();
 
-        self.state.column += 1;
+        match char {
+            // The carriage return of a CRLF is whitespace at the end of the line.
+            '\r' if self.peekable.peek() == Some(&'\n') => self.state.column += 1,
+            '\n' | '\r' => {
+                self.state.column = 0;
+                self.state.line += 1;
+                self.state.indentation = Some(Indentation {
+                    start: self.state.index,
+                    tabs: false,
+                    spaces: false,
+                });
+            }
+            '\t' => {
+                let width = self.state.tab_width;
+                self.state.column += width - self.state.column % width;
+
+                if let Some(indentation) = &mut self.state.indentation {
+                    indentation.tabs = true;
+                }
+            }
+            ' ' => {
+                self.state.column += 1;
 
-        if char == '\n' {
-            self.state.column = 0;
-            self.state.line += 1;
+                if let Some(indentation) = &mut self.state.indentation {
+                    indentation.spaces = true;
+                }
+            }
+            '\x0C' => self.state.column += 1,
+            _ => {
+                self.state.column += 1;
+                self.state.indentation = None;
+            }
         }
 
         Some(char)
@@ -156,6 +254,38 @@ impl<'a> Lexer<'a> {
         }));
     }
 
+    /// Warns when the indentation of the line of the next token, that is compared with the layout,
+    /// has both tabs and spaces, or when it doesn't use the whitespace of the block that the line
+    /// continues. It warns only once for each line.
+    fn check_indentation(&mut self) {
+        let Some(indentation) = self.state.indentation else {
+            return;
+        };
+
+        let column = self.state.column;
+        let block = self.state.layout.iter_mut().rev().find(|x| x.column <= column);
+
+        let mixed = match (indentation.whitespace(), block) {
+            (Some(Whitespace::Mixed), _) => true,
+            (Some(whitespace), Some(block)) => {
+                *block.whitespace.get_or_insert(whitespace) != whitespace
+            }
+            _ => false,
+        };
+
+        if mixed {
+            self.state.indentation = None;
+            self.state.reporter.report(Diagnostic::new(error::Error {
+                location: Span {
+                    file: self.state.file,
+                    start: Byte(indentation.start),
+                    end: Byte(self.state.index),
+                },
+                message: error::ErrorKind::MixedIndentation,
+            }));
+        }
+    }
+
     fn spanned<T>(&self, token: T) -> Spanned<T> {
         Spanned::new(token, self.span())
     }
@@ -190,7 +320,7 @@ impl<'a> Lexer<'a> {
         cloned.next();
 
         if let Some(('-', '-')) = self.peekable.peek().zip(cloned.peek()) {
-            self.accumulate(|char| *char != '\n');
+            self.accumulate(|char| !matches!(char, '\n' | '\r'));
             let symbol = Symbol::intern(&self.input[self.state.start..self.state.index]);
             let comment = self.spanned(symbol);
 
@@ -235,6 +365,10 @@ impl<'a> Lexer<'a> {
             }
             "effect" => TokenData::Effect,
             "handle" => TokenData::Handle,
+            "perform" => TokenData::Perform,
+            "finally" => TokenData::Finally,
+            "return" => TokenData::Return,
+            "mask" => TokenData::Mask,
             "mod" => TokenData::Mod,
             "let" => TokenData::Let,
             "when" => TokenData::When,
@@ -245,6 +379,7 @@ impl<'a> Lexer<'a> {
             "use" => TokenData::Use,
             "as" => TokenData::As,
             "type" => TokenData::Type,
+            "newtype" => TokenData::Newtype,
             "pub" => TokenData::Pub,
             "in" => TokenData::In,
             "forall" => TokenData::Forall,
@@ -252,6 +387,13 @@ impl<'a> Lexer<'a> {
             "external" => TokenData::External,
             "trait" => TokenData::Trait,
             "impl" => TokenData::Impl,
+            "ctl" => TokenData::Ctl,
+            "fun" => TokenData::Fun,
+            "test" => TokenData::Test,
+            "expect" => TokenData::Expect,
+            "while" => TokenData::While,
+            "for" => TokenData::For,
+            "once" => TokenData::Once,
             _ => TokenData::LowerIdent,
         }
     }
@@ -259,15 +401,19 @@ impl<'a> Lexer<'a> {
     fn classify_token(&mut self, line: usize) -> (TokenData, Symbol) {
         let last_layout = self.state.layout.last();
 
-        let cond = last_layout.is_some() && self.state.column < *last_layout.unwrap();
+        let cond = last_layout.is_some_and(|last| self.state.column < last.column);
         if line != self.state.line || cond {
+            if last_layout.is_some() {
+                self.check_indentation();
+            }
+
             let column = self.state.column;
-            let last = self.state.layout.last();
+            let last = self.state.layout.last().map(|x| x.column);
 
             match last {
                 None => (),
-                Some(last_column) if column > *last_column => (),
-                Some(last_column) if column < *last_column => {
+                Some(last_column) if column > last_column => (),
+                Some(last_column) if column < last_column => {
                     self.state.layout.pop();
                     return (TokenData::End, Symbol::intern("end"));
                 }
@@ -277,9 +423,14 @@ impl<'a> Lexer<'a> {
 
         let result = if let Some(char) = self.advance() {
             match char {
+                '#' if self.peekable.peek() == Some(&'[') => {
+                    self.advance();
+                    TokenData::HashBracket
+                }
                 '#' => {
-                    self.bump();
-                    self.accumulate(|char| char.is_ascii_digit());
+                    // The symbol of a command is its name, without the hash.
+                    self.save();
+                    self.accumulate(is_identifier_char);
                     TokenData::Command
                 }
                 '{' => TokenData::LBrace,
@@ -355,6 +506,7 @@ impl<'a> Lexer<'a> {
                     }
                 }
                 '~' => TokenData::Tilde,
+                '@' => TokenData::At,
                 '!' => {
                     if let Some('=') = self.peekable.peek() {
                         self.advance();
@@ -377,10 +529,20 @@ impl<'a> Lexer<'a> {
                 ':' => TokenData::Colon,
                 ';' => TokenData::Semicolon,
                 ',' => TokenData::Comma,
-                '.' => TokenData::Dot,
+                '.' => {
+                    if let Some('.') = self.peekable.peek() {
+                        self.advance();
+                        TokenData::DotDot
+                    } else {
+                        TokenData::Dot
+                    }
+                }
                 '0'..='9' => {
                     self.accumulate(|char| char.is_ascii_digit());
-                    if let Some('.') = self.peekable.peek() {
+                    let mut after = self.peekable.clone();
+                    after.next();
+                    // The dots of a range like `1..10` don't start the fraction.
+                    if self.peekable.peek() == Some(&'.') && after.peek() != Some(&'.') {
                         self.advance();
                         self.accumulate(|char| char.is_ascii_digit());
                         TokenData::Float
@@ -389,6 +551,7 @@ impl<'a> Lexer<'a> {
                     }
                 }
                 '"' => return self.string(),
+                '\'' => return self.char_literal(),
                 'A'..='Z' => {
                     self.accumulate(is_identifier_char);
                     TokenData::UpperIdent
@@ -427,12 +590,21 @@ impl<'a> Lexer<'a> {
             LexState::PushLayout => {
                 self.state.lex_state = LexState::Common;
 
-                let last = self.state.layout.last().copied().unwrap_or_default();
+                let last = self.state.layout.last().map_or(0, |x| x.column);
+
+                if line != self.state.line {
+                    self.check_indentation();
+                }
 
                 if self.state.column <= last {
                     self.classify_token(line)
                 } else {
-                    self.state.layout.push(self.state.column);
+                    let whitespace = self.state.indentation.and_then(|x| x.whitespace());
+
+                    self.state.layout.push(Block {
+                        column: self.state.column,
+                        whitespace: whitespace.filter(|x| *x != Whitespace::Mixed),
+                    });
                     (TokenData::Begin, Symbol::intern("begin"))
                 }
             }
@@ -461,7 +633,7 @@ impl<'a> Iterator for Lexer<'a> {
 
 #[cfg(test)]
 mod tests {
-    use vulpi_report::hash::HashReporter;
+    use vulpi_report::{hash::HashReporter, hash_reporter};
 
     use super::*;
 
@@ -483,4 +655,77 @@ mod tests {
             assert!(token.kind != TokenData::Error);
         }
     }
+
+    fn kinds(lexer: &mut Lexer) -> Vec<TokenData> {
+        let mut kinds = vec![];
+
+        loop {
+            let token = lexer.bump();
+            kinds.push(token.kind);
+
+            if token.kind == TokenData::Eof {
+                return kinds;
+            }
+        }
+    }
+
+    #[test]
+    fn test_line_breaks() {
+        let source = "let x = do -- a\n  a\n  b\nlet y = 1\n";
+        let kinds_of = |source: &str| kinds(&mut Lexer::new(source, FileId(0), hash_reporter()));
+
+        let expected = kinds_of(source);
+        assert!(expected.contains(&TokenData::Sep));
+        assert_eq!(kinds_of(&source.replace('\n', "\r\n")), expected);
+        assert_eq!(kinds_of(&source.replace('\n', "\r")), expected);
+    }
+
+    #[test]
+    fn test_tabs() {
+        // With a width of 2 the tab is at the column of the two spaces, so they're in the block.
+        // The tab indents a line of a block that is indented with spaces, so both widths warn.
+        let source = "let x = do\n  a\n\tb\n";
+        let width = |width| {
+            let reporter = hash_reporter();
+            let lexer = Lexer::new(source, FileId(0), reporter.clone());
+            let kinds = kinds(&mut lexer.with_tab_width(width));
+
+            let diagnostics = reporter.all_diagnostics();
+            assert_eq!(diagnostics.len(), 1);
+            assert_eq!(diagnostics[0].location().start, Byte(15));
+            kinds
+        };
+
+        assert!(width(2).contains(&TokenData::Sep));
+        assert!(!width(8).contains(&TokenData::Sep));
+
+        let reporter = hash_reporter();
+        let source = "let x = do\n    a\n    b\n  \tc\n\td\n";
+        kinds(&mut Lexer::new(source, FileId(0), reporter.clone()));
+
+        let diagnostics = reporter.all_diagnostics();
+        assert_eq!(diagnostics.len(), 2);
+        assert_eq!(diagnostics[0].location().start, Byte(23));
+        assert_eq!(diagnostics[1].location().start, Byte(28));
+    }
+
+    #[test]
+    fn test_stream() {
+        let source = "let x = do\n    a -- end\n    b\n";
+        let mut tokens = lex(source);
+
+        assert_eq!(tokens.peek_nth(3).map(|x| x.kind), Some(TokenData::Do));
+        assert_eq!(tokens.next_if(TokenData::Let).map(|x| x.data()), Some("let".into()));
+        assert!(tokens.next_if(TokenData::Let).is_none());
+
+        let rest: Vec<_> = tokens.collect();
+        let kinds: Vec<_> = rest.iter().map(|x| x.kind).collect();
+
+        use TokenData::*;
+        assert_eq!(kinds, [LowerIdent, Equal, Do, LowerIdent, LowerIdent, Eof]);
+
+        let b = &rest[4];
+        assert_eq!((b.value.span.start.0, b.value.span.end.0), (28, 29));
+        assert_eq!(b.comments[0].comment.data.get(), "-- end");
+    }
 }
@@ -35,6 +35,28 @@ impl<'a> Lexer<'a> {
         Some(result)
     }
 
+    /// Parses a char literal after its first quote. Names can't start with a quote, so it's
+    /// never the start of one.
+    pub(crate) fn char_literal(&mut self) -> (TokenData, Symbol) {
+        let char = self.char();
+
+        match char {
+            Some(char) if self.peekable.peek() == Some(&'\'') => {
+                self.advance();
+                (TokenData::Char, Symbol::intern(&char.to_string()))
+            }
+            _ => {
+                self.accumulate(|x| *x != '\'' && *x != '\n');
+                if self.peekable.peek() == Some(&'\'') {
+                    self.advance();
+                }
+
+                self.report(ErrorKind::InvalidChar);
+                (TokenData::Error, Symbol::intern(""))
+            }
+        }
+    }
+
     pub(crate) fn string(&mut self) -> (TokenData, Symbol) {
         let mut string = String::new();
 
@@ -50,7 +72,7 @@ impl<'a> Lexer<'a> {
                 }
                 '"' => break,
                 _ => {
-                    string.push(self.advance().unwrap());
+                    string.extend(self.advance());
                 }
             }
         }
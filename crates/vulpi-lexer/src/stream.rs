@@ -0,0 +1,102 @@
+//! A stream of the tokens of a source code for the tools that only read the words of a file, like
+//! syntax highlighters and code mods. It has no layout: the blocks that the lexer opens and closes
+//! with the indentation are left out, so the tokens are the ones that are written in the source.
+//! The positions of the tokens are the byte offsets in their spans, and their comments and
+//! whitespace come attached to them like in the parser.
+
+use std::collections::VecDeque;
+
+use vulpi_location::FileId;
+use vulpi_syntax::tokens::{Token, TokenData};
+
+use crate::Lexer;
+
+/// Lexes a source code without layout. The errors of the lexer are not reported: the characters
+/// that are not part of a token become [TokenData::Error] tokens.
+pub fn lex(source: &str) -> Tokens<'_> {
+    Tokens {
+        lexer: Lexer::new(source, FileId(0), vulpi_report::hash_reporter()),
+        buffer: VecDeque::new(),
+        finished: false,
+    }
+}
+
+/// The tokens of a source code, up to the end of file, with as much lookahead as needed. The last
+/// token is the [TokenData::Eof] one, that has the comments at the end of the file.
+#[derive(Clone)]
+pub struct Tokens<'a> {
+    lexer: Lexer<'a>,
+    buffer: VecDeque<Token>,
+    finished: bool,
+}
+
+impl<'a> Tokens<'a> {
+    fn lex_token(&mut self) -> Option<Token> {
+        if self.finished {
+            return None;
+        }
+
+        // The comments and whitespace before a layout token are the ones before the token that
+        // comes after it, because the layout tokens are made at the position of that token.
+        let mut skipped: Option<Token> = None;
+
+        loop {
+            let mut token = self.lexer.bump();
+
+            match token.kind {
+                TokenData::Begin | TokenData::End | TokenData::Sep => {
+                    match &mut skipped {
+                        Some(skipped) => skipped.comments.extend(token.comments),
+                        None => skipped = Some(token),
+                    }
+                    continue;
+                }
+                TokenData::Eof => self.finished = true,
+                _ => (),
+            }
+
+            if let Some(mut skipped) = skipped {
+                skipped.comments.append(&mut token.comments);
+                token.comments = skipped.comments;
+
+                if token.whitespace.span.start == token.whitespace.span.end {
+                    token.whitespace = skipped.whitespace;
+                }
+            }
+
+            return Some(token);
+        }
+    }
+
+    /// The token after the next `n` ones, without consuming them.
+    pub fn peek_nth(&mut self, n: usize) -> Option<&Token> {
+        while self.buffer.len() <= n {
+            let token = self.lex_token()?;
+            self.buffer.push_back(token);
+        }
+
+        self.buffer.get(n)
+    }
+
+    /// The next token, without consuming it.
+    pub fn peek(&mut self) -> Option<&Token> {
+        self.peek_nth(0)
+    }
+
+    /// Consumes the next token if it's of a kind.
+    pub fn next_if(&mut self, kind: TokenData) -> Option<Token> {
+        if self.peek()?.kind == kind {
+            self.next()
+        } else {
+            None
+        }
+    }
+}
+
+impl<'a> Iterator for Tokens<'a> {
+    type Item = Token;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.buffer.pop_front().or_else(|| self.lex_token())
+    }
+}
@@ -0,0 +1,181 @@
+//! Conversion of the bytes of a source to lines and columns. Diagnostics show columns as
+//! characters, and the language server protocol counts them in UTF-16 code units, so the index
+//! keeps the characters of each line that are wider than a byte.
+
+use crate::{Byte, Span};
+
+/// The unit that columns are counted in.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Encoding {
+    /// Bytes of UTF-8.
+    Utf8,
+
+    /// Code units of UTF-16, as the language server protocol counts them by default.
+    Utf16,
+
+    /// Characters.
+    Utf32,
+}
+
+/// A line and a column, both starting at zero.
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Debug, Default)]
+pub struct LineCol {
+    pub line: usize,
+    pub column: usize,
+}
+
+/// A character that takes more than one byte, with its offset from the start of its line.
+#[derive(Clone, Copy, Debug)]
+struct Wide {
+    offset: usize,
+    utf8: usize,
+    utf16: usize,
+}
+
+impl Wide {
+    fn len(&self, encoding: Encoding) -> usize {
+        match encoding {
+            Encoding::Utf8 => self.utf8,
+            Encoding::Utf16 => self.utf16,
+            Encoding::Utf32 => 1,
+        }
+    }
+}
+
+/// The starts of the lines of a source. A line ends at its line feed, so the byte of the line feed
+/// is the last column of the line.
+#[derive(Clone, Debug)]
+pub struct LineIndex {
+    starts: Vec<usize>,
+    wide: Vec<Vec<Wide>>,
+    len: usize,
+}
+
+impl LineIndex {
+    pub fn new(source: &str) -> Self {
+        let mut starts = vec![0];
+        let mut wide = vec![Vec::new()];
+
+        for (i, c) in source.char_indices() {
+            if c == '\n' {
+                starts.push(i + 1);
+                wide.push(Vec::new());
+            } else if c.len_utf8() > 1 {
+                wide.last_mut().unwrap().push(Wide {
+                    offset: i - starts.last().unwrap(),
+                    utf8: c.len_utf8(),
+                    utf16: c.len_utf16(),
+                });
+            }
+        }
+
+        Self {
+            starts,
+            wide,
+            len: source.len(),
+        }
+    }
+
+    /// The number of lines. A source that ends with a line feed has an empty line after it.
+    pub fn lines(&self) -> usize {
+        self.starts.len()
+    }
+
+    /// The bytes of a line, without its line feed.
+    pub fn line(&self, line: usize) -> Option<(Byte, Byte)> {
+        let start = *self.starts.get(line)?;
+        let end = self.starts.get(line + 1).map_or(self.len, |x| x - 1);
+        Some((Byte(start), Byte(end)))
+    }
+
+    /// The line and the column of a byte. Bytes inside of a character are at its column.
+    pub fn line_col(&self, byte: &Byte, encoding: Encoding) -> Option<LineCol> {
+        if byte.0 > self.len {
+            return None;
+        }
+
+        let line = self.starts.partition_point(|x| *x <= byte.0) - 1;
+        let offset = byte.0 - self.starts[line];
+
+        let mut column = offset;
+
+        if encoding != Encoding::Utf8 {
+            for wide in self.wide[line].iter().take_while(|x| x.offset < offset) {
+                if offset < wide.offset + wide.utf8 {
+                    column -= offset - wide.offset;
+                } else {
+                    column = column - wide.utf8 + wide.len(encoding);
+                }
+            }
+        }
+
+        Some(LineCol { line, column })
+    }
+
+    /// The byte at a line and a column. Columns inside of a character are at its start and columns
+    /// after the end of the line are at its end.
+    pub fn byte(&self, position: LineCol, encoding: Encoding) -> Option<Byte> {
+        let (start, end) = self.line(position.line)?;
+
+        let mut column = 0;
+        let mut offset = 0;
+
+        if encoding != Encoding::Utf8 {
+            for wide in &self.wide[position.line] {
+                let narrow = wide.offset - offset;
+
+                if column + narrow >= position.column {
+                    break;
+                }
+
+                column += narrow + wide.len(encoding);
+                offset = wide.offset + wide.utf8;
+
+                if column > position.column {
+                    return Some(Byte(start.0 + wide.offset));
+                }
+            }
+        }
+
+        offset += position.column - column;
+
+        Some(Byte((start.0 + offset).min(end.0)))
+    }
+
+    /// The positions of the start and of the end of a span.
+    pub fn span(&self, span: &Span, encoding: Encoding) -> Option<(LineCol, LineCol)> {
+        let start = self.line_col(&span.start, encoding)?;
+        let end = self.line_col(&span.end, encoding)?;
+        Some((start, end))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn columns() {
+        let index = LineIndex::new("let a = 1\nlet é = \"𝔸\" ++ b\n");
+
+        let position = |line, column| LineCol { line, column };
+
+        // The `b` of the second line is after a character of two bytes and one of four bytes.
+        let b = Byte(10 + 19);
+
+        assert_eq!(index.line_col(&b, Encoding::Utf8), Some(position(1, 19)));
+        assert_eq!(index.line_col(&b, Encoding::Utf16), Some(position(1, 16)));
+        assert_eq!(index.line_col(&b, Encoding::Utf32), Some(position(1, 15)));
+
+        for encoding in [Encoding::Utf8, Encoding::Utf16, Encoding::Utf32] {
+            let position = index.line_col(&b, encoding).unwrap();
+            assert_eq!(index.byte(position, encoding), Some(b.clone()));
+        }
+
+        assert_eq!(
+            index.line_col(&Byte(9), Encoding::Utf8),
+            Some(position(0, 9))
+        );
+        assert_eq!(index.lines(), 3);
+    }
+}
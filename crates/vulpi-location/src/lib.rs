@@ -1,16 +1,27 @@
 //! This module exposes a lot of structures that locate things inside a source code. It's really
 //! useful to generate error messages.
 
-use std::fmt::Debug;
+use std::{
+    cell::{Cell, RefCell},
+    fmt::Debug,
+};
 
+use serde::{Deserialize, Deserializer, Serialize};
 use vulpi_show::{Show, TreeDisplay};
 
+pub mod index;
+pub mod sources;
+
+pub use index::{Encoding, LineCol, LineIndex};
+pub use sources::Sources;
+
 /// A new-type for a usize. It's used to locate a byte inside a source code.
-#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Default)]
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Default, Serialize, Deserialize)]
 pub struct Byte(pub usize);
 
-/// A span that locates a piece of data inside a source code.
-#[derive(Clone, Default)]
+/// A span that locates a piece of data inside a source code. Spans are ordered by their file, then
+/// by their start and then by their end.
+#[derive(Clone, Default, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize)]
 pub struct Span {
     pub file: FileId,
     pub start: Byte,
@@ -63,10 +74,42 @@ impl Span {
             end: std::cmp::max(self.end, other.end),
         }
     }
+
+    /// The smallest span that contains both spans. A span of another file is ignored.
+    pub fn merge(&self, other: &Span) -> Span {
+        if self.file != other.file {
+            return self.clone();
+        }
+
+        self.clone().mix(other.clone())
+    }
+
+    /// Checks if the other span is inside of this one.
+    pub fn contains(&self, other: &Span) -> bool {
+        self.file == other.file && self.start <= other.start && other.end <= self.end
+    }
+
+    /// Checks if the byte is inside of the span. The end of the span is not inside of it.
+    pub fn contains_byte(&self, byte: &Byte) -> bool {
+        self.start <= *byte && *byte < self.end
+    }
+
+    /// Checks if the spans share any byte.
+    pub fn overlaps(&self, other: &Span) -> bool {
+        self.file == other.file && self.start < other.end && other.start < self.end
+    }
+
+    pub fn len(&self) -> usize {
+        self.end.0.saturating_sub(self.start.0)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
 }
 
 /// A span that locates a piece of data inside a source code.
-#[derive(Clone)]
+#[derive(Clone, Serialize, Deserialize)]
 pub struct Spanned<T> {
     pub data: T,
     pub span: Span,
@@ -112,5 +155,96 @@ impl<T> Spanned<T> {
 }
 
 /// The identifier of a file.
-#[derive(Clone, Default, Copy, Hash, PartialEq, Eq, PartialOrd, Ord, Debug)]
+#[derive(
+    Clone, Default, Copy, Hash, PartialEq, Eq, PartialOrd, Ord, Debug, Serialize, Deserialize,
+)]
 pub struct FileId(pub usize);
+
+thread_local! {
+    static FILE: Cell<Option<FileId>> = const { Cell::new(None) };
+    static SPAN: RefCell<Option<Span>> = const { RefCell::new(None) };
+}
+
+/// Runs a function when it's dropped, so the thread locals are restored even if the function that
+/// runs with their new values panics.
+struct Restore<F: FnOnce()>(Option<F>);
+
+impl<F: FnOnce()> Drop for Restore<F> {
+    fn drop(&mut self) {
+        if let Some(restore) = self.0.take() {
+            restore()
+        }
+    }
+}
+
+/// Deserializes the spans of a single file with the id that the file has now. Files are numbered
+/// in the order that they're loaded, so the ids stored by other compilations are not the same.
+pub fn with_file<T>(file: FileId, f: impl FnOnce() -> T) -> T {
+    let old = FILE.with(|x| x.replace(Some(file)));
+    let _restore = Restore(Some(move || FILE.with(|x| x.set(old))));
+    f()
+}
+
+/// Deserializes all the spans as the same one. It's used for things whose sources are not
+/// available, so they're located where they're used instead.
+pub fn with_span<T>(span: Span, f: impl FnOnce() -> T) -> T {
+    let old = SPAN.with(|x| x.replace(Some(span)));
+    let _restore = Restore(Some(move || SPAN.with(|x| *x.borrow_mut() = old)));
+    f()
+}
+
+impl<'de> Deserialize<'de> for Span {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        #[derive(Deserialize)]
+        struct Stored {
+            file: FileId,
+            start: Byte,
+            end: Byte,
+        }
+
+        let stored = Stored::deserialize(deserializer)?;
+
+        if let Some(span) = SPAN.with(|x| x.borrow().clone()) {
+            return Ok(span);
+        }
+
+        Ok(Span {
+            file: FILE.with(|x| x.get()).unwrap_or(stored.file),
+            start: stored.start,
+            end: stored.end,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::panic::{catch_unwind, AssertUnwindSafe};
+
+    use super::*;
+
+    #[test]
+    fn overrides_are_restored_after_a_panic() {
+        let span = Span::new(FileId(3), Byte(1), Byte(2));
+
+        let file = catch_unwind(|| with_file(FileId(7), || panic!("failed to decode")));
+        let spanned = catch_unwind(AssertUnwindSafe(|| {
+            with_span(span.clone(), || panic!("failed to decode"))
+        }));
+
+        assert!(file.is_err() && spanned.is_err());
+        assert_eq!(FILE.with(|x| x.get()), None);
+        assert!(SPAN.with(|x| x.borrow().is_none()));
+    }
+
+    #[test]
+    fn overrides_nest() {
+        with_file(FileId(1), || {
+            with_file(FileId(2), || {
+                assert_eq!(FILE.with(|x| x.get()), Some(FileId(2)))
+            });
+            assert_eq!(FILE.with(|x| x.get()), Some(FileId(1)));
+        });
+
+        assert_eq!(FILE.with(|x| x.get()), None);
+    }
+}
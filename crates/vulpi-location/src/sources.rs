@@ -0,0 +1,48 @@
+//! The files that spans point to, with the lines of their sources. Backends describe the code that
+//! they generate by the positions of the sources that it comes from, like source maps and line
+//! tables of debuggers do.
+
+use std::collections::BTreeMap;
+
+use crate::{Encoding, FileId, LineCol, LineIndex, Span};
+
+#[derive(Clone, Debug)]
+pub struct Source {
+    pub path: String,
+    pub content: String,
+    pub index: LineIndex,
+}
+
+/// The sources of the files of a compilation, ordered by their identifiers.
+#[derive(Clone, Debug, Default)]
+pub struct Sources {
+    files: BTreeMap<FileId, Source>,
+}
+
+impl Sources {
+    pub fn insert(&mut self, file: FileId, path: impl Into<String>, content: String) {
+        let source = Source {
+            path: path.into(),
+            index: LineIndex::new(&content),
+            content,
+        };
+
+        self.files.insert(file, source);
+    }
+
+    pub fn get(&self, file: FileId) -> Option<&Source> {
+        self.files.get(&file)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (FileId, &Source)> {
+        self.files.iter().map(|(id, source)| (*id, source))
+    }
+
+    /// The source and the position of the start of a span. Spans of files that are not known have
+    /// no position.
+    pub fn locate(&self, span: &Span, encoding: Encoding) -> Option<(&Source, LineCol)> {
+        let source = self.files.get(&span.file)?;
+        let position = source.index.line_col(&span.start, encoding)?;
+        Some((source, position))
+    }
+}
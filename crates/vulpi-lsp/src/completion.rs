@@ -0,0 +1,894 @@
+//! Completion of the name at the cursor. After a path and a dot the names are the ones of the
+//! module that the path is, inside of a type they're types and type variables, and anywhere else
+//! they're the variables that are bound around the cursor and the functions of the module and of
+//! the modules that it opens. When the type checker knows the type that the cursor has to have, the
+//! names that cannot give a value of it are left out.
+
+use std::{
+    collections::{HashMap, HashSet},
+    path::PathBuf,
+};
+
+use lsp_types::CompletionItemKind;
+use vulpi_build::query::Database;
+use vulpi_intern::Symbol;
+use vulpi_location::Span;
+use vulpi_resolver::{DefinitionKind, Module};
+use vulpi_syntax::{
+    elaborated,
+    r#abstract::{self as abs, ExprKind, LetBinder, PatternKind, Program, Qualified, SttmKind},
+    tokens::{Token, TokenData},
+    visit::{Visit, Visitor},
+};
+use vulpi_typer::{r#virtual::Virtual, real::Real, Env, HoleInner, Type, TypeKind};
+use vulpi_vfs::{path::Path, FileSystem};
+
+use crate::semantic::{is_identifier, Class, Declarations};
+
+/// A name that can be written at the cursor.
+#[derive(Debug, PartialEq, Eq)]
+pub struct Item {
+    pub label: String,
+    pub class: Class,
+
+    /// The type of the name, if the type checker knows it.
+    pub detail: Option<String>,
+}
+
+/// The kind of item of the protocol that editors show next to the names of a class.
+pub fn kind(class: Class) -> CompletionItemKind {
+    match class {
+        Class::Module => CompletionItemKind::MODULE,
+        Class::Type => CompletionItemKind::STRUCT,
+        Class::Effect => CompletionItemKind::EVENT,
+        Class::Trait => CompletionItemKind::INTERFACE,
+        Class::Constructor => CompletionItemKind::ENUM_MEMBER,
+        Class::Function => CompletionItemKind::FUNCTION,
+        Class::Operation => CompletionItemKind::EVENT,
+        Class::TypeVariable => CompletionItemKind::TYPE_PARAMETER,
+        Class::Variable => CompletionItemKind::VARIABLE,
+    }
+}
+
+/// The names that can be written at a byte of a module, sorted by their labels. The variables of
+/// the declaration at the byte are only known if it parses.
+pub fn complete<FS: FileSystem<Path = PathBuf>>(
+    db: &mut Database<FS>,
+    module: &Path,
+    byte: usize,
+) -> Vec<Item> {
+    let file = db.file(module);
+    let tokens = db.tokens(&file);
+    let resolved = db.resolved(module);
+    let typed = db.typed(module);
+
+    let Some((_, program)) = resolved.programs.iter().find(|(path, _)| path == module) else {
+        return Vec::new();
+    };
+
+    let modules = db.modules();
+    let mut available = HashMap::new();
+
+    for path in &modules {
+        db.resolved(path).module.register(&mut available);
+    }
+
+    // The modules without sources are loaded from their interface files.
+    for path in db.libraries() {
+        if let Some(Ok(library)) = db.library(&path) {
+            library.artifact.namespace.register(&mut available);
+        }
+    }
+
+    let mut completer = Completer {
+        db,
+        modules,
+        available,
+        declarations: Declarations::new(&resolved.programs),
+        candidates: Vec::new(),
+    };
+
+    let namespace = &resolved.module;
+    let decl = enclosing(program, byte);
+    let elaborated = decl.and_then(|x| elaborated_let(&typed, &x.signature.name));
+
+    let position = position(&tokens, program, byte);
+
+    match &position {
+        Position::Member(path) => completer.members(namespace, path),
+        Position::Type => completer.types(namespace, program, byte),
+        Position::Value => {
+            if let Some(decl) = decl {
+                completer.locals(decl, elaborated, byte);
+            }
+
+            completer.values(namespace);
+        }
+    }
+
+    let expected = match position {
+        Position::Type => None,
+        _ => elaborated.and_then(|x| expected(x, byte)),
+    };
+
+    completer.finish(expected)
+}
+
+/// What the name at the cursor can be, from the tokens before it.
+enum Position {
+    /// A name inside of the module or the namespace of a path.
+    Member(Path),
+    Type,
+    Value,
+}
+
+fn position(tokens: &[Token], program: &Program, byte: usize) -> Position {
+    let layout = [
+        TokenData::Begin,
+        TokenData::End,
+        TokenData::Sep,
+        TokenData::Eof,
+    ];
+
+    let mut before = tokens
+        .iter()
+        .rev()
+        .filter(|x| x.value.span.end.0 <= byte && !layout.iter().any(|kind| x.is(*kind)))
+        .peekable();
+
+    // The identifier that is being written.
+    before.next_if(|x| is_identifier(x) && x.value.span.end.0 == byte);
+
+    match before.next() {
+        Some(dot) if dot.is(TokenData::Dot) => {
+            let mut segments = Vec::new();
+
+            while let Some(segment) = before.next_if(|x| x.is(TokenData::UpperIdent)) {
+                segments.push(segment.symbol());
+
+                if before.next_if(|x| x.is(TokenData::Dot)).is_none() {
+                    break;
+                }
+            }
+
+            segments.reverse();
+            Position::Member(Path { segments })
+        }
+        Some(token) if token.is(TokenData::Colon) || token.is(TokenData::RightArrow) => {
+            Position::Type
+        }
+        _ => {
+            let mut inside = InType { byte, found: false };
+            program.walk(&mut inside);
+
+            if inside.found {
+                Position::Type
+            } else {
+                Position::Value
+            }
+        }
+    }
+}
+
+struct Completer<'a, FS: FileSystem<Path = PathBuf>> {
+    db: &'a mut Database<FS>,
+    modules: Vec<Path>,
+
+    /// The namespaces of the modules of the crate and of the declarations inside of them, by their
+    /// paths, like the ones that the resolver searches the paths in.
+    available: HashMap<Path, Module>,
+
+    declarations: Declarations,
+    candidates: Vec<(String, Class, Option<Type<Real>>)>,
+}
+
+impl<'a, FS: FileSystem<Path = PathBuf>> Completer<'a, FS> {
+    fn members(&mut self, namespace: &Module, path: &Path) {
+        if path.is_empty() {
+            return;
+        }
+
+        let Some(found) = self.namespace(namespace, path.clone(), &mut HashSet::new()) else {
+            return;
+        };
+
+        // The private names of a module can be used in the modules inside of it.
+        let current = namespace.name().clone();
+        let private = found.name().segments.starts_with(&current.segments);
+
+        self.declared(&found, DefinitionKind::Type, private);
+        self.declared(&found, DefinitionKind::Trait, private);
+        self.declared(&found, DefinitionKind::Value, private);
+        self.submodules(&found);
+    }
+
+    fn types(&mut self, namespace: &Module, program: &Program, byte: usize) {
+        for variable in type_variables(program, namespace, byte) {
+            self.candidates
+                .push((variable.get(), Class::TypeVariable, None));
+        }
+
+        for (module, private) in self.opened(namespace) {
+            self.declared(&module, DefinitionKind::Type, private);
+            self.declared(&module, DefinitionKind::Trait, private);
+        }
+
+        self.namespaces(namespace);
+    }
+
+    fn values(&mut self, namespace: &Module) {
+        for (module, private) in self.opened(namespace) {
+            self.declared(&module, DefinitionKind::Value, private);
+        }
+
+        self.namespaces(namespace);
+    }
+
+    /// The variables that are bound around the cursor. The types of the parameters of the
+    /// declaration are known, and the variables that are bound later shadow them.
+    fn locals(
+        &mut self,
+        decl: &abs::LetDecl,
+        elaborated: Option<&elaborated::LetDecl<Type<Real>>>,
+        byte: usize,
+    ) {
+        let mut scope = Scope {
+            byte,
+            names: Vec::new(),
+        };
+
+        for arm in &decl.body {
+            scope.arm(arm);
+        }
+
+        for name in scope.names.into_iter().rev() {
+            self.candidates.push((name.get(), Class::Variable, None));
+        }
+
+        let mut parameters = Vec::new();
+
+        for binder in &decl.signature.binders {
+            if let LetBinder::Param(binder) = binder {
+                Bound(&mut parameters).visit_pattern(&binder.pat);
+            }
+        }
+
+        let types: HashMap<_, _> = elaborated
+            .iter()
+            .flat_map(|x| &x.binders)
+            .filter_map(|(pattern, typ)| match &**pattern {
+                elaborated::PatternKind::Variable(name) => Some((name.clone(), typ.clone())),
+                _ => None,
+            })
+            .collect();
+
+        for name in parameters {
+            let typ = types.get(&name).cloned();
+            self.candidates.push((name.get(), Class::Variable, typ));
+        }
+    }
+
+    /// Adds the names of a kind that a module declares, with the private ones if they can be used.
+    fn declared(&mut self, module: &Module, kind: DefinitionKind, private: bool) {
+        let path = module.name().symbol();
+
+        let names: Vec<_> = {
+            let declared = module.declared();
+
+            let map = match kind {
+                DefinitionKind::Type => &declared.types,
+                DefinitionKind::Value => &declared.values,
+                DefinitionKind::Trait => &declared.traits,
+            };
+
+            map.iter()
+                .filter(|(_, x)| private || x.visibility == abs::Visibility::Public)
+                .map(|(name, _)| name.clone())
+                .collect()
+        };
+
+        let class = match kind {
+            DefinitionKind::Type => Class::Type,
+            DefinitionKind::Trait => Class::Trait,
+            DefinitionKind::Value => match self.declarations.namespaces.get(&path) {
+                Some(Class::Type) => Class::Constructor,
+                Some(Class::Effect) => Class::Operation,
+                _ => Class::Function,
+            },
+        };
+
+        for name in names {
+            let qualified = Qualified {
+                path: path.clone(),
+                name: name.clone(),
+            };
+
+            let class = self.declarations.class(&qualified, class);
+
+            let typ = match class {
+                Class::Function => self.signature(&qualified),
+                _ => None,
+            };
+
+            self.candidates.push((name.get(), class, typ));
+        }
+    }
+
+    /// Adds the aliases of the modules and the namespaces that the names of paths start with.
+    fn namespaces(&mut self, namespace: &Module) {
+        let aliases: Vec<_> = namespace.modules().keys().cloned().collect();
+
+        for alias in aliases {
+            self.candidates.push((alias.get(), Class::Module, None));
+        }
+
+        for (module, _) in self.opened(namespace) {
+            self.submodules(&module);
+        }
+    }
+
+    fn submodules(&mut self, module: &Module) {
+        let submodules: Vec<_> = module
+            .submodules()
+            .iter()
+            .map(|(name, x)| (name.clone(), x.name().symbol()))
+            .collect();
+
+        for (name, path) in submodules {
+            let class = self.declarations.namespaces.get(&path).copied();
+            let class = class.unwrap_or(Class::Module);
+            self.candidates.push((name.get(), class, None));
+        }
+    }
+
+    /// The module itself and the ones that it opens, with the ones that those open publicly, and
+    /// if their private names can be used.
+    fn opened(&self, namespace: &Module) -> Vec<(Module, bool)> {
+        let mut opened = vec![(namespace.clone(), true)];
+        let mut visited = HashSet::new();
+        let mut paths: Vec<_> = namespace.opened().keys().cloned().collect();
+
+        while let Some(path) = paths.pop() {
+            if !visited.insert(path.clone()) {
+                continue;
+            }
+
+            if let Some(module) = self.available.get(&path) {
+                let public = module
+                    .opened()
+                    .iter()
+                    .filter(|(_, visibility)| **visibility == abs::Visibility::Public)
+                    .map(|(path, _)| path.clone())
+                    .collect::<Vec<_>>();
+
+                paths.extend(public);
+                opened.push((module.clone(), false));
+            }
+        }
+
+        opened
+    }
+
+    /// The namespace of a path in a module, searched like the resolver does: as an alias, as a
+    /// module of the crate, as a namespace inside of the module and then in the opened modules.
+    fn namespace(
+        &self,
+        module: &Module,
+        path: Path,
+        visited: &mut HashSet<Path>,
+    ) -> Option<Module> {
+        if !visited.insert(module.name().clone()) {
+            return None;
+        }
+
+        let path = match module.modules().get(&path.symbol()) {
+            Some((alias, _)) => alias.clone(),
+            None => path,
+        };
+
+        if let Some(found) = self.available.get(&path) {
+            return Some(found.clone());
+        }
+
+        if let Some(found) = module.submodules().get(&path.symbol()) {
+            return Some(found.clone());
+        }
+
+        let opened: Vec<_> = module.opened().keys().cloned().collect();
+
+        opened
+            .iter()
+            .filter_map(|x| self.available.get(x).cloned())
+            .find_map(|opened| self.namespace(&opened, path.clone(), visited))
+    }
+
+    /// The type of a function of the crate, if it was type checked.
+    fn signature(&mut self, name: &Qualified) -> Option<Type<Real>> {
+        let path = name.path.get();
+
+        let module = self
+            .modules
+            .iter()
+            .filter(|x| {
+                let module = x.symbol().get();
+                path == module || path.starts_with(&format!("{}.", module))
+            })
+            .max_by_key(|x| x.segments.len())?
+            .clone();
+
+        let typed = self.db.typed(&module);
+        signature(&typed, name)
+    }
+
+    /// The candidates that fit the expected type, without the ones that are shadowed by the ones
+    /// with the same label that were added before them.
+    fn finish(self, expected: Option<Type<Real>>) -> Vec<Item> {
+        let mut seen = HashSet::new();
+
+        let mut items: Vec<_> = self
+            .candidates
+            .into_iter()
+            .filter(|(label, _, _)| seen.insert(label.clone()))
+            .filter(|(_, _, typ)| match (&expected, typ) {
+                (Some(expected), Some(typ)) => fits(typ, expected),
+                _ => true,
+            })
+            .map(|(label, class, typ)| Item {
+                label,
+                class,
+                detail: typ.map(|x| x.show(&Env::default()).to_string()),
+            })
+            .collect();
+
+        items.sort_by(|x, y| x.label.cmp(&y.label));
+        items
+    }
+}
+
+fn contains(span: &Span, byte: usize) -> bool {
+    span.start.0 <= byte && byte <= span.end.0
+}
+
+/// The let declaration that a byte is inside of, in the module, in its submodules or in the
+/// implementations of traits.
+fn enclosing(program: &Program, byte: usize) -> Option<&abs::LetDecl> {
+    let impls = program.impls.iter().flat_map(|x| &x.body);
+
+    if let Some(decl) = program
+        .lets
+        .iter()
+        .chain(impls)
+        .find(|x| contains(&x.declaration, byte))
+    {
+        return Some(decl);
+    }
+
+    program
+        .modules
+        .iter()
+        .filter_map(|x| x.decls.as_ref())
+        .find_map(|x| enclosing(x, byte))
+}
+
+fn elaborated_let<'a>(
+    program: &'a elaborated::Program<Type<Real>>,
+    name: &Qualified,
+) -> Option<&'a elaborated::LetDecl<Type<Real>>> {
+    program.lets.get(name).or_else(|| {
+        program
+            .modules
+            .values()
+            .find_map(|x| elaborated_let(x, name))
+    })
+}
+
+/// The type of a let declaration or of an external of a program or of its submodules.
+pub(crate) fn signature(
+    program: &elaborated::Program<Type<Real>>,
+    name: &Qualified,
+) -> Option<Type<Real>> {
+    let external = || program.externals.get(name).map(|x| x.typ.clone());
+    let nested = || program.modules.values().find_map(|x| signature(x, name));
+
+    program
+        .lets
+        .get(name)
+        .map(|x| x.typ.clone())
+        .or_else(external)
+        .or_else(nested)
+}
+
+/// The type variables of the declaration that a byte is in, that is the last one that starts
+/// before it.
+fn type_variables(program: &Program, namespace: &Module, byte: usize) -> Vec<Symbol> {
+    let definitions: HashMap<_, _> = namespace.definitions().into_iter().collect();
+    let mut declarations = Vec::new();
+
+    for decl in &program.lets {
+        let signature = &decl.signature;
+        let types = signature.binders.iter().map(|x| x.typ());
+
+        let variables = types
+            .chain(signature.ret.iter())
+            .flat_map(|x| x.data.free_variables())
+            .collect();
+
+        declarations.push((decl.declaration.start.0, variables));
+    }
+
+    let binders = |binders: &[abs::TypeBinder]| binders.iter().map(|x| x.name().clone()).collect();
+
+    for decl in &program.types {
+        if let Some(span) = definitions.get(&decl.name) {
+            declarations.push((span.start.0, binders(&decl.binders)));
+        }
+    }
+
+    for decl in &program.effects {
+        declarations.push((decl.span.start.0, binders(&decl.binders)));
+    }
+
+    for decl in &program.traits {
+        declarations.push((decl.span.start.0, binders(&decl.binders)));
+    }
+
+    declarations
+        .into_iter()
+        .filter(|(start, _)| *start <= byte)
+        .max_by_key(|(start, _)| *start)
+        .map(|(_, variables)| variables)
+        .unwrap_or_default()
+}
+
+/// Finds if a byte is inside of a type.
+struct InType {
+    byte: usize,
+    found: bool,
+}
+
+impl Visitor for InType {
+    fn visit_type(&mut self, typ: &abs::Type) {
+        self.found |= contains(&typ.span, self.byte);
+    }
+}
+
+/// The variables that a pattern binds.
+struct Bound<'a>(&'a mut Vec<Symbol>);
+
+impl<'a> Visitor for Bound<'a> {
+    fn visit_pattern(&mut self, pattern: &abs::Pattern) {
+        match &pattern.data {
+            PatternKind::Variable(name) => self.0.push(name.clone()),
+            PatternKind::Effect(effect) => self.0.extend(effect.cont.clone()),
+            _ => (),
+        }
+
+        pattern.walk(self)
+    }
+}
+
+/// The variables that are bound around a byte, from the outermost to the innermost.
+struct Scope {
+    byte: usize,
+    names: Vec<Symbol>,
+}
+
+impl Scope {
+    fn bind(&mut self, pattern: &abs::Pattern) {
+        Bound(&mut self.names).visit_pattern(pattern)
+    }
+
+    fn arm(&mut self, arm: &abs::PatternArm) {
+        let mut exprs = arm.guard.iter().chain([&arm.expr]);
+
+        if exprs.any(|x| contains(&x.span, self.byte)) {
+            for pattern in &arm.patterns {
+                self.bind(pattern);
+            }
+
+            arm.guard.iter().for_each(|x| self.visit_expr(x));
+            self.visit_expr(&arm.expr);
+        }
+    }
+}
+
+impl Visitor for Scope {
+    fn visit_expr(&mut self, expr: &abs::Expr) {
+        if !contains(&expr.span, self.byte) {
+            return;
+        }
+
+        match &expr.data {
+            ExprKind::Lambda(lambda) => {
+                self.bind(&lambda.param);
+                self.visit_expr(&lambda.body);
+            }
+            ExprKind::Let(let_expr) => {
+                self.visit_expr(&let_expr.body);
+
+                if contains(&let_expr.value.span, self.byte) {
+                    self.bind(&let_expr.pattern);
+                    self.visit_expr(&let_expr.value);
+                }
+            }
+            ExprKind::LetGroup(group) => {
+                let names = group.bindings.iter().map(|x| x.name.clone());
+                self.names.extend(names);
+
+                group.bindings.iter().for_each(|x| self.visit_expr(&x.body));
+                self.visit_expr(&group.value);
+            }
+            ExprKind::When(when) => {
+                when.scrutinee.iter().for_each(|x| self.visit_expr(x));
+                when.arms.iter().for_each(|x| self.arm(x));
+            }
+            ExprKind::Cases(cases) => cases.arms.iter().for_each(|x| self.arm(x)),
+            ExprKind::Handler(handler) => {
+                if let Some(name) = &handler.name {
+                    if contains(&handler.expr.span, self.byte) {
+                        self.names.push(name.clone());
+                    }
+                }
+
+                self.visit_expr(&handler.expr);
+                self.visit_expr(&handler.handler);
+                handler.finally.iter().for_each(|x| self.visit_expr(x));
+            }
+            ExprKind::Do(block) => {
+                for sttm in &block.sttms {
+                    if sttm.span.start.0 > self.byte {
+                        break;
+                    }
+
+                    match &sttm.data {
+                        SttmKind::Let(sttm) if contains(&sttm.expr.span, self.byte) => {
+                            self.visit_expr(&sttm.expr)
+                        }
+                        SttmKind::Let(sttm) => self.bind(&sttm.pat),
+                        SttmKind::Expr(expr) => self.visit_expr(expr),
+                        SttmKind::Error => (),
+                    }
+                }
+            }
+            _ => expr.walk(self),
+        }
+    }
+}
+
+/// The type that the expression at a byte of a declaration has to have, when the type checker
+/// knows it: the type that the declaration returns, or the one of a parameter of the function that
+/// it's an argument of.
+fn expected(decl: &elaborated::LetDecl<Type<Real>>, byte: usize) -> Option<Type<Real>> {
+    let spine = strip(&decl.typ).arrow_spine();
+
+    let ret = match &spine[decl.binders.len().min(spine.len() - 1)..] {
+        [ret] => Some(ret.clone()),
+        _ => None,
+    };
+
+    decl.body
+        .iter()
+        .find(|arm| contains(&arm.expr.span, byte))
+        .and_then(|arm| expected_in(&arm.expr, ret, byte))
+}
+
+fn expected_in(
+    expr: &elaborated::Expr<Type<Real>>,
+    typ: Option<Type<Real>>,
+    byte: usize,
+) -> Option<Type<Real>> {
+    use elaborated::ExprKind;
+
+    match &*expr.data {
+        ExprKind::Application(_) => {
+            let mut args = Vec::new();
+            let mut head = expr;
+
+            while let ExprKind::Application(app) = &*head.data {
+                args.push(&app.args);
+                head = &app.func;
+            }
+
+            args.reverse();
+
+            let index = args.iter().position(|x| contains(&x.span, byte))?;
+
+            let parameter = match &*head.data {
+                ExprKind::Function(_, typ) => strip(typ).arrow_spine().get(index).cloned(),
+                _ => None,
+            };
+
+            expected_in(args[index], parameter, byte)
+        }
+        ExprKind::Let(let_expr) if contains(&let_expr.next.span, byte) => {
+            expected_in(&let_expr.next, typ, byte)
+        }
+        ExprKind::When(when) => when
+            .arms
+            .iter()
+            .find(|arm| contains(&arm.expr.span, byte))
+            .and_then(|arm| expected_in(&arm.expr, typ, byte)),
+        ExprKind::Do(block) => match block.last() {
+            Some(elaborated::SttmKind::Expr(last)) if contains(&last.span, byte) => {
+                expected_in(last, typ, byte)
+            }
+            _ => None,
+        },
+        ExprKind::Variable(_)
+        | ExprKind::Function(_, _)
+        | ExprKind::Constructor(_, _)
+        | ExprKind::Error => typ,
+        _ => None,
+    }
+}
+
+/// A type without the quantifiers and the constraints before it.
+fn strip(typ: &Type<Real>) -> Type<Real> {
+    match typ.as_ref() {
+        TypeKind::Forall(forall) => strip(&forall.body),
+        TypeKind::Qualified(_, typ) => strip(typ),
+        _ => typ.clone(),
+    }
+}
+
+/// The type that a type gives after it's applied to all of its arguments, as far as the completion
+/// can tell them apart. Variables and holes can be anything.
+#[derive(PartialEq)]
+enum Head {
+    Named(Qualified),
+    Tuple(usize),
+    Unknown,
+}
+
+fn head(typ: &Type<Real>) -> Head {
+    match typ.as_ref() {
+        TypeKind::Forall(forall) => head(&forall.body),
+        TypeKind::Arrow(arrow) => head(&arrow.body),
+        TypeKind::Qualified(_, typ) | TypeKind::Application(typ, _) => head(typ),
+        TypeKind::Variable(name) => Head::Named(name.clone()),
+        TypeKind::Tuple(types) => Head::Tuple(types.len()),
+        TypeKind::Hole(hole) => match &hole.get() {
+            HoleInner::Filled(typ) => virtual_head(typ),
+            HoleInner::Empty(..) => Head::Unknown,
+        },
+        _ => Head::Unknown,
+    }
+}
+
+fn virtual_head(typ: &Type<Virtual>) -> Head {
+    match typ.deref().as_ref() {
+        TypeKind::Arrow(pi) => virtual_head(&pi.body),
+        TypeKind::Qualified(_, typ) | TypeKind::Application(typ, _) => virtual_head(typ),
+        TypeKind::Variable(name) => Head::Named(name.clone()),
+        TypeKind::Tuple(types) => Head::Tuple(types.len()),
+        _ => Head::Unknown,
+    }
+}
+
+/// Finds if something of a type can give a value of the expected one.
+fn fits(typ: &Type<Real>, expected: &Type<Real>) -> bool {
+    match (head(typ), head(expected)) {
+        (Head::Unknown, _) | (_, Head::Unknown) => true,
+        (head, expected) => head == expected,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use vulpi_build::{cfg::Target, memory::MemoryFileSystem, real::RealFileSystem};
+
+    use super::*;
+
+    const MAIN: &str = "use Proj.Log\nuse Proj.Log as L\n\npub type Bool = | True | False\n\nlet yes : Bool = Bool.True\n\nlet not (y : Bool) : Bool = y\n\nlet main (x : L.Int) : () = Log.log (L.id x)\n";
+
+    /// The completions after a number of bytes of the first occurrence of a text in the main module.
+    fn completions(text: &str, offset: usize) -> Vec<Item> {
+        let name = Symbol::intern("Proj");
+
+        let mut fs = MemoryFileSystem::new(name.clone());
+        fs.insert(
+            PathBuf::from("Log.vp"),
+            "pub type Int\n\npub effect Log where\n  pub log : Int -> ()\n\npub let id (x : a) : a = x\n"
+                .to_string(),
+        );
+        fs.insert(PathBuf::from("Main.vp"), MAIN.to_string());
+
+        let mut db = Database::new(fs, name, PathBuf::from("Main.vp"), Target::Js);
+        let main = db.root_module();
+
+        assert!(db.diagnostics().is_empty());
+
+        let byte = MAIN.find(text).unwrap() + offset;
+        complete(&mut db, &main, byte)
+    }
+
+    fn labels(items: &[Item]) -> Vec<(&str, Class)> {
+        items.iter().map(|x| (x.label.as_str(), x.class)).collect()
+    }
+
+    #[test]
+    fn completes_by_position() {
+        let members = completions("L.id x", 2);
+
+        assert_eq!(
+            labels(&members),
+            [
+                ("Int", Class::Type),
+                ("Log", Class::Effect),
+                ("id", Class::Function)
+            ]
+        );
+
+        let types = completions("Bool) : Bool", 0);
+
+        assert_eq!(
+            labels(&types),
+            [
+                ("Bool", Class::Type),
+                ("Int", Class::Type),
+                ("L", Class::Module),
+                ("Log", Class::Effect)
+            ]
+        );
+
+        // The argument of `L.id` is an `Int`, so `main`, `not` and `yes` are left out.
+        let values = completions("x)", 1);
+
+        assert_eq!(
+            labels(&values),
+            [
+                ("Bool", Class::Type),
+                ("Int", Class::Type),
+                ("L", Class::Module),
+                ("Log", Class::Effect),
+                ("id", Class::Function),
+                ("x", Class::Variable)
+            ]
+        );
+
+        assert_eq!(values[5].detail.as_deref(), Some("Int"));
+    }
+
+    #[test]
+    fn completes_the_members_of_interface_files() {
+        let directory = std::env::temp_dir().join(format!("vulpi-lsp-{}", std::process::id()));
+        let library = directory.join("lib");
+        let (app, build) = (directory.join("app"), directory.join("build"));
+
+        let main = "use Shapes\n\npub let main (x: ()) : () = Shapes.name Shapes.Color.Green\n";
+
+        for project in [&library, &app] {
+            std::fs::create_dir_all(project).unwrap();
+            std::fs::write(project.join("Main.vp"), main).unwrap();
+        }
+
+        std::fs::write(
+            library.join("Shapes.vp"),
+            "pub type Color = | Red | Green\n\npub let name (x: Color) : () = ()\n",
+        )
+        .unwrap();
+
+        // The library writes the interface of `Shapes` to the build directory of the app.
+        let name = Symbol::intern("Lib");
+        let fs = RealFileSystem::new(name.clone(), library, build.clone());
+
+        let mut compiler = vulpi_build::ProjectCompiler::new(name.clone(), fs);
+        compiler.interfaces = true;
+
+        assert!(compiler.check(name, PathBuf::from("Main.vp")).is_some());
+
+        let name = Symbol::intern("App");
+        let fs = RealFileSystem::new(name.clone(), app, build);
+        let mut db = Database::new(fs, name, PathBuf::from("Main.vp"), Target::Vm);
+        let main_module = db.root_module();
+
+        let byte = main.find("Shapes.name").unwrap() + 7;
+        let members = complete(&mut db, &main_module, byte);
+
+        std::fs::remove_dir_all(&directory).unwrap();
+
+        assert_eq!(labels(&members), [("Color", Class::Type), ("name", Class::Function)]);
+    }
+}
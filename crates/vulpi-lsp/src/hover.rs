@@ -0,0 +1,92 @@
+//! The information that editors show when the cursor is over a name. It's the type of the let
+//! declaration or of the external that the name declares or refers to, printed as it's written in
+//! the source.
+
+use std::path::{Path, PathBuf};
+
+use vulpi_build::query::Database;
+use vulpi_pretty::{Doc, WIDTH};
+use vulpi_syntax::r#abstract::Qualified;
+use vulpi_typer::{real::Real, Env, Type};
+use vulpi_vfs::FileSystem;
+
+use crate::completion;
+
+/// The signature of the name at a byte of a file, like `add : Int -> Int -> Int`.
+pub fn hover<FS: FileSystem<Path = PathBuf>>(
+    db: &mut Database<FS>,
+    file: &Path,
+    byte: usize,
+) -> Option<String> {
+    let name = db.name_at(file, byte)?;
+    let typ = signature(db, &name)?;
+
+    let doc = Doc::text(format!("{} :", name.name.get()))
+        + (Doc::line() + typ.pretty(&Env::default())).nest(2);
+
+    Some(doc.group().render(WIDTH))
+}
+
+/// The type of a declaration, from the module that declares it or from the interface file that
+/// the module was loaded from.
+fn signature<FS: FileSystem<Path = PathBuf>>(
+    db: &mut Database<FS>,
+    name: &Qualified,
+) -> Option<Type<Real>> {
+    let path = name.path.get();
+    let modules = db.modules();
+
+    let module = modules
+        .iter()
+        .cloned()
+        .chain(db.libraries())
+        .filter(|x| {
+            let module = x.symbol().get();
+            path == module || path.starts_with(&format!("{}.", module))
+        })
+        .max_by_key(|x| x.segments.len())?;
+
+    if modules.contains(&module) {
+        completion::signature(&db.typed(&module), name)
+    } else {
+        let library = db.library(&module)?.ok()?;
+        completion::signature(&library.artifact.program, name)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use vulpi_build::{cfg::Target, memory::MemoryFileSystem};
+    use vulpi_intern::Symbol;
+
+    use super::*;
+
+    const MAIN: &str = "use Proj.Log\n\nlet twice (f : a -> a) (x : a) : a = f (f x)\n\nlet main (x : Int) : Int = twice id x\n";
+
+    fn hover_at(text: &str) -> Option<String> {
+        let name = Symbol::intern("Proj");
+
+        let mut fs = MemoryFileSystem::new(name.clone());
+        fs.insert(
+            PathBuf::from("Log.vp"),
+            "pub type Int\n\npub let id (x : a) : a = x\n".to_string(),
+        );
+        fs.insert(PathBuf::from("Main.vp"), MAIN.to_string());
+
+        let mut db = Database::new(fs, name, PathBuf::from("Main.vp"), Target::Js);
+        assert!(db.diagnostics().is_empty());
+
+        let byte = MAIN.rfind(text).unwrap();
+        hover(&mut db, Path::new("Main.vp"), byte)
+    }
+
+    #[test]
+    fn shows_the_types_of_the_names() {
+        assert_eq!(
+            hover_at("twice id").as_deref(),
+            Some("twice : forall a. (a -> a) -> a -> a")
+        );
+        assert_eq!(hover_at("id x").as_deref(), Some("id : forall a. a -> a"));
+        assert_eq!(hover_at("x\n"), None);
+    }
+}
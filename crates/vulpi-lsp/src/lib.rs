@@ -0,0 +1,342 @@
+//! The language server of Vulpi. It keeps the project in a query [Database], so the sources that
+//! the editor changes only check again the modules that are affected by them, and it talks with
+//! the editor through the standard input and output.
+
+use std::{collections::HashMap, error::Error, fs, path::PathBuf};
+
+use lsp_server::{Connection, ErrorCode, Message, Notification, Request, Response};
+use lsp_types::{
+    notification::{
+        DidChangeTextDocument, DidCloseTextDocument, DidOpenTextDocument,
+        Notification as NotificationTrait,
+    },
+    request::{
+        Completion, GotoDefinition, HoverRequest, References, Rename, Request as RequestTrait,
+        SemanticTokensFullRequest,
+    },
+    CompletionItem, CompletionOptions, CompletionParams, CompletionResponse,
+    DidChangeTextDocumentParams, DidCloseTextDocumentParams, DidOpenTextDocumentParams,
+    GotoDefinitionParams, GotoDefinitionResponse, Hover, HoverContents, HoverParams,
+    HoverProviderCapability, Location, MarkupContent, MarkupKind, OneOf, Position, Range,
+    ReferenceParams, RenameParams, SemanticTokens, SemanticTokensFullOptions,
+    SemanticTokensOptions, SemanticTokensParams, SemanticTokensResult,
+    SemanticTokensServerCapabilities, ServerCapabilities, TextDocumentPositionParams,
+    TextDocumentSyncCapability, TextDocumentSyncKind, TextEdit, Url, WorkspaceEdit,
+};
+use vulpi_build::{query::Database, real::RealFileSystem};
+use vulpi_location::{
+    index::{Encoding, LineCol, LineIndex},
+    Span,
+};
+use vulpi_report::IntoDiagnostic;
+use vulpi_syntax::r#abstract::Qualified;
+use vulpi_vfs::{path::Path, FileSystem};
+
+pub mod completion;
+pub mod hover;
+pub mod semantic;
+
+pub type Result<T> = std::result::Result<T, Box<dyn Error + Send + Sync>>;
+
+struct Server {
+    connection: Connection,
+    db: Database<RealFileSystem>,
+}
+
+/// Answers the requests of an editor until it shuts the server down.
+pub fn serve(db: Database<RealFileSystem>) -> Result<()> {
+    let (connection, threads) = Connection::stdio();
+
+    let capabilities = ServerCapabilities {
+        text_document_sync: Some(TextDocumentSyncCapability::Kind(TextDocumentSyncKind::FULL)),
+        semantic_tokens_provider: Some(SemanticTokensServerCapabilities::SemanticTokensOptions(
+            SemanticTokensOptions {
+                legend: semantic::legend(),
+                full: Some(SemanticTokensFullOptions::Bool(true)),
+                ..Default::default()
+            },
+        )),
+        completion_provider: Some(CompletionOptions {
+            trigger_characters: Some(vec![".".to_string()]),
+            ..Default::default()
+        }),
+        hover_provider: Some(HoverProviderCapability::Simple(true)),
+        definition_provider: Some(OneOf::Left(true)),
+        references_provider: Some(OneOf::Left(true)),
+        rename_provider: Some(OneOf::Left(true)),
+        ..Default::default()
+    };
+
+    connection.initialize(serde_json::to_value(capabilities)?)?;
+
+    let mut server = Server { connection, db };
+    server.run()?;
+
+    drop(server);
+    threads.join()?;
+
+    Ok(())
+}
+
+impl Server {
+    fn run(&mut self) -> Result<()> {
+        while let Ok(message) = self.connection.receiver.recv() {
+            match message {
+                Message::Request(request) => {
+                    if self.connection.handle_shutdown(&request)? {
+                        return Ok(());
+                    }
+
+                    let response = self.request(request);
+                    self.connection.sender.send(Message::Response(response))?;
+                }
+                Message::Notification(notification) => self.notification(notification)?,
+                Message::Response(_) => (),
+            }
+        }
+
+        Ok(())
+    }
+
+    fn request(&mut self, request: Request) -> Response {
+        match request.method.as_str() {
+            SemanticTokensFullRequest::METHOD => {
+                self.respond::<SemanticTokensFullRequest>(request, Self::semantic_tokens)
+            }
+            Completion::METHOD => self.respond::<Completion>(request, Self::completion),
+            HoverRequest::METHOD => self.respond::<HoverRequest>(request, Self::hover),
+            GotoDefinition::METHOD => self.respond::<GotoDefinition>(request, Self::definition),
+            References::METHOD => self.respond::<References>(request, Self::references),
+            Rename::METHOD => self.respond::<Rename>(request, Self::rename),
+            method => Response::new_err(
+                request.id,
+                ErrorCode::MethodNotFound as i32,
+                format!("unknown method '{}'", method),
+            ),
+        }
+    }
+
+    /// Answers a request with a handler that fails with the message of an error.
+    fn respond<R: RequestTrait>(
+        &mut self,
+        request: Request,
+        handler: fn(&mut Self, R::Params) -> std::result::Result<R::Result, String>,
+    ) -> Response {
+        match serde_json::from_value::<R::Params>(request.params) {
+            Ok(params) => match handler(self, params) {
+                Ok(result) => Response::new_ok(request.id, result),
+                Err(message) => {
+                    Response::new_err(request.id, ErrorCode::RequestFailed as i32, message)
+                }
+            },
+            Err(err) => {
+                Response::new_err(request.id, ErrorCode::InvalidParams as i32, err.to_string())
+            }
+        }
+    }
+
+    fn notification(&mut self, notification: Notification) -> Result<()> {
+        match notification.method.as_str() {
+            DidOpenTextDocument::METHOD => {
+                let params: DidOpenTextDocumentParams =
+                    serde_json::from_value(notification.params)?;
+                self.change(&params.text_document.uri, Some(params.text_document.text));
+            }
+            DidChangeTextDocument::METHOD => {
+                let params: DidChangeTextDocumentParams =
+                    serde_json::from_value(notification.params)?;
+                let text = params.content_changes.into_iter().last().map(|x| x.text);
+                self.change(&params.text_document.uri, text);
+            }
+            DidCloseTextDocument::METHOD => {
+                let params: DidCloseTextDocumentParams =
+                    serde_json::from_value(notification.params)?;
+                self.change(&params.text_document.uri, None);
+            }
+            _ => (),
+        }
+
+        Ok(())
+    }
+
+    /// Changes the source of a document to the one in the editor, or to the one in the disk when
+    /// the editor closes it.
+    fn change(&mut self, uri: &Url, text: Option<String>) {
+        let Some((_, file)) = self.module(uri) else {
+            return;
+        };
+
+        match text.or_else(|| fs::read_to_string(&file).ok()) {
+            Some(text) => {
+                let _ = self.db.set_source(file, text);
+            }
+            None => self.db.remove_source(file),
+        }
+    }
+
+    /// The module of a document and its file as the database knows it. The paths are compared
+    /// canonicalized, because editors and the database build them in different ways.
+    fn module(&mut self, uri: &Url) -> Option<(Path, PathBuf)> {
+        let path = uri.to_file_path().ok()?.canonicalize().ok()?;
+
+        self.db.modules().into_iter().find_map(|module| {
+            let file = self.db.file(&module);
+            let same = file.canonicalize().is_ok_and(|x| x == path);
+            same.then_some((module, file))
+        })
+    }
+
+    /// The module and the file of a position in a document, and its byte. The column of the
+    /// position is in UTF-16.
+    fn byte(&mut self, position: &TextDocumentPositionParams) -> Option<(Path, PathBuf, usize)> {
+        let (module, file) = self.module(&position.text_document.uri)?;
+        let (_, source) = self.db.source(&file)?;
+
+        let line_col = LineCol {
+            line: position.position.line as usize,
+            column: position.position.character as usize,
+        };
+
+        let byte = LineIndex::new(&source).byte(line_col, Encoding::Utf16)?;
+        Some((module, file, byte.0))
+    }
+
+    /// The declaration that the name at a position declares or refers to.
+    fn name_at(&mut self, position: &TextDocumentPositionParams) -> Option<Qualified> {
+        let (_, file, byte) = self.byte(position)?;
+        self.db.name_at(&file, byte)
+    }
+
+    /// The location of a span in the protocol, with columns in UTF-16.
+    fn location(&self, span: &Span) -> Option<Location> {
+        let path = self.db.fs.path(span.file).ok()?;
+        let source = self.db.fs.read(span.file).ok()?;
+
+        let (start, end) = LineIndex::new(&source).span(span, Encoding::Utf16)?;
+        let position = |x: LineCol| Position::new(x.line as u32, x.column as u32);
+
+        Some(Location {
+            uri: Url::from_file_path(path).ok()?,
+            range: Range::new(position(start), position(end)),
+        })
+    }
+
+    fn semantic_tokens(
+        &mut self,
+        params: SemanticTokensParams,
+    ) -> std::result::Result<Option<SemanticTokensResult>, String> {
+        let Some((module, file)) = self.module(&params.text_document.uri) else {
+            return Ok(None);
+        };
+
+        let Some((_, source)) = self.db.source(&file) else {
+            return Ok(None);
+        };
+
+        let names = semantic::classify(&mut self.db, &module);
+
+        Ok(Some(SemanticTokensResult::Tokens(SemanticTokens {
+            result_id: None,
+            data: semantic::encode(&source, &names),
+        })))
+    }
+
+    fn completion(
+        &mut self,
+        params: CompletionParams,
+    ) -> std::result::Result<Option<CompletionResponse>, String> {
+        let Some((module, _, byte)) = self.byte(&params.text_document_position) else {
+            return Ok(None);
+        };
+
+        let items = completion::complete(&mut self.db, &module, byte)
+            .into_iter()
+            .map(|item| CompletionItem {
+                label: item.label,
+                kind: Some(completion::kind(item.class)),
+                detail: item.detail,
+                ..Default::default()
+            })
+            .collect();
+
+        Ok(Some(CompletionResponse::Array(items)))
+    }
+
+    /// Shows the type of a name in a block of code.
+    fn hover(&mut self, params: HoverParams) -> std::result::Result<Option<Hover>, String> {
+        let Some((_, file, byte)) = self.byte(&params.text_document_position_params) else {
+            return Ok(None);
+        };
+
+        let signature = hover::hover(&mut self.db, &file, byte);
+
+        Ok(signature.map(|signature| Hover {
+            contents: HoverContents::Markup(MarkupContent {
+                kind: MarkupKind::Markdown,
+                value: format!("```vulpi\n{}\n```", signature),
+            }),
+            range: None,
+        }))
+    }
+
+    /// Goes to the declaration of a name. The declarations of the modules that are loaded from
+    /// interface files are in the sources of the crates that wrote them.
+    fn definition(
+        &mut self,
+        params: GotoDefinitionParams,
+    ) -> std::result::Result<Option<GotoDefinitionResponse>, String> {
+        let Some(name) = self.name_at(&params.text_document_position_params) else {
+            return Ok(None);
+        };
+
+        let location = self.db.declaration(&name).and_then(|x| self.location(&x));
+        Ok(location.map(GotoDefinitionResponse::Scalar))
+    }
+
+    fn references(
+        &mut self,
+        params: ReferenceParams,
+    ) -> std::result::Result<Option<Vec<Location>>, String> {
+        let Some(name) = self.name_at(&params.text_document_position) else {
+            return Ok(None);
+        };
+
+        let mut spans: Vec<_> = self.db.references(&name).into_iter().map(|x| x.1).collect();
+
+        if params.context.include_declaration {
+            spans.extend(self.db.definition(&name).map(|x| x.1));
+        }
+
+        Ok(Some(
+            spans.iter().filter_map(|x| self.location(x)).collect(),
+        ))
+    }
+
+    fn rename(
+        &mut self,
+        params: RenameParams,
+    ) -> std::result::Result<Option<WorkspaceEdit>, String> {
+        let Some(name) = self.name_at(&params.text_document_position) else {
+            return Ok(None);
+        };
+
+        let edits = vulpi_build::rename::rename(&mut self.db, &name, &params.new_name)
+            .map_err(|err| err.message().plain())?;
+
+        let mut changes: HashMap<Url, Vec<TextEdit>> = HashMap::new();
+
+        for suggestion in edits.into_values().flatten() {
+            if let Some(location) = self.location(&suggestion.span) {
+                changes.entry(location.uri).or_default().push(TextEdit {
+                    range: location.range,
+                    new_text: suggestion.replacement,
+                });
+            }
+        }
+
+        Ok(Some(WorkspaceEdit {
+            changes: Some(changes),
+            ..Default::default()
+        }))
+    }
+}
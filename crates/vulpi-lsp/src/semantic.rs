@@ -0,0 +1,513 @@
+//! Semantic tokens, the highlighting that comes from the compiler instead of the grammar of the
+//! editor. The lexer only knows upper and lower case identifiers, so `Foo.bar` can be a function of
+//! a module or an operation of an effect, and `Foo.Bar` can be a constructor of a type or a type of
+//! a module. The names are classified with the resolved programs, that know what each path is.
+
+use std::{
+    collections::{BTreeMap, HashMap},
+    path::PathBuf,
+};
+
+use lsp_types::{SemanticToken, SemanticTokenType, SemanticTokensLegend};
+use vulpi_build::query::Database;
+use vulpi_intern::Symbol;
+use vulpi_location::{
+    index::{Encoding, LineIndex},
+    Span,
+};
+use vulpi_syntax::{
+    concrete::{
+        top_level::{TypeBinder, TypeDef},
+        tree::TopLevel,
+        Path as ConcretePath, Upper,
+    },
+    r#abstract::{self as abs, ExprKind, PatternKind, Program, Qualified, TypeKind},
+    tokens::{Token, TokenData},
+    visit::{Visit, Visitor},
+};
+use vulpi_vfs::{path::Path, FileSystem};
+
+/// What an identifier names.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Class {
+    Module,
+    Type,
+    Effect,
+    Trait,
+    Constructor,
+    Function,
+    Operation,
+    TypeVariable,
+    Variable,
+}
+
+impl Class {
+    /// The classes in the order of the legend, so a class is sent as its position.
+    pub const ALL: [Class; 9] = [
+        Class::Module,
+        Class::Type,
+        Class::Effect,
+        Class::Trait,
+        Class::Constructor,
+        Class::Function,
+        Class::Operation,
+        Class::TypeVariable,
+        Class::Variable,
+    ];
+
+    /// The standard type of the protocol that editors color like the class. Effects and their
+    /// operations are events, the closest thing that the protocol has to them.
+    pub fn token_type(self) -> SemanticTokenType {
+        match self {
+            Class::Module => SemanticTokenType::NAMESPACE,
+            Class::Type => SemanticTokenType::TYPE,
+            Class::Effect => SemanticTokenType::EVENT,
+            Class::Trait => SemanticTokenType::INTERFACE,
+            Class::Constructor => SemanticTokenType::ENUM_MEMBER,
+            Class::Function => SemanticTokenType::FUNCTION,
+            Class::Operation => SemanticTokenType::EVENT,
+            Class::TypeVariable => SemanticTokenType::TYPE_PARAMETER,
+            Class::Variable => SemanticTokenType::VARIABLE,
+        }
+    }
+}
+
+pub fn legend() -> SemanticTokensLegend {
+    SemanticTokensLegend {
+        token_types: Class::ALL.iter().map(|x| x.token_type()).collect(),
+        token_modifiers: Vec::new(),
+    }
+}
+
+/// The classified identifiers of a module, sorted by their positions.
+pub fn classify<FS: FileSystem<Path = PathBuf>>(
+    db: &mut Database<FS>,
+    module: &Path,
+) -> Vec<(Span, Class)> {
+    let file = db.file(module);
+    let tokens = db.tokens(&file);
+    let cst = db.cst(&file);
+    let resolved = db.resolved(module);
+
+    let mut classifier = Classifier {
+        tokens: &tokens,
+        names: BTreeMap::new(),
+        declarations: Declarations::new(&resolved.programs),
+    };
+
+    classifier.top_levels(&cst.top_levels);
+
+    if let Some((_, program)) = resolved.programs.iter().find(|(path, _)| path == module) {
+        program.walk(&mut classifier);
+    }
+
+    classifier
+        .names
+        .into_iter()
+        .map(|(token, class)| (tokens[token].value.span.clone(), class))
+        .collect()
+}
+
+/// Encodes the identifiers as the protocol wants them: each one relative to the one before it, with
+/// columns in UTF-16.
+pub fn encode(source: &str, names: &[(Span, Class)]) -> Vec<SemanticToken> {
+    let index = LineIndex::new(source);
+
+    let mut result = Vec::new();
+    let mut last_line = 0;
+    let mut last_column = 0;
+
+    for (span, class) in names {
+        let Some((start, end)) = index.span(span, Encoding::Utf16) else {
+            continue;
+        };
+
+        let delta_start = if start.line == last_line {
+            start.column - last_column
+        } else {
+            start.column
+        };
+
+        result.push(SemanticToken {
+            delta_line: (start.line - last_line) as u32,
+            delta_start: delta_start as u32,
+            length: (end.column - start.column) as u32,
+            token_type: Class::ALL.iter().position(|x| x == class).unwrap() as u32,
+            token_modifiers_bitset: 0,
+        });
+
+        last_line = start.line;
+        last_column = start.column;
+    }
+
+    result
+}
+
+/// The classes of the declarations that the kind of a name doesn't tell apart.
+#[derive(Default)]
+pub(crate) struct Declarations {
+    /// The types, effects, traits and operations of the modules that the module uses.
+    declared: HashMap<Qualified, Class>,
+
+    /// The namespaces of the types, effects and traits, that their constructors, operations and
+    /// methods are inside of.
+    pub namespaces: HashMap<Symbol, Class>,
+}
+
+impl Declarations {
+    pub fn new(programs: &[(Path, Program)]) -> Self {
+        let mut declarations = Declarations::default();
+
+        for (_, program) in programs {
+            declarations.add(program);
+        }
+
+        declarations
+    }
+
+    /// The class of a declaration, or the one of its kind if it's not one of the declarations that
+    /// are told apart.
+    pub fn class(&self, qualified: &Qualified, kind: Class) -> Class {
+        self.declared.get(qualified).copied().unwrap_or(kind)
+    }
+
+    fn add(&mut self, program: &Program) {
+        for decl in &program.types {
+            self.declared.insert(decl.name.clone(), Class::Type);
+            self.namespaces.insert(decl.namespace.clone(), Class::Type);
+        }
+
+        for decl in &program.effects {
+            self.declared.insert(decl.name.clone(), Class::Effect);
+            self.namespaces
+                .insert(decl.namespace.clone(), Class::Effect);
+
+            for field in &decl.fields {
+                self.declared.insert(field.name.clone(), Class::Operation);
+            }
+        }
+
+        for decl in &program.traits {
+            self.declared.insert(decl.name.clone(), Class::Trait);
+            self.namespaces.insert(decl.namespace.clone(), Class::Trait);
+        }
+
+        for module in &program.modules {
+            if let Some(decls) = &module.decls {
+                self.add(decls);
+            }
+        }
+    }
+}
+
+struct Classifier<'a> {
+    tokens: &'a [Token],
+
+    /// The classes of the identifiers by the indices of their tokens.
+    names: BTreeMap<usize, Class>,
+
+    declarations: Declarations,
+}
+
+impl<'a> Classifier<'a> {
+    /// The index of the first token that starts at or after a byte.
+    fn position(&self, span: &Span) -> usize {
+        self.tokens
+            .partition_point(|x| x.value.span.start.0 < span.start.0)
+    }
+
+    fn token(&mut self, token: &Token, class: Class) {
+        let index = self.position(&token.value.span);
+        self.names.insert(index, class);
+    }
+
+    /// Classifies the identifiers of a path that starts at the start of a span. The last one is
+    /// the name and the ones before it are modules, or the type, effect or trait that the name is
+    /// inside of. Nodes that the resolver made up have spans of other things, so the path is only
+    /// classified if it ends with the name.
+    fn path(&mut self, span: &Span, name: &Symbol, class: Class, namespace: Option<Class>) {
+        let mut index = self.position(span);
+
+        if self
+            .tokens
+            .get(index)
+            .is_some_and(|x| x.is(TokenData::LBrace))
+        {
+            index += 1;
+        }
+
+        let mut segments = Vec::new();
+
+        while let Some(token) = self.tokens.get(index) {
+            if token.value.span.start.0 >= span.end.0 || !is_identifier(token) {
+                break;
+            }
+
+            segments.push(index);
+
+            match self.tokens.get(index + 1) {
+                Some(dot) if dot.is(TokenData::Dot) => index += 2,
+                _ => break,
+            }
+        }
+
+        let Some((last, segments)) = segments.split_last() else {
+            return;
+        };
+
+        if self.tokens[*last].value.data != *name {
+            return;
+        }
+
+        self.names.insert(*last, class);
+
+        for (i, segment) in segments.iter().enumerate() {
+            let class = match namespace {
+                Some(class) if i + 1 == segments.len() => class,
+                _ => Class::Module,
+            };
+
+            self.names.insert(*segment, class);
+        }
+    }
+
+    fn qualified(&mut self, span: &Span, qualified: &Qualified, class: Class) {
+        let class = self.declarations.class(qualified, class);
+        let namespace = self.declarations.namespaces.get(&qualified.path).copied();
+        self.path(span, &qualified.name, class, namespace);
+    }
+
+    fn module_path(&mut self, path: &ConcretePath<Upper>, class: Class) {
+        for (segment, _) in &path.segments {
+            self.token(&segment.0, Class::Module);
+        }
+
+        self.token(&path.last.0, class);
+    }
+
+    fn binders(&mut self, binders: &[TypeBinder]) {
+        for binder in binders {
+            match binder {
+                TypeBinder::Implicit(name) => self.token(&name.0, Class::TypeVariable),
+                TypeBinder::Explicit(binder) => {
+                    self.token(&binder.data.name.0, Class::TypeVariable)
+                }
+            }
+        }
+    }
+
+    /// Classifies the names that declarations introduce, that the resolved program doesn't have
+    /// the spans of.
+    fn top_levels(&mut self, top_levels: &[TopLevel]) {
+        for top_level in top_levels {
+            match top_level {
+                TopLevel::Let(decl) => self.token(&decl.signature.name.0, Class::Function),
+                TopLevel::Type(decl) => {
+                    self.token(&decl.name.0, Class::Type);
+                    self.binders(&decl.binders);
+
+                    if let Some((_, TypeDef::Sum(sum))) = &decl.def {
+                        for constructor in &sum.constructors {
+                            self.token(&constructor.name.0, Class::Constructor);
+                        }
+                    }
+                }
+                TopLevel::Effect(decl) => {
+                    self.token(&decl.name.0, Class::Effect);
+                    self.binders(&decl.binders);
+
+                    for field in &decl.fields {
+                        self.token(&field.name.0, Class::Operation);
+                    }
+                }
+                TopLevel::Use(decl) => {
+                    self.module_path(&decl.path, Class::Module);
+
+                    if let Some(alias) = &decl.alias {
+                        self.token(&alias.alias.0, Class::Module);
+                    }
+                }
+                TopLevel::Impl(decl) => {
+                    self.module_path(&decl.name, Class::Trait);
+
+                    for body in &decl.body {
+                        self.token(&body.signature.name.0, Class::Function);
+                    }
+                }
+                TopLevel::Trait(decl) => {
+                    self.token(&decl.name.0, Class::Trait);
+                    self.binders(&decl.binders);
+
+                    for signature in &decl.body {
+                        self.token(&signature.name.0, Class::Function);
+                    }
+                }
+                TopLevel::Module(decl) => {
+                    self.token(&decl.name.0, Class::Module);
+
+                    if let Some(part) = &decl.part {
+                        self.top_levels(&part.top_levels);
+                    }
+                }
+                TopLevel::External(decl) => {
+                    self.token(&decl.name.0, Class::Function);
+
+                    if let Some((_, effect)) = &decl.effect {
+                        self.module_path(effect, Class::Effect);
+                    }
+                }
+                TopLevel::When(decl) => {
+                    for arm in &decl.arms {
+                        self.top_levels(&arm.top_levels);
+                    }
+                }
+                // The variables of the pattern are classified with the resolved program.
+                TopLevel::Pattern(_) => (),
+                TopLevel::Error(_) | TopLevel::Command(_) | TopLevel::Test(_) => (),
+            }
+        }
+    }
+}
+
+impl<'a> Visitor for Classifier<'a> {
+    fn visit_expr(&mut self, expr: &abs::Expr) {
+        match &expr.data {
+            ExprKind::Variable(name) => self.path(&expr.span, name, Class::Variable, None),
+            ExprKind::Function(name) => self.qualified(&expr.span, name, Class::Function),
+            ExprKind::Constructor(name) => self.qualified(&expr.span, name, Class::Constructor),
+            ExprKind::RecordInstance(instance) => {
+                self.qualified(&expr.span, &instance.name, Class::Type)
+            }
+            ExprKind::Perform(perform) => {
+                // The path of the operation starts at the token after the `perform`.
+                let index = self.position(&expr.span) + 1;
+
+                if let Some(token) = self.tokens.get(index) {
+                    let span = Span {
+                        start: token.value.span.start.clone(),
+                        ..expr.span.clone()
+                    };
+
+                    self.qualified(&span, &perform.operation, Class::Operation);
+                }
+            }
+            ExprKind::Mask(mask) => {
+                // The path of the effect starts at the token after the `mask<`.
+                let index = self.position(&expr.span) + 2;
+
+                if let Some(token) = self.tokens.get(index) {
+                    let span = Span {
+                        start: token.value.span.start.clone(),
+                        ..expr.span.clone()
+                    };
+
+                    self.qualified(&span, &mask.effect, Class::Type);
+                }
+            }
+            ExprKind::Operation(operation) => {
+                let index = self.position(&expr.span);
+
+                if let Some(token) = self.tokens.get(index) {
+                    if token.value.data == operation.handler {
+                        self.names.insert(index, Class::Variable);
+                    }
+                }
+
+                if let Some(token) = self.tokens.get(index + 2) {
+                    if token.value.data == operation.name {
+                        self.names.insert(index + 2, Class::Operation);
+                    }
+                }
+            }
+            _ => (),
+        }
+
+        expr.walk(self)
+    }
+
+    fn visit_pattern(&mut self, pattern: &abs::Pattern) {
+        match &pattern.data {
+            PatternKind::Variable(name) => self.path(&pattern.span, name, Class::Variable, None),
+            PatternKind::Application(app) => {
+                self.qualified(&pattern.span, &app.func, Class::Constructor)
+            }
+            PatternKind::Effect(eff) => self.qualified(&pattern.span, &eff.func, Class::Operation),
+            _ => (),
+        }
+
+        pattern.walk(self)
+    }
+
+    fn visit_type(&mut self, typ: &abs::Type) {
+        match &typ.data {
+            TypeKind::Type(name) => self.qualified(&typ.span, name, Class::Type),
+            TypeKind::TypeVariable(name) => self.path(&typ.span, name, Class::TypeVariable, None),
+            _ => (),
+        }
+
+        typ.walk(self)
+    }
+}
+
+pub(crate) fn is_identifier(token: &Token) -> bool {
+    token.is(TokenData::UpperIdent) || token.is(TokenData::LowerIdent)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use vulpi_build::{cfg::Target, memory::MemoryFileSystem};
+
+    use super::*;
+
+    #[test]
+    fn classifies_paths() {
+        let name = Symbol::intern("Proj");
+
+        let mut fs = MemoryFileSystem::new(name.clone());
+        fs.insert(
+            PathBuf::from("Log.vp"),
+            "pub type Int\n\npub effect Log where\n  pub log : Int -> ()\n\npub let id (x : a) : a = x\n"
+                .to_string(),
+        );
+        fs.insert(
+            PathBuf::from("Main.vp"),
+            "use Proj.Log\nuse Proj.Log as L\n\nlet main (x : L.Int) : () = Log.log (L.id x)\n"
+                .to_string(),
+        );
+
+        let mut db = Database::new(fs, name, PathBuf::from("Main.vp"), Target::Js);
+        let main = db.root_module();
+
+        assert!(db.diagnostics().is_empty());
+
+        let source = db.source(&PathBuf::from("Main.vp")).unwrap().1;
+
+        let names: Vec<_> = classify(&mut db, &main)
+            .into_iter()
+            .map(|(span, class)| (&source[span.start.0..span.end.0], class))
+            .collect();
+
+        // `Log.log` is an operation of an effect and `L.id` is a function of a module, although
+        // both are an upper case identifier and a lower case one.
+        let expected = [
+            ("Proj", Class::Module),
+            ("Log", Class::Module),
+            ("Proj", Class::Module),
+            ("Log", Class::Module),
+            ("L", Class::Module),
+            ("main", Class::Function),
+            ("x", Class::Variable),
+            ("L", Class::Module),
+            ("Int", Class::Type),
+            ("Log", Class::Effect),
+            ("log", Class::Operation),
+            ("L", Class::Module),
+            ("id", Class::Function),
+            ("x", Class::Variable),
+        ];
+
+        assert_eq!(names, expected);
+    }
+}
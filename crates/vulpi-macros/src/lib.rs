@@ -120,3 +120,140 @@ pub fn derive_helper_attr(item: TokenStream) -> TokenStream {
     }
     .into()
 }
+
+/// The traversals that can be derived for the abstract tree. They're implemented in the module
+/// `visit` of the crate that derives them.
+#[derive(Clone, Copy)]
+enum Traversal {
+    Visit,
+    VisitMut,
+    Fold,
+}
+
+/// A pattern that binds every field of a struct or of a variant, and the names that it binds.
+fn bind(fields: &syn::Fields) -> (proc_macro2::TokenStream, Vec<syn::Ident>) {
+    let names = fields
+        .iter()
+        .enumerate()
+        .map(|(i, field)| {
+            field.ident.clone().unwrap_or_else(|| {
+                syn::Ident::new(&format!("field{}", i), proc_macro2::Span::call_site())
+            })
+        })
+        .collect::<Vec<_>>();
+
+    let pattern = match fields {
+        syn::Fields::Named(_) => quote! { { #(#names),* } },
+        syn::Fields::Unnamed(_) => quote! { ( #(#names),* ) },
+        syn::Fields::Unit => quote! {},
+    };
+
+    (pattern, names)
+}
+
+/// The body of the traversal of the fields that a pattern binds.
+fn traverse(
+    traversal: Traversal,
+    path: proc_macro2::TokenStream,
+    fields: &syn::Fields,
+) -> proc_macro2::TokenStream {
+    let (pattern, names) = bind(fields);
+
+    match traversal {
+        Traversal::Visit => quote! {
+            #path #pattern => {
+                #(crate::visit::Visit::visit(#names, visitor);)*
+            }
+        },
+        Traversal::VisitMut => quote! {
+            #path #pattern => {
+                #(crate::visit::VisitMut::visit_mut(#names, visitor);)*
+            }
+        },
+        Traversal::Fold => quote! {
+            #path #pattern => {
+                #(let #names = crate::visit::Fold::fold(#names, folder);)*
+                #path #pattern
+            }
+        },
+    }
+}
+
+fn derive_traversal(item: TokenStream, traversal: Traversal) -> TokenStream {
+    let parsed = syn::parse::<Item>(item).unwrap();
+
+    let (name, gen, arms) = match parsed {
+        Item::Enum(enum_) => {
+            let name = enum_.ident;
+
+            let arms = enum_
+                .variants
+                .iter()
+                .map(|variant| {
+                    let variant_name = &variant.ident;
+                    traverse(traversal, quote! { #name::#variant_name }, &variant.fields)
+                })
+                .collect::<Vec<_>>();
+
+            (name, enum_.generics, arms)
+        }
+        Item::Struct(struct_) => {
+            let name = struct_.ident;
+            let arms = vec![traverse(traversal, quote! { #name }, &struct_.fields)];
+            (name, struct_.generics, arms)
+        }
+        _ => panic!("Only structs and enums are supported"),
+    };
+
+    match traversal {
+        Traversal::Visit => quote! {
+            impl #gen crate::visit::Visit for #name #gen {
+                #[allow(unused_variables)]
+                fn walk<V: crate::visit::Visitor + ?Sized>(&self, visitor: &mut V) {
+                    match self {
+                        #(#arms)*
+                    }
+                }
+            }
+        },
+        Traversal::VisitMut => quote! {
+            impl #gen crate::visit::VisitMut for #name #gen {
+                #[allow(unused_variables)]
+                fn walk_mut<V: crate::visit::VisitorMut + ?Sized>(&mut self, visitor: &mut V) {
+                    match self {
+                        #(#arms)*
+                    }
+                }
+            }
+        },
+        Traversal::Fold => quote! {
+            impl #gen crate::visit::Fold for #name #gen {
+                #[allow(unused_variables)]
+                fn walk_fold<F: crate::visit::Folder + ?Sized>(self, folder: &mut F) -> Self {
+                    match self {
+                        #(#arms)*
+                    }
+                }
+            }
+        },
+    }
+    .into()
+}
+
+/// Derives `Visit` for a node of the abstract tree, visiting each of its fields in order.
+#[proc_macro_derive(Visit)]
+pub fn derive_visit(item: TokenStream) -> TokenStream {
+    derive_traversal(item, Traversal::Visit)
+}
+
+/// Derives `VisitMut` for a node of the abstract tree, visiting each of its fields in order.
+#[proc_macro_derive(VisitMut)]
+pub fn derive_visit_mut(item: TokenStream) -> TokenStream {
+    derive_traversal(item, Traversal::VisitMut)
+}
+
+/// Derives `Fold` for a node of the abstract tree, rebuilding it from its folded fields.
+#[proc_macro_derive(Fold)]
+pub fn derive_fold(item: TokenStream) -> TokenStream {
+    derive_traversal(item, Traversal::Fold)
+}
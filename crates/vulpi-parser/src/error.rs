@@ -5,6 +5,11 @@ use vulpi_syntax::tokens::Token;
 #[derive(Debug)]
 pub enum ParserError {
     UnexpectedToken(Box<Token>, Span),
+    TooDeep(Span),
+    /// Attributes before a declaration that cannot have them, like a `use`.
+    MisplacedAttribute(Span),
+    /// Two elements of a list without the comma between them.
+    MissingComma(Span),
 }
 
 impl IntoDiagnostic for ParserError {
@@ -13,6 +18,20 @@ impl IntoDiagnostic for ParserError {
             ParserError::UnexpectedToken(token, _) => {
                 format!("unexpected token '{:?}'", token.kind).into()
             }
+            ParserError::TooDeep(_) => "this is nested too deeply".into(),
+            ParserError::MisplacedAttribute(_) => {
+                "attributes are not allowed before this declaration".into()
+            }
+            ParserError::MissingComma(_) => "a comma is missing here".into(),
+        }
+    }
+
+    fn code(&self) -> Option<usize> {
+        match self {
+            ParserError::UnexpectedToken(_, _) => Some(100),
+            ParserError::TooDeep(_) => Some(101),
+            ParserError::MisplacedAttribute(_) => Some(102),
+            ParserError::MissingComma(_) => Some(103),
         }
     }
 
@@ -23,6 +42,9 @@ impl IntoDiagnostic for ParserError {
     fn location(&self) -> Span {
         match self {
             ParserError::UnexpectedToken(_, span) => span.clone(),
+            ParserError::TooDeep(span) => span.clone(),
+            ParserError::MisplacedAttribute(span) => span.clone(),
+            ParserError::MissingComma(span) => span.clone(),
         }
     }
 }
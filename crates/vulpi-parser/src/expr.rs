@@ -1,9 +1,9 @@
-use crate::{Parser, Result};
+use crate::{error::ParserError, Parser, Result};
 
 use vulpi_location::Spanned;
 use vulpi_syntax::{
     concrete::{tree::*, Either, Path, Upper},
-    tokens::TokenData,
+    tokens::{Token, TokenData},
 };
 
 impl<'a> Parser<'a> {
@@ -16,7 +16,7 @@ impl<'a> Parser<'a> {
 
     pub fn record_instance(&mut self, name: Path<Upper>) -> Result<RecordInstance> {
         let left_brace = self.expect(TokenData::LBrace)?;
-        let fields = self.sep_by(TokenData::Comma, Self::record_field)?;
+        let fields = self.enclosed(|this| this.sep_by(TokenData::Comma, Self::record_field))?;
         let right_brace = self.expect(TokenData::RBrace)?;
         Ok(RecordInstance {
             name,
@@ -28,7 +28,7 @@ impl<'a> Parser<'a> {
 
     pub fn record_update(&mut self, expr: Box<Expr>) -> Result<RecordUpdate> {
         let left_brace = self.expect(TokenData::LBrace)?;
-        let fields = self.sep_by(TokenData::Comma, Self::record_field)?;
+        let fields = self.enclosed(|this| this.sep_by(TokenData::Comma, Self::record_field))?;
         let right_brace = self.expect(TokenData::RBrace)?;
         Ok(RecordUpdate {
             expr,
@@ -51,9 +51,27 @@ impl<'a> Parser<'a> {
         })
     }
 
+    pub fn expect_sttm(&mut self) -> Result<ExpectSttm> {
+        let expect = self.expect(TokenData::Expect)?;
+        let pattern = self.pattern()?;
+        let eq = self.expect(TokenData::Equal)?;
+        let expr = self.expr()?;
+        let else_ = self.expect(TokenData::Else)?;
+        let alternative = self.expr()?;
+        Ok(ExpectSttm {
+            expect,
+            pattern,
+            eq,
+            expr,
+            else_,
+            alternative,
+        })
+    }
+
     pub fn statement_kind(&mut self) -> Result<StatementKind> {
         match self.token() {
             TokenData::Let => self.let_sttm().map(StatementKind::Let),
+            TokenData::Expect => self.expect_sttm().map(StatementKind::Expect),
             _ => self.expr().map(StatementKind::Expr),
         }
     }
@@ -71,7 +89,7 @@ impl<'a> Parser<'a> {
         let mut statements = Vec::new();
 
         while !self.at(TokenData::End) {
-            let stmt = parse(self)?;
+            let stmt = self.enclosed(&parse)?;
 
             if self.at(TokenData::Sep) {
                 self.bump();
@@ -86,8 +104,17 @@ impl<'a> Parser<'a> {
     }
     
     pub fn list_expr(&mut self) -> Result<ListExpr> {
-        let left_bracket = self.expect(TokenData::LBracket)?;
-        let values = self.sep_by(TokenData::Comma, Self::expr)?;
+        self.delimited_list(TokenData::LBracket)
+    }
+
+    pub fn array_expr(&mut self) -> Result<ListExpr> {
+        self.delimited_list(TokenData::HashBracket)
+    }
+
+    /// The elements of a list or of an array, that only differ by the token that opens them.
+    fn delimited_list(&mut self, open: TokenData) -> Result<ListExpr> {
+        let left_bracket = self.expect(open)?;
+        let values = self.enclosed(|this| this.sep_by(TokenData::Comma, Self::expr))?;
         let right_bracket = self.expect(TokenData::RBracket)?;
         
         Ok(ListExpr {
@@ -99,10 +126,31 @@ impl<'a> Parser<'a> {
 
     pub fn expr_atom_kind(&mut self) -> Result<ExprKind> {
         match self.token() {
+            TokenData::LowerIdent if self.at_group_end() => self.unexpected(),
+            TokenData::LowerIdent if self.at_field(TokenData::Equal) => self.unexpected(),
             TokenData::LBracket => Ok(ExprKind::List(self.list_expr()?)),
+            TokenData::HashBracket if self.at_attribute() => self.unexpected(),
+            TokenData::HashBracket => Ok(ExprKind::Array(self.array_expr()?)),
             TokenData::Less => Ok(ExprKind::HtmlNode(self.html_node()?)),
             TokenData::UpperIdent | TokenData::LowerIdent => {
-                let path = self.path_ident()?;
+                // A path that ends with `do` is the module of a qualified `do`.
+                let path = self.path(|this| {
+                    if this.at(TokenData::Do) {
+                        Ok(None)
+                    } else {
+                        this.ident().map(Some)
+                    }
+                })?;
+
+                let Some(last) = path.last else {
+                    return Ok(ExprKind::Do(self.do_block(path.segments)?));
+                };
+
+                let path = Path {
+                    segments: path.segments,
+                    last,
+                    span: path.span,
+                };
 
                 match path.diferentiate() {
                     Either::Left(upper) => {
@@ -122,14 +170,14 @@ impl<'a> Parser<'a> {
             TokenData::LPar => {
                 let exprs = self.parenthesis(|this| this.sep_by(TokenData::Comma, Self::expr))?;
 
-                if exprs.data.is_empty() {
-                    todo!()
-                } else if exprs.data.len() == 1 {
-                    Ok(ExprKind::Parenthesis(
-                        exprs.map(|x| x.into_iter().next().unwrap()),
-                    ))
-                } else {
-                    Ok(ExprKind::Tuple(exprs))
+                // The unit is a token of its own, so `( )` with spaces is not one.
+                match exprs.data.len() {
+                    0 => Err(ParserError::UnexpectedToken(
+                        Box::new(exprs.right.clone()),
+                        exprs.right.value.span.clone(),
+                    )),
+                    1 => Ok(ExprKind::Parenthesis(exprs.map(|mut x| x.remove(0)))),
+                    _ => Ok(ExprKind::Tuple(exprs)),
                 }
             }
             _ => self.literal().map(ExprKind::Literal),
@@ -140,13 +188,38 @@ impl<'a> Parser<'a> {
         self.spanned(Self::expr_atom_kind).map(Box::new)
     }
 
+    /// Parses a call to an operation like `perform Log.log x`, that takes its arguments like an
+    /// application.
+    pub fn perform_expr(&mut self) -> Result<Box<Expr>> {
+        let perform = self.expect(TokenData::Perform)?;
+        let operation = self.path_lower()?;
+        let args = self.many(Self::acessor)?;
+
+        let range = self.with_span(perform.value.span.clone());
+
+        Ok(Box::new(Spanned {
+            span: range,
+            data: ExprKind::Perform(PerformExpr {
+                perform,
+                operation,
+                args,
+            }),
+        }))
+    }
+
     pub fn expr_application(&mut self) -> Result<Box<Expr>> {
+        if self.at(TokenData::Perform) {
+            return self.perform_expr();
+        }
+
         let func = self.acessor()?;
         let args = self.many(Self::acessor)?;
         if args.is_empty() {
             Ok(func)
         } else {
-            let range = func.span.clone().mix(args.last().unwrap().span.clone());
+            let range = args
+                .iter()
+                .fold(func.span.clone(), |x, y| x.mix(y.span.clone()));
             Ok(Box::new(Spanned {
                 span: range,
                 data: ExprKind::Application(ApplicationExpr { func, args }),
@@ -165,7 +238,7 @@ impl<'a> Parser<'a> {
             // Cloned peek inside the expr_precedence
             self.bump();
 
-            let right = self.expr_binary(upper)?;
+            let right = self.nested(|this| this.expr_binary(upper))?;
 
             let range = left.span.clone().mix(right.span.clone());
 
@@ -223,15 +296,56 @@ impl<'a> Parser<'a> {
         }
     }
 
-    pub fn expr_do(&mut self) -> Result<Box<Expr>> {
+    pub fn do_block(&mut self, path: Vec<(Upper, Token)>) -> Result<DoExpr> {
         let do_ = self.expect(TokenData::Do)?;
         let statements = self.block(Self::statement)?;
-        let range = self.with_span(do_.value.span.clone());
+        Ok(DoExpr {
+            path,
+            do_,
+            block: Block { statements },
+        })
+    }
+
+    pub fn expr_do(&mut self) -> Result<Box<Expr>> {
+        let start = self.span();
+        let do_ = self.do_block(vec![])?;
+        let range = self.with_span(start);
         Ok(Box::new(Spanned {
             span: range,
-            data: ExprKind::Do(DoExpr {
-                do_,
-                block: Block { statements },
+            data: ExprKind::Do(do_),
+        }))
+    }
+
+    pub fn while_expr(&mut self) -> Result<Box<Expr>> {
+        let while_ = self.expect(TokenData::While)?;
+        let condition = self.expr()?;
+        let body = self.do_block(vec![])?;
+        let range = self.with_span(while_.value.span.clone());
+        Ok(Box::new(Spanned {
+            span: range,
+            data: ExprKind::While(WhileExpr {
+                while_,
+                condition,
+                body,
+            }),
+        }))
+    }
+
+    pub fn for_expr(&mut self) -> Result<Box<Expr>> {
+        let for_ = self.expect(TokenData::For)?;
+        let pattern = self.pattern()?;
+        let in_ = self.expect(TokenData::In)?;
+        let list = self.expr()?;
+        let body = self.do_block(vec![])?;
+        let range = self.with_span(for_.value.span.clone());
+        Ok(Box::new(Spanned {
+            span: range,
+            data: ExprKind::For(ForExpr {
+                for_,
+                pattern,
+                in_,
+                list,
+                body,
             }),
         }))
     }
@@ -272,8 +386,80 @@ impl<'a> Parser<'a> {
         }
     }
 
+
+    pub fn let_binding(&mut self) -> Result<LetBinding> {
+        let name = self.lower()?;
+        let params = self.many(Self::pattern)?;
+        let eq = self.expect(TokenData::Equal)?;
+
+        let in_group = std::mem::replace(&mut self.in_group, true);
+        let value = self.expr();
+        self.in_group = in_group;
+
+        Ok(LetBinding {
+            name,
+            params,
+            eq,
+            value: value?,
+        })
+    }
+
+    /// A let that starts with a name, that is a group of bindings if it has more than one or if it
+    /// has parameters, and a let of a variable otherwise.
+    pub fn let_group(&mut self, let_: Token) -> Result<Box<Expr>> {
+        let mut bindings = Vec::new();
+
+        loop {
+            let binding = self.let_binding()?;
+
+            if self.at_and() {
+                bindings.push((binding, Some(self.bump())));
+            } else {
+                bindings.push((binding, None));
+                break;
+            }
+        }
+
+        let in_ = self.expect(TokenData::In)?;
+        let value = self.expr()?;
+
+        let span = self.with_span(let_.value.span.clone());
+
+        let data = match bindings.as_slice() {
+            [(binding, None)] if binding.params.is_empty() => {
+                let (binding, _) = bindings.pop().unwrap();
+
+                ExprKind::Let(LetExpr {
+                    let_,
+                    pattern: Box::new(Spanned {
+                        span: binding.name.0.value.span.clone(),
+                        data: PatternKind::Variable(binding.name),
+                    }),
+                    eq: binding.eq,
+                    body: binding.value,
+                    in_,
+                    value,
+                })
+            }
+            _ => ExprKind::LetGroup(LetGroupExpr {
+                let_,
+                bindings,
+                in_,
+                value,
+            }),
+        };
+
+        Ok(Box::new(Spanned { span, data }))
+    }
+
     pub fn let_expr(&mut self) -> Result<Box<Expr>> {
         let let_ = self.expect(TokenData::Let)?;
+
+        // A name followed by `@` is the start of an as-pattern and not of a binding.
+        if self.at(TokenData::LowerIdent) && !self.then(TokenData::At) {
+            return self.let_group(let_);
+        }
+
         let pattern = self.pattern()?;
         let eq = self.expect(TokenData::Equal)?;
         let value = self.expr()?;
@@ -365,17 +551,109 @@ impl<'a> Parser<'a> {
         }))
     }
 
+    pub fn cases_expr(&mut self) -> Result<Box<Expr>> {
+        let cases = self.expect(TokenData::Cases)?;
+        let arms = self.block(Self::pattern_arm)?;
+
+        let range = self.with_span(cases.value.span.clone());
+
+        Ok(Box::new(Spanned {
+            span: range,
+            data: ExprKind::Cases(CasesExpr { cases, arms }),
+        }))
+    }
+
+    pub fn named_handler_expr(&mut self, handle: Token) -> Result<Box<Expr>> {
+        let name = self.lower()?;
+        let eq = self.expect(TokenData::Equal)?;
+        let handler = self.expr()?;
+        let in_ = self.expect(TokenData::In)?;
+        let expr = self.expr()?;
+
+        let range = self.with_span(handle.value.span.clone());
+
+        Ok(Box::new(Spanned {
+            span: range,
+            data: ExprKind::NamedHandler(NamedHandlerExpr {
+                handle,
+                name,
+                eq,
+                handler,
+                in_,
+                expr,
+            }),
+        }))
+    }
+
+    pub fn handler_expr(&mut self) -> Result<Box<Expr>> {
+        let handle = self.expect(TokenData::Handle)?;
+
+        if self.at(TokenData::LowerIdent) && self.then(TokenData::Equal) {
+            return self.named_handler_expr(handle);
+        }
+
+        let expr = self.expr()?;
+        let with = self.expect(TokenData::With)?;
+        let handler = self.expr()?;
+
+        let finally = if self.at(TokenData::Finally) {
+            let finally = self.bump();
+            Some((finally, self.expr()?))
+        } else {
+            None
+        };
+
+        let range = self.with_span(handle.value.span.clone());
+
+        Ok(Box::new(Spanned {
+            span: range,
+            data: ExprKind::Handler(HandlerExpr {
+                handle,
+                expr,
+                with,
+                handler,
+                finally,
+            }),
+        }))
+    }
+
+    pub fn mask_expr(&mut self) -> Result<Box<Expr>> {
+        let mask = self.expect(TokenData::Mask)?;
+        let less = self.expect(TokenData::Less)?;
+        let effect = self.path_upper()?;
+        let greater = self.expect(TokenData::Greater)?;
+        let expr = self.expr()?;
+
+        let range = self.with_span(mask.value.span.clone());
+
+        Ok(Box::new(Spanned {
+            span: range,
+            data: ExprKind::Mask(MaskExpr {
+                mask,
+                less,
+                effect,
+                greater,
+                expr,
+            }),
+        }))
+    }
+
     pub fn expr_part(&mut self) -> Result<Box<Expr>> {
         match self.token() {
             TokenData::BackSlash => self.lambda_expr(),
             TokenData::Let => self.let_expr(),
             TokenData::Do => self.expr_do(),
+            TokenData::While => self.while_expr(),
+            TokenData::For => self.for_expr(),
             TokenData::When => self.when_expr(),
+            TokenData::Cases => self.cases_expr(),
+            TokenData::Handle => self.handler_expr(),
+            TokenData::Mask => self.mask_expr(),
             _ => self.expr_annotation(),
         }
     }
 
-    pub fn expr(&mut self) -> Result<Box<Expr>> {
+    fn expr_raw(&mut self) -> Result<Box<Expr>> {
         let mut left = self.expr_part()?;
 
         while self.at(TokenData::PipeRight) {
@@ -394,4 +672,8 @@ impl<'a> Parser<'a> {
 
         Ok(left)
     }
+
+    pub fn expr(&mut self) -> Result<Box<Expr>> {
+        self.nested(Self::expr_raw)
+    }
 }
@@ -29,11 +29,15 @@ impl<'a> Parser<'a> {
     }
 
     pub fn path_ident(&mut self) -> Result<Path<Ident>> {
-        self.path(|parser| match parser.peek().kind {
-            TokenData::LowerIdent => Ok(Ident::Lower(parser.lower()?)),
-            TokenData::UpperIdent => Ok(Ident::Upper(parser.upper()?)),
-            _ => parser.unexpected(),
-        })
+        self.path(Self::ident)
+    }
+
+    pub fn ident(&mut self) -> Result<Ident> {
+        match self.peek().kind {
+            TokenData::LowerIdent => Ok(Ident::Lower(self.lower()?)),
+            TokenData::UpperIdent => Ok(Ident::Upper(self.upper()?)),
+            _ => self.unexpected(),
+        }
     }
 
     pub fn path_upper(&mut self) -> Result<Path<Upper>> {
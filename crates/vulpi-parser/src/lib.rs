@@ -2,6 +2,7 @@
 //! nodes. It's a classical LL(1) parser with a recursive descent and pratt parsing.
 
 use error::ParserError;
+use vulpi_intern::Symbol;
 use vulpi_lexer::Lexer;
 use vulpi_location::{Byte, FileId, Span, Spanned};
 use vulpi_report::{Diagnostic, Report};
@@ -20,6 +21,11 @@ pub mod r#type;
 
 pub type Result<T> = std::result::Result<T, error::ParserError>;
 
+/// How deep the expressions, types, patterns and declarations can be nested inside each other.
+/// The parser is recursive, so the limit keeps it from overflowing the stack of a main thread, even
+/// in a build without optimizations.
+pub const MAX_DEPTH: usize = 128;
+
 /// The parser main structure.
 pub struct Parser<'a> {
     pub lexer: Lexer<'a>,
@@ -30,13 +36,20 @@ pub struct Parser<'a> {
     pub next: Token,
 
     pub eaten: bool,
-    pub file: FileId,
+
+    /// How many of the nodes that can nest are being parsed.
+    pub depth: usize,
+
+    /// Whether the value of a binding of a let group is being parsed, where a bare `and` starts the
+    /// next binding instead of being an argument.
+    pub in_group: bool,
 
     pub reporter: Report,
 }
 
 impl<'a> Parser<'a> {
-    pub fn new(mut lexer: Lexer<'a>, file: FileId, report: Report) -> Self {
+    pub fn new(mut lexer: Lexer<'a>, report: Report) -> Self {
+        let file = lexer.file();
         let current = lexer.bump();
         let next = lexer.bump();
 
@@ -50,7 +63,8 @@ impl<'a> Parser<'a> {
                 end: Byte(0),
             },
             eaten: false,
-            file,
+            depth: 0,
+            in_group: false,
             reporter: report,
         }
     }
@@ -63,7 +77,11 @@ impl<'a> Parser<'a> {
         std::mem::swap(&mut self.current, &mut self.next);
         std::mem::swap(&mut ret, &mut self.next);
 
-        self.last_pos = ret.value.span.clone();
+        // The layout tokens are at the token that comes after them, so the nodes that end with a
+        // block end at its last token instead.
+        if !matches!(ret.kind, TokenData::Begin | TokenData::Sep | TokenData::End) {
+            self.last_pos = ret.value.span.clone();
+        }
 
         ret
     }
@@ -179,37 +197,68 @@ impl<'a> Parser<'a> {
         Ok(Spanned::new(value, start.mix(end)))
     }
 
-    /// Parses a list of elements separated by a given token.
+    /// Parses a list of elements separated by a given token. The list can end with a separator.
+    /// When the elements are separated by commas and one of them is missing, the next element is
+    /// parsed anyway: the missing comma is reported and a virtual one takes its place.
     pub fn sep_by<T>(
         &mut self,
         sep: TokenData,
         mut fun: impl FnMut(&mut Self) -> Result<T>,
     ) -> Result<Vec<(T, Option<Token>)>> {
         let mut values = Vec::new();
+        let mut next = self.test(&mut fun)?;
 
-        while let Some(res) = self.test(&mut fun)? {
-            let sep = if self.at(sep) {
-                Some(self.bump())
-            } else {
-                None
-            };
+        while let Some(res) = next {
+            if self.at(sep) {
+                values.push((res, Some(self.bump())));
+                next = self.test(&mut fun)?;
+                continue;
+            }
 
-            let at_end = sep.is_none();
+            if sep != TokenData::Comma {
+                values.push((res, None));
+                break;
+            }
 
-            values.push((res, sep));
+            let end = self.last_pos.clone();
 
-            if at_end {
-                break;
+            // The list was consumed even if no element comes after it.
+            next = self.test(&mut fun)?;
+            self.eaten = true;
+
+            if next.is_some() {
+                let comma = self.virtual_comma(end);
+                let error = ParserError::MissingComma(comma.value.span.clone());
+                self.reporter.report(Diagnostic::new(error));
+                values.push((res, Some(comma)));
+            } else {
+                values.push((res, None));
             }
         }
 
-        if self.at(sep) && !values.is_empty() {
-            values.last_mut().unwrap().1 = Some(self.bump());
+        if let Some(last) = values.last_mut().filter(|_| self.at(sep)) {
+            last.1 = Some(self.bump());
         }
 
         Ok(values)
     }
 
+    /// A comma that is not in the source, right after the end of a span.
+    fn virtual_comma(&self, after: Span) -> Token {
+        let span = Span {
+            file: after.file,
+            start: after.end.clone(),
+            end: after.end,
+        };
+
+        Token {
+            comments: vec![],
+            whitespace: Spanned::new(Symbol::intern(""), span.clone()),
+            kind: TokenData::Comma,
+            value: Spanned::new(Symbol::intern(","), span),
+        }
+    }
+
     /// Parses a list of elements.
     pub fn many<T>(&mut self, mut fun: impl FnMut(&mut Self) -> Result<T>) -> Result<Vec<T>> {
         let mut values = Vec::new();
@@ -221,6 +270,53 @@ impl<'a> Parser<'a> {
         Ok(values)
     }
 
+    /// Parses a node that can contain itself, failing if it's nested deeper than [MAX_DEPTH].
+    pub fn nested<T>(&mut self, fun: impl FnOnce(&mut Self) -> Result<T>) -> Result<T> {
+        if self.depth >= MAX_DEPTH {
+            return Err(ParserError::TooDeep(self.span()));
+        }
+
+        self.depth += 1;
+        let result = fun(self);
+        self.depth -= 1;
+
+        result
+    }
+
+    /// Parses a node that is closed by a token of its own, so an `and` inside of it belongs to it
+    /// even if it's in the value of a binding of a let group.
+    pub fn enclosed<T>(&mut self, fun: impl FnOnce(&mut Self) -> Result<T>) -> Result<T> {
+        let in_group = std::mem::replace(&mut self.in_group, false);
+        let result = fun(self);
+        self.in_group = in_group;
+
+        result
+    }
+
+    /// Checks if the current token is an `and`, that separates the bindings of a let group. It's
+    /// not a keyword, so it can still be the name of a function.
+    pub fn at_and(&self) -> bool {
+        self.at(TokenData::LowerIdent) && self.peek().value.data.get() == "and"
+    }
+
+    /// Checks if the current token is the name of a field of a record followed by the token that
+    /// comes after the name, like the `y` of `{ x = 1 y = 2 }`. It starts the next field after a
+    /// missing comma instead of being an argument of the value of the last one.
+    pub fn at_field(&self, token: TokenData) -> bool {
+        self.at(TokenData::LowerIdent) && self.then(token)
+    }
+
+    /// Checks if the current token is an `and` that ends the value of a binding of a let group.
+    pub fn at_group_end(&self) -> bool {
+        self.in_group && self.at_and()
+    }
+
+    /// Checks if the current token is a `#[` at the start of a line. It opens the attributes of
+    /// the next declaration instead of an array, because an array never starts a line of a body.
+    pub fn at_attribute(&self) -> bool {
+        self.at(TokenData::HashBracket) && self.peek().whitespace.data.get().ends_with('\n')
+    }
+
     pub fn with_span(&mut self, start: Span) -> Span {
         let end = self.last_pos.clone();
         start.mix(end)
@@ -233,7 +329,7 @@ impl<'a> Parser<'a> {
         parse: impl Fn(&mut Self) -> Result<T>,
     ) -> Result<Parenthesis<T>> {
         let left = self.expect(TokenData::LPar)?;
-        let data = parse(self)?;
+        let data = self.enclosed(parse)?;
         let right = self.expect(TokenData::RPar)?;
 
         Ok(Parenthesis { left, data, right })
@@ -242,7 +338,105 @@ impl<'a> Parser<'a> {
 
 /// The entrypoint of the parsing, it parses a string into a Program.
 pub fn parse(reporter: Report, file_id: FileId, source: &str) -> Program {
-    let lexer = Lexer::new(source, file_id, reporter.clone());
-    let mut parser = Parser::new(lexer, file_id, reporter);
+    parse_with_tab_width(reporter, file_id, source, vulpi_lexer::TAB_WIDTH)
+}
+
+/// Parses a program whose tabs go to the next multiple of `tab_width` columns.
+pub fn parse_with_tab_width(
+    reporter: Report,
+    file_id: FileId,
+    source: &str,
+    tab_width: usize,
+) -> Program {
+    let lexer = Lexer::new(source, file_id, reporter.clone()).with_tab_width(tab_width);
+    let mut parser = Parser::new(lexer, reporter);
     parser.program()
 }
+
+/// Parses any sequence of bytes, for fuzzing and for editors, where the input may be incomplete or
+/// not even text. It never panics: the bytes that are not UTF-8 are replaced, and the parts that
+/// cannot be parsed are reported and kept as error nodes of the program.
+pub fn parse_resilient(reporter: Report, file_id: FileId, source: &[u8]) -> Program {
+    let source = String::from_utf8_lossy(source);
+    parse(reporter, file_id, &source)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use vulpi_report::IntoDiagnostic;
+    use vulpi_syntax::concrete::tree::TopLevel;
+
+    fn errors(source: &[u8]) -> Vec<Option<usize>> {
+        let reporter = vulpi_report::hash_reporter();
+        parse_resilient(reporter.clone(), FileId(0), source);
+        reporter.detach().iter().map(|x| x.code()).collect()
+    }
+
+    #[test]
+    fn reports_instead_of_panicking() {
+        assert_eq!(errors(b"let x = ( )"), vec![Some(100)]);
+        assert_eq!(errors(b"let x : ( ) = 1"), vec![Some(100)]);
+        assert!(!errors(b"let x = \xff\xfe (").is_empty());
+
+        // The limit is for the stack of a main thread, that is larger than the one of a test.
+        let deep = std::thread::Builder::new()
+            .stack_size(8 * 1024 * 1024)
+            .spawn(|| errors(format!("let x = {}", "(".repeat(100_000)).as_bytes()))
+            .unwrap();
+
+        assert!(!deep.join().unwrap().is_empty());
+    }
+
+    #[test]
+    fn accepts_trailing_commas() {
+        assert!(errors(b"let x = (1, 2,)").is_empty());
+        assert!(errors(b"let x : (Int, Int,) = [1, 2,]").is_empty());
+        assert!(errors(b"let x = Point { x = 1, y = 2, }").is_empty());
+        assert!(errors(b"type R = { x : Int, y : Int, }").is_empty());
+        assert!(errors(b"#[deprecated(a, b,)]\nlet x = 1").is_empty());
+    }
+
+    #[test]
+    fn recovers_missing_commas() {
+        let reporter = vulpi_report::hash_reporter();
+        parse(reporter.clone(), FileId(0), "let x = Point { x = 1 y = f 2 }");
+
+        let diagnostics = reporter.detach();
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(diagnostics[0].code(), Some(103));
+        assert_eq!(diagnostics[0].location().start, Byte(21));
+
+        assert_eq!(errors(b"type R = { x : Int pub y : List Int }"), vec![Some(103)]);
+        assert_eq!(errors(b"#[deprecated(a b)]\nlet x = 1"), vec![Some(103)]);
+    }
+
+    #[test]
+    fn blocks_end_at_their_last_token() {
+        let source = "let x = do\n  f 1\n  2\n\nlet y = 3";
+        let program = parse(vulpi_report::hash_reporter(), FileId(0), source);
+
+        let TopLevel::Let(decl) = &program.top_levels[0] else {
+            panic!("the first declaration is a let")
+        };
+
+        assert_eq!(decl.span.end, Byte(source.find("\n\n").unwrap()));
+    }
+
+    #[test]
+    fn spans_are_in_the_file_of_the_lexer() {
+        let reporter = vulpi_report::hash_reporter();
+        let program = parse(reporter.clone(), FileId(7), "let x = 1\n\nlet y = (");
+
+        let TopLevel::Let(decl) = &program.top_levels[0] else {
+            panic!("the first declaration is a let")
+        };
+
+        assert_eq!(decl.span.file, FileId(7));
+
+        // The error at the end of the file has no token of its own, but it's still in the file.
+        let diagnostics = reporter.detach();
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(diagnostics[0].location().file, FileId(7));
+    }
+}
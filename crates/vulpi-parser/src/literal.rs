@@ -35,4 +35,20 @@ impl<'a> Parser<'a> {
     pub fn literal(&mut self) -> Result<Literal> {
         self.spanned(Self::literal_kind)
     }
+
+    /// A literal of a pattern, that can be a number with a minus sign.
+    pub fn pattern_literal(&mut self) -> Result<Literal> {
+        if !self.at(TokenData::Minus) {
+            return self.literal();
+        }
+
+        self.spanned(|this| {
+            let minus = this.bump();
+
+            match this.token() {
+                TokenData::Int | TokenData::Float => Ok(LiteralKind::Negative(minus, this.bump())),
+                _ => this.unexpected(),
+            }
+        })
+    }
 }
@@ -1,29 +1,101 @@
+use vulpi_location::Spanned;
 use vulpi_syntax::{
     concrete::{pattern::*, Either},
     tokens::TokenData,
 };
 
-use crate::{Parser, Result};
+use crate::{error::ParserError, Parser, Result};
 
 impl<'a> Parser<'a> {
     pub fn pattern_atom_kind(&mut self) -> Result<PatternKind> {
         match self.token() {
             TokenData::Wildcard => Ok(PatternKind::Wildcard(self.bump())),
-            TokenData::LowerIdent => self.lower().map(PatternKind::Variable),
+            TokenData::LowerIdent => {
+                let name = self.lower()?;
+
+                if self.at(TokenData::At) {
+                    let at = self.bump();
+                    let pattern = self.pattern_atom()?;
+                    Ok(PatternKind::As(PatAs { name, at, pattern }))
+                } else {
+                    Ok(PatternKind::Variable(name))
+                }
+            }
             TokenData::UpperIdent => {
                 let path = self.path_ident()?;
                 match path.diferentiate() {
                     Either::Left(upper) => Ok(PatternKind::Constructor(upper)),
-                    Either::Right(_) => todo!(),
+                    Either::Right(lower) => Err(ParserError::UnexpectedToken(
+                        Box::new(lower.last.0.clone()),
+                        lower.last.0.value.span.clone(),
+                    )),
+                }
+            }
+            TokenData::LPar => {
+                let pats = self.parenthesis(|this| {
+                    this.sep_by(TokenData::Comma, Self::pattern_annotation)
+                })?;
+
+                match pats.data.len() {
+                    0 => Err(ParserError::UnexpectedToken(
+                        Box::new(pats.right.clone()),
+                        pats.right.value.span.clone(),
+                    )),
+                    1 if pats.data[0].1.is_none() => {
+                        Ok(PatternKind::Parenthesis(pats.map(|mut x| x.remove(0).0)))
+                    }
+                    _ => Ok(PatternKind::Tuple(
+                        pats.data.into_iter().map(|(x, sep)| (*x, sep)).collect(),
+                    )),
+                }
+            }
+            TokenData::LBrace => self
+                .pattern_effect()
+                .map(Box::new)
+                .map(PatternKind::Effect),
+            TokenData::Return => {
+                let return_ = self.bump();
+                let pattern = self.pattern_application()?;
+                Ok(PatternKind::Return(PatReturn { return_, pattern }))
+            }
+            _ => {
+                let start = self.pattern_literal()?;
+
+                if self.at(TokenData::DotDot) {
+                    let dots = self.bump();
+                    let end = self.pattern_literal()?;
+                    Ok(PatternKind::Range(Box::new(PatRange { start, dots, end })))
+                } else {
+                    Ok(PatternKind::Literal(start))
                 }
             }
-            TokenData::LPar => self
-                .parenthesis(Self::pattern)
-                .map(PatternKind::Parenthesis),
-            _ => self.literal().map(PatternKind::Literal),
         }
     }
 
+    pub fn pattern_effect(&mut self) -> Result<PatEffect> {
+        let left_brace = self.expect(TokenData::LBrace)?;
+        let func = self.path_lower()?;
+        let args = self.many(Self::pattern_atom)?;
+
+        let cont = if self.at(TokenData::RightArrow) {
+            let arrow = self.bump();
+            let name = self.lower()?;
+            Some((arrow, name))
+        } else {
+            None
+        };
+
+        let right_brace = self.expect(TokenData::RBrace)?;
+
+        Ok(PatEffect {
+            left_brace,
+            func,
+            args,
+            cont,
+            right_brace,
+        })
+    }
+
     pub fn pattern_atom(&mut self) -> Result<Box<Pattern>> {
         self.spanned(Self::pattern_atom_kind).map(Box::new)
     }
@@ -50,7 +122,36 @@ impl<'a> Parser<'a> {
         }
     }
 
+    /// A pattern inside of parenthesis, that can have a type like `(x : Int)`.
+    fn pattern_annotation(&mut self) -> Result<Box<Pattern>> {
+        let left = self.pattern()?;
+
+        if self.at(TokenData::Colon) {
+            let colon = self.bump();
+            let right = self.typ()?;
+
+            Ok(Box::new(Spanned {
+                span: left.span.clone().mix(right.span.clone()),
+                data: PatternKind::Annotation(PatAscription { left, colon, right }),
+            }))
+        } else {
+            Ok(left)
+        }
+    }
+
     pub fn pattern(&mut self) -> Result<Box<Pattern>> {
-        self.pattern_application()
+        let left = self.nested(Self::pattern_application)?;
+
+        if self.at(TokenData::Bar) {
+            let bar = self.bump();
+            let right = self.pattern()?;
+
+            Ok(Box::new(Spanned {
+                span: left.span.clone().mix(right.span.clone()),
+                data: PatternKind::Or(PatOr { left, bar, right }),
+            }))
+        } else {
+            Ok(left)
+        }
     }
 }
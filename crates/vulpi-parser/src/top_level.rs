@@ -1,8 +1,36 @@
+use vulpi_report::Diagnostic;
 use vulpi_syntax::{concrete::top_level::*, tokens::TokenData};
 
-use crate::{Parser, Result};
+use crate::{error::ParserError, Parser, Result};
 
 impl<'a> Parser<'a> {
+    pub fn decl_attribute(&mut self) -> Result<DeclAttribute> {
+        let hash_bracket = self.expect(TokenData::HashBracket)?;
+        let name = self.lower()?;
+
+        let args = if self.at(TokenData::LPar) {
+            Some(self.parenthesis(|this| this.sep_by(TokenData::Comma, Self::lower))?)
+        } else {
+            None
+        };
+
+        let message = if self.at(TokenData::String) {
+            Some(self.bump())
+        } else {
+            None
+        };
+
+        let right_bracket = self.expect(TokenData::RBracket)?;
+
+        Ok(DeclAttribute {
+            hash_bracket,
+            name,
+            args,
+            message,
+            right_bracket,
+        })
+    }
+
     pub fn binder(&mut self) -> Result<Binder> {
         let left_paren = self.expect(TokenData::LPar)?;
         let pattern = self.pattern()?;
@@ -63,7 +91,16 @@ impl<'a> Parser<'a> {
         Ok(LetCase { pipe, arm })
     }
 
-    pub fn let_decl(&mut self, visibility: Visibility) -> Result<LetDecl> {
+    pub fn let_decl(
+        &mut self,
+        attributes: Vec<DeclAttribute>,
+        visibility: Visibility,
+    ) -> Result<LetDecl> {
+        let start = match &visibility {
+            Visibility::Public(token) => token.value.span.clone(),
+            Visibility::Private => self.span(),
+        };
+
         let signature = self.let_signature(visibility)?;
 
         let body = if self.at(TokenData::Equal) {
@@ -76,10 +113,51 @@ impl<'a> Parser<'a> {
             self.unexpected()?
         };
 
-        Ok(LetDecl { signature, body })
+        Ok(LetDecl {
+            attributes,
+            signature,
+            body,
+            span: start.mix(self.last_pos.clone()),
+        })
     }
 
-    fn trait_decl(&mut self, visibility: Visibility) -> Result<TraitDecl> {
+    /// A let declaration whose name is a pattern, like `let (a, b) = pair`.
+    pub fn pattern_decl(&mut self, visibility: Visibility) -> Result<PatternDecl> {
+        let start = match &visibility {
+            Visibility::Public(token) => token.value.span.clone(),
+            Visibility::Private => self.span(),
+        };
+
+        let let_ = self.expect(TokenData::Let)?;
+        let pattern = self.pattern()?;
+
+        let ret = if self.at(TokenData::Colon) {
+            let colon = self.bump();
+            let typ = self.typ()?;
+            Some((colon, typ))
+        } else {
+            None
+        };
+
+        let eq = self.expect(TokenData::Equal)?;
+        let body = self.expr()?;
+
+        Ok(PatternDecl {
+            visibility,
+            let_,
+            pattern,
+            ret,
+            eq,
+            body,
+            span: start.mix(self.last_pos.clone()),
+        })
+    }
+
+    fn trait_decl(
+        &mut self,
+        attributes: Vec<DeclAttribute>,
+        visibility: Visibility,
+    ) -> Result<TraitDecl> {
         let trait_ = self.expect(TokenData::Trait)?;
         let supers = self.many(Self::trait_binder)?;
         let name = self.upper()?;
@@ -87,6 +165,7 @@ impl<'a> Parser<'a> {
         let where_ = self.expect(TokenData::Where)?;
         let body = self.block(|ctx| ctx.let_signature(Visibility::Private))?;
         Ok(TraitDecl {
+            attributes,
             visibility,
             trait_,
             supers,
@@ -103,7 +182,7 @@ impl<'a> Parser<'a> {
         let name = self.path_upper()?;
         let types = self.many(Self::type_atom)?;
         let where_ = self.expect(TokenData::Where)?;
-        let body = self.block(|ctx| ctx.let_decl(Visibility::Private))?;
+        let body = self.block(|ctx| ctx.let_decl(Vec::new(), Visibility::Private))?;
         Ok(TraitImpl {
             impl_,
             supers,
@@ -137,6 +216,7 @@ impl<'a> Parser<'a> {
 
     pub fn constructor_decl(&mut self) -> Result<Constructor> {
         let pipe = self.expect(TokenData::Bar)?;
+        let attributes = self.many(Self::decl_attribute)?;
         let name = self.upper()?;
         let args = self.many(Self::type_atom)?;
 
@@ -150,6 +230,7 @@ impl<'a> Parser<'a> {
 
         Ok(Constructor {
             pipe,
+            attributes,
             name,
             args,
             typ,
@@ -177,10 +258,7 @@ impl<'a> Parser<'a> {
     pub fn command_decl(&mut self) -> Result<CommandDecl> {
         let command = self.expect(TokenData::Command)?;
         let name = self.expect(TokenData::String)?;
-        Ok(CommandDecl {
-            command: command.symbol(),
-            name: name.symbol(),
-        })
+        Ok(CommandDecl { command, name })
     }
 
     pub fn record_decl(&mut self) -> Result<RecordDecl> {
@@ -203,8 +281,16 @@ impl<'a> Parser<'a> {
         }
     }
 
-    pub fn type_decl(&mut self, visibility: Visibility) -> Result<TypeDecl> {
-        let type_ = self.expect(TokenData::Type)?;
+    pub fn type_decl(
+        &mut self,
+        attributes: Vec<DeclAttribute>,
+        visibility: Visibility,
+    ) -> Result<TypeDecl> {
+        let type_ = if self.at(TokenData::Newtype) {
+            self.bump()
+        } else {
+            self.expect(TokenData::Type)?
+        };
         let name = self.upper()?;
         let binders = self.many(Self::type_binder)?;
 
@@ -217,6 +303,7 @@ impl<'a> Parser<'a> {
         };
 
         Ok(TypeDecl {
+            attributes,
             type_,
             name,
             binders,
@@ -225,6 +312,67 @@ impl<'a> Parser<'a> {
         })
     }
 
+    pub fn effect_field(&mut self) -> Result<EffectField> {
+        let visibility = self.visibility()?;
+
+        let kind = if self.at_any(&[TokenData::Ctl, TokenData::Fun, TokenData::Once]) {
+            Some(self.bump())
+        } else {
+            None
+        };
+
+        let name = self.lower()?;
+        let args = self.many(Self::type_atom)?;
+        let colon = self.expect(TokenData::Colon)?;
+        let ret = self.typ()?;
+
+        let default = if self.at(TokenData::Equal) {
+            let eq = self.bump();
+            Some((eq, self.expr()?))
+        } else {
+            None
+        };
+
+        Ok(EffectField {
+            visibility,
+            kind,
+            name,
+            args,
+            colon,
+            ret,
+            default,
+        })
+    }
+
+    pub fn effect_decl(
+        &mut self,
+        attributes: Vec<DeclAttribute>,
+        visibility: Visibility,
+    ) -> Result<EffectDecl> {
+        let once = if self.at(TokenData::Once) {
+            Some(self.bump())
+        } else {
+            None
+        };
+
+        let effect = self.expect(TokenData::Effect)?;
+        let name = self.upper()?;
+        let binders = self.many(Self::type_binder)?;
+        let where_ = self.expect(TokenData::Where)?;
+        let fields = self.block(Self::effect_field)?;
+
+        Ok(EffectDecl {
+            attributes,
+            visibility,
+            once,
+            effect,
+            name,
+            binders,
+            where_,
+            fields,
+        })
+    }
+
     pub fn use_alias(&mut self) -> Result<UseAlias> {
         let as_ = self.expect(TokenData::As)?;
         let alias = self.upper()?;
@@ -257,7 +405,11 @@ impl<'a> Parser<'a> {
         })
     }
 
-    pub fn mod_decl(&mut self, visibility: Visibility) -> Result<ModuleDecl> {
+    pub fn mod_decl(
+        &mut self,
+        attributes: Vec<DeclAttribute>,
+        visibility: Visibility,
+    ) -> Result<ModuleDecl> {
         let mod_ = self.expect(TokenData::Mod)?;
         let name = self.upper()?;
 
@@ -275,6 +427,7 @@ impl<'a> Parser<'a> {
         };
 
         Ok(ModuleDecl {
+            attributes,
             visibility,
             mod_,
             name,
@@ -282,43 +435,164 @@ impl<'a> Parser<'a> {
         })
     }
 
-    pub fn external_decl(&mut self, visibility: Visibility) -> Result<ExtDecl> {
+    pub fn when_arm(&mut self) -> Result<WhenArm> {
+        let values = self.sep_by(TokenData::Bar, |this| this.expect(TokenData::String))?;
+        let where_ = self.expect(TokenData::Where)?;
+        let top_levels = self.block(Self::top_level)?;
+
+        Ok(WhenArm {
+            values,
+            where_,
+            top_levels,
+        })
+    }
+
+    pub fn when_decl(&mut self) -> Result<WhenDecl> {
+        let when = self.expect(TokenData::When)?;
+        let condition = self.lower()?;
+        let is = self.expect(TokenData::Is)?;
+        let arms = self.block(Self::when_arm)?;
+
+        Ok(WhenDecl {
+            when,
+            condition,
+            is,
+            arms,
+        })
+    }
+
+    pub fn test_decl(&mut self) -> Result<TestDecl> {
+        let test = self.expect(TokenData::Test)?;
+        let description = self.expect(TokenData::String)?;
+        let equal = self.expect(TokenData::Equal)?;
+        let body = self.expr()?;
+
+        Ok(TestDecl {
+            test,
+            description,
+            equal,
+            body,
+        })
+    }
+
+    pub fn external_decl(
+        &mut self,
+        attributes: Vec<DeclAttribute>,
+        visibility: Visibility,
+    ) -> Result<ExtDecl> {
         let external = self.expect(TokenData::External)?;
+
+        let convention = if self.at(TokenData::String) {
+            Some(self.bump())
+        } else {
+            None
+        };
+
+        let let_ = if self.at(TokenData::Let) {
+            Some(self.bump())
+        } else {
+            None
+        };
+
         let name = self.lower()?;
         let colon = self.expect(TokenData::Colon)?;
         let typ = self.typ()?;
+
+        let effect = if self.at(TokenData::Slash) {
+            let slash = self.bump();
+            Some((slash, self.path_upper()?))
+        } else {
+            None
+        };
+
         let equal = self.expect(TokenData::Equal)?;
         let str = self.expect(TokenData::String)?;
 
         Ok(ExtDecl {
+            attributes,
             visibility,
             external,
+            convention,
+            let_,
             name,
             colon,
             typ,
+            effect,
             equal,
             str,
         })
     }
 
     pub fn top_level(&mut self) -> Result<TopLevel> {
+        self.nested(Self::top_level_raw)
+    }
+
+    fn top_level_raw(&mut self) -> Result<TopLevel> {
+        let attrs = self.many(Self::decl_attribute)?;
+
+        if !attrs.is_empty() && self.at(TokenData::Sep) {
+            self.bump();
+        }
+
         let vis = self.visibility()?;
+
+        let attributed = match self.token() {
+            TokenData::Let => !self.at_pattern_decl(),
+            TokenData::Type
+            | TokenData::Newtype
+            | TokenData::Effect
+            | TokenData::Once
+            | TokenData::Trait
+            | TokenData::Mod
+            | TokenData::External => true,
+            _ => false,
+        };
+
+        if let (false, Some(first), Some(last)) = (attributed, attrs.first(), attrs.last()) {
+            let span = first.hash_bracket.value.span.clone();
+            let err =
+                ParserError::MisplacedAttribute(span.mix(last.right_bracket.value.span.clone()));
+            self.reporter.report(Diagnostic::new(err));
+        }
+
         match self.token() {
-            TokenData::Let => self.let_decl(vis).map(Box::new).map(TopLevel::Let),
-            TokenData::Type => self.type_decl(vis).map(Box::new).map(TopLevel::Type),
+            TokenData::Let if self.at_pattern_decl() => {
+                self.pattern_decl(vis).map(Box::new).map(TopLevel::Pattern)
+            }
+            TokenData::Let => self.let_decl(attrs, vis).map(Box::new).map(TopLevel::Let),
+            TokenData::Type | TokenData::Newtype => {
+                self.type_decl(attrs, vis).map(Box::new).map(TopLevel::Type)
+            }
+            TokenData::Effect | TokenData::Once => self
+                .effect_decl(attrs, vis)
+                .map(Box::new)
+                .map(TopLevel::Effect),
             TokenData::Use => self.use_decl(vis).map(Box::new).map(TopLevel::Use),
             TokenData::Impl => self.trait_impl().map(Box::new).map(TopLevel::Impl),
-            TokenData::Trait => self.trait_decl(vis).map(Box::new).map(TopLevel::Trait),
-            TokenData::Mod => self.mod_decl(vis).map(Box::new).map(TopLevel::Module),
+            TokenData::Trait => self
+                .trait_decl(attrs, vis)
+                .map(Box::new)
+                .map(TopLevel::Trait),
+            TokenData::Mod => self
+                .mod_decl(attrs, vis)
+                .map(Box::new)
+                .map(TopLevel::Module),
             TokenData::Command => self.command_decl().map(Box::new).map(TopLevel::Command),
+            TokenData::When => self.when_decl().map(Box::new).map(TopLevel::When),
+            TokenData::Test => self.test_decl().map(Box::new).map(TopLevel::Test),
             TokenData::External => self
-                .external_decl(vis)
+                .external_decl(attrs, vis)
                 .map(Box::new)
                 .map(TopLevel::External),
             _ => self.unexpected(),
         }
     }
 
+    /// Checks if the current `let` binds a pattern instead of a name.
+    fn at_pattern_decl(&self) -> bool {
+        self.at(TokenData::Let) && !self.then(TokenData::LowerIdent)
+    }
+
     pub fn program(&mut self) -> Program {
         let mut top_levels = vec![];
 
@@ -6,7 +6,7 @@ use vulpi_syntax::concrete::{
 };
 use vulpi_syntax::tokens::TokenData;
 
-use crate::{Parser, Result};
+use crate::{error::ParserError, Parser, Result};
 
 impl<'a> Parser<'a> {
     fn kind_atom_raw(&mut self) -> Result<KindType> {
@@ -39,7 +39,7 @@ impl<'a> Parser<'a> {
     }
 
     pub fn kind(&mut self) -> Result<Box<Kind>> {
-        self.kind_arrow()
+        self.nested(Self::kind_arrow)
     }
 
     fn type_variable(&mut self) -> Result<Lower> {
@@ -62,20 +62,21 @@ impl<'a> Parser<'a> {
 
     fn type_atom_raw(&mut self) -> Result<TypeKind> {
         match self.token() {
+            TokenData::LowerIdent if self.at_group_end() => self.unexpected(),
+            TokenData::LowerIdent if self.at_field(TokenData::Colon) => self.unexpected(),
             TokenData::LowerIdent => self.type_variable().map(TypeKind::TypeVariable),
             TokenData::UpperIdent => self.path(Self::upper).map(TypeKind::Type),
             TokenData::Unit => Ok(TypeKind::Unit(self.bump())),
             TokenData::LPar => {
                 let exprs = self.parenthesis(|this| this.sep_by(TokenData::Comma, Self::typ))?;
 
-                if exprs.data.is_empty() {
-                    todo!()
-                } else if exprs.data.len() == 1 {
-                    Ok(TypeKind::Parenthesis(
-                        exprs.map(|x| x.into_iter().next().unwrap()),
-                    ))
-                } else {
-                    Ok(TypeKind::Tuple(exprs))
+                match exprs.data.len() {
+                    0 => Err(ParserError::UnexpectedToken(
+                        Box::new(exprs.right.clone()),
+                        exprs.right.value.span.clone(),
+                    )),
+                    1 => Ok(TypeKind::Parenthesis(exprs.map(|mut x| x.remove(0)))),
+                    _ => Ok(TypeKind::Tuple(exprs)),
                 }
             }
 
@@ -95,11 +96,10 @@ impl<'a> Parser<'a> {
         if args.is_empty() {
             Ok(func)
         } else {
-            let start = func.span.clone();
-            let end = args.last().unwrap().span.clone();
+            let span = args.iter().fold(func.span.clone(), |x, y| x.mix(y.span.clone()));
 
             Ok(Box::new(Spanned {
-                span: start.mix(end),
+                span,
                 data: TypeKind::Application(TypeApplication { func, args }),
             }))
         }
@@ -111,7 +111,7 @@ impl<'a> Parser<'a> {
         if self.at(TokenData::RightArrow) {
             let arrow = self.bump();
 
-            let right = self.type_arrow()?;
+            let right = self.nested(Self::type_arrow)?;
 
             Ok(Box::new(Spanned {
                 span: left.span.clone().mix(right.span.clone()),
@@ -122,8 +122,7 @@ impl<'a> Parser<'a> {
         }
     }
 
-    /// Parses types
-    pub fn typ(&mut self) -> Result<Box<Type>> {
+    fn typ_raw(&mut self) -> Result<Box<Type>> {
         match self.token() {
             TokenData::Forall => self
                 .spanned(|x| x.type_forall().map(TypeKind::Forall))
@@ -131,4 +130,9 @@ impl<'a> Parser<'a> {
             _ => self.type_arrow(),
         }
     }
+
+    /// Parses types
+    pub fn typ(&mut self) -> Result<Box<Type>> {
+        self.nested(Self::typ_raw)
+    }
 }
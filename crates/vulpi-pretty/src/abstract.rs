@@ -0,0 +1,956 @@
+//! Printing of the abstract tree as source. Names that were resolved are printed with their whole
+//! paths, and the sugar that the resolver removes is printed as what it turned into, like lists as
+//! applications of their constructors.
+
+use vulpi_intern::Symbol;
+use vulpi_location::Span;
+use vulpi_syntax::r#abstract::*;
+
+use crate::{Doc, Pretty};
+
+const INDENT: usize = 2;
+
+/// How tightly an expression is bound, from the forms that extend as far as they can, like lambdas,
+/// to the atoms. An expression in a place that binds tighter than it is put inside of parenthesis.
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum Prec {
+    Open,
+    Annotation,
+    Binary,
+    Application,
+    Atom,
+}
+
+fn parens(doc: Doc) -> Doc {
+    Doc::text("(") + doc + Doc::text(")")
+}
+
+fn name(symbol: &Symbol) -> Doc {
+    Doc::text(symbol.get())
+}
+
+fn qualified(qualified: &Qualified) -> Doc {
+    let path = qualified.path.get();
+
+    if path.is_empty() {
+        name(&qualified.name)
+    } else {
+        Doc::text(qualified.to_string())
+    }
+}
+
+fn visibility(visibility: &Visibility) -> Doc {
+    match visibility {
+        Visibility::Public => Doc::text("pub "),
+        Visibility::Super | Visibility::Private => Doc::Nil,
+    }
+}
+
+/// A string or a character between quotes, with the characters that the lexer escapes escaped.
+fn quote(text: &str, quote: char) -> Doc {
+    let mut result = String::from(quote);
+
+    for char in text.chars() {
+        match char {
+            '\n' => result.push_str("\\n"),
+            '\r' => result.push_str("\\r"),
+            '\t' => result.push_str("\\t"),
+            '\0' => result.push_str("\\0"),
+            '\\' | '"' | '\'' if char == '\\' || char == quote => {
+                result.push('\\');
+                result.push(char)
+            }
+            char => result.push(char),
+        }
+    }
+
+    result.push(quote);
+    Doc::Text(result)
+}
+
+/// Items between delimiters, that break with one item in each line.
+fn delimited(open: &str, items: Vec<Doc>, close: &str) -> Doc {
+    let items = Doc::join(items, Doc::text(",") + Doc::line());
+
+    (Doc::text(open) + (Doc::softline() + items).nest(INDENT) + Doc::softline() + Doc::text(close))
+        .group()
+        .nest(INDENT)
+}
+
+/// Lines that are a block of the layout, like the statements of a `do`.
+fn block(lines: Vec<Doc>) -> Doc {
+    Doc::concat(lines.into_iter().map(|x| Doc::hardline() + x)).nest(INDENT)
+}
+
+// Kinds
+
+impl Pretty for Kind {
+    fn pretty(&self) -> Doc {
+        match &self.data {
+            KindType::Star => Doc::text("*"),
+            KindType::Constraint => Doc::text("Constraint"),
+            KindType::Arrow(left, right) => {
+                let left = match left.data {
+                    KindType::Arrow(..) => parens(left.pretty()),
+                    _ => left.pretty(),
+                };
+
+                left + Doc::text(" -> ") + right.pretty()
+            }
+            KindType::Error => Doc::text("<error>"),
+        }
+    }
+}
+
+// Types
+
+fn type_prec(typ: &Type) -> Prec {
+    match &typ.data {
+        TypeKind::Forall(_) => Prec::Open,
+        TypeKind::Arrow(_) => Prec::Binary,
+        TypeKind::Application(_) => Prec::Application,
+        _ => Prec::Atom,
+    }
+}
+
+fn typ(typ: &Type, prec: Prec) -> Doc {
+    if type_prec(typ) < prec {
+        parens(typ.pretty())
+    } else {
+        typ.pretty()
+    }
+}
+
+fn type_binder(binder: &TypeBinder) -> Doc {
+    match binder {
+        TypeBinder::Implicit(binder) => name(binder),
+        TypeBinder::Explicit(binder, kind) => {
+            parens(name(binder) + Doc::text(" : ") + kind.pretty())
+        }
+    }
+}
+
+fn type_binders(binders: &[TypeBinder]) -> Doc {
+    Doc::concat(binders.iter().map(|x| Doc::text(" ") + type_binder(x)))
+}
+
+impl Pretty for Type {
+    fn pretty(&self) -> Doc {
+        match &self.data {
+            TypeKind::Arrow(pi) => {
+                let left = typ(&pi.left, Prec::Application);
+                let right = typ(&pi.right, Prec::Binary);
+                (left + Doc::text(" ->") + Doc::line() + right).group()
+            }
+            TypeKind::Tuple(types) => {
+                let types = types.iter().map(|x| x.pretty()).collect();
+                delimited("(", types, ")")
+            }
+            TypeKind::Application(app) => {
+                let args = app.args.iter().map(|x| Doc::line() + typ(x, Prec::Atom));
+
+                (typ(&app.func, Prec::Atom) + Doc::concat(args).nest(INDENT)).group()
+            }
+            TypeKind::Forall(forall) => {
+                let binders = Doc::join(forall.params.iter().map(type_binder), Doc::text(" "));
+                let body = (Doc::line() + forall.body.pretty()).nest(INDENT);
+                (Doc::text("forall ") + binders + Doc::text(".") + body).group()
+            }
+            TypeKind::TypeVariable(variable) => name(variable),
+            TypeKind::Type(name) => qualified(name),
+            TypeKind::Unit => Doc::text("()"),
+            TypeKind::Error => Doc::text("<error>"),
+        }
+    }
+}
+
+// Literals
+
+impl Pretty for Literal {
+    fn pretty(&self) -> Doc {
+        match &self.data {
+            LiteralKind::String(string) => quote(&string.get(), '"'),
+            LiteralKind::Char(char) => quote(&char.get(), '\''),
+            LiteralKind::Integer(number) | LiteralKind::Float(number) => name(number),
+            LiteralKind::Unit => Doc::text("()"),
+        }
+    }
+}
+
+// Patterns
+
+fn pattern(pat: &Pattern, prec: Prec) -> Doc {
+    match &pat.data {
+        PatternKind::Application(app) if !app.args.is_empty() && prec > Prec::Application => {
+            parens(pat.pretty())
+        }
+        _ => pat.pretty(),
+    }
+}
+
+impl Pretty for Pattern {
+    fn pretty(&self) -> Doc {
+        match &self.data {
+            PatternKind::Wildcard => Doc::text("_"),
+            PatternKind::Variable(variable) => name(variable),
+            PatternKind::Literal(literal) => literal.pretty(),
+            PatternKind::Range(range) => {
+                range.start.pretty() + Doc::text("..") + range.end.pretty()
+            }
+            PatternKind::Tuple(pats) => {
+                let pats = pats.iter().map(|x| x.pretty()).collect();
+                delimited("(", pats, ")")
+            }
+            PatternKind::Ascription(asc) => {
+                parens(asc.pat.pretty() + Doc::text(" : ") + asc.typ.pretty())
+            }
+            PatternKind::As(as_) => {
+                name(&as_.name) + Doc::text("@") + pattern(&as_.pattern, Prec::Atom)
+            }
+            PatternKind::Or(or) => parens(or.left.pretty() + Doc::text(" | ") + or.right.pretty()),
+            PatternKind::Application(app) => {
+                let args = app
+                    .args
+                    .iter()
+                    .map(|x| Doc::text(" ") + pattern(x, Prec::Atom));
+                qualified(&app.func) + Doc::concat(args)
+            }
+            PatternKind::Effect(eff) => {
+                let args = eff
+                    .args
+                    .iter()
+                    .map(|x| Doc::text(" ") + pattern(x, Prec::Atom));
+
+                let cont = match &eff.cont {
+                    Some(cont) => Doc::text(" -> ") + name(cont),
+                    None => Doc::Nil,
+                };
+
+                Doc::text("{ ") + qualified(&eff.func) + Doc::concat(args) + cont + Doc::text(" }")
+            }
+            PatternKind::Return(pat) => Doc::text("return ") + pattern(pat, Prec::Atom),
+            PatternKind::Error => Doc::text("<error>"),
+        }
+    }
+}
+
+// Expressions
+
+/// The operators that are desugared to applications of the functions of the prelude, with their
+/// groups of precedence.
+fn operator(expr: &Expr) -> Option<(&'static str, u8)> {
+    let ExprKind::Application(ApplicationExpr {
+        app: AppKind::Infix,
+        func,
+        args,
+    }) = &expr.data
+    else {
+        return None;
+    };
+
+    let ExprKind::Function(name) = &func.data else {
+        return None;
+    };
+
+    if args.len() != 2 {
+        return None;
+    }
+
+    let operator = match name.name.get().as_str() {
+        "add" => ("+", 1),
+        "sub" => ("-", 1),
+        "mul" => ("*", 3),
+        "div" => ("/", 3),
+        "rem" => ("%", 3),
+        "eq" => ("==", 5),
+        "neq" => ("!=", 5),
+        "lt" => ("<", 7),
+        "le" => ("<=", 7),
+        "gt" => (">", 7),
+        "ge" => (">=", 7),
+        "or" => ("||", 9),
+        "and" => ("&&", 9),
+        "concat" => ("++", 9),
+        "pipe" => ("|>", 0),
+        _ => return None,
+    };
+
+    Some(operator)
+}
+
+fn expr_prec(expr: &Expr) -> Prec {
+    match &expr.data {
+        ExprKind::Lambda(_)
+        | ExprKind::Let(_)
+        | ExprKind::LetGroup(_)
+        | ExprKind::When(_)
+        | ExprKind::Cases(_)
+        | ExprKind::Handler(_)
+        | ExprKind::Mask(_)
+        | ExprKind::Do(_) => Prec::Open,
+        ExprKind::Annotation(_) | ExprKind::RecordUpdate(_) => Prec::Annotation,
+        ExprKind::Application(app) if app.args.is_empty() => expr_prec(&app.func),
+        ExprKind::Application(_) => match operator(expr) {
+            Some((_, 0)) => Prec::Open,
+            Some(_) => Prec::Binary,
+            None => Prec::Application,
+        },
+        ExprKind::Perform(_) => Prec::Application,
+        _ => Prec::Atom,
+    }
+}
+
+fn expr(expr: &Expr, prec: Prec) -> Doc {
+    if expr_prec(expr) < prec {
+        parens(expr.pretty())
+    } else {
+        expr.pretty()
+    }
+}
+
+/// The expressions that start a block of the layout, that are better after the `=` or the `=>`
+/// that comes before them than in the next line.
+fn is_block(expr: &Expr) -> bool {
+    match &expr.data {
+        ExprKind::Do(_) | ExprKind::When(_) | ExprKind::Cases(_) => true,
+        ExprKind::LetGroup(group) => as_loop(group).is_some(),
+        _ => false,
+    }
+}
+
+/// A `while` or a `for` that the resolver turned into a function named `loop$` that calls itself.
+enum Loop<'a> {
+    While(&'a Expr, &'a Block),
+    For(&'a Pattern, &'a Expr, &'a Block),
+}
+
+impl Pretty for Loop<'_> {
+    fn pretty(&self) -> Doc {
+        let (head, body) = match self {
+            Loop::While(condition, body) => (Doc::text("while ") + condition.pretty(), body),
+            Loop::For(pat, list, body) => {
+                let head = Doc::text("for ")
+                    + pattern(pat, Prec::Application)
+                    + Doc::text(" in ")
+                    + list.pretty();
+
+                (head, body)
+            }
+        };
+
+        head + Doc::text(" do") + block(body.sttms.iter().map(statement).collect())
+    }
+}
+
+/// The loop that a let group was written as, if it has the shape that the resolver gives to them.
+///
+/// ```text
+/// let loop$ param = when scrutinee is
+///   arm => do { statements ; loop$ next }
+///   _   => ()
+/// in loop$ start
+/// ```
+fn as_loop<'a>(group: &'a LetGroupExpr) -> Option<Loop<'a>> {
+    let [binding] = group.bindings.as_slice() else {
+        return None;
+    };
+
+    if binding.name.get() != "loop$" {
+        return None;
+    }
+
+    let ExprKind::Lambda(lambda) = &binding.body.data else {
+        return None;
+    };
+
+    let ExprKind::When(when) = &lambda.body.data else {
+        return None;
+    };
+
+    let ([scrutinee], [arm, _]) = (when.scrutinee.as_slice(), when.arms.as_slice()) else {
+        return None;
+    };
+
+    let (ExprKind::Do(statements), ExprKind::Application(start)) =
+        (&arm.expr.data, &group.value.data)
+    else {
+        return None;
+    };
+
+    let body = |sttm: &'a Sttm| match &sttm.data {
+        SttmKind::Expr(expr) => match &expr.data {
+            ExprKind::Do(block) => Some(block),
+            _ => None,
+        },
+        _ => None,
+    };
+
+    match (&lambda.param.data, statements.sttms.as_slice()) {
+        (PatternKind::Wildcard, [block, _]) => Some(Loop::While(scrutinee, body(block)?)),
+        (PatternKind::Variable(_), [bind, block, _]) => {
+            let SttmKind::Let(bind) = &bind.data else {
+                return None;
+            };
+
+            Some(Loop::For(&bind.pat, start.args.first()?, body(block)?))
+        }
+        _ => None,
+    }
+}
+
+/// The expression after an `=` or an `=>`, in the same line if it fits.
+fn body(body: &Expr) -> Doc {
+    if is_block(body) {
+        Doc::text(" ") + body.pretty()
+    } else {
+        (Doc::line() + body.pretty()).nest(INDENT).group()
+    }
+}
+
+/// An operand of an operator, that is put inside of parenthesis when it's an operator that the
+/// parser wouldn't associate in the same way without them. Pipes and most of the operators are
+/// associated to the left, while `||`, `&&` and `++` are associated to the right.
+fn operand(operand: &Expr, group: u8, left: bool) -> Doc {
+    let Some((_, inner)) = operator(operand) else {
+        let prec = if group == 0 {
+            Prec::Annotation
+        } else {
+            Prec::Application
+        };
+        return expr(operand, prec);
+    };
+
+    let associates = match (group, inner) {
+        (0, 0) => left,
+        (0, _) => true,
+        (9, 9) => !left,
+        (9, _) | (_, 9) => false,
+        _ => inner > group || (inner == group && left),
+    };
+
+    if associates {
+        operand.pretty()
+    } else {
+        parens(operand.pretty())
+    }
+}
+
+/// The `in` of a `let` and the expression after it. When it breaks, the line is indented, because
+/// a line in the column of a block would be taken as the next line of the block.
+/// The parameters of the lambdas that are nested right inside of the expression, and the body of
+/// the innermost one.
+fn lambdas(mut value: &Expr) -> (Vec<&Pattern>, &Expr) {
+    let mut params = Vec::new();
+
+    while let ExprKind::Lambda(lambda) = &value.data {
+        params.push(&lambda.param);
+        value = &lambda.body;
+    }
+
+    (params, value)
+}
+
+fn params(params: Vec<&Pattern>) -> Doc {
+    // A constructor takes the parameters after it as its arguments.
+    let last = params.len().saturating_sub(1);
+    let params = params
+        .into_iter()
+        .enumerate()
+        .map(|(i, param)| match param.data {
+            PatternKind::Application(_) if i < last => parens(param.pretty()),
+            _ => pattern(param, Prec::Atom),
+        });
+
+    Doc::join(params, Doc::text(" "))
+}
+
+fn in_(value: &Expr) -> Doc {
+    (Doc::line() + Doc::text("in ") + value.pretty()).nest(INDENT)
+}
+
+fn arm(arm: &PatternArm) -> Doc {
+    let patterns = Doc::join(
+        arm.patterns.iter().map(|x| pattern(x, Prec::Application)),
+        Doc::text(", "),
+    );
+
+    let guard = match &arm.guard {
+        Some(guard) => Doc::text(" if ") + guard.pretty(),
+        None => Doc::Nil,
+    };
+
+    patterns + guard + Doc::text(" =>") + body(&arm.expr)
+}
+
+fn fields(fields: &[(Span, Symbol, Expr)]) -> Doc {
+    let fields = fields
+        .iter()
+        .map(|(_, field, value)| name(field) + Doc::text(" = ") + value.pretty())
+        .collect();
+
+    delimited("{ ", fields, " }")
+}
+
+fn statement(sttm: &Sttm) -> Doc {
+    match &sttm.data {
+        SttmKind::Let(sttm) => {
+            Doc::text("let ")
+                + pattern(&sttm.pat, Prec::Application)
+                + Doc::text(" =")
+                + body(&sttm.expr)
+        }
+        // A `let` at the start of a statement is a statement, so the expression needs parenthesis.
+        SttmKind::Expr(value) => match &value.data {
+            ExprKind::Let(_) => parens(value.pretty()),
+            ExprKind::LetGroup(group) if as_loop(group).is_none() => parens(value.pretty()),
+            _ => value.pretty(),
+        },
+        SttmKind::Error => Doc::text("<error>"),
+    }
+}
+
+impl Pretty for Expr {
+    fn pretty(&self) -> Doc {
+        match &self.data {
+            ExprKind::Lambda(_) => {
+                let (patterns, value) = lambdas(self);
+                (Doc::text("\\") + params(patterns) + Doc::text(" =>") + body(value)).group()
+            }
+            ExprKind::Application(app) => {
+                if let Some((op, group)) = operator(self) {
+                    let left = operand(&app.args[0], group, true);
+                    let right = operand(&app.args[1], group, false);
+                    let right = (Doc::line() + right).nest(INDENT);
+                    return (left + Doc::text(" ") + Doc::text(op) + right).group();
+                }
+
+                let args = app.args.iter().map(|x| Doc::line() + expr(x, Prec::Atom));
+
+                (expr(&app.func, Prec::Atom) + Doc::concat(args).nest(INDENT)).group()
+            }
+            ExprKind::Perform(perform) => {
+                let args = perform
+                    .args
+                    .iter()
+                    .map(|x| Doc::line() + expr(x, Prec::Atom));
+
+                (Doc::text("perform ")
+                    + qualified(&perform.operation)
+                    + Doc::concat(args).nest(INDENT))
+                .group()
+            }
+            ExprKind::Variable(variable) => name(variable),
+            ExprKind::Constructor(name) | ExprKind::Function(name) => qualified(name),
+            ExprKind::Projection(projection) => {
+                // The dot after a number would be lexed as a part of it, and the one after a path
+                // would make the field a part of the path.
+                let value = match projection.expr.data {
+                    ExprKind::Projection(_)
+                    | ExprKind::Literal(_)
+                    | ExprKind::Constructor(_)
+                    | ExprKind::Function(_) => parens(projection.expr.pretty()),
+                    _ => expr(&projection.expr, Prec::Atom),
+                };
+
+                value + Doc::text(".") + name(&projection.field)
+            }
+            ExprKind::Let(let_) => {
+                let value = Doc::text("let ")
+                    + pattern(&let_.pattern, Prec::Application)
+                    + Doc::text(" =")
+                    + body(&let_.body);
+
+                (value + in_(&let_.value)).group()
+            }
+            ExprKind::LetGroup(group) => {
+                if let Some(loop_) = as_loop(group) {
+                    return loop_.pretty();
+                }
+
+                let bindings = group.bindings.iter().map(|binding| {
+                    let (patterns, value) = lambdas(&binding.body);
+
+                    let patterns = if patterns.is_empty() {
+                        Doc::Nil
+                    } else {
+                        Doc::text(" ") + params(patterns)
+                    };
+
+                    // The blocks of a binding are indented more than the `and` after it.
+                    (name(&binding.name) + patterns + Doc::text(" =") + body(value)).nest(INDENT)
+                });
+
+                let ands = (Doc::line() + Doc::text("and ")).nest(INDENT);
+                let value = Doc::text("let ") + Doc::join(bindings, ands);
+
+                (value + in_(&group.value)).group()
+            }
+            ExprKind::When(when) => {
+                let scrutinee =
+                    Doc::join(when.scrutinee.iter().map(|x| x.pretty()), Doc::text(", "));
+                Doc::text("when ")
+                    + scrutinee
+                    + Doc::text(" is")
+                    + block(when.arms.iter().map(arm).collect())
+            }
+            ExprKind::Cases(cases) => {
+                Doc::text("cases") + block(cases.arms.iter().map(arm).collect())
+            }
+            ExprKind::Handler(handler) => match &handler.name {
+                Some(handler_name) => {
+                    let value = Doc::text("handle ")
+                        + name(handler_name)
+                        + Doc::text(" =")
+                        + body(&handler.handler);
+
+                    (value + in_(&handler.expr)).group()
+                }
+                None => {
+                    // The clauses are indented less than the blocks of the handled expression, so
+                    // they're not a part of them.
+                    let value = Doc::text("handle ") + handler.expr.pretty().nest(INDENT);
+
+                    // The `finally` would be taken by a handler that ends the expression, but not
+                    // by a block, that ends in the line before it.
+                    let clauses = if handler.finally.is_some() && !is_block(&handler.handler) {
+                        expr(&handler.handler, Prec::Annotation)
+                    } else {
+                        handler.handler.pretty()
+                    };
+
+                    let mut with = Doc::line() + Doc::text("with ") + clauses;
+
+                    if let Some(finally) = &handler.finally {
+                        with = with + Doc::line() + Doc::text("finally ") + finally.pretty();
+                    }
+
+                    (value + with.nest(INDENT)).group()
+                }
+            },
+            ExprKind::Mask(mask) => {
+                Doc::text("mask<") + qualified(&mask.effect) + Doc::text("> ") + mask.expr.pretty()
+            }
+            ExprKind::Operation(operation) => {
+                name(&operation.handler) + Doc::text(".") + name(&operation.name)
+            }
+            ExprKind::Do(block_) => {
+                Doc::text("do") + block(block_.sttms.iter().map(statement).collect())
+            }
+            ExprKind::Literal(literal) => literal.pretty(),
+            ExprKind::Annotation(annotation) => {
+                let value = expr(&annotation.expr, Prec::Binary);
+                (value + Doc::text(" :") + (Doc::line() + annotation.typ.pretty()).nest(INDENT))
+                    .group()
+            }
+            ExprKind::RecordInstance(instance) => {
+                qualified(&instance.name) + Doc::text(" ") + fields(&instance.fields)
+            }
+            ExprKind::RecordUpdate(update) => {
+                // A constructor before the fields would be the name of a record instance, even at
+                // the end of an application.
+                let value = match update.expr.data {
+                    ExprKind::Constructor(_) => parens(update.expr.pretty()),
+                    _ => expr(&update.expr, Prec::Atom),
+                };
+
+                value + Doc::text(" ") + fields(&update.fields)
+            }
+            ExprKind::Tuple(tuple) => {
+                let exprs = tuple.exprs.iter().map(|x| x.pretty()).collect();
+                delimited("(", exprs, ")")
+            }
+            ExprKind::Error => Doc::text("<error>"),
+        }
+    }
+}
+
+// Declarations
+
+fn let_binder(binder: &LetBinder) -> Doc {
+    match binder {
+        LetBinder::Param(binder) => {
+            parens(pattern(&binder.pat, Prec::Application) + Doc::text(" : ") + binder.typ.pretty())
+        }
+        LetBinder::Trait(typ) => Doc::text("[") + typ.pretty() + Doc::text("]"),
+    }
+}
+
+impl Pretty for LetSignature {
+    fn pretty(&self) -> Doc {
+        let binders = self.binders.iter().map(|x| Doc::line() + let_binder(x));
+
+        let ret = match &self.ret {
+            Some(ret) => Doc::text(" :") + Doc::line() + ret.pretty(),
+            None => Doc::Nil,
+        };
+
+        let rest = (Doc::concat(binders) + ret).nest(INDENT);
+        (visibility(&self.visibility) + Doc::text("let ") + name(&self.name.name) + rest).group()
+    }
+}
+
+/// The attributes of a declaration, in the lines before it.
+fn attributes(attributes: &Attributes) -> Vec<Doc> {
+    let mut docs = Vec::new();
+
+    if !attributes.allow.is_empty() {
+        let lints = Doc::join(attributes.allow.iter().map(name), Doc::text(", "));
+        docs.push(Doc::text("#[allow(") + lints + Doc::text(")]"));
+    }
+
+    match &attributes.deprecated {
+        Some(Deprecation {
+            message: Some(message),
+        }) => docs.push(Doc::text("#[deprecated ") + quote(&message.get(), '"') + Doc::text("]")),
+        Some(_) => docs.push(Doc::text("#[deprecated]")),
+        None => (),
+    }
+
+    if attributes.opaque {
+        docs.push(Doc::text("#[opaque]"));
+    }
+
+    docs
+}
+
+impl Pretty for Attributes {
+    fn pretty(&self) -> Doc {
+        Doc::concat(attributes(self).into_iter().map(|x| x + Doc::hardline()))
+    }
+}
+
+impl Pretty for LetDecl {
+    fn pretty(&self) -> Doc {
+        let signature = self.signature.pretty();
+
+        match self.body.as_slice() {
+            [single] if single.patterns.is_empty() && single.guard.is_none() => {
+                signature + Doc::text(" =") + body(&single.expr)
+            }
+            arms => {
+                let arms = arms.iter().map(|x| Doc::text("| ") + arm(x));
+                signature + block(arms.collect())
+            }
+        }
+    }
+}
+
+impl Pretty for TypeDecl {
+    fn pretty(&self) -> Doc {
+        let keyword = if self.newtype { "newtype " } else { "type " };
+
+        let head = visibility(&self.visibility)
+            + Doc::text(keyword)
+            + name(&self.name.name)
+            + type_binders(&self.binders);
+
+        let def = match &self.def {
+            TypeDef::Sum(sum) => {
+                let constructors = sum.constructors.iter().map(|constructor| {
+                    let args = constructor
+                        .args
+                        .iter()
+                        .map(|x| Doc::text(" ") + typ(x, Prec::Atom));
+
+                    let ret = match &constructor.typ {
+                        Some(ret) => Doc::text(" : ") + ret.pretty(),
+                        None => Doc::Nil,
+                    };
+
+                    let attributes = attributes(&constructor.attributes)
+                        .into_iter()
+                        .map(|x| x + Doc::text(" "));
+
+                    Doc::line()
+                        + Doc::text("| ")
+                        + Doc::concat(attributes)
+                        + name(&constructor.name.name)
+                        + Doc::concat(args)
+                        + ret
+                });
+
+                Doc::text(" =") + Doc::concat(constructors).nest(INDENT).group()
+            }
+            TypeDef::Record(record) => {
+                let fields = record
+                    .fields
+                    .iter()
+                    .map(|(field, typ, vis)| {
+                        visibility(vis) + name(&field.name) + Doc::text(" : ") + typ.pretty()
+                    })
+                    .collect();
+
+                Doc::text(" = ") + delimited("{ ", fields, " }")
+            }
+            TypeDef::Synonym(synonym) => Doc::text(" = ") + typ(synonym, Prec::Atom),
+            TypeDef::Abstract => Doc::Nil,
+        };
+
+        head + def
+    }
+}
+
+impl Pretty for EffectField {
+    fn pretty(&self) -> Doc {
+        let kind = match (self.kind, self.resumption) {
+            (OperationKind::Fun, _) => Doc::text("fun "),
+            (OperationKind::Ctl, Resumption::Once) => Doc::text("once "),
+            (OperationKind::Ctl, _) => Doc::Nil,
+        };
+
+        let args = self
+            .args
+            .iter()
+            .map(|x| Doc::text(" ") + typ(x, Prec::Atom));
+
+        let default = match &self.default {
+            Some(default) => Doc::text(" =") + body(default),
+            None => Doc::Nil,
+        };
+
+        // The types that break are indented more than the block of the fields.
+        let signature =
+            name(&self.name.name) + Doc::concat(args) + Doc::text(" : ") + self.ret.pretty();
+
+        visibility(&self.visibility) + kind + signature.nest(INDENT) + default
+    }
+}
+
+impl Pretty for EffectDecl {
+    fn pretty(&self) -> Doc {
+        let linear = self
+            .fields
+            .iter()
+            .any(|x| x.resumption == Resumption::Linear);
+
+        let once = if linear { Doc::text("once ") } else { Doc::Nil };
+
+        visibility(&self.visibility)
+            + once
+            + Doc::text("effect ")
+            + name(&self.name.name)
+            + type_binders(&self.binders)
+            + Doc::text(" where")
+            + block(self.fields.iter().map(|x| x.pretty()).collect())
+    }
+}
+
+impl Pretty for TraitDecl {
+    fn pretty(&self) -> Doc {
+        let supers = self
+            .supers
+            .iter()
+            .map(|x| Doc::text("[") + x.pretty() + Doc::text("] "));
+
+        Doc::text("trait ")
+            + Doc::concat(supers)
+            + name(&self.name.name)
+            + type_binders(&self.binders)
+            + Doc::text(" where")
+            + block(self.body.iter().map(|x| x.pretty()).collect())
+    }
+}
+
+impl Pretty for TraitImpl {
+    fn pretty(&self) -> Doc {
+        let binders = self
+            .binders
+            .iter()
+            .map(|x| Doc::text(" ") + typ(x, Prec::Atom));
+
+        Doc::text("impl ")
+            + qualified(&self.name)
+            + Doc::concat(binders)
+            + Doc::text(" where")
+            + block(self.body.iter().map(|x| x.pretty()).collect())
+    }
+}
+
+impl Pretty for ExtDecl {
+    fn pretty(&self) -> Doc {
+        let convention = match &self.convention {
+            Some(convention) => quote(&convention.get(), '"') + Doc::text(" "),
+            None => Doc::Nil,
+        };
+
+        let effect = match &self.effect {
+            Some(effect) => Doc::text(" / ") + qualified(effect),
+            None => Doc::Nil,
+        };
+
+        visibility(&self.visibility)
+            + Doc::text("external ")
+            + convention
+            + name(&self.name.name)
+            + Doc::text(" : ")
+            + self.typ.pretty().nest(INDENT)
+            + effect
+            + Doc::text(" = ")
+            + quote(&self.ret.get(), '"')
+    }
+}
+
+impl Pretty for TestDecl {
+    fn pretty(&self) -> Doc {
+        Doc::text("test ")
+            + quote(&self.description.get(), '"')
+            + Doc::text(" =")
+            + body(&self.body)
+    }
+}
+
+impl Pretty for ModuleDecl {
+    fn pretty(&self) -> Doc {
+        let head = visibility(&self.visibility) + Doc::text("mod ") + name(&self.name);
+
+        match &self.decls {
+            Some(program) => {
+                let decls = (Doc::hardline() + program.pretty()).nest(INDENT);
+                head + Doc::text(" where") + decls
+            }
+            None => head,
+        }
+    }
+}
+
+impl Pretty for Program {
+    fn pretty(&self) -> Doc {
+        let commands = self.commands.iter().map(|(name, command)| {
+            let command = command.get();
+            let command = command.strip_prefix('#').unwrap_or(&command);
+            Doc::text(format!("#{} ", command)) + quote(&name.get(), '"')
+        });
+
+        let decls = commands
+            .chain(
+                self.types
+                    .iter()
+                    .map(|x| x.attributes.pretty() + x.pretty()),
+            )
+            .chain(
+                self.effects
+                    .iter()
+                    .map(|x| x.attributes.pretty() + x.pretty()),
+            )
+            .chain(
+                self.traits
+                    .iter()
+                    .map(|x| x.attributes.pretty() + x.pretty()),
+            )
+            .chain(
+                self.externals
+                    .iter()
+                    .map(|x| x.attributes.pretty() + x.pretty()),
+            )
+            .chain(self.lets.iter().map(|x| x.attributes.pretty() + x.pretty()))
+            .chain(self.impls.iter().map(|x| x.pretty()))
+            .chain(self.tests.iter().map(|x| x.pretty()))
+            .chain(
+                self.modules
+                    .iter()
+                    .map(|x| x.attributes.pretty() + x.pretty()),
+            );
+
+        Doc::join(decls, Doc::hardline() + Doc::hardline())
+    }
+}
@@ -0,0 +1,1236 @@
+//! Printing of the concrete tree as formatted source, for `vulpi fmt`. The tokens are printed as
+//! they are in the source, with the comments around them, while the layout is normalized: blocks
+//! are indented by two spaces, operators have spaces around them, the lines that don't fit are
+//! broken and the imports that are next to each other are sorted.
+//!
+//! The comments are taken from the tokens of the lexer instead of the tokens of the tree, because
+//! the virtual tokens of the layout keep the comments before them and they're not in the tree.
+
+use std::{
+    cell::RefCell,
+    collections::{HashMap, HashSet},
+};
+
+use vulpi_intern::Symbol;
+use vulpi_syntax::{
+    concrete::{tree::*, Lower, Parenthesis, Path, Upper},
+    tokens::{Token, TokenData},
+};
+
+use crate::Doc;
+
+const INDENT: usize = 2;
+
+/// The comments and the empty lines of a source, by the byte where the token after them starts.
+#[derive(Default)]
+pub struct Trivia {
+    leading: HashMap<usize, Vec<(Symbol, bool)>>,
+    trailing: HashMap<usize, Symbol>,
+    blank: HashSet<usize>,
+}
+
+impl Trivia {
+    /// Takes the comments of all the tokens of a source, up to its end of file. A comment that
+    /// starts in the line of the token before it stays at the end of that line.
+    pub fn new(tokens: &[Token]) -> Self {
+        let mut trivia = Trivia::default();
+        let mut previous = None;
+
+        // The whitespace and the comments since the last token that is in the tree.
+        let mut comments = Vec::new();
+        let mut whitespace = String::new();
+
+        for token in tokens {
+            for comment in &token.comments {
+                whitespace.push_str(&comment.whitespace.data.get());
+                comments.push((
+                    std::mem::take(&mut whitespace),
+                    comment.comment.data.clone(),
+                ));
+            }
+
+            whitespace.push_str(&token.whitespace.data.get());
+
+            if matches!(
+                token.kind,
+                TokenData::Begin | TokenData::End | TokenData::Sep
+            ) {
+                continue;
+            }
+
+            let start = token.value.span.start.0;
+            let mut comments = std::mem::take(&mut comments).into_iter().peekable();
+
+            if let Some(previous) = previous {
+                if let Some((_, comment)) = comments.next_if(|(space, _)| !space.contains('\n')) {
+                    trivia.trailing.insert(previous, comment);
+                }
+            }
+
+            let space = comments.peek().map_or(&whitespace, |(space, _)| space);
+
+            if previous.is_some() && space.matches('\n').count() > 1 {
+                trivia.blank.insert(start);
+            }
+
+            // A comment that has an empty line after it stays apart from the token.
+            let comments = comments.collect::<Vec<_>>();
+            let after = comments.iter().skip(1).map(|(space, _)| space);
+
+            let leading = comments
+                .iter()
+                .zip(after.chain(std::iter::once(&whitespace)))
+                .map(|((_, comment), after)| (comment.clone(), after.matches('\n').count() > 1))
+                .collect::<Vec<_>>();
+
+            if !leading.is_empty() {
+                trivia.leading.insert(start, leading);
+            }
+
+            whitespace.clear();
+            previous = Some(start);
+        }
+
+        trivia
+    }
+
+    /// The number of comments, to check that the formatted source has all of them.
+    pub fn comments(&self) -> usize {
+        self.leading.values().map(Vec::len).sum::<usize>() + self.trailing.len()
+    }
+}
+
+pub struct Printer<'a> {
+    source: &'a str,
+    trivia: Trivia,
+
+    /// Tokens whose comments before them were already printed somewhere else.
+    detached: RefCell<HashSet<usize>>,
+}
+
+impl<'a> Printer<'a> {
+    pub fn new(source: &'a str, trivia: Trivia) -> Self {
+        Self {
+            source,
+            trivia,
+            detached: Default::default(),
+        }
+    }
+
+    /// The comments before a token, each one in its own line, and the one after it.
+    fn comments(&self, token: &Token) -> (Doc, Doc) {
+        let start = start(token);
+
+        let leading = self.detach(start);
+
+        let trailing = match self.trivia.trailing.get(&start) {
+            Some(comment) => Doc::suffix(format!(" {}", comment.get())),
+            None => Doc::Nil,
+        };
+
+        (leading, trailing)
+    }
+
+    /// Takes the comments before a token, so they aren't printed with it anymore.
+    fn detach(&self, start: usize) -> Doc {
+        if !self.detached.borrow_mut().insert(start) {
+            return Doc::Nil;
+        }
+
+        let leading = self.trivia.leading.get(&start).into_iter().flatten();
+
+        Doc::concat(leading.map(|(comment, blank)| {
+            let blank = if *blank { Doc::hardline() } else { Doc::Nil };
+            Doc::text(comment.get()) + Doc::hardline() + blank
+        }))
+    }
+
+    /// A token as it's written in the source, with its comments.
+    fn token(&self, token: &Token) -> Doc {
+        let span = &token.value.span;
+        let (leading, trailing) = self.comments(token);
+
+        // The span of a command starts after its `#`.
+        let hash = if token.kind == TokenData::Command {
+            Doc::text("#")
+        } else {
+            Doc::Nil
+        };
+
+        leading + hash + Doc::text(&self.source[span.start.0..span.end.0]) + trailing
+    }
+
+    /// A token that is left out of the source, like a trailing comma, whose comments are kept.
+    fn omit(&self, token: &Token) -> Doc {
+        let (leading, trailing) = self.comments(token);
+        leading + trailing
+    }
+
+    fn space(&self, token: &Token) -> Doc {
+        Doc::text(" ") + self.token(token)
+    }
+
+    fn lower(&self, lower: &Lower) -> Doc {
+        self.token(&lower.0)
+    }
+
+    fn upper(&self, upper: &Upper) -> Doc {
+        self.token(&upper.0)
+    }
+
+    fn path<T>(&self, path: &Path<T>, last: impl Fn(&T) -> Doc) -> Doc {
+        let segments = path
+            .segments
+            .iter()
+            .map(|(upper, dot)| self.upper(upper) + self.token(dot));
+
+        Doc::concat(segments) + last(&path.last)
+    }
+
+    fn upper_path(&self, path: &Path<Upper>) -> Doc {
+        self.path(path, |x| self.upper(x))
+    }
+
+    fn lower_path(&self, path: &Path<Lower>) -> Doc {
+        self.path(path, |x| self.lower(x))
+    }
+
+    fn visibility(&self, visibility: &Visibility) -> Doc {
+        match visibility {
+            Visibility::Public(token) => self.token(token) + Doc::text(" "),
+            Visibility::Private => Doc::Nil,
+        }
+    }
+
+    fn parenthesis<T>(&self, parens: &Parenthesis<T>, data: impl Fn(&T) -> Doc) -> Doc {
+        self.token(&parens.left) + data(&parens.data) + self.token(&parens.right)
+    }
+
+    /// The items of a list separated by commas. A comma after the last item is left out.
+    fn separated<T>(&self, items: &[(T, Option<Token>)], item: impl Fn(&T) -> Doc) -> Vec<Doc> {
+        items
+            .iter()
+            .enumerate()
+            .map(|(index, (value, comma))| match comma {
+                Some(comma) if index + 1 == items.len() => item(value) + self.omit(comma),
+                Some(comma) => item(value) + self.token(comma),
+                None => item(value),
+            })
+            .collect()
+    }
+
+    /// Items between delimiters, that break with one item in each line.
+    fn delimited(&self, open: &Token, items: Vec<Doc>, close: &Token) -> Doc {
+        let softline = match open.kind {
+            TokenData::LBrace => Doc::line(),
+            _ => Doc::softline(),
+        };
+
+        let items = (softline.clone() + Doc::join(items, Doc::line())).nest(INDENT);
+        (self.token(open) + items + softline + self.token(close)).group()
+    }
+
+    /// A line that starts at a byte of the source, and if there's an empty line before it. The
+    /// comments before the line are put out of its groups, so they don't break them.
+    fn line(&self, first: Option<usize>, line: impl FnOnce() -> Doc) -> (bool, Doc) {
+        let blank = first.is_some_and(|x| self.trivia.blank.contains(&x));
+        let comments = first.map_or(Doc::Nil, |x| self.detach(x));
+        (blank, comments + line())
+    }
+
+    /// The lines of a block of the layout, with the empty lines between them kept.
+    fn block(&self, lines: Vec<(bool, Doc)>) -> Doc {
+        let lines = lines.into_iter().enumerate().map(|(index, (blank, line))| {
+            let blank = if index != 0 && blank {
+                Doc::hardline()
+            } else {
+                Doc::Nil
+            };
+            blank + Doc::hardline() + line
+        });
+
+        Doc::concat(lines).nest(INDENT)
+    }
+
+    // Kinds
+
+    fn kind(&self, kind: &Kind) -> Doc {
+        match &kind.data {
+            KindType::Star(token) => self.token(token),
+            KindType::Variable(upper) => self.upper(upper),
+            KindType::Arrow(left, arrow, right) => {
+                self.kind(left) + self.space(arrow) + Doc::text(" ") + self.kind(right)
+            }
+            KindType::Parenthesis(parens) => self.parenthesis(parens, |x| self.kind(x)),
+        }
+    }
+
+    // Types
+
+    fn type_binder(&self, binder: &TypeBinder) -> Doc {
+        match binder {
+            TypeBinder::Implicit(lower) => self.lower(lower),
+            TypeBinder::Explicit(parens) => self.parenthesis(parens, |binder| {
+                self.lower(&binder.name)
+                    + self.space(&binder.colon)
+                    + Doc::text(" ")
+                    + self.kind(&binder.kind)
+            }),
+        }
+    }
+
+    fn type_binders(&self, binders: &[TypeBinder]) -> Doc {
+        Doc::concat(binders.iter().map(|x| Doc::text(" ") + self.type_binder(x)))
+    }
+
+    fn typ(&self, typ: &Type) -> Doc {
+        match &typ.data {
+            TypeKind::Parenthesis(parens) => self.parenthesis(parens, |(typ, comma)| {
+                self.typ(typ) + comma.as_ref().map_or(Doc::Nil, |x| self.token(x))
+            }),
+            TypeKind::Tuple(parens) => {
+                let types = self.separated(&parens.data, |x| self.typ(x));
+                self.delimited(&parens.left, types, &parens.right)
+            }
+            TypeKind::Type(path) => self.upper_path(path),
+            TypeKind::TypeVariable(lower) => self.lower(lower),
+            TypeKind::Arrow(arrow) => {
+                // The arrows on the right are in the same group, so they break together.
+                let mut right = Vec::new();
+                let mut last = arrow;
+
+                while let TypeKind::Arrow(arrow) = &last.right.data {
+                    right.push(self.space(&last.arrow) + Doc::line() + self.typ(&arrow.left));
+                    last = arrow;
+                }
+
+                right.push(self.space(&last.arrow) + Doc::line() + self.typ(&last.right));
+
+                (self.typ(&arrow.left) + Doc::concat(right).nest(INDENT)).group()
+            }
+            TypeKind::Application(app) => {
+                let args = app.args.iter().map(|x| Doc::line() + self.typ(x));
+                (self.typ(&app.func) + Doc::concat(args).nest(INDENT)).group()
+            }
+            TypeKind::Forall(forall) => {
+                let binders = forall.params.iter().map(|x| self.type_binder(x));
+                let binders = Doc::join(binders, Doc::text(" "));
+                let body = (Doc::line() + self.typ(&forall.body)).nest(INDENT);
+
+                (self.token(&forall.forall)
+                    + Doc::text(" ")
+                    + binders
+                    + self.token(&forall.dot)
+                    + body)
+                    .group()
+            }
+            TypeKind::Unit(token) => self.token(token),
+        }
+    }
+
+    // Patterns
+
+    fn pattern(&self, pattern: &Pattern) -> Doc {
+        match &pattern.data {
+            PatternKind::Wildcard(token) => self.token(token),
+            PatternKind::Constructor(path) => self.upper_path(path),
+            PatternKind::Variable(lower) => self.lower(lower),
+            PatternKind::Literal(literal) => self.literal(literal),
+            PatternKind::Range(range) => {
+                self.literal(&range.start) + self.token(&range.dots) + self.literal(&range.end)
+            }
+            PatternKind::Annotation(annotation) => {
+                self.pattern(&annotation.left)
+                    + self.space(&annotation.colon)
+                    + Doc::text(" ")
+                    + self.typ(&annotation.right)
+            }
+            PatternKind::Tuple(pats) => {
+                let pats = self.separated(pats, |x| self.pattern(x));
+                Doc::text("(") + Doc::join(pats, Doc::text(" ")) + Doc::text(")")
+            }
+            PatternKind::Application(app) => {
+                let args = app.args.iter().map(|x| Doc::text(" ") + self.pattern(x));
+                self.upper_path(&app.func) + Doc::concat(args)
+            }
+            PatternKind::Effect(effect) => {
+                let args = effect.args.iter().map(|x| Doc::text(" ") + self.pattern(x));
+
+                let cont = match &effect.cont {
+                    Some((arrow, name)) => self.space(arrow) + Doc::text(" ") + self.lower(name),
+                    None => Doc::Nil,
+                };
+
+                self.token(&effect.left_brace)
+                    + Doc::text(" ")
+                    + self.lower_path(&effect.func)
+                    + Doc::concat(args)
+                    + cont
+                    + self.space(&effect.right_brace)
+            }
+            PatternKind::Return(return_) => {
+                self.token(&return_.return_) + Doc::text(" ") + self.pattern(&return_.pattern)
+            }
+            PatternKind::As(as_) => {
+                self.lower(&as_.name) + self.token(&as_.at) + self.pattern(&as_.pattern)
+            }
+            PatternKind::Or(or) => {
+                self.pattern(&or.left)
+                    + self.space(&or.bar)
+                    + Doc::text(" ")
+                    + self.pattern(&or.right)
+            }
+            PatternKind::Parenthesis(parens) => self.parenthesis(parens, |x| self.pattern(x)),
+        }
+    }
+
+    fn literal(&self, literal: &Literal) -> Doc {
+        match &literal.data {
+            LiteralKind::String(token)
+            | LiteralKind::Integer(token)
+            | LiteralKind::Float(token)
+            | LiteralKind::Char(token)
+            | LiteralKind::Unit(token) => self.token(token),
+            LiteralKind::Negative(minus, number) => self.token(minus) + self.token(number),
+        }
+    }
+
+    // Expressions
+
+    /// The expression after an `=` or an `=>`. The ones that start a block of the layout stay in
+    /// the same line, and the other ones go to the next line if they don't fit.
+    fn let_binding(&self, binding: &LetBinding) -> Doc {
+        let params = binding
+            .params
+            .iter()
+            .map(|x| Doc::text(" ") + self.pattern(x));
+
+        self.lower(&binding.name)
+            + Doc::concat(params)
+            + self.space(&binding.eq)
+            + self.body(&binding.value)
+    }
+
+    fn body(&self, expr: &Expr) -> Doc {
+        if hangs(expr) {
+            Doc::text(" ") + self.expr(expr)
+        } else {
+            (Doc::line() + self.expr(expr)).nest(INDENT).group()
+        }
+    }
+
+    fn arm(&self, arm: &PatternArm) -> Doc {
+        let patterns = self.separated(&arm.patterns, |x| self.pattern(x));
+
+        let guard = match &arm.guard {
+            Some((if_, guard)) => self.space(if_) + Doc::text(" ") + self.expr(guard),
+            None => Doc::Nil,
+        };
+
+        Doc::join(patterns, Doc::text(" ")) + guard + self.space(&arm.arrow) + self.body(&arm.expr)
+    }
+
+    fn arms(&self, arms: &[PatternArm]) -> Doc {
+        let arms = arms.iter().map(|arm| {
+            let first = arm.patterns.first().map(|(x, _)| x.span.start.0);
+            self.line(first, || self.arm(arm))
+        });
+
+        self.block(arms.collect())
+    }
+
+    fn fields(&self, fields: &[(RecordField, Option<Token>)]) -> Vec<Doc> {
+        self.separated(fields, |field| {
+            self.lower(&field.name)
+                + self.space(&field.eq)
+                + Doc::text(" ")
+                + self.expr(&field.expr)
+        })
+    }
+
+    fn html(&self, node: &HtmlNode) -> Doc {
+        let attributes = node.attributes.iter().map(|attribute| {
+            Doc::line()
+                + self.upper(&attribute.name)
+                + self.token(&attribute.eq)
+                + self.expr(&attribute.value)
+        });
+
+        let open = self.token(&node.left_angle)
+            + self.lower(&node.name)
+            + Doc::concat(attributes).nest(INDENT)
+            + self.token(&node.right_angle);
+
+        let children = node.children.iter().map(|x| Doc::softline() + self.html(x));
+
+        let close = self.token(&node.left_angle_slash)
+            + self.lower(&node.name_end)
+            + self.token(&node.right_angle_end);
+
+        (open + Doc::concat(children).nest(INDENT) + Doc::softline() + close).group()
+    }
+
+    fn statement(&self, sttm: &Sttm) -> Doc {
+        match &sttm.data {
+            StatementKind::Let(sttm) => {
+                self.token(&sttm.let_)
+                    + Doc::text(" ")
+                    + self.pattern(&sttm.pattern)
+                    + self.space(&sttm.eq)
+                    + self.body(&sttm.expr)
+            }
+            StatementKind::Expect(sttm) => {
+                // The `else` is indented less than the blocks of the expression, so it's not a
+                // part of them.
+                let alternative =
+                    (Doc::line() + self.token(&sttm.else_) + self.body(&sttm.alternative))
+                        .nest(INDENT);
+
+                (self.token(&sttm.expect)
+                    + Doc::text(" ")
+                    + self.pattern(&sttm.pattern)
+                    + self.space(&sttm.eq)
+                    + self.body(&sttm.expr).nest(INDENT)
+                    + alternative)
+                    .group()
+            }
+            StatementKind::Expr(expr) => self.expr(expr),
+            StatementKind::Error(tokens) => self.tokens(tokens),
+        }
+    }
+
+    fn do_expr(&self, do_: &DoExpr) -> Doc {
+        let path = do_
+            .path
+            .iter()
+            .map(|(upper, dot)| self.upper(upper) + self.token(dot));
+
+        let statements = do_
+            .block
+            .statements
+            .iter()
+            .map(|x| self.line(Some(x.span.start.0), || self.statement(x)));
+
+        Doc::concat(path) + self.token(&do_.do_) + self.block(statements.collect())
+    }
+
+    /// Tokens that couldn't be parsed, separated by spaces.
+    fn tokens(&self, tokens: &[Token]) -> Doc {
+        Doc::join(tokens.iter().map(|x| self.token(x)), Doc::text(" "))
+    }
+
+    pub fn expr(&self, expr: &Expr) -> Doc {
+        match &expr.data {
+            ExprKind::Lambda(lambda) => {
+                let patterns = lambda.patterns.iter().map(|x| self.pattern(x));
+
+                (self.token(&lambda.lambda)
+                    + Doc::join(patterns, Doc::text(" "))
+                    + self.space(&lambda.arrow)
+                    + self.body(&lambda.expr))
+                .group()
+            }
+            ExprKind::List(list) | ExprKind::Array(list) => {
+                let values = self.separated(&list.values, |x| self.expr(x));
+                self.delimited(&list.left_bracket, values, &list.right_bracket)
+            }
+            ExprKind::Application(app) => {
+                let args = app.args.iter().map(|x| Doc::line() + self.expr(x));
+                (self.expr(&app.func) + Doc::concat(args).nest(INDENT)).group()
+            }
+            ExprKind::Perform(perform) => {
+                let args = perform.args.iter().map(|x| Doc::line() + self.expr(x));
+                let operation = self.lower_path(&perform.operation);
+
+                (self.token(&perform.perform)
+                    + Doc::text(" ")
+                    + operation
+                    + Doc::concat(args).nest(INDENT))
+                .group()
+            }
+            ExprKind::HtmlNode(node) => self.html(node),
+            ExprKind::Variable(lower) => self.lower(lower),
+            ExprKind::Constructor(path) => self.upper_path(path),
+            ExprKind::Function(path) => self.lower_path(path),
+            ExprKind::Projection(projection) => {
+                self.expr(&projection.expr)
+                    + self.token(&projection.dot)
+                    + self.lower(&projection.field)
+            }
+            ExprKind::Binary(binary) => {
+                let right = (Doc::line() + self.expr(&binary.right)).nest(INDENT);
+                (self.expr(&binary.left) + self.space(operator(&binary.op)) + right).group()
+            }
+            ExprKind::Let(let_) => {
+                let value = self.token(&let_.let_)
+                    + Doc::text(" ")
+                    + self.pattern(&let_.pattern)
+                    + self.space(&let_.eq)
+                    + self.body(&let_.body);
+
+                // The `in` stays in the line of the value when the body doesn't fit there.
+                let value = (value + Doc::line() + self.token(&let_.in_)).group();
+                (value + Doc::line() + self.expr(&let_.value)).group()
+            }
+            ExprKind::LetGroup(group) => {
+                let mut value = self.token(&group.let_) + Doc::text(" ");
+
+                for (binding, and) in &group.bindings {
+                    value = value + self.let_binding(binding);
+
+                    if let Some(and) = and {
+                        value = value + Doc::line() + self.token(and) + Doc::text(" ");
+                    }
+                }
+
+                let value = (value + Doc::line() + self.token(&group.in_)).group();
+                (value + Doc::line() + self.expr(&group.value)).group()
+            }
+            ExprKind::When(when) => {
+                let scrutinee = self.separated(&when.scrutinee, |x| self.expr(x));
+
+                self.token(&when.when)
+                    + Doc::text(" ")
+                    + Doc::join(scrutinee, Doc::text(" "))
+                    + self.space(&when.is)
+                    + self.arms(&when.arms)
+            }
+            ExprKind::Cases(cases) => self.token(&cases.cases) + self.arms(&cases.arms),
+            ExprKind::Handler(handler) => {
+                // The clauses are indented less than the blocks of the handled expression, so
+                // they're not a part of them.
+                let mut with = Doc::line()
+                    + self.token(&handler.with)
+                    + Doc::text(" ")
+                    + self.expr(&handler.handler);
+
+                if let Some((finally, expr)) = &handler.finally {
+                    with =
+                        with + Doc::line() + self.token(finally) + Doc::text(" ") + self.expr(expr);
+                }
+
+                (self.token(&handler.handle)
+                    + Doc::text(" ")
+                    + self.expr(&handler.expr).nest(INDENT)
+                    + with.nest(INDENT))
+                .group()
+            }
+            ExprKind::NamedHandler(handler) => {
+                let value = self.token(&handler.handle)
+                    + Doc::text(" ")
+                    + self.lower(&handler.name)
+                    + self.space(&handler.eq)
+                    + self.body(&handler.handler);
+
+                let value = (value + Doc::line() + self.token(&handler.in_)).group();
+                (value + Doc::line() + self.expr(&handler.expr)).group()
+            }
+            ExprKind::Mask(mask) => {
+                self.token(&mask.mask)
+                    + self.token(&mask.less)
+                    + self.upper_path(&mask.effect)
+                    + self.token(&mask.greater)
+                    + Doc::text(" ")
+                    + self.expr(&mask.expr)
+            }
+            ExprKind::Do(do_) => self.do_expr(do_),
+            ExprKind::While(while_) => {
+                self.token(&while_.while_)
+                    + Doc::text(" ")
+                    + self.expr(&while_.condition)
+                    + Doc::text(" ")
+                    + self.do_expr(&while_.body)
+            }
+            ExprKind::For(for_) => {
+                self.token(&for_.for_)
+                    + Doc::text(" ")
+                    + self.pattern(&for_.pattern)
+                    + self.space(&for_.in_)
+                    + Doc::text(" ")
+                    + self.expr(&for_.list)
+                    + Doc::text(" ")
+                    + self.do_expr(&for_.body)
+            }
+            ExprKind::Literal(literal) => self.literal(literal),
+            ExprKind::Annotation(annotation) => {
+                let typ = (Doc::line() + self.typ(&annotation.typ)).nest(INDENT);
+                (self.expr(&annotation.expr) + self.space(&annotation.colon) + typ).group()
+            }
+            ExprKind::RecordInstance(instance) => {
+                let fields = self.fields(&instance.fields);
+
+                self.upper_path(&instance.name)
+                    + Doc::text(" ")
+                    + self.delimited(&instance.left_brace, fields, &instance.right_brace)
+            }
+            ExprKind::RecordUpdate(update) => {
+                let fields = self.fields(&update.fields);
+
+                self.expr(&update.expr)
+                    + Doc::text(" ")
+                    + self.delimited(&update.left_brace, fields, &update.right_brace)
+            }
+            ExprKind::Parenthesis(parens) => self.parenthesis(parens, |(expr, comma)| {
+                self.expr(expr) + comma.as_ref().map_or(Doc::Nil, |x| self.token(x))
+            }),
+            ExprKind::Tuple(tuple) => {
+                let exprs = self.separated(&tuple.data, |x| self.expr(x));
+                self.delimited(&tuple.left, exprs, &tuple.right)
+            }
+        }
+    }
+
+    // Declarations
+
+    fn let_binder(&self, binder: &LetBinder) -> Doc {
+        match binder {
+            LetBinder::Param(binder) => {
+                self.token(&binder.left_paren)
+                    + self.pattern(&binder.pattern)
+                    + self.space(&binder.colon)
+                    + Doc::text(" ")
+                    + self.typ(&binder.typ)
+                    + self.token(&binder.right_paren)
+            }
+            LetBinder::Trait(binder) => self.trait_binder(binder),
+        }
+    }
+
+    fn trait_binder(&self, binder: &TraitBinder) -> Doc {
+        self.token(&binder.left_bracket) + self.typ(&binder.typ) + self.token(&binder.right_bracket)
+    }
+
+    fn let_signature(&self, signature: &LetSignature) -> Doc {
+        let binders = signature
+            .binders
+            .iter()
+            .map(|x| Doc::line() + self.let_binder(x));
+
+        let ret = match &signature.ret {
+            Some((colon, typ)) => self.space(colon) + Doc::line() + self.typ(typ),
+            None => Doc::Nil,
+        };
+
+        let head = self.visibility(&signature.visibility)
+            + self.token(&signature.let_)
+            + Doc::text(" ")
+            + self.lower(&signature.name);
+
+        (head + (Doc::concat(binders) + ret).nest(INDENT)).group()
+    }
+
+    fn let_decl(&self, decl: &LetDecl) -> Doc {
+        let signature = self.let_signature(&decl.signature);
+
+        match &decl.body {
+            LetMode::Body(eq, expr) => signature + self.space(eq) + self.body(expr),
+            LetMode::Cases(cases) => {
+                let cases = cases.iter().map(|case| {
+                    self.line(Some(start(&case.pipe)), || {
+                        self.token(&case.pipe) + Doc::text(" ") + self.arm(&case.arm)
+                    })
+                });
+
+                signature + self.block(cases.collect())
+            }
+        }
+    }
+
+    fn pattern_decl(&self, decl: &PatternDecl) -> Doc {
+        let ret = match &decl.ret {
+            Some((colon, typ)) => self.space(colon) + Doc::line() + self.typ(typ),
+            None => Doc::Nil,
+        };
+
+        let head = self.visibility(&decl.visibility)
+            + self.token(&decl.let_)
+            + Doc::text(" ")
+            + self.pattern(&decl.pattern);
+
+        (head + ret.nest(INDENT)).group() + self.space(&decl.eq) + self.body(&decl.body)
+    }
+
+    fn type_decl(&self, decl: &TypeDecl) -> Doc {
+        let head = self.visibility(&decl.visibility)
+            + self.token(&decl.type_)
+            + Doc::text(" ")
+            + self.upper(&decl.name)
+            + self.type_binders(&decl.binders);
+
+        let Some((eq, def)) = &decl.def else {
+            return head;
+        };
+
+        let def = match def {
+            TypeDef::Sum(sum) => {
+                let constructors = sum.constructors.iter().map(|constructor| {
+                    self.line(Some(start(&constructor.pipe)), || {
+                        self.constructor(constructor)
+                    })
+                });
+
+                // A type with many constructors has one of them in each line.
+                match constructors.collect::<Vec<_>>() {
+                    constructors if constructors.len() > 1 => self.block(constructors),
+                    constructors => {
+                        let constructors = constructors.into_iter().map(|(_, x)| Doc::line() + x);
+                        Doc::concat(constructors).nest(INDENT).group()
+                    }
+                }
+            }
+            TypeDef::Record(record) => {
+                let fields = self.separated(&record.fields, |field| {
+                    self.visibility(&field.visibility)
+                        + self.lower(&field.name)
+                        + self.space(&field.colon)
+                        + Doc::text(" ")
+                        + self.typ(&field.typ)
+                });
+
+                Doc::text(" ") + self.delimited(&record.left_brace, fields, &record.right_brace)
+            }
+            TypeDef::Synonym(typ) => Doc::text(" ") + self.typ(typ),
+        };
+
+        head + self.space(eq) + def
+    }
+
+    fn constructor(&self, constructor: &Constructor) -> Doc {
+        let args = constructor
+            .args
+            .iter()
+            .map(|x| Doc::text(" ") + self.typ(x));
+
+        let typ = match &constructor.typ {
+            Some((colon, typ)) => self.space(colon) + Doc::text(" ") + self.typ(typ),
+            None => Doc::Nil,
+        };
+
+        let attributes = constructor
+            .attributes
+            .iter()
+            .map(|x| self.attribute(x) + Doc::text(" "));
+
+        self.token(&constructor.pipe)
+            + Doc::text(" ")
+            + Doc::concat(attributes)
+            + self.upper(&constructor.name)
+            + Doc::concat(args)
+            + typ
+    }
+
+    fn effect_field(&self, field: &EffectField) -> Doc {
+        let kind = match &field.kind {
+            Some(kind) => self.token(kind) + Doc::text(" "),
+            None => Doc::Nil,
+        };
+
+        let args = field.args.iter().map(|x| Doc::text(" ") + self.typ(x));
+
+        let default = match &field.default {
+            Some((eq, expr)) => self.space(eq) + self.body(expr),
+            None => Doc::Nil,
+        };
+
+        self.visibility(&field.visibility)
+            + kind
+            + self.lower(&field.name)
+            + Doc::concat(args)
+            + self.space(&field.colon)
+            + Doc::text(" ")
+            + self.typ(&field.ret)
+            + default
+    }
+
+    fn effect_decl(&self, decl: &EffectDecl) -> Doc {
+        let fields = decl.fields.iter().map(|field| {
+            let first = match &field.visibility {
+                Visibility::Public(token) => token,
+                Visibility::Private => field.kind.as_ref().unwrap_or(&field.name.0),
+            };
+
+            self.line(Some(start(first)), || self.effect_field(field))
+        });
+
+        let once = match &decl.once {
+            Some(once) => self.token(once) + Doc::text(" "),
+            None => Doc::Nil,
+        };
+
+        self.visibility(&decl.visibility)
+            + once
+            + self.token(&decl.effect)
+            + Doc::text(" ")
+            + self.upper(&decl.name)
+            + self.type_binders(&decl.binders)
+            + self.space(&decl.where_)
+            + self.block(fields.collect())
+    }
+
+    fn trait_decl(&self, decl: &TraitDecl) -> Doc {
+        let supers = decl
+            .supers
+            .iter()
+            .map(|x| Doc::text(" ") + self.trait_binder(x));
+
+        let body = decl
+            .body
+            .iter()
+            .map(|x| self.line(Some(start(first_of_signature(x))), || self.let_signature(x)));
+
+        self.visibility(&decl.visibility)
+            + self.token(&decl.trait_)
+            + Doc::concat(supers)
+            + Doc::text(" ")
+            + self.upper(&decl.name)
+            + self.type_binders(&decl.binders)
+            + self.space(&decl.where_)
+            + self.block(body.collect())
+    }
+
+    fn trait_impl(&self, decl: &TraitImpl) -> Doc {
+        let supers = decl
+            .supers
+            .iter()
+            .map(|x| Doc::text(" ") + self.trait_binder(x));
+
+        let types = decl.types.iter().map(|x| Doc::text(" ") + self.typ(x));
+
+        let body = decl.body.iter().map(|x| {
+            let first = first_of_signature(&x.signature);
+            self.line(Some(start(first)), || self.let_decl(x))
+        });
+
+        self.token(&decl.impl_)
+            + Doc::concat(supers)
+            + Doc::text(" ")
+            + self.upper_path(&decl.name)
+            + Doc::concat(types)
+            + self.space(&decl.where_)
+            + self.block(body.collect())
+    }
+
+    fn ext_decl(&self, decl: &ExtDecl) -> Doc {
+        let optional = |token: &Option<Token>| match token {
+            Some(token) => self.token(token) + Doc::text(" "),
+            None => Doc::Nil,
+        };
+
+        let effect = match &decl.effect {
+            Some((slash, path)) => self.space(slash) + Doc::text(" ") + self.upper_path(path),
+            None => Doc::Nil,
+        };
+
+        self.visibility(&decl.visibility)
+            + self.token(&decl.external)
+            + Doc::text(" ")
+            + optional(&decl.convention)
+            + optional(&decl.let_)
+            + self.lower(&decl.name)
+            + self.space(&decl.colon)
+            + Doc::text(" ")
+            + self.typ(&decl.typ)
+            + effect
+            + self.space(&decl.equal)
+            + (Doc::line() + self.token(&decl.str)).nest(INDENT).group()
+    }
+
+    fn use_decl(&self, decl: &UseDecl) -> Doc {
+        let alias = match &decl.alias {
+            Some(alias) => self.space(&alias.as_) + Doc::text(" ") + self.upper(&alias.alias),
+            None => Doc::Nil,
+        };
+
+        self.visibility(&decl.visibility)
+            + self.token(&decl.use_)
+            + Doc::text(" ")
+            + self.upper_path(&decl.path)
+            + alias
+    }
+
+    fn when_decl(&self, decl: &WhenDecl) -> Doc {
+        let arms = decl.arms.iter().map(|arm| {
+            let values = arm.values.iter().map(|(value, bar)| {
+                let bar = bar.as_ref().map_or(Doc::Nil, |x| self.space(x));
+                self.token(value) + bar
+            });
+
+            let first = arm.values.first().map(|(x, _)| start(x));
+
+            self.line(first, || {
+                Doc::join(values, Doc::text(" "))
+                    + self.space(&arm.where_)
+                    + self.top_levels(&arm.top_levels)
+            })
+        });
+
+        self.token(&decl.when)
+            + Doc::text(" ")
+            + self.lower(&decl.condition)
+            + self.space(&decl.is)
+            + self.block(arms.collect())
+    }
+
+    fn attribute(&self, attribute: &DeclAttribute) -> Doc {
+        let args = match &attribute.args {
+            Some(args) => self.parenthesis(args, |args| {
+                Doc::join(self.separated(args, |x| self.lower(x)), Doc::text(" "))
+            }),
+            None => Doc::Nil,
+        };
+
+        let message = match &attribute.message {
+            Some(message) => self.space(message),
+            None => Doc::Nil,
+        };
+
+        self.token(&attribute.hash_bracket)
+            + self.lower(&attribute.name)
+            + args
+            + message
+            + self.token(&attribute.right_bracket)
+    }
+
+    /// The attributes of a declaration, each in a line before it.
+    fn attributes(&self, attributes: &[DeclAttribute]) -> Doc {
+        let lines = attributes
+            .iter()
+            .map(|attribute| self.attribute(attribute) + Doc::hardline());
+
+        Doc::concat(lines)
+    }
+
+    fn top_level(&self, top_level: &TopLevel) -> Doc {
+        let attributes = attributes_of(top_level).map_or(Doc::Nil, |x| self.attributes(x));
+        attributes + self.declaration(top_level)
+    }
+
+    fn declaration(&self, top_level: &TopLevel) -> Doc {
+        match top_level {
+            TopLevel::Let(decl) => self.let_decl(decl),
+            TopLevel::Pattern(decl) => self.pattern_decl(decl),
+            TopLevel::Type(decl) => self.type_decl(decl),
+            TopLevel::Effect(decl) => self.effect_decl(decl),
+            TopLevel::Use(decl) => self.use_decl(decl),
+            TopLevel::Impl(decl) => self.trait_impl(decl),
+            TopLevel::Trait(decl) => self.trait_decl(decl),
+            TopLevel::Module(decl) => {
+                let head = self.visibility(&decl.visibility)
+                    + self.token(&decl.mod_)
+                    + Doc::text(" ")
+                    + self.upper(&decl.name);
+
+                match &decl.part {
+                    Some(part) => {
+                        head + self.space(&part.where_) + self.top_levels(&part.top_levels)
+                    }
+                    None => head,
+                }
+            }
+            TopLevel::Error(tokens) => self.tokens(tokens),
+            TopLevel::External(decl) => self.ext_decl(decl),
+            TopLevel::Command(decl) => self.token(&decl.command) + self.space(&decl.name),
+            TopLevel::When(decl) => self.when_decl(decl),
+            TopLevel::Test(decl) => {
+                self.token(&decl.test)
+                    + self.space(&decl.description)
+                    + self.space(&decl.equal)
+                    + self.body(&decl.body)
+            }
+        }
+    }
+
+    /// The declarations of a module, in a block. The imports that are next to each other are
+    /// sorted by their paths.
+    fn top_levels(&self, top_levels: &[TopLevel]) -> Doc {
+        self.block(self.declarations(top_levels))
+    }
+
+    fn declarations(&self, top_levels: &[TopLevel]) -> Vec<(bool, Doc)> {
+        let mut lines = Vec::new();
+        let mut index = 0;
+
+        while index < top_levels.len() {
+            let uses = top_levels[index..]
+                .iter()
+                .take_while(|x| matches!(x, TopLevel::Use(_)))
+                .count();
+
+            if uses == 0 {
+                let top_level = &top_levels[index];
+                let first = first_of_top_level(top_level).map(start);
+                lines.push(self.line(first, || self.top_level(top_level)));
+                index += 1;
+                continue;
+            }
+
+            let run = &top_levels[index..index + uses];
+
+            let mut sorted = run.iter().collect::<Vec<_>>();
+            sorted.sort_by_cached_key(|x| match x {
+                TopLevel::Use(decl) => Vec::<Symbol>::from(&decl.path)
+                    .iter()
+                    .map(|x| x.get())
+                    .collect::<Vec<_>>(),
+                _ => unreachable!(),
+            });
+
+            // The empty lines between the imports are removed, so they're one group, and the
+            // comments before the group stay before it, like the header of a module.
+            let (blank, mut header) =
+                self.line(first_of_top_level(&run[0]).map(start), || Doc::Nil);
+
+            for (position, top_level) in sorted.into_iter().enumerate() {
+                let first = first_of_top_level(top_level).map(start);
+                let (_, line) = self.line(first, || self.top_level(top_level));
+                let header = std::mem::replace(&mut header, Doc::Nil);
+                lines.push((position == 0 && blank, header + line));
+            }
+
+            index += uses;
+        }
+
+        lines
+    }
+
+    /// The formatted source of a module, that ends with a line feed unless it's empty.
+    pub fn program(&self, program: &Program) -> Doc {
+        let mut lines = Vec::new();
+
+        for (index, (blank, line)) in self
+            .declarations(&program.top_levels)
+            .into_iter()
+            .enumerate()
+        {
+            if index != 0 {
+                lines.push(if blank {
+                    Doc::hardline() + Doc::hardline()
+                } else {
+                    Doc::hardline()
+                });
+            }
+
+            lines.push(line);
+        }
+
+        // The comments at the end of the file are before its end of file.
+        let eof = start(&program.eof);
+
+        if let Some(comments) = self.trivia.leading.get(&eof) {
+            if !lines.is_empty() {
+                let blank = self.trivia.blank.contains(&eof);
+                lines.push(if blank {
+                    Doc::hardline() + Doc::hardline()
+                } else {
+                    Doc::hardline()
+                });
+            }
+
+            let comments = comments.iter().enumerate().map(|(index, (comment, _))| {
+                // The empty lines between the comments are kept.
+                let separator = match index {
+                    0 => Doc::Nil,
+                    _ if comments[index - 1].1 => Doc::hardline() + Doc::hardline(),
+                    _ => Doc::hardline(),
+                };
+
+                separator + Doc::text(comment.get())
+            });
+
+            lines.push(Doc::concat(comments));
+        }
+
+        if lines.is_empty() {
+            return Doc::Nil;
+        }
+
+        Doc::concat(lines) + Doc::hardline()
+    }
+}
+
+/// Checks if an expression ends with a block of the layout, like a lambda whose body is a `do`,
+/// or with delimiters whose items can break.
+fn hangs(expr: &Expr) -> bool {
+    match &expr.data {
+        ExprKind::Do(_) | ExprKind::When(_) | ExprKind::Cases(_) => true,
+        ExprKind::While(_) | ExprKind::For(_) => true,
+        ExprKind::List(_) | ExprKind::Array(_) => true,
+        ExprKind::RecordInstance(_) | ExprKind::RecordUpdate(_) => true,
+        ExprKind::Lambda(lambda) => hangs(&lambda.expr),
+        _ => false,
+    }
+}
+
+fn operator(operator: &Operator) -> &Token {
+    match operator {
+        Operator::Add(token)
+        | Operator::Sub(token)
+        | Operator::Mul(token)
+        | Operator::Div(token)
+        | Operator::Rem(token)
+        | Operator::And(token)
+        | Operator::Or(token)
+        | Operator::Xor(token)
+        | Operator::Not(token)
+        | Operator::Eq(token)
+        | Operator::Neq(token)
+        | Operator::Lt(token)
+        | Operator::Gt(token)
+        | Operator::Le(token)
+        | Operator::Ge(token)
+        | Operator::Shl(token)
+        | Operator::Shr(token)
+        | Operator::Pipe(token)
+        | Operator::Concat(token) => token,
+    }
+}
+
+fn start(token: &Token) -> usize {
+    token.value.span.start.0
+}
+
+fn first_of_signature(signature: &LetSignature) -> &Token {
+    match &signature.visibility {
+        Visibility::Public(token) => token,
+        Visibility::Private => &signature.let_,
+    }
+}
+
+fn attributes_of(top_level: &TopLevel) -> Option<&[DeclAttribute]> {
+    match top_level {
+        TopLevel::Let(decl) => Some(&decl.attributes),
+        TopLevel::Type(decl) => Some(&decl.attributes),
+        TopLevel::Effect(decl) => Some(&decl.attributes),
+        TopLevel::Trait(decl) => Some(&decl.attributes),
+        TopLevel::Module(decl) => Some(&decl.attributes),
+        TopLevel::External(decl) => Some(&decl.attributes),
+        _ => None,
+    }
+}
+
+fn first_of_top_level(top_level: &TopLevel) -> Option<&Token> {
+    if let Some(attribute) = attributes_of(top_level).and_then(|x| x.first()) {
+        return Some(&attribute.hash_bracket);
+    }
+
+    let (public, token) = match top_level {
+        TopLevel::Let(decl) => return Some(first_of_signature(&decl.signature)),
+        TopLevel::Pattern(decl) => (&decl.visibility, &decl.let_),
+        TopLevel::Type(decl) => (&decl.visibility, &decl.type_),
+        TopLevel::Effect(decl) => (&decl.visibility, decl.once.as_ref().unwrap_or(&decl.effect)),
+        TopLevel::Use(decl) => (&decl.visibility, &decl.use_),
+        TopLevel::Impl(decl) => return Some(&decl.impl_),
+        TopLevel::Trait(decl) => (&decl.visibility, &decl.trait_),
+        TopLevel::Module(decl) => (&decl.visibility, &decl.mod_),
+        TopLevel::Error(tokens) => return tokens.first(),
+        TopLevel::External(decl) => (&decl.visibility, &decl.external),
+        TopLevel::Command(decl) => return Some(&decl.command),
+        TopLevel::When(decl) => return Some(&decl.when),
+        TopLevel::Test(decl) => return Some(&decl.test),
+    };
+
+    match public {
+        Visibility::Public(token) => Some(token),
+        Visibility::Private => Some(token),
+    }
+}
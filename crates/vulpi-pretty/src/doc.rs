@@ -0,0 +1,218 @@
+//! Documents of the layout algorithm of Wadler's "A prettier printer". A document is text with
+//! lines that can break, and a [Doc::group] is laid out in a single line when it fits in the width,
+//! or with all of its lines broken when it doesn't.
+
+use std::ops::Add;
+
+#[derive(Clone, Debug)]
+pub enum Doc {
+    Nil,
+    Text(String),
+
+    /// A space in a single line, or a line break.
+    Line,
+
+    /// Nothing in a single line, or a line break.
+    Softline,
+
+    /// A line break that can't be laid out in a single line, so it breaks the groups around it.
+    Hardline,
+
+    /// Text that is put at the end of the line, before the next line break, like a comment after
+    /// a token. A group doesn't fit in a single line if there's text after it in the line.
+    Suffix(String),
+
+    /// Indents the lines that break inside of it.
+    Nest(usize, Box<Doc>),
+
+    Concat(Vec<Doc>),
+    Group(Box<Doc>),
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Mode {
+    Flat,
+    Break,
+}
+
+impl Doc {
+    pub fn text(text: impl Into<String>) -> Self {
+        Doc::Text(text.into())
+    }
+
+    pub fn line() -> Self {
+        Doc::Line
+    }
+
+    pub fn softline() -> Self {
+        Doc::Softline
+    }
+
+    pub fn hardline() -> Self {
+        Doc::Hardline
+    }
+
+    pub fn suffix(text: impl Into<String>) -> Self {
+        Doc::Suffix(text.into())
+    }
+
+    pub fn concat(docs: impl IntoIterator<Item = Doc>) -> Self {
+        Doc::Concat(docs.into_iter().collect())
+    }
+
+    /// The documents with a separator between each one of them.
+    pub fn join(docs: impl IntoIterator<Item = Doc>, separator: Doc) -> Self {
+        let mut result = Vec::new();
+
+        for (index, doc) in docs.into_iter().enumerate() {
+            if index != 0 {
+                result.push(separator.clone());
+            }
+
+            result.push(doc);
+        }
+
+        Doc::Concat(result)
+    }
+
+    pub fn append(self, other: Doc) -> Self {
+        match self {
+            Doc::Concat(mut docs) => {
+                docs.push(other);
+                Doc::Concat(docs)
+            }
+            doc => Doc::Concat(vec![doc, other]),
+        }
+    }
+
+    pub fn nest(self, indent: usize) -> Self {
+        Doc::Nest(indent, Box::new(self))
+    }
+
+    pub fn group(self) -> Self {
+        Doc::Group(Box::new(self))
+    }
+
+    /// Lays out the document in lines of at most `width` columns, when its texts allow it.
+    pub fn render(&self, width: usize) -> String {
+        let mut output = String::new();
+        let mut column = 0;
+        let mut suffix = String::new();
+        let mut stack = vec![(0, Mode::Break, self)];
+
+        while let Some((indent, mode, doc)) = stack.pop() {
+            match doc {
+                Doc::Nil => (),
+                Doc::Text(text) => {
+                    output.push_str(text);
+                    column += text.chars().count();
+                }
+                Doc::Line if mode == Mode::Flat => {
+                    output.push(' ');
+                    column += 1;
+                }
+                Doc::Softline if mode == Mode::Flat => (),
+                Doc::Suffix(text) => suffix.push_str(text),
+                Doc::Line | Doc::Softline | Doc::Hardline => {
+                    output.push_str(&std::mem::take(&mut suffix));
+
+                    // Lines that only have the indentation are left empty.
+                    output.truncate(output.trim_end_matches(' ').len());
+                    output.push('\n');
+                    output.extend(std::iter::repeat_n(' ', indent));
+                    column = indent;
+                }
+                Doc::Nest(nest, doc) => stack.push((indent + nest, mode, doc)),
+                Doc::Concat(docs) => stack.extend(docs.iter().rev().map(|x| (indent, mode, x))),
+                Doc::Group(doc) => {
+                    let width = width.saturating_sub(column);
+                    let flat = mode == Mode::Flat || fits(width, !suffix.is_empty(), doc, &stack);
+                    let mode = if flat { Mode::Flat } else { Mode::Break };
+                    stack.push((indent, mode, doc))
+                }
+            }
+        }
+
+        output.push_str(&suffix);
+        output
+    }
+}
+
+/// Checks if a group fits in a single line, with the documents after it up to their first line
+/// break. Nothing fits after a suffix, because it ends the line.
+fn fits(mut width: usize, mut suffix: bool, group: &Doc, rest: &[(usize, Mode, &Doc)]) -> bool {
+    let mut rest = rest.iter().rev().map(|(_, mode, doc)| (*mode, *doc));
+    let mut stack = vec![(Mode::Flat, group)];
+
+    loop {
+        let Some((mode, doc)) = stack.pop().or_else(|| rest.next()) else {
+            return true;
+        };
+
+        match doc {
+            Doc::Nil => (),
+            Doc::Suffix(_) => suffix = true,
+            Doc::Text(text) => {
+                let len = text.chars().count();
+
+                if len > width || (suffix && len != 0) {
+                    return false;
+                }
+
+                width -= len;
+            }
+            Doc::Line if mode == Mode::Flat => {
+                if width == 0 || suffix {
+                    return false;
+                }
+
+                width -= 1;
+            }
+            Doc::Softline if mode == Mode::Flat => (),
+            Doc::Hardline if mode == Mode::Flat => return false,
+            Doc::Line | Doc::Softline | Doc::Hardline => return true,
+            Doc::Nest(_, doc) => stack.push((mode, doc)),
+            Doc::Concat(docs) => stack.extend(docs.iter().rev().map(|x| (mode, x))),
+            Doc::Group(doc) => stack.push((mode, doc)),
+        }
+    }
+}
+
+impl Add for Doc {
+    type Output = Doc;
+
+    fn add(self, other: Doc) -> Doc {
+        self.append(other)
+    }
+}
+
+impl From<&str> for Doc {
+    fn from(text: &str) -> Self {
+        Doc::text(text)
+    }
+}
+
+impl From<String> for Doc {
+    fn from(text: String) -> Self {
+        Doc::Text(text)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn groups() {
+        let call = |args: &[&str]| {
+            let args = args.iter().map(|x| Doc::line() + Doc::text(*x));
+            (Doc::text("f") + Doc::concat(args).nest(2)).group()
+        };
+
+        assert_eq!(call(&["a", "b"]).render(10), "f a b");
+        assert_eq!(call(&["alpha", "beta"]).render(10), "f\n  alpha\n  beta");
+
+        let block = Doc::text("do") + (Doc::hardline() + call(&["a"])).nest(2);
+        assert_eq!(block.group().render(80), "do\n  f a");
+    }
+}
@@ -0,0 +1,21 @@
+//! Printing of the abstract tree back to the surface syntax of Vulpi. The trees are turned into a
+//! [Doc] that is laid out in a width, so the same printer gives compact source for snippets and
+//! readable source for whole modules.
+
+pub mod r#abstract;
+pub mod concrete;
+pub mod doc;
+
+pub use doc::Doc;
+
+/// The width that modules are printed in.
+pub const WIDTH: usize = 100;
+
+pub trait Pretty {
+    fn pretty(&self) -> Doc;
+
+    /// The source of the node, laid out in a width.
+    fn to_source(&self, width: usize) -> String {
+        self.pretty().render(width)
+    }
+}
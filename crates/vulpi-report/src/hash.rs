@@ -1,12 +1,12 @@
-//! Simple reporter for diagnostics using a hashmap to store things.
+//! Simple reporter for diagnostics using a map ordered by file to store things.
 
-use crate::{Diagnostic, Reporter};
-use std::collections::HashMap;
+use crate::{Diagnostic, Reporter, Severity};
+use std::collections::BTreeMap;
 use vulpi_location::FileId;
 
 #[derive(Default)]
 pub struct HashReporter {
-    map: HashMap<FileId, Vec<Diagnostic>>,
+    map: BTreeMap<FileId, Vec<Diagnostic>>,
     errored: bool,
 }
 
@@ -18,7 +18,7 @@ impl HashReporter {
 
 impl Reporter for HashReporter {
     fn report(&mut self, diagnostic: Diagnostic) {
-        self.errored = true;
+        self.errored |= matches!(diagnostic.severity(), Severity::Error);
         self.map
             .entry(diagnostic.location().file)
             .or_default()
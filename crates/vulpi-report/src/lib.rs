@@ -1,23 +1,30 @@
 //! Module for handling errors that can occur during the compilation process. It's used to report
 //! errors to the user.
 
-use std::{cell::RefCell, rc::Rc};
+use std::{
+    cell::RefCell,
+    collections::{HashMap, HashSet},
+    rc::Rc,
+};
 
-use renderer::{classic::Classic, Renderer};
+use renderer::{classic::Classic, json::Json, Renderer};
 use vulpi_location::{FileId, Span};
 
 pub mod hash;
 pub mod renderer;
 
-/// A type for representing the severity of a [Diagnostic].
+/// A type for representing the severity of a [Diagnostic]. Only errors make the compilation fail.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
 pub enum Severity {
     Error,
     Warning,
-    Info,
+    Note,
+    Help,
 }
 
 /// A type for representing the color of a [Word]. It's all numerated because it's easier to change
 /// the color of a word according to what the user wants.
+#[derive(Clone)]
 pub enum Color {
     Fst,
     Snd,
@@ -26,6 +33,7 @@ pub enum Color {
 }
 
 /// A type for representing the style of a [Word].
+#[derive(Clone)]
 pub enum Style {
     Bold,
     Dimmed,
@@ -33,9 +41,11 @@ pub enum Style {
 }
 
 /// A type for representing a word in a [Text].
+#[derive(Clone)]
 pub struct Word(Style, Color, String);
 
 /// A type for representing a text. It's used to generate error messages.
+#[derive(Clone)]
 pub enum Text {
     Phrase(Vec<Word>),
     Styled(Style, String),
@@ -44,6 +54,20 @@ pub enum Text {
     Break,
 }
 
+impl Text {
+    /// The text without its styles.
+    pub fn plain(&self) -> String {
+        match self {
+            Text::Phrase(words) => {
+                let words = words.iter().map(|Word(_, _, x)| x.as_str());
+                words.collect::<Vec<_>>().join(" ")
+            }
+            Text::Styled(_, text) | Text::Colored(_, text) | Text::Text(text) => text.clone(),
+            Text::Break => "\n".to_string(),
+        }
+    }
+}
+
 impl From<&str> for Text {
     fn from(s: &str) -> Self {
         Text::Text(s.to_owned())
@@ -58,23 +82,59 @@ impl From<String> for Text {
 
 /// A position in the source code that has or not a message. It's used to generate underlined parts
 /// with messages.
+#[derive(Clone)]
 pub struct Marker {
     pub position: Span,
     pub subtitle: Option<Text>,
 }
 
+/// A change to the source code that fixes a diagnostic. The text of the span is replaced by the
+/// replacement, so an empty span inserts it and an empty replacement removes the span.
+#[derive(Clone)]
+pub struct Suggestion {
+    pub span: Span,
+    pub replacement: String,
+}
+
 /// Errors that can occur during the compilation process.
 pub trait IntoDiagnostic {
+    /// The code that identifies the kind of the diagnostic, shown as `E0042`. The codes of each
+    /// crate of the compiler start at a different hundred: 0 for the lexer, 100 for the parser, 200
+    /// for the resolver, 300 for the type checker, 400 for the core language, 500 and 600 for the
+    /// JavaScript and native backends and 700 for the build system.
     fn code(&self) -> Option<usize> {
         None
     }
 
+    /// The name of the lint that the diagnostic belongs to. Lints are diagnostics about code that
+    /// is valid, so they can be allowed or turned into warnings by the user.
+    fn lint(&self) -> Option<&'static str> {
+        None
+    }
+
     fn hint(&self) -> Option<Text> {
         None
     }
 
     fn message(&self) -> Text;
 
+    /// A subtitle for the location of the diagnostic, when it has markers to tell them apart.
+    fn label(&self) -> Option<Text> {
+        None
+    }
+
+    /// Other positions that are related to the diagnostic, like the place where a name was first
+    /// bound. They're underlined with their subtitles next to the location of the diagnostic.
+    fn markers(&self) -> Vec<Marker> {
+        vec![]
+    }
+
+    /// Changes that fix the diagnostic and that can be applied without asking the user, like the
+    /// ones of `vulpi fix`.
+    fn suggestions(&self) -> Vec<Suggestion> {
+        vec![]
+    }
+
     fn severity(&self) -> Severity;
 
     fn location(&self) -> Span;
@@ -93,6 +153,10 @@ impl Diagnostic {
         self.0.code()
     }
 
+    pub fn lint(&self) -> Option<&'static str> {
+        self.0.lint()
+    }
+
     pub fn hint(&self) -> Option<Text> {
         self.0.hint()
     }
@@ -101,6 +165,18 @@ impl Diagnostic {
         self.0.message()
     }
 
+    pub fn label(&self) -> Option<Text> {
+        self.0.label()
+    }
+
+    pub fn markers(&self) -> Vec<Marker> {
+        self.0.markers()
+    }
+
+    pub fn suggestions(&self) -> Vec<Suggestion> {
+        self.0.suggestions()
+    }
+
     pub fn severity(&self) -> Severity {
         self.0.severity()
     }
@@ -110,6 +186,76 @@ impl Diagnostic {
     }
 }
 
+/// A diagnostic whose contents were computed, so it doesn't depend on the data of the compiler and
+/// can be sent to other threads. Reports of threads that compile parts of a crate are detached and
+/// merged into the report of the crate.
+#[derive(Clone)]
+pub struct Detached {
+    code: Option<usize>,
+    lint: Option<&'static str>,
+    hint: Option<Text>,
+    message: Text,
+    label: Option<Text>,
+    markers: Vec<Marker>,
+    suggestions: Vec<Suggestion>,
+    severity: Severity,
+    location: Span,
+}
+
+impl IntoDiagnostic for Detached {
+    fn code(&self) -> Option<usize> {
+        self.code
+    }
+
+    fn lint(&self) -> Option<&'static str> {
+        self.lint
+    }
+
+    fn hint(&self) -> Option<Text> {
+        self.hint.clone()
+    }
+
+    fn message(&self) -> Text {
+        self.message.clone()
+    }
+
+    fn label(&self) -> Option<Text> {
+        self.label.clone()
+    }
+
+    fn markers(&self) -> Vec<Marker> {
+        self.markers.clone()
+    }
+
+    fn suggestions(&self) -> Vec<Suggestion> {
+        self.suggestions.clone()
+    }
+
+    fn severity(&self) -> Severity {
+        self.severity
+    }
+
+    fn location(&self) -> Span {
+        self.location.clone()
+    }
+}
+
+impl Diagnostic {
+    pub fn detach(&self) -> Detached {
+        Detached {
+            code: self.code(),
+            lint: self.lint(),
+            hint: self.hint(),
+            message: self.message(),
+            label: self.label(),
+            markers: self.markers(),
+            suggestions: self.suggestions(),
+            severity: self.severity(),
+            location: self.location(),
+        }
+    }
+}
+
 /// A reporter is a structure that gets and record errors. It's used to store and report errors to
 /// the user.
 pub trait Reporter {
@@ -129,20 +275,99 @@ pub trait Reporter {
     fn has_errors(&self) -> bool;
 }
 
+/// What is done with the diagnostics of a lint.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Level {
+    /// The diagnostics are not reported.
+    Allow,
+
+    /// The diagnostics are reported as warnings.
+    Warn,
+}
+
+/// The levels of the lints that the user chose, and whether warnings make the compilation fail.
+#[derive(Clone, Default)]
+pub struct Lints {
+    levels: HashMap<&'static str, Level>,
+    deny_warnings: bool,
+}
+
+impl Lints {
+    pub fn set(&mut self, lint: &'static str, level: Level) {
+        self.levels.insert(lint, level);
+    }
+
+    pub fn level(&self, lint: &str) -> Option<Level> {
+        self.levels.get(lint).copied()
+    }
+
+    /// Makes the warnings errors, so they make the compilation fail.
+    pub fn deny_warnings(&mut self) {
+        self.deny_warnings = true;
+    }
+
+    /// The diagnostic with the levels applied, or none if its lint is allowed.
+    pub fn apply(&self, diagnostic: Diagnostic) -> Option<Diagnostic> {
+        if let Some(Level::Allow) = diagnostic.lint().and_then(|x| self.level(x)) {
+            return None;
+        }
+
+        if self.deny_warnings && diagnostic.severity() == Severity::Warning {
+            let mut detached = diagnostic.detach();
+            detached.severity = Severity::Error;
+            return Some(Diagnostic::new(detached));
+        }
+
+        Some(diagnostic)
+    }
+}
+
 /// A structure that stores and reports errors to the user. It's inside a Rc or Arc because it
 /// needs to be shared between all steps of the compiler
 #[derive(Clone)]
-pub struct Report(Rc<RefCell<dyn Reporter>>);
+pub struct Report(
+    Rc<RefCell<dyn Reporter>>,
+    Rc<RefCell<Lints>>,
+    Rc<RefCell<HashSet<Reported>>>,
+);
+
+/// The code and the primary span of a diagnostic that was reported. A name that cannot be found is
+/// looked up many times, and each one would report it again.
+type Reported = (usize, Span);
 
 impl Report {
     pub fn new(reporter: impl Reporter + 'static) -> Self {
-        Self(Rc::new(RefCell::new(reporter)))
+        Self(
+            Rc::new(RefCell::new(reporter)),
+            Default::default(),
+            Default::default(),
+        )
     }
 
+    /// Reports a diagnostic after the levels of the lints are applied to it. Diagnostics with the
+    /// same code and primary span as one that was reported before are dropped.
     pub fn report(&self, diagnostic: Diagnostic) {
+        let Some(diagnostic) = self.1.borrow().apply(diagnostic) else {
+            return;
+        };
+
+        if let Some(code) = diagnostic.code() {
+            if !self.2.borrow_mut().insert((code, diagnostic.location())) {
+                return;
+            }
+        }
+
         self.0.borrow_mut().report(diagnostic);
     }
 
+    pub fn lints(&self) -> Lints {
+        self.1.borrow().clone()
+    }
+
+    pub fn set_lints(&self, lints: Lints) {
+        *self.1.borrow_mut() = lints;
+    }
+
     pub fn diagnostics(&self, file: FileId) -> Vec<Diagnostic> {
         self.0.borrow().diagnostics(file).to_vec()
     }
@@ -153,23 +378,111 @@ impl Report {
 
     pub fn clear(&self, file: FileId) {
         self.0.borrow_mut().clear(file);
+        self.2.borrow_mut().retain(|(_, span)| span.file != file);
     }
 
     pub fn has_errors(&self) -> bool {
         self.0.borrow().has_errors()
     }
 
-    pub fn to_stderr(&self, ctx: Classic) {
-        if self.has_errors() {
+    /// Detaches all the diagnostics so they can be merged into a report of another thread.
+    pub fn detach(&self) -> Vec<Detached> {
+        self.all_diagnostics()
+            .iter()
+            .map(Diagnostic::detach)
+            .collect()
+    }
+
+    pub fn merge(&self, diagnostics: Vec<Detached>) {
+        for diagnostic in diagnostics {
+            self.report(Diagnostic::new(diagnostic));
+        }
+    }
+
+    /// Prints the diagnostics with code frames. Only the first errors that were reported are shown
+    /// when there is a limit, and the number of the other ones is shown after them.
+    pub fn to_stderr(&self, ctx: Classic, limit: Option<usize>) {
+        let mut errors = 0;
+
+        let diagnostics = self
+            .all_diagnostics()
+            .into_iter()
+            .filter(|diagnostic| {
+                if diagnostic.severity() != Severity::Error {
+                    return true;
+                }
+
+                errors += 1;
+                limit.is_none_or(|limit| errors <= limit)
+            })
+            .collect::<Vec<_>>();
+
+        if !diagnostics.is_empty() {
             eprintln!();
 
-            for diagnostic in self.all_diagnostics().iter().rev() {
+            for diagnostic in diagnostics.iter().rev() {
                 diagnostic.render(&ctx, &mut std::io::stderr()).unwrap();
             }
         }
+
+        if let Some(hidden) = limit.and_then(|limit| errors.checked_sub(limit)) {
+            if hidden != 0 {
+                let plural = if hidden == 1 { "" } else { "s" };
+                eprintln!("  and {} more error{}\n", hidden, plural);
+            }
+        }
+    }
+
+    /// Prints all the diagnostics as JSON, one in each line, in the same order as
+    /// [Report::to_stderr].
+    pub fn to_stdout_json(&self, ctx: Json) {
+        for diagnostic in self.all_diagnostics().iter().rev() {
+            diagnostic.render(&ctx, &mut std::io::stdout()).unwrap();
+        }
     }
 }
 
 pub fn hash_reporter() -> Report {
     Report::new(hash::HashReporter::new())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use vulpi_location::Byte;
+
+    fn error(code: Option<usize>, start: usize) -> Diagnostic {
+        Diagnostic::new(Detached {
+            code,
+            lint: None,
+            hint: None,
+            message: "cannot find 'x'".into(),
+            label: None,
+            markers: vec![],
+            suggestions: vec![],
+            severity: Severity::Error,
+            location: Span::new(FileId(0), Byte(start), Byte(start + 1)),
+        })
+    }
+
+    #[test]
+    fn reports_each_code_once_for_each_primary_span() {
+        let report = hash_reporter();
+
+        report.report(error(Some(200), 0));
+        report.report(error(Some(200), 0));
+        report.report(error(Some(302), 0));
+        report.report(error(Some(200), 4));
+        assert_eq!(report.all_diagnostics().len(), 3);
+
+        // Diagnostics without a code cannot be told apart, so all of them are kept.
+        report.report(error(None, 0));
+        report.report(error(None, 0));
+        assert_eq!(report.all_diagnostics().len(), 5);
+
+        // The diagnostics of a file that is checked again are reported again.
+        report.clear(FileId(0));
+        report.report(error(Some(200), 0));
+        assert_eq!(report.all_diagnostics().len(), 1);
+    }
+}
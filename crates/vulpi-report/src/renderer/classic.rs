@@ -1,9 +1,10 @@
-use std::path::PathBuf;
+use std::{collections::BTreeSet, path::PathBuf};
 
+use vulpi_location::{Byte, Encoding, FileId, LineIndex};
 use vulpi_vfs::FileSystem;
 use yansi::Paint;
 
-use crate::{renderer::LineGuide, Color, Diagnostic, Style, Text, Word};
+use crate::{Color, Diagnostic, Marker, Severity, Style, Suggestion, Text, Word};
 
 use super::Renderer;
 
@@ -82,82 +83,285 @@ impl<'a> Renderer<Classic<'a>> for Text {
     }
 }
 
-impl<'a> Renderer<Classic<'a>> for Diagnostic {
-    fn render(&self, ctx: &Classic<'a>, writer: &mut impl std::io::Write) -> std::io::Result<()> {
-        // At this point we are probably sure that the file exists, so we can unwrap.
-        let path = ctx.fs.path(self.location().file).unwrap();
-        let relative = path.strip_prefix(&ctx.cwd).unwrap();
+/// The lines shown before and after the lines that are underlined.
+const CONTEXT: usize = 1;
 
-        let content = ctx.fs.read(self.location().file).unwrap();
+/// A span of a diagnostic that is underlined in a code frame. The primary span is the location of
+/// the diagnostic and the secondary ones are its markers.
+struct Underline {
+    primary: bool,
+    start: (usize, usize),
+    end: (usize, usize),
+    label: Option<Text>,
+}
 
-        let range = self.location();
+impl Underline {
+    fn paint(&self, text: String) -> Paint<String> {
+        if self.primary {
+            Paint::new(text).bold().fg(yansi::Color::Red)
+        } else {
+            Paint::new(text).bold().fg(yansi::Color::Blue)
+        }
+    }
 
-        let line_guide = LineGuide::new(&content);
+    /// Labels that are plain text are shown in the color of the underline.
+    fn label(
+        &self,
+        ctx: &Classic,
+        writer: &mut impl std::io::Write,
+        label: &Text,
+    ) -> std::io::Result<()> {
+        match label {
+            Text::Text(text) => write!(writer, "{}", self.paint(text.clone())),
+            label => label.render(ctx, writer),
+        }
+    }
 
-        let start = line_guide.to_line_and_column(range.start).unwrap();
-        let end = line_guide.to_line_and_column(range.end).unwrap();
+    /// The columns that are underlined in a line. Spans of many lines underline the rest of their
+    /// first line and the start of their last line.
+    fn columns(&self, number: usize, line: &str) -> Option<(usize, usize)> {
+        let width = line.chars().count();
+        let column = |byte: usize| line[..byte.min(line.len())].chars().count();
+
+        let (start, end) = if self.start.0 == number && self.end.0 == number {
+            (column(self.start.1), column(self.end.1))
+        } else if self.start.0 == number {
+            (column(self.start.1), width)
+        } else if self.end.0 == number {
+            let indentation = line.chars().take_while(|x| x.is_whitespace()).count();
+            (indentation, column(self.end.1))
+        } else {
+            return None;
+        };
 
-        write!(
-            writer,
-            "  {} ",
-            yansi::Color::White
-                .style()
-                .bg(yansi::Color::Red)
-                .paint(" ERROR ")
-        )?;
+        // Empty spans, like the end of a file, still point at a column.
+        Some((start, end.max(start + 1)))
+    }
+}
 
-        self.message().render(ctx, writer)?;
+/// Renders the lines of a file with the markers that are in it underlined. The primary marker is
+/// the one whose flag is set.
+fn frame(
+    ctx: &Classic,
+    writer: &mut impl std::io::Write,
+    file: FileId,
+    markers: Vec<(Marker, bool)>,
+) -> std::io::Result<()> {
+    let (Ok(path), Ok(content)) = (ctx.fs.path(file), ctx.fs.read(file)) else {
+        return Ok(());
+    };
+
+    let relative = path.strip_prefix(&ctx.cwd).unwrap_or(path);
+    let index = LineIndex::new(&content);
+    let lines = content.lines().collect::<Vec<_>>();
+    let position = |byte: &Byte| {
+        let position = index.line_col(byte, Encoding::Utf8)?;
+        Some((position.line, position.column))
+    };
+
+    let underlines = markers
+        .into_iter()
+        .filter_map(|(marker, primary)| {
+            Some(Underline {
+                primary,
+                start: position(&marker.position.start)?,
+                end: position(&marker.position.end)?,
+                label: marker.subtitle,
+            })
+        })
+        .collect::<Vec<_>>();
+
+    let Some(first) = underlines.first() else {
+        return Ok(());
+    };
+
+    let arrow = Paint::new("┌─>").fg(yansi::Color::Cyan).dimmed();
+    let vbar = Paint::new("│").fg(yansi::Color::Cyan).dimmed();
+
+    writeln!(
+        writer,
+        "      {arrow} {}:{}:{} ",
+        relative.display(),
+        first.start.0 + 1,
+        first.start.1 + 1
+    )?;
+
+    writeln!(writer, "      {vbar} ")?;
+
+    let mut shown = BTreeSet::new();
+
+    for underline in &underlines {
+        let last = (underline.end.0 + CONTEXT).min(lines.len().saturating_sub(1));
+        shown.extend(underline.start.0.saturating_sub(CONTEXT)..=last);
+    }
 
-        let guide = Paint::new("┌─>").fg(yansi::Color::Cyan).dimmed();
+    let mut previous = None;
+
+    for number in shown {
+        let Some(line) = lines.get(number) else {
+            continue;
+        };
+
+        if previous.is_some_and(|x| x + 1 != number) {
+            writeln!(writer, "  {:>3} {vbar} ", "...")?;
+        }
+
+        previous = Some(number);
+
+        writeln!(writer, "  {:>3} {vbar} {}", number + 1, line)?;
+
+        let mut marked = underlines
+            .iter()
+            .filter_map(|x| x.columns(number, line).map(|columns| (x, columns)))
+            .collect::<Vec<_>>();
+
+        if marked.is_empty() {
+            continue;
+        }
+
+        marked.sort_by_key(|(_, (start, _))| *start);
+
+        write!(writer, "      {vbar} ")?;
+
+        let mut cursor = 0;
+
+        for (underline, (start, end)) in &marked {
+            let start = (*start).max(cursor);
+
+            if start >= *end {
+                continue;
+            }
+
+            let mark = if underline.primary { "^" } else { "-" };
+
+            write!(writer, "{}", " ".repeat(start - cursor))?;
+            write!(writer, "{}", underline.paint(mark.repeat(end - start)))?;
+
+            cursor = *end;
+        }
+
+        // The labels are shown at the last line of their spans. The label of the rightmost
+        // underline is shown after it and the others below the underlines, at their columns.
+        let mut labeled = marked
+            .iter()
+            .filter(|(x, _)| x.end.0 == number)
+            .filter_map(|(x, (start, _))| x.label.as_ref().map(|label| (*x, *start, label)))
+            .collect::<Vec<_>>();
+
+        let rightmost = marked.iter().map(|(_, (start, _))| *start).max();
+
+        match labeled.last() {
+            Some((underline, start, label)) if Some(*start) == rightmost => {
+                write!(writer, " ")?;
+                underline.label(ctx, writer, label)?;
+                labeled.pop();
+            }
+            _ => (),
+        }
 
         writeln!(writer)?;
-        writeln!(writer)?;
-        writeln!(
+
+        for (underline, start, label) in labeled.into_iter().rev() {
+            write!(writer, "      {vbar} {}", " ".repeat(start))?;
+            underline.label(ctx, writer, label)?;
+            writeln!(writer)?;
+        }
+    }
+
+    Ok(())
+}
+
+/// A description of a suggestion in a line, like "insert `pub `" or "remove lines 3 to 5".
+fn describe(ctx: &Classic, suggestion: &Suggestion) -> String {
+    let Suggestion { span, replacement } = suggestion;
+
+    let replacement = replacement.lines().next().unwrap_or_default();
+
+    if span.start == span.end {
+        return format!("insert `{}`", replacement);
+    }
+
+    if !replacement.is_empty() {
+        return format!("replace with `{}`", replacement);
+    }
+
+    let content = ctx.fs.read(span.file).unwrap_or_default();
+    let index = LineIndex::new(&content);
+
+    match index.span(span, Encoding::Utf8) {
+        Some((start, end)) if start.line == end.line => format!("remove line {}", start.line + 1),
+        Some((start, end)) => format!("remove lines {} to {}", start.line + 1, end.line + 1),
+        None => "remove it".to_string(),
+    }
+}
+
+impl<'a> Renderer<Classic<'a>> for Diagnostic {
+    fn render(&self, ctx: &Classic<'a>, writer: &mut impl std::io::Write) -> std::io::Result<()> {
+        let (label, color) = match self.severity() {
+            Severity::Error => (" ERROR ", yansi::Color::Red),
+            Severity::Warning => (" WARNING ", yansi::Color::Yellow),
+            Severity::Note => (" NOTE ", yansi::Color::Blue),
+            Severity::Help => (" HELP ", yansi::Color::Green),
+        };
+
+        write!(
             writer,
-            "      {guide} {}:{}:{} ",
-            relative.display(),
-            start.0 + 1,
-            start.1 + 1
+            "  {} ",
+            yansi::Color::White.style().bg(color).paint(label)
         )?;
 
-        let vbar = Paint::new("│").fg(yansi::Color::Cyan).dimmed();
+        if let Some(code) = self.code() {
+            write!(
+                writer,
+                "{} ",
+                Paint::new(format!("[E{:04}]", code)).dimmed()
+            )?;
+        }
 
-        writeln!(writer, "      {vbar} ")?;
+        if let Some(lint) = self.lint() {
+            write!(writer, "{} ", Paint::new(format!("[{}]", lint)).dimmed())?;
+        }
 
-        let is_inline = start.0 == end.0;
+        self.message().render(ctx, writer)?;
 
-        let lines = content.lines().collect::<Vec<_>>();
+        writeln!(writer)?;
+        writeln!(writer)?;
 
-        let minimum = start.0.saturating_sub(2);
-        let maximum = (end.0 + 2).min(lines.len());
+        // The markers of each file are shown in a frame of their own, starting with the file of
+        // the location of the diagnostic.
+        let location = Marker {
+            position: self.location(),
+            subtitle: self.label(),
+        };
 
-        for (i, line) in lines[minimum..maximum].iter().enumerate() {
-            let line_number = minimum + i + 1;
+        let markers = self.markers().into_iter().map(|x| (x, false));
 
-            write!(writer, "  {:>3} {vbar} ", line_number)?;
+        let mut files: Vec<(FileId, Vec<(Marker, bool)>)> = Vec::new();
 
-            if is_inline && line_number == start.0 + 1 {
-                let line = line.to_string();
+        for (marker, primary) in std::iter::once((location, true)).chain(markers) {
+            let file = marker.position.file;
 
-                writeln!(writer, "{}", line)?;
+            match files.iter_mut().find(|(x, _)| *x == file) {
+                Some((_, markers)) => markers.push((marker, primary)),
+                None => files.push((file, vec![(marker, primary)])),
+            }
+        }
 
-                writeln!(
-                    writer,
-                    "      {vbar} {}{}",
-                    " ".repeat(start.1),
-                    Paint::new("^".repeat(end.1 - start.1))
-                        .bold()
-                        .fg(yansi::Color::Red)
-                )?;
-            } else if is_inline && line_number == end.0 + 1 {
-                let mut line = line.to_string();
+        for (file, markers) in files {
+            frame(ctx, writer, file, markers)?;
+        }
 
-                line.insert(end.1 + 1, '^');
+        if let Some(hint) = self.hint() {
+            let equals = Paint::new("=").fg(yansi::Color::Cyan).dimmed();
+            write!(writer, "      {equals} hint: ")?;
+            hint.render(ctx, writer)?;
+            writeln!(writer)?;
+        }
 
-                writeln!(writer, "{}", line)?;
-            } else {
-                writeln!(writer, "{}", line)?;
-            }
+        for suggestion in self.suggestions() {
+            let equals = Paint::new("=").fg(yansi::Color::Cyan).dimmed();
+            let fix = describe(ctx, &suggestion);
+            writeln!(writer, "      {equals} fix: {}", Paint::new(fix).bold())?;
         }
 
         writeln!(writer)
@@ -0,0 +1,118 @@
+//! Renderer of diagnostics as JSON, for editors and other tools that read the output of the
+//! compiler. Each diagnostic is an object in a line of its own:
+//!
+//! ```json
+//! {"children":[...],"code":"E0203","fixes":[],"lint":null,"message":"duplicate pattern: a",...}
+//! ```
+//!
+//! Spans have the offsets of their bytes and their lines and columns, that start at one and count
+//! characters. The markers of a diagnostic are its children, with the severity `note`, and its hint
+//! is a child with the severity `help` and no span. Its fixes have a span and the text that replaces
+//! it.
+
+use std::path::PathBuf;
+
+use serde_json::{json, Value};
+use vulpi_location::{Byte, Encoding, LineIndex, Span};
+use vulpi_vfs::FileSystem;
+
+use crate::{Diagnostic, Severity, Text};
+
+use super::{Reader, Renderer};
+
+pub struct Json<'a> {
+    fs: &'a dyn FileSystem<Path = PathBuf>,
+    cwd: PathBuf,
+}
+
+impl<'a> Json<'a> {
+    pub fn new(fs: &'a (dyn FileSystem<Path = PathBuf> + 'static), cwd: PathBuf) -> Self {
+        Self { fs, cwd }
+    }
+
+    fn span(&self, span: &Span, label: Option<&Text>) -> Value {
+        let path = self.fs.path(span.file).ok();
+        let file = path.map(|x| x.strip_prefix(&self.cwd).unwrap_or(x).display().to_string());
+
+        let content = self.fs.read(span.file).unwrap_or_default();
+        let index = LineIndex::new(&content);
+
+        let position = |byte: &Byte| {
+            let position = index.line_col(byte, Encoding::Utf32)?;
+            Some((position.line + 1, position.column + 1))
+        };
+
+        let start = position(&span.start);
+        let end = position(&span.end);
+
+        json!({
+            "file": file,
+            "byte_start": span.start.0,
+            "byte_end": span.end.0,
+            "line_start": start.map(|x| x.0),
+            "column_start": start.map(|x| x.1),
+            "line_end": end.map(|x| x.0),
+            "column_end": end.map(|x| x.1),
+            "label": label.map(|x| plain(self, x)),
+        })
+    }
+}
+
+/// The text without its styles.
+fn plain(ctx: &Json, text: &Text) -> String {
+    let mut reader = Reader::default();
+    text.render(ctx, &mut reader).unwrap();
+    reader.to_string()
+}
+
+impl<'a> Renderer<Json<'a>> for Text {
+    fn render(&self, _: &Json<'a>, writer: &mut impl std::io::Write) -> std::io::Result<()> {
+        write!(writer, "{}", self.plain())
+    }
+}
+
+impl<'a> Renderer<Json<'a>> for Diagnostic {
+    fn render(&self, ctx: &Json<'a>, writer: &mut impl std::io::Write) -> std::io::Result<()> {
+        let severity = match self.severity() {
+            Severity::Error => "error",
+            Severity::Warning => "warning",
+            Severity::Note => "note",
+            Severity::Help => "help",
+        };
+
+        let mut children = self
+            .markers()
+            .iter()
+            .map(|marker| {
+                json!({
+                    "severity": "note",
+                    "message": marker.subtitle.as_ref().map(|x| plain(ctx, x)).unwrap_or_default(),
+                    "spans": [ctx.span(&marker.position, marker.subtitle.as_ref())],
+                })
+            })
+            .collect::<Vec<_>>();
+
+        if let Some(hint) = self.hint() {
+            children.push(json!({
+                "severity": "help",
+                "message": plain(ctx, &hint),
+                "spans": [],
+            }));
+        }
+
+        let value = json!({
+            "code": self.code().map(|x| format!("E{:04}", x)),
+            "severity": severity,
+            "lint": self.lint(),
+            "message": plain(ctx, &self.message()).trim_end(),
+            "spans": [ctx.span(&self.location(), self.label().as_ref())],
+            "children": children,
+            "fixes": self.suggestions().iter().map(|x| json!({
+                "span": ctx.span(&x.span, None),
+                "replacement": x.replacement,
+            })).collect::<Vec<_>>(),
+        });
+
+        writeln!(writer, "{}", value)
+    }
+}
@@ -1,16 +1,46 @@
 use vulpi_intern::Symbol;
-use vulpi_location::Span;
-use vulpi_report::IntoDiagnostic;
-use vulpi_syntax::r#abstract::Qualified;
+use vulpi_location::{Byte, Span};
+use vulpi_report::{IntoDiagnostic, Marker, Suggestion};
+use vulpi_vfs::path::Path;
+
+use crate::Definition;
+
+/// The lint of the uses of deprecated definitions outside of the modules that declare them.
+pub const DEPRECATED: &str = "deprecated";
 
 pub enum ResolverErrorKind {
     NotFound(Symbol),
     ListIsNotAvailable,
     InvalidPath(Vec<Symbol>),
-    DuplicatePattern(Symbol),
-    PrivateDefinition,
-    CycleBetweenConstants(Vec<Qualified>),
+    /// A name that is bound twice in a pattern, with the span where it was first bound.
+    DuplicatePattern(Symbol, Span),
+    /// A definition that is not public, with its spans when it's not an alias.
+    PrivateDefinition(Option<Definition>),
     NotImplemented(Symbol, Symbol),
+    HandlerAsValue(Symbol),
+    /// A name that is not found but that is declared as public in a module that is not imported.
+    NotImported(Symbol, Path),
+    /// A local variable that the guard of an effect pattern uses but that the pattern doesn't bind.
+    OuterVariableInGuard(Symbol),
+    /// A part of the pattern of a top level declaration that may not match its value.
+    RefutablePattern,
+    /// A statement at the end of a qualified `do` that has no value to result in.
+    StatementAtEndOfDo,
+    /// A variable that some alternatives of an or-pattern bind but that the other ones don't.
+    OrPatternVariable(Symbol),
+    UnknownAttribute(Symbol),
+    /// An attribute that is known but written with the wrong arguments.
+    MalformedAttribute(Symbol),
+    /// A use of a deprecated definition, with the message of its attribute.
+    Deprecated(Symbol, Option<Symbol>),
+    /// An attribute that is known but that cannot be written before the declaration.
+    MisplacedAttribute(Symbol),
+    /// A constructor of an opaque type, with the name of the type, used outside of its module.
+    OpaqueDefinition(Symbol, Symbol),
+    /// A newtype that doesn't have exactly one constructor with exactly one argument.
+    InvalidNewtype(Symbol),
+    /// A coercion of a newtype, with the name of the type, used outside of its module.
+    ForeignCoercion(Symbol, Symbol),
 }
 
 pub struct ResolverError {
@@ -28,27 +58,214 @@ impl IntoDiagnostic for ResolverError {
             )
             .into(),
             ResolverErrorKind::ListIsNotAvailable => "List is not available".into(), 
-            ResolverErrorKind::NotFound(name) => format!("cannot find '{}'", name.get()).into(),
+            ResolverErrorKind::NotFound(name) | ResolverErrorKind::NotImported(name, _) => {
+                format!("cannot find '{}'", name.get()).into()
+            }
             ResolverErrorKind::InvalidPath(name) => format!(
                 "the path '{}' cannot be found",
                 name.iter().map(|s| s.get()).collect::<Vec<_>>().join(".")
             )
             .into(),
-            ResolverErrorKind::DuplicatePattern(name) => {
+            ResolverErrorKind::DuplicatePattern(name, _) => {
                 format!("duplicate pattern: {}", name.get()).into()
             }
-            ResolverErrorKind::PrivateDefinition => "private definition".into(),
-            ResolverErrorKind::CycleBetweenConstants(cycle) => {
-                let mut cycle = cycle.iter().map(|q| q.to_string()).collect::<Vec<_>>();
-                cycle.sort_by_key(|k| k.to_string());
+            ResolverErrorKind::PrivateDefinition(_) => "private definition".into(),
+            ResolverErrorKind::OuterVariableInGuard(name) => format!(
+                "the guard of an effect pattern cannot use '{}'",
+                name.get()
+            )
+            .into(),
+            ResolverErrorKind::HandlerAsValue(name) => format!(
+                "the handler '{}' can only be used to perform operations",
+                name.get()
+            )
+            .into(),
+            ResolverErrorKind::RefutablePattern => {
+                "the pattern of a top level declaration must always match".into()
+            }
+            ResolverErrorKind::StatementAtEndOfDo => {
+                "a qualified `do` must end with an expression".into()
+            }
+            ResolverErrorKind::OrPatternVariable(name) => format!(
+                "the variable '{}' is not bound by every alternative of the pattern",
+                name.get()
+            )
+            .into(),
+            ResolverErrorKind::UnknownAttribute(name) => {
+                format!("unknown attribute '{}'", name.get()).into()
+            }
+            ResolverErrorKind::MalformedAttribute(name) => {
+                format!("malformed attribute '{}'", name.get()).into()
+            }
+            ResolverErrorKind::Deprecated(name, _) => {
+                format!("'{}' is deprecated", name.get()).into()
+            }
+            ResolverErrorKind::MisplacedAttribute(name) => {
+                let place = if name.get() == "pure" { "a let or an external" } else { "a type" };
+                let name = name.get();
+                format!("the attribute '{name}' can only be written before {place}").into()
+            }
+            ResolverErrorKind::OpaqueDefinition(name, typ) => format!(
+                "'{}' cannot be used outside of the module of the opaque type '{}'",
+                name.get(),
+                typ.get()
+            )
+            .into(),
+            ResolverErrorKind::InvalidNewtype(name) => format!(
+                "the newtype '{}' must have one constructor with one argument",
+                name.get()
+            )
+            .into(),
+            ResolverErrorKind::ForeignCoercion(name, typ) => format!(
+                "the coercion '{}' of the newtype '{}' can only be used inside of its module",
+                name.get(),
+                typ.get()
+            )
+            .into(),
+        }
+    }
+
+    fn code(&self) -> Option<usize> {
+        match &self.kind {
+            ResolverErrorKind::NotFound(_) => Some(200),
+            ResolverErrorKind::ListIsNotAvailable => Some(201),
+            ResolverErrorKind::InvalidPath(_) => Some(202),
+            ResolverErrorKind::DuplicatePattern(_, _) => Some(203),
+            ResolverErrorKind::PrivateDefinition(_) => Some(204),
+            ResolverErrorKind::NotImplemented(_, _) => Some(206),
+            ResolverErrorKind::HandlerAsValue(_) => Some(207),
+            ResolverErrorKind::NotImported(_, _) => Some(208),
+            ResolverErrorKind::OuterVariableInGuard(_) => Some(209),
+            ResolverErrorKind::RefutablePattern => Some(210),
+            ResolverErrorKind::StatementAtEndOfDo => Some(211),
+            ResolverErrorKind::OrPatternVariable(_) => Some(212),
+            ResolverErrorKind::UnknownAttribute(_) => Some(213),
+            ResolverErrorKind::MalformedAttribute(_) => Some(214),
+            ResolverErrorKind::Deprecated(_, _) => Some(215),
+            ResolverErrorKind::MisplacedAttribute(_) => Some(216),
+            ResolverErrorKind::OpaqueDefinition(_, _) => Some(217),
+            ResolverErrorKind::InvalidNewtype(_) => Some(218),
+            ResolverErrorKind::ForeignCoercion(_, _) => Some(219),
+        }
+    }
+
+    fn hint(&self) -> Option<vulpi_report::Text> {
+        match &self.kind {
+            ResolverErrorKind::NotImported(name, path) => {
+                Some(format!("'{}' is declared in '{}'", name.get(), path).into())
+            }
+            ResolverErrorKind::OuterVariableInGuard(_) => Some(
+                "only the arguments and the continuation that the pattern binds can be used".into(),
+            ),
+            ResolverErrorKind::RefutablePattern => {
+                Some("use a `when` in the body to handle the values that don't match".into())
+            }
+            ResolverErrorKind::StatementAtEndOfDo => {
+                Some("the last statement is the value of the whole block".into())
+            }
+            ResolverErrorKind::OrPatternVariable(_) => {
+                Some("bind it in the other alternatives or replace it by a wildcard".into())
+            }
+            ResolverErrorKind::UnknownAttribute(_) => {
+                Some("the attributes are `allow`, `deprecated` and `opaque`".into())
+            }
+            ResolverErrorKind::MalformedAttribute(name) if name.get() == "allow" => {
+                Some("write the lints between parentheses, like `#[allow(unused)]`".into())
+            }
+            ResolverErrorKind::MalformedAttribute(name) if name.get() == "opaque" => {
+                Some("it takes no arguments, so it's written as `#[opaque]`".into())
+            }
+            ResolverErrorKind::MalformedAttribute(_) => {
+                Some("it takes an optional message, like `#[deprecated \"use other\"]`".into())
+            }
+            ResolverErrorKind::Deprecated(_, Some(message)) => Some(message.get().into()),
+            ResolverErrorKind::OpaqueDefinition(_, _) => {
+                Some("use the functions of its module to build and inspect its values".into())
+            }
+            ResolverErrorKind::InvalidNewtype(_) => {
+                Some("declare it with `type` to give it other constructors".into())
+            }
+            ResolverErrorKind::ForeignCoercion(_, _) => {
+                Some("use its constructor to build values and a `when` to inspect them".into())
+            }
+            _ => None,
+        }
+    }
+
+    fn label(&self) -> Option<vulpi_report::Text> {
+        match &self.kind {
+            ResolverErrorKind::PrivateDefinition(Some(_)) => Some("used here".into()),
+            ResolverErrorKind::RefutablePattern => Some("this may not match".into()),
+            _ => None,
+        }
+    }
+
+    fn markers(&self) -> Vec<Marker> {
+        match &self.kind {
+            ResolverErrorKind::DuplicatePattern(_, first) => vec![Marker {
+                position: first.clone(),
+                subtitle: Some("first bound here".into()),
+            }],
+            ResolverErrorKind::PrivateDefinition(Some(definition))
+                if definition.name.start != definition.name.end =>
+            {
+                let mut markers = vec![Marker {
+                    position: definition.name.clone(),
+                    subtitle: Some("declared private here".into()),
+                }];
+
+                // Fields and constructors have no keyword other than their names.
+                if definition.keyword.start != definition.name.start {
+                    markers.push(Marker {
+                        position: definition.keyword.clone(),
+                        subtitle: Some("without `pub`".into()),
+                    });
+                }
+
+                markers
+            }
+            _ => vec![],
+        }
+    }
 
-                format!("cycle between '{}'", cycle.join(" -> ")).into()
+    fn suggestions(&self) -> Vec<Suggestion> {
+        match &self.kind {
+            // Definitions without a span, like the primitive ones, cannot be changed.
+            ResolverErrorKind::PrivateDefinition(Some(definition))
+                if definition.name.start != definition.name.end =>
+            {
+                vec![Suggestion {
+                    span: Span {
+                        end: definition.keyword.start.clone(),
+                        ..definition.keyword.clone()
+                    },
+                    replacement: "pub ".to_string(),
+                }]
             }
+            ResolverErrorKind::NotImported(_, path) => vec![Suggestion {
+                span: Span {
+                    file: self.span.file,
+                    start: Byte(0),
+                    end: Byte(0),
+                },
+                replacement: format!("use {}\n", path),
+            }],
+            _ => vec![],
+        }
+    }
+
+    fn lint(&self) -> Option<&'static str> {
+        match &self.kind {
+            ResolverErrorKind::Deprecated(_, _) => Some(DEPRECATED),
+            _ => None,
         }
     }
 
     fn severity(&self) -> vulpi_report::Severity {
-        vulpi_report::Severity::Error
+        match &self.kind {
+            ResolverErrorKind::Deprecated(_, _) => vulpi_report::Severity::Warning,
+            _ => vulpi_report::Severity::Error,
+        }
     }
 
     fn location(&self) -> Span {
@@ -2,10 +2,11 @@
 //! syntax tree with all the names resolved.
 
 use std::cell::{Ref, RefMut};
-use std::collections::HashMap;
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
 use std::{cell::RefCell, rc::Rc};
 
 use petgraph::prelude::DiGraph;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
 use petgraph::stable_graph::NodeIndex;
 
 use vulpi_intern::Symbol;
@@ -15,12 +16,15 @@ use vulpi_syntax::concrete::tree::LetMode;
 use vulpi_syntax::concrete::{self, tree};
 use vulpi_syntax::r#abstract as abs;
 use vulpi_syntax::r#abstract::Visibility;
+use vulpi_syntax::tokens::{Token, TokenData};
+use vulpi_show::{Show, TreeDisplay};
 use vulpi_vfs::path::{Path, Qualified};
 
-pub mod cycle;
 pub mod dependencies;
 mod error;
 
+pub use error::DEPRECATED;
+
 pub enum Either<L, R> {
     Left(L),
     Right(R),
@@ -40,7 +44,7 @@ pub enum DefinitionKind {
 }
 
 /// Definition bag is a bag of definitions. It is used to store the definitions of a module.
-#[derive(Default, Clone)]
+#[derive(Default, Clone, Serialize, Deserialize)]
 pub struct Bag<V> {
     pub types: V,
     pub values: V,
@@ -59,17 +63,126 @@ impl<V> Bag<V> {
 
 pub type Alias = (Qualified, abs::Visibility);
 
+/// A declared name, with the spans that the diagnostics about its visibility point at.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Definition {
+    pub visibility: abs::Visibility,
+
+    /// The keyword of the declaration, like `let` or `type`, that the visibility is written before.
+    /// It's the name itself for the fields and the constructors.
+    pub keyword: Span,
+
+    /// The name in the declaration.
+    pub name: Span,
+
+    /// Set when the declaration is marked with `#[deprecated]`.
+    pub deprecated: Option<Deprecated>,
+}
+
+/// The deprecation of a definition. The uses of the definition outside of the module where it's
+/// declared are warned about, with the message that tells what to use instead.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Deprecated {
+    pub module: Path,
+    pub message: Option<Symbol>,
+}
+
 /// Namespace of a module.
+#[derive(Serialize, Deserialize)]
 pub struct Namespace {
     name: Path,
-    declared: Bag<HashMap<Symbol, abs::Visibility>>,
-    constants: HashMap<abs::Qualified, HashMap<abs::Qualified, Span>>,
-    traits: HashMap<Symbol, HashMap<Symbol, Span>>,
-
-    aliases: Bag<HashMap<Symbol, Alias>>,
-    modules: HashMap<Symbol, (Path, abs::Visibility)>,
-    submodules: HashMap<Symbol, Module>,
-    opened: HashMap<Path, Visibility>,
+    declared: Bag<BTreeMap<Symbol, Definition>>,
+    constants: BTreeMap<abs::Qualified, BTreeMap<abs::Qualified, Span>>,
+    traits: BTreeMap<Symbol, BTreeMap<Symbol, Span>>,
+
+    aliases: Bag<BTreeMap<Symbol, Alias>>,
+    modules: BTreeMap<Symbol, (Path, abs::Visibility)>,
+    submodules: BTreeMap<Symbol, Module>,
+    opened: BTreeMap<Path, Visibility>,
+
+    /// The types of the namespace that are records, whose fields are declared in their submodules.
+    records: BTreeSet<Symbol>,
+
+    /// Set in the namespace of an opaque type, whose constructors can only be used by the module
+    /// that declares the type.
+    opaque: bool,
+
+    /// Set in the namespace of a newtype, whose coercions can only be used by the module that
+    /// declares the type.
+    newtype: bool,
+
+    /// The spans of the names in the module that were resolved to each declaration. They're not
+    /// a part of the interface of the module.
+    #[serde(skip)]
+    references: BTreeMap<abs::Qualified, Vec<Span>>,
+}
+
+/// Shows the names of a namespace sorted, so the output doesn't depend on the order of the maps.
+impl Show for Namespace {
+    fn show(&self) -> TreeDisplay {
+        fn visibility(visibility: &abs::Visibility) -> &'static str {
+            match visibility {
+                abs::Visibility::Public => "pub ",
+                abs::Visibility::Super => "pub(super) ",
+                abs::Visibility::Private => "",
+            }
+        }
+
+        fn section(label: &str, mut lines: Vec<String>) -> TreeDisplay {
+            lines.sort();
+
+            lines
+                .iter()
+                .fold(TreeDisplay::label(label), |tree, line| {
+                    tree.with(TreeDisplay::label(line))
+                })
+        }
+
+        fn declared(map: &BTreeMap<Symbol, Definition>) -> Vec<String> {
+            map.iter()
+                .map(|(name, x)| format!("{}{}", visibility(&x.visibility), name.get()))
+                .collect()
+        }
+
+        fn aliases(map: &BTreeMap<Symbol, Alias>) -> Vec<String> {
+            map.iter()
+                .map(|(name, (qualified, vis))| {
+                    let path = qualified.path.with(qualified.name.clone());
+                    format!("{}{} => {}", visibility(vis), name.get(), path)
+                })
+                .collect()
+        }
+
+        let submodules = self
+            .submodules
+            .values()
+            .fold(TreeDisplay::label("Submodules"), |tree, module| {
+                tree.with(module.borrow().show())
+            });
+
+        let modules = self
+            .modules
+            .iter()
+            .map(|(name, (path, vis))| format!("{}{} => {}", visibility(vis), name.get(), path))
+            .collect();
+
+        let opened = self
+            .opened
+            .iter()
+            .map(|(path, vis)| format!("{}{}", visibility(vis), path))
+            .collect();
+
+        TreeDisplay::label(&format!("Namespace {}", self.name))
+            .with(section("Types", declared(&self.declared.types)))
+            .with(section("Values", declared(&self.declared.values)))
+            .with(section("Traits", declared(&self.declared.traits)))
+            .with(section("Type aliases", aliases(&self.aliases.types)))
+            .with(section("Value aliases", aliases(&self.aliases.values)))
+            .with(section("Trait aliases", aliases(&self.aliases.traits)))
+            .with(section("Modules", modules))
+            .with(section("Opened", opened))
+            .with(submodules)
+    }
 }
 
 pub fn from_upper_path(path: &concrete::Path<concrete::Upper>) -> Path {
@@ -115,13 +228,26 @@ pub fn from_constructor_upper_path(path: &concrete::Path<concrete::Upper>) -> Qu
 #[derive(Clone)]
 pub struct Module(Rc<RefCell<Namespace>>);
 
+impl Serialize for Module {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        self.borrow().serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for Module {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let namespace = Namespace::deserialize(deserializer)?;
+        Ok(Module(Rc::new(RefCell::new(namespace))))
+    }
+}
+
 /// Getters for the namespace.
 impl Module {
-    pub fn modules_mut(&self) -> RefMut<'_, HashMap<Symbol, (Path, abs::Visibility)>> {
+    pub fn modules_mut(&self) -> RefMut<'_, BTreeMap<Symbol, (Path, abs::Visibility)>> {
         std::cell::RefMut::map(self.borrow_mut(), |this| &mut this.modules)
     }
 
-    pub fn modules(&self) -> Ref<'_, HashMap<Symbol, (Path, abs::Visibility)>> {
+    pub fn modules(&self) -> Ref<'_, BTreeMap<Symbol, (Path, abs::Visibility)>> {
         std::cell::Ref::map(self.borrow(), |this| &this.modules)
     }
 
@@ -129,23 +255,27 @@ impl Module {
         std::cell::Ref::map(self.borrow(), |this| &this.name)
     }
 
-    fn declared(&self) -> Ref<'_, Bag<HashMap<Symbol, abs::Visibility>>> {
+    pub fn declared(&self) -> Ref<'_, Bag<BTreeMap<Symbol, Definition>>> {
         std::cell::Ref::map(self.borrow(), |this| &this.declared)
     }
 
-    fn aliases(&self) -> Ref<'_, Bag<HashMap<Symbol, Alias>>> {
+    fn aliases(&self) -> Ref<'_, Bag<BTreeMap<Symbol, Alias>>> {
         std::cell::Ref::map(self.borrow(), |this| &this.aliases)
     }
 
-    fn opened(&self) -> Ref<'_, HashMap<Path, abs::Visibility>> {
+    pub fn opened(&self) -> Ref<'_, BTreeMap<Path, abs::Visibility>> {
         std::cell::Ref::map(self.borrow(), |this| &this.opened)
     }
 
-    fn traits(&self) -> RefMut<'_, HashMap<Symbol, HashMap<Symbol, Span>>> {
+    pub fn submodules(&self) -> Ref<'_, BTreeMap<Symbol, Module>> {
+        std::cell::Ref::map(self.borrow(), |this| &this.submodules)
+    }
+
+    fn traits(&self) -> RefMut<'_, BTreeMap<Symbol, BTreeMap<Symbol, Span>>> {
         std::cell::RefMut::map(self.borrow_mut(), |this| &mut this.traits)
     }
 
-    fn opened_mut(&self) -> RefMut<'_, HashMap<Path, abs::Visibility>> {
+    fn opened_mut(&self) -> RefMut<'_, BTreeMap<Path, abs::Visibility>> {
         std::cell::RefMut::map(self.borrow_mut(), |this| &mut this.opened)
     }
 }
@@ -162,6 +292,10 @@ impl Module {
             submodules: Default::default(),
             opened: Default::default(),
             modules: Default::default(),
+            references: Default::default(),
+            records: Default::default(),
+            opaque: false,
+            newtype: false,
         })))
     }
 
@@ -174,15 +308,98 @@ impl Module {
     }
 
     /// Defines a name in the current namespace. It takes the visibility of the definition, the
-    /// kind of the definition, and the name of the definition.
-    pub fn define<Vis: Into<abs::Visibility>>(&self, kind: DefinitionKind, vis: Vis, name: Symbol) {
+    /// kind of the definition, the name of the definition and the spans of its keyword and of its
+    /// name.
+    pub fn define<Vis: Into<abs::Visibility>>(
+        &self,
+        kind: DefinitionKind,
+        vis: Vis,
+        name: Symbol,
+        keyword: Span,
+        span: Span,
+    ) {
         let bag = &mut self.borrow_mut().declared;
 
+        let definition = Definition {
+            visibility: vis.into(),
+            keyword,
+            name: span,
+            deprecated: None,
+        };
+
         match kind {
-            DefinitionKind::Type => bag.types.insert(name, vis.into()),
-            DefinitionKind::Value => bag.values.insert(name, vis.into()),
-            DefinitionKind::Trait => bag.traits.insert(name, vis.into()),
+            DefinitionKind::Type => bag.types.insert(name, definition),
+            DefinitionKind::Value => bag.values.insert(name, definition),
+            DefinitionKind::Trait => bag.traits.insert(name, definition),
+        };
+    }
+
+    /// Marks a definition of the namespace as deprecated by the attributes of its declaration, that
+    /// is written in the module with the path.
+    pub fn deprecate(
+        &self,
+        kind: DefinitionKind,
+        name: &Symbol,
+        module: Path,
+        attributes: &abs::Attributes,
+    ) {
+        let Some(deprecation) = &attributes.deprecated else {
+            return;
         };
+
+        let bag = &mut self.borrow_mut().declared;
+
+        let definition = match kind {
+            DefinitionKind::Type => bag.types.get_mut(name),
+            DefinitionKind::Value => bag.values.get_mut(name),
+            DefinitionKind::Trait => bag.traits.get_mut(name),
+        };
+
+        if let Some(definition) = definition {
+            definition.deprecated = Some(Deprecated {
+                module,
+                message: deprecation.message.clone(),
+            });
+        }
+    }
+
+    /// The declarations that the module and its submodules use, with the spans of the names that
+    /// refer to them.
+    pub fn references(&self) -> BTreeMap<abs::Qualified, Vec<Span>> {
+        let mut references = self.borrow().references.clone();
+
+        for submodule in self.borrow().submodules.values() {
+            for (qualified, spans) in submodule.references() {
+                references.entry(qualified).or_default().extend(spans);
+            }
+        }
+
+        references
+    }
+
+    /// The declarations of the module and of its submodules, with the spans of their names.
+    pub fn definitions(&self) -> Vec<(abs::Qualified, Span)> {
+        let namespace = self.borrow();
+        let path = namespace.name.symbol();
+
+        let declared = &namespace.declared;
+        let mut definitions: Vec<_> = [&declared.types, &declared.values, &declared.traits]
+            .into_iter()
+            .flatten()
+            .map(|(name, definition)| {
+                let qualified = abs::Qualified {
+                    path: path.clone(),
+                    name: name.clone(),
+                };
+                (qualified, definition.name.clone())
+            })
+            .collect();
+
+        for submodule in namespace.submodules.values() {
+            definitions.extend(submodule.definitions());
+        }
+
+        definitions
     }
 
     pub fn fork(&self, name: Symbol) -> Module {
@@ -194,10 +411,21 @@ impl Module {
             .or_insert_with(|| Module::new(path.with(name.clone())))
             .clone()
     }
+
+    /// Makes the namespace and the ones inside of it available by their paths. The contexts do it
+    /// while the namespaces of types and effects are declared, so the namespaces that are loaded
+    /// without being resolved need it to have their constructors found.
+    pub fn register(&self, available: &mut HashMap<Path, Module>) {
+        for submodule in self.borrow().submodules.values() {
+            submodule.register(available);
+        }
+
+        available.insert(self.name().clone(), self.clone());
+    }
 }
 
 impl Module {
-    fn search_declared(&self, kind: DefinitionKind, name: Symbol) -> Option<abs::Visibility> {
+    fn search_declared(&self, kind: DefinitionKind, name: Symbol) -> Option<Definition> {
         self.declared()
             .apply(kind, |declared| declared.get(&name).cloned())
     }
@@ -240,11 +468,11 @@ impl Module {
             return Ok(None);
         }
 
-        if let Some(visibility) = self.search_declared(kind, name.clone()) {
-            if let abs::Visibility::Private = visibility {
+        if let Some(definition) = self.search_declared(kind, name.clone()) {
+            if let abs::Visibility::Private = definition.visibility {
                 return Err(Diagnostic::new(error::ResolverError {
                     span,
-                    kind: error::ResolverErrorKind::PrivateDefinition,
+                    kind: error::ResolverErrorKind::PrivateDefinition(Some(definition)),
                 }));
             }
 
@@ -255,7 +483,7 @@ impl Module {
             if let abs::Visibility::Private = visibility {
                 return Err(Diagnostic::new(error::ResolverError {
                     span,
-                    kind: error::ResolverErrorKind::PrivateDefinition,
+                    kind: error::ResolverErrorKind::PrivateDefinition(None),
                 }));
             }
 
@@ -373,11 +601,19 @@ impl Module {
 pub struct Context {
     pub module: Module,
     scope: RefCell<Bag<im_rc::HashSet<Symbol>>>,
+    handlers: RefCell<im_rc::HashSet<Symbol>>,
     reporter: Report,
     available: Rc<RefCell<HashMap<Path, Module>>>,
 
     in_head: bool,
     constant: Option<abs::Qualified>,
+
+    /// The lints allowed by the attributes of the declarations around the one being resolved.
+    allow: Vec<Symbol>,
+
+    /// The modules around an inline module, from the innermost one. Their names can be used
+    /// inside of it without being qualified.
+    enclosing: Vec<Module>,
 }
 
 impl Context {
@@ -405,6 +641,24 @@ impl Context {
         self.available.borrow()
     }
 
+    /// Marks a definition of the module as deprecated when its attributes say so. The declaration
+    /// is written in the current module, even when the definition is in a submodule of it.
+    pub fn deprecate(
+        &self,
+        module: &Module,
+        kind: DefinitionKind,
+        name: &Symbol,
+        attributes: &abs::Attributes,
+    ) {
+        let path = self.module.name().clone();
+        module.deprecate(kind, name, path, attributes);
+    }
+
+    /// Allows the lints of the attributes of a declaration while it's resolved.
+    pub fn allowing(&mut self, attributes: &abs::Attributes) {
+        self.allow.extend(attributes.allow.iter().cloned());
+    }
+
     pub fn reset_constant(&mut self) {
         self.constant = None;
         self.in_head = false;
@@ -418,18 +672,127 @@ impl Context {
         Context {
             module: Module::new(name),
             scope: Default::default(),
+            handlers: Default::default(),
             available,
             reporter: report,
 
             in_head: false,
             constant: None,
+            allow: Vec::new(),
+            enclosing: Vec::new(),
+        }
+    }
+
+    /// Records that the name in a span refers to a declaration, if it was resolved.
+    pub fn reference(&self, span: Span, qualified: Option<abs::Qualified>) -> Option<abs::Qualified> {
+        if let Some(qualified) = &qualified {
+            self.module
+                .borrow_mut()
+                .references
+                .entry(qualified.clone())
+                .or_default()
+                .push(span.clone());
+
+            self.check_opaque(span.clone(), qualified);
+            self.check_coercion(span.clone(), qualified);
+            self.warn_deprecated(span, qualified);
+        }
+
+        qualified
+    }
+
+    /// The name of the type that the namespace belongs to, when the module being resolved is not
+    /// the one that declares the type.
+    fn foreign_type(&self, namespace: &Module) -> Option<Symbol> {
+        let mut owner = namespace.name().clone();
+        let typ = owner.segments.pop()?;
+
+        if self.module.name().segments.starts_with(&owner.segments) {
+            None
+        } else {
+            Some(typ)
+        }
+    }
+
+    /// Reports a use of a constructor of an opaque type outside of the module that declares it.
+    fn check_opaque(&self, span: Span, qualified: &abs::Qualified) {
+        let Some(module) = self.module_of(&qualified.path) else {
+            return;
+        };
+
+        if !module.borrow().opaque {
+            return;
+        }
+
+        let Some(typ) = self.foreign_type(&module) else {
+            return;
+        };
+
+        self.reporter.report(Diagnostic::new(error::ResolverError {
+            span,
+            kind: error::ResolverErrorKind::OpaqueDefinition(qualified.name.clone(), typ),
+        }));
+    }
+
+    /// Reports a use of a coercion of a newtype outside of the module that declares it.
+    fn check_coercion(&self, span: Span, qualified: &abs::Qualified) {
+        let Some(module) = self.module_of(&qualified.path) else {
+            return;
+        };
+
+        let coercions = [abs::TypeDecl::WRAP, abs::TypeDecl::UNWRAP];
+
+        if !module.borrow().newtype || !coercions.contains(&&*qualified.name.get()) {
+            return;
+        }
+
+        let Some(typ) = self.foreign_type(&module) else {
+            return;
+        };
+
+        self.reporter.report(Diagnostic::new(error::ResolverError {
+            span,
+            kind: error::ResolverErrorKind::ForeignCoercion(qualified.name.clone(), typ),
+        }));
+    }
+
+    /// Warns about a use of a deprecated definition outside of the module where it's declared.
+    fn warn_deprecated(&self, span: Span, qualified: &abs::Qualified) {
+        let Some(module) = self.module_of(&qualified.path) else {
+            return;
+        };
+
+        let deprecated = {
+            let declared = module.declared();
+            let deprecated = [&declared.values, &declared.types, &declared.traits]
+                .into_iter()
+                .find_map(|x| x.get(&qualified.name))
+                .and_then(|x| x.deprecated.clone());
+            deprecated
+        };
+
+        let Some(deprecated) = deprecated else {
+            return;
+        };
+
+        let inside = self.module.name().segments.starts_with(&deprecated.module.segments);
+
+        if inside || self.allow.iter().any(|x| x.get() == DEPRECATED) {
+            return;
         }
+
+        self.reporter.report(Diagnostic::new(error::ResolverError {
+            span,
+            kind: error::ResolverErrorKind::Deprecated(qualified.name.clone(), deprecated.message),
+        }));
     }
 
     pub fn search(&self, kind: DefinitionKind, span: Span, name: Symbol) -> Option<abs::Qualified> {
         let searched = self
-            .module
-            .search(span.clone(), self.available.clone(), kind, name.clone());
+            .lexical()
+            .map(|module| module.search(span.clone(), self.available.clone(), kind, name.clone()))
+            .find(|x| !matches!(x, Ok(None)))
+            .unwrap_or(Ok(None));
 
         match searched {
             Ok(Some(res)) => Some(abs::Qualified {
@@ -437,9 +800,14 @@ impl Context {
                 name: res.name,
             }),
             Ok(None) => {
+                let kind = match self.importable(kind, &name) {
+                    Some(path) => error::ResolverErrorKind::NotImported(name, path),
+                    None => error::ResolverErrorKind::NotFound(name),
+                };
+
                 self.reporter.report(Diagnostic::new(error::ResolverError {
                     span: span.clone(),
-                    kind: error::ResolverErrorKind::NotFound(name),
+                    kind,
                 }));
                 None
             }
@@ -451,29 +819,66 @@ impl Context {
     }
 
     pub fn get_path(
+        &self,
+        kind: DefinitionKind,
+        span: Span,
+        path: Qualified,
+        first: bool,
+    ) -> Option<Qualified> {
+        self.get_path_visiting(kind, span, path, first, &mut HashSet::new())
+    }
+
+    /// Searches a path in the modules that are opened too. Modules can open each other, like the
+    /// prelude and the modules that use it, so the ones that were searched are skipped.
+    fn get_path_visiting(
         &self,
         kind: DefinitionKind,
         span: Span,
         mut path: Qualified,
         first: bool,
+        visited: &mut HashSet<Path>,
     ) -> Option<Qualified> {
-        if let Some((alias, _)) = self.module.modules().get(&path.path.symbol()) {
-            path.path = alias.clone();
+        let alias = self
+            .lexical()
+            .find_map(|module| module.modules().get(&path.path.symbol()).cloned());
+
+        if let Some((alias, _)) = alias {
+            path.path = alias;
         }
 
         let module = if path.path.is_empty() {
-            self.module.clone()
+            // Unqualified names are searched in the modules around the current one too.
+            let found = self.lexical().find(|module| {
+                let searched = module.search(
+                    span.clone(),
+                    self.available.clone(),
+                    kind,
+                    path.name.clone(),
+                );
+                !matches!(searched, Ok(None))
+            });
+
+            found.unwrap_or(&self.module).clone()
         } else if let Some(module) = self.available().get(&path.path).cloned() {
             module
-        } else if let Some(module) = self.module.search_submodules(path.path.symbol()) {
+        } else if let Some(module) = self.submodule(&path.path) {
             module
         } else {
+            visited.insert(self.module.name().clone());
+
             for (module_path, _) in self.module.opened().iter() {
+                if visited.contains(module_path) {
+                    continue;
+                }
+
                 let available = self.available().get(module_path).cloned();
                 if let Some(module) = available {
                     let mut forked = self.clone();
                     forked.module = module;
-                    if let Some(result) = forked.get_path(kind, span.clone(), path.clone(), false) {
+                    forked.enclosing = Vec::new();
+                    let result =
+                        forked.get_path_visiting(kind, span.clone(), path.clone(), false, visited);
+                    if let Some(result) = result {
                         return Some(result);
                     }
                 }
@@ -525,6 +930,32 @@ impl Context {
         })
     }
 
+    /// Resolves the function that a binary operator stands for. Operators are functions of the
+    /// `Operator` module of the standard library, or of the prelude of the projects that replace
+    /// it with their own.
+    pub fn resolve_operator(&self, span: Span, name: &str) -> Option<abs::Qualified> {
+        let operator = Path {
+            segments: vec![Symbol::intern("Operator")],
+        };
+
+        let module = if self.available().contains_key(&operator) {
+            operator
+        } else {
+            Path {
+                segments: vec![Symbol::intern("Prelude")],
+            }
+        };
+
+        self.resolve(
+            DefinitionKind::Value,
+            span,
+            Qualified {
+                path: module,
+                name: Symbol::intern(name),
+            },
+        )
+    }
+
     /// Creates a nested context.
     pub fn fork(&self, name: Symbol) -> Context {
         let path = { self.module.borrow().name.clone() };
@@ -540,13 +971,38 @@ impl Context {
         Context {
             module,
             scope,
+            handlers: Default::default(),
             reporter: self.reporter.clone(),
             available: self.available.clone(),
             in_head: self.in_head,
             constant: self.constant.clone(),
+            allow: self.allow.clone(),
+            enclosing: self.enclosing.clone(),
         }
     }
 
+    /// Forks the context into an inline module, that can use the names of the current module.
+    pub fn inline(&self, name: Symbol) -> Context {
+        let mut ctx = self.fork(name);
+        ctx.enclosing.insert(0, self.module.clone());
+        ctx
+    }
+
+    /// The current module and the modules around it, from the innermost one.
+    fn lexical(&self) -> impl Iterator<Item = &Module> {
+        std::iter::once(&self.module).chain(&self.enclosing)
+    }
+
+    /// Finds an inline module or the namespace of a type by its path, starting from the current
+    /// module or from one of the modules around it.
+    fn submodule(&self, path: &Path) -> Option<Module> {
+        self.lexical().find_map(|module| {
+            path.segments
+                .iter()
+                .try_fold(module.clone(), |module, name| module.search_submodules(name.clone()))
+        })
+    }
+
     pub fn scoped<T>(&self, fun: impl FnOnce(&mut Context) -> T) -> T {
         let mut ctx = self.clone();
         fun(&mut ctx)
@@ -555,11 +1011,25 @@ impl Context {
     pub fn with(&self, kind: DefinitionKind, name: Symbol) {
         match kind {
             DefinitionKind::Type => self.scope.borrow_mut().types.insert(name),
-            DefinitionKind::Value => self.scope.borrow_mut().values.insert(name),
+            DefinitionKind::Value => {
+                self.handlers.borrow_mut().remove(&name);
+                self.scope.borrow_mut().values.insert(name)
+            }
             DefinitionKind::Trait => self.scope.borrow_mut().traits.insert(name),
         };
     }
 
+    /// Adds a named handler to the scope. Handlers share the scope of values, so a value with the
+    /// same name that is bound later shadows the handler.
+    pub fn with_handler(&self, name: Symbol) {
+        self.with(DefinitionKind::Value, name.clone());
+        self.handlers.borrow_mut().insert(name);
+    }
+
+    pub fn is_handler(&self, name: &Symbol) -> bool {
+        self.handlers.borrow().contains(name)
+    }
+
     pub fn in_scope(&self, kind: DefinitionKind, name: Symbol) -> bool {
         let bag = &self.scope.borrow_mut();
 
@@ -573,11 +1043,112 @@ impl Context {
     pub fn declared(&self, kind: DefinitionKind, name: Symbol) -> Option<abs::Visibility> {
         let bag = &self.module.borrow().declared;
 
-        match kind {
-            DefinitionKind::Type => bag.types.get(&name).cloned(),
-            DefinitionKind::Value => bag.values.get(&name).cloned(),
-            DefinitionKind::Trait => bag.traits.get(&name).cloned(),
+        let definition = match kind {
+            DefinitionKind::Type => bag.types.get(&name),
+            DefinitionKind::Value => bag.values.get(&name),
+            DefinitionKind::Trait => bag.traits.get(&name),
+        };
+
+        definition.map(|x| x.visibility.clone())
+    }
+
+    /// Checks if a constructor is the only one of its type, so its patterns match every value that
+    /// has the constructor's arguments.
+    pub fn is_only_constructor(&self, constructor: &abs::Qualified) -> bool {
+        self.module_of(&constructor.path)
+            .is_some_and(|x| x.declared().values.len() == 1)
+    }
+
+    /// The module with a path of the abstract tree. The modules of the constructors of a type are
+    /// found through the module of the type, because they're not always available by themselves.
+    fn module_of(&self, path: &Symbol) -> Option<Module> {
+        let mut segments: Vec<_> = path.get().split('.').map(Symbol::intern).collect();
+        let path = Path { segments: segments.clone() };
+
+        self.available().get(&path).cloned().or_else(|| {
+            let name = segments.pop()?;
+            let parent = self.available().get(&Path { segments }).cloned()?;
+            parent.search_submodules(name)
+        })
+    }
+
+    /// The records that can be named without a path and that declare a field with the name, sorted
+    /// so the typer reports them in the same order.
+    pub fn records_with_field(&self, field: &Symbol) -> Vec<abs::Qualified> {
+        let current = self.module.name().clone();
+
+        let mut types: Vec<Qualified> = self
+            .module
+            .declared()
+            .types
+            .keys()
+            .map(|name| Qualified {
+                path: current.clone(),
+                name: name.clone(),
+            })
+            .collect();
+
+        types.extend(self.module.aliases().types.values().map(|(x, _)| x.clone()));
+
+        for path in self.module.opened().keys() {
+            let Some(module) = self.available().get(path).cloned() else {
+                continue;
+            };
+
+            let declared = module.declared();
+
+            let public = declared
+                .types
+                .iter()
+                .filter(|(_, x)| x.visibility == abs::Visibility::Public)
+                .map(|(name, _)| Qualified {
+                    path: path.clone(),
+                    name: name.clone(),
+                });
+
+            types.extend(public);
         }
+
+        let mut records: Vec<_> = types
+            .into_iter()
+            .filter(|typ| {
+                let available = self.available();
+
+                let is_record = available
+                    .get(&typ.path)
+                    .is_some_and(|x| x.borrow().records.contains(&typ.name));
+
+                is_record
+                    && available
+                        .get(&typ.path.with(typ.name.clone()))
+                        .is_some_and(|x| x.declared().values.contains_key(field))
+            })
+            .map(|typ| abs::Qualified {
+                path: typ.path.symbol(),
+                name: typ.name,
+            })
+            .collect();
+
+        records.sort();
+        records.dedup();
+        records
+    }
+
+    /// The first module that declares a public definition with the name, so it can be suggested to
+    /// be imported where the name is not found.
+    fn importable(&self, kind: DefinitionKind, name: &Symbol) -> Option<Path> {
+        let current = self.module.name().clone();
+
+        self.available()
+            .iter()
+            .filter(|(path, module)| {
+                **path != current
+                    && module
+                        .search_declared(kind, name.clone())
+                        .is_some_and(|x| x.visibility == abs::Visibility::Public)
+            })
+            .map(|(path, _)| path.clone())
+            .min()
     }
 }
 
@@ -611,25 +1182,39 @@ pub mod top_level {
 
         match top_level {
             Let(let_decl) => Some(resolve_let(ctx, *let_decl, true).map(abs::TopLevel::Let)),
+            Pattern(decl) => Some(resolve_pattern_decl(ctx, *decl).map(abs::TopLevel::Pattern)),
             Type(type_decl) => Some(resolve_type_decl(ctx, *type_decl).map(abs::TopLevel::Type)),
+            Effect(effect) => Some(resolve_effect(ctx, *effect).map(abs::TopLevel::Effect)),
             Module(mod_decl) => Some(resolve_module(ctx, *mod_decl).map(abs::TopLevel::Module)),
             External(ext) => Some(resolve_external(ctx, *ext).map(abs::TopLevel::External)),
             Use(use_decl) => Some(resolve_use(ctx, *use_decl).map(|_| abs::TopLevel::Use)),
             Trait(trait_) => Some(resolve_trait(ctx, *trait_).map(abs::TopLevel::Trait)),
             Impl(impl_) => Some(resolve_impl(ctx, *impl_).map(abs::TopLevel::Impl)),
             Command(cmd) => Some(Solver::new(move |_| {
-                abs::TopLevel::Command(cmd.name.clone(), cmd.command.clone())
+                abs::TopLevel::Command(cmd.name.symbol(), cmd.command.symbol())
             })),
+            Test(test) => Some(resolve_test(*test).map(abs::TopLevel::Test)),
+            // The declarations of the conditions that don't hold are removed before the modules
+            // are resolved, and the other ones are moved to the top level.
+            When(_) => None,
             Error(_) => None,
         }
     }
 
     pub fn resolve_trait(ctx: Context, decl: tree::TraitDecl) -> Solver<abs::TraitDecl> {
         let name = decl.name.symbol();
+        let attributes = transform_attributes(&ctx, decl.attributes);
         let submodule = ctx.fork(decl.name.symbol());
 
-        ctx.module
-            .define(DefinitionKind::Type, decl.visibility.clone(), name.clone());
+        ctx.module.define(
+            DefinitionKind::Type,
+            decl.visibility.clone(),
+            name.clone(),
+            decl.trait_.value.span.clone(),
+            decl.name.0.value.span.clone(),
+        );
+
+        ctx.deprecate(&ctx.module, DefinitionKind::Type, &name, &attributes);
 
         ctx.module.traits().insert(
             name.clone(),
@@ -647,6 +1232,8 @@ pub mod top_level {
 
         Solver::new(move |ctx| {
             ctx.scoped(|ctx| {
+                ctx.allowing(&attributes);
+
                 let binders = decl
                     .binders
                     .into_iter()
@@ -673,6 +1260,7 @@ pub mod top_level {
                     binders,
                     body,
                     span: decl.name.0.value.span.clone(),
+                    attributes,
                 }
             })
         })
@@ -700,6 +1288,16 @@ pub mod top_level {
             let path = from_constructor_upper_path(&decl.name);
             let searched = ctx.get_path(DefinitionKind::Type, decl.name.span.clone(), path, true);
 
+            if let Some(searched) = &searched {
+                ctx.reference(
+                    decl.name.last.0.value.span.clone(),
+                    Some(abs::Qualified {
+                        path: searched.path.symbol(),
+                        name: searched.name.clone(),
+                    }),
+                );
+            }
+
             ctx.scoped(|ctx| {
                 let binders = decl
                     .types
@@ -766,8 +1364,13 @@ pub mod top_level {
         // in the IDE.
         let span = sig.name.0.value.span.clone();
 
-        ctx.module
-            .define(DefinitionKind::Value, sig.visibility.clone(), name.clone());
+        ctx.module.define(
+            DefinitionKind::Value,
+            sig.visibility.clone(),
+            name.clone(),
+            sig.let_.value.span.clone(),
+            sig.name.0.value.span.clone(),
+        );
 
         Solver::new(move |ctx| {
             ctx.scoped(|ctx| {
@@ -802,17 +1405,25 @@ pub mod top_level {
         // Gets the location of the name, so we can present the errors in a less annoying way
         // in the IDE.
         let span = decl.signature.name.0.value.span.clone();
+        let declaration = decl.span.clone();
+        let attributes = transform_value_attributes(&ctx, decl.attributes);
 
         if declare {
             ctx.module.define(
                 DefinitionKind::Value,
                 decl.signature.visibility.clone(),
                 name.clone(),
+                decl.signature.let_.value.span.clone(),
+                decl.signature.name.0.value.span.clone(),
             );
+
+            ctx.deprecate(&ctx.module, DefinitionKind::Value, &name, &attributes);
         }
 
         Solver::new(move |ctx| {
             ctx.scoped(|ctx| {
+                ctx.allowing(&attributes);
+
                 let binders = decl
                     .signature
                     .binders
@@ -859,61 +1470,259 @@ pub mod top_level {
                     signature,
                     body,
                     constant,
+                    declaration,
+                    attributes,
                 }
             })
         })
     }
 
-    /// Resolve a type declaration and returns the solver for it.
-    pub fn resolve_type_decl(ctx: Context, decl: tree::TypeDecl) -> Solver<abs::TypeDecl> {
-        let name = decl.name.symbol();
-        let submodule = ctx.fork(decl.name.symbol());
+    /// Resolve a declaration of a pattern. It's desugared into a declaration of the value of the
+    /// body, named after the position of the `let` keyword so nothing can refer to it, and one
+    /// declaration for each variable of the pattern that takes the variable out of that value.
+    pub fn resolve_pattern_decl(
+        ctx: Context,
+        decl: tree::PatternDecl,
+    ) -> Solver<Vec<abs::LetDecl>> {
+        let keyword = decl.let_.value.span.clone();
+        let declaration = decl.span.clone();
 
-        ctx.module
-            .define(DefinitionKind::Type, decl.visibility.clone(), name.clone());
+        let mut variables = Vec::new();
+        pattern::variables(&decl.pattern, &mut variables);
 
-        match &decl.def {
-            None => {}
-            Some((_, tree::TypeDef::Record(record))) => {
-                for (field, _) in &record.fields {
-                    let name = field.name.symbol();
-                    let vis = into_field_visiblity(field.visibility.clone().into());
-                    submodule.module.define(DefinitionKind::Value, vis, name);
-                }
-            }
-            Some((_, tree::TypeDef::Sum(sum))) => {
-                for cons in &sum.constructors {
-                    let name = cons.name.symbol();
-                    submodule
-                        .module
-                        .define(DefinitionKind::Value, Visibility::Public, name);
-                }
-            }
-            Some((_, tree::TypeDef::Synonym(_synonym))) => todo!(),
+        for variable in &variables {
+            ctx.module.define(
+                DefinitionKind::Value,
+                decl.visibility.clone(),
+                variable.symbol(),
+                keyword.clone(),
+                variable.0.value.span.clone(),
+            );
         }
 
-        let namespace = submodule.module.name().clone();
-
         Solver::new(move |ctx| {
             ctx.scoped(|ctx| {
-                let binders = decl
-                    .binders
-                    .into_iter()
-                    .map(|x| transform_type_binder(ctx, x))
-                    .collect::<Vec<_>>();
+                let path = ctx.module.name().symbol();
 
-                for binder in &binders {
-                    match binder {
-                        abs::TypeBinder::Implicit(x) => ctx.with(DefinitionKind::Type, x.clone()),
-                        abs::TypeBinder::Explicit(x, _) => {
-                            ctx.with(DefinitionKind::Type, x.clone())
-                        }
-                    }
-                }
+                let value = abs::Qualified {
+                    path: path.clone(),
+                    name: Symbol::intern(&format!("pattern'{}", keyword.start.0)),
+                };
 
-                let def = match decl.def {
-                    None => abs::TypeDef::Abstract,
-                    Some((_, tree::TypeDef::Record(record))) => {
+                let signature = abs::LetSignature {
+                    span: decl.pattern.span.clone(),
+                    name: value.clone(),
+                    visibility: abs::Visibility::Private,
+                    binders: vec![],
+                    ret: decl.ret.map(|(_, typ)| transform_type(ctx, *typ)),
+                };
+
+                let body = abs::PatternArm {
+                    patterns: vec![],
+                    expr: expr::transform(ctx, *decl.body),
+                    guard: None,
+                };
+
+                let pattern = pattern::transform(ctx, *decl.pattern);
+
+                let refutable = pattern::refutable(ctx, &pattern);
+
+                if let Some(span) = refutable.clone() {
+                    ctx.reporter.report(Diagnostic::new(error::ResolverError {
+                        span,
+                        kind: error::ResolverErrorKind::RefutablePattern,
+                    }));
+                }
+
+                let mut decls = vec![abs::LetDecl {
+                    signature,
+                    body: vec![body],
+                    constant: None,
+                    declaration: declaration.clone(),
+                    attributes: Default::default(),
+                }];
+
+                for variable in variables {
+                    let span = variable.0.value.span.clone();
+                    let name = variable.symbol();
+
+                    let spanned = |data| Box::new(Spanned::new(data, span.clone()));
+
+                    let arm = abs::PatternArm {
+                        patterns: vec![pattern.clone()],
+                        expr: spanned(abs::ExprKind::Variable(name.clone())),
+                        guard: None,
+                    };
+
+                    // The variables of a pattern that may not match are not checked again.
+                    let when = match refutable {
+                        Some(_) => abs::ExprKind::Error,
+                        None => abs::ExprKind::When(abs::WhenExpr {
+                            scrutinee: vec![spanned(abs::ExprKind::Function(value.clone()))],
+                            arms: vec![arm],
+                        }),
+                    };
+
+                    let signature = abs::LetSignature {
+                        span: span.clone(),
+                        name: abs::Qualified {
+                            path: path.clone(),
+                            name,
+                        },
+                        visibility: decl.visibility.clone().into(),
+                        binders: vec![],
+                        ret: None,
+                    };
+
+                    decls.push(abs::LetDecl {
+                        signature,
+                        body: vec![abs::PatternArm {
+                            patterns: vec![],
+                            expr: spanned(when),
+                            guard: None,
+                        }],
+                        constant: None,
+                        declaration: span,
+                        attributes: Default::default(),
+                    });
+                }
+
+                decls
+            })
+        })
+    }
+
+    /// Resolve a test declaration. Tests don't define names, so nothing can refer to them, and
+    /// their names are made from the position of the `test` keyword.
+    pub fn resolve_test(decl: tree::TestDecl) -> Solver<abs::TestDecl> {
+        let keyword = decl.test.value.span.clone();
+        let span = keyword.clone().mix(decl.body.span.clone());
+
+        Solver::new(move |ctx| {
+            ctx.scoped(|ctx| {
+                let name = abs::Qualified {
+                    path: ctx.module.name().symbol(),
+                    name: Symbol::intern(&format!("test'{}", keyword.start.0)),
+                };
+
+                abs::TestDecl {
+                    name,
+                    description: decl.description.symbol(),
+                    body: expr::transform(ctx, *decl.body),
+                    span,
+                }
+            })
+        })
+    }
+
+    /// Resolve a type declaration and returns the solver for it.
+    pub fn resolve_type_decl(ctx: Context, decl: tree::TypeDecl) -> Solver<abs::TypeDecl> {
+        let name = decl.name.symbol();
+        let attributes = transform_type_attributes(&ctx, decl.attributes);
+        let submodule = ctx.fork(decl.name.symbol());
+
+        submodule.module.borrow_mut().opaque = attributes.opaque;
+
+        let newtype = decl.type_.kind == TokenData::Newtype;
+
+        let newtype = newtype && {
+            let wraps = matches!(
+                &decl.def,
+                Some((_, tree::TypeDef::Sum(sum)))
+                    if sum.constructors.len() == 1 && sum.constructors[0].args.len() == 1
+            );
+
+            if !wraps {
+                ctx.reporter.report(Diagnostic::new(error::ResolverError {
+                    span: decl.name.0.value.span.clone(),
+                    kind: error::ResolverErrorKind::InvalidNewtype(name.clone()),
+                }));
+            }
+
+            wraps
+        };
+
+        if newtype {
+            submodule.module.borrow_mut().newtype = true;
+
+            for coercion in [abs::TypeDecl::WRAP, abs::TypeDecl::UNWRAP] {
+                submodule.module.define(
+                    DefinitionKind::Value,
+                    Visibility::Public,
+                    Symbol::intern(coercion),
+                    decl.type_.value.span.clone(),
+                    decl.name.0.value.span.clone(),
+                );
+            }
+        }
+
+        ctx.module.define(
+            DefinitionKind::Type,
+            decl.visibility.clone(),
+            name.clone(),
+            decl.type_.value.span.clone(),
+            decl.name.0.value.span.clone(),
+        );
+
+        ctx.deprecate(&ctx.module, DefinitionKind::Type, &name, &attributes);
+
+        let mut constructor_attributes = Vec::new();
+
+        match &decl.def {
+            None => {}
+            Some((_, tree::TypeDef::Record(record))) => {
+                ctx.module.borrow_mut().records.insert(name.clone());
+
+                for (field, _) in &record.fields {
+                    let name = field.name.symbol();
+                    let vis = into_field_visiblity(field.visibility.clone().into());
+                    let span = field.name.0.value.span.clone();
+                    submodule
+                        .module
+                        .define(DefinitionKind::Value, vis, name, span.clone(), span);
+                }
+            }
+            Some((_, tree::TypeDef::Sum(sum))) => {
+                for cons in &sum.constructors {
+                    let name = cons.name.symbol();
+                    let span = cons.name.0.value.span.clone();
+                    let vis = Visibility::Public;
+                    submodule
+                        .module
+                        .define(DefinitionKind::Value, vis, name.clone(), span.clone(), span);
+
+                    let attributes = transform_attributes(&ctx, cons.attributes.clone());
+                    ctx.deprecate(&submodule.module, DefinitionKind::Value, &name, &attributes);
+                    constructor_attributes.push(attributes);
+                }
+            }
+            Some((_, tree::TypeDef::Synonym(_synonym))) => todo!(),
+        }
+
+        let namespace = submodule.module.name().clone();
+
+        Solver::new(move |ctx| {
+            ctx.scoped(|ctx| {
+                ctx.allowing(&attributes);
+
+                let binders = decl
+                    .binders
+                    .into_iter()
+                    .map(|x| transform_type_binder(ctx, x))
+                    .collect::<Vec<_>>();
+
+                for binder in &binders {
+                    match binder {
+                        abs::TypeBinder::Implicit(x) => ctx.with(DefinitionKind::Type, x.clone()),
+                        abs::TypeBinder::Explicit(x, _) => {
+                            ctx.with(DefinitionKind::Type, x.clone())
+                        }
+                    }
+                }
+
+                let def = match decl.def {
+                    None => abs::TypeDef::Abstract,
+                    Some((_, tree::TypeDef::Record(record))) => {
                         let fields = record
                             .fields
                             .into_iter()
@@ -938,7 +1747,8 @@ pub mod top_level {
                         let constructors = sum
                             .constructors
                             .into_iter()
-                            .map(|cons| {
+                            .zip(constructor_attributes)
+                            .map(|(cons, attributes)| {
                                 let name = cons.name.symbol();
                                 let args = cons
                                     .args
@@ -953,6 +1763,7 @@ pub mod top_level {
                                     },
                                     args,
                                     typ,
+                                    attributes,
                                 }
                             })
                             .collect();
@@ -971,6 +1782,96 @@ pub mod top_level {
                     visibility: decl.visibility.into(),
                     binders,
                     def,
+                    attributes,
+                    newtype,
+                }
+            })
+        })
+    }
+
+    /// Resolve an effect declaration and returns the solver for it. The operations of the effect
+    /// live inside of a submodule with the name of the effect, just like constructors.
+    pub fn resolve_effect(ctx: Context, decl: tree::EffectDecl) -> Solver<abs::EffectDecl> {
+        let name = decl.name.symbol();
+        let attributes = transform_attributes(&ctx, decl.attributes);
+        let submodule = ctx.fork(decl.name.symbol());
+
+        ctx.module.define(
+            DefinitionKind::Type,
+            decl.visibility.clone(),
+            name.clone(),
+            decl.effect.value.span.clone(),
+            decl.name.0.value.span.clone(),
+        );
+
+        ctx.deprecate(&ctx.module, DefinitionKind::Type, &name, &attributes);
+
+        for field in &decl.fields {
+            let vis = into_field_visiblity(field.visibility.clone().into());
+            let span = field.name.0.value.span.clone();
+            submodule
+                .module
+                .define(DefinitionKind::Value, vis, field.name.symbol(), span.clone(), span);
+        }
+
+        let namespace = submodule.module.name().clone();
+
+        Solver::new(move |ctx| {
+            ctx.scoped(|ctx| {
+                ctx.allowing(&attributes);
+
+                let binders = decl
+                    .binders
+                    .into_iter()
+                    .map(|x| transform_type_binder(ctx, x))
+                    .collect::<Vec<_>>();
+
+                for binder in &binders {
+                    ctx.with(DefinitionKind::Type, binder.name().clone());
+                }
+
+                let linear = decl.once.is_some();
+
+                let fields = decl
+                    .fields
+                    .into_iter()
+                    .map(|field| abs::EffectField {
+                        name: abs::Qualified {
+                            path: namespace.clone().symbol(),
+                            name: field.name.symbol(),
+                        },
+                        visibility: field.visibility.into(),
+                        kind: match field.kind.as_ref().map(|x| &x.kind) {
+                            Some(TokenData::Fun) => abs::OperationKind::Fun,
+                            _ => abs::OperationKind::Ctl,
+                        },
+                        resumption: match field.kind.map(|x| x.kind) {
+                            Some(TokenData::Fun) => abs::Resumption::Many,
+                            _ if linear => abs::Resumption::Linear,
+                            Some(TokenData::Once) => abs::Resumption::Once,
+                            _ => abs::Resumption::Many,
+                        },
+                        args: field
+                            .args
+                            .into_iter()
+                            .map(|x| transform_type(ctx, *x))
+                            .collect(),
+                        ret: transform_type(ctx, *field.ret),
+                        default: field.default.map(|(_, x)| expr::transform(ctx, *x)),
+                    })
+                    .collect();
+
+                abs::EffectDecl {
+                    name: abs::Qualified {
+                        path: ctx.module.name().symbol(),
+                        name,
+                    },
+                    namespace: namespace.symbol(),
+                    visibility: decl.visibility.into(),
+                    binders,
+                    fields,
+                    span: decl.name.0.value.span.clone(),
+                    attributes,
                 }
             })
         })
@@ -979,21 +1880,43 @@ pub mod top_level {
     /// Resolve an external declaration and returns the solver for it.
     pub fn resolve_external(ctx: Context, decl: tree::ExtDecl) -> Solver<abs::ExtDecl> {
         let name = decl.name.symbol();
+        let attributes = transform_value_attributes(&ctx, decl.attributes);
 
-        ctx.module
-            .define(DefinitionKind::Value, decl.visibility.clone(), name.clone());
+        ctx.module.define(
+            DefinitionKind::Value,
+            decl.visibility.clone(),
+            name.clone(),
+            decl.external.value.span.clone(),
+            decl.name.0.value.span.clone(),
+        );
+
+        ctx.deprecate(&ctx.module, DefinitionKind::Value, &name, &attributes);
 
         let namespace = ctx.module.name().clone();
 
-        Solver::new(move |module| abs::ExtDecl {
-            name: abs::Qualified {
-                path: namespace.clone().symbol(),
-                name,
-            },
-            namespace: namespace.symbol(),
-            visibility: decl.visibility.into(),
-            typ: transform_type(&module, *decl.typ),
-            ret: decl.str.symbol(),
+        Solver::new(move |mut module| {
+            module.allowing(&attributes);
+
+            abs::ExtDecl {
+                name: abs::Qualified {
+                    path: namespace.clone().symbol(),
+                    name,
+                },
+                namespace: namespace.symbol(),
+                visibility: decl.visibility.into(),
+                convention: decl.convention.map(|x| x.symbol()),
+                typ: transform_type(&module, *decl.typ),
+                effect: decl.effect.and_then(|(_, path)| {
+                    let effect = module.resolve(
+                        DefinitionKind::Type,
+                        path.span.clone(),
+                        from_constructor_upper_path(&path),
+                    );
+                    module.reference(path.last.0.value.span.clone(), effect)
+                }),
+                ret: decl.str.symbol(),
+                attributes,
+            }
         })
     }
 
@@ -1017,7 +1940,9 @@ pub mod top_level {
                 for solver in solvers {
                     match solver.eval(ctx.clone()) {
                         abs::TopLevel::Let(x) => program.lets.push(x),
+                        abs::TopLevel::Pattern(x) => program.lets.extend(x),
                         abs::TopLevel::Type(x) => program.types.push(x),
+                        abs::TopLevel::Effect(x) => program.effects.push(x),
                         abs::TopLevel::Module(x) => program.modules.push(x),
                         abs::TopLevel::External(x) => program.externals.push(x),
                         abs::TopLevel::Trait(t) => program.traits.push(t),
@@ -1026,6 +1951,7 @@ pub mod top_level {
                         abs::TopLevel::Command(name, symbol) => {
                             program.commands.push((name, symbol))
                         }
+                        abs::TopLevel::Test(x) => program.tests.push(x),
                         abs::TopLevel::Use => (),
                     }
                 }
@@ -1034,18 +1960,61 @@ pub mod top_level {
             })
         }
 
-        let new_context = ctx.fork(decl.name.symbol());
+        let attributes = transform_attributes(&ctx, decl.attributes);
+        let new_context = ctx.inline(decl.name.symbol());
         let solver = decl
             .part
             .map(|x| resolve_module_inline(new_context.clone(), x));
 
-        Solver::new(move |ctx| abs::ModuleDecl {
-            visibility: decl.visibility.into(),
-            name: decl.name.symbol(),
-            decls: solver.map(|x| x.eval(ctx)),
+        Solver::new(move |ctx| {
+            // The declarations are resolved inside of the module, with the lints that are allowed
+            // around it.
+            let mut inner = new_context;
+            inner.allow = ctx.allow.clone();
+            inner.allowing(&attributes);
+
+            let mut decls = solver.map(|x| x.eval(inner));
+
+            if let Some(decls) = &mut decls {
+                allow_in(decls, &attributes.allow);
+            }
+
+            abs::ModuleDecl {
+                visibility: decl.visibility.into(),
+                name: decl.name.symbol(),
+                decls,
+                attributes,
+            }
         })
     }
 
+    /// Allows the lints that a module allows in all of the declarations inside of it.
+    fn allow_in(program: &mut abs::Program, allow: &[Symbol]) {
+        let impls = program.impls.iter_mut().flat_map(|x| &mut x.body);
+
+        let attributes = (program.lets.iter_mut().map(|x| &mut x.attributes))
+            .chain(impls.map(|x| &mut x.attributes))
+            .chain(program.types.iter_mut().map(|x| &mut x.attributes))
+            .chain(program.effects.iter_mut().map(|x| &mut x.attributes))
+            .chain(program.traits.iter_mut().map(|x| &mut x.attributes))
+            .chain(program.externals.iter_mut().map(|x| &mut x.attributes))
+            .chain(program.modules.iter_mut().map(|x| &mut x.attributes));
+
+        for attributes in attributes {
+            for lint in allow {
+                if !attributes.allow.contains(lint) {
+                    attributes.allow.push(lint.clone());
+                }
+            }
+        }
+
+        for module in &mut program.modules {
+            if let Some(decls) = &mut module.decls {
+                allow_in(decls, allow);
+            }
+        }
+    }
+
     pub fn resolve_use(ctx: Context, decl: tree::UseDecl) -> Solver<()> {
         if let Some(alias) = decl.alias {
             ctx.module.modules_mut().insert(
@@ -1078,6 +2047,15 @@ pub fn transform_literal(literal: tree::Literal) -> abs::Literal {
         tree::LiteralKind::Integer(x) => abs::LiteralKind::Integer(x.symbol()),
         tree::LiteralKind::Float(x) => abs::LiteralKind::Float(x.symbol()),
         tree::LiteralKind::Unit(_) => abs::LiteralKind::Unit,
+        tree::LiteralKind::Negative(_, x) => {
+            let number = Symbol::intern(&format!("-{}", x.symbol().get()));
+
+            if x.kind == TokenData::Float {
+                abs::LiteralKind::Float(number)
+            } else {
+                abs::LiteralKind::Integer(number)
+            }
+        }
     };
 
     Box::new(Spanned {
@@ -1088,7 +2066,7 @@ pub fn transform_literal(literal: tree::Literal) -> abs::Literal {
 
 /// Patterns are the ones that can be used in a match expression.
 pub mod pattern {
-    use im_rc::HashSet;
+    use im_rc::HashMap;
 
     use vulpi_report::Diagnostic;
 
@@ -1097,7 +2075,7 @@ pub mod pattern {
     fn transform_pat(
         ctx: &Context,
         pattern: tree::Pattern,
-        vars: &mut HashSet<Symbol>,
+        vars: &mut HashMap<Symbol, Span>,
     ) -> abs::Pattern {
         let data = match pattern.data {
             tree::PatternKind::Wildcard(_) => abs::PatternKind::Wildcard,
@@ -1107,6 +2085,7 @@ pub mod pattern {
                     pattern.span.clone(),
                     from_constructor_upper_path(&x),
                 );
+                let func = ctx.reference(x.last.0.value.span.clone(), func);
                 match func {
                     Some(res) => abs::PatternKind::Application(abs::PatApplication {
                         func: res,
@@ -1116,14 +2095,17 @@ pub mod pattern {
                 }
             }
             tree::PatternKind::Variable(x) => {
-                if vars.contains(&x.symbol()) {
+                if let Some(first) = vars.get(&x.symbol()) {
                     ctx.reporter.report(Diagnostic::new(error::ResolverError {
                         span: pattern.span.clone(),
-                        kind: error::ResolverErrorKind::DuplicatePattern(x.symbol()),
+                        kind: error::ResolverErrorKind::DuplicatePattern(
+                            x.symbol(),
+                            first.clone(),
+                        ),
                     }));
                     abs::PatternKind::Error
                 } else {
-                    vars.insert(x.symbol());
+                    vars.insert(x.symbol(), pattern.span.clone());
                     abs::PatternKind::Variable(x.symbol())
                 }
             }
@@ -1131,6 +2113,10 @@ pub mod pattern {
                 let lit = transform_literal(x);
                 abs::PatternKind::Literal(lit)
             }
+            tree::PatternKind::Range(range) => abs::PatternKind::Range(abs::PatRange {
+                start: transform_literal(range.start),
+                end: transform_literal(range.end),
+            }),
             tree::PatternKind::Annotation(app) => {
                 let pat = transform_pat(ctx, *app.left, vars);
                 let typ = transform_type(ctx, *app.right);
@@ -1151,6 +2137,7 @@ pub mod pattern {
                     pattern.span.clone(),
                     from_constructor_upper_path(&app.func),
                 );
+                let func = ctx.reference(app.func.last.0.value.span.clone(), func);
 
                 match func {
                     Some(func) => {
@@ -1165,6 +2152,93 @@ pub mod pattern {
                     None => abs::PatternKind::Error,
                 }
             }
+            tree::PatternKind::Effect(eff) => {
+                let func = ctx.resolve(
+                    DefinitionKind::Value,
+                    eff.func.span.clone(),
+                    from_lower_path(&eff.func),
+                );
+                let func = ctx.reference(eff.func.last.0.value.span.clone(), func);
+
+                match func {
+                    Some(func) => {
+                        let args = eff
+                            .args
+                            .into_iter()
+                            .map(|x| transform_pat(ctx, *x, vars))
+                            .collect();
+
+                        let cont = eff.cont.and_then(|(_, name)| {
+                            if let Some(first) = vars.get(&name.symbol()) {
+                                ctx.reporter.report(Diagnostic::new(error::ResolverError {
+                                    span: name.0.value.span.clone(),
+                                    kind: error::ResolverErrorKind::DuplicatePattern(
+                                        name.symbol(),
+                                        first.clone(),
+                                    ),
+                                }));
+                                None
+                            } else {
+                                vars.insert(name.symbol(), name.0.value.span.clone());
+                                Some(name.symbol())
+                            }
+                        });
+
+                        abs::PatternKind::Effect(abs::PatEffect { func, args, cont })
+                    }
+                    None => abs::PatternKind::Error,
+                }
+            }
+            tree::PatternKind::Return(return_) => {
+                abs::PatternKind::Return(transform_pat(ctx, *return_.pattern, vars))
+            }
+            tree::PatternKind::Or(or) => {
+                let mut left_vars = vars.clone();
+                let left = transform_pat(ctx, *or.left, &mut left_vars);
+
+                let mut right_vars = vars.clone();
+                let right = transform_pat(ctx, *or.right, &mut right_vars);
+
+                // Every alternative has to bind the same variables, so the body can use them
+                // whichever one matched.
+                let mut missing: Vec<_> = left_vars
+                    .iter()
+                    .chain(&right_vars)
+                    .filter(|(name, _)| {
+                        !(left_vars.contains_key(name) && right_vars.contains_key(name))
+                    })
+                    .collect();
+
+                missing.sort_by_key(|(_, span)| span.start.0);
+
+                for (name, span) in missing {
+                    ctx.reporter.report(Diagnostic::new(error::ResolverError {
+                        span: span.clone(),
+                        kind: error::ResolverErrorKind::OrPatternVariable(name.clone()),
+                    }));
+                }
+
+                vars.extend(left_vars);
+                vars.extend(right_vars);
+
+                abs::PatternKind::Or(abs::PatOr { left, right })
+            }
+            tree::PatternKind::As(as_) => {
+                let name = as_.name.symbol();
+
+                if let Some(first) = vars.get(&name) {
+                    ctx.reporter.report(Diagnostic::new(error::ResolverError {
+                        span: as_.name.0.value.span.clone(),
+                        kind: error::ResolverErrorKind::DuplicatePattern(name, first.clone()),
+                    }));
+                    return transform_pat(ctx, *as_.pattern, vars);
+                }
+
+                vars.insert(name.clone(), as_.name.0.value.span.clone());
+                let pattern = transform_pat(ctx, *as_.pattern, vars);
+
+                abs::PatternKind::As(abs::PatAs { name, pattern })
+            }
             tree::PatternKind::Parenthesis(x) => {
                 return transform_pat(ctx, *x.data, vars);
             }
@@ -1176,126 +2250,649 @@ pub mod pattern {
         })
     }
 
-    /// Transform a pattern into an abstract pattern.
-    pub fn transform(ctx: &Context, pattern: tree::Pattern) -> abs::Pattern {
-        let mut vars = Default::default();
+    /// The variables that a pattern binds, in the order that they're written.
+    pub fn variables(pattern: &tree::Pattern, variables: &mut Vec<concrete::Lower>) {
+        match &pattern.data {
+            tree::PatternKind::Variable(x) => variables.push(x.clone()),
+            tree::PatternKind::Annotation(x) => self::variables(&x.left, variables),
+            tree::PatternKind::Tuple(x) => x.iter().for_each(|(x, _)| self::variables(x, variables)),
+            tree::PatternKind::Application(x) => {
+                x.args.iter().for_each(|x| self::variables(x, variables))
+            }
+            tree::PatternKind::Effect(x) => {
+                x.args.iter().for_each(|x| self::variables(x, variables));
+                variables.extend(x.cont.iter().map(|(_, x)| x.clone()));
+            }
+            tree::PatternKind::Parenthesis(x) => self::variables(&x.data, variables),
+            tree::PatternKind::Return(x) => self::variables(&x.pattern, variables),
+            tree::PatternKind::Or(x) => self::variables(&x.left, variables),
+            tree::PatternKind::As(x) => {
+                variables.push(x.name.clone());
+                self::variables(&x.pattern, variables)
+            }
+            tree::PatternKind::Wildcard(_)
+            | tree::PatternKind::Constructor(_)
+            | tree::PatternKind::Literal(_)
+            | tree::PatternKind::Range(_) => (),
+        }
+    }
+
+    /// The span of a part of the pattern that may not match, like a literal or a constructor of a
+    /// type that has other ones.
+    pub fn refutable(ctx: &Context, pattern: &abs::Pattern) -> Option<Span> {
+        match &pattern.data {
+            abs::PatternKind::Wildcard | abs::PatternKind::Variable(_) | abs::PatternKind::Error => {
+                None
+            }
+            abs::PatternKind::Tuple(x) => x.iter().find_map(|x| refutable(ctx, x)),
+            abs::PatternKind::Ascription(x) => refutable(ctx, &x.pat),
+            abs::PatternKind::As(x) => refutable(ctx, &x.pattern),
+            abs::PatternKind::Application(x) if ctx.is_only_constructor(&x.func) => {
+                x.args.iter().find_map(|x| refutable(ctx, x))
+            }
+            abs::PatternKind::Or(x) => {
+                refutable(ctx, &x.left)?;
+                refutable(ctx, &x.right)?;
+                Some(pattern.span.clone())
+            }
+            abs::PatternKind::Literal(_)
+            | abs::PatternKind::Range(_)
+            | abs::PatternKind::Application(_)
+            | abs::PatternKind::Effect(_)
+            | abs::PatternKind::Return(_) => Some(pattern.span.clone()),
+        }
+    }
+
+    /// Transform a pattern into an abstract pattern.
+    pub fn transform(ctx: &Context, pattern: tree::Pattern) -> abs::Pattern {
+        let mut vars = Default::default();
+
+        let pattern = transform_pat(ctx, pattern, &mut vars);
+
+        for var in vars.keys() {
+            ctx.with(DefinitionKind::Value, var.clone());
+        }
+
+        pattern
+    }
+
+    pub fn transform_row(ctx: &Context, patterns: Vec<Box<tree::Pattern>>) -> Vec<abs::Pattern> {
+        let mut vars = Default::default();
+
+        let patterns = patterns
+            .into_iter()
+            .map(|x| transform_pat(ctx, *x, &mut vars))
+            .collect::<Vec<_>>();
+
+        for var in vars.keys() {
+            ctx.with(DefinitionKind::Value, var.clone());
+        }
+
+        patterns
+    }
+
+    pub fn transform_attribute(ctx: &mut Context, attribute: tree::Attribute) -> abs::Expr {
+        let func = ctx.resolve(
+            DefinitionKind::Value,
+            attribute.name.0.value.span.clone(),
+            Qualified {
+                path: Path { segments: vec![] },
+                name: attribute.name.symbol(),
+            },
+        );
+        let func = ctx.reference(attribute.name.0.value.span.clone(), func);
+
+        let res = if let Some(func) = func {
+            let expr = expr::transform(ctx, *attribute.value);
+            abs::ExprKind::Application(abs::ApplicationExpr {
+                app: abs::AppKind::Normal,
+                func: Box::new(Spanned::new(
+                    abs::ExprKind::Constructor(func),
+                    Default::default(),
+                )),
+                args: vec![expr],
+            })
+        } else {
+            abs::ExprKind::Error
+        };
+
+        Box::new(Spanned::new(res, Default::default()))
+    }
+
+    /// Transform a pattern into an abstract pattern.
+    pub fn transform_pattern_arm(ctx: &mut Context, arm: tree::PatternArm) -> abs::PatternArm {
+        if !arm.patterns.is_empty() {
+            ctx.reset_constant()
+        }
+
+        ctx.scoped(|ctx| {
+            let outer = ctx.scope.borrow().values.clone();
+
+            let patterns: Vec<_> = arm
+                .patterns
+                .into_iter()
+                .map(|x| pattern::transform(ctx, *x.0))
+                .collect();
+
+            let expr = expr::transform(ctx, *arm.expr);
+            let guard = arm.guard.map(|x| expr::transform(ctx, *x.1));
+
+            if let ([pattern], Some(guard)) = (patterns.as_slice(), &guard) {
+                if let abs::PatternKind::Effect(eff) = &pattern.data {
+                    check_effect_guard(ctx, &outer, eff, guard);
+                }
+            }
+
+            abs::PatternArm {
+                patterns,
+                expr,
+                guard,
+            }
+        })
+    }
+
+    /// Reports the local variables that the guard of an effect pattern uses but that the pattern
+    /// doesn't bind. The guard runs in the clause of the operation, and can only look at the
+    /// arguments of the operation and at its continuation.
+    fn check_effect_guard(
+        ctx: &Context,
+        outer: &im_rc::HashSet<Symbol>,
+        eff: &abs::PatEffect,
+        guard: &abs::Expr,
+    ) {
+        use vulpi_syntax::visit::{Visit, Visitor};
+
+        #[derive(Default)]
+        struct Binders(std::collections::HashSet<Symbol>);
+
+        impl Visitor for Binders {
+            fn visit_pattern(&mut self, pattern: &abs::Pattern) {
+                if let abs::PatternKind::Variable(name) = &pattern.data {
+                    self.0.insert(name.clone());
+                }
+
+                pattern.walk(self)
+            }
+        }
+
+        struct Uses<'a> {
+            outer: &'a im_rc::HashSet<Symbol>,
+            binders: std::collections::HashSet<Symbol>,
+            found: Vec<(Symbol, Span)>,
+        }
+
+        impl<'a> Visitor for Uses<'a> {
+            fn visit_expr(&mut self, expr: &abs::Expr) {
+                if let abs::ExprKind::Variable(name) = &expr.data {
+                    if self.outer.contains(name) && !self.binders.contains(name) {
+                        self.found.push((name.clone(), expr.span.clone()));
+                    }
+                }
+
+                expr.walk(self)
+            }
+        }
+
+        let mut binders = Binders::default();
+
+        for arg in &eff.args {
+            binders.visit_pattern(arg);
+        }
+
+        binders.0.extend(eff.cont.clone());
+
+        let mut uses = Uses {
+            outer,
+            binders: binders.0,
+            found: Vec::new(),
+        };
+
+        uses.visit_expr(guard);
+
+        for (name, span) in uses.found {
+            ctx.reporter.report(Diagnostic::new(error::ResolverError {
+                span,
+                kind: error::ResolverErrorKind::OuterVariableInGuard(name),
+            }));
+        }
+    }
+
+    /// Transform a let mode into a list of pattern arms.
+    pub fn transform_let_mode(ctx: &mut Context, mode: LetMode) -> Vec<abs::PatternArm> {
+        match mode {
+            LetMode::Body(_, expr) => {
+                vec![abs::PatternArm {
+                    patterns: vec![],
+                    expr: expr::transform(ctx, *expr),
+                    guard: None,
+                }]
+            }
+            LetMode::Cases(cases) => cases
+                .into_iter()
+                .map(|x| transform_pattern_arm(ctx, x.arm))
+                .collect(),
+        }
+    }
+}
+
+/// Expressions are the ones that can be used in a function body.
+pub mod expr {
+    use super::*;
+    use crate::error::ResolverError;
+    use vulpi_syntax::r#abstract::SttmKind::Expr;
+
+    fn is_handler_var(ctx: &Context, expr: &concrete::tree::Expr) -> bool {
+        matches!(&expr.data, tree::ExprKind::Variable(x) if ctx.is_handler(&x.symbol()))
+    }
+
+    /// Transforms the parameters of a function and its body into a lambda for each parameter.
+    pub fn lambda(
+        ctx: &mut Context,
+        span: Span,
+        patterns: Vec<Box<tree::Pattern>>,
+        body: concrete::tree::Expr,
+    ) -> abs::Expr {
+        if ctx.in_head {
+            ctx.reset_constant()
+        }
+
+        ctx.scoped(|ctx| {
+            let pats: Vec<_> = pattern::transform_row(ctx, patterns);
+
+            let body = transform(ctx, body);
+
+            pats.into_iter().rev().fold(body, |body, param| {
+                Box::new(Spanned {
+                    data: abs::ExprKind::Lambda(abs::LambdaExpr { param, body }),
+                    span: span.clone(),
+                })
+            })
+        })
+    }
+
+    /// Transforms a qualified `do`, like `Option.do`, into applications of the `andThen` of its
+    /// module, so `let x = e` followed by the rest of the block is `andThen (\x => rest) e`.
+    fn qualified_do(ctx: &mut Context, span: Span, do_expr: tree::DoExpr) -> abs::ExprKind {
+        let path = Path {
+            segments: do_expr.path.iter().map(|(x, _)| x.symbol()).collect(),
+        };
+
+        let name = Qualified {
+            path,
+            name: Symbol::intern("andThen"),
+        };
+
+        let Some(bind) = ctx.resolve(DefinitionKind::Value, span.clone(), name) else {
+            return abs::ExprKind::Error;
+        };
+
+        // The statements that failed to parse were already reported.
+        let statements: Vec<_> = do_expr
+            .block
+            .statements
+            .into_iter()
+            .filter(|x| !matches!(x.data, tree::StatementKind::Error(_)))
+            .collect();
+
+        bind_statements(ctx, &bind, statements.into_iter()).data
+    }
+
+    fn bind_statements(
+        ctx: &mut Context,
+        bind: &abs::Qualified,
+        mut statements: std::vec::IntoIter<tree::Sttm>,
+    ) -> abs::Expr {
+        let Some(sttm) = statements.next() else {
+            return Box::new(Spanned::new(abs::ExprKind::Error, Span::ghost()));
+        };
+
+        let (pattern, value) = match sttm.data {
+            tree::StatementKind::Expr(expr) if statements.len() == 0 => {
+                return transform(ctx, *expr)
+            }
+            tree::StatementKind::Expr(expr) => (None, expr),
+            tree::StatementKind::Let(let_sttm) if statements.len() > 0 => {
+                (Some(let_sttm.pattern), let_sttm.expr)
+            }
+            tree::StatementKind::Expect(expect) if statements.len() > 0 => {
+                return expect_when(ctx, sttm.span, expect, |ctx| {
+                    bind_statements(ctx, bind, statements)
+                });
+            }
+            _ => {
+                ctx.reporter.report(Diagnostic::new(ResolverError {
+                    span: sttm.span.clone(),
+                    kind: error::ResolverErrorKind::StatementAtEndOfDo,
+                }));
+
+                return Box::new(Spanned::new(abs::ExprKind::Error, sttm.span));
+            }
+        };
+
+        let value = transform(ctx, *value);
+
+        let lambda = ctx.scoped(|ctx| {
+            let param = match pattern {
+                Some(pattern) => pattern::transform(ctx, *pattern),
+                None => Box::new(Spanned::new(abs::PatternKind::Wildcard, sttm.span.clone())),
+            };
+
+            let body = bind_statements(ctx, bind, statements);
+            abs::ExprKind::Lambda(abs::LambdaExpr { param, body })
+        });
+
+        let func = abs::ExprKind::Function(bind.clone());
+
+        let application = abs::ApplicationExpr {
+            app: abs::AppKind::Normal,
+            func: Box::new(Spanned::new(func, sttm.span.clone())),
+            args: vec![Box::new(Spanned::new(lambda, sttm.span.clone())), value],
+        };
+
+        Box::new(Spanned::new(abs::ExprKind::Application(application), sttm.span))
+    }
+
+    /// Transforms the statements of a block. The statements after an `expect` are in the arm of
+    /// a `when` that matches its pattern, so the block only goes on when the pattern matches.
+    fn block(ctx: &mut Context, mut statements: std::vec::IntoIter<tree::Sttm>) -> abs::Block {
+        let mut sttms = Vec::new();
 
-        let pattern = transform_pat(ctx, pattern, &mut vars);
+        while let Some(sttm) = statements.next() {
+            match sttm.data {
+                tree::StatementKind::Expect(expect) => {
+                    let span = sttm.span.clone();
 
-        for var in vars {
-            ctx.with(DefinitionKind::Value, var);
+                    let when = expect_when(ctx, sttm.span, expect, |ctx| {
+                        let rest = abs::ExprKind::Do(block(ctx, statements));
+                        Box::new(Spanned::new(rest, span.clone()))
+                    });
+
+                    sttms.push(Spanned::new(abs::SttmKind::Expr(when), span));
+                    break;
+                }
+                _ => sttms.push(transform_sttm(ctx, sttm)),
+            }
         }
 
-        pattern
+        abs::Block { sttms }
     }
 
-    pub fn transform_row(ctx: &Context, patterns: Vec<Box<tree::Pattern>>) -> Vec<abs::Pattern> {
-        let mut vars = Default::default();
-
-        let patterns = patterns
-            .into_iter()
-            .map(|x| transform_pat(ctx, *x, &mut vars))
-            .collect::<Vec<_>>();
+    /// The `when` of an `expect` statement. The first arm binds the pattern in the rest of the
+    /// block and the other one results in the alternative.
+    pub fn expect_when(
+        ctx: &mut Context,
+        span: Span,
+        expect: tree::ExpectSttm,
+        rest: impl FnOnce(&mut Context) -> abs::Expr,
+    ) -> abs::Expr {
+        ctx.in_head = false;
+
+        let scrutinee = transform(ctx, *expect.expr);
+        let alternative = transform(ctx, *expect.alternative);
+
+        let matched = ctx.scoped(|ctx| abs::PatternArm {
+            patterns: vec![pattern::transform(ctx, *expect.pattern)],
+            expr: rest(ctx),
+            guard: None,
+        });
+
+        let otherwise = abs::PatternArm {
+            patterns: vec![Box::new(Spanned::new(
+                abs::PatternKind::Wildcard,
+                span.clone(),
+            ))],
+            expr: alternative,
+            guard: None,
+        };
 
-        for var in vars {
-            ctx.with(DefinitionKind::Value, var);
-        }
+        let when = abs::WhenExpr {
+            scrutinee: vec![scrutinee],
+            arms: vec![matched, otherwise],
+        };
 
-        patterns
+        Box::new(Spanned::new(abs::ExprKind::When(when), span))
     }
 
-    pub fn transform_attribute(ctx: &mut Context, attribute: tree::Attribute) -> abs::Expr {
-        let func = ctx.resolve(
-            DefinitionKind::Value,
-            attribute.name.0.value.span.clone(),
-            Qualified {
-                path: Path { segments: vec![] },
-                name: attribute.name.symbol(),
+    /// Resolves a constructor that the desugaring of a loop uses, the same way that the list
+    /// literals resolve theirs.
+    fn constructor(
+        ctx: &mut Context,
+        span: Span,
+        module: &str,
+        name: &str,
+    ) -> Option<abs::Qualified> {
+        let name = Qualified {
+            path: Path {
+                segments: vec![Symbol::intern(module)],
             },
-        );
+            name: Symbol::intern(name),
+        };
 
-        let res = if let Some(func) = func {
-            let expr = expr::transform(ctx, *attribute.value);
-            abs::ExprKind::Application(abs::ApplicationExpr {
+        let constructor = ctx.resolve(DefinitionKind::Value, span.clone(), name)?;
+        ctx.insert_constant(constructor.clone(), span);
+        Some(constructor)
+    }
+
+    /// A loop as a function that calls itself. The function matches the scrutinee against the
+    /// pattern of the arm, runs the statements and calls itself again with the next argument.
+    /// Anything else finishes the loop with a unit.
+    ///
+    /// ```text
+    /// let loop$ param = when scrutinee is
+    ///   arm => do { statements ; loop$ next }
+    ///   _   => ()
+    /// in loop$ start
+    /// ```
+    fn loop_function(
+        span: &Span,
+        param: abs::Pattern,
+        scrutinee: abs::Expr,
+        arm: abs::Pattern,
+        mut statements: Vec<abs::Sttm>,
+        next: abs::Expr,
+        start: abs::Expr,
+    ) -> abs::ExprKind {
+        let name = Symbol::intern("loop$");
+        let spanned = |data| Box::new(Spanned::new(data, span.clone()));
+
+        let call = |arg| {
+            spanned(abs::ExprKind::Application(abs::ApplicationExpr {
                 app: abs::AppKind::Normal,
-                func: Box::new(Spanned::new(
-                    abs::ExprKind::Constructor(func),
-                    Default::default(),
-                )),
-                args: vec![expr],
-            })
-        } else {
-            abs::ExprKind::Error
+                func: spanned(abs::ExprKind::Variable(name.clone())),
+                args: vec![arg],
+            }))
         };
 
-        Box::new(Spanned::new(res, Default::default()))
-    }
+        statements.push(Spanned::new(abs::SttmKind::Expr(call(next)), span.clone()));
 
-    /// Transform a pattern into an abstract pattern.
-    pub fn transform_pattern_arm(ctx: &mut Context, arm: tree::PatternArm) -> abs::PatternArm {
-        if !arm.patterns.is_empty() {
-            ctx.reset_constant()
-        }
+        let unit = Box::new(Spanned::new(abs::LiteralKind::Unit, span.clone()));
 
-        ctx.scoped(|ctx| abs::PatternArm {
-            patterns: arm
-                .patterns
-                .into_iter()
-                .map(|x| pattern::transform(ctx, *x.0))
-                .collect(),
-            expr: expr::transform(ctx, *arm.expr),
-            guard: arm.guard.map(|x| expr::transform(ctx, *x.1)),
+        let when = abs::WhenExpr {
+            scrutinee: vec![scrutinee],
+            arms: vec![
+                abs::PatternArm {
+                    patterns: vec![arm],
+                    expr: spanned(abs::ExprKind::Do(abs::Block { sttms: statements })),
+                    guard: None,
+                },
+                abs::PatternArm {
+                    patterns: vec![Box::new(Spanned::new(
+                        abs::PatternKind::Wildcard,
+                        span.clone(),
+                    ))],
+                    expr: spanned(abs::ExprKind::Literal(unit)),
+                    guard: None,
+                },
+            ],
+        };
+
+        let function = abs::ExprKind::Lambda(abs::LambdaExpr {
+            param,
+            body: spanned(abs::ExprKind::When(when)),
+        });
+
+        abs::ExprKind::LetGroup(abs::LetGroupExpr {
+            bindings: vec![abs::LetBinding {
+                name: name.clone(),
+                body: spanned(function),
+            }],
+            value: call(start),
         })
     }
 
-    /// Transform a let mode into a list of pattern arms.
-    pub fn transform_let_mode(ctx: &mut Context, mode: LetMode) -> Vec<abs::PatternArm> {
-        match mode {
-            LetMode::Body(_, expr) => {
-                vec![abs::PatternArm {
-                    patterns: vec![],
-                    expr: expr::transform(ctx, *expr),
-                    guard: None,
-                }]
-            }
-            LetMode::Cases(cases) => cases
-                .into_iter()
-                .map(|x| transform_pattern_arm(ctx, x.arm))
-                .collect(),
-        }
+    /// Transforms a `while` into a loop function that takes a unit and runs the block while the
+    /// condition is `Bool.True`.
+    fn while_loop(ctx: &mut Context, span: Span, while_: tree::WhileExpr) -> abs::ExprKind {
+        ctx.in_head = false;
+
+        let Some(true_) = constructor(ctx, span.clone(), "Bool", "True") else {
+            return abs::ExprKind::Error;
+        };
+
+        let condition = transform(ctx, *while_.condition);
+        let body = ctx.scoped(|ctx| block(ctx, while_.body.block.statements.into_iter()));
+
+        let spanned = |data| Box::new(Spanned::new(data, span.clone()));
+        let unit = || {
+            let unit = Box::new(Spanned::new(abs::LiteralKind::Unit, span.clone()));
+            spanned(abs::ExprKind::Literal(unit))
+        };
+
+        let arm = abs::PatternKind::Application(abs::PatApplication {
+            func: true_,
+            args: vec![],
+        });
+
+        let body = abs::SttmKind::Expr(spanned(abs::ExprKind::Do(body)));
+
+        loop_function(
+            &span,
+            Box::new(Spanned::new(abs::PatternKind::Wildcard, span.clone())),
+            condition,
+            Box::new(Spanned::new(arm, span.clone())),
+            vec![Spanned::new(body, span.clone())],
+            unit(),
+            unit(),
+        )
     }
-}
 
-/// Expressions are the ones that can be used in a function body.
-pub mod expr {
-    use super::*;
-    use crate::error::ResolverError;
-    use vulpi_syntax::r#abstract::SttmKind::Expr;
+    /// Transforms a `for` into a loop function that takes the rest of the list, and binds the
+    /// pattern to each element before it runs the block.
+    fn for_loop(ctx: &mut Context, span: Span, for_: tree::ForExpr) -> abs::ExprKind {
+        ctx.in_head = false;
 
-    /// Transforms an expression into an abstract expression.
-    pub fn transform(ctx: &mut Context, expr: concrete::tree::Expr) -> abs::Expr {
-        use tree::ExprKind::*;
+        let Some(cons) = constructor(ctx, span.clone(), "List", "Cons") else {
+            return abs::ExprKind::Error;
+        };
 
-        let data = match expr.data {
-            Lambda(lam) => {
-                if ctx.in_head {
-                    ctx.reset_constant()
+        let list = transform(ctx, *for_.list);
+
+        let param = Symbol::intern("list$");
+        let item = Symbol::intern("item$");
+        let rest = Symbol::intern("rest$");
+
+        let variable = |name: &Symbol| {
+            Box::new(Spanned::new(
+                abs::PatternKind::Variable(name.clone()),
+                span.clone(),
+            ))
+        };
+
+        let value = |name: &Symbol| {
+            Box::new(Spanned::new(
+                abs::ExprKind::Variable(name.clone()),
+                span.clone(),
+            ))
+        };
+
+        let arm = abs::PatternKind::Application(abs::PatApplication {
+            func: cons,
+            args: vec![variable(&item), variable(&rest)],
+        });
+
+        let statements = ctx.scoped(|ctx| {
+            let pat = pattern::transform(ctx, *for_.pattern);
+            let body = block(ctx, for_.body.block.statements.into_iter());
+
+            let bind = abs::SttmKind::Let(abs::LetSttm {
+                pat,
+                expr: value(&item),
+            });
+
+            let body = abs::SttmKind::Expr(Box::new(Spanned::new(
+                abs::ExprKind::Do(body),
+                span.clone(),
+            )));
+
+            vec![Spanned::new(bind, span.clone()), Spanned::new(body, span.clone())]
+        });
+
+        loop_function(
+            &span,
+            variable(&param),
+            value(&param),
+            Box::new(Spanned::new(arm, span.clone())),
+            statements,
+            value(&rest),
+            list,
+        )
+    }
+
+    /// Transforms a let group. The names of all the bindings are in scope before any of them is
+    /// transformed, so they can refer to each other.
+    fn let_group(ctx: &mut Context, group: tree::LetGroupExpr) -> abs::ExprKind {
+        ctx.scoped(|ctx| {
+            let mut names: HashMap<Symbol, Span> = HashMap::new();
+            let mut unique = Vec::new();
+
+            for (binding, _) in group.bindings {
+                let span = binding.name.0.value.span.clone();
+
+                if let Some(first) = names.get(&binding.name.symbol()) {
+                    ctx.reporter.report(Diagnostic::new(ResolverError {
+                        span,
+                        kind: error::ResolverErrorKind::DuplicatePattern(
+                            binding.name.symbol(),
+                            first.clone(),
+                        ),
+                    }));
+                } else {
+                    names.insert(binding.name.symbol(), span);
+                    ctx.with(DefinitionKind::Value, binding.name.symbol());
+                    unique.push(binding);
                 }
+            }
 
-                return ctx.scoped(|ctx| {
-                    let pats: Vec<_> = pattern::transform_row(ctx, lam.patterns);
+            let mut bindings = Vec::new();
 
-                    let body = transform(ctx, *lam.expr);
+            for binding in unique {
+                let body = if binding.params.is_empty() {
+                    transform(ctx, *binding.value)
+                } else {
+                    let span = binding.name.0.value.span.clone().mix(binding.value.span.clone());
+                    lambda(ctx, span, binding.params, *binding.value)
+                };
 
-                    pats.into_iter().rev().fold(body, |body, param| {
-                        Box::new(Spanned {
-                            data: abs::ExprKind::Lambda(abs::LambdaExpr { param, body }),
-                            span: expr.span.clone(),
-                        })
-                    })
+                bindings.push(abs::LetBinding {
+                    name: binding.name.symbol(),
+                    body,
                 });
             }
 
+            abs::ExprKind::LetGroup(abs::LetGroupExpr {
+                bindings,
+                value: transform(ctx, *group.value),
+            })
+        })
+    }
+
+    /// Transforms an expression into an abstract expression.
+    pub fn transform(ctx: &mut Context, expr: concrete::tree::Expr) -> abs::Expr {
+        use tree::ExprKind::*;
+
+        let data = match expr.data {
+            Lambda(lam) => return lambda(ctx, expr.span, lam.patterns, *lam.expr),
+
             List(list) => {
                 let values: Vec<_> = list
                     .values
@@ -1306,6 +2903,16 @@ pub mod expr {
                 fold_list(ctx, expr.span.clone(), values)
             }
 
+            Array(array) => {
+                let values: Vec<_> = array
+                    .values
+                    .into_iter()
+                    .map(|(expr, _)| transform(ctx, *expr))
+                    .collect();
+
+                fold_array(ctx, expr.span.clone(), values)
+            }
+
             Application(app) => {
                 ctx.in_head = false;
 
@@ -1320,11 +2927,50 @@ pub mod expr {
                 })
             }
 
+            Perform(perform) => {
+                ctx.in_head = false;
+
+                // Variables are never operations, so a name without a path is searched in the
+                // declarations even if a variable has it.
+                let path = perform.operation;
+                let span = path.span.clone();
+
+                let searched = if path.segments.is_empty() {
+                    ctx.search(DefinitionKind::Value, span, path.last.symbol())
+                } else {
+                    ctx.resolve(DefinitionKind::Value, span, from_lower_path(&path))
+                };
+
+                match ctx.reference(path.last.0.value.span.clone(), searched) {
+                    Some(operation) => {
+                        ctx.insert_constant(operation.clone(), expr.span.clone());
+
+                        abs::ExprKind::Perform(abs::PerformExpr {
+                            operation,
+                            args: perform
+                                .args
+                                .into_iter()
+                                .map(|expr| transform(ctx, *expr))
+                                .collect(),
+                        })
+                    }
+                    None => abs::ExprKind::Error,
+                }
+            }
+
+            Variable(x) if ctx.is_handler(&x.symbol()) => {
+                ctx.reporter.report(Diagnostic::new(ResolverError {
+                    span: expr.span.clone(),
+                    kind: error::ResolverErrorKind::HandlerAsValue(x.symbol()),
+                }));
+                abs::ExprKind::Error
+            }
             Variable(x) => {
                 if ctx.in_scope(DefinitionKind::Value, x.symbol()) {
                     abs::ExprKind::Variable(x.symbol())
                 } else {
                     let searched = ctx.search(DefinitionKind::Value, expr.span.clone(), x.symbol());
+                    let searched = ctx.reference(expr.span.clone(), searched);
 
                     match searched {
                         Some(res) => {
@@ -1336,11 +2982,12 @@ pub mod expr {
                 }
             }
             Constructor(x) => {
-                match ctx.resolve(
+                let constructor = ctx.resolve(
                     DefinitionKind::Value,
                     expr.span.clone(),
                     from_constructor_upper_path(&x),
-                ) {
+                );
+                match ctx.reference(x.last.0.value.span.clone(), constructor) {
                     Some(res) => {
                         ctx.insert_constant(res.clone(), expr.span.clone());
                         abs::ExprKind::Constructor(res)
@@ -1351,6 +2998,7 @@ pub mod expr {
             Function(path) => {
                 let qualified = from_lower_path(&path);
                 let searched = ctx.resolve(DefinitionKind::Value, expr.span.clone(), qualified);
+                let searched = ctx.reference(path.last.0.value.span.clone(), searched);
                 match searched {
                     Some(res) => {
                         ctx.insert_constant(res.clone(), expr.span.clone());
@@ -1361,8 +3009,19 @@ pub mod expr {
                 }
             }
 
+            Projection(projection) if is_handler_var(ctx, &projection.expr) => {
+                let tree::ExprKind::Variable(handler) = projection.expr.data else {
+                    unreachable!()
+                };
+
+                abs::ExprKind::Operation(abs::OperationExpr {
+                    handler: handler.symbol(),
+                    name: projection.field.symbol(),
+                })
+            }
             Projection(projection) => abs::ExprKind::Projection(abs::ProjectionExpr {
                 expr: transform(ctx, *projection.expr),
+                candidates: ctx.records_with_field(&projection.field.symbol()),
                 field: projection.field.symbol(),
             }),
             Binary(bin) => {
@@ -1393,16 +3052,7 @@ pub mod expr {
                     tree::Operator::Concat(_) => "concat",
                 };
 
-                let path = ctx.resolve(
-                    DefinitionKind::Value,
-                    expr.span.clone(),
-                    Qualified {
-                        path: Path {
-                            segments: vec![Symbol::intern("Prelude")],
-                        },
-                        name: Symbol::intern(name),
-                    },
-                );
+                let path = ctx.resolve_operator(expr.span.clone(), name);
 
                 if let Some(path) = path {
                     abs::ExprKind::Application(abs::ApplicationExpr {
@@ -1427,6 +3077,7 @@ pub mod expr {
                     })
                 })
             }
+            LetGroup(group) => let_group(ctx, group),
             When(when) => {
                 ctx.in_head = false;
                 abs::ExprKind::When(abs::WhenExpr {
@@ -1442,15 +3093,66 @@ pub mod expr {
                         .collect(),
                 })
             }
-            Do(do_expr) => ctx.scoped(|ctx| {
-                abs::ExprKind::Do(abs::Block {
-                    sttms: do_expr
-                        .block
-                        .statements
+            Cases(cases) => {
+                if ctx.in_head {
+                    ctx.reset_constant()
+                }
+
+                abs::ExprKind::Cases(abs::CasesExpr {
+                    arms: cases
+                        .arms
                         .into_iter()
-                        .map(|x| transform_sttm(ctx, x))
+                        .map(|x| pattern::transform_pattern_arm(ctx, x))
                         .collect(),
                 })
+            }
+            Handler(handler) => {
+                ctx.in_head = false;
+                abs::ExprKind::Handler(abs::HandlerExpr {
+                    name: None,
+                    expr: transform(ctx, *handler.expr),
+                    handler: transform(ctx, *handler.handler),
+                    finally: handler.finally.map(|(_, expr)| transform(ctx, *expr)),
+                })
+            }
+            NamedHandler(handler) => {
+                ctx.in_head = false;
+
+                let name = handler.name.symbol();
+                let elab_handler = transform(ctx, *handler.handler);
+
+                ctx.scoped(|ctx| {
+                    ctx.with_handler(name.clone());
+
+                    abs::ExprKind::Handler(abs::HandlerExpr {
+                        name: Some(name),
+                        expr: transform(ctx, *handler.expr),
+                        handler: elab_handler,
+                        finally: None,
+                    })
+                })
+            }
+            Mask(mask) => {
+                ctx.in_head = false;
+
+                let effect = ctx.resolve(
+                    DefinitionKind::Type,
+                    mask.effect.span.clone(),
+                    from_constructor_upper_path(&mask.effect),
+                );
+
+                let expr = transform(ctx, *mask.expr);
+
+                match ctx.reference(mask.effect.last.0.value.span.clone(), effect) {
+                    Some(effect) => abs::ExprKind::Mask(abs::MaskExpr { effect, expr }),
+                    None => abs::ExprKind::Error,
+                }
+            }
+            While(while_) => while_loop(ctx, expr.span.clone(), while_),
+            For(for_) => for_loop(ctx, expr.span.clone(), for_),
+            Do(do_expr) if !do_expr.path.is_empty() => qualified_do(ctx, expr.span.clone(), do_expr),
+            Do(do_expr) => ctx.scoped(|ctx| {
+                abs::ExprKind::Do(block(ctx, do_expr.block.statements.into_iter()))
             }),
             Literal(x) => abs::ExprKind::Literal(transform_literal(x)),
             Annotation(x) => {
@@ -1466,6 +3168,7 @@ pub mod expr {
                     expr.span.clone(),
                     from_constructor_upper_path(&record_instance.name),
                 );
+                let path = ctx.reference(record_instance.name.last.0.value.span.clone(), path);
 
                 match path {
                     Some(name) => abs::ExprKind::RecordInstance(abs::RecordInstance {
@@ -1614,6 +3317,66 @@ pub mod expr {
             abs::ExprKind::Error
         }
     }
+
+    /// Transforms an array literal into a block that pushes the values into a new array.
+    ///
+    /// ```text
+    /// do
+    ///   let array$ = Array.empty ()
+    ///   Array.push array$ value
+    ///   array$
+    /// ```
+    fn fold_array(ctx: &mut Context, span: Span, values: Vec<abs::Expr>) -> abs::ExprKind {
+        let mut operation = |name: &str| {
+            let path = Qualified {
+                path: Path {
+                    segments: vec![Symbol::intern("Array")],
+                },
+                name: Symbol::intern(name),
+            };
+
+            let operation = ctx.resolve(DefinitionKind::Value, span.clone(), path)?;
+            ctx.insert_constant(operation.clone(), span.clone());
+            Some(operation)
+        };
+
+        let (Some(empty), Some(push)) = (operation("empty"), operation("push")) else {
+            return abs::ExprKind::Error;
+        };
+
+        let name = Symbol::intern("array$");
+        let spanned = |data| Box::new(Spanned::new(data, span.clone()));
+        let array = || spanned(abs::ExprKind::Variable(name.clone()));
+
+        let call = |func, args| {
+            spanned(abs::ExprKind::Application(abs::ApplicationExpr {
+                app: abs::AppKind::Normal,
+                func: spanned(abs::ExprKind::Function(func)),
+                args,
+            }))
+        };
+
+        let unit = spanned(abs::ExprKind::Literal(Box::new(Spanned::new(
+            abs::LiteralKind::Unit,
+            span.clone(),
+        ))));
+
+        let bind = abs::SttmKind::Let(abs::LetSttm {
+            pat: Box::new(Spanned::new(abs::PatternKind::Variable(name.clone()), span.clone())),
+            expr: call(empty, vec![unit]),
+        });
+
+        let mut sttms = vec![Spanned::new(bind, span.clone())];
+
+        for value in values {
+            let push = abs::SttmKind::Expr(call(push.clone(), vec![array(), value]));
+            sttms.push(Spanned::new(push, span.clone()));
+        }
+
+        sttms.push(Spanned::new(abs::SttmKind::Expr(array()), span.clone()));
+
+        abs::ExprKind::Do(abs::Block { sttms })
+    }
 }
 
 /// The super module can access all the names in the module of an struct, so this is useful
@@ -1655,6 +3418,97 @@ pub fn transform_type_binder(_ctx: &Context, binder: tree::TypeBinder) -> abs::T
     }
 }
 
+/// Transforms the attributes of a declaration, reporting the ones that are unknown or that have the
+/// wrong arguments.
+pub fn transform_attributes(
+    ctx: &Context,
+    attributes: Vec<tree::DeclAttribute>,
+) -> abs::Attributes {
+    let mut result = abs::Attributes::default();
+
+    for attribute in attributes {
+        let name = attribute.name.symbol();
+
+        let kind = match (name.get().as_str(), attribute.args, attribute.message) {
+            ("allow", Some(args), None) => {
+                result.allow.extend(args.data.into_iter().map(|(x, _)| x.symbol()));
+                continue;
+            }
+            ("deprecated", None, message) => {
+                let message = message.map(|x| x.symbol());
+                result.deprecated = Some(abs::Deprecation { message });
+                continue;
+            }
+            ("allow" | "deprecated", _, _) => error::ResolverErrorKind::MalformedAttribute(name),
+            ("opaque" | "pure", _, _) => error::ResolverErrorKind::MisplacedAttribute(name),
+            _ => error::ResolverErrorKind::UnknownAttribute(name),
+        };
+
+        report_attribute(ctx, &attribute.hash_bracket, &attribute.right_bracket, kind);
+    }
+
+    result
+}
+
+/// Transforms the attributes of a type declaration, that is the only one that can be opaque.
+pub fn transform_type_attributes(
+    ctx: &Context,
+    attributes: Vec<tree::DeclAttribute>,
+) -> abs::Attributes {
+    let (opaque, others): (Vec<_>, Vec<_>) = attributes
+        .into_iter()
+        .partition(|x| x.name.symbol().get() == "opaque");
+
+    let mut result = transform_attributes(ctx, others);
+    result.opaque = transform_flag(ctx, opaque);
+    result
+}
+
+/// Transforms the attributes of a let or an external declaration, the only ones that can be pure.
+pub fn transform_value_attributes(
+    ctx: &Context,
+    attributes: Vec<tree::DeclAttribute>,
+) -> abs::Attributes {
+    let (pure, others): (Vec<_>, Vec<_>) = attributes
+        .into_iter()
+        .partition(|x| x.name.symbol().get() == "pure");
+
+    let mut result = transform_attributes(ctx, others);
+    result.pure = transform_flag(ctx, pure);
+    result
+}
+
+/// Whether an attribute without arguments, like `#[opaque]`, is written. The ones that have
+/// arguments are reported.
+fn transform_flag(ctx: &Context, attributes: Vec<tree::DeclAttribute>) -> bool {
+    let mut written = false;
+
+    for attribute in attributes {
+        if attribute.args.is_none() && attribute.message.is_none() {
+            written = true;
+        } else {
+            let kind = error::ResolverErrorKind::MalformedAttribute(attribute.name.symbol());
+            report_attribute(ctx, &attribute.hash_bracket, &attribute.right_bracket, kind);
+        }
+    }
+
+    written
+}
+
+fn report_attribute(
+    ctx: &Context,
+    start: &Token,
+    end: &Token,
+    kind: error::ResolverErrorKind,
+) {
+    let span = start.value.span.clone();
+
+    ctx.reporter.report(Diagnostic::new(error::ResolverError {
+        span: span.mix(end.value.span.clone()),
+        kind,
+    }));
+}
+
 pub fn transform_type(ctx: &Context, concrete_type: tree::Type) -> abs::Type {
     let data = match concrete_type.data {
         tree::TypeKind::Parenthesis(x) => return transform_type(ctx, *x.data.0),
@@ -1670,6 +3524,7 @@ pub fn transform_type(ctx: &Context, concrete_type: tree::Type) -> abs::Type {
                 concrete_type.span.clone(),
                 from_constructor_upper_path(&typ),
             );
+            let path = ctx.reference(typ.last.0.value.span.clone(), path);
             match path {
                 Some(res) => abs::TypeKind::Type(res),
                 None => abs::TypeKind::Error,
@@ -1747,6 +3602,17 @@ pub fn transform_sttm(ctx: &mut Context, sttm: concrete::tree::Sttm) -> abs::Stt
             let expr = expr::transform(ctx, *expr);
             abs::SttmKind::Expr(expr)
         }
+        // Without the rest of its block, an `expect` only checks the value.
+        tree::StatementKind::Expect(expect) => {
+            let span = sttm.span.clone();
+
+            let when = expr::expect_when(ctx, sttm.span.clone(), expect, |_| {
+                let rest = abs::ExprKind::Do(abs::Block { sttms: vec![] });
+                Box::new(Spanned::new(rest, span))
+            });
+
+            abs::SttmKind::Expr(when)
+        }
         tree::StatementKind::Error(_) => abs::SttmKind::Error,
     };
 
@@ -1760,6 +3626,54 @@ pub fn transform_sttm(ctx: &mut Context, sttm: concrete::tree::Sttm) -> abs::Stt
 pub fn resolve(ctx: &Context, program: tree::Program) -> Solver<abs::Program> {
     let mut solvers = vec![];
 
+    // Primitive effects and the `Async` effect with its tasks are defined by the type checker but
+    // they live in the prelude.
+    if ctx.module.name().segments == [Symbol::intern("Prelude")] {
+        ctx.module.define(
+            DefinitionKind::Type,
+            Visibility::Public,
+            Symbol::intern("IO"),
+            Span::ghost(),
+            Span::ghost(),
+        );
+
+        ctx.module.define(
+            DefinitionKind::Type,
+            Visibility::Public,
+            Symbol::intern("Task"),
+            Span::ghost(),
+            Span::ghost(),
+        );
+
+        let effects = [
+            ("Ref", &["new", "get", "set"][..]),
+            ("Array", &["empty", "make", "get", "set", "length", "push"][..]),
+            ("Async", &["spawn", "await", "yield"][..]),
+        ];
+
+        for (effect, operations) in effects {
+            ctx.module.define(
+                DefinitionKind::Type,
+                Visibility::Public,
+                Symbol::intern(effect),
+                Span::ghost(),
+                Span::ghost(),
+            );
+
+            let submodule = ctx.fork(Symbol::intern(effect));
+
+            for operation in operations {
+                submodule.module.define(
+                    DefinitionKind::Value,
+                    Visibility::Public,
+                    Symbol::intern(operation),
+                    Span::ghost(),
+                    Span::ghost(),
+                );
+            }
+        }
+    }
+
     for top_level in program.top_levels {
         if let Some(res) = top_level::resolve(ctx.clone(), top_level) {
             solvers.push(res);
@@ -1772,12 +3686,15 @@ pub fn resolve(ctx: &Context, program: tree::Program) -> Solver<abs::Program> {
         for solver in solvers {
             match solver.eval(ctx.clone()) {
                 abs::TopLevel::Let(x) => program.lets.push(x),
+                abs::TopLevel::Pattern(x) => program.lets.extend(x),
                 abs::TopLevel::Type(x) => program.types.push(x),
+                abs::TopLevel::Effect(x) => program.effects.push(x),
                 abs::TopLevel::Module(x) => program.modules.push(x),
                 abs::TopLevel::External(x) => program.externals.push(x),
                 abs::TopLevel::Trait(x) => program.traits.push(x),
                 abs::TopLevel::Impl(Some(t)) => program.impls.push(t),
                 abs::TopLevel::Command(name, symbol) => program.commands.push((name, symbol)),
+                abs::TopLevel::Test(x) => program.tests.push(x),
                 abs::TopLevel::Impl(None) => (),
                 abs::TopLevel::Use => (),
             }
@@ -0,0 +1,411 @@
+// The garbage collector of the runtime. Values are untagged words and the compiled code doesn't
+// say which of them are pointers, so the collector is conservative: every word of the stack, of
+// the global cells and of the blocks that are not raw is treated as a pointer if it points inside
+// of an allocated block. Blocks never move, because a word that looks like a pointer may be an
+// integer that cannot be changed, and the unreachable ones are swept into free lists.
+//
+// The heap is made of chunks that are aligned to their size. Small blocks are rounded up to a size
+// class and every chunk only has blocks of a single class, so the block that a word points to is
+// found by dividing its offset in the chunk. Big blocks get chunks of their own.
+
+#include <setjmp.h>
+#include <stdio.h>
+#include <stdlib.h>
+#include <string.h>
+
+#include "vulpi.h"
+
+#define CHUNK_BITS 16
+#define CHUNK_SIZE ((size_t)1 << CHUNK_BITS)
+
+// The bytes that can be allocated before the first collection. After that the collector runs when
+// the allocated bytes reach the ones that survived the last collection.
+#ifndef MINIMUM_THRESHOLD
+#define MINIMUM_THRESHOLD ((size_t)8 << 20)
+#endif
+
+static const size_t classes[] = {
+    8, 16, 24, 32, 48, 64, 96, 128, 192, 256, 384, 512, 768, 1024, 1536, 2048, 3072, 4096, 8192,
+};
+
+#define CLASSES (sizeof(classes) / sizeof(classes[0]))
+#define LARGE 8192
+
+struct chunk {
+    char *start;
+
+    // The size of the blocks, or of the whole chunk if it has a single big block.
+    size_t size;
+    size_t blocks;
+    size_t pieces;
+    int raw;
+
+    uint8_t *allocated;
+    uint8_t *marked;
+    void *free;
+
+    struct chunk *next;
+
+    // The next chunk of the same class with free blocks.
+    struct chunk *available;
+};
+
+// The table of the global cells emitted by the native backend: the number of cells followed by
+// their addresses.
+extern const value vulpi_roots[];
+
+static struct chunk *chunks;
+static struct chunk *available[2][CLASSES];
+
+// An open addressing table from the pieces of CHUNK_SIZE bytes to the chunks that cover them.
+struct entry {
+    uintptr_t piece;
+    struct chunk *chunk;
+};
+
+static struct entry *table;
+static size_t table_size;
+static size_t table_count;
+
+static uintptr_t heap_low = UINTPTR_MAX;
+static uintptr_t heap_high;
+
+static size_t allocated_bytes;
+static size_t threshold = MINIMUM_THRESHOLD;
+static char *stack_bottom;
+
+struct range {
+    char *start;
+    size_t size;
+};
+
+static struct range *mark_stack;
+static size_t mark_count;
+static size_t mark_capacity;
+
+static void *allocate_or_fail(size_t bytes) {
+    void *memory = calloc(1, bytes);
+
+    if (memory == NULL) {
+        fprintf(stderr, "[Error]: out of memory\n");
+        exit(1);
+    }
+
+    return memory;
+}
+
+static size_t slot(uintptr_t piece) {
+    return (size_t)((piece * 0x9E3779B97F4A7C15ull) >> 16) & (table_size - 1);
+}
+
+static void table_insert(uintptr_t piece, struct chunk *chunk) {
+    if ((table_count + 1) * 2 > table_size) {
+        struct entry *old = table;
+        size_t old_size = table_size;
+
+        table_size = table_size ? table_size * 2 : 1024;
+        table = allocate_or_fail(table_size * sizeof(struct entry));
+        table_count = 0;
+
+        for (size_t i = 0; i < old_size; i++) {
+            if (old[i].chunk != NULL) {
+                table_insert(old[i].piece, old[i].chunk);
+            }
+        }
+
+        free(old);
+    }
+
+    size_t i = slot(piece);
+
+    while (table[i].chunk != NULL) {
+        i = (i + 1) & (table_size - 1);
+    }
+
+    table[i].piece = piece;
+    table[i].chunk = chunk;
+    table_count++;
+}
+
+static struct chunk *table_find(uintptr_t piece) {
+    if (table_size == 0) {
+        return NULL;
+    }
+
+    for (size_t i = slot(piece); table[i].chunk != NULL; i = (i + 1) & (table_size - 1)) {
+        if (table[i].piece == piece) {
+            return table[i].chunk;
+        }
+    }
+
+    return NULL;
+}
+
+static void table_add(struct chunk *chunk) {
+    uintptr_t start = (uintptr_t)chunk->start;
+
+    for (size_t i = 0; i < chunk->pieces; i++) {
+        table_insert((start >> CHUNK_BITS) + i, chunk);
+    }
+
+    uintptr_t end = start + chunk->pieces * CHUNK_SIZE;
+    heap_low = start < heap_low ? start : heap_low;
+    heap_high = end > heap_high ? end : heap_high;
+}
+
+static struct chunk *new_chunk(size_t size, size_t pieces, int raw) {
+    struct chunk *chunk = allocate_or_fail(sizeof(struct chunk));
+    void *start;
+
+    if (posix_memalign(&start, CHUNK_SIZE, pieces * CHUNK_SIZE) != 0) {
+        fprintf(stderr, "[Error]: out of memory\n");
+        exit(1);
+    }
+
+    chunk->start = start;
+    chunk->size = size;
+    chunk->blocks = pieces * CHUNK_SIZE / size;
+    chunk->pieces = pieces;
+    chunk->raw = raw;
+    chunk->allocated = allocate_or_fail(chunk->blocks);
+    chunk->marked = allocate_or_fail(chunk->blocks);
+    chunk->next = chunks;
+    chunks = chunk;
+
+    table_add(chunk);
+
+    return chunk;
+}
+
+static void free_chunk(struct chunk *chunk) {
+    free(chunk->start);
+    free(chunk->allocated);
+    free(chunk->marked);
+    free(chunk);
+}
+
+// Links the blocks of the chunk that are not allocated into its free list, in the order of their
+// addresses.
+static size_t sweep_chunk(struct chunk *chunk) {
+    size_t used = 0;
+    chunk->free = NULL;
+
+    for (size_t i = chunk->blocks; i-- > 0;) {
+        if (chunk->allocated[i] && chunk->marked[i]) {
+            chunk->marked[i] = 0;
+            used++;
+        } else {
+            void **block = (void **)(chunk->start + i * chunk->size);
+            chunk->allocated[i] = 0;
+            *block = chunk->free;
+            chunk->free = block;
+        }
+    }
+
+    return used;
+}
+
+void vulpi_gc_init(void *bottom) {
+    stack_bottom = bottom;
+}
+
+static void mark_word(value word) {
+    uintptr_t address = (uintptr_t)word;
+
+    if (address < heap_low || address >= heap_high) {
+        return;
+    }
+
+    struct chunk *chunk = table_find(address >> CHUNK_BITS);
+
+    if (chunk == NULL) {
+        return;
+    }
+
+    size_t i = (address - (uintptr_t)chunk->start) / chunk->size;
+
+    if (i >= chunk->blocks || !chunk->allocated[i] || chunk->marked[i]) {
+        return;
+    }
+
+    chunk->marked[i] = 1;
+
+    if (chunk->raw) {
+        return;
+    }
+
+    if (mark_count == mark_capacity) {
+        mark_capacity = mark_capacity ? mark_capacity * 2 : 1024;
+        mark_stack = realloc(mark_stack, mark_capacity * sizeof(struct range));
+
+        if (mark_stack == NULL) {
+            fprintf(stderr, "[Error]: out of memory\n");
+            exit(1);
+        }
+    }
+
+    mark_stack[mark_count].start = chunk->start + i * chunk->size;
+    mark_stack[mark_count].size = chunk->size;
+    mark_count++;
+}
+
+static void mark_range(char *start, char *end) {
+    start = (char *)(((uintptr_t)start + sizeof(value) - 1) & ~(uintptr_t)(sizeof(value) - 1));
+
+    for (char *word = start; word + sizeof(value) <= end; word += sizeof(value)) {
+        value contents;
+        memcpy(&contents, word, sizeof(value));
+        mark_word(contents);
+    }
+}
+
+static void mark_all(void) {
+    while (mark_count > 0) {
+        struct range range = mark_stack[--mark_count];
+        mark_range(range.start, range.start + range.size);
+    }
+}
+
+// The stack is scanned from a frame below the one of `vulpi_gc_collect`, so the registers that it
+// saved are scanned too.
+__attribute__((noinline)) static void mark_stack_from_here(void) {
+    char top;
+    mark_range(&top, stack_bottom);
+    mark_all();
+}
+
+static void sweep(void) {
+    size_t live = 0;
+    struct chunk **link = &chunks;
+
+    memset(available, 0, sizeof(available));
+
+    for (struct chunk *chunk = chunks; chunk != NULL;) {
+        struct chunk *next = chunk->next;
+        size_t used = sweep_chunk(chunk);
+
+        if (used == 0) {
+            *link = next;
+            free_chunk(chunk);
+        } else {
+            live += used * chunk->size;
+            link = &chunk->next;
+
+            if (chunk->free != NULL && chunk->size <= LARGE) {
+                size_t c = 0;
+
+                while (classes[c] != chunk->size) {
+                    c++;
+                }
+
+                chunk->available = available[chunk->raw][c];
+                available[chunk->raw][c] = chunk;
+            }
+        }
+
+        chunk = next;
+    }
+
+    free(table);
+    table = NULL;
+    table_size = 0;
+    table_count = 0;
+    heap_low = UINTPTR_MAX;
+    heap_high = 0;
+
+    for (struct chunk *chunk = chunks; chunk != NULL; chunk = chunk->next) {
+        table_add(chunk);
+    }
+
+    allocated_bytes = 0;
+    threshold = live > MINIMUM_THRESHOLD ? live : MINIMUM_THRESHOLD;
+}
+
+void vulpi_gc_collect(void) {
+    jmp_buf registers;
+    setjmp(registers);
+
+#if defined(__GNUC__)
+    __builtin_unwind_init();
+#endif
+
+    for (value i = 1; i <= vulpi_roots[0]; i++) {
+        mark_word(*(value *)vulpi_roots[i]);
+    }
+
+    mark_stack_from_here();
+    sweep();
+
+    // Keeps the registers alive until the stack was scanned.
+    __asm__ volatile("" : : "r"(&registers) : "memory");
+}
+
+static size_t class_of(size_t bytes) {
+    size_t c = 0;
+
+    while (classes[c] < bytes) {
+        c++;
+    }
+
+    return c;
+}
+
+void *vulpi_gc_alloc(size_t bytes, int raw) {
+    raw = raw != 0;
+    bytes = bytes ? bytes : sizeof(value);
+
+    if (allocated_bytes >= threshold) {
+        vulpi_gc_collect();
+    }
+
+    if (bytes > LARGE) {
+        size_t pieces = (bytes + CHUNK_SIZE - 1) / CHUNK_SIZE;
+        struct chunk *chunk = new_chunk(pieces * CHUNK_SIZE, pieces, raw);
+
+        chunk->allocated[0] = 1;
+        allocated_bytes += chunk->size;
+        memset(chunk->start, 0, chunk->size);
+
+        return chunk->start;
+    }
+
+    size_t c = class_of(bytes);
+    struct chunk *chunk = available[raw][c];
+
+    while (chunk != NULL && chunk->free == NULL) {
+        chunk = chunk->available;
+    }
+
+    if (chunk == NULL) {
+        chunk = new_chunk(classes[c], 1, raw);
+        sweep_chunk(chunk);
+        chunk->available = NULL;
+    }
+
+    available[raw][c] = chunk;
+
+    void **block = chunk->free;
+    chunk->free = *block;
+    chunk->allocated[((char *)block - chunk->start) / chunk->size] = 1;
+    allocated_bytes += chunk->size;
+    memset(block, 0, chunk->size);
+
+    return block;
+}
+
+value vulpi_alloc_constructor(value tag, value fields) {
+    value *block = vulpi_gc_alloc((fields + 1) * sizeof(value), 0);
+    block[0] = tag;
+    return (value)block;
+}
+
+value vulpi_alloc_closure(value code, value arity, value captures) {
+    value *block = vulpi_gc_alloc((captures + 2) * sizeof(value), 0);
+    block[0] = code;
+    block[1] = arity;
+    return (value)block;
+}
+
+value vulpi_alloc_string(value bytes) {
+    return (value)vulpi_gc_alloc(bytes, 1);
+}
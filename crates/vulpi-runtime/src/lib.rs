@@ -0,0 +1,57 @@
+//! The runtime that executables of the native backend are linked with. It's written in C and
+//! compiled by the C compiler of the system when linking, so this crate carries its sources
+//! and the names of the symbols that the compiled code and the runtime share.
+//!
+//! The heap is managed by a conservative mark and sweep collector. The compiled code allocates
+//! through [ALLOC_CONSTRUCTOR] and [ALLOC_CLOSURE], and it emits a table called [ROOTS] with the
+//! addresses of the cells of the global values, because the collector can't find them otherwise.
+//! The stack is scanned word by word, so the code doesn't need to describe its frames.
+//!
+//! The [Value] of the [value] module is the representation of the values of the interpreters that
+//! run in the compiler, like the virtual machine.
+
+pub mod value;
+
+pub use value::Value;
+
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+/// Allocates the block of a boxed constructor. Receives the tag and the number of fields and
+/// returns the block with the tag already stored.
+pub const ALLOC_CONSTRUCTOR: &str = "vulpi_alloc_constructor";
+
+/// Allocates the block of a closure. Receives the address of the code, the arity and the number
+/// of captured variables and returns the block with the code and the arity already stored.
+pub const ALLOC_CLOSURE: &str = "vulpi_alloc_closure";
+
+/// The table of the global cells: the number of them followed by the address of each one.
+pub const ROOTS: &str = "vulpi_roots";
+
+/// The files of the runtime, by their names.
+const SOURCES: &[(&str, &str)] = &[
+    ("vulpi.h", include_str!("vulpi.h")),
+    ("gc.c", include_str!("gc.c")),
+    ("runtime.c", include_str!("runtime.c")),
+];
+
+/// Writes the sources of the runtime to the directory and returns the paths of the ones that have
+/// to be compiled.
+pub fn write(directory: &Path) -> io::Result<Vec<PathBuf>> {
+    fs::create_dir_all(directory)?;
+
+    let mut compiled = Vec::new();
+
+    for (name, source) in SOURCES {
+        let path = directory.join(name);
+        fs::write(&path, source)?;
+
+        if name.ends_with(".c") {
+            compiled.push(path);
+        }
+    }
+
+    Ok(compiled)
+}
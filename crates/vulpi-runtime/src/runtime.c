@@ -0,0 +1,457 @@
+// The runtime of programs compiled by the native backend: application of closures and the
+// functions behind the primitives. The memory is managed by the collector in `gc.c`.
+
+#include <stdio.h>
+#include <stdlib.h>
+#include <string.h>
+#include <math.h>
+
+#include "vulpi.h"
+
+extern value vulpi_main(void);
+
+value vulpi_apply(value function, value argc, value *argv);
+
+// The code of partial applications. The environment has the function that was applied, the number
+// of arguments that it received and the arguments themselves.
+static value vulpi_partial(struct closure *self, value *args) {
+    struct closure *function = (struct closure *)self->env[0];
+    value given = self->env[1];
+    value total = given + self->arity;
+    value all[total];
+
+    memcpy(all, &self->env[2], given * sizeof(value));
+    memcpy(all + given, args, self->arity * sizeof(value));
+
+    return vulpi_apply((value)function, total, all);
+}
+
+value vulpi_apply(value function, value argc, value *argv) {
+    for (;;) {
+        struct closure *closure = (struct closure *)function;
+
+        if (argc == closure->arity) {
+            return closure->code(closure, argv);
+        }
+
+        if (argc < closure->arity) {
+            struct closure *partial = (struct closure *)vulpi_alloc_closure(
+                (value)vulpi_partial, closure->arity - argc, argc + 2);
+
+            partial->env[0] = function;
+            partial->env[1] = argc;
+            memcpy(&partial->env[2], argv, argc * sizeof(value));
+
+            return (value)partial;
+        }
+
+        function = closure->code(closure, argv);
+        argv += closure->arity;
+        argc -= closure->arity;
+    }
+}
+
+static void vulpi_fail(const char *message) {
+    fprintf(stderr, "[Error]: %s\n", message);
+    exit(1);
+}
+
+value vulpi_unknown_external(value name) {
+    fprintf(stderr, "[Error]: the external '%s' is not known by the native backend\n", (char *)name);
+    exit(1);
+}
+
+value vulpi_string_concat(value left, value right) {
+    size_t l = strlen((char *)left);
+    size_t r = strlen((char *)right);
+    char *result = (char *)vulpi_alloc_string(l + r + 1);
+
+    memcpy(result, (char *)left, l);
+    memcpy(result + l, (char *)right, r + 1);
+
+    return (value)result;
+}
+
+value vulpi_string_compare(value left, value right) {
+    return strcmp((char *)left, (char *)right);
+}
+
+// The checked and the saturating versions of the arithmetic. The wrapping ones are instructions.
+
+value vulpi_int_checked_add(value left, value right) {
+    value result;
+
+    if (__builtin_add_overflow(left, right, &result)) {
+        vulpi_fail("integer overflow");
+    }
+
+    return result;
+}
+
+value vulpi_int_checked_sub(value left, value right) {
+    value result;
+
+    if (__builtin_sub_overflow(left, right, &result)) {
+        vulpi_fail("integer overflow");
+    }
+
+    return result;
+}
+
+value vulpi_int_checked_mul(value left, value right) {
+    value result;
+
+    if (__builtin_mul_overflow(left, right, &result)) {
+        vulpi_fail("integer overflow");
+    }
+
+    return result;
+}
+
+value vulpi_int_saturating_add(value left, value right) {
+    value result;
+
+    if (__builtin_add_overflow(left, right, &result)) {
+        return right < 0 ? INT64_MIN : INT64_MAX;
+    }
+
+    return result;
+}
+
+value vulpi_int_saturating_sub(value left, value right) {
+    value result;
+
+    if (__builtin_sub_overflow(left, right, &result)) {
+        return right > 0 ? INT64_MIN : INT64_MAX;
+    }
+
+    return result;
+}
+
+value vulpi_int_saturating_mul(value left, value right) {
+    value result;
+
+    if (__builtin_mul_overflow(left, right, &result)) {
+        return (left < 0) != (right < 0) ? INT64_MIN : INT64_MAX;
+    }
+
+    return result;
+}
+
+// The float is kept in the bits of the word. Not a number is zero and the floats outside of the
+// range of the type are its minimum or its maximum.
+value vulpi_float_to_int(value bits, value min, value max) {
+    double x;
+    memcpy(&x, &bits, sizeof(double));
+
+    if (x != x) {
+        return 0;
+    }
+
+    if (x <= (double)min) {
+        return min;
+    }
+
+    if (x >= (double)max) {
+        return max;
+    }
+
+    return (value)x;
+}
+
+// Integers wrap around, so the minimum integer divided by minus one is itself instead of the trap
+// of the division instruction.
+
+value vulpi_int_div(value left, value right) {
+    if (right == 0) {
+        vulpi_fail("division by zero");
+    }
+
+    return right == -1 ? (value)(0 - (uint64_t)left) : left / right;
+}
+
+value vulpi_int_rem(value left, value right) {
+    if (right == 0) {
+        vulpi_fail("division by zero");
+    }
+
+    return right == -1 ? 0 : left % right;
+}
+
+// Strings are encoded in UTF-8. They're measured and indexed by code points, so the bytes that
+// continue a code point are skipped, or sliced by the offsets of bytes that start code points.
+
+static int vulpi_continuation(char byte) {
+    return (byte & 0xC0) == 0x80;
+}
+
+value vulpi_string_length(value string) {
+    value length = 0;
+
+    for (char *c = (char *)string; *c; c++) {
+        length += !vulpi_continuation(*c);
+    }
+
+    return length;
+}
+
+// The code point of the character that starts at the byte.
+static value vulpi_decode(unsigned char *c) {
+    if (*c < 0x80) {
+        return *c;
+    }
+
+    int extra = *c >= 0xF0 ? 3 : *c >= 0xE0 ? 2 : 1;
+    value code = *c & (0x3F >> extra);
+
+    for (int j = 1; j <= extra; j++) {
+        code = (code << 6) | (c[j] & 0x3F);
+    }
+
+    return code;
+}
+
+value vulpi_string_index(value string, value index) {
+    unsigned char *c = (unsigned char *)string;
+
+    for (value i = 0; *c && i <= index; c++) {
+        if (vulpi_continuation(*c)) {
+            continue;
+        }
+
+        if (i++ < index) {
+            continue;
+        }
+
+        return vulpi_decode(c);
+    }
+
+    char message[64];
+    snprintf(message, 64, "the index %lld is outside of the string", (long long)index);
+    vulpi_fail(message);
+    return 0;
+}
+
+value vulpi_string_byte_length(value string) {
+    return strlen((char *)string);
+}
+
+// Checks that the offset is the start of a character or the end of the string.
+static void vulpi_boundary(value string, value offset) {
+    char message[64];
+
+    if (offset < 0 || offset > (value)strlen((char *)string)) {
+        snprintf(message, 64, "the index %lld is outside of the string", (long long)offset);
+        vulpi_fail(message);
+    }
+
+    if (vulpi_continuation(((char *)string)[offset])) {
+        snprintf(message, 64, "the offset %lld is inside of a character", (long long)offset);
+        vulpi_fail(message);
+    }
+}
+
+value vulpi_string_slice(value string, value start, value end) {
+    vulpi_boundary(string, start);
+    vulpi_boundary(string, end);
+
+    if (end < start) {
+        char message[64];
+        snprintf(message, 64, "the index %lld is outside of the string", (long long)end);
+        vulpi_fail(message);
+    }
+
+    char *result = (char *)vulpi_alloc_string(end - start + 1);
+    memcpy(result, (char *)string + start, end - start);
+    result[end - start] = 0;
+
+    return (value)result;
+}
+
+value vulpi_string_char_at(value string, value offset) {
+    vulpi_boundary(string, offset);
+
+    if (((char *)string)[offset] == 0) {
+        char message[64];
+        snprintf(message, 64, "the index %lld is outside of the string", (long long)offset);
+        vulpi_fail(message);
+    }
+
+    return vulpi_decode((unsigned char *)string + offset);
+}
+
+value vulpi_char_width(value code) {
+    return code < 0x80 ? 1 : code < 0x800 ? 2 : code < 0x10000 ? 3 : 4;
+}
+
+value vulpi_int_to_char(value code) {
+    if (code < 0 || code > 0x10FFFF || (code >= 0xD800 && code <= 0xDFFF)) {
+        char message[64];
+        snprintf(message, 64, "%lld is not the code point of a character", (long long)code);
+        vulpi_fail(message);
+    }
+
+    return code;
+}
+
+// An array is a block with a tag, its length, its capacity and the address of the block of its
+// elements, that is replaced by a block with twice the capacity when it's full.
+
+struct array {
+    value tag;
+    value length;
+    value capacity;
+    value *elements;
+};
+
+value vulpi_array_make(value length, value x) {
+    if (length < 0) {
+        char message[64];
+        snprintf(message, 64, "the length %lld of an array is negative", (long long)length);
+        vulpi_fail(message);
+    }
+
+    struct array *array = vulpi_gc_alloc(sizeof(struct array), 0);
+    array->length = length;
+    array->capacity = length;
+    array->elements = length ? vulpi_gc_alloc(length * sizeof(value), 0) : NULL;
+
+    for (value i = 0; i < length; i++) {
+        array->elements[i] = x;
+    }
+
+    return (value)array;
+}
+
+static void vulpi_element(struct array *array, value index) {
+    if (index < 0 || index >= array->length) {
+        char message[96];
+        snprintf(message, 96, "the index %lld is outside of an array of length %lld",
+                 (long long)index, (long long)array->length);
+        vulpi_fail(message);
+    }
+}
+
+value vulpi_array_get(value array, value index) {
+    vulpi_element((struct array *)array, index);
+    return ((struct array *)array)->elements[index];
+}
+
+value vulpi_array_set(value array, value index, value x) {
+    vulpi_element((struct array *)array, index);
+    ((struct array *)array)->elements[index] = x;
+    return 0;
+}
+
+value vulpi_array_push(value block, value x) {
+    struct array *array = (struct array *)block;
+
+    if (array->length == array->capacity) {
+        value capacity = array->capacity ? array->capacity * 2 : 4;
+        value *elements = vulpi_gc_alloc(capacity * sizeof(value), 0);
+
+        if (array->length) {
+            memcpy(elements, array->elements, array->length * sizeof(value));
+        }
+
+        array->elements = elements;
+        array->capacity = capacity;
+    }
+
+    array->elements[array->length++] = x;
+    return 0;
+}
+
+value vulpi_float_rem(value left, value right) {
+    double l, r, result;
+
+    memcpy(&l, &left, sizeof(double));
+    memcpy(&r, &right, sizeof(double));
+    result = fmod(l, r);
+    memcpy(&left, &result, sizeof(double));
+
+    return left;
+}
+
+value vulpi_int_to_string(value x) {
+    char *result = (char *)vulpi_alloc_string(21);
+    snprintf(result, 21, "%lld", (long long)x);
+    return (value)result;
+}
+
+value vulpi_float_to_string(value x) {
+    double f;
+    char *result = (char *)vulpi_alloc_string(32);
+
+    memcpy(&f, &x, sizeof(double));
+    snprintf(result, 32, "%g", f);
+
+    return (value)result;
+}
+
+value vulpi_char_to_string(value x) {
+    char *result = (char *)vulpi_alloc_string(5);
+    uint32_t c = (uint32_t)x;
+
+    if (c < 0x80) {
+        result[0] = c;
+        result[1] = 0;
+    } else if (c < 0x800) {
+        result[0] = 0xC0 | (c >> 6);
+        result[1] = 0x80 | (c & 0x3F);
+        result[2] = 0;
+    } else if (c < 0x10000) {
+        result[0] = 0xE0 | (c >> 12);
+        result[1] = 0x80 | ((c >> 6) & 0x3F);
+        result[2] = 0x80 | (c & 0x3F);
+        result[3] = 0;
+    } else {
+        result[0] = 0xF0 | (c >> 18);
+        result[1] = 0x80 | ((c >> 12) & 0x3F);
+        result[2] = 0x80 | ((c >> 6) & 0x3F);
+        result[3] = 0x80 | (c & 0x3F);
+        result[4] = 0;
+    }
+
+    return (value)result;
+}
+
+// The printing functions receive the stream as the last argument, 1 for the standard output and 2
+// for the standard error. Floats are printed with `%g`, everything else looks like the output of
+// the virtual machine.
+
+static FILE *vulpi_stream(value stream) {
+    return stream == 2 ? stderr : stdout;
+}
+
+value vulpi_print_string(value x, value stream) {
+    fprintf(vulpi_stream(stream), "%s\n", (char *)x);
+    return 0;
+}
+
+value vulpi_print_int(value x, value stream) {
+    fprintf(vulpi_stream(stream), "%lld\n", (long long)x);
+    return 0;
+}
+
+value vulpi_print_float(value x, value stream) {
+    return vulpi_print_string(vulpi_float_to_string(x), stream);
+}
+
+value vulpi_print_char(value x, value stream) {
+    fprintf(vulpi_stream(stream), "'%s'\n", (char *)vulpi_char_to_string(x));
+    return 0;
+}
+
+value vulpi_print_unit(value x, value stream) {
+    (void)x;
+    fprintf(vulpi_stream(stream), "()\n");
+    return 0;
+}
+
+int main(void) {
+    value bottom = 0;
+    vulpi_gc_init(&bottom);
+    vulpi_main();
+    return 0;
+}
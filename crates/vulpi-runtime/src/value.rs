@@ -0,0 +1,254 @@
+//! The representation of the values of a running program that is shared by the virtual machine,
+//! the embedding API and the host functions, so a value goes from one of them to the others as it
+//! is.
+//!
+//! The enum is `repr(u8)`: its first byte is the tag of the variant and the payload follows it.
+//! The tags are part of the representation and are never reused:
+//!
+//! | Tag | Variant              | Payload                                             |
+//! |-----|----------------------|-----------------------------------------------------|
+//! | 0   | [Value::Int]         | a 64 bit integer                                    |
+//! | 1   | [Value::Float]       | a 64 bit float                                      |
+//! | 2   | [Value::String]      | a shared string                                     |
+//! | 3   | [Value::Char]        | a Unicode scalar value                              |
+//! | 4   | [Value::Unit]        | nothing                                             |
+//! | 5   | [Value::Constructor] | the number of the constructor and its fields        |
+//! | 6   | [Value::Closure]     | a shared [Closure]                                  |
+//! | 7   | [Value::Handle]      | a shared object of the runtime, like a continuation |
+//!
+//! Constructors are numbered by the module that declares them, and constructors without fields
+//! don't allocate anything.
+
+use std::{any::Any, cell::RefCell, ops::Deref, rc::Rc};
+
+use vulpi_intern::Symbol;
+
+/// The code that a closure runs when it has all of its arguments.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Code {
+    /// A function of the bytecode, by its index in the module.
+    Function(u32),
+
+    /// A primitive operation, by the index of the global of the module that binds it.
+    Primitive(u32),
+
+    /// A host function, by the binding of the external that calls it.
+    Host(Symbol),
+}
+
+#[derive(Clone)]
+pub struct Closure {
+    pub code: Code,
+    pub captures: Rc<[Value]>,
+
+    /// Arguments of a partial application that are waiting for the rest of them.
+    pub args: Vec<Value>,
+}
+
+impl Closure {
+    /// A closure without captures and without arguments.
+    pub fn new(code: Code) -> Self {
+        Self {
+            code,
+            captures: Rc::new([]),
+            args: vec![],
+        }
+    }
+}
+
+/// The fields of a constructor. A constructor without fields doesn't allocate them.
+#[derive(Clone, Default)]
+pub struct Fields(Option<Rc<[Value]>>);
+
+impl Fields {
+    pub fn empty() -> Self {
+        Self(None)
+    }
+}
+
+impl From<Vec<Value>> for Fields {
+    fn from(fields: Vec<Value>) -> Self {
+        if fields.is_empty() {
+            Self(None)
+        } else {
+            Self(Some(fields.into()))
+        }
+    }
+}
+
+impl Deref for Fields {
+    type Target = [Value];
+
+    fn deref(&self) -> &[Value] {
+        self.0.as_deref().unwrap_or(&[])
+    }
+}
+
+/// A shared object of the runtime that programs only pass around, like continuations, cells of the
+/// `Ref` effect, arrays and instances of handlers. Its owner finds out what it is by downcasting
+/// it.
+#[derive(Clone)]
+pub struct Handle(Rc<dyn Any>);
+
+impl Handle {
+    pub fn new<T: Any>(object: T) -> Self {
+        Self(Rc::new(object))
+    }
+
+    pub fn downcast_ref<T: Any>(&self) -> Option<&T> {
+        self.0.downcast_ref()
+    }
+
+    /// Checks if both handles are the same object.
+    pub fn ptr_eq(&self, other: &Handle) -> bool {
+        std::ptr::addr_eq(Rc::as_ptr(&self.0), Rc::as_ptr(&other.0))
+    }
+}
+
+impl<T: Any> From<Rc<T>> for Handle {
+    fn from(object: Rc<T>) -> Self {
+        Self(object)
+    }
+}
+
+#[derive(Clone)]
+#[repr(u8)]
+pub enum Value {
+    Int(i64) = 0,
+    Float(f64) = 1,
+    String(Rc<str>) = 2,
+    Char(char) = 3,
+    Unit = 4,
+
+    /// A constructor with its number in the module and its fields.
+    Constructor {
+        tag: u32,
+        fields: Fields,
+    } = 5,
+
+    /// A function, a primitive or a host function with the arguments that it received.
+    Closure(Rc<Closure>) = 6,
+    Handle(Handle) = 7,
+}
+
+impl Value {
+    /// The tag of the variant, as described in the [module](self) documentation.
+    pub fn kind(&self) -> u8 {
+        match self {
+            Value::Int(_) => 0,
+            Value::Float(_) => 1,
+            Value::String(_) => 2,
+            Value::Char(_) => 3,
+            Value::Unit => 4,
+            Value::Constructor { .. } => 5,
+            Value::Closure(_) => 6,
+            Value::Handle(_) => 7,
+        }
+    }
+
+    pub fn constructor(tag: u32, fields: Vec<Value>) -> Value {
+        Value::Constructor {
+            tag,
+            fields: fields.into(),
+        }
+    }
+
+    /// A new mutable cell with the value.
+    pub fn cell(value: Value) -> Value {
+        Value::Handle(Handle::new(RefCell::new(value)))
+    }
+
+    /// The mutable cell of the value, if it's one.
+    pub fn as_cell(&self) -> Option<&RefCell<Value>> {
+        match self {
+            Value::Handle(handle) => handle.downcast_ref(),
+            _ => None,
+        }
+    }
+
+    /// A new array with the elements.
+    pub fn array(elements: Vec<Value>) -> Value {
+        Value::Handle(Handle::new(RefCell::new(elements)))
+    }
+
+    /// The elements of the array of the value, if it's one.
+    pub fn as_array(&self) -> Option<&RefCell<Vec<Value>>> {
+        match self {
+            Value::Handle(handle) => handle.downcast_ref(),
+            _ => None,
+        }
+    }
+}
+
+/// Takes the fields out of a constructor that is not shared, so they can be dropped later.
+fn unshare(value: &mut Value, fields: &mut Vec<Value>) {
+    if let Value::Constructor {
+        fields: Fields(Some(data)),
+        ..
+    } = value
+    {
+        if let Some(data) = Rc::get_mut(data) {
+            for field in data.iter_mut() {
+                if let Value::Constructor { .. } = field {
+                    fields.push(std::mem::replace(field, Value::Unit));
+                }
+            }
+        }
+    }
+}
+
+/// Constructors are dropped with a stack instead of recursion, so dropping a long list does not
+/// overflow the stack of the machine.
+impl Drop for Value {
+    fn drop(&mut self) {
+        let mut fields = Vec::new();
+        unshare(self, &mut fields);
+
+        while let Some(mut field) = fields.pop() {
+            unshare(&mut field, &mut fields);
+        }
+    }
+}
+
+/// Structural equality of the data. Closures are never equal and handles are equal when they're
+/// the same object.
+impl PartialEq for Value {
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (Value::Int(l), Value::Int(r)) => l == r,
+            (Value::Float(l), Value::Float(r)) => l == r,
+            (Value::Char(l), Value::Char(r)) => l == r,
+            (Value::String(l), Value::String(r)) => l == r,
+            (Value::Unit, Value::Unit) => true,
+            (
+                Value::Constructor {
+                    tag: l,
+                    fields: l_fields,
+                },
+                Value::Constructor {
+                    tag: r,
+                    fields: r_fields,
+                },
+            ) => l == r && **l_fields == **r_fields,
+            (Value::Handle(l), Value::Handle(r)) => l.ptr_eq(r),
+            _ => false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn long_lists_drop_without_recursion() {
+        let mut list = Value::constructor(0, vec![]);
+
+        for i in 0..1_000_000 {
+            list = Value::constructor(1, vec![Value::Int(i), list]);
+        }
+
+        assert_eq!(list.kind(), 5);
+        drop(list);
+    }
+}
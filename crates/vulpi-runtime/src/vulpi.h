@@ -0,0 +1,32 @@
+// Declarations shared by the files of the runtime. Every value is a 64 bit word and the layout of
+// the blocks in the heap is described in `codegen.rs` of the native backend.
+
+#ifndef VULPI_H
+#define VULPI_H
+
+#include <stdint.h>
+#include <stddef.h>
+
+typedef int64_t value;
+
+struct closure {
+    value (*code)(struct closure *self, value *args);
+    value arity;
+    value env[];
+};
+
+// Starts the collector. The stack is scanned from the current position to the given address,
+// which must be in the frame of the function that calls the compiled program.
+void vulpi_gc_init(void *bottom);
+
+// Allocates a zeroed block in the heap. The words of the blocks that are not raw are scanned by the
+// collector, so raw blocks are used for the data that cannot have pointers, like strings.
+void *vulpi_gc_alloc(size_t bytes, int raw);
+
+void vulpi_gc_collect(void);
+
+value vulpi_alloc_constructor(value tag, value fields);
+value vulpi_alloc_closure(value code, value arity, value captures);
+value vulpi_alloc_string(value bytes);
+
+#endif
@@ -1,5 +1,5 @@
 use std::{
-    collections::{HashMap, HashSet},
+    collections::{BTreeMap, HashMap, HashSet},
     fmt::Display,
     ops::Range,
 };
@@ -37,6 +37,73 @@ impl TreeDisplay {
         self.children.push(child);
         self
     }
+
+    /// The tree as JSON, with a `label` and the `children` of each node, so two trees can be
+    /// compared by tools that diff JSON.
+    pub fn to_json(&self) -> String {
+        let mut json = String::new();
+        self.write_json(&mut json);
+        json
+    }
+
+    fn write_json(&self, json: &mut String) {
+        json.push_str("{\"label\":");
+        json.push_str(&quote(&self.label));
+        json.push_str(",\"children\":[");
+
+        for (index, child) in self.children.iter().enumerate() {
+            if index != 0 {
+                json.push(',');
+            }
+
+            child.write_json(json);
+        }
+
+        json.push_str("]}");
+    }
+
+    /// The tree as a graph of Graphviz. Each node is numbered in the order that it's visited.
+    pub fn to_dot(&self) -> String {
+        let mut dot = String::from("digraph {\n    node [shape=box];\n");
+        self.write_dot(&mut dot, &mut 0);
+        dot.push_str("}\n");
+        dot
+    }
+
+    fn write_dot(&self, dot: &mut String, counter: &mut usize) -> usize {
+        let id = *counter;
+        *counter += 1;
+
+        dot.push_str(&format!("    n{} [label={}];\n", id, quote(&self.label)));
+
+        for child in &self.children {
+            let child = child.write_dot(dot, counter);
+            dot.push_str(&format!("    n{} -> n{};\n", id, child));
+        }
+
+        id
+    }
+}
+
+/// A string between double quotes, with the characters that JSON and Graphviz don't accept inside
+/// of them escaped.
+fn quote(string: &str) -> String {
+    let mut quoted = String::from("\"");
+
+    for char in string.chars() {
+        match char {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            '\n' => quoted.push_str("\\n"),
+            '\r' => quoted.push_str("\\r"),
+            '\t' => quoted.push_str("\\t"),
+            char if char.is_control() => quoted.push_str(&format!("\\u{:04x}", char as u32)),
+            char => quoted.push(char),
+        }
+    }
+
+    quoted.push('"');
+    quoted
 }
 
 impl Display for TreeDisplay {
@@ -103,6 +170,20 @@ impl<T: Show, U: Show> Show for HashMap<T, U> {
     }
 }
 
+impl<T: Show, U: Show> Show for BTreeMap<T, U> {
+    fn show(&self) -> TreeDisplay {
+        let mut node = TreeDisplay::label("BTreeMap");
+        for (key, value) in self {
+            node = node.with(
+                TreeDisplay::label("Entry")
+                    .with(key.show())
+                    .with(value.show()),
+            );
+        }
+        node
+    }
+}
+
 impl<T: Show> Show for HashSet<T> {
     fn show(&self) -> TreeDisplay {
         let mut node = TreeDisplay::label("HashSet");
@@ -132,6 +213,17 @@ impl<T: Show, U: Show, V: Show> Show for (T, U, V) {
     }
 }
 
+impl<T: Show, U: Show, V: Show, W: Show> Show for (T, U, V, W) {
+    fn show(&self) -> TreeDisplay {
+        let mut node = TreeDisplay::label("Tuple");
+        node = node.with(self.0.show());
+        node = node.with(self.1.show());
+        node = node.with(self.2.show());
+        node = node.with(self.3.show());
+        node
+    }
+}
+
 impl<T: std::fmt::Debug> Show for Range<T> {
     fn show(&self) -> TreeDisplay {
         TreeDisplay::label(&format!("Range({:?}..{:?})", self.start, self.end))
@@ -164,4 +256,13 @@ mod tests {
             .with(TreeDisplay::label("child2").with(TreeDisplay::label("child3")));
         println!("{}", node);
     }
+
+    #[test]
+    fn json() {
+        let node = TreeDisplay::label("root").with(TreeDisplay::label("a \"b\"\n"));
+        assert_eq!(
+            node.to_json(),
+            r#"{"label":"root","children":[{"label":"a \"b\"\n","children":[]}]}"#
+        );
+    }
 }
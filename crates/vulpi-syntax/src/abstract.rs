@@ -1,13 +1,16 @@
-use std::collections::{HashMap, HashSet};
+use std::collections::{BTreeMap, HashSet};
 
 use vulpi_intern::Symbol;
 use vulpi_location::{Span, Spanned};
-use vulpi_macros::Show;
+use vulpi_macros::{Fold, Show, Visit, VisitMut};
 
+use serde::{Deserialize, Serialize};
 use vulpi_show::{Show, TreeDisplay};
 
+use crate::visit::{Visit, Visitor};
 
-#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
 pub struct Qualified {
     pub path: Symbol,
     pub name: Symbol,
@@ -33,7 +36,7 @@ impl Show for Qualified {
     }
 }
 
-#[derive(Show)]
+#[derive(Show, Clone, Visit, VisitMut, Fold, Serialize, Deserialize)]
 pub enum KindType {
     Star,
     Constraint,
@@ -45,19 +48,19 @@ pub type Kind = Box<Spanned<KindType>>;
 
 // Types
 
-#[derive(Show)]
+#[derive(Show, Clone, Visit, VisitMut, Fold, Serialize, Deserialize)]
 pub struct PiType {
     pub left: Type,
     pub right: Type,
 }
 
-#[derive(Show)]
+#[derive(Show, Clone, Visit, VisitMut, Fold, Serialize, Deserialize)]
 pub struct TypeApplication {
     pub func: Type,
     pub args: Vec<Type>,
 }
 
-#[derive(Show)]
+#[derive(Show, Clone, Visit, VisitMut, Fold, Serialize, Deserialize)]
 pub enum TypeBinder {
     Implicit(Symbol),
     Explicit(Symbol, Kind),
@@ -72,13 +75,13 @@ impl TypeBinder {
     }
 }
 
-#[derive(Show)]
+#[derive(Show, Clone, Visit, VisitMut, Fold, Serialize, Deserialize)]
 pub struct TypeForall {
     pub params: Vec<TypeBinder>,
     pub body: Type,
 }
 
-#[derive(Show)]
+#[derive(Show, Clone, Visit, VisitMut, Fold, Serialize, Deserialize)]
 pub enum TypeKind {
     Arrow(PiType),
     Tuple(Vec<Type>),
@@ -95,58 +98,45 @@ pub type Type = Box<Spanned<TypeKind>>;
 
 impl TypeKind {
     pub fn free_variables(&self) -> HashSet<Symbol> {
-        match self {
-            TypeKind::Arrow(pi) => {
-                let mut set = pi.left.data.free_variables();
-                set.extend(pi.right.data.free_variables());
-
-                set
-            }
-            TypeKind::Tuple(t) => {
-                let mut set = HashSet::new();
-
-                for typ in t {
-                    set.extend(typ.data.free_variables());
+        #[derive(Default)]
+        struct Free(HashSet<Symbol>);
+
+        impl Free {
+            fn kind(&mut self, kind: &TypeKind) {
+                match kind {
+                    TypeKind::Forall(f) => {
+                        let mut body = Free::default();
+                        body.visit_type(&f.body);
+
+                        for binder in &f.params {
+                            body.0.remove(binder.name());
+                        }
+
+                        self.0.extend(body.0);
+                    }
+                    TypeKind::TypeVariable(v) => {
+                        self.0.insert(v.clone());
+                    }
+                    _ => kind.walk(self),
                 }
-
-                set
             }
-            TypeKind::Application(app) => {
-                let mut set = app.func.data.free_variables();
-
-                for arg in &app.args {
-                    set.extend(arg.data.free_variables());
-                }
-
-                set
-            }
-            TypeKind::Forall(f) => {
-                let mut set = HashSet::new();
-
-                set.extend(f.body.data.free_variables());
-
-                for binder in &f.params {
-                    match binder {
-                        TypeBinder::Implicit(p) => set.remove(p),
-                        TypeBinder::Explicit(p, _) => set.remove(p),
-                    };
-                }
+        }
 
-                set
-            }
-            TypeKind::TypeVariable(v) => {
-                let mut set = HashSet::new();
-                set.insert(v.clone());
-                set
+        impl Visitor for Free {
+            fn visit_type(&mut self, typ: &Type) {
+                self.kind(&typ.data)
             }
-            _ => HashSet::new(),
         }
+
+        let mut free = Free::default();
+        free.kind(self);
+        free.0
     }
 }
 
 // Literal
 
-#[derive(Show)]
+#[derive(Show, Clone, Visit, VisitMut, Fold, Serialize, Deserialize)]
 pub enum LiteralKind {
     String(Symbol),
     Integer(Symbol),
@@ -159,13 +149,13 @@ pub type Literal = Box<Spanned<LiteralKind>>;
 
 // Statements
 
-#[derive(Show)]
+#[derive(Show, Clone, Visit, VisitMut, Fold, Serialize, Deserialize)]
 pub struct LetSttm {
     pub pat: Pattern,
     pub expr: Expr,
 }
 
-#[derive(Show)]
+#[derive(Show, Clone, Visit, VisitMut, Fold, Serialize, Deserialize)]
 pub enum SttmKind {
     Let(LetSttm),
     Expr(Expr),
@@ -174,115 +164,199 @@ pub enum SttmKind {
 
 pub type Sttm = Spanned<SttmKind>;
 
-#[derive(Show)]
+#[derive(Show, Clone, Visit, VisitMut, Fold, Serialize, Deserialize)]
 pub struct Block {
     pub sttms: Vec<Sttm>,
 }
 
 // Patterns
 
-#[derive(Show)]
+#[derive(Show, Clone, Visit, VisitMut, Fold, Serialize, Deserialize)]
 pub struct PatAscription {
     pub pat: Pattern,
     pub typ: Type,
 }
 
-#[derive(Show)]
+#[derive(Show, Clone, Visit, VisitMut, Fold, Serialize, Deserialize)]
+pub struct PatRange {
+    pub start: Literal,
+    pub end: Literal,
+}
+
+#[derive(Show, Clone, Visit, VisitMut, Fold, Serialize, Deserialize)]
+pub struct PatAs {
+    pub name: Symbol,
+    pub pattern: Pattern,
+}
+
+#[derive(Show, Clone, Visit, VisitMut, Fold, Serialize, Deserialize)]
 pub struct PatOr {
     pub left: Pattern,
     pub right: Pattern,
 }
 
-#[derive(Show)]
+#[derive(Show, Clone, Visit, VisitMut, Fold, Serialize, Deserialize)]
 pub struct PatApplication {
     pub func: Qualified,
     pub args: Vec<Pattern>,
 }
 
-#[derive(Show)]
+#[derive(Show, Clone, Visit, VisitMut, Fold, Serialize, Deserialize)]
+pub struct PatEffect {
+    pub func: Qualified,
+    pub args: Vec<Pattern>,
+    pub cont: Option<Symbol>,
+}
+
+#[derive(Show, Clone, Visit, VisitMut, Fold, Serialize, Deserialize)]
 pub enum PatternKind {
     Wildcard,
     Variable(Symbol),
     Literal(Literal),
+    Range(PatRange),
     Tuple(Vec<Pattern>),
     Ascription(PatAscription),
     Or(PatOr),
+    As(PatAs),
     Application(PatApplication),
+    Effect(PatEffect),
+
+    /// The pattern of the final value of the computation in a handler.
+    Return(Pattern),
 
     Error,
 }
 
 pub type Pattern = Box<Spanned<PatternKind>>;
 
-#[derive(Show)]
+#[derive(Show, Clone, Visit, VisitMut, Fold, Serialize, Deserialize)]
 pub struct LambdaExpr {
     pub param: Pattern,
     pub body: Expr,
 }
 
-#[derive(Show)]
+#[derive(Show, Clone, Visit, VisitMut, Fold, Serialize, Deserialize)]
 pub enum AppKind {
     Infix,
     Normal,
 }
 
-#[derive(Show)]
+#[derive(Show, Clone, Visit, VisitMut, Fold, Serialize, Deserialize)]
 pub struct ApplicationExpr {
     pub app: AppKind,
     pub func: Expr,
     pub args: Vec<Expr>,
 }
 
-#[derive(Show)]
+#[derive(Show, Clone, Visit, VisitMut, Fold, Serialize, Deserialize)]
 pub struct ProjectionExpr {
     pub expr: Expr,
     pub field: Symbol,
+
+    /// The records in scope that have a field with the name. The type of the expression picks
+    /// one of them, and it's only needed to be unique when the type is not known.
+    pub candidates: Vec<Qualified>,
 }
 
-#[derive(Show)]
+#[derive(Show, Clone, Visit, VisitMut, Fold, Serialize, Deserialize)]
 pub struct PatternArm {
     pub patterns: Vec<Pattern>,
     pub expr: Expr,
     pub guard: Option<Expr>,
 }
 
-#[derive(Show)]
+#[derive(Show, Clone, Visit, VisitMut, Fold, Serialize, Deserialize)]
 pub struct WhenExpr {
     pub scrutinee: Vec<Expr>,
     pub arms: Vec<PatternArm>,
 }
 
-#[derive(Show)]
+#[derive(Show, Clone, Visit, VisitMut, Fold, Serialize, Deserialize)]
+pub struct CasesExpr {
+    pub arms: Vec<PatternArm>,
+}
+
+#[derive(Show, Clone, Visit, VisitMut, Fold, Serialize, Deserialize)]
+pub struct HandlerExpr {
+    pub name: Option<Symbol>,
+    pub expr: Expr,
+    pub handler: Expr,
+
+    /// The expression after `finally`, that runs when the handled expression is done, even if an
+    /// operation aborts it.
+    pub finally: Option<Expr>,
+}
+
+/// Operation performed on a named handler like `h.get`. The operation is found by the type checker
+/// using the effects that the handler handles.
+#[derive(Show, Clone, Visit, VisitMut, Fold, Serialize, Deserialize)]
+pub struct OperationExpr {
+    pub handler: Symbol,
+    pub name: Symbol,
+}
+
+/// Call to an operation of an effect that is written with `perform`, like `perform Log.log x`. It
+/// behaves like the application of the operation, but the type checker knows that the name must
+/// be an operation.
+#[derive(Show, Clone, Visit, VisitMut, Fold, Serialize, Deserialize)]
+pub struct PerformExpr {
+    pub operation: Qualified,
+    pub args: Vec<Expr>,
+}
+
+/// An expression whose operations of the effect skip the closest handler of it, so they go to the
+/// one outside of it.
+#[derive(Show, Clone, Visit, VisitMut, Fold, Serialize, Deserialize)]
+pub struct MaskExpr {
+    pub effect: Qualified,
+    pub expr: Expr,
+}
+
+#[derive(Show, Clone, Visit, VisitMut, Fold, Serialize, Deserialize)]
 pub struct AnnotationExpr {
     pub expr: Expr,
     pub typ: Type,
 }
 
-#[derive(Show)]
+#[derive(Show, Clone, Visit, VisitMut, Fold, Serialize, Deserialize)]
 pub struct LetExpr {
     pub pattern: Pattern,
     pub body: Expr,
     pub value: Expr,
 }
 
-#[derive(Show)]
+#[derive(Show, Clone, Visit, VisitMut, Fold, Serialize, Deserialize)]
+pub struct LetBinding {
+    pub name: Symbol,
+    pub body: Expr,
+}
+
+/// A group of bindings like `let f x = .. and g y = .. in value`. All the names of the group are
+/// in scope in each binding, so they can call each other.
+#[derive(Show, Clone, Visit, VisitMut, Fold, Serialize, Deserialize)]
+pub struct LetGroupExpr {
+    pub bindings: Vec<LetBinding>,
+    pub value: Expr,
+}
+
+#[derive(Show, Clone, Visit, VisitMut, Fold, Serialize, Deserialize)]
 pub struct RecordInstance {
     pub name: Qualified,
     pub fields: Vec<(Span, Symbol, Expr)>,
 }
 
-#[derive(Show)]
+#[derive(Show, Clone, Visit, VisitMut, Fold, Serialize, Deserialize)]
 pub struct RecordUpdate {
     pub expr: Expr,
     pub fields: Vec<(Span, Symbol, Expr)>,
 }
 
-#[derive(Show)]
+#[derive(Show, Clone, Visit, VisitMut, Fold, Serialize, Deserialize)]
 pub struct Tuple {
     pub exprs: Vec<Expr>,
 }
 
-#[derive(Show)]
+#[derive(Show, Clone, Visit, VisitMut, Fold, Serialize, Deserialize)]
 pub enum ExprKind {
     Lambda(LambdaExpr),
     Application(ApplicationExpr),
@@ -293,7 +367,13 @@ pub enum ExprKind {
 
     Projection(ProjectionExpr),
     Let(LetExpr),
+    LetGroup(LetGroupExpr),
     When(WhenExpr),
+    Cases(CasesExpr),
+    Handler(HandlerExpr),
+    Operation(OperationExpr),
+    Perform(PerformExpr),
+    Mask(MaskExpr),
     Do(Block),
     Literal(Literal),
 
@@ -328,7 +408,7 @@ impl ExprKind {
 
 pub type Expr = Box<Spanned<ExprKind>>;
 
-#[derive(Show, Clone, PartialEq, Eq)]
+#[derive(Show, Clone, Visit, VisitMut, Fold, PartialEq, Eq, Serialize, Deserialize)]
 pub enum Visibility {
     Public,
     Super,
@@ -345,13 +425,13 @@ impl From<crate::concrete::top_level::Visibility> for Visibility {
     }
 }
 
-#[derive(Show)]
+#[derive(Show, Clone, Visit, VisitMut, Fold, Serialize, Deserialize)]
 pub struct Binder {
     pub pat: Pattern,
     pub typ: Type,
 }
 
-#[derive(Show)]
+#[derive(Show, Clone, Visit, VisitMut, Fold, Serialize, Deserialize)]
 pub enum LetBinder {
     Param(Binder),
     Trait(Type),
@@ -366,7 +446,7 @@ impl LetBinder {
     }
 }
 
-#[derive(Show)]
+#[derive(Show, Clone, Visit, VisitMut, Fold, Serialize, Deserialize)]
 pub struct LetSignature {
     pub span: Span,
     pub visibility: Visibility,
@@ -375,7 +455,35 @@ pub struct LetSignature {
     pub ret: Option<Type>,
 }
 
-#[derive(Show)]
+/// A declaration written with `#[deprecated]`, with the message that says what to use instead.
+#[derive(Show, Clone, Visit, VisitMut, Fold, Serialize, Deserialize)]
+pub struct Deprecation {
+    pub message: Option<Symbol>,
+}
+
+/// The attributes of a declaration. The lints allowed by the modules around it are allowed in it
+/// too.
+#[derive(Show, Clone, Visit, VisitMut, Fold, Default, Serialize, Deserialize)]
+pub struct Attributes {
+    /// The lints whose warnings are not reported inside of the declaration.
+    pub allow: Vec<Symbol>,
+    pub deprecated: Option<Deprecation>,
+
+    /// Hides the constructors and the fields of a type from the modules other than its own.
+    pub opaque: bool,
+
+    /// Rejects the calls to the externals of the `IO` effect in a let declaration. An external
+    /// with it doesn't talk with the outside world, so it's not in that effect.
+    pub pure: bool,
+}
+
+impl Attributes {
+    pub fn allows(&self, lint: &str) -> bool {
+        self.allow.iter().any(|x| x.get() == lint)
+    }
+}
+
+#[derive(Show, Clone, Visit, VisitMut, Fold, Serialize, Deserialize)]
 pub struct TraitDecl {
     pub name: Qualified,
     pub supers: Vec<Type>,
@@ -383,40 +491,78 @@ pub struct TraitDecl {
     pub binders: Vec<TypeBinder>,
     pub body: Vec<LetSignature>,
     pub span: Span,
+    pub attributes: Attributes,
 }
 
-#[derive(Show)]
+#[derive(Show, Clone, Visit, VisitMut, Fold, Serialize, Deserialize)]
 pub struct TraitImpl {
     pub name: Qualified,
     pub binders: Vec<Type>,
     pub body: Vec<LetDecl>,
 }
 
-#[derive(Show)]
+/// Serializes a map whose keys are not strings as a list of pairs, because formats like JSON only
+/// have maps with string keys.
+mod pairs {
+    use std::collections::BTreeMap;
+
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+    type Map<K, V> = Option<BTreeMap<K, V>>;
+
+    pub fn serialize<K, V, S>(map: &Map<K, V>, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        K: Serialize,
+        V: Serialize,
+        S: Serializer,
+    {
+        let pairs = map.as_ref().map(|map| map.iter().collect::<Vec<_>>());
+        pairs.serialize(serializer)
+    }
+
+    pub fn deserialize<'de, K, V, D>(deserializer: D) -> Result<Map<K, V>, D::Error>
+    where
+        K: Deserialize<'de> + Ord,
+        V: Deserialize<'de>,
+        D: Deserializer<'de>,
+    {
+        let pairs = Option::<Vec<(K, V)>>::deserialize(deserializer)?;
+        Ok(pairs.map(|pairs| pairs.into_iter().collect()))
+    }
+}
+
+#[derive(Show, Clone, Visit, VisitMut, Fold, Serialize, Deserialize)]
 pub struct LetDecl {
     pub signature: LetSignature,
     pub body: Vec<PatternArm>,
-    pub constant: Option<HashMap<Qualified, Span>>,
+    #[serde(with = "pairs")]
+    pub constant: Option<BTreeMap<Qualified, Span>>,
+
+    /// The span of the whole declaration, from its visibility to the end of its body.
+    pub declaration: Span,
+
+    pub attributes: Attributes,
 }
 
-#[derive(Show)]
+#[derive(Show, Clone, Visit, VisitMut, Fold, Serialize, Deserialize)]
 pub struct Constructor {
     pub name: Qualified,
     pub args: Vec<Type>,
     pub typ: Option<Type>,
+    pub attributes: Attributes,
 }
 
-#[derive(Show)]
+#[derive(Show, Clone, Visit, VisitMut, Fold, Serialize, Deserialize)]
 pub struct SumDecl {
     pub constructors: Vec<Constructor>,
 }
 
-#[derive(Show)]
+#[derive(Show, Clone, Visit, VisitMut, Fold, Serialize, Deserialize)]
 pub struct RecordDecl {
     pub fields: Vec<(Qualified, Type, Visibility)>,
 }
 
-#[derive(Show)]
+#[derive(Show, Clone, Visit, VisitMut, Fold, Serialize, Deserialize)]
 pub enum TypeDef {
     Sum(SumDecl),
     Record(RecordDecl),
@@ -424,49 +570,127 @@ pub enum TypeDef {
     Abstract,
 }
 
-#[derive(Show)]
+#[derive(Show, Clone, Visit, VisitMut, Fold, Serialize, Deserialize)]
 pub struct TypeDecl {
     pub visibility: Visibility,
     pub name: Qualified,
     pub namespace: Symbol,
     pub binders: Vec<TypeBinder>,
     pub def: TypeDef,
+    pub attributes: Attributes,
+
+    /// Declared with `newtype`, so it has a single constructor with a single argument that is
+    /// erased at runtime, and the coercions `wrap` and `unwrap` in its namespace.
+    pub newtype: bool,
+}
+
+impl TypeDecl {
+    /// The coercion from the argument of a newtype to the newtype.
+    pub const WRAP: &'static str = "wrap";
+
+    /// The coercion from a newtype to its argument.
+    pub const UNWRAP: &'static str = "unwrap";
+}
+
+/// Operations declared with `ctl` give a continuation to the handler, while operations declared
+/// with `fun` always resume with the result of the handler, so they can be compiled as calls.
+#[derive(Show, Clone, Visit, VisitMut, Fold, Copy, PartialEq, Eq, Debug, Serialize, Deserialize)]
+pub enum OperationKind {
+    Ctl,
+    Fun,
+}
+
+/// How many times the handlers of an operation may resume its continuation.
+#[derive(Show, Clone, Visit, VisitMut, Fold, Copy, PartialEq, Eq, Debug, Serialize, Deserialize)]
+pub enum Resumption {
+    /// Any number of times, including none.
+    Many,
+
+    /// Exactly once, declared with `once` in place of `ctl`. The handlers that may not resume it
+    /// once get a warning.
+    Once,
+
+    /// Exactly once, because the effect is declared with `once`. The handlers that may not resume
+    /// it once get an error.
+    Linear,
+}
+
+#[derive(Show, Clone, Visit, VisitMut, Fold, Serialize, Deserialize)]
+pub struct EffectField {
+    pub name: Qualified,
+    pub visibility: Visibility,
+    pub kind: OperationKind,
+    pub resumption: Resumption,
+    pub args: Vec<Type>,
+    pub ret: Type,
+    pub default: Option<Expr>,
 }
 
-#[derive(Show)]
+#[derive(Show, Clone, Visit, VisitMut, Fold, Serialize, Deserialize)]
+pub struct EffectDecl {
+    pub visibility: Visibility,
+    pub name: Qualified,
+    pub namespace: Symbol,
+    pub binders: Vec<TypeBinder>,
+    pub fields: Vec<EffectField>,
+    pub span: Span,
+    pub attributes: Attributes,
+}
+
+#[derive(Show, Clone, Visit, VisitMut, Fold, Serialize, Deserialize)]
 pub struct ModuleDecl {
     pub visibility: Visibility,
     pub name: Symbol,
     pub decls: Option<Program>,
+    pub attributes: Attributes,
 }
 
-#[derive(Show)]
+#[derive(Show, Clone, Visit, VisitMut, Fold, Serialize, Deserialize)]
 pub struct ExtDecl {
     pub name: Qualified,
     pub visibility: Visibility,
     pub namespace: Symbol,
+    pub convention: Option<Symbol>,
     pub typ: Type,
+    pub effect: Option<Qualified>,
     pub ret: Symbol,
+    pub attributes: Attributes,
+}
+
+/// A test of `vulpi test`. It has a name that cannot be written, because nothing refers to it.
+#[derive(Show, Clone, Visit, VisitMut, Fold, Serialize, Deserialize)]
+pub struct TestDecl {
+    pub name: Qualified,
+    pub description: Symbol,
+    pub body: Expr,
+    pub span: Span,
 }
 
+#[derive(Clone, Visit, VisitMut, Fold, Serialize, Deserialize)]
 pub enum TopLevel {
     Let(LetDecl),
+    /// The declarations that a declaration of a pattern is desugared into.
+    Pattern(Vec<LetDecl>),
     Type(TypeDecl),
+    Effect(EffectDecl),
     Module(ModuleDecl),
     External(ExtDecl),
     Trait(TraitDecl),
     Impl(Option<TraitImpl>),
     Command(Symbol, Symbol),
+    Test(TestDecl),
     Use,
 }
 
-#[derive(Show, Default)]
+#[derive(Show, Clone, Visit, VisitMut, Fold, Default, Serialize, Deserialize)]
 pub struct Program {
     pub lets: Vec<LetDecl>,
     pub types: Vec<TypeDecl>,
+    pub effects: Vec<EffectDecl>,
     pub modules: Vec<ModuleDecl>,
     pub traits: Vec<TraitDecl>,
     pub impls: Vec<TraitImpl>,
     pub externals: Vec<ExtDecl>,
-    pub commands: Vec<(Symbol, Symbol)>
+    pub commands: Vec<(Symbol, Symbol)>,
+    pub tests: Vec<TestDecl>,
 }
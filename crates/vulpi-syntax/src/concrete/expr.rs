@@ -77,6 +77,14 @@ pub struct ApplicationExpr {
     pub args: Vec<Box<Expr>>,
 }
 
+/// A call to an operation of an effect that is written with `perform`, like `perform Log.log x`.
+#[derive(Show, Clone)]
+pub struct PerformExpr {
+    pub perform: Token,
+    pub operation: Path<Lower>,
+    pub args: Vec<Box<Expr>>,
+}
+
 #[derive(Show, Clone)]
 pub struct ProjectionExpr {
     pub expr: Box<Expr>,
@@ -117,6 +125,42 @@ pub struct WhenExpr {
     pub arms: Vec<PatternArm>,
 }
 
+#[derive(Show, Clone)]
+pub struct CasesExpr {
+    pub cases: Token,
+    pub arms: Vec<PatternArm>,
+}
+
+#[derive(Show, Clone)]
+pub struct HandlerExpr {
+    pub handle: Token,
+    pub expr: Box<Expr>,
+    pub with: Token,
+    pub handler: Box<Expr>,
+    pub finally: Option<(Token, Box<Expr>)>,
+}
+
+/// An expression whose operations of an effect skip the closest handler of it, like
+/// `mask<Log> expr`.
+#[derive(Show, Clone)]
+pub struct MaskExpr {
+    pub mask: Token,
+    pub less: Token,
+    pub effect: Path<Upper>,
+    pub greater: Token,
+    pub expr: Box<Expr>,
+}
+
+#[derive(Show, Clone)]
+pub struct NamedHandlerExpr {
+    pub handle: Token,
+    pub name: Lower,
+    pub eq: Token,
+    pub handler: Box<Expr>,
+    pub in_: Token,
+    pub expr: Box<Expr>,
+}
+
 #[derive(Show, Clone)]
 pub struct AnnotationExpr {
     pub expr: Box<Expr>,
@@ -134,6 +178,42 @@ pub struct LetExpr {
     pub value: Box<Expr>,
 }
 
+/// A binding of a let group, that is a function when it has parameters.
+#[derive(Show, Clone)]
+pub struct LetBinding {
+    pub name: Lower,
+    pub params: Vec<Box<Pattern>>,
+    pub eq: Token,
+    pub value: Box<Expr>,
+}
+
+/// A group of bindings separated by `and`, like `let f x = .. and g y = .. in ..`.
+#[derive(Show, Clone)]
+pub struct LetGroupExpr {
+    pub let_: Token,
+    pub bindings: Vec<(LetBinding, Option<Token>)>,
+    pub in_: Token,
+    pub value: Box<Expr>,
+}
+
+/// A loop like `while cond do ..`, that runs the block while the condition is true.
+#[derive(Show, Clone)]
+pub struct WhileExpr {
+    pub while_: Token,
+    pub condition: Box<Expr>,
+    pub body: DoExpr,
+}
+
+/// A loop like `for x in list do ..`, that runs the block for each element of a list.
+#[derive(Show, Clone)]
+pub struct ForExpr {
+    pub for_: Token,
+    pub pattern: Box<Pattern>,
+    pub in_: Token,
+    pub list: Box<Expr>,
+    pub body: DoExpr,
+}
+
 #[derive(Show, Clone)]
 pub struct Attribute {
     pub name: Upper,
@@ -182,7 +262,9 @@ pub type Tuple = Parenthesis<Vec<(Box<Spanned<ExprKind>>, Option<Token>)>>;
 pub enum ExprKind {
     Lambda(LambdaExpr),
     List(ListExpr),
+    Array(ListExpr),
     Application(ApplicationExpr),
+    Perform(PerformExpr),
     HtmlNode(HtmlNode),
 
     Variable(Lower),
@@ -192,8 +274,15 @@ pub enum ExprKind {
     Projection(ProjectionExpr),
     Binary(BinaryExpr),
     Let(LetExpr),
+    LetGroup(LetGroupExpr),
     When(WhenExpr),
+    Cases(CasesExpr),
+    Handler(HandlerExpr),
+    NamedHandler(NamedHandlerExpr),
+    Mask(MaskExpr),
     Do(DoExpr),
+    While(WhileExpr),
+    For(ForExpr),
     Literal(Literal),
 
     Annotation(AnnotationExpr),
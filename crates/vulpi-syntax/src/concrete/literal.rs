@@ -9,6 +9,10 @@ pub enum LiteralKind {
     Float(Token),
     Char(Token),
     Unit(Token),
+
+    /// A number after a minus sign, that is only written in patterns because expressions negate
+    /// numbers with a subtraction.
+    Negative(Token, Token),
 }
 
 pub type Literal = Spanned<LiteralKind>;
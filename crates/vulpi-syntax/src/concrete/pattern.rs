@@ -18,15 +18,60 @@ pub struct PatApplication {
     pub args: Vec<Box<Pattern>>,
 }
 
+#[derive(Show, Clone)]
+pub struct PatEffect {
+    pub left_brace: Token,
+    pub func: Path<Lower>,
+    pub args: Vec<Box<Pattern>>,
+    pub cont: Option<(Token, Lower)>,
+    pub right_brace: Token,
+}
+
+/// An inclusive range of literals like `'a'..'z'`.
+#[derive(Show, Clone)]
+pub struct PatRange {
+    pub start: Literal,
+    pub dots: Token,
+    pub end: Literal,
+}
+
+/// A pattern that also binds the whole value to a name, like `list@(Cons x xs)`.
+#[derive(Show, Clone)]
+pub struct PatAs {
+    pub name: Lower,
+    pub at: Token,
+    pub pattern: Box<Pattern>,
+}
+
+/// The pattern of the final value of the computation in a handler, like `return x`.
+#[derive(Show, Clone)]
+pub struct PatReturn {
+    pub return_: Token,
+    pub pattern: Box<Pattern>,
+}
+
+/// Alternatives of a pattern like `Just 0 | Nothing`, that are nested to the right.
+#[derive(Show, Clone)]
+pub struct PatOr {
+    pub left: Box<Pattern>,
+    pub bar: Token,
+    pub right: Box<Pattern>,
+}
+
 #[derive(Show, Clone)]
 pub enum PatternKind {
     Wildcard(Token),
     Constructor(Path<Upper>),
     Variable(Lower),
     Literal(Literal),
+    Range(Box<PatRange>),
     Annotation(PatAscription),
     Tuple(Vec<(Pattern, Option<Token>)>),
     Application(PatApplication),
+    Effect(Box<PatEffect>),
+    Return(PatReturn),
+    Or(PatOr),
+    As(PatAs),
     Parenthesis(Parenthesis<Box<Pattern>>),
 }
 
@@ -3,7 +3,7 @@ use vulpi_macros::Show;
 
 use crate::tokens::Token;
 
-use super::{expr::Expr, tree::Pattern};
+use super::{expr::Expr, tree::Pattern, Upper};
 
 #[derive(Show, Clone)]
 pub struct LetSttm {
@@ -13,9 +13,22 @@ pub struct LetSttm {
     pub expr: Box<Expr>,
 }
 
+/// A statement that continues the block only when the value matches the pattern. Otherwise, the
+/// block results in the alternative.
+#[derive(Show, Clone)]
+pub struct ExpectSttm {
+    pub expect: Token,
+    pub pattern: Box<Pattern>,
+    pub eq: Token,
+    pub expr: Box<Expr>,
+    pub else_: Token,
+    pub alternative: Box<Expr>,
+}
+
 #[derive(Show, Clone)]
 pub enum StatementKind {
     Let(LetSttm),
+    Expect(ExpectSttm),
     Expr(Box<Expr>),
     Error(Vec<Token>),
 }
@@ -29,6 +42,9 @@ pub struct Block {
 
 #[derive(Show, Clone)]
 pub struct DoExpr {
+    /// The module of a qualified `do`, like `Option` in `Option.do`, whose `andThen` sequences the
+    /// statements. It's empty in a `do` of effectful statements.
+    pub path: Vec<(Upper, Token)>,
     pub do_: Token,
     pub block: Block,
 }
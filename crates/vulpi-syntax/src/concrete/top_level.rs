@@ -1,4 +1,5 @@
 use vulpi_intern::Symbol;
+use vulpi_location::Span;
 use vulpi_macros::Show;
 
 use crate::tokens::Token;
@@ -17,6 +18,19 @@ use super::{
     Lower, Parenthesis, Path, Upper,
 };
 
+/// The names between the parentheses of an attribute, separated by commas.
+pub type AttributeArgs = Parenthesis<Vec<(Lower, Option<Token>)>>;
+
+/// An attribute written before a declaration, like `#[allow(unused)]` or `#[deprecated "use f"]`.
+#[derive(Show, Clone)]
+pub struct DeclAttribute {
+    pub hash_bracket: Token,
+    pub name: Lower,
+    pub args: Option<AttributeArgs>,
+    pub message: Option<Token>,
+    pub right_bracket: Token,
+}
+
 #[derive(Show, Clone)]
 pub struct Binder {
     pub left_paren: Token,
@@ -56,6 +70,7 @@ pub struct LetSignature {
 
 #[derive(Show, Clone)]
 pub struct TraitDecl {
+    pub attributes: Vec<DeclAttribute>,
     pub visibility: Visibility,
     pub trait_: Token,
     pub supers: Vec<TraitBinder>,
@@ -77,13 +92,32 @@ pub struct TraitImpl {
 
 #[derive(Show, Clone)]
 pub struct LetDecl {
+    pub attributes: Vec<DeclAttribute>,
     pub signature: LetSignature,
     pub body: LetMode,
+
+    /// The span of the whole declaration, from its visibility to the end of its body.
+    pub span: Span,
+}
+
+/// A declaration that binds the variables of a pattern, like `let (a, b) = pair`.
+#[derive(Show, Clone)]
+pub struct PatternDecl {
+    pub visibility: Visibility,
+    pub let_: Token,
+    pub pattern: Box<Pattern>,
+    pub ret: Option<(Token, Box<Type>)>,
+    pub eq: Token,
+    pub body: Box<Expr>,
+
+    /// The span of the whole declaration, from its visibility to the end of its body.
+    pub span: Span,
 }
 
 #[derive(Show, Clone)]
 pub struct Constructor {
     pub pipe: Token,
+    pub attributes: Vec<DeclAttribute>,
     pub name: Upper,
     pub args: Vec<Box<Type>>,
     pub typ: Option<(Token, Box<Type>)>,
@@ -137,7 +171,9 @@ pub enum TypeDef {
 
 #[derive(Show, Clone)]
 pub struct TypeDecl {
+    pub attributes: Vec<DeclAttribute>,
     pub visibility: Visibility,
+    /// The `type` keyword, or the `newtype` keyword for a type without a runtime representation.
     pub type_: Token,
     pub name: Upper,
     pub binders: Vec<TypeBinder>,
@@ -187,6 +223,7 @@ impl ModuleInline {
 
 #[derive(Show, Clone)]
 pub struct ModuleDecl {
+    pub attributes: Vec<DeclAttribute>,
     pub visibility: Visibility,
     pub mod_: Token,
     pub name: Upper,
@@ -213,25 +250,84 @@ impl ModuleDecl {
 
 #[derive(Show, Clone)]
 pub struct ExtDecl {
+    pub attributes: Vec<DeclAttribute>,
     pub visibility: Visibility,
     pub external: Token,
+    pub convention: Option<Token>,
+    pub let_: Option<Token>,
     pub name: Lower,
     pub colon: Token,
     pub typ: Box<Type>,
+    pub effect: Option<(Token, Path<Upper>)>,
     pub equal: Token,
     pub str: Token,
 }
 
+/// An operation of an effect. The kind is `ctl`, `fun` or `once`, that is a `ctl` operation whose
+/// continuation should be resumed exactly once.
+#[derive(Show, Clone)]
+pub struct EffectField {
+    pub visibility: Visibility,
+    pub kind: Option<Token>,
+    pub name: Lower,
+    pub args: Vec<Box<Type>>,
+    pub colon: Token,
+    pub ret: Box<Type>,
+    pub default: Option<(Token, Box<Expr>)>,
+}
+
+/// The declaration of an effect. With `once` before `effect`, the handlers must resume the
+/// continuations of all of its `ctl` operations exactly once.
+#[derive(Show, Clone)]
+pub struct EffectDecl {
+    pub attributes: Vec<DeclAttribute>,
+    pub visibility: Visibility,
+    pub once: Option<Token>,
+    pub effect: Token,
+    pub name: Upper,
+    pub binders: Vec<TypeBinder>,
+    pub where_: Token,
+    pub fields: Vec<EffectField>,
+}
+
 #[derive(Show, Clone)]
 pub struct CommandDecl {
-    pub name: Symbol,
-    pub command: Symbol,
+    pub command: Token,
+    pub name: Token,
+}
+
+/// A test that `vulpi test` runs, described by a string and passing if its body is `True`.
+#[derive(Show, Clone)]
+pub struct TestDecl {
+    pub test: Token,
+    pub description: Token,
+    pub equal: Token,
+    pub body: Box<Expr>,
+}
+
+#[derive(Show, Clone)]
+pub struct WhenArm {
+    pub values: Vec<(Token, Option<Token>)>,
+    pub where_: Token,
+    pub top_levels: Vec<TopLevel>,
+}
+
+/// Declarations that only exist when a condition of the compilation has one of the values, like
+/// the externals of a backend.
+#[derive(Show, Clone)]
+pub struct WhenDecl {
+    pub when: Token,
+    pub condition: Lower,
+    pub is: Token,
+    pub arms: Vec<WhenArm>,
 }
 
 #[derive(Show, Clone)]
 pub enum TopLevel {
     Let(Box<LetDecl>),
+    Pattern(Box<PatternDecl>),
     Type(Box<TypeDecl>),
+    Effect(Box<EffectDecl>),
     Use(Box<UseDecl>),
     Impl(Box<TraitImpl>),
     Trait(Box<TraitDecl>),
@@ -239,6 +335,8 @@ pub enum TopLevel {
     Error(Vec<Token>),
     External(Box<ExtDecl>),
     Command(Box<CommandDecl>),
+    When(Box<WhenDecl>),
+    Test(Box<TestDecl>),
 }
 
 #[derive(Show, Clone)]
@@ -1,12 +1,13 @@
-use std::collections::HashMap;
+use std::collections::BTreeMap;
 
+use serde::{Deserialize, Serialize};
 use vulpi_intern::Symbol;
 use vulpi_location::{Span, Spanned};
 use vulpi_macros::Show;
 
-use crate::r#abstract::Qualified;
+use crate::r#abstract::{OperationKind, Qualified, Visibility};
 
-#[derive(Show, PartialEq, Eq, Hash, Clone, Debug)]
+#[derive(Show, PartialEq, Eq, Hash, Clone, Debug, Serialize, Deserialize)]
 pub enum LiteralKind {
     String(Symbol),
     Integer(Symbol),
@@ -17,13 +18,36 @@ pub enum LiteralKind {
 
 pub type Literal = Box<LiteralKind>;
 
-#[derive(Show, Clone)]
+impl LiteralKind {
+    /// The position of an integer or of a character in the order that range patterns use, that
+    /// is the integer itself or the code point of the character.
+    pub fn ordinal(&self) -> Option<i64> {
+        match self {
+            LiteralKind::Integer(n) => n.get().replace('_', "").parse().ok(),
+            LiteralKind::Char(c) => c.get().chars().next().map(|c| c as i64),
+            _ => None,
+        }
+    }
+
+    /// The literal of the same kind at a position, that is the inverse of [LiteralKind::ordinal].
+    pub fn with_ordinal(&self, ordinal: i64) -> LiteralKind {
+        match self {
+            LiteralKind::Char(_) => {
+                let char = char::from_u32(ordinal as u32).unwrap_or(char::REPLACEMENT_CHARACTER);
+                LiteralKind::Char(Symbol::intern(&char.to_string()))
+            }
+            _ => LiteralKind::Integer(Symbol::intern(&ordinal.to_string())),
+        }
+    }
+}
+
+#[derive(Show, Clone, Serialize, Deserialize)]
 pub struct LetStatement<T> {
     pub pattern: Pattern,
     pub expr: Expr<T>,
 }
 
-#[derive(Show, Clone)]
+#[derive(Show, Clone, Serialize, Deserialize)]
 pub enum SttmKind<T> {
     Let(LetStatement<T>),
     Expr(Expr<T>),
@@ -34,105 +58,152 @@ pub type Statement<T> = SttmKind<T>;
 
 pub type Block<T> = Vec<Statement<T>>;
 
-#[derive(Show, Clone)]
-pub struct PatOr {
-    pub left: Pattern,
-    pub right: Pattern,
+#[derive(Show, Clone, Debug, Serialize, Deserialize)]
+pub struct PatApplication {
+    pub func: Qualified,
+    pub args: Vec<Pattern>,
 }
 
-#[derive(Show, Clone, Debug)]
-pub struct PatApplication {
+#[derive(Show, Clone, Debug, Serialize, Deserialize)]
+pub struct PatEffect {
     pub func: Qualified,
     pub args: Vec<Pattern>,
+    pub cont: Option<Symbol>,
 }
 
-#[derive(Show, Clone, Debug)]
+#[derive(Show, Clone, Debug, Serialize, Deserialize)]
 pub enum PatternKind {
     Wildcard,
     Variable(Symbol),
     Literal(Literal),
+
+    /// An inclusive range of integers or of characters.
+    Range(Literal, Literal),
+
+    /// The alternatives of an or-pattern, that bind the same variables.
+    Or(Vec<Pattern>),
+
+    /// A pattern that also binds the whole value to the name.
+    As(Symbol, Pattern),
     Application(PatApplication),
+    Effect(PatEffect),
     Tuple(Vec<Pattern>),
     Error,
 }
 
 pub type Pattern = Box<PatternKind>;
 
-#[derive(Show, Clone)]
+#[derive(Show, Clone, Serialize, Deserialize)]
 pub struct LambdaExpr<T> {
     pub param: Pattern,
     pub body: Expr<T>,
 }
 
-#[derive(Show, Clone)]
+#[derive(Show, Clone, Serialize, Deserialize)]
 pub enum AppKind {
     Infix,
     Normal,
 }
 
-#[derive(Show, Clone)]
+#[derive(Show, Clone, Serialize, Deserialize)]
 pub struct ApplicationExpr<T> {
     pub typ: T,
     pub func: Expr<T>,
     pub args: Expr<T>,
 }
 
-#[derive(Show, Clone)]
+#[derive(Show, Clone, Serialize, Deserialize)]
 pub struct ProjectionExpr<T> {
     pub field: Qualified,
     pub expr: Expr<T>,
 }
 
-#[derive(Show, Clone)]
+#[derive(Show, Clone, Serialize, Deserialize)]
 pub struct PatternArm<T> {
     pub patterns: Vec<Pattern>,
     pub expr: Expr<T>,
     pub guard: Option<Expr<T>>,
 }
 
-#[derive(Show, Clone)]
+#[derive(Show, Clone, Serialize, Deserialize)]
 pub struct WhenExpr<T> {
     pub scrutinee: Vec<Expr<T>>,
     pub arms: Vec<PatternArm<T>>,
 }
 
-#[derive(Show, Clone)]
+#[derive(Show, Clone, Serialize, Deserialize)]
+pub enum Handler<T> {
+    Cases(Vec<PatternArm<T>>),
+    Function(Expr<T>),
+}
+
+#[derive(Show, Clone, Serialize, Deserialize)]
+pub struct HandlerExpr<T> {
+    pub name: Option<Symbol>,
+    pub expr: Expr<T>,
+    pub handler: Handler<T>,
+    pub defaults: Vec<(Qualified, Qualified)>,
+    pub finally: Option<Expr<T>>,
+}
+
+/// An expression whose operations skip the closest handler of them. The operations are the ones of
+/// the masked effect.
+#[derive(Show, Clone, Serialize, Deserialize)]
+pub struct MaskExpr<T> {
+    pub operations: Vec<Qualified>,
+    pub expr: Expr<T>,
+}
+
+#[derive(Show, Clone, Serialize, Deserialize)]
 pub struct LetExpr<T> {
     pub pattern: Pattern,
     pub body: Expr<T>,
     pub next: Expr<T>,
 }
 
-#[derive(Show, Clone)]
+/// Bindings that refer to each other, so all of them are in scope in each one of them. They're
+/// always functions.
+#[derive(Show, Clone, Serialize, Deserialize)]
+pub struct LetRecExpr<T> {
+    pub bindings: Vec<(Symbol, Expr<T>)>,
+    pub next: Expr<T>,
+}
+
+#[derive(Show, Clone, Serialize, Deserialize)]
 pub struct RecordInstance<T> {
     pub name: Qualified,
     pub fields: Vec<(Symbol, Expr<T>)>,
 }
 
-#[derive(Show, Clone)]
+#[derive(Show, Clone, Serialize, Deserialize)]
 pub struct RecordUpdate<T> {
     pub name: Qualified,
     pub expr: Expr<T>,
     pub fields: Vec<(Symbol, Expr<T>)>,
 }
 
-#[derive(Show, Clone)]
+#[derive(Show, Clone, Serialize, Deserialize)]
 pub struct Tuple<T> {
     pub exprs: Vec<Expr<T>>,
 }
 
-#[derive(Show, Clone)]
+#[derive(Show, Clone, Serialize, Deserialize)]
 pub enum ExprKind<T> {
     Lambda(LambdaExpr<T>),
     Application(ApplicationExpr<T>),
 
     Variable(Symbol),
     Constructor(Qualified, Qualified),
+    /// A reference to a let declaration with the type that it's instantiated to.
     Function(Qualified, T),
 
     Projection(ProjectionExpr<T>),
     Let(LetExpr<T>),
+    LetRec(LetRecExpr<T>),
     When(WhenExpr<T>),
+    Handler(HandlerExpr<T>),
+    Mask(MaskExpr<T>),
+    Operation(Symbol, Qualified),
     Do(Block<T>),
     Literal(Literal),
 
@@ -145,45 +216,174 @@ pub enum ExprKind<T> {
 
 pub type Expr<T> = Spanned<Box<ExprKind<T>>>;
 
-#[derive(Show, Clone)]
+#[derive(Show, Clone, Serialize, Deserialize)]
 pub struct LetDecl<T> {
     pub name: Qualified,
+    pub span: Span,
+    pub visibility: Visibility,
+    pub typ: T,
     pub binders: Vec<(Pattern, T)>,
     pub body: Vec<PatternArm<T>>,
-    pub constants: Option<HashMap<Qualified, Span>>,
+    pub constants: Option<BTreeMap<Qualified, Span>>,
+
+    /// The span of the whole declaration, from its visibility to the end of its body.
+    pub declaration: Span,
+
+    /// The lints that are allowed in the declaration, by its attributes or by its modules.
+    pub allow: Vec<Symbol>,
+}
+
+/// A test of `vulpi test`, whose body has the type `Bool`.
+#[derive(Show, Clone, Serialize, Deserialize)]
+pub struct TestDecl<T> {
+    pub name: Qualified,
+    pub description: Symbol,
+    pub span: Span,
+    pub typ: T,
+    pub body: Expr<T>,
 }
 
-#[derive(Show, Clone)]
+#[derive(Show, Clone, Serialize, Deserialize)]
 pub enum TypeDecl {
     Abstract,
     Enum(Vec<(Qualified, usize)>),
     Record(Vec<Qualified>),
+    Effect(Vec<(Qualified, usize, OperationKind, Option<Qualified>)>),
 }
 
-#[derive(Show, Clone)]
+#[derive(Show, Clone, Serialize, Deserialize)]
 pub struct ExternalDecl<T> {
     pub name: Qualified,
     pub typ: T,
+    pub effect: Qualified,
+    pub convention: Option<Symbol>,
     pub binding: Symbol,
 }
 
-#[derive(Show, Clone)]
+#[derive(Show, Clone, Serialize, Deserialize)]
 pub struct Program<T> {
-    pub modules: HashMap<Symbol, Program<T>>,
-    pub lets: HashMap<Qualified, LetDecl<T>>,
-    pub types: HashMap<Qualified, TypeDecl>,
-    pub externals: HashMap<Qualified, ExternalDecl<T>>,
+    pub modules: BTreeMap<Symbol, Program<T>>,
+    pub lets: BTreeMap<Qualified, LetDecl<T>>,
+    pub types: BTreeMap<Qualified, TypeDecl>,
+    pub externals: BTreeMap<Qualified, ExternalDecl<T>>,
     pub commands: Vec<(Symbol, Symbol)>,
+    pub tests: Vec<TestDecl<T>>,
 }
 
 impl<T> Default for Program<T> {
     fn default() -> Self {
         Self {
-            modules: HashMap::new(),
-            lets: HashMap::new(),
-            types: HashMap::new(),
-            externals: HashMap::new(),
+            modules: BTreeMap::new(),
+            lets: BTreeMap::new(),
+            types: BTreeMap::new(),
+            externals: BTreeMap::new(),
             commands: Vec::new(),
+            tests: Vec::new(),
+        }
+    }
+}
+
+/// The nodes of the elaborated tree that have types inside of them. The types are changed in
+/// place, in the order that they appear in the node.
+pub trait TypesMut<T> {
+    fn types_mut(&mut self, f: &mut dyn FnMut(&mut T));
+}
+
+impl<T> TypesMut<T> for Expr<T> {
+    fn types_mut(&mut self, f: &mut dyn FnMut(&mut T)) {
+        match &mut *self.data {
+            ExprKind::Lambda(lambda) => lambda.body.types_mut(f),
+            ExprKind::Application(app) => {
+                f(&mut app.typ);
+                app.func.types_mut(f);
+                app.args.types_mut(f);
+            }
+            ExprKind::Function(_, typ) => f(typ),
+            ExprKind::Projection(projection) => projection.expr.types_mut(f),
+            ExprKind::Let(let_expr) => {
+                let_expr.body.types_mut(f);
+                let_expr.next.types_mut(f);
+            }
+            ExprKind::LetRec(let_rec) => {
+                for (_, binding) in &mut let_rec.bindings {
+                    binding.types_mut(f);
+                }
+                let_rec.next.types_mut(f);
+            }
+            ExprKind::When(when) => {
+                for scrutinee in &mut when.scrutinee {
+                    scrutinee.types_mut(f);
+                }
+                for arm in &mut when.arms {
+                    arm.types_mut(f);
+                }
+            }
+            ExprKind::Handler(handler) => {
+                handler.expr.types_mut(f);
+                handler.handler.types_mut(f);
+                if let Some(finally) = &mut handler.finally {
+                    finally.types_mut(f);
+                }
+            }
+            ExprKind::Mask(mask) => mask.expr.types_mut(f),
+            ExprKind::Do(block) => {
+                for statement in block {
+                    statement.types_mut(f);
+                }
+            }
+            ExprKind::RecordInstance(instance) => {
+                for (_, expr) in &mut instance.fields {
+                    expr.types_mut(f);
+                }
+            }
+            ExprKind::RecordUpdate(update) => {
+                update.expr.types_mut(f);
+                for (_, expr) in &mut update.fields {
+                    expr.types_mut(f);
+                }
+            }
+            ExprKind::Tuple(tuple) => {
+                for expr in &mut tuple.exprs {
+                    expr.types_mut(f);
+                }
+            }
+            ExprKind::Variable(_)
+            | ExprKind::Constructor(_, _)
+            | ExprKind::Operation(_, _)
+            | ExprKind::Literal(_)
+            | ExprKind::Error => {}
+        }
+    }
+}
+
+impl<T> TypesMut<T> for Statement<T> {
+    fn types_mut(&mut self, f: &mut dyn FnMut(&mut T)) {
+        match self {
+            SttmKind::Let(let_sttm) => let_sttm.expr.types_mut(f),
+            SttmKind::Expr(expr) => expr.types_mut(f),
+            SttmKind::Error => {}
+        }
+    }
+}
+
+impl<T> TypesMut<T> for PatternArm<T> {
+    fn types_mut(&mut self, f: &mut dyn FnMut(&mut T)) {
+        self.expr.types_mut(f);
+        if let Some(guard) = &mut self.guard {
+            guard.types_mut(f);
+        }
+    }
+}
+
+impl<T> TypesMut<T> for Handler<T> {
+    fn types_mut(&mut self, f: &mut dyn FnMut(&mut T)) {
+        match self {
+            Handler::Cases(arms) => {
+                for arm in arms {
+                    arm.types_mut(f);
+                }
+            }
+            Handler::Function(expr) => expr.types_mut(f),
         }
     }
 }
@@ -1,10 +1,13 @@
-use std::{collections::HashMap};
+use std::collections::{BTreeMap, HashMap};
 
 use vulpi_intern::Symbol;
 use vulpi_location::Span;
 use vulpi_macros::Show;
 
-use crate::{r#abstract::Qualified, elaborated::Literal};
+use crate::{
+    elaborated::Literal,
+    r#abstract::{OperationKind, Qualified},
+};
 
 #[derive(Show, Clone)]
 pub enum ConsDef {
@@ -63,7 +66,16 @@ pub enum ExprKind {
     Tuple(Vec<Expr>),
 
     Switch(Symbol, Tree, Vec<Expr>),
-    
+
+    /// Performs an operation, giving control to the closest handler of that operation or to the
+    /// handler instance given in the first field.
+    Perform(Option<Expr>, Qualified, Vec<Expr>),
+
+    /// Runs the thunk with the handler installed. Each clause receives the arguments of the
+    /// operation and the continuation, and the last expression receives the final value. The
+    /// thunk of a named handler receives the handler instance as its only parameter. Clauses of
+    /// `fun` operations receive no continuation and their result is the result of the operation.
+    Handle(Expr, Vec<(Qualified, OperationKind, Expr)>, Expr),
 }
 
 pub type Expr = Box<ExprKind>;
@@ -73,7 +85,7 @@ pub struct LetDecl {
     pub name: Qualified,
     pub body: Expr,
     pub is_in_source_code: bool,
-    pub constants: Option<HashMap<Qualified, Span>>,
+    pub constants: Option<BTreeMap<Qualified, Span>>,
 }
 
 #[derive(Show, Clone)]
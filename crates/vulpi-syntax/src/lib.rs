@@ -3,3 +3,4 @@ pub mod concrete;
 pub mod elaborated;
 pub mod lambda;
 pub mod tokens;
+pub mod visit;
@@ -19,6 +19,7 @@ pub enum TokenData {
     Use,      // 'use' keyword
     As,       // 'as' keyword
     Type,     // 'type' keyword
+    Newtype,  // 'newtype' keyword
     Pub,      // 'pub' keyword
     Do,       // 'do' keyword
     In,       // 'in' keyword
@@ -26,26 +27,38 @@ pub enum TokenData {
     Where,    // 'where' keyword
     Mod,      // 'mod' keyword
     Handle,   // 'handle' keyword
+    Perform,  // 'perform' keyword
+    Finally,  // 'finally' keyword
+    Return,   // 'return' keyword
+    Mask,     // 'mask' keyword
     Cases,    // 'request' keyword
     Effect,   // 'effect' keyword
     External, // 'external' keyword
     Trait,    // 'trait' keyword
     Impl,     // 'impl' keyword
+    Ctl,      // 'ctl' keyword
+    Fun,      // 'fun' keyword
+    Test,     // 'test' keyword
+    Expect,   // 'expect' keyword
+    While,    // 'while' keyword
+    For,      // 'for' keyword
+    Once,     // 'once' keyword
 
     String, // String literal
     Int,    // Integer literal
     Float,  // Float Literal
     Char,   // Char literal
 
-    LBrace,     // '{'
-    RBrace,     // '}'
-    LPar,       // '('
-    RPar,       // ')'
-    LBracket,   // '['
-    RBracket,   // ']'
-    LeftArrow,  // '<-'
-    RightArrow, // '->'
-    FatArrow,   // '=>'
+    LBrace,      // '{'
+    RBrace,      // '}'
+    LPar,        // '('
+    RPar,        // ')'
+    LBracket,    // '['
+    RBracket,    // ']'
+    HashBracket, // '#['
+    LeftArrow,   // '<-'
+    RightArrow,  // '->'
+    FatArrow,    // '=>'
     Unit,
 
     LowerIdent, // Identifier
@@ -57,11 +70,13 @@ pub enum TokenData {
     Semicolon,   // ';'
     Comma,       // ','
     Dot,         // '.'
+    DotDot,      // '..'
     Exclamation, // '!'
     Equal,       // '='
     Bar,         // '|'
     PipeRight,   // '|>'
     PlusPlus,    // '++'
+    At,          // '@'
 
     Plus,      // '+'
     Minus,     // '-'
@@ -146,6 +161,8 @@ impl ToString for Token {
             Semicolon => ";".to_string(),
             Comma => ",".to_string(),
             Dot => ".".to_string(),
+            DotDot => "..".to_string(),
+            At => "@".to_string(),
             Exclamation => "!".to_string(),
             Equal => "=".to_string(),
             Bar => "|".to_string(),
@@ -180,12 +197,20 @@ impl ToString for Token {
             Use => "use".to_string(),
             As => "as".to_string(),
             Type => "type".to_string(),
+            Newtype => "newtype".to_string(),
             Pub => "pub".to_string(),
             Do => "do".to_string(),
             Where => "where".to_string(),
             Forall => "forall".to_string(),
             Trait => "trait".to_string(),
             Impl => "impl".to_string(),
+            Ctl => "ctl".to_string(),
+            Fun => "fun".to_string(),
+            Test => "test".to_string(),
+            Expect => "expect".to_string(),
+            While => "while".to_string(),
+            For => "for".to_string(),
+            Once => "once".to_string(),
             In => "in".to_string(),
             LBrace => "{{".to_string(),
             RBrace => "}}".to_string(),
@@ -193,6 +218,7 @@ impl ToString for Token {
             RPar => ")".to_string(),
             LBracket => "[".to_string(),
             RBracket => "]".to_string(),
+            HashBracket => "#[".to_string(),
             LessSlash => "</".to_string(),
             LeftArrow => "<-".to_string(),
             RightArrow => "->".to_string(),
@@ -204,6 +230,10 @@ impl ToString for Token {
             Wildcard => "_".to_string(),
             Mod => "mod".to_string(),
             Handle => "handle".to_string(),
+            Perform => "perform".to_string(),
+            Finally => "finally".to_string(),
+            Return => "return".to_string(),
+            Mask => "mask".to_string(),
             Cases => "cases".to_string(),
             Effect => "effect".to_string(),
             External => "external".to_string(),
@@ -0,0 +1,476 @@
+//! Traversals of the abstract tree. A [Visitor] or a [Folder] overrides the methods of the nodes
+//! that it's interested in and calls `walk` on them to keep going into their children, while the
+//! nodes are traversed by the implementations that are derived with `Visit`, `VisitMut` and `Fold`
+//! from `vulpi-macros`.
+//!
+//! ```ignore
+//! struct Variables(Vec<Symbol>);
+//!
+//! impl Visitor for Variables {
+//!     fn visit_expr(&mut self, expr: &Expr) {
+//!         if let ExprKind::Variable(name) = &expr.data {
+//!             self.0.push(name.clone());
+//!         }
+//!
+//!         expr.walk(self)
+//!     }
+//! }
+//! ```
+
+use std::collections::BTreeMap;
+
+use vulpi_intern::Symbol;
+use vulpi_location::{Span, Spanned};
+
+use crate::r#abstract::*;
+
+pub trait Visitor {
+    fn visit_expr(&mut self, expr: &Expr) {
+        expr.walk(self)
+    }
+
+    fn visit_pattern(&mut self, pattern: &Pattern) {
+        pattern.walk(self)
+    }
+
+    fn visit_type(&mut self, typ: &Type) {
+        typ.walk(self)
+    }
+
+    fn visit_kind(&mut self, kind: &Kind) {
+        kind.walk(self)
+    }
+
+    fn visit_literal(&mut self, literal: &Literal) {
+        literal.walk(self)
+    }
+
+    fn visit_symbol(&mut self, _symbol: &Symbol) {}
+
+    fn visit_qualified(&mut self, _qualified: &Qualified) {}
+
+    fn visit_span(&mut self, _span: &Span) {}
+}
+
+pub trait VisitorMut {
+    fn visit_expr_mut(&mut self, expr: &mut Expr) {
+        expr.walk_mut(self)
+    }
+
+    fn visit_pattern_mut(&mut self, pattern: &mut Pattern) {
+        pattern.walk_mut(self)
+    }
+
+    fn visit_type_mut(&mut self, typ: &mut Type) {
+        typ.walk_mut(self)
+    }
+
+    fn visit_kind_mut(&mut self, kind: &mut Kind) {
+        kind.walk_mut(self)
+    }
+
+    fn visit_literal_mut(&mut self, literal: &mut Literal) {
+        literal.walk_mut(self)
+    }
+
+    fn visit_symbol_mut(&mut self, _symbol: &mut Symbol) {}
+
+    fn visit_qualified_mut(&mut self, _qualified: &mut Qualified) {}
+
+    fn visit_span_mut(&mut self, _span: &mut Span) {}
+}
+
+pub trait Folder {
+    fn fold_expr(&mut self, expr: Expr) -> Expr {
+        expr.walk_fold(self)
+    }
+
+    fn fold_pattern(&mut self, pattern: Pattern) -> Pattern {
+        pattern.walk_fold(self)
+    }
+
+    fn fold_type(&mut self, typ: Type) -> Type {
+        typ.walk_fold(self)
+    }
+
+    fn fold_kind(&mut self, kind: Kind) -> Kind {
+        kind.walk_fold(self)
+    }
+
+    fn fold_literal(&mut self, literal: Literal) -> Literal {
+        literal.walk_fold(self)
+    }
+
+    fn fold_symbol(&mut self, symbol: Symbol) -> Symbol {
+        symbol
+    }
+
+    fn fold_qualified(&mut self, qualified: Qualified) -> Qualified {
+        qualified
+    }
+
+    fn fold_span(&mut self, span: Span) -> Span {
+        span
+    }
+}
+
+/// A node that a [Visitor] can go through. Visiting a node calls the method of the visitor for
+/// it, if there's one, and walking it visits its children.
+pub trait Visit {
+    fn visit<V: Visitor + ?Sized>(&self, visitor: &mut V) {
+        self.walk(visitor)
+    }
+
+    fn walk<V: Visitor + ?Sized>(&self, visitor: &mut V);
+}
+
+pub trait VisitMut {
+    fn visit_mut<V: VisitorMut + ?Sized>(&mut self, visitor: &mut V) {
+        self.walk_mut(visitor)
+    }
+
+    fn walk_mut<V: VisitorMut + ?Sized>(&mut self, visitor: &mut V);
+}
+
+pub trait Fold: Sized {
+    fn fold<F: Folder + ?Sized>(self, folder: &mut F) -> Self {
+        self.walk_fold(folder)
+    }
+
+    fn walk_fold<F: Folder + ?Sized>(self, folder: &mut F) -> Self;
+}
+
+/// Implements the traversals of the nodes that have a method in the visitors. They're boxes of
+/// spanned nodes, so the span is visited before the node.
+macro_rules! node {
+    ($kind:ty, $visit:ident, $visit_mut:ident, $fold:ident) => {
+        impl Visit for Box<Spanned<$kind>> {
+            fn visit<V: Visitor + ?Sized>(&self, visitor: &mut V) {
+                visitor.$visit(self)
+            }
+
+            fn walk<V: Visitor + ?Sized>(&self, visitor: &mut V) {
+                self.as_ref().walk(visitor)
+            }
+        }
+
+        impl VisitMut for Box<Spanned<$kind>> {
+            fn visit_mut<V: VisitorMut + ?Sized>(&mut self, visitor: &mut V) {
+                visitor.$visit_mut(self)
+            }
+
+            fn walk_mut<V: VisitorMut + ?Sized>(&mut self, visitor: &mut V) {
+                self.as_mut().walk_mut(visitor)
+            }
+        }
+
+        impl Fold for Box<Spanned<$kind>> {
+            fn fold<F: Folder + ?Sized>(self, folder: &mut F) -> Self {
+                folder.$fold(self)
+            }
+
+            fn walk_fold<F: Folder + ?Sized>(self, folder: &mut F) -> Self {
+                Box::new((*self).walk_fold(folder))
+            }
+        }
+    };
+}
+
+node!(ExprKind, visit_expr, visit_expr_mut, fold_expr);
+node!(PatternKind, visit_pattern, visit_pattern_mut, fold_pattern);
+node!(TypeKind, visit_type, visit_type_mut, fold_type);
+node!(KindType, visit_kind, visit_kind_mut, fold_kind);
+node!(LiteralKind, visit_literal, visit_literal_mut, fold_literal);
+
+/// Implements the traversals of the leaves, that have a method in the visitors but no children.
+macro_rules! leaf {
+    ($type:ty, $visit:ident, $visit_mut:ident, $fold:ident) => {
+        impl Visit for $type {
+            fn visit<V: Visitor + ?Sized>(&self, visitor: &mut V) {
+                visitor.$visit(self)
+            }
+
+            fn walk<V: Visitor + ?Sized>(&self, _: &mut V) {}
+        }
+
+        impl VisitMut for $type {
+            fn visit_mut<V: VisitorMut + ?Sized>(&mut self, visitor: &mut V) {
+                visitor.$visit_mut(self)
+            }
+
+            fn walk_mut<V: VisitorMut + ?Sized>(&mut self, _: &mut V) {}
+        }
+
+        impl Fold for $type {
+            fn fold<F: Folder + ?Sized>(self, folder: &mut F) -> Self {
+                folder.$fold(self)
+            }
+
+            fn walk_fold<F: Folder + ?Sized>(self, _: &mut F) -> Self {
+                self
+            }
+        }
+    };
+}
+
+leaf!(Symbol, visit_symbol, visit_symbol_mut, fold_symbol);
+leaf!(
+    Qualified,
+    visit_qualified,
+    visit_qualified_mut,
+    fold_qualified
+);
+leaf!(Span, visit_span, visit_span_mut, fold_span);
+
+impl<T: Visit> Visit for Spanned<T> {
+    fn walk<V: Visitor + ?Sized>(&self, visitor: &mut V) {
+        self.span.visit(visitor);
+        self.data.visit(visitor);
+    }
+}
+
+impl<T: VisitMut> VisitMut for Spanned<T> {
+    fn walk_mut<V: VisitorMut + ?Sized>(&mut self, visitor: &mut V) {
+        self.span.visit_mut(visitor);
+        self.data.visit_mut(visitor);
+    }
+}
+
+impl<T: Fold> Fold for Spanned<T> {
+    fn walk_fold<F: Folder + ?Sized>(self, folder: &mut F) -> Self {
+        Spanned {
+            span: self.span.fold(folder),
+            data: self.data.fold(folder),
+        }
+    }
+}
+
+impl<T: Visit> Visit for Vec<T> {
+    fn walk<V: Visitor + ?Sized>(&self, visitor: &mut V) {
+        self.iter().for_each(|x| x.visit(visitor))
+    }
+}
+
+impl<T: VisitMut> VisitMut for Vec<T> {
+    fn walk_mut<V: VisitorMut + ?Sized>(&mut self, visitor: &mut V) {
+        self.iter_mut().for_each(|x| x.visit_mut(visitor))
+    }
+}
+
+impl<T: Fold> Fold for Vec<T> {
+    fn walk_fold<F: Folder + ?Sized>(self, folder: &mut F) -> Self {
+        self.into_iter().map(|x| x.fold(folder)).collect()
+    }
+}
+
+impl<T: Visit> Visit for Option<T> {
+    fn walk<V: Visitor + ?Sized>(&self, visitor: &mut V) {
+        if let Some(x) = self {
+            x.visit(visitor)
+        }
+    }
+}
+
+impl<T: VisitMut> VisitMut for Option<T> {
+    fn walk_mut<V: VisitorMut + ?Sized>(&mut self, visitor: &mut V) {
+        if let Some(x) = self {
+            x.visit_mut(visitor)
+        }
+    }
+}
+
+impl<T: Fold> Fold for Option<T> {
+    fn walk_fold<F: Folder + ?Sized>(self, folder: &mut F) -> Self {
+        self.map(|x| x.fold(folder))
+    }
+}
+
+/// The flags of the nodes have nothing to traverse and no method in the visitors.
+impl Visit for bool {
+    fn walk<V: Visitor + ?Sized>(&self, _: &mut V) {}
+}
+
+impl VisitMut for bool {
+    fn walk_mut<V: VisitorMut + ?Sized>(&mut self, _: &mut V) {}
+}
+
+impl Fold for bool {
+    fn walk_fold<F: Folder + ?Sized>(self, _: &mut F) -> Self {
+        self
+    }
+}
+
+impl<T: Visit, U: Visit> Visit for (T, U) {
+    fn walk<V: Visitor + ?Sized>(&self, visitor: &mut V) {
+        self.0.visit(visitor);
+        self.1.visit(visitor);
+    }
+}
+
+impl<T: VisitMut, U: VisitMut> VisitMut for (T, U) {
+    fn walk_mut<V: VisitorMut + ?Sized>(&mut self, visitor: &mut V) {
+        self.0.visit_mut(visitor);
+        self.1.visit_mut(visitor);
+    }
+}
+
+impl<T: Fold, U: Fold> Fold for (T, U) {
+    fn walk_fold<F: Folder + ?Sized>(self, folder: &mut F) -> Self {
+        (self.0.fold(folder), self.1.fold(folder))
+    }
+}
+
+impl<T: Visit, U: Visit, W: Visit> Visit for (T, U, W) {
+    fn walk<V: Visitor + ?Sized>(&self, visitor: &mut V) {
+        self.0.visit(visitor);
+        self.1.visit(visitor);
+        self.2.visit(visitor);
+    }
+}
+
+impl<T: VisitMut, U: VisitMut, W: VisitMut> VisitMut for (T, U, W) {
+    fn walk_mut<V: VisitorMut + ?Sized>(&mut self, visitor: &mut V) {
+        self.0.visit_mut(visitor);
+        self.1.visit_mut(visitor);
+        self.2.visit_mut(visitor);
+    }
+}
+
+impl<T: Fold, U: Fold, W: Fold> Fold for (T, U, W) {
+    fn walk_fold<F: Folder + ?Sized>(self, folder: &mut F) -> Self {
+        (
+            self.0.fold(folder),
+            self.1.fold(folder),
+            self.2.fold(folder),
+        )
+    }
+}
+
+impl<K: Visit, T: Visit> Visit for BTreeMap<K, T> {
+    fn walk<V: Visitor + ?Sized>(&self, visitor: &mut V) {
+        for (key, value) in self {
+            key.visit(visitor);
+            value.visit(visitor);
+        }
+    }
+}
+
+/// The keys of a map are not visited mutably, because changing them could break its order.
+impl<K, T: VisitMut> VisitMut for BTreeMap<K, T> {
+    fn walk_mut<V: VisitorMut + ?Sized>(&mut self, visitor: &mut V) {
+        self.values_mut().for_each(|x| x.visit_mut(visitor))
+    }
+}
+
+impl<K: Fold + Ord, T: Fold> Fold for BTreeMap<K, T> {
+    fn walk_fold<F: Folder + ?Sized>(self, folder: &mut F) -> Self {
+        self.into_iter()
+            .map(|(key, value)| (key.fold(folder), value.fold(folder)))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn spanned<T>(data: T) -> Box<Spanned<T>> {
+        Box::new(Spanned::new(data, Span::ghost()))
+    }
+
+    fn variable(name: &str) -> Expr {
+        spanned(ExprKind::Variable(Symbol::intern(name)))
+    }
+
+    /// The tree of `\x => Prelude.add x y`.
+    fn lambda() -> Expr {
+        let add = Qualified {
+            path: Symbol::intern("Prelude"),
+            name: Symbol::intern("add"),
+        };
+
+        spanned(ExprKind::Lambda(LambdaExpr {
+            param: spanned(PatternKind::Variable(Symbol::intern("x"))),
+            body: spanned(ExprKind::Application(ApplicationExpr {
+                app: AppKind::Normal,
+                func: spanned(ExprKind::Function(add)),
+                args: vec![variable("x"), variable("y")],
+            })),
+        }))
+    }
+
+    #[derive(Default)]
+    struct Names {
+        symbols: Vec<String>,
+        functions: Vec<String>,
+        variables: usize,
+    }
+
+    impl Visitor for Names {
+        fn visit_expr(&mut self, expr: &Expr) {
+            if let ExprKind::Variable(_) = &expr.data {
+                self.variables += 1;
+            }
+
+            expr.walk(self)
+        }
+
+        fn visit_symbol(&mut self, symbol: &Symbol) {
+            self.symbols.push(symbol.get());
+        }
+
+        fn visit_qualified(&mut self, qualified: &Qualified) {
+            self.functions.push(qualified.to_string());
+        }
+    }
+
+    fn names(expr: &Expr) -> Names {
+        let mut names = Names::default();
+        expr.visit(&mut names);
+        names
+    }
+
+    #[test]
+    fn visitors_go_through_the_children_in_order() {
+        let names = names(&lambda());
+
+        assert_eq!(names.symbols, vec!["x", "x", "y"]);
+        assert_eq!(names.functions, vec!["Prelude.add"]);
+        assert_eq!(names.variables, 2);
+    }
+
+    #[test]
+    fn mutable_visitors_and_folders_change_the_nodes() {
+        struct Rename;
+
+        impl VisitorMut for Rename {
+            fn visit_symbol_mut(&mut self, symbol: &mut Symbol) {
+                if symbol.get() == "x" {
+                    *symbol = Symbol::intern("z");
+                }
+            }
+        }
+
+        let mut expr = lambda();
+        expr.visit_mut(&mut Rename);
+        assert_eq!(names(&expr).symbols, vec!["z", "z", "y"]);
+
+        // The variable is replaced by a literal, so its symbol is not visited anymore.
+        struct Constant;
+
+        impl Folder for Constant {
+            fn fold_expr(&mut self, expr: Expr) -> Expr {
+                match &expr.data {
+                    ExprKind::Variable(name) if name.get() == "y" => spanned(ExprKind::Literal(
+                        spanned(LiteralKind::Integer(Symbol::intern("1"))),
+                    )),
+                    _ => expr.walk_fold(self),
+                }
+            }
+        }
+
+        let names = names(&expr.fold(&mut Constant));
+        assert_eq!(names.symbols, vec!["z", "z", "1"]);
+        assert_eq!(names.variables, 1);
+    }
+}
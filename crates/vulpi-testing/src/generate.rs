@@ -0,0 +1,1054 @@
+//! Random programs for round trip tests. A [Gen] makes abstract programs that are well formed: the
+//! names that they use are declared, the types are applied to as many arguments as they have
+//! parameters and the constructors in the patterns have all of their arguments. The programs are
+//! not type checked, so their values don't need to have the types that they are annotated with.
+//!
+//! The blocks of the layout, like `do` and `when`, are only generated at the end of a line, because
+//! a block cannot be closed by a token that comes after it in its last line, like `,` or `)`.
+//!
+//! The same seed always makes the same program, so a failure can be reproduced from its seed.
+
+use vulpi_intern::Symbol;
+use vulpi_location::{Span, Spanned};
+use vulpi_syntax::r#abstract::*;
+
+/// How deep the expressions, types and patterns can be nested.
+const DEPTH: usize = 4;
+
+/// The characters of the strings, with the ones that are escaped.
+const CHARS: &[char] = &['a', 'z', ' ', '"', '\'', '\\', '\n', '\t', 'é'];
+
+fn spanned<T>(data: T) -> Box<Spanned<T>> {
+    Box::new(Spanned::new(data, Span::ghost()))
+}
+
+/// The names that are declared before the place that is being generated.
+#[derive(Default)]
+struct Scope {
+    /// Types with the number of their parameters.
+    types: Vec<(Qualified, usize)>,
+
+    /// Constructors of sum types with the number of their arguments.
+    constructors: Vec<(Qualified, usize)>,
+
+    /// Record types with their fields.
+    records: Vec<(Qualified, Vec<Symbol>)>,
+
+    /// Declarations of values, that are lets and externals.
+    values: Vec<Qualified>,
+
+    /// Effects, that can be masked.
+    effects: Vec<Qualified>,
+
+    /// Operations of effects with the number of their arguments.
+    operations: Vec<(Qualified, usize)>,
+
+    /// The `Bool.True` and the `List.Cons` that the loops are resolved with.
+    loops: Option<(Qualified, Qualified)>,
+
+    /// Type variables of the type that is being declared.
+    variables: Vec<Symbol>,
+
+    /// Variables bound by patterns.
+    locals: Vec<Symbol>,
+}
+
+pub struct Gen {
+    state: u64,
+    depth: usize,
+
+    /// If the expression that is being generated ends its line, so it can be a block.
+    open: bool,
+
+    /// If the pattern that is being generated can bind variables. The alternatives of an or-pattern
+    /// don't, so they always bind the same ones.
+    binds: bool,
+
+    fresh: usize,
+    namespace: Symbol,
+    scope: Scope,
+}
+
+impl Gen {
+    /// A generator of programs for the module with a namespace, like `Test.Main` for the root
+    /// module of a project.
+    pub fn new(seed: u64, namespace: &str) -> Self {
+        Self {
+            // The state of a xorshift cannot be zero.
+            state: seed.wrapping_mul(0x9E37_79B9_7F4A_7C15) | 1,
+            depth: DEPTH,
+            open: false,
+            binds: true,
+            fresh: 0,
+            namespace: Symbol::intern(namespace),
+            scope: Scope::default(),
+        }
+    }
+
+    fn next(&mut self) -> u64 {
+        self.state ^= self.state << 13;
+        self.state ^= self.state >> 7;
+        self.state ^= self.state << 17;
+        self.state
+    }
+
+    /// A number from zero up to a bound, without it.
+    fn below(&mut self, bound: usize) -> usize {
+        (self.next() % bound as u64) as usize
+    }
+
+    fn chance(&mut self, percent: usize) -> bool {
+        self.below(100) < percent
+    }
+
+    fn pick<T: Clone>(&mut self, items: &[T]) -> Option<T> {
+        if items.is_empty() {
+            None
+        } else {
+            Some(items[self.below(items.len())].clone())
+        }
+    }
+
+    fn many<T>(&mut self, min: usize, max: usize, mut fun: impl FnMut(&mut Self) -> T) -> Vec<T> {
+        let count = min + self.below(max - min + 1);
+        (0..count).map(|_| fun(self)).collect()
+    }
+
+    /// Runs a function one level deeper, or the leaf if there are no more levels.
+    fn nested<T>(
+        &mut self,
+        leaf: impl FnOnce(&mut Self) -> T,
+        fun: impl FnOnce(&mut Self) -> T,
+    ) -> T {
+        if self.depth == 0 {
+            return leaf(self);
+        }
+
+        self.depth -= 1;
+        let result = fun(self);
+        self.depth += 1;
+        result
+    }
+
+    /// Runs a function for an expression that ends its line or not.
+    fn opened<T>(&mut self, open: bool, fun: impl FnOnce(&mut Self) -> T) -> T {
+        let before = std::mem::replace(&mut self.open, open);
+        let result = fun(self);
+        self.open = before;
+        result
+    }
+
+    /// A name that was not used before, starting with a prefix that gives its case.
+    fn name(&mut self, prefix: &str) -> Symbol {
+        self.fresh += 1;
+        Symbol::intern(&format!("{}{}", prefix, self.fresh))
+    }
+
+    fn qualified(&mut self, prefix: &str) -> Qualified {
+        Qualified {
+            path: self.namespace.clone(),
+            name: self.name(prefix),
+        }
+    }
+
+    /// A qualified name inside of the namespace of a type, like the constructors of a sum.
+    fn member(&mut self, typ: &Qualified, prefix: &str) -> Qualified {
+        Qualified {
+            path: Symbol::intern(&typ.to_string()),
+            name: self.name(prefix),
+        }
+    }
+
+    fn text(&mut self, min: usize, max: usize) -> String {
+        self.many(min, max, |this| this.pick(CHARS).unwrap())
+            .into_iter()
+            .collect()
+    }
+
+    fn visibility(&mut self) -> Visibility {
+        if self.chance(50) {
+            Visibility::Public
+        } else {
+            Visibility::Private
+        }
+    }
+
+    // Types
+
+    pub fn typ(&mut self) -> Type {
+        self.nested(Self::type_atom, |this| match this.below(6) {
+            0 => spanned(TypeKind::Arrow(PiType {
+                left: this.typ(),
+                right: this.typ(),
+            })),
+            1 => spanned(TypeKind::Tuple(this.many(2, 3, Self::typ))),
+            _ => this.type_atom(),
+        })
+    }
+
+    /// A type without arrows or tuples, that is a type applied to its arguments.
+    fn type_atom(&mut self) -> Type {
+        let variable = self.pick(&self.scope.variables.clone());
+
+        if let (Some(variable), true) = (variable, self.chance(30)) {
+            return spanned(TypeKind::TypeVariable(variable));
+        }
+
+        let Some((name, arity)) = self.pick(&self.scope.types.clone()) else {
+            return spanned(TypeKind::Unit);
+        };
+
+        let func = spanned(TypeKind::Type(name));
+
+        if arity == 0 {
+            return func;
+        }
+
+        let args = (0..arity)
+            .map(|_| self.nested(|this| this.leaf_type(), |this| this.typ()))
+            .collect();
+
+        spanned(TypeKind::Application(TypeApplication { func, args }))
+    }
+
+    /// A type that is a single name.
+    fn leaf_type(&mut self) -> Type {
+        let types = self.scope.types.clone();
+        let nullary: Vec<_> = types.into_iter().filter(|x| x.1 == 0).collect();
+
+        match self.pick(&nullary) {
+            Some((name, _)) => spanned(TypeKind::Type(name)),
+            None => spanned(TypeKind::Unit),
+        }
+    }
+
+    // Literals
+
+    fn literal(&mut self) -> Literal {
+        // The lexer has no tokens for chars, so they are not generated.
+        let kind = match self.below(3) {
+            0 => LiteralKind::String(Symbol::intern(&self.text(0, 4))),
+            1 => LiteralKind::Float(Symbol::intern(&format!("{}.5", self.below(100)))),
+            _ => LiteralKind::Integer(Symbol::intern(&self.below(1000).to_string())),
+        };
+
+        spanned(kind)
+    }
+
+    // Patterns
+
+    /// A pattern, with the variables that it binds added to the scope.
+    pub fn pattern(&mut self) -> Pattern {
+        self.nested(Self::pattern_leaf, |this| match this.below(7) {
+            0 => spanned(PatternKind::Tuple(this.many(2, 3, Self::pattern))),
+            1 => this.pattern_constructor(),
+            2 => this.pattern_or(),
+            3 => this.pattern_as(),
+            _ => this.pattern_leaf(),
+        })
+    }
+
+    /// An as-pattern, that is just the pattern inside of it where variables can't be bound.
+    fn pattern_as(&mut self) -> Pattern {
+        let pattern = self.pattern();
+
+        if !self.binds {
+            return pattern;
+        }
+
+        let name = self.name("x");
+        self.scope.locals.push(name.clone());
+
+        spanned(PatternKind::As(PatAs { name, pattern }))
+    }
+
+    fn pattern_or(&mut self) -> Pattern {
+        let binds = std::mem::replace(&mut self.binds, false);
+        let left = self.pattern();
+        let right = self.pattern();
+        self.binds = binds;
+
+        spanned(PatternKind::Or(PatOr { left, right }))
+    }
+
+    fn pattern_leaf(&mut self) -> Pattern {
+        match self.below(5) {
+            0 => spanned(PatternKind::Wildcard),
+            1 => spanned(PatternKind::Literal(self.literal())),
+            2 => self.pattern_range(),
+            _ => self.pattern_variable(),
+        }
+    }
+
+    /// A range of integers, that can start or end at negative ones.
+    fn pattern_range(&mut self) -> Pattern {
+        let start = self.below(200) as i64 - 100;
+        let end = start + self.below(100) as i64;
+
+        let integer =
+            |number: i64| spanned(LiteralKind::Integer(Symbol::intern(&number.to_string())));
+
+        spanned(PatternKind::Range(PatRange {
+            start: integer(start),
+            end: integer(end),
+        }))
+    }
+
+    fn pattern_variable(&mut self) -> Pattern {
+        if !self.binds {
+            return spanned(PatternKind::Wildcard);
+        }
+
+        let name = self.name("x");
+        self.scope.locals.push(name.clone());
+        spanned(PatternKind::Variable(name))
+    }
+
+    fn pattern_constructor(&mut self) -> Pattern {
+        let Some((func, arity)) = self.pick(&self.scope.constructors.clone()) else {
+            return self.pattern_variable();
+        };
+
+        let args = (0..arity).map(|_| self.pattern()).collect();
+        spanned(PatternKind::Application(PatApplication { func, args }))
+    }
+
+    // Expressions
+
+    /// Runs a function with the variables that it binds removed from the scope after it.
+    fn scoped<T>(&mut self, fun: impl FnOnce(&mut Self) -> T) -> T {
+        let locals = self.scope.locals.len();
+        let result = fun(self);
+        self.scope.locals.truncate(locals);
+        result
+    }
+
+    /// An expression that doesn't end its line.
+    pub fn expr(&mut self) -> Expr {
+        self.opened(false, Self::expr_kind)
+    }
+
+    /// An expression that ends its line, like the body of a declaration.
+    pub fn body(&mut self) -> Expr {
+        self.opened(true, Self::expr_kind)
+    }
+
+    fn expr_kind(&mut self) -> Expr {
+        self.nested(Self::expr_atom, |this| match this.below(20) {
+            0 => this.scoped(|this| {
+                let param = this.pattern();
+                let body = this.expr_kind();
+                spanned(ExprKind::Lambda(LambdaExpr { param, body }))
+            }),
+            1 => {
+                let body = this.expr();
+                this.scoped(|this| {
+                    let pattern = this.pattern();
+                    let value = this.expr_kind();
+                    spanned(ExprKind::Let(LetExpr {
+                        pattern,
+                        body,
+                        value,
+                    }))
+                })
+            }
+            2 if this.open => {
+                let scrutinee = this.many(1, 2, Self::expr);
+                let arms = this.many(1, 3, |this| this.arm(scrutinee.len()));
+                spanned(ExprKind::When(WhenExpr { scrutinee, arms }))
+            }
+            3 if this.open => this.scoped(|this| {
+                let sttms = this.many(1, 4, Self::statement);
+                spanned(ExprKind::Do(Block { sttms }))
+            }),
+            4 => spanned(ExprKind::Tuple(Tuple {
+                exprs: this.many(2, 3, Self::expr),
+            })),
+            5 => spanned(ExprKind::Annotation(AnnotationExpr {
+                expr: this.expr(),
+                typ: this.typ(),
+            })),
+            6 => this.record(),
+            7..=9 => this.application(),
+            10 => this.let_group(),
+            11 if this.open => {
+                let arms = this.many(1, 3, |this| this.arm(1));
+                spanned(ExprKind::Cases(CasesExpr { arms }))
+            }
+            12 if this.open => this.handler(),
+            13 => this.projection(),
+            14 => this.record_update(),
+            15 => this.perform(),
+            16 => this.mask(),
+            17 if this.open => this.loop_(),
+            _ => this.expr_atom(),
+        })
+    }
+
+    /// A handler whose handled expression ends its line, because the `with` starts the next one.
+    /// The handler ends its line too when there's no `finally` after it, and `cases` always does.
+    fn handler(&mut self) -> Expr {
+        let expr = self.body();
+        let finally = self.chance(30);
+
+        let handler = if self.chance(70) {
+            let arms = self.many(1, 3, Self::handler_arm);
+            spanned(ExprKind::Cases(CasesExpr { arms }))
+        } else {
+            self.opened(!finally, Self::expr_kind)
+        };
+
+        let finally = if finally { Some(self.body()) } else { None };
+
+        spanned(ExprKind::Handler(HandlerExpr {
+            name: None,
+            expr,
+            handler,
+            finally,
+        }))
+    }
+
+    /// An arm of the cases of a handler, that matches an operation, the final value or any value.
+    /// The guards of operations can't use the variables around them, so they are not generated.
+    fn handler_arm(&mut self) -> PatternArm {
+        self.scoped(|this| {
+            let operation = this.pick(&this.scope.operations.clone());
+
+            let pattern = match (operation, this.below(3)) {
+                (Some((func, arity)), 0 | 1) => {
+                    let args = (0..arity).map(|_| this.pattern()).collect();
+
+                    let cont = if this.chance(70) {
+                        let cont = this.name("k");
+                        this.scope.locals.push(cont.clone());
+                        Some(cont)
+                    } else {
+                        None
+                    };
+
+                    spanned(PatternKind::Effect(PatEffect { func, args, cont }))
+                }
+                (_, 2) => spanned(PatternKind::Return(this.pattern())),
+                _ => this.pattern(),
+            };
+
+            PatternArm {
+                patterns: vec![pattern],
+                expr: this.body(),
+                guard: None,
+            }
+        })
+    }
+
+    fn projection(&mut self) -> Expr {
+        let Some((_, fields)) = self.pick(&self.scope.records.clone()) else {
+            return self.expr_atom();
+        };
+
+        spanned(ExprKind::Projection(ProjectionExpr {
+            expr: self.expr(),
+            field: self.pick(&fields).unwrap(),
+            candidates: Vec::new(),
+        }))
+    }
+
+    fn record_update(&mut self) -> Expr {
+        let Some((_, fields)) = self.pick(&self.scope.records.clone()) else {
+            return self.expr_atom();
+        };
+
+        let mut updated = Vec::new();
+
+        for field in fields {
+            if self.chance(50) {
+                updated.push((Span::ghost(), field, self.expr()));
+            }
+        }
+
+        if updated.is_empty() {
+            return self.expr_atom();
+        }
+
+        spanned(ExprKind::RecordUpdate(RecordUpdate {
+            expr: self.expr(),
+            fields: updated,
+        }))
+    }
+
+    fn perform(&mut self) -> Expr {
+        let Some((operation, arity)) = self.pick(&self.scope.operations.clone()) else {
+            return self.expr_atom();
+        };
+
+        spanned(ExprKind::Perform(PerformExpr {
+            operation,
+            args: (0..arity).map(|_| self.expr()).collect(),
+        }))
+    }
+
+    fn mask(&mut self) -> Expr {
+        let Some(effect) = self.pick(&self.scope.effects.clone()) else {
+            return self.expr_atom();
+        };
+
+        spanned(ExprKind::Mask(MaskExpr {
+            effect,
+            expr: self.expr_kind(),
+        }))
+    }
+
+    /// A `while` or a `for`, as the function that calls itself that the resolver turns them into.
+    fn loop_(&mut self) -> Expr {
+        let Some((true_, cons)) = self.scope.loops.clone() else {
+            return self.expr_atom();
+        };
+
+        let variable = |name: &str| spanned(PatternKind::Variable(Symbol::intern(name)));
+        let value = |name: &str| spanned(ExprKind::Variable(Symbol::intern(name)));
+        let unit = || spanned(ExprKind::Literal(spanned(LiteralKind::Unit)));
+        let sttm = |kind| Spanned::new(kind, Span::ghost());
+
+        if self.chance(50) {
+            let condition = self.expr();
+            let body = self.scoped(|this| this.many(1, 3, Self::statement));
+
+            let arm = PatternKind::Application(PatApplication {
+                func: true_,
+                args: Vec::new(),
+            });
+
+            let body = SttmKind::Expr(spanned(ExprKind::Do(Block { sttms: body })));
+
+            loop_function(
+                spanned(PatternKind::Wildcard),
+                condition,
+                spanned(arm),
+                vec![sttm(body)],
+                unit(),
+                unit(),
+            )
+        } else {
+            let list = self.expr();
+
+            let statements = self.scoped(|this| {
+                let pat = this.pattern();
+                let body = this.many(1, 3, Self::statement);
+
+                let bind = SttmKind::Let(LetSttm {
+                    pat,
+                    expr: value("item$"),
+                });
+
+                let body = SttmKind::Expr(spanned(ExprKind::Do(Block { sttms: body })));
+                vec![sttm(bind), sttm(body)]
+            });
+
+            let arm = PatternKind::Application(PatApplication {
+                func: cons,
+                args: vec![variable("item$"), variable("rest$")],
+            });
+
+            loop_function(
+                variable("list$"),
+                value("list$"),
+                spanned(arm),
+                statements,
+                value("rest$"),
+                list,
+            )
+        }
+    }
+
+    /// A let group whose bindings are functions, that can call each other.
+    fn let_group(&mut self) -> Expr {
+        self.scoped(|this| {
+            let names = this.many(1, 3, |this| this.name("f"));
+            this.scope.locals.extend(names.iter().cloned());
+
+            let bindings = names
+                .into_iter()
+                .map(|name| {
+                    let body = this.scoped(|this| {
+                        let param = this.pattern();
+                        let body = this.expr();
+                        spanned(ExprKind::Lambda(LambdaExpr { param, body }))
+                    });
+
+                    LetBinding { name, body }
+                })
+                .collect();
+
+            let value = this.expr_kind();
+            spanned(ExprKind::LetGroup(LetGroupExpr { bindings, value }))
+        })
+    }
+
+    fn expr_atom(&mut self) -> Expr {
+        match self.below(4) {
+            0 => {
+                if let Some(local) = self.pick(&self.scope.locals.clone()) {
+                    return spanned(ExprKind::Variable(local));
+                }
+            }
+            1 => {
+                if let Some(value) = self.pick(&self.scope.values.clone()) {
+                    return spanned(ExprKind::Function(value));
+                }
+            }
+            2 => {
+                if let Some((name, _)) = self.pick(&self.scope.constructors.clone()) {
+                    return spanned(ExprKind::Constructor(name));
+                }
+            }
+            _ => (),
+        }
+
+        spanned(ExprKind::Literal(self.literal()))
+    }
+
+    fn application(&mut self) -> Expr {
+        let func = self.expr_atom();
+
+        if let ExprKind::Literal(_) = func.data {
+            return func;
+        }
+
+        spanned(ExprKind::Application(ApplicationExpr {
+            app: AppKind::Normal,
+            func,
+            args: self.many(1, 3, Self::expr),
+        }))
+    }
+
+    fn record(&mut self) -> Expr {
+        let Some((name, fields)) = self.pick(&self.scope.records.clone()) else {
+            return self.expr_atom();
+        };
+
+        let fields = fields
+            .into_iter()
+            .map(|field| (Span::ghost(), field, self.expr()))
+            .collect();
+
+        spanned(ExprKind::RecordInstance(RecordInstance { name, fields }))
+    }
+
+    fn arm(&mut self, patterns: usize) -> PatternArm {
+        self.scoped(|this| {
+            let patterns = (0..patterns).map(|_| this.pattern()).collect();
+
+            let guard = if this.chance(20) {
+                Some(this.expr())
+            } else {
+                None
+            };
+
+            PatternArm {
+                patterns,
+                expr: this.body(),
+                guard,
+            }
+        })
+    }
+
+    /// A statement of a block. The variables of a `let` are in the scope of the ones after it.
+    fn statement(&mut self) -> Sttm {
+        let kind = if self.chance(40) {
+            let expr = self.body();
+            let pat = self.pattern();
+            SttmKind::Let(LetSttm { pat, expr })
+        } else {
+            let expr = self.body();
+
+            // A `let` is put inside of parenthesis by the printer, so it cannot end with a block.
+            match expr.data {
+                ExprKind::Let(_) | ExprKind::LetGroup(_) => SttmKind::Expr(self.expr()),
+                _ => SttmKind::Expr(expr),
+            }
+        };
+
+        Spanned::new(kind, Span::ghost())
+    }
+
+    // Declarations
+
+    fn type_binders(&mut self, count: usize) -> Vec<TypeBinder> {
+        self.scope.variables = (0..count).map(|_| self.name("t")).collect();
+
+        let variables = self.scope.variables.clone();
+        variables.into_iter().map(TypeBinder::Implicit).collect()
+    }
+
+    /// A type that is added to the scope before its definition, so it can refer to itself.
+    fn type_decl(&mut self) -> TypeDecl {
+        let name = self.qualified("T");
+        let arity = self.below(3);
+        let binders = self.type_binders(arity);
+
+        self.scope.types.push((name.clone(), arity));
+
+        let def = match self.below(4) {
+            0 => TypeDef::Abstract,
+            1 => {
+                let fields: Vec<_> = self.many(1, 3, |this| {
+                    let field = this.member(&name, "f");
+                    (field, this.typ(), this.visibility())
+                });
+
+                let names = fields.iter().map(|x| x.0.name.clone()).collect();
+                self.scope.records.push((name.clone(), names));
+
+                TypeDef::Record(RecordDecl { fields })
+            }
+            _ => {
+                let constructors = self.many(1, 3, |this| {
+                    let constructor = this.member(&name, "C");
+                    let args = this.many(0, 2, Self::typ);
+                    this.scope
+                        .constructors
+                        .push((constructor.clone(), args.len()));
+
+                    Constructor {
+                        name: constructor,
+                        args,
+                        typ: None,
+                        attributes: Default::default(),
+                    }
+                });
+
+                TypeDef::Sum(SumDecl { constructors })
+            }
+        };
+
+        // Only the types with one constructor with one argument can be newtypes.
+        let wraps = match &def {
+            TypeDef::Sum(sum) => matches!(sum.constructors.as_slice(), [x] if x.args.len() == 1),
+            _ => false,
+        };
+        let newtype = wraps && self.below(2) == 0;
+
+        self.scope.variables.clear();
+
+        TypeDecl {
+            visibility: self.visibility(),
+            namespace: Symbol::intern(&name.to_string()),
+            name,
+            binders,
+            def,
+            attributes: Default::default(),
+            newtype,
+        }
+    }
+
+    /// An effect whose operations are added to the scope after it, so they can't be used in the
+    /// types of each other.
+    fn effect_decl(&mut self) -> EffectDecl {
+        let name = self.qualified("E");
+
+        let fields: Vec<_> = self.many(1, 3, |this| {
+            let (kind, resumption) = match this.below(3) {
+                0 => (OperationKind::Fun, Resumption::Many),
+                1 => (OperationKind::Ctl, Resumption::Once),
+                _ => (OperationKind::Ctl, Resumption::Many),
+            };
+
+            EffectField {
+                name: this.member(&name, "op"),
+                visibility: this.visibility(),
+                kind,
+                resumption,
+                args: this.many(1, 2, Self::typ),
+                ret: this.typ(),
+                default: None,
+            }
+        });
+
+        let operations = fields.iter().map(|x| (x.name.clone(), x.args.len()));
+        self.scope.operations.extend(operations);
+        self.scope.effects.push(name.clone());
+
+        EffectDecl {
+            visibility: self.visibility(),
+            namespace: Symbol::intern(&name.to_string()),
+            name,
+            binders: Vec::new(),
+            fields,
+            span: Span::ghost(),
+            attributes: Default::default(),
+        }
+    }
+
+    fn external_decl(&mut self) -> ExtDecl {
+        let name = self.qualified("e");
+        self.scope.values.push(name.clone());
+
+        ExtDecl {
+            visibility: self.visibility(),
+            namespace: Symbol::intern(&name.to_string()),
+            name,
+            convention: None,
+            typ: self.typ(),
+            effect: None,
+            ret: Symbol::intern(&self.text(1, 3)),
+            attributes: Default::default(),
+        }
+    }
+
+    /// A let whose name was added to the scope before, so the lets can refer to each other.
+    fn let_decl(&mut self, name: Qualified) -> LetDecl {
+        self.scoped(|this| {
+            let binders = this.many(0, 2, |this| {
+                let typ = this.typ();
+                let pat = this.pattern();
+                LetBinder::Param(Binder { pat, typ })
+            });
+
+            let ret = if this.chance(70) {
+                Some(this.typ())
+            } else {
+                None
+            };
+
+            let body = if this.chance(30) {
+                let patterns = 1 + this.below(2);
+                this.many(1, 3, |this| this.arm(patterns))
+            } else {
+                vec![PatternArm {
+                    patterns: Vec::new(),
+                    expr: this.body(),
+                    guard: None,
+                }]
+            };
+
+            LetDecl {
+                signature: LetSignature {
+                    span: Span::ghost(),
+                    visibility: this.visibility(),
+                    name,
+                    binders,
+                    ret,
+                },
+                body,
+                constant: None,
+                declaration: Span::ghost(),
+                attributes: Default::default(),
+            }
+        })
+    }
+
+    fn test_decl(&mut self) -> TestDecl {
+        TestDecl {
+            name: self.qualified("test"),
+            description: Symbol::intern(&self.text(0, 6)),
+            body: self.body(),
+            span: Span::ghost(),
+        }
+    }
+
+    /// A module declared inside of the program, with the declarations of the program before it in
+    /// its scope. Its own declarations are not in the scope of the program.
+    fn module_decl(&mut self) -> ModuleDecl {
+        let name = self.name("M");
+        let namespace = Symbol::intern(&format!("{}.{}", self.namespace.get(), name.get()));
+
+        let outer = std::mem::replace(&mut self.namespace, namespace);
+
+        let types = self.scope.types.len();
+        let constructors = self.scope.constructors.len();
+        let records = self.scope.records.len();
+        let values = self.scope.values.len();
+        let effects = self.scope.effects.len();
+        let operations = self.scope.operations.len();
+
+        let decls = self.declarations(false);
+
+        self.scope.types.truncate(types);
+        self.scope.constructors.truncate(constructors);
+        self.scope.records.truncate(records);
+        self.scope.values.truncate(values);
+        self.scope.effects.truncate(effects);
+        self.scope.operations.truncate(operations);
+
+        self.namespace = outer;
+
+        ModuleDecl {
+            visibility: self.visibility(),
+            name,
+            decls: Some(decls),
+            attributes: Default::default(),
+        }
+    }
+
+    fn declarations(&mut self, modules: bool) -> Program {
+        let types = self.many(1, 3, Self::type_decl);
+        let effects = self.many(0, 2, Self::effect_decl);
+        let externals = self.many(0, 2, Self::external_decl);
+
+        let names = self.many(1, 4, |this| this.qualified("v"));
+        self.scope.values.extend(names.iter().cloned());
+
+        let lets = names.into_iter().map(|x| self.let_decl(x)).collect();
+        let tests = self.many(0, 2, Self::test_decl);
+
+        let modules = if modules {
+            self.many(0, 1, Self::module_decl)
+        } else {
+            Vec::new()
+        };
+
+        Program {
+            types,
+            effects,
+            externals,
+            lets,
+            tests,
+            modules,
+            ..Program::default()
+        }
+    }
+
+    /// The `Bool` and the `List` of the prelude, that the loops need.
+    fn prelude(&mut self) -> Vec<TypeDecl> {
+        let mut types = Vec::new();
+
+        let bool_ = (
+            "Bool",
+            Vec::new(),
+            vec![("True", Vec::new()), ("False", Vec::new())],
+        );
+
+        let list = Qualified {
+            path: self.namespace.clone(),
+            name: Symbol::intern("List"),
+        };
+
+        let a = Symbol::intern("a");
+        let variable = || spanned(TypeKind::TypeVariable(a.clone()));
+        let applied = spanned(TypeKind::Application(TypeApplication {
+            func: spanned(TypeKind::Type(list)),
+            args: vec![variable()],
+        }));
+
+        let cons = vec![("Cons", vec![variable(), applied]), ("Nil", Vec::new())];
+        let list = ("List", vec![a.clone()], cons);
+
+        for (name, binders, constructors) in [bool_, list] {
+            let name = Qualified {
+                path: self.namespace.clone(),
+                name: Symbol::intern(name),
+            };
+
+            let namespace = Symbol::intern(&name.to_string());
+
+            let constructors: Vec<_> = constructors
+                .into_iter()
+                .map(|(constructor, args)| Constructor {
+                    name: Qualified {
+                        path: namespace.clone(),
+                        name: Symbol::intern(constructor),
+                    },
+                    args,
+                    typ: None,
+                    attributes: Default::default(),
+                })
+                .collect();
+
+            self.scope.types.push((name.clone(), binders.len()));
+
+            for constructor in &constructors {
+                let arity = constructor.args.len();
+                self.scope
+                    .constructors
+                    .push((constructor.name.clone(), arity));
+            }
+
+            types.push(TypeDecl {
+                visibility: Visibility::Public,
+                name,
+                namespace,
+                binders: binders.into_iter().map(TypeBinder::Implicit).collect(),
+                def: TypeDef::Sum(SumDecl { constructors }),
+                attributes: Default::default(),
+                newtype: false,
+            });
+        }
+
+        let constructor = |types: &[TypeDecl], index: usize| match &types[index].def {
+            TypeDef::Sum(sum) => sum.constructors[0].name.clone(),
+            _ => unreachable!(),
+        };
+
+        self.scope.loops = Some((constructor(&types, 0), constructor(&types, 1)));
+
+        types
+    }
+
+    /// A program with types, effects, externals, lets, tests and modules, in the order that the
+    /// printer puts them. The first types are the ones of the [Gen::prelude].
+    pub fn program(&mut self) -> Program {
+        let prelude = self.prelude();
+        let mut program = self.declarations(true);
+        program.types.splice(0..0, prelude);
+        program
+    }
+}
+
+/// The function that calls itself of a loop, like the resolver writes it.
+///
+/// ```text
+/// let loop$ param = when scrutinee is
+///   arm => do { statements ; loop$ next }
+///   _   => ()
+/// in loop$ start
+/// ```
+fn loop_function(
+    param: Pattern,
+    scrutinee: Expr,
+    arm: Pattern,
+    mut statements: Vec<Sttm>,
+    next: Expr,
+    start: Expr,
+) -> Expr {
+    let name = Symbol::intern("loop$");
+
+    let call = |arg| {
+        spanned(ExprKind::Application(ApplicationExpr {
+            app: AppKind::Normal,
+            func: spanned(ExprKind::Variable(name.clone())),
+            args: vec![arg],
+        }))
+    };
+
+    statements.push(Spanned::new(SttmKind::Expr(call(next)), Span::ghost()));
+
+    let unit = spanned(ExprKind::Literal(spanned(LiteralKind::Unit)));
+
+    let when = WhenExpr {
+        scrutinee: vec![scrutinee],
+        arms: vec![
+            PatternArm {
+                patterns: vec![arm],
+                expr: spanned(ExprKind::Do(Block { sttms: statements })),
+                guard: None,
+            },
+            PatternArm {
+                patterns: vec![spanned(PatternKind::Wildcard)],
+                expr: unit,
+                guard: None,
+            },
+        ],
+    };
+
+    let function = ExprKind::Lambda(LambdaExpr {
+        param,
+        body: spanned(ExprKind::When(when)),
+    });
+
+    spanned(ExprKind::LetGroup(LetGroupExpr {
+        bindings: vec![LetBinding {
+            name: name.clone(),
+            body: spanned(function),
+        }],
+        value: call(start),
+    }))
+}
@@ -0,0 +1,380 @@
+//! Snapshot tests of the compiler. A [Project] is a crate whose sources are in memory, and each
+//! [Stage] of its compilation is rendered as text that can be compared with a golden file checked
+//! in next to the test. The golden files that don't exist are created, and all of them are written
+//! again instead of compared when the variable [BLESS] is set, so a change to the output of the
+//! compiler is reviewed as a change to the golden files.
+//!
+//! The parser and the printer are also tested against each other, with the random programs of
+//! [generate] that are printed, parsed and resolved by [round_trip].
+
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use vulpi_build::{cfg::Target, memory::MemoryFileSystem, query::Database};
+use vulpi_intern::Symbol;
+use vulpi_location::{Encoding, LineIndex, Sources};
+use vulpi_pretty::{Pretty, WIDTH};
+use vulpi_report::{Detached, IntoDiagnostic, Severity};
+use vulpi_show::Show;
+use vulpi_syntax::{
+    elaborated,
+    r#abstract::{self, Qualified},
+};
+use vulpi_typer::{real::Real, Type};
+use vulpi_vfs::FileSystem;
+use vulpi_vm::machine::Machine;
+
+pub mod generate;
+
+/// The variable of the environment that makes the golden files be written instead of compared.
+pub const BLESS: &str = "VULPI_BLESS";
+
+/// The file of a directory of snapshots that is a module of each project, instead of a test.
+pub const PRELUDE: &str = "Prelude.vp";
+
+const EXTENSION: &str = "vp";
+
+/// The outputs of the compiler that can be compared. The trees are rendered by `vulpi-show` and
+/// are the same as the ones that `vulpi build --emit` prints.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Stage {
+    /// The tokens of the root module.
+    Tokens,
+
+    /// The concrete tree of the root module.
+    Cst,
+
+    /// The abstract tree of the root module, with the names resolved.
+    Ast,
+
+    /// The abstract tree of the root module, printed as source by the pretty printer.
+    Source,
+
+    /// The elaborated program of the root module, with the types inferred.
+    Typed,
+
+    /// The core language of the crate, without optimizations.
+    Core,
+
+    /// The diagnostics of the crate, one in each line.
+    Diagnostics,
+
+    /// What the `main` of the root module prints when it runs in the virtual machine, followed by
+    /// the error and its stack trace if it stops with one, or the diagnostics if the crate has
+    /// errors.
+    Output,
+}
+
+impl Stage {
+    pub const ALL: [Stage; 8] = [
+        Stage::Tokens,
+        Stage::Cst,
+        Stage::Ast,
+        Stage::Source,
+        Stage::Typed,
+        Stage::Core,
+        Stage::Diagnostics,
+        Stage::Output,
+    ];
+
+    /// The name of the stage, that is the extension of its golden files.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Stage::Tokens => "tokens",
+            Stage::Cst => "cst",
+            Stage::Ast => "ast",
+            Stage::Source => "source",
+            Stage::Typed => "typed",
+            Stage::Core => "core",
+            Stage::Diagnostics => "diagnostics",
+            Stage::Output => "output",
+        }
+    }
+}
+
+/// A crate named `Test` whose root module is `Main.vp`.
+#[derive(Clone)]
+pub struct Project {
+    files: Vec<(PathBuf, String)>,
+}
+
+impl Project {
+    pub fn new(main: &str) -> Self {
+        Self {
+            files: vec![(PathBuf::from("Main.vp"), main.to_string())],
+        }
+    }
+
+    /// Adds a module at a path relative to the root module, like `Data/List.vp` for `Data.List`.
+    pub fn module(mut self, path: impl Into<PathBuf>, source: &str) -> Self {
+        self.files.push((path.into(), source.to_string()));
+        self
+    }
+
+    fn database(&self) -> Database<MemoryFileSystem> {
+        let name = Symbol::intern("Test");
+        let mut fs = MemoryFileSystem::new(name.clone());
+
+        for (path, source) in &self.files {
+            fs.insert(path.clone(), source.clone());
+        }
+
+        Database::new(fs, name, PathBuf::from("Main.vp"), Target::Vm)
+    }
+
+    /// Compiles the crate up to a stage and renders it. Each call compiles the crate again.
+    pub fn render(&self, stage: Stage) -> String {
+        let mut db = self.database();
+        let root = db.root_module();
+        let file = db.file(&root);
+
+        match stage {
+            Stage::Tokens => db.tokens(&file).show().to_string(),
+            Stage::Cst => db.cst(&file).show().to_string(),
+            Stage::Ast => program(&mut db).show().to_string(),
+            Stage::Source => program(&mut db).to_source(WIDTH),
+            Stage::Typed => db.typed(&root).show().to_string(),
+            Stage::Core => vulpi_core::lower::lower(&programs(&mut db))
+                .show()
+                .to_string(),
+            Stage::Diagnostics => diagnostics(&mut db),
+            Stage::Output => output(&mut db),
+        }
+    }
+}
+
+/// The abstract program of the root module.
+fn program(db: &mut Database<MemoryFileSystem>) -> r#abstract::Program {
+    let root = db.root_module();
+    let resolved = db.resolved(&root);
+
+    let (_, program) = resolved
+        .programs
+        .iter()
+        .find(|(path, _)| *path == root)
+        .expect("the root module is resolved");
+
+    program.clone()
+}
+
+fn programs(db: &mut Database<MemoryFileSystem>) -> Vec<elaborated::Program<Type<Real>>> {
+    db.modules()
+        .iter()
+        .map(|module| (*db.typed(module)).clone())
+        .collect()
+}
+
+fn diagnostics(db: &mut Database<MemoryFileSystem>) -> String {
+    let diagnostics = db.diagnostics();
+    lines(db, &diagnostics)
+}
+
+/// The diagnostics as `path:line:column: severity[code]: message`, sorted by their positions.
+fn lines(db: &Database<MemoryFileSystem>, diagnostics: &[Detached]) -> String {
+    let mut lines: Vec<_> = diagnostics
+        .iter()
+        .map(|diagnostic| {
+            let span = diagnostic.location();
+            let path = db.fs.path(span.file).cloned().unwrap_or_default();
+            let source = db.fs.read(span.file).unwrap_or_default();
+
+            let position = LineIndex::new(&source)
+                .line_col(&span.start, Encoding::Utf32)
+                .map(|x| (x.line + 1, x.column + 1))
+                .unwrap_or_default();
+
+            let severity = match diagnostic.severity() {
+                Severity::Error => "error",
+                Severity::Warning => "warning",
+                Severity::Note => "note",
+                Severity::Help => "help",
+            };
+
+            let code = diagnostic
+                .code()
+                .map(|x| format!("[E{:04}]", x))
+                .unwrap_or_default();
+
+            let line = format!(
+                "{}:{}:{}: {}{}: {}",
+                path.display(),
+                position.0,
+                position.1,
+                severity,
+                code,
+                diagnostic.message().plain()
+            );
+
+            (path, span.start.0, line)
+        })
+        .collect();
+
+    lines.sort();
+    lines.into_iter().map(|x| x.2 + "\n").collect()
+}
+
+fn output(db: &mut Database<MemoryFileSystem>) -> String {
+    let has_errors = db
+        .diagnostics()
+        .iter()
+        .any(|x| x.severity() == Severity::Error);
+
+    if has_errors {
+        return diagnostics(db);
+    }
+
+    let entry = Qualified {
+        path: db.root_module().symbol(),
+        name: Symbol::intern("main"),
+    };
+
+    let mut core = vulpi_core::lower::lower(&programs(db));
+    vulpi_core::init::sort(&mut core, Some(&entry));
+    let bytecode = vulpi_vm::compile::compile(&core);
+
+    let mut output = Vec::new();
+
+    let (result, trace) = {
+        let mut machine = Machine::with_output(&bytecode, Box::new(&mut output));
+        let result = machine.initialize(&entry).and_then(|_| machine.run(&entry));
+        (result, machine.trace().to_vec())
+    };
+
+    let mut output = String::from_utf8_lossy(&output).to_string();
+
+    if let Err(err) = result {
+        let mut sources = Sources::default();
+
+        for location in &trace {
+            let file = location.span.file;
+
+            if let (Ok(path), Ok(source)) = (db.fs.path(file), db.fs.read(file)) {
+                sources.insert(file, path.display().to_string(), source);
+            }
+        }
+
+        output.push_str(&format!("[Error]: {}\n", err));
+        output.push_str(&vulpi_vm::trace::render(&trace, &sources));
+    }
+
+    output
+}
+
+/// The namespace of the root module of a [Project], that the programs to [round_trip] are in.
+pub const NAMESPACE: &str = "Test.Main";
+
+/// Prints a program of the root module, and parses and resolves the source again. The program that
+/// is resolved must be printed as the same source, without diagnostics of the resolver.
+pub fn round_trip(generated: &r#abstract::Program) -> Result<(), String> {
+    let source = generated.to_source(WIDTH);
+    let mut db = Project::new(&source).database();
+
+    let diagnostics = db.resolution_diagnostics();
+
+    if !diagnostics.is_empty() {
+        return Err(format!(
+            "the source doesn't resolve:\n{}\n{}",
+            lines(&db, &diagnostics),
+            source
+        ));
+    }
+
+    let printed = program(&mut db).to_source(WIDTH);
+
+    if printed == source {
+        Ok(())
+    } else {
+        Err(format!(
+            "the source is printed differently after it's parsed\n\nexpected:\n{}\n\ngot:\n{}",
+            source, printed
+        ))
+    }
+}
+
+/// Compares a text with a golden file, or writes it to the file if it doesn't exist or if the
+/// golden files are blessed. Panics with both of them if they are not the same.
+pub fn assert_snapshot(golden: impl AsRef<Path>, actual: &str) {
+    if let Err(mismatch) = snapshot(golden.as_ref(), actual) {
+        panic!("{}", mismatch);
+    }
+}
+
+fn snapshot(golden: &Path, actual: &str) -> Result<(), String> {
+    let blessed = std::env::var_os(BLESS).is_some();
+
+    match fs::read_to_string(golden) {
+        Ok(expected) if !blessed => {
+            if expected == actual {
+                Ok(())
+            } else {
+                Err(mismatch(golden, &expected, actual))
+            }
+        }
+        _ => fs::write(golden, actual)
+            .map_err(|err| format!("cannot write '{}': {}", golden.display(), err)),
+    }
+}
+
+fn mismatch(golden: &Path, expected: &str, actual: &str) -> String {
+    let line = expected
+        .lines()
+        .zip(actual.lines())
+        .take_while(|(x, y)| x == y)
+        .count();
+
+    format!(
+        "the snapshot '{}' differs at line {}, set {} to update it\n\nexpected:\n{}\n\ngot:\n{}",
+        golden.display(),
+        line + 1,
+        BLESS,
+        expected,
+        actual
+    )
+}
+
+/// Renders the stages of each file of a directory as a project and compares them with the golden
+/// files next to it, named after the file and the stage, like `records.typed` for `records.vp`.
+/// The [PRELUDE] of the directory is a module of each project. All of the files are compared
+/// before it panics, so they are all written when the golden files are blessed.
+pub fn assert_directory(directory: impl AsRef<Path>, stages: &[Stage]) {
+    let directory = directory.as_ref();
+
+    let entries = fs::read_dir(directory)
+        .unwrap_or_else(|err| panic!("cannot read '{}': {}", directory.display(), err));
+
+    let mut files: Vec<_> = entries
+        .flatten()
+        .map(|entry| entry.path())
+        .filter(|path| path.extension().is_some_and(|x| x == EXTENSION))
+        .collect();
+
+    files.sort();
+
+    let prelude = directory.join(PRELUDE);
+    let prelude = fs::read_to_string(&prelude).ok();
+
+    let mut mismatches = Vec::new();
+
+    for file in files.iter().filter(|x| x.file_name().unwrap() != PRELUDE) {
+        let source = fs::read_to_string(file).unwrap();
+        let mut project = Project::new(&source);
+
+        if let Some(prelude) = &prelude {
+            project = project.module(PRELUDE, prelude);
+        }
+
+        for stage in stages {
+            let golden = file.with_extension(stage.name());
+
+            if let Err(mismatch) = snapshot(&golden, &project.render(*stage)) {
+                mismatches.push(mismatch);
+            }
+        }
+    }
+
+    if !mismatches.is_empty() {
+        panic!("{}", mismatches.join("\n\n"));
+    }
+}
@@ -0,0 +1,9 @@
+//! The fixtures that the tests of the crate share.
+
+use vulpi_testing::{Project, PRELUDE};
+
+/// A crate with the [PRELUDE] of the snapshots, that declares the numbers, the strings and the
+/// functions that print them.
+pub fn with_prelude(main: &str) -> Project {
+    Project::new(main).module(PRELUDE, include_str!("../snapshots/Prelude.vp"))
+}
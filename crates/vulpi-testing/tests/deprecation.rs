@@ -0,0 +1,49 @@
+mod common;
+
+use vulpi_testing::Stage;
+
+const SHAPES: &str = r#"use Prelude
+
+#[deprecated "use Shape"]
+pub type Figure = | Figure Int
+
+pub type Shape =
+  | #[deprecated "use Square"] Box Int
+  | Square Int
+
+pub let side (shape : Shape) : Int =
+  when shape is
+    Shape.Box n => n
+    Shape.Square n => n
+
+#[deprecated]
+pub let unit : Shape = Shape.Box 1
+"#;
+
+const MAIN: &str = r#"use Prelude
+use Shapes
+
+#[deprecated "use Shapes.side"]
+let width (shape : Shapes.Shape) : Int = Shapes.side shape
+
+#[allow(deprecated)]
+let legacy (figure : Shapes.Figure) : Shapes.Shape = Shapes.Shape.Box 2
+
+let main (x : ()) : () = do
+  printInt (width (Shapes.Shape.Box 1))
+  printInt (Shapes.side Shapes.unit)
+  printInt (Shapes.side (legacy (Shapes.Figure.Figure 0)))
+"#;
+
+#[test]
+fn deprecated_definitions_are_warned_outside_of_their_modules() {
+    let project = common::with_prelude(MAIN).module("Shapes.vp", SHAPES);
+
+    let expected = concat!(
+        "Main.vp:11:33: warning[E0215]: 'Box' is deprecated\n",
+        "Main.vp:12:32: warning[E0215]: 'unit' is deprecated\n",
+    );
+
+    assert_eq!(project.render(Stage::Diagnostics), expected);
+    assert_eq!(project.render(Stage::Output), "1\n1\n2\n");
+}
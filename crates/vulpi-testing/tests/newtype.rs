@@ -0,0 +1,60 @@
+mod common;
+
+use vulpi_testing::{Project, Stage};
+
+const DISTANCE: &str = r#"use Prelude
+
+pub newtype Meters = | Meters Int
+
+pub newtype Pair a = | Pair a
+
+pub let meters (n : Int) : Meters = Meters.wrap n
+
+pub let longer (m : Meters) : Meters = Meters.wrap (add (Meters.unwrap m) 1)
+
+pub let first (pair : Pair Int) : Int = Pair.unwrap pair
+"#;
+
+fn project(main: &str) -> Project {
+    common::with_prelude(main).module("Distance.vp", DISTANCE)
+}
+
+#[test]
+fn newtypes_are_coerced_inside_of_their_modules() {
+    let project = project(
+        r#"use Prelude
+use Distance
+
+let size (m : Distance.Meters) : Int =
+  when m is
+    Distance.Meters.Meters n => n
+
+let main (x : ()) : () = do
+  printInt (size (Distance.longer (Distance.meters 41)))
+  printInt (Distance.first (Distance.Pair.Pair 3))
+"#,
+    );
+
+    assert_eq!(project.render(Stage::Diagnostics), "");
+    assert_eq!(project.render(Stage::Output), "42\n3\n");
+}
+
+#[test]
+fn coercions_are_hidden_from_other_modules() {
+    let project = project(
+        r#"use Prelude
+use Distance
+
+newtype Both = | Both Int Int
+
+let size (m : Distance.Meters) : Int = Distance.Meters.unwrap m
+"#,
+    );
+
+    let expected = "Main.vp:4:9: error[E0218]: the newtype 'Both' must have one constructor with \
+                    one argument\n\
+                    Main.vp:6:56: error[E0219]: the coercion 'unwrap' of the newtype 'Meters' can \
+                    only be used inside of its module\n";
+
+    assert_eq!(project.render(Stage::Diagnostics), expected);
+}
@@ -0,0 +1,70 @@
+mod common;
+
+use vulpi_testing::{Project, Stage};
+
+const COUNTER: &str = r#"use Prelude
+
+#[opaque]
+pub type Counter = { count : Int }
+
+pub let zero : Counter = Counter { count = 0 }
+
+pub let next (counter : Counter) : Counter = counter { count = counter.count + 1 }
+
+pub let count (counter : Counter) : Int = counter.count
+
+#[opaque]
+pub type Step = | Step Int
+
+pub let step : Step = Step.Step 1
+"#;
+
+fn project(main: &str) -> Project {
+    common::with_prelude(main).module("Counter.vp", COUNTER)
+}
+
+#[test]
+fn opaque_types_are_used_through_their_modules() {
+    let project = project(
+        r#"use Prelude
+use Counter
+
+let main (x : ()) : () = printInt (Counter.count (Counter.next (Counter.next Counter.zero)))
+"#,
+    );
+
+    assert_eq!(project.render(Stage::Diagnostics), "");
+    assert_eq!(project.render(Stage::Output), "2\n");
+}
+
+#[test]
+fn opaque_definitions_are_hidden_from_other_modules() {
+    let project = project(
+        r#"use Prelude
+use Counter
+
+let forge : Counter.Counter = Counter.Counter { count = 9 }
+
+let peek (counter : Counter.Counter) : Int = counter.count
+
+let reset (counter : Counter.Counter) : Counter.Counter = counter { count = 0 }
+
+let size (step : Counter.Step) : Int =
+  when step is
+    Counter.Step.Step n => n
+"#,
+    );
+
+    let hidden = "error[E0333]: the fields of the opaque type 'Counter' cannot be used outside of \
+                  its module";
+
+    let expected = format!(
+        "Main.vp:4:31: {hidden}\n\
+         Main.vp:6:46: {hidden}\n\
+         Main.vp:8:59: {hidden}\n\
+         Main.vp:12:18: error[E0217]: 'Step' cannot be used outside of the module of the opaque \
+         type 'Step'\n"
+    );
+
+    assert_eq!(project.render(Stage::Diagnostics), expected);
+}
@@ -0,0 +1,46 @@
+mod common;
+
+use vulpi_testing::{Project, Stage};
+
+fn project(main: &str) -> Project {
+    common::with_prelude(main)
+}
+
+#[test]
+fn types_are_printed_with_the_parenthesis_that_they_need() {
+    let project = project(
+        r#"use Prelude
+
+type Pair a b = | Pair a b
+
+let arrows (f : Int -> Int) : (Int -> Int) -> Int = f
+
+let applied (p : Pair (Int -> Int) (Pair Int Int)) : Int = p
+"#,
+    );
+
+    let expected = "Main.vp:5:53: error[E0302]: type mismatch: Int -> Int != (Int -> Int) -> Int\n\
+                    Main.vp:7:60: error[E0302]: type mismatch: Pair (Int -> Int) (Pair Int Int) \
+                    != Int\n";
+
+    assert_eq!(project.render(Stage::Diagnostics), expected);
+}
+
+#[test]
+fn bound_variables_keep_the_names_that_were_written() {
+    let project = project(
+        r#"use Prelude
+
+let first (f : (forall a. (forall b. b -> a) -> a) -> Int) : Int = f 1
+
+let shadowed (f : (forall a. (forall a. a -> a) -> a) -> Int) : Int = f 1
+"#,
+    );
+
+    let expected = "Main.vp:3:70: error[E0302]: type mismatch: Int != forall a. (forall b. b -> \
+                    a) -> a\n\
+                    Main.vp:5:73: error[E0302]: type mismatch: Int != forall a. (forall a1. a1 \
+                    -> a1) -> a\n";
+
+    assert_eq!(project.render(Stage::Diagnostics), expected);
+}
@@ -0,0 +1,12 @@
+use vulpi_testing::{generate::Gen, round_trip, NAMESPACE};
+
+#[test]
+fn printed_programs_parse_as_themselves() {
+    for seed in 0..300 {
+        let program = Gen::new(seed, NAMESPACE).program();
+
+        if let Err(err) = round_trip(&program) {
+            panic!("the program of the seed {} fails: {}", seed, err);
+        }
+    }
+}
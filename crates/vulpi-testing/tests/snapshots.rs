@@ -0,0 +1,10 @@
+use vulpi_testing::{assert_directory, Stage};
+
+#[test]
+fn snapshots() {
+    let directory = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/snapshots");
+    assert_directory(
+        directory,
+        &[Stage::Source, Stage::Diagnostics, Stage::Output],
+    );
+}
@@ -0,0 +1,41 @@
+mod common;
+
+use vulpi_location::FileId;
+use vulpi_testing::Stage;
+
+const MAIN: &str = r#"use Prelude
+
+pub effect Tick where
+  pub tick () : ()
+
+let ticks (x : ()) : Int =
+  handle do
+    Tick.tick ()
+    3
+  with
+    cases
+      { Tick.tick u -> k } => k ()
+  finally print "done"
+
+let main (x : ()) : () = do
+  let y = handle do
+      Tick.tick ()
+      printInt 1
+    with cases
+      { Tick.tick u -> k } => k ()
+  y
+"#;
+
+#[test]
+fn handlers_of_blocks_are_printed_as_sources_that_parse() {
+    let printed = common::with_prelude(MAIN).render(Stage::Source);
+
+    let reporter = vulpi_report::hash_reporter();
+    vulpi_parser::parse(reporter.clone(), FileId(0), &printed);
+
+    assert!(
+        !reporter.has_errors(),
+        "the source doesn't parse:\n{}",
+        printed
+    );
+}
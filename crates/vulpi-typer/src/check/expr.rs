@@ -6,7 +6,7 @@ use vulpi_syntax::{elaborated, r#abstract::Expr, r#abstract::ExprKind, r#abstrac
 use crate::{context::Context, real::Real, Env, Type, TypeKind, Virtual};
 
 use super::Check;
-use crate::infer::Infer;
+use crate::infer::{expr::infer_projection, Infer};
 
 impl Check for Expr {
     type Return = elaborated::Expr<Type<Real>>;
@@ -42,6 +42,10 @@ impl Check for Expr {
 
                 Box::new(elaborated::ExprKind::Do(stmts))
             }
+            (ExprKind::Literal(literal), _) => {
+                let elab = literal.check(typ.clone(), (ctx, env.clone()));
+                Box::new(elaborated::ExprKind::Literal(elab))
+            }
             (_, TypeKind::Forall(l)) => {
                 let lvl_ty = Type::new(TypeKind::Bound(env.level));
                 self.check(
@@ -50,6 +54,13 @@ impl Check for Expr {
                 )
                 .data
             }
+            (ExprKind::Projection(projection), _) => {
+                let context = (&mut *ctx, env.clone());
+                let (expr_ty, elab_expr) =
+                    infer_projection(projection, Some(&typ), context, self.span.clone());
+                ctx.subsumes(env, expr_ty, typ);
+                elab_expr.data
+            }
             _ => {
                 let (expr_ty, elab_expr) = self.infer((ctx, env.clone()));
                 ctx.subsumes(env, expr_ty, typ);
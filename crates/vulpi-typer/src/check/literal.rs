@@ -0,0 +1,90 @@
+//! Checking of literals. Numbers that are expected to be of one of the numeric types of the prelude
+//! take that type if they're values of it, so the sized types don't need literals of their own.
+//! Other whole numbers default to `Int`, or to `Float` when the type can't be an `Int`.
+
+use vulpi_intern::Symbol;
+use vulpi_syntax::{
+    elaborated,
+    r#abstract::{Literal, LiteralKind, Qualified},
+};
+
+use super::Check;
+use crate::{
+    context::Context, errors::TypeErrorKind, infer::Infer, number::Number, r#virtual::Virtual, Env,
+    Type, TypeKind,
+};
+
+impl Check for Literal {
+    type Return = elaborated::Literal;
+
+    type Context<'a> = (&'a mut Context, Env);
+
+    fn check(&self, typ: Type<Virtual>, (ctx, env): Self::Context<'_>) -> Self::Return {
+        env.set_current_span(self.span.clone());
+
+        let number = match typ.deref().as_ref() {
+            TypeKind::Variable(name) if name.path.get() == "Prelude" => {
+                Number::of(&name.name.get()).map(|number| (name.name.clone(), number))
+            }
+            _ => None,
+        };
+
+        match (&self.data, number) {
+            (LiteralKind::Integer(n), Some((name, number @ Number::Int { .. }))) => {
+                if !number.fits(&n.get()) {
+                    ctx.report(&env, TypeErrorKind::LiteralOutOfRange(n.clone(), name));
+                }
+
+                Box::new(elaborated::LiteralKind::Integer(n.clone()))
+            }
+            (LiteralKind::Float(n), Some((name, number @ Number::Float { .. }))) => {
+                if !number.fits(&n.get()) {
+                    ctx.report(&env, TypeErrorKind::LiteralOutOfRange(n.clone(), name));
+                    return Box::new(elaborated::LiteralKind::Float(n.clone()));
+                }
+
+                // The literal is written with the precision of the type, so every backend starts
+                // from the same value.
+                let value: f64 = n.get().replace('_', "").parse().unwrap();
+                let rounded = number.round(value);
+
+                if rounded == value {
+                    Box::new(elaborated::LiteralKind::Float(n.clone()))
+                } else {
+                    let text = Symbol::intern(&format!("{:?}", rounded));
+                    Box::new(elaborated::LiteralKind::Float(text))
+                }
+            }
+            (LiteralKind::Integer(n), _) => {
+                let prelude = |name: &str| {
+                    Type::variable(Qualified {
+                        path: Symbol::intern("Prelude"),
+                        name: Symbol::intern(name),
+                    })
+                };
+
+                let (int, float) = (prelude("Int"), prelude("Float"));
+
+                let mut fits = |default: &Type<Virtual>| {
+                    ctx.attempt(|ctx| ctx.unify(env.clone(), default.clone(), typ.clone()))
+                };
+
+                // The defaults of a whole number are tried in order, so the ones that don't fit
+                // leave the holes of the type as they were.
+                if !fits(&int) && fits(&float) && Number::FLOAT.fits(&n.get()) {
+                    ctx.subsumes(env, float, typ);
+                    Box::new(elaborated::LiteralKind::Float(n.clone()))
+                } else {
+                    let (literal_ty, elab) = self.infer((ctx, env.clone()));
+                    ctx.subsumes(env, literal_ty, typ);
+                    elab
+                }
+            }
+            _ => {
+                let (literal_ty, elab) = self.infer((ctx, env.clone()));
+                ctx.subsumes(env, literal_ty, typ);
+                elab
+            }
+        }
+    }
+}
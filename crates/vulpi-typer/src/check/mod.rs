@@ -4,6 +4,7 @@
 use crate::{Type, Virtual};
 
 pub mod expr;
+pub mod literal;
 pub mod pat;
 
 pub trait Check {
@@ -7,7 +7,10 @@ use vulpi_syntax::{
 };
 
 use crate::eval::Quote;
-use crate::infer::Infer;
+use crate::infer::{
+    pat::{elaborate_range, flatten_or, merge_bindings},
+    Infer,
+};
 use crate::{context::Context, errors::TypeErrorKind, r#virtual::Virtual, real::Real, Env, Type};
 
 use super::Check;
@@ -112,6 +115,38 @@ impl Check for Pattern {
 
                 Box::new(elaborated::PatternKind::Variable(n.clone()))
             }
+            PatternKind::Literal(literal) => {
+                let elab = literal.check(ann_ty, (ctx, env));
+                Box::new(elaborated::PatternKind::Literal(elab))
+            }
+            PatternKind::Range(range) => {
+                let start = range.start.check(ann_ty.clone(), (ctx, env.clone()));
+                let end = range.end.check(ann_ty, (ctx, env.clone()));
+                env.set_current_span(self.span.clone());
+                elaborate_range(ctx, env, start, end)
+            }
+            PatternKind::Or(_) => {
+                let mut alternatives = Vec::new();
+                flatten_or(self, &mut alternatives);
+
+                let first = alternatives[0].check(ann_ty.clone(), (ctx, map, env.clone()));
+                let mut elab_alternatives = vec![first];
+
+                for alternative in &alternatives[1..] {
+                    let mut bindings = HashMap::new();
+                    let elab = alternative.check(ann_ty.clone(), (ctx, &mut bindings, env.clone()));
+                    merge_bindings(ctx, &env, map, bindings);
+                    elab_alternatives.push(elab);
+                }
+
+                Box::new(elaborated::PatternKind::Or(elab_alternatives))
+            }
+            PatternKind::As(as_) => {
+                let pat = as_.pattern.check(ann_ty.clone(), (ctx, map, env.clone()));
+                map.insert(as_.name.clone(), ann_ty);
+
+                Box::new(elaborated::PatternKind::As(as_.name.clone(), pat))
+            }
             _ => {
                 let (typ, elab_pat) = self.infer((ctx, map, env.clone()));
                 ctx.subsumes(env, typ, ann_ty);
@@ -2,19 +2,153 @@
 //! not need to be immutable like the Env.
 
 use vulpi_intern::Symbol;
-use vulpi_report::{Diagnostic, Report};
+use vulpi_report::{Diagnostic, IntoDiagnostic, Report};
 use vulpi_syntax::{elaborated, r#abstract::Qualified};
 
+use vulpi_syntax::r#abstract::OperationKind;
+
 use crate::{
     errors::{TypeError, TypeErrorKind},
-    module::Modules,
+    eval::Eval,
+    module::{Def, LetDef, Modules, TypeData},
     r#virtual::Env,
     r#virtual::Pi,
     r#virtual::Virtual,
-    real::Real,
-    HoleInner, State, Type, TypeKind,
+    real::{Forall, Real},
+    store::HoleStore,
+    HoleInner, Index, State, Type, TypeKind,
 };
 
+/// The primitive effect of mutable cells. The cells that its operations work on have the type of
+/// the effect, like `Ref Int`.
+pub fn ref_effect() -> Qualified {
+    Qualified {
+        path: Symbol::intern("Prelude"),
+        name: Symbol::intern("Ref"),
+    }
+}
+
+/// The primitive effect of mutable arrays, that are indexed from zero and grow at the end. Like
+/// the cells of `Ref`, the arrays have the type of the effect.
+pub fn array_effect() -> Qualified {
+    Qualified {
+        path: Symbol::intern("Prelude"),
+        name: Symbol::intern("Array"),
+    }
+}
+
+/// The primitive effects, that take the type of their values as their only argument.
+pub fn primitive_effects() -> Vec<Qualified> {
+    vec![ref_effect(), array_effect()]
+}
+
+/// The operations of the primitive effects, with their effects, their types and the bindings of
+/// the primitives that implement them. They're not performed, the backends run the primitives
+/// instead.
+pub fn primitive_operations() -> Vec<(Qualified, Qualified, Type<Real>, &'static str)> {
+    let a = || Type::<Real>::new(TypeKind::Bound(Index(0)));
+    let of = |effect: &Qualified| {
+        Type::<Real>::new(TypeKind::Application(Type::variable(effect.clone()), a()))
+    };
+
+    let int = || {
+        Type::<Real>::variable(Qualified {
+            path: Symbol::intern("Prelude"),
+            name: Symbol::intern("Int"),
+        })
+    };
+
+    let unit = || Type::<Real>::new(TypeKind::Tuple(vec![]));
+
+    let (cell, array) = (|| of(&ref_effect()), || of(&array_effect()));
+
+    let fun = Type::<Real>::function;
+
+    let operations = [
+        (ref_effect(), "new", fun(vec![a()], cell()), "ref_new"),
+        (ref_effect(), "get", fun(vec![cell()], a()), "ref_get"),
+        (ref_effect(), "set", fun(vec![cell(), a()], unit()), "ref_set"),
+        (array_effect(), "empty", fun(vec![unit()], array()), "array_empty"),
+        (array_effect(), "make", fun(vec![int(), a()], array()), "array_make"),
+        (array_effect(), "get", fun(vec![array(), int()], a()), "array_get"),
+        (array_effect(), "set", fun(vec![array(), int(), a()], unit()), "array_set"),
+        (array_effect(), "length", fun(vec![array()], int()), "array_length"),
+        (array_effect(), "push", fun(vec![array(), a()], unit()), "array_push"),
+    ];
+
+    operations
+        .into_iter()
+        .map(|(effect, name, typ, binding)| {
+            let typ = Type::forall(Forall {
+                name: Symbol::intern("a"),
+                kind: Type::typ(),
+                body: typ,
+            });
+
+            let name = Qualified {
+                path: Symbol::intern(&effect.to_string()),
+                name: Symbol::intern(name),
+            };
+
+            (effect, name, typ, binding)
+        })
+        .collect()
+}
+
+/// The built-in effect of the tasks that run concurrently. Unlike the primitive effects, its
+/// operations are performed like the ones of any effect, so a handler can schedule the tasks
+/// itself. The virtual machine schedules the ones that no handler handles.
+pub fn async_effect() -> Qualified {
+    Qualified {
+        path: Symbol::intern("Prelude"),
+        name: Symbol::intern("Async"),
+    }
+}
+
+/// The tasks of the `Async` effect, that take the type of their results as their only argument.
+pub fn task_type() -> Qualified {
+    Qualified {
+        path: Symbol::intern("Prelude"),
+        name: Symbol::intern("Task"),
+    }
+}
+
+/// The operations of the `Async` effect with their types. They're `ctl` operations, so the
+/// handlers get the continuations of the tasks.
+pub fn async_operations() -> Vec<(Qualified, Type<Real>)> {
+    let a = || Type::<Real>::new(TypeKind::Bound(Index(0)));
+    let task = || Type::<Real>::new(TypeKind::Application(Type::variable(task_type()), a()));
+    let unit = || Type::<Real>::new(TypeKind::Tuple(vec![]));
+
+    let forall = |body| {
+        Type::forall(Forall {
+            name: Symbol::intern("a"),
+            kind: Type::typ(),
+            body,
+        })
+    };
+
+    let fun = Type::<Real>::function;
+
+    let operations = [
+        ("spawn", forall(fun(vec![fun(vec![unit()], a())], task()))),
+        ("await", forall(fun(vec![task()], a()))),
+        ("yield", fun(vec![unit()], unit())),
+    ];
+
+    operations
+        .into_iter()
+        .map(|(name, typ)| {
+            let name = Qualified {
+                path: Symbol::intern(&async_effect().to_string()),
+                name: Symbol::intern(name),
+            };
+
+            (name, typ)
+        })
+        .collect()
+}
+
 /// A mutable context that is used differently from [Env]. It is used to keep data between every
 /// thing inside the type checker.
 pub struct Context {
@@ -23,25 +157,171 @@ pub struct Context {
     pub modules: Modules,
     pub elaborated: elaborated::Program<Type<Real>>,
     pub errored: bool,
-    }
+
+    /// The lints allowed by the attributes of the declaration that is being defined.
+    pub allowed: Vec<Symbol>,
+
+    /// Whether the declaration that is being defined is written with `#[pure]`.
+    pub pure: bool,
+
+    /// The module of the declaration that is being defined, that the definitions of the opaque
+    /// types of other modules are hidden from.
+    pub module: Option<Symbol>,
+
+    /// The holes that were unified during type checking.
+    pub holes: HoleStore,
+}
 
 impl Context {
     pub fn new(reporter: Report) -> Self {
-        Self {
+        let mut ctx = Self {
             counter: 0,
             reporter,
             modules: Default::default(),
             elaborated: Default::default(),
             errored: false,
+            allowed: Vec::new(),
+            pure: false,
+            module: None,
+            holes: Default::default(),
+        };
+
+        ctx.declare_primitives();
+        ctx
+    }
+
+    /// Declares the primitive effects and the `Async` effect with its tasks. They live in the
+    /// prelude but are not written in it.
+    fn declare_primitives(&mut self) {
+        let io = self.io_effect();
+
+        self.modules.get(&io.path).types.insert(
+            io.name.clone(),
+            TypeData {
+                kind: Type::typ(),
+                binders: vec![],
+                module: Symbol::intern(&io.to_string()),
+                def: Def::Effect(vec![]),
+                opaque: false,
+            },
+        );
+
+        let operations = primitive_operations();
+
+        for effect in primitive_effects() {
+            let names = operations.iter().filter(|x| x.0 == effect);
+
+            self.modules.get(&effect.path).types.insert(
+                effect.name.clone(),
+                TypeData {
+                    kind: Type::<Virtual>::function(vec![Type::typ()], Type::typ()),
+                    binders: vec![(Symbol::intern("a"), Type::typ())],
+                    module: Symbol::intern(&effect.to_string()),
+                    def: Def::Effect(names.map(|x| x.1.clone()).collect()),
+                    opaque: false,
+                },
+            );
+        }
+
+        let task = task_type();
+
+        self.modules.get(&task.path).types.insert(
+            task.name.clone(),
+            TypeData {
+                kind: Type::<Virtual>::function(vec![Type::typ()], Type::typ()),
+                binders: vec![(Symbol::intern("a"), Type::typ())],
+                module: Symbol::intern(&task.to_string()),
+                def: Def::Type,
+                opaque: false,
+            },
+        );
+
+        let effect = async_effect();
+        let operations_of_async = async_operations();
+
+        self.modules.get(&effect.path).types.insert(
+            effect.name.clone(),
+            TypeData {
+                kind: Type::typ(),
+                binders: vec![],
+                module: Symbol::intern(&effect.to_string()),
+                def: Def::Effect(operations_of_async.iter().map(|x| x.0.clone()).collect()),
+                opaque: false,
+            },
+        );
+
+        let operations = operations
+            .into_iter()
+            .map(|(effect, name, typ, _)| (effect, name, typ, OperationKind::Fun))
+            .chain(
+                operations_of_async
+                    .into_iter()
+                    .map(|(name, typ)| (effect.clone(), name, typ, OperationKind::Ctl)),
+            );
+
+        for (effect, name, typ, kind) in operations {
+            let arity = typ.forall_spine().1.arrow_spine().len() - 1;
+            let module = self.modules.get(&name.path);
+
+            module
+                .operations
+                .insert(name.name.clone(), (typ.clone(), arity, effect, kind));
+
+            let typ = typ.eval(&Env::default());
+
+            module.variables.insert(
+                name.name,
+                LetDef {
+                    typ: typ.clone(),
+                    unbound: vec![],
+                    args: vec![],
+                    ret: typ,
+                },
+            );
+        }
+    }
+
+    /// The primitive effect of external functions that talk with the outside world.
+    pub fn io_effect(&self) -> Qualified {
+        Qualified {
+            path: Symbol::intern("Prelude"),
+            name: Symbol::intern("IO"),
         }
     }
 
     pub fn report(&mut self, env: &Env, kind: TypeErrorKind) {
-        self.errored = true;
-        self.reporter.report(Diagnostic::new(TypeError {
+        let error = TypeError {
             span: env.span.borrow().clone(),
             kind,
-        }));
+        };
+
+        if let Some(lint) = error.lint() {
+            if self.allowed.iter().any(|x| x.get() == lint) {
+                return;
+            }
+        }
+
+        self.errored = true;
+        self.reporter.report(Diagnostic::new(error));
+    }
+
+    /// Checks that the definition of a type can be used by the declaration that is being defined,
+    /// and reports it otherwise. Opaque types only show their definitions to their own modules and
+    /// to the modules inside of them.
+    pub fn check_opaque(&mut self, env: &Env, name: &Qualified, typ: &TypeData) -> bool {
+        let path = name.path.get();
+
+        let inside = self.module.as_ref().is_none_or(|module| {
+            let module = module.get();
+            module == path || module.starts_with(&format!("{}.", path))
+        });
+
+        if typ.opaque && !inside {
+            self.report(env, TypeErrorKind::OpaqueType(name.clone()));
+            false
+        } else {
+            true
+        }
     }
 
     fn inc_counter(&mut self) -> usize {
@@ -67,7 +347,8 @@ impl Context {
 
     /// Creates a new hole that is a type that is not yet known
     pub fn hole<S: State>(&mut self, env: &Env, kind: Type<Virtual>) -> Type<S> {
-        env.hole(kind, self.new_name())
+        let name = self.new_name();
+        Type::new(TypeKind::Hole(self.holes.empty(name, kind, env.level)))
     }
 
     pub fn as_function(
@@ -75,7 +356,7 @@ impl Context {
         env: &Env,
         typ: Type<Virtual>,
     ) -> Option<(Type<Virtual>, Type<Virtual>)> {
-        match typ.deref().as_ref() {
+        match self.holes.deref(&typ).as_ref() {
             TypeKind::Arrow(pi) => Some((pi.typ.clone(), pi.body.clone())),
             TypeKind::Error => Some((typ.clone(), typ.clone())),
             TypeKind::Forall(_) => {
@@ -83,15 +364,16 @@ impl Context {
                 self.as_function(env, typ)
             }
             TypeKind::Hole(empty) => {
-                let hole_inner = empty.0.borrow().clone();
-                if let HoleInner::Empty(_, kind, _) = hole_inner {
+                if let HoleInner::Empty(_, kind, ..) = empty.get() {
                     let hole_a = self.hole(env, kind.clone());
                     let hole_b = self.hole(env, kind);
 
-                    empty.fill(Type::new(TypeKind::Arrow(Pi {
+                    let arrow = Type::new(TypeKind::Arrow(Pi {
                         typ: hole_a.clone(),
                         body: hole_b.clone(),
-                    })));
+                    }));
+
+                    self.holes.fill(empty, arrow);
 
                     Some((hole_a, hole_b))
                 } else {
@@ -106,7 +388,8 @@ impl Context {
     pub fn instantiate(&mut self, env: &Env, typ: &Type<Virtual>) -> Type<Virtual> {
         match typ.deref().as_ref() {
             TypeKind::Forall(forall) => {
-                let arg = env.hole(forall.kind.clone(), forall.name.clone());
+                let hole = self.holes.empty(forall.name.clone(), forall.kind.clone(), env.level);
+                let arg = Type::new(TypeKind::Hole(hole));
                 let kind = forall.kind.clone();
                 // Applies the body using the hole argument.
                 forall.body.apply(Some(forall.name.clone()), arg, kind)
@@ -137,6 +420,33 @@ impl Context {
         typ
     }
 
+    /// The type of a record applied to new holes, with the type of one of its fields for them.
+    pub fn field_of(
+        &mut self,
+        env: &Env,
+        record: &Qualified,
+        field: &Symbol,
+    ) -> Option<(Type<Virtual>, Type<Virtual>)> {
+        let typ = self.modules.typ(record);
+
+        let Def::Record(fields) = &typ.def else {
+            return None;
+        };
+
+        let field = fields.iter().find(|x| x.name == *field)?;
+        let field = self.modules.field(field).eval(env);
+
+        let args: Vec<_> = typ
+            .binders
+            .iter()
+            .map(|x| self.hole::<Virtual>(env, x.1.clone()))
+            .collect();
+
+        let record = Type::<Virtual>::application(Type::variable(record.clone()), args.clone());
+
+        Some((record, self.instantiate_with_arguments(&field, args)))
+    }
+
     pub fn instantiate_all(&mut self, env: &Env, typ: &Type<Virtual>) -> Type<Virtual> {
         match typ.deref().as_ref() {
             TypeKind::Forall(_) => {
@@ -3,6 +3,8 @@
 
 use std::fmt::Display;
 
+use std::collections::HashMap;
+
 use im_rc::HashSet;
 
 use vulpi_syntax::{
@@ -10,7 +12,10 @@ use vulpi_syntax::{
     r#abstract::Qualified,
 };
 
-use crate::{context::Context, eval::Eval, real::Real, Env, Type, TypeKind, Virtual};
+use crate::{
+    context::Context, eval::Eval, module::Def, number::Number, real::Real, Env, Type, TypeKind,
+    Virtual,
+};
 
 #[derive(Clone, Debug)]
 pub enum Pat {
@@ -18,6 +23,8 @@ pub enum Pat {
     Constructor(Qualified, Vec<Pat>),
     Wildcard,
     Literal(Literal),
+    Range(Literal, Literal),
+    Or(Vec<Pat>),
 }
 
 impl Display for Pat {
@@ -45,17 +52,35 @@ impl Display for Pat {
                 }
             }
             Pat::Wildcard => write!(f, "_"),
-            Pat::Literal(lit) => match &**lit {
-                LiteralKind::String(s) => write!(f, "\"{}\"", s.get()),
-                LiteralKind::Integer(i) => write!(f, "{}", i.get()),
-                LiteralKind::Float(fe) => write!(f, "{}", fe.get()),
-                LiteralKind::Char(c) => write!(f, "'{}'", c.get()),
-                LiteralKind::Unit => write!(f, "()"),
-            },
+            Pat::Literal(lit) => write_literal(f, lit),
+            Pat::Range(start, end) => {
+                write_literal(f, start)?;
+                write!(f, "..")?;
+                write_literal(f, end)
+            }
+            Pat::Or(alternatives) => {
+                for (i, alternative) in alternatives.iter().enumerate() {
+                    if i != 0 {
+                        write!(f, " | ")?;
+                    }
+                    write!(f, "{}", alternative)?;
+                }
+                Ok(())
+            }
         }
     }
 }
 
+fn write_literal(f: &mut std::fmt::Formatter<'_>, lit: &Literal) -> std::fmt::Result {
+    match &**lit {
+        LiteralKind::String(s) => write!(f, "\"{}\"", s.get()),
+        LiteralKind::Integer(i) => write!(f, "{}", i.get()),
+        LiteralKind::Float(fe) => write!(f, "{}", fe.get()),
+        LiteralKind::Char(c) => write!(f, "'{}'", c.get().escape_debug()),
+        LiteralKind::Unit => write!(f, "()"),
+    }
+}
+
 impl Pat {
     pub fn from_pattern(pat: &Pattern) -> Option<Pat> {
         match &**pat {
@@ -67,6 +92,14 @@ impl Pat {
                     .collect::<Option<Vec<_>>>()?,
             )),
             PatternKind::Literal(l) => Some(Pat::Literal(l.clone())),
+            PatternKind::Range(start, end) => Some(Pat::Range(start.clone(), end.clone())),
+            PatternKind::Or(alternatives) => Some(Pat::Or(
+                alternatives
+                    .iter()
+                    .map(Pat::from_pattern)
+                    .collect::<Option<Vec<_>>>()?,
+            )),
+            PatternKind::As(_, p) => Pat::from_pattern(p),
             PatternKind::Application(p) => Some(Pat::Constructor(
                 p.func.clone(),
                 p.args
@@ -74,6 +107,7 @@ impl Pat {
                     .map(Pat::from_pattern)
                     .collect::<Option<Vec<_>>>()?,
             )),
+            PatternKind::Effect(_) => None,
             PatternKind::Error => None,
         }
     }
@@ -88,6 +122,28 @@ impl Pat {
             _ => None,
         }
     }
+
+    /// The first and the last position of the values that an integer, a character or a range of
+    /// them matches.
+    pub fn bounds(&self) -> Option<(i64, i64)> {
+        match self {
+            Pat::Literal(lit) => lit.ordinal().map(|x| (x, x)),
+            Pat::Range(start, end) => Some((start.ordinal()?, end.ordinal()?)),
+            _ => None,
+        }
+    }
+
+    /// The pattern of the values from `lo` to `hi` with literals of the same kind as `like`.
+    fn interval(like: &Literal, lo: i64, hi: i64) -> Pat {
+        if lo == hi {
+            Pat::Literal(Box::new(like.with_ordinal(lo)))
+        } else {
+            Pat::Range(
+                Box::new(like.with_ordinal(lo)),
+                Box::new(like.with_ordinal(hi)),
+            )
+        }
+    }
 }
 
 /// A line in the problem matrix. It's used to indicate that there's an answer to a open pattern
@@ -139,7 +195,7 @@ impl Row<Pat> {
             (Pat::Wildcard, Pat::Tuple(args)) => vec![self.inline(wildcards(args.len()))],
             (Pat::Wildcard, Pat::Constructor(_, args)) => vec![self.inline(wildcards(args.len()))],
             (Pat::Wildcard, Pat::Wildcard) => vec![self.pop_front()],
-            (Pat::Wildcard, Pat::Literal(_)) => vec![self.pop_front()],
+            (Pat::Wildcard, Pat::Literal(_) | Pat::Range(_, _)) => vec![self.pop_front()],
 
             (Pat::Constructor(n, _), Pat::Constructor(m, args)) if n == *m => {
                 vec![self.inline(args.clone())]
@@ -151,12 +207,43 @@ impl Row<Pat> {
                 vec![self.inline(b.to_vec())]
             }
 
+            (Pat::Tuple(a), Pat::Wildcard) => vec![self.inline(wildcards(a.len()))],
+
+            // The unit is the only value of the empty tuple.
+            (Pat::Tuple(a), Pat::Literal(lit)) if a.is_empty() && is_unit(lit) => {
+                vec![self.pop_front()]
+            }
+
             (Pat::Literal(n), Pat::Literal(m)) if n == *m => vec![self.pop_front()],
 
+            (Pat::Literal(_) | Pat::Range(_, _), Pat::Wildcard) => vec![self.pop_front()],
+
+            // The intervals that are tested never overlap partially with the patterns of the
+            // column, so the ones that contain their start contain all of them.
+            (useful @ (Pat::Literal(_) | Pat::Range(_, _)), first) => {
+                match (useful.bounds(), first.bounds()) {
+                    (Some((lo, _)), Some((start, end))) if start <= lo && lo <= end => {
+                        vec![self.pop_front()]
+                    }
+                    _ => vec![],
+                }
+            }
+
             (_, _) => vec![],
         }
     }
 
+    /// The rows of each alternative of an or-pattern in the first column.
+    pub fn expand(self) -> Vec<Row<Pat>> {
+        match self.first() {
+            Pat::Or(alternatives) => alternatives
+                .iter()
+                .flat_map(|x| self.inline(vec![x.clone()]).expand())
+                .collect(),
+            _ => vec![self],
+        }
+    }
+
     pub fn default_row(self) -> Vec<Row<Pat>> {
         let first = &self.0[0];
         match first {
@@ -187,6 +274,31 @@ impl Matrix<Pat> {
         self.0.iter().flat_map(|x| x.used_constructor()).collect()
     }
 
+    /// The range patterns of the first column.
+    pub fn ranges(&self) -> Vec<Pat> {
+        self.0
+            .iter()
+            .map(|x| x.first().clone())
+            .filter(|x| matches!(x, Pat::Range(_, _)))
+            .collect()
+    }
+
+    /// The positions where the values matched by the integers, characters and ranges of the
+    /// first column start or stop.
+    pub fn cuts(&self) -> Vec<i64> {
+        let mut cuts = self
+            .0
+            .iter()
+            .flat_map(|x| x.first().bounds())
+            .flat_map(|(start, end)| [Some(start), end.checked_add(1)])
+            .flatten()
+            .collect::<Vec<_>>();
+
+        cuts.sort();
+        cuts.dedup();
+        cuts
+    }
+
     pub fn specialize(self, useful: Pat) -> Matrix<Pat> {
         Matrix(
             self.0
@@ -199,6 +311,14 @@ impl Matrix<Pat> {
     pub fn default_matrix(self) -> Matrix<Pat> {
         Matrix(self.0.into_iter().flat_map(|x| x.default_row()).collect())
     }
+
+    pub fn expand(self) -> Matrix<Pat> {
+        Matrix(self.0.into_iter().flat_map(|x| x.expand()).collect())
+    }
+}
+
+fn is_unit(lit: &Literal) -> bool {
+    matches!(&**lit, LiteralKind::Unit)
 }
 
 fn wildcards(n: usize) -> Vec<Pat> {
@@ -382,7 +502,13 @@ impl Problem {
         self.specialize(ctx, env, spine, case_pats, case)
     }
 
-    pub fn exaustive(self, ctx: &mut Context, env: Env) -> Witness {
+    pub fn exaustive(mut self, ctx: &mut Context, env: Env) -> Witness {
+        // The or-patterns that come to the first column are split into a row for each of their
+        // alternatives.
+        if !self.case.is_empty() {
+            self.matrix = self.matrix.expand();
+        }
+
         if self.is_empty() {
             Witness::NonExhaustive(self.case)
         } else if self.is_exhaustive() {
@@ -405,6 +531,50 @@ impl Problem {
         }
     }
 
+    /// The intervals of positions of the values of a type, if they're integers or characters.
+    /// Characters are the code points that are not surrogates.
+    pub fn domain(type_name: &Qualified) -> Option<Vec<(i64, i64)>> {
+        if type_name.path.get() != "Prelude" {
+            return None;
+        }
+
+        match type_name.name.get().as_str() {
+            "Char" => Some(vec![(0, 0xD7FF), (0xE000, 0x10FFFF)]),
+            name => Some(vec![Number::of(name)?.range()?]),
+        }
+    }
+
+    /// Checks a column of ranges by splitting the values of its type at the positions where the
+    /// patterns start or stop, so every interval is either inside or outside of each pattern.
+    pub fn split_ranges(self, ctx: &mut Context, env: Env, domain: Vec<(i64, i64)>) -> Witness {
+        let ranges = self.matrix.ranges();
+        let Some(Pat::Range(like, _)) = ranges.first() else {
+            unreachable!("the column has ranges")
+        };
+
+        let cuts = self.matrix.cuts();
+
+        for (lo, hi) in domain {
+            let mut start = lo;
+            let ends = cuts.iter().filter(|x| lo < **x && **x <= hi).map(|x| x - 1);
+
+            for end in ends.chain([hi]) {
+                let pat = Pat::interval(like, start, end);
+                let witness = self
+                    .clone()
+                    .specialize(ctx, env.clone(), vec![], vec![], pat.clone());
+
+                if witness.non_exaustive() {
+                    return witness.preppend(pat);
+                }
+
+                start = end.wrapping_add(1);
+            }
+        }
+
+        Witness::Ok
+    }
+
     pub fn synthetize(&self, ctx: &mut Context, name: Qualified) -> Pat {
         let (_, args, _) = ctx.modules.constructor(&name);
         Pat::Constructor(name.clone(), wildcards(args))
@@ -417,8 +587,12 @@ impl Problem {
         type_name: Qualified,
         type_spine: Vec<Type<Virtual>>,
     ) -> Witness {
+        let ranges = Self::domain(&type_name).filter(|_| !self.matrix.ranges().is_empty());
+
         if self.matrix.is_wildcard() {
             self.specialize_wildcard(ctx, env)
+        } else if let Some(domain) = ranges {
+            self.split_ranges(ctx, env, domain)
         } else {
             match self.is_complete_signature(ctx, type_name.clone()) {
                 Completeness::Complete(_) => self.split(ctx, env, type_name, type_spine),
@@ -531,3 +705,105 @@ impl Problem {
         }
     }
 }
+
+/// Checks if the effect patterns of a handler cover every operation of the effects that it
+/// handles. Operations with a default implementation are always covered. It returns the handled effects alongside the operations that are not covered.
+pub fn unhandled_operations(
+    ctx: &mut Context,
+    env: Env,
+    arms: &[PatternArm<Type<Real>>],
+) -> Vec<(Qualified, Vec<Qualified>)> {
+    let mut effects = Vec::new();
+    let mut rows: HashMap<Qualified, Vec<Row<Pat>>> = HashMap::new();
+
+    for arm in arms {
+        let Some(PatternKind::Effect(eff)) = arm.patterns.first().map(|x| &**x) else {
+            continue;
+        };
+
+        let Some((_, _, effect, _)) = ctx.modules.operation(&eff.func) else {
+            continue;
+        };
+
+        if !effects.contains(&effect) {
+            effects.push(effect);
+        }
+
+        // A guard can fail, so the requests of its arm can still be unhandled.
+        if arm.guard.is_some() {
+            continue;
+        }
+
+        if let Some(row) = eff
+            .args
+            .iter()
+            .map(Pat::from_pattern)
+            .collect::<Option<Vec<_>>>()
+        {
+            rows.entry(eff.func.clone())
+                .or_default()
+                .push(Row(row.into()));
+        }
+    }
+
+    let mut result = Vec::new();
+
+    for effect in effects {
+        let Def::Effect(operations) = ctx.modules.typ(&effect).def else {
+            continue;
+        };
+
+        let missing = operations
+            .into_iter()
+            .filter(|op| {
+                ctx.modules.operation_default(op).is_none()
+                    && !is_operation_covered(ctx, env.clone(), op, rows.get(op))
+            })
+            .collect::<Vec<_>>();
+
+        if !missing.is_empty() {
+            result.push((effect, missing));
+        }
+    }
+
+    result
+}
+
+fn is_operation_covered(
+    ctx: &mut Context,
+    env: Env,
+    operation: &Qualified,
+    rows: Option<&Vec<Row<Pat>>>,
+) -> bool {
+    let Some(rows) = rows else {
+        return false;
+    };
+
+    let Some((typ, arity, _, _)) = ctx.modules.operation(operation) else {
+        return false;
+    };
+
+    if arity == 0 {
+        return true;
+    }
+
+    let mut typ = ctx.instantiate_all(&env, &typ.eval(&env));
+    let mut types = Vec::new();
+
+    for _ in 0..arity {
+        let Some((arg, rest)) = ctx.as_function(&env, typ) else {
+            return true;
+        };
+
+        types.push(arg);
+        typ = rest;
+    }
+
+    let problem = Problem {
+        types: Row(types.into()),
+        case: Row(wildcards(arity).into()),
+        matrix: Matrix(rows.clone()),
+    };
+
+    !problem.exaustive(ctx, env).non_exaustive()
+}
@@ -1,10 +1,13 @@
 use std::collections::HashSet;
 
 use vulpi_intern::Symbol;
+use vulpi_location::Spanned;
 use vulpi_syntax::{
     elaborated::{self},
     r#abstract::{
-        LetBinder, Qualified, TraitDecl, {ExtDecl, LetDecl, TypeDef}, {Program, TypeDecl},
+        self, EffectDecl, LetBinder, Qualified, Resumption, TestDecl, TraitDecl, Visibility,
+        {ExtDecl, LetDecl, TypeDef},
+        {Program, TypeDecl},
     },
 };
 
@@ -19,7 +22,8 @@ use crate::{
     module::{Def, LetDef, TraitData, TypeData},
     r#virtual::Virtual,
     real::{Forall, Real},
-    Env, Index, Kind, Type,
+    typed,
+    Env, Index, Kind, Type, TypeKind,
 };
 
 fn free_variables(let_sig: &vulpi_syntax::r#abstract::LetSignature) -> HashSet<Symbol> {
@@ -114,6 +118,7 @@ impl Declare for TraitDecl {
                 binders: names.into_iter().zip(binders.clone()).collect(),
                 module: self.namespace.clone(),
                 def: Def::Constraint,
+                opaque: false,
             },
         );
 
@@ -202,8 +207,96 @@ impl Declare for TraitDecl {
     fn define(&self, _context: (&mut Context, Env)) -> Self::Return {}
 }
 
+/// The coercions of a newtype, that are functions from the argument of its constructor to the
+/// newtype and back. Both of them are erased with the constructor, so they cost nothing.
+fn coercions(
+    ctx: &mut Context,
+    decl: &TypeDecl,
+    constructor: &Qualified,
+    (arg, ret): (Type<Real>, Type<Real>),
+    (env, start_env): (&Env, &Env),
+) -> Vec<(Qualified, elaborated::LetDecl<Type<Real>>)> {
+    let type_decl = ctx.modules.typ(&decl.name);
+    let span = env.span.borrow().clone();
+    let value = Symbol::intern("value");
+
+    let wrap = elaborated::ExprKind::Constructor(decl.name.clone(), constructor.clone());
+
+    let unwrap = elaborated::ExprKind::Lambda(elaborated::LambdaExpr {
+        param: Box::new(elaborated::PatternKind::Application(
+            elaborated::PatApplication {
+                func: constructor.clone(),
+                args: vec![Box::new(elaborated::PatternKind::Variable(value.clone()))],
+            },
+        )),
+        body: Spanned::new(Box::new(elaborated::ExprKind::Variable(value)), span.clone()),
+    });
+
+    let coercions = [
+        (TypeDecl::WRAP, wrap, Type::<Real>::function(vec![arg.clone()], ret.clone())),
+        (TypeDecl::UNWRAP, unwrap, Type::<Real>::function(vec![ret], arg)),
+    ];
+
+    // They're synthesized for every newtype, so they're not reported when they're not used.
+    let mut allow = decl.attributes.allow.clone();
+    allow.push(Symbol::intern("unused"));
+
+    let mut lets = Vec::new();
+
+    for (name, expr, func) in coercions {
+        let name = Qualified {
+            path: decl.namespace.clone(),
+            name: Symbol::intern(name),
+        };
+
+        let mut typ = func.clone();
+
+        for (name, binder) in type_decl.binders.iter().rev() {
+            typ = Type::forall(Forall {
+                name: name.clone(),
+                kind: binder.clone().quote(env.level),
+                body: typ,
+            });
+        }
+
+        ctx.modules.get(&name.path).variables.insert(
+            name.name.clone(),
+            LetDef {
+                typ: typ.eval(start_env),
+                unbound: vec![],
+                ret: func.eval(env),
+                args: vec![],
+            },
+        );
+
+        let decl = elaborated::LetDecl {
+            name: name.clone(),
+            span: span.clone(),
+            visibility: Visibility::Private,
+            typ,
+            binders: vec![],
+            body: vec![elaborated::PatternArm {
+                patterns: vec![],
+                expr: Spanned::new(Box::new(expr), span.clone()),
+                guard: None,
+            }],
+            constants: None,
+            declaration: span.clone(),
+            allow: allow.clone(),
+        };
+
+        lets.push((name, decl));
+    }
+
+    lets
+}
+
 impl Declare for TypeDecl {
-    type Return = (Qualified, elaborated::TypeDecl);
+    type Return = (
+        Qualified,
+        elaborated::TypeDecl,
+        Vec<(Qualified, elaborated::LetDecl<Type<Real>>)>,
+    );
 
     fn declare(&self, (ctx, env): (&mut Context, Env)) {
         let vec = &self.binders;
@@ -229,11 +322,13 @@ impl Declare for TypeDecl {
                 binders: names.into_iter().zip(binders).collect(),
                 module: self.namespace.clone(),
                 def,
+                opaque: self.attributes.opaque,
             },
         );
     }
 
     fn define(&self, (ctx, mut env): (&mut Context, Env)) -> Self::Return {
+        let start_env = env.clone();
         let type_decl = ctx.modules.typ(&self.name);
 
         for (name, binder) in &type_decl.binders {
@@ -248,6 +343,8 @@ impl Declare for TypeDecl {
                 .collect(),
         );
 
+        let mut lets = Vec::new();
+
         let decl = match &self.def {
             TypeDef::Sum(cons) => {
                 let mut constructors = Vec::new();
@@ -266,6 +363,11 @@ impl Declare for TypeDecl {
                         types.push(typ);
                     }
 
+                    if let (true, [arg]) = (self.newtype, types.as_slice()) {
+                        let types = (arg.clone(), ret_type.clone());
+                        lets = coercions(ctx, self, &cons.name, types, (&env, &start_env));
+                    }
+
                     let typ = Type::<Real>::function(types, ret_type.clone());
                     cons_types.push((cons.name.clone(), cons.args.len(), typ));
                 }
@@ -323,7 +425,181 @@ impl Declare for TypeDecl {
             TypeDef::Abstract => elaborated::TypeDecl::Abstract,
         };
 
-        (self.name.clone(), decl)
+        (self.name.clone(), decl, lets)
+    }
+}
+
+/// The name of the function that is synthesized for the default implementation of an operation.
+/// It cannot be written by the user, so it never clashes with other definitions.
+fn default_name(operation: &Qualified) -> Qualified {
+    Qualified {
+        path: operation.path.clone(),
+        name: Symbol::intern(&format!("{}.default", operation.name.get())),
+    }
+}
+
+impl Declare for EffectDecl {
+    type Return = (
+        Qualified,
+        elaborated::TypeDecl,
+        Vec<(Qualified, elaborated::LetDecl<Type<Real>>)>,
+    );
+
+    fn declare(&self, (ctx, env): (&mut Context, Env)) {
+        env.set_current_span(self.span.clone());
+
+        let mut names = Vec::new();
+        let mut binders = Vec::new();
+
+        for binder in &self.binders {
+            let (n, binder) = binder.infer((ctx, env.clone()));
+            binders.push(binder.eval(&env));
+            names.push(n);
+        }
+
+        let kind = Type::<Virtual>::function(binders.clone(), Type::typ());
+
+        let operations = self.fields.iter().map(|x| x.name.clone()).collect();
+
+        let module = ctx.modules.get(&self.name.path);
+
+        module.types.insert(
+            self.name.name.clone(),
+            TypeData {
+                kind,
+                binders: names.into_iter().zip(binders).collect(),
+                module: self.namespace.clone(),
+                def: Def::Effect(operations),
+                opaque: false,
+            },
+        );
+
+        module
+            .effects
+            .insert(self.name.name.clone(), self.span.clone());
+
+        for field in self.fields.iter().filter(|x| x.default.is_some()) {
+            ctx.modules
+                .get(&field.name.path)
+                .defaults
+                .insert(field.name.name.clone(), default_name(&field.name));
+        }
+
+        for field in self.fields.iter().filter(|x| x.resumption != Resumption::Many) {
+            ctx.modules
+                .get(&field.name.path)
+                .resumptions
+                .insert(field.name.name.clone(), field.resumption);
+        }
+    }
+
+    fn define(&self, (ctx, mut env): (&mut Context, Env)) -> Self::Return {
+        let start_env = env.clone();
+        let effect_decl = ctx.modules.typ(&self.name);
+        let allowed = std::mem::replace(&mut ctx.allowed, self.attributes.allow.clone());
+        let module = ctx.module.replace(self.name.path.clone());
+
+        for (name, binder) in &effect_decl.binders {
+            env = env.add(Some(name.clone()), binder.clone());
+        }
+
+        let mut operations = Vec::new();
+        let mut defaults = Vec::new();
+
+        for field in &self.fields {
+            let mut args = Vec::new();
+
+            for arg in &field.args {
+                env.set_current_span(arg.span.clone());
+                let (typ, kind) = arg.infer((ctx, env.clone()));
+                ctx.subsumes(env.clone(), kind, Kind::typ());
+                args.push(typ);
+            }
+
+            env.set_current_span(field.ret.span.clone());
+            let (ret, kind) = field.ret.infer((ctx, env.clone()));
+            ctx.subsumes(env.clone(), kind, Kind::typ());
+
+            let mut typ = Type::<Real>::function(args.clone(), ret.clone());
+
+            for (name, binder) in effect_decl.binders.iter().rev() {
+                typ = Type::forall(Forall {
+                    name: name.clone(),
+                    kind: binder.clone().quote(env.level),
+                    body: typ,
+                });
+            }
+
+            let module = ctx.modules.get(&field.name.path);
+
+            module.operations.insert(
+                field.name.name.clone(),
+                (typ.clone(), field.args.len(), self.name.clone(), field.kind),
+            );
+
+            // Operations are called just like functions, so they are also available as values.
+            module.variables.insert(
+                field.name.name.clone(),
+                LetDef {
+                    typ: typ.eval(&start_env),
+                    unbound: vec![],
+                    ret: ret.eval(&env),
+                    args: args.clone(),
+                },
+            );
+
+            // The default implementation is a function that receives the arguments of the
+            // operation and returns its result, so it is stored just like a let declaration.
+            let default = field.default.as_ref().map(|default| {
+                let name = default_name(&field.name);
+
+                env.set_current_span(default.span.clone());
+                let func = Type::<Real>::function(args.clone(), ret.clone());
+                let expr = default.check(func.eval(&env), (ctx, env.clone()));
+
+                ctx.modules.get(&name.path).variables.insert(
+                    name.name.clone(),
+                    LetDef {
+                        typ: typ.eval(&start_env),
+                        unbound: vec![],
+                        ret: func.eval(&env),
+                        args: vec![],
+                    },
+                );
+
+                defaults.push((
+                    name.clone(),
+                    elaborated::LetDecl {
+                        name: name.clone(),
+                        span: default.span.clone(),
+                        visibility: Visibility::Private,
+                        typ: typ.clone(),
+                        binders: vec![],
+                        body: vec![elaborated::PatternArm {
+                            patterns: vec![],
+                            expr,
+                            guard: None,
+                        }],
+                        constants: None,
+                        declaration: default.span.clone(),
+                        allow: self.attributes.allow.clone(),
+                    },
+                ));
+
+                name
+            });
+
+            operations.push((field.name.clone(), field.args.len(), field.kind, default));
+        }
+
+        ctx.allowed = allowed;
+        ctx.module = module;
+
+        (
+            self.name.clone(),
+            elaborated::TypeDecl::Effect(operations),
+            defaults,
+        )
     }
 }
 
@@ -357,6 +633,24 @@ impl Declare for ExtDecl {
 
         let typ = typ.eval(&start_env);
 
+        let effect = match &self.effect {
+            Some(effect) => {
+                if !matches!(ctx.modules.typ(effect).def, Def::Effect(_)) {
+                    env.set_current_span(self.typ.span.clone());
+                    ctx.report(&env, TypeErrorKind::NotAnEffect(effect.clone()));
+                }
+                effect.clone()
+            }
+            None => ctx.io_effect(),
+        };
+
+        if !self.attributes.pure {
+            ctx.modules
+                .get(&self.namespace)
+                .externals
+                .insert(self.name.name.clone(), effect.clone());
+        }
+
         ctx.modules.get(&self.namespace).variables.insert(
             self.name.name.clone(),
             LetDef {
@@ -372,6 +666,8 @@ impl Declare for ExtDecl {
             elaborated::ExternalDecl {
                 name: self.name.clone(),
                 typ: typ.quote(env.level),
+                effect,
+                convention: self.convention.clone(),
                 binding: self.ret.clone(),
             },
         );
@@ -465,8 +761,12 @@ impl Declare for LetDecl {
 
     fn define(&self, (ctx, mut env): (&mut Context, Env)) -> Self::Return {
         env.set_current_span(self.signature.span.clone());
+        let allowed = std::mem::replace(&mut ctx.allowed, self.attributes.allow.clone());
+        let pure = std::mem::replace(&mut ctx.pure, self.attributes.pure);
+        let module = ctx.module.replace(self.signature.name.path.clone());
 
         let let_decl = ctx.modules.let_decl(&self.signature.name).clone();
+        let decl_typ = let_decl.typ.quote(env.level);
 
         for (fv, typ) in &let_decl.unbound {
             env = env.add(Some(fv.clone()), typ.eval(&env).clone());
@@ -515,64 +815,320 @@ impl Declare for LetDecl {
             }
         }
 
+        ctx.allowed = allowed;
+        ctx.pure = pure;
+        ctx.module = module;
+
         (
             self.signature.name.clone(),
             elaborated::LetDecl {
                 name: self.signature.name.clone(),
+                span: self.signature.span.clone(),
+                visibility: self.signature.visibility.clone(),
+                typ: decl_typ,
                 binders,
                 body,
                 constants: self.constant.clone(),
+                declaration: self.declaration.clone(),
+                allow: self.attributes.allow.clone(),
             },
         )
     }
 }
 
+impl Declare for TestDecl {
+    type Return = elaborated::TestDecl<Type<Real>>;
+
+    fn declare(&self, _context: (&mut Context, Env)) {}
+
+    fn define(&self, (ctx, env): (&mut Context, Env)) -> Self::Return {
+        env.set_current_span(self.span.clone());
+
+        let bool = ctx.find_prelude_type("Bool", env.clone());
+
+        let module = ctx.module.replace(self.name.path.clone());
+        let body = self.body.check(bool.clone(), (ctx, env.clone()));
+        ctx.module = module;
+
+        elaborated::TestDecl {
+            name: self.name.clone(),
+            description: self.description.clone(),
+            span: self.span.clone(),
+            typ: bool.quote(env.level),
+            body,
+        }
+    }
+}
+
 pub struct Programs(pub Vec<Program>);
 
+/// Adds the program and then the programs of the modules declared inside of it, so all of them
+/// are declared before any of them is defined and they can refer to each other in any order.
+fn flatten<'a>(program: &'a Program, programs: &mut Vec<&'a Program>) {
+    programs.push(program);
+
+    for module in &program.modules {
+        if let Some(decls) = &module.decls {
+            flatten(decls, programs);
+        }
+    }
+}
+
+/// Puts the elaborated programs, in the order of [flatten], back inside of the programs of the
+/// modules that declare them.
+fn nest(
+    program: &Program,
+    elaborated: &mut impl Iterator<Item = elaborated::Program<Type<Real>>>,
+) -> elaborated::Program<Type<Real>> {
+    let mut result = elaborated.next().unwrap_or_default();
+
+    for module in &program.modules {
+        if let Some(decls) = &module.decls {
+            let nested = nest(decls, elaborated);
+            result.modules.insert(module.name.clone(), nested);
+        }
+    }
+
+    result
+}
+
 impl Declare for Programs {
-    type Return = Vec<elaborated::Program<Type<Real>>>;
+    type Return = Vec<typed::Module>;
 
     fn declare(&self, (ctx, env): (&mut Context, Env)) {
-        for program in self.0.iter() {
+        let programs = self.flatten();
+
+        for program in &programs {
             program.types.declare((ctx, env.clone()));
         }
 
-        for program in self.0.iter() {
+        for program in &programs {
+            program.effects.declare((ctx, env.clone()));
+        }
+
+        for program in &programs {
             program.lets.declare((ctx, env.clone()));
         }
 
-        for program in self.0.iter() {
+        for program in &programs {
             program.externals.declare((ctx, env.clone()));
         }
 
-        for program in self.0.iter() {
+        for program in &programs {
             program.traits.declare((ctx, env.clone()));
         }
     }
 
     fn define(&self, (context, env): (&mut Context, Env)) -> Self::Return {
-        let mut programs = vec![elaborated::Program::default(); self.0.len()];
+        let flat = self.flatten();
+        let mut programs = vec![elaborated::Program::default(); flat.len()];
 
-        for (i, program) in self.0.iter().enumerate() {
-            let typ = program.types.define((context, env.clone()));
-            programs[i].types = typ.into_iter().collect();
+        for (i, program) in flat.iter().enumerate() {
+            for (name, decl, coercions) in program.types.define((context, env.clone())) {
+                programs[i].types.insert(name, decl);
+                programs[i].lets.extend(coercions);
+            }
         }
 
-        for (i, program) in self.0.iter().enumerate() {
+        for (i, program) in flat.iter().enumerate() {
+            for (name, decl, defaults) in program.effects.define((context, env.clone())) {
+                programs[i].types.insert(name, decl);
+                programs[i].lets.extend(defaults);
+            }
+        }
+
+        for (i, program) in flat.iter().enumerate() {
             let let_decl = program.lets.define((context, env.clone()));
-            programs[i].lets = let_decl.into_iter().collect();
+            programs[i].lets.extend(let_decl);
         }
 
-        for (i, program) in self.0.iter().enumerate() {
+        for (i, program) in flat.iter().enumerate() {
             let ext_decl = program.externals.define((context, env.clone()));
             programs[i].externals = ext_decl.into_iter().collect();
         }
 
-        for (i, program) in self.0.iter().enumerate() {
+        for (i, program) in flat.iter().enumerate() {
             let _trait_decl = program.traits.define((context, env.clone()));
             programs[i].commands = program.commands.clone();
         }
 
+        for (i, program) in flat.iter().enumerate() {
+            programs[i].tests = program.tests.define((context, env.clone()));
+        }
+
+        let mut programs = programs.into_iter();
+
+        self.0
+            .iter()
+            .map(|program| typed::zonk(nest(program, &mut programs)))
+            .collect()
+    }
+}
+
+impl Programs {
+    /// The programs followed by the programs of their inline modules.
+    fn flatten(&self) -> Vec<&Program> {
+        let mut programs = Vec::new();
+
+        for program in &self.0 {
+            flatten(program, &mut programs);
+        }
+
         programs
     }
+
+    /// Type checks one of the programs, that can use the declarations of the others. The others
+    /// are declared and their types and effects are defined too, because constructors and
+    /// operations are registered when they're defined, but their diagnostics are discarded, so
+    /// they're only reported by the program that has them. The programs of inline modules are
+    /// checked together with the program that declares them.
+    pub fn check_one(
+        &self,
+        index: usize,
+        (ctx, env): (&mut Context, Env),
+    ) -> typed::Module {
+        let reporter = ctx.reporter.clone();
+        let sink = vulpi_report::hash_reporter();
+
+        let select = |ctx: &mut Context, i: usize| {
+            ctx.reporter = if i == index {
+                reporter.clone()
+            } else {
+                sink.clone()
+            };
+        };
+
+        // Each program with the program that it belongs to and its position among the programs
+        // of that one.
+        let programs: Vec<_> = self
+            .0
+            .iter()
+            .enumerate()
+            .flat_map(|(i, program)| {
+                let mut flat = Vec::new();
+                flatten(program, &mut flat);
+                flat.into_iter().enumerate().map(move |(j, x)| (i, j, x))
+            })
+            .collect();
+
+        for (i, _, program) in &programs {
+            select(ctx, *i);
+            program.types.declare((ctx, env.clone()));
+        }
+
+        for (i, _, program) in &programs {
+            select(ctx, *i);
+            program.effects.declare((ctx, env.clone()));
+        }
+
+        for (i, _, program) in &programs {
+            select(ctx, *i);
+            program.lets.declare((ctx, env.clone()));
+        }
+
+        for (i, _, program) in &programs {
+            select(ctx, *i);
+            program.externals.declare((ctx, env.clone()));
+        }
+
+        for (i, _, program) in &programs {
+            select(ctx, *i);
+            program.traits.declare((ctx, env.clone()));
+        }
+
+        let own: Vec<_> = programs
+            .iter()
+            .filter(|(i, _, _)| *i == index)
+            .map(|(_, _, program)| *program)
+            .collect();
+
+        let mut elaborated = vec![elaborated::Program::default(); own.len()];
+
+        for (i, j, program) in &programs {
+            select(ctx, *i);
+            for (name, decl, coercions) in program.types.define((ctx, env.clone())) {
+                if *i == index {
+                    elaborated[*j].types.insert(name, decl);
+                    elaborated[*j].lets.extend(coercions);
+                }
+            }
+        }
+
+        for (i, j, program) in &programs {
+            select(ctx, *i);
+            for (name, decl, defaults) in program.effects.define((ctx, env.clone())) {
+                if *i == index {
+                    elaborated[*j].types.insert(name, decl);
+                    elaborated[*j].lets.extend(defaults);
+                }
+            }
+        }
+
+        select(ctx, index);
+
+        for (j, program) in own.iter().enumerate() {
+            let lets = program.lets.define((ctx, env.clone()));
+            elaborated[j].lets.extend(lets);
+        }
+
+        for (j, program) in own.iter().enumerate() {
+            let externals = program.externals.define((ctx, env.clone()));
+            elaborated[j].externals = externals.into_iter().collect();
+        }
+
+        for (j, program) in own.iter().enumerate() {
+            program.traits.define((ctx, env.clone()));
+            elaborated[j].commands = program.commands.clone();
+        }
+
+        for (j, program) in own.iter().enumerate() {
+            elaborated[j].tests = program.tests.define((ctx, env.clone()));
+        }
+
+        typed::zonk(nest(&self.0[index], &mut elaborated.into_iter()))
+    }
+
+    /// Infers the kind of a type that uses the types and the effects of the programs. Only the
+    /// diagnostics of the type itself are reported.
+    pub fn kind(&self, typ: &r#abstract::Type, (ctx, env): (&mut Context, Env)) -> Kind<Real> {
+        let reporter = std::mem::replace(&mut ctx.reporter, vulpi_report::hash_reporter());
+
+        let programs = self.flatten();
+
+        for program in &programs {
+            program.types.declare((ctx, env.clone()));
+        }
+
+        for program in &programs {
+            program.effects.declare((ctx, env.clone()));
+        }
+
+        // The definitions give the kinds of the binders that are used by the constructors.
+        for program in &programs {
+            program.types.define((ctx, env.clone()));
+        }
+
+        for program in &programs {
+            program.effects.define((ctx, env.clone()));
+        }
+
+        ctx.reporter = reporter;
+
+        let (_, kind) = typ.infer((ctx, env.clone()));
+        default_kind(ctx, &kind);
+        kind.quote(env.level)
+    }
+}
+
+/// Fills the holes that are left in a kind with `Type`, like the binders of a type that are not
+/// used by its definition.
+fn default_kind(ctx: &mut Context, kind: &Kind<Virtual>) {
+    match ctx.holes.deref(kind).as_ref() {
+        TypeKind::Hole(hole) => ctx.holes.fill(hole, Kind::typ()),
+        TypeKind::Arrow(pi) => {
+            default_kind(ctx, &pi.typ);
+            default_kind(ctx, &pi.body);
+        }
+        _ => (),
+    }
 }
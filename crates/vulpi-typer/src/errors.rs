@@ -2,15 +2,20 @@
 
 use vulpi_intern::Symbol;
 use vulpi_location::Span;
-use vulpi_report::{IntoDiagnostic, Text};
+use vulpi_report::{IntoDiagnostic, Marker, Severity, Text};
 use vulpi_syntax::r#abstract::Qualified;
 
 use crate::{
     coverage::{Pat, Row},
+    number::Number,
     real::Real,
     Env, Type,
 };
 
+/// The lint of the handlers that may not resume the continuation of a `once` operation exactly
+/// once.
+pub const ONE_SHOT: &str = "one_shot";
+
 pub enum TypeErrorKind {
     EmptyCase,
     UnboundTypeVariable(Symbol),
@@ -30,8 +35,31 @@ pub enum TypeErrorKind {
     DuplicatedField,
     NotFoundField,
     NotARecord,
+    /// A field of an expression whose type is not known, that more than one record in scope has.
+    AmbiguousField(Symbol, Vec<Qualified>),
+    /// The definition of an opaque type used outside of the module that declares it.
+    OpaqueType(Qualified),
+    /// An external of the `IO` effect used by a declaration written with `#[pure]`.
+    ImpureExternal(Qualified),
     MissingField(Symbol),
     NonExhaustive(Row<Pat>),
+    NotAnOperation(Qualified),
+    UnhandledOperation(Vec<Symbol>, Span),
+    UnknownOperation(Symbol, Symbol),
+    NotAnEffect(Qualified),
+    ContinuationInFun(Qualified),
+    PrimitiveOperation(Qualified),
+    RecursiveValue(Symbol),
+    LiteralOutOfRange(Symbol, Symbol),
+    NotOrdinalRange,
+    EmptyRange,
+
+    /// The arm of a handler may resume the continuation of a one-shot operation more than once.
+    /// It's an error if the effect is declared with `once`.
+    ResumedMoreThanOnce(Qualified, bool),
+
+    /// The arm of a handler may not resume the continuation of a one-shot operation.
+    NotResumed(Qualified, bool),
 }
 
 pub struct TypeError {
@@ -71,9 +99,26 @@ impl IntoDiagnostic for TypeError {
             TypeErrorKind::DuplicatedField => Text::from("duplicated field".to_string()),
             TypeErrorKind::NotFoundField => Text::from("not found field".to_string()),
             TypeErrorKind::NotARecord => Text::from("not a record".to_string()),
+            TypeErrorKind::AmbiguousField(name, records) => Text::from(format!(
+                "the field '{}' is ambiguous between the records {}",
+                name.get(),
+                records
+                    .iter()
+                    .map(|record| format!("'{}.{}'", record.path.get(), record.name.get()))
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            )),
             TypeErrorKind::MissingField(name) => {
                 Text::from(format!("missing field: {}", name.get()))
             }
+            TypeErrorKind::OpaqueType(name) => Text::from(format!(
+                "the fields of the opaque type '{}' cannot be used outside of its module",
+                name.name.get()
+            )),
+            TypeErrorKind::ImpureExternal(name) => Text::from(format!(
+                "the external '{}' has the IO effect so it cannot be called by a pure declaration",
+                name.name.get()
+            )),
             TypeErrorKind::MissingLabel(name) => {
                 Text::from(format!("missing label: {}", name.name.get()))
             }
@@ -96,11 +141,181 @@ impl IntoDiagnostic for TypeError {
             TypeErrorKind::NonExhaustive(row) => {
                 Text::from(format!("non-exhaustive patterns: {}", row))
             }
+            TypeErrorKind::NotAnOperation(name) => {
+                Text::from(format!("not an effect operation: {}", name.name.get()))
+            }
+            TypeErrorKind::UnhandledOperation(names, _) => Text::from(format!(
+                "unhandled operations: {}",
+                names
+                    .iter()
+                    .map(|name| name.get())
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            )),
+            TypeErrorKind::ContinuationInFun(name) => Text::from(format!(
+                "the operation '{}' is declared with 'fun' so it has no continuation",
+                name.name.get()
+            )),
+            TypeErrorKind::NotAnEffect(name) => {
+                Text::from(format!("not an effect: {}", name.name.get()))
+            }
+            TypeErrorKind::PrimitiveOperation(name) => Text::from(format!(
+                "the operation '{}' of a primitive effect cannot be handled",
+                name.name.get()
+            )),
+            TypeErrorKind::RecursiveValue(name) => {
+                Text::from(format!("the value '{}' cannot refer to itself", name.get()))
+            }
+            TypeErrorKind::LiteralOutOfRange(literal, typ) => Text::from(format!(
+                "the literal '{}' is not a value of '{}'",
+                literal.get(),
+                typ.get()
+            )),
+            TypeErrorKind::NotOrdinalRange => {
+                Text::from("only integers and characters can be in a range".to_string())
+            }
+            TypeErrorKind::EmptyRange => {
+                Text::from("the start of the range is greater than its end".to_string())
+            }
+            TypeErrorKind::ResumedMoreThanOnce(name, _) => Text::from(format!(
+                "the continuation of '{}' may be resumed more than once",
+                name.name.get()
+            )),
+            TypeErrorKind::NotResumed(name, _) => Text::from(format!(
+                "the continuation of '{}' may not be resumed",
+                name.name.get()
+            )),
+            TypeErrorKind::UnknownOperation(handler, name) => Text::from(format!(
+                "the handler '{}' does not handle an operation called '{}'",
+                handler.get(),
+                name.get()
+            )),
+        }
+    }
+
+    fn hint(&self) -> Option<Text> {
+        match &self.kind {
+            TypeErrorKind::UnhandledOperation(_, _) => Some(Text::from(
+                "add a case for each operation or a forwarding clause".to_string(),
+            )),
+            TypeErrorKind::ContinuationInFun(_) => Some(Text::from(
+                "declare the operation with 'ctl' to capture the continuation".to_string(),
+            )),
+            TypeErrorKind::PrimitiveOperation(name) if name.path.get() == "Prelude.Array" => Some(
+                Text::from("arrays are changed by the operations themselves".to_string()),
+            ),
+            TypeErrorKind::PrimitiveOperation(_) => Some(Text::from(
+                "the cells of 'Ref' are changed by the operations themselves".to_string(),
+            )),
+            TypeErrorKind::RecursiveValue(_) => Some(Text::from(
+                "only functions can be recursive, so add a parameter to it".to_string(),
+            )),
+            TypeErrorKind::LiteralOutOfRange(_, typ) => {
+                let (min, max) = Number::of(&typ.get())?.range()?;
+                Some(Text::from(format!(
+                    "the values of '{}' go from {} to {}",
+                    typ.get(),
+                    min,
+                    max
+                )))
+            }
+            TypeErrorKind::OpaqueType(_) => Some(Text::from(
+                "use the functions of its module to build and inspect its values".to_string(),
+            )),
+            TypeErrorKind::ImpureExternal(_) => Some(Text::from(
+                "remove the #[pure] attribute of the declaration".to_string(),
+            )),
+            TypeErrorKind::AmbiguousField(_, _) => Some(Text::from(
+                "annotate the type of the expression to pick one of them".to_string(),
+            )),
+            TypeErrorKind::EmptyRange => Some(Text::from(
+                "ranges include both ends, so 'a..a' matches only 'a'".to_string(),
+            )),
+            TypeErrorKind::ResumedMoreThanOnce(_, true) | TypeErrorKind::NotResumed(_, true) => {
+                Some(Text::from(
+                    "the effect is declared with 'once', so its continuations must be resumed \
+                     exactly once"
+                        .to_string(),
+                ))
+            }
+            TypeErrorKind::ResumedMoreThanOnce(_, false) | TypeErrorKind::NotResumed(_, false) => {
+                Some(Text::from(
+                    "the operation is declared with 'once', so it must be resumed exactly once"
+                        .to_string(),
+                ))
+            }
+            _ => None,
+        }
+    }
+
+    fn markers(&self) -> Vec<Marker> {
+        match &self.kind {
+            TypeErrorKind::UnhandledOperation(_, effect) if effect.start != effect.end => {
+                vec![Marker {
+                    position: effect.clone(),
+                    subtitle: Some("effect declared here".into()),
+                }]
+            }
+            _ => vec![],
+        }
+    }
+
+    fn code(&self) -> Option<usize> {
+        match &self.kind {
+            TypeErrorKind::EmptyCase => Some(300),
+            TypeErrorKind::UnboundTypeVariable(_) => Some(301),
+            TypeErrorKind::TypeMismatch(_, _, _) => Some(302),
+            TypeErrorKind::KindMismatch(_, _, _) => Some(303),
+            TypeErrorKind::InfiniteType => Some(304),
+            TypeErrorKind::CannotFind(_) => Some(305),
+            TypeErrorKind::AtLeastOneArgument => Some(306),
+            TypeErrorKind::EscapingScope => Some(307),
+            TypeErrorKind::NotAFunctionKind => Some(308),
+            TypeErrorKind::WrongArity(_, _) => Some(309),
+            TypeErrorKind::NotAFunction(_, _) => Some(310),
+            TypeErrorKind::NotImplemented => Some(311),
+            TypeErrorKind::MissingLabel(_) => Some(312),
+            TypeErrorKind::InvalidLabels(_) => Some(313),
+            TypeErrorKind::PatternsNotAllowedHere => Some(314),
+            TypeErrorKind::DuplicatedField => Some(315),
+            TypeErrorKind::NotFoundField => Some(316),
+            TypeErrorKind::NotARecord => Some(317),
+            TypeErrorKind::MissingField(_) => Some(318),
+            TypeErrorKind::NonExhaustive(_) => Some(319),
+            TypeErrorKind::NotAnOperation(_) => Some(320),
+            TypeErrorKind::UnhandledOperation(_, _) => Some(321),
+            TypeErrorKind::UnknownOperation(_, _) => Some(322),
+            TypeErrorKind::NotAnEffect(_) => Some(323),
+            TypeErrorKind::ContinuationInFun(_) => Some(324),
+            TypeErrorKind::PrimitiveOperation(_) => Some(325),
+            TypeErrorKind::RecursiveValue(_) => Some(326),
+            TypeErrorKind::LiteralOutOfRange(_, _) => Some(327),
+            TypeErrorKind::NotOrdinalRange => Some(328),
+            TypeErrorKind::EmptyRange => Some(329),
+            TypeErrorKind::ResumedMoreThanOnce(_, _) => Some(330),
+            TypeErrorKind::NotResumed(_, _) => Some(331),
+            TypeErrorKind::AmbiguousField(_, _) => Some(332),
+            TypeErrorKind::OpaqueType(_) => Some(333),
+            TypeErrorKind::ImpureExternal(_) => Some(334),
+        }
+    }
+
+    fn lint(&self) -> Option<&'static str> {
+        match &self.kind {
+            TypeErrorKind::ResumedMoreThanOnce(_, false) | TypeErrorKind::NotResumed(_, false) => {
+                Some(ONE_SHOT)
+            }
+            _ => None,
         }
     }
 
-    fn severity(&self) -> vulpi_report::Severity {
-        vulpi_report::Severity::Error
+    fn severity(&self) -> Severity {
+        match &self.kind {
+            TypeErrorKind::ResumedMoreThanOnce(_, false) | TypeErrorKind::NotResumed(_, false) => {
+                Severity::Warning
+            }
+            _ => Severity::Error,
+        }
     }
 
     fn location(&self) -> Span {
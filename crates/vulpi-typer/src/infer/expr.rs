@@ -10,18 +10,21 @@ use crate::check::Check;
 use im_rc::HashMap;
 use im_rc::HashSet;
 use vulpi_intern::Symbol;
-use vulpi_location::Spanned;
+use vulpi_location::{Span, Spanned};
 use vulpi_syntax::elaborated;
 use vulpi_syntax::r#abstract::Qualified;
 use vulpi_syntax::{
     r#abstract::Sttm,
-    r#abstract::{Expr, ExprKind, SttmKind},
+    r#abstract::{AppKind, ApplicationExpr, Expr, ExprKind, ProjectionExpr, SttmKind},
 };
 
 use crate::eval::Eval;
 use crate::eval::Quote;
+use crate::module::Def;
 use crate::{context::Context, errors::TypeErrorKind, r#virtual::Virtual, Env, Type};
 
+use super::group;
+use super::handler;
 use super::Infer;
 
 impl Infer for Expr {
@@ -84,13 +87,24 @@ impl Infer for Expr {
                     n.clone(),
                 )),
             ),
-            ExprKind::Function(n) => (
-                ctx.modules.let_decl(n).typ.clone(),
-                Box::new(elaborated::ExprKind::Function(
-                    n.clone(),
-                    ctx.modules.let_decl(n).typ.clone().quote(env.level),
-                )),
-            ),
+            ExprKind::Function(n) => {
+                if ctx.pure && ctx.modules.external_effect(n) == Some(ctx.io_effect()) {
+                    ctx.report(&env, TypeErrorKind::ImpureExternal(n.clone()));
+                }
+
+                // The type is instantiated right away, so the elaborated tree records the
+                // instantiation that is used by monomorphization.
+                let typ = ctx.modules.let_decl(n).typ.clone();
+                let typ = ctx.instantiate_all(&env, &typ);
+
+                (
+                    typ.clone(),
+                    Box::new(elaborated::ExprKind::Function(
+                        n.clone(),
+                        typ.quote(env.level),
+                    )),
+                )
+            }
             ExprKind::Let(e) => {
                 let (val_ty, body_elab) = e.body.infer((ctx, env.clone()));
 
@@ -114,6 +128,9 @@ impl Infer for Expr {
                     })),
                 )
             }
+            ExprKind::LetGroup(group) => {
+                group::infer_group(ctx, env.clone(), group, self.span.clone())
+            }
             ExprKind::Tuple(t) => {
                 let mut types = Vec::new();
                 let mut elaborated = Vec::new();
@@ -133,43 +150,210 @@ impl Infer for Expr {
             }
             ExprKind::Error => (Type::error(), Box::new(elaborated::ExprKind::Error)),
             ExprKind::When(when) => {
-                // TODO: Check mode
-                ctx.errored = false;
-
-                let (_, arms, ret, elab_arms) = when.arms.infer((ctx, env.clone()));
-                let perform = !ctx.errored;
-
-                if arms.len() != when.scrutinee.len() {
-                    ctx.report(
-                        &env,
-                        TypeErrorKind::WrongArity(arms.len(), when.scrutinee.len()),
-                    );
-                }
-
+                let mut scrutinees = Vec::new();
                 let mut elab_scrutinee = Vec::new();
 
-                for (arm, scrutinee) in arms.iter().cloned().zip(when.scrutinee.iter()) {
+                for scrutinee in &when.scrutinee {
                     let (typ, elab) = scrutinee.infer((ctx, env.clone()));
-                    ctx.subsumes(env.clone(), arm, typ);
+                    scrutinees.push(typ);
                     elab_scrutinee.push(elab);
                 }
 
-                if perform {
-                    let arms = arms.iter().map(|x| ctx.instantiate(&env, x)).collect();
+                let size = when.arms.first().map_or(scrutinees.len(), |x| x.patterns.len());
 
-                    let problem = Problem::exhaustiveness(&elab_arms, arms);
+                if size != scrutinees.len() {
+                    ctx.report(&env, TypeErrorKind::WrongArity(size, scrutinees.len()));
+                    (Type::error(), Box::new(elaborated::ExprKind::Error))
+                } else {
+                    // The patterns are checked against the scrutinees, so their literals can take
+                    // the types of the scrutinees.
+                    ctx.errored = false;
+
+                    let ret = ctx.hole(&env, Type::typ());
+                    let typ = Type::<Virtual>::function(scrutinees.clone(), ret.clone());
+                    let elab_arms = when.arms.check(typ, (ctx, env.clone()));
+                    let perform = !ctx.errored;
+
+                    if perform {
+                        let arms = scrutinees
+                            .iter()
+                            .map(|x| ctx.instantiate(&env, x))
+                            .collect();
+
+                        let problem = Problem::exhaustiveness(&elab_arms, arms);
+
+                        if let Witness::NonExhaustive(case) = problem.exaustive(ctx, env.clone()) {
+                            ctx.report(&env, TypeErrorKind::NonExhaustive(case));
+                        };
+                    }
+
+                    (
+                        ret,
+                        Box::new(elaborated::ExprKind::When(elaborated::WhenExpr {
+                            scrutinee: elab_scrutinee,
+                            arms: elab_arms,
+                        })),
+                    )
+                }
+            }
+            ExprKind::Cases(cases) => {
+                ctx.errored = false;
+
+                let (typ, types, _, elab_arms) = cases.arms.infer((ctx, env.clone()));
+
+                if !ctx.errored && !elab_arms.is_empty() {
+                    let types = types.iter().map(|x| ctx.instantiate(&env, x)).collect();
+
+                    let problem = Problem::exhaustiveness(&elab_arms, types);
 
                     if let Witness::NonExhaustive(case) = problem.exaustive(ctx, env.clone()) {
                         ctx.report(&env, TypeErrorKind::NonExhaustive(case));
                     };
                 }
 
-                (
-                    ret,
+                // A `cases` expression is a function that matches directly on its arguments, so
+                // it's elaborated into a chain of lambdas over a `when` expression.
+                let names = types.iter().map(|_| ctx.new_name()).collect::<Vec<_>>();
+
+                let scrutinee = names
+                    .iter()
+                    .map(|name| {
+                        Spanned::new(
+                            Box::new(elaborated::ExprKind::Variable(name.clone())),
+                            self.span.clone(),
+                        )
+                    })
+                    .collect();
+
+                let when = Spanned::new(
                     Box::new(elaborated::ExprKind::When(elaborated::WhenExpr {
-                        scrutinee: elab_scrutinee,
+                        scrutinee,
                         arms: elab_arms,
                     })),
+                    self.span.clone(),
+                );
+
+                let body = names.into_iter().rfold(when, |body, name| {
+                    Spanned::new(
+                        Box::new(elaborated::ExprKind::Lambda(elaborated::LambdaExpr {
+                            param: Box::new(elaborated::PatternKind::Variable(name)),
+                            body,
+                        })),
+                        self.span.clone(),
+                    )
+                });
+
+                (typ, body.data)
+            }
+            ExprKind::Handler(handler) => {
+                let mut body_env = env.clone();
+
+                let effects = handler::handled_effects(ctx, &handler.handler);
+                let defaults = handler::default_operations(ctx, &effects);
+
+                if let Some(name) = &handler.name {
+                    body_env.add_instance(name.clone(), effects);
+                }
+
+                let (comp, elab_expr) = handler.expr.infer((ctx, body_env));
+                let ret = ctx.hole(&env, Type::typ());
+
+                env.set_current_span(self.span.clone());
+
+                let elab_handler = if let ExprKind::Cases(cases) = &handler.handler.data {
+                    elaborated::Handler::Cases(handler::infer_cases(
+                        ctx,
+                        env.clone(),
+                        &cases.arms,
+                        comp,
+                        ret.clone(),
+                    ))
+                } else {
+                    let typ = Type::<Virtual>::function(vec![comp], ret.clone());
+                    elaborated::Handler::Function(handler.handler.check(typ, (ctx, env.clone())))
+                };
+
+                // The result of the `finally` is thrown away, so it must be unit.
+                let finally = handler
+                    .finally
+                    .as_ref()
+                    .map(|x| x.check(Type::tuple(vec![]), (ctx, env.clone())));
+
+                (
+                    ret,
+                    Box::new(elaborated::ExprKind::Handler(elaborated::HandlerExpr {
+                        name: handler.name.clone(),
+                        expr: elab_expr,
+                        handler: elab_handler,
+                        defaults,
+                        finally,
+                    })),
+                )
+            }
+            ExprKind::Operation(op) => {
+                let effects = env.instances.get(&op.handler).cloned().unwrap_or_default();
+
+                match handler::find_operation(ctx, &effects, &op.name) {
+                    Some(name) => (
+                        ctx.modules.let_decl(&name).typ.clone(),
+                        Box::new(elaborated::ExprKind::Operation(op.handler.clone(), name)),
+                    ),
+                    None => {
+                        ctx.report(
+                            &env,
+                            TypeErrorKind::UnknownOperation(op.handler.clone(), op.name.clone()),
+                        );
+                        (Type::error(), Box::new(elaborated::ExprKind::Error))
+                    }
+                }
+            }
+            ExprKind::Perform(perform) => {
+                let Some((_, arity, _, _)) = ctx.modules.operation(&perform.operation) else {
+                    ctx.report(&env, TypeErrorKind::NotAnOperation(perform.operation.clone()));
+                    return (
+                        Type::error(),
+                        Spanned::new(Box::new(elaborated::ExprKind::Error), self.span.clone()),
+                    );
+                };
+
+                if perform.args.len() > arity {
+                    ctx.report(&env, TypeErrorKind::WrongArity(arity, perform.args.len()));
+                    return (
+                        Type::error(),
+                        Spanned::new(Box::new(elaborated::ExprKind::Error), self.span.clone()),
+                    );
+                }
+
+                // It's elaborated like the application of the operation, so the backends don't
+                // know how the call was written.
+                let func = ExprKind::Function(perform.operation.clone());
+                let func = Spanned::new(func, self.span.clone());
+
+                let app = ExprKind::Application(ApplicationExpr {
+                    app: AppKind::Normal,
+                    func: Box::new(func),
+                    args: perform.args.clone(),
+                });
+
+                return Box::new(Spanned::new(app, self.span.clone())).infer((ctx, env));
+            }
+            ExprKind::Mask(mask) => {
+                let operations = match ctx.modules.typ(&mask.effect).def {
+                    Def::Effect(operations) => operations,
+                    _ => {
+                        ctx.report(&env, TypeErrorKind::NotAnEffect(mask.effect.clone()));
+                        vec![]
+                    }
+                };
+
+                let (typ, expr) = mask.expr.infer((ctx, env.clone()));
+
+                (
+                    typ,
+                    Box::new(elaborated::ExprKind::Mask(elaborated::MaskExpr {
+                        operations,
+                        expr,
+                    })),
                 )
             }
             ExprKind::Do(block) => {
@@ -191,10 +375,9 @@ impl Infer for Expr {
                 (typ, Box::new(elaborated::ExprKind::Literal(elab)))
             }
             ExprKind::Annotation(ann) => {
-                let (expr_typ, elab_expr) = ann.expr.infer((ctx, env.clone()));
                 let (typ, _) = ann.typ.infer((ctx, env.clone()));
                 let right = typ.eval(&env);
-                ctx.subsumes(env.clone(), expr_typ, right.clone());
+                let elab_expr = ann.expr.check(right.clone(), (ctx, env.clone()));
                 (right, elab_expr.data)
             }
             ExprKind::Lambda(lam) => {
@@ -215,52 +398,18 @@ impl Infer for Expr {
                     })),
                 )
             }
-            ExprKind::Projection(expr) => {
-                let (ty, elab_expr) = expr.expr.infer((ctx, env.clone()));
-                let (head, spine) = ty.application_spine();
-
-                let TypeKind::Variable(name) = head.as_ref() else {
-                    ctx.report(&env, TypeErrorKind::NotARecord);
-                    return (
-                        Type::error(),
-                        Spanned::new(Box::new(elaborated::ExprKind::Error), self.span.clone()),
-                    );
-                };
-
-                let typ = ctx.modules.typ(name);
-
-                let crate::module::Def::Record(rec) = typ.def else {
-                    ctx.report(&env, TypeErrorKind::NotARecord);
-                    return (
-                        Type::error(),
-                        Spanned::new(Box::new(elaborated::ExprKind::Error), self.span.clone()),
-                    );
-                };
+            ExprKind::Projection(projection) => {
+                return infer_projection(projection, None, (ctx, env), self.span.clone());
+            }
+            ExprKind::RecordInstance(instance) => {
+                let typ = ctx.modules.typ(&instance.name);
 
-                let Some(field_name) = rec.iter().find(|x| x.name == expr.field) else {
-                    ctx.report(&env, TypeErrorKind::NotFoundField);
+                if !ctx.check_opaque(&env, &instance.name, &typ) {
                     return (
                         Type::error(),
                         Spanned::new(Box::new(elaborated::ExprKind::Error), self.span.clone()),
                     );
-                };
-
-                let field = ctx.modules.field(field_name);
-
-                let eval_ty = field.eval(&env);
-
-                (
-                    ctx.instantiate_with_arguments(&eval_ty, spine),
-                    Box::new(elaborated::ExprKind::Projection(
-                        elaborated::ProjectionExpr {
-                            expr: elab_expr,
-                            field: field_name.clone(),
-                        },
-                    )),
-                )
-            }
-            ExprKind::RecordInstance(instance) => {
-                let typ = ctx.modules.typ(&instance.name);
+                }
 
                 let crate::module::Def::Record(rec) = typ.def else {
                     ctx.report(&env, TypeErrorKind::NotARecord);
@@ -351,6 +500,13 @@ impl Infer for Expr {
                     );
                 };
 
+                if !ctx.check_opaque(&env, name, &typ) {
+                    return (
+                        Type::error(),
+                        Spanned::new(Box::new(elaborated::ExprKind::Error), self.span.clone()),
+                    );
+                }
+
                 let crate::module::Def::Record(rec) = &typ.def else {
                     ctx.report(&env, TypeErrorKind::NotARecord);
                     return (
@@ -409,6 +565,109 @@ impl Infer for Expr {
     }
 }
 
+/// Infers the type of a field projection. The type of the expression picks the record of the
+/// field, and when it's not known yet, the only record with the field does, or the only one whose
+/// field can have the expected type.
+pub fn infer_projection(
+    projection: &ProjectionExpr,
+    expected: Option<&Type<Virtual>>,
+    (ctx, env): (&mut Context, Env),
+    span: Span,
+) -> (Type<Virtual>, elaborated::Expr<Type<Real>>) {
+    let error = || {
+        let error = Box::new(elaborated::ExprKind::Error);
+        (Type::error(), Spanned::new(error, span.clone()))
+    };
+
+    let (ty, elab_expr) = projection.expr.infer((ctx, env.clone()));
+    let (head, mut spine) = ty.application_spine();
+
+    let mut candidates = projection.candidates.clone();
+
+    if let (TypeKind::Hole(_), Some(expected)) = (head.deref().as_ref(), expected) {
+        let fitting: Vec<_> = candidates
+            .iter()
+            .filter(|record| {
+                ctx.attempt(|ctx| {
+                    let Some((record_type, field)) = ctx.field_of(&env, record, &projection.field)
+                    else {
+                        return Err(Box::new(TypeErrorKind::NotFoundField));
+                    };
+
+                    ctx.unify(env.clone(), ty.clone(), record_type)?;
+                    ctx.unify(env.clone(), field, expected.clone())
+                })
+            })
+            .cloned()
+            .collect();
+
+        if !fitting.is_empty() {
+            candidates = fitting;
+        }
+    }
+
+    let name = match (head.deref().as_ref(), candidates.as_slice()) {
+        (TypeKind::Variable(name), _) => name.clone(),
+
+        // The type is not known yet, so the only record with the field decides it.
+        (TypeKind::Hole(_), [record]) => {
+            spine = ctx
+                .modules
+                .typ(record)
+                .binders
+                .iter()
+                .map(|x| ctx.hole::<Virtual>(&env, x.1.clone()))
+                .collect();
+
+            let record_type = Type::variable(record.clone());
+            let record_type = Type::<Virtual>::application(record_type, spine.clone());
+
+            ctx.subsumes(env.clone(), ty.clone(), record_type);
+            record.clone()
+        }
+        (TypeKind::Hole(_), [_, _, ..]) => {
+            let field = projection.field.clone();
+            let candidates = projection.candidates.clone();
+            ctx.report(&env, TypeErrorKind::AmbiguousField(field, candidates));
+            return error();
+        }
+        _ => {
+            ctx.report(&env, TypeErrorKind::NotARecord);
+            return error();
+        }
+    };
+
+    let typ = ctx.modules.typ(&name);
+
+    if !ctx.check_opaque(&env, &name, &typ) {
+        return error();
+    }
+
+    let crate::module::Def::Record(rec) = typ.def else {
+        ctx.report(&env, TypeErrorKind::NotARecord);
+        return error();
+    };
+
+    let Some(field_name) = rec.iter().find(|x| x.name == projection.field) else {
+        ctx.report(&env, TypeErrorKind::NotFoundField);
+        return error();
+    };
+
+    let field = ctx.modules.field(field_name);
+
+    let eval_ty = field.eval(&env);
+
+    let projection = elaborated::ProjectionExpr {
+        expr: elab_expr,
+        field: field_name.clone(),
+    };
+
+    (
+        ctx.instantiate_with_arguments(&eval_ty, spine),
+        Spanned::new(Box::new(elaborated::ExprKind::Projection(projection)), span),
+    )
+}
+
 impl Infer for Sttm {
     type Return = (Type<Virtual>, Env, elaborated::Statement<Type<Real>>);
 
@@ -0,0 +1,210 @@
+//! Inference of let groups. The bindings of a group are split into the sets of bindings that refer
+//! to each other, the strongly connected components of the group, and each set is inferred after
+//! the ones that it uses, so the types of the bindings that it uses are already known. Just like
+//! the other lets, the bindings are not generalized.
+
+use std::collections::HashMap;
+
+use vulpi_intern::Symbol;
+use vulpi_location::{Span, Spanned};
+use vulpi_syntax::{
+    elaborated,
+    r#abstract::{Expr, ExprKind, LetBinding, LetGroupExpr},
+    visit::{Visit, Visitor},
+};
+
+use crate::{context::Context, errors::TypeErrorKind, r#virtual::Virtual, real::Real, Env, Type};
+
+use super::Infer;
+
+/// The bindings of the group that an expression uses.
+struct Uses<'a> {
+    names: &'a HashMap<Symbol, usize>,
+    found: Vec<usize>,
+}
+
+impl Visitor for Uses<'_> {
+    fn visit_expr(&mut self, expr: &Expr) {
+        if let ExprKind::Variable(name) = &expr.data {
+            self.found.extend(self.names.get(name).copied());
+        }
+
+        expr.walk(self)
+    }
+}
+
+/// The strongly connected components of the graph, found with the algorithm of Tarjan. Each one
+/// comes after the components that it has edges to.
+struct Components<'a> {
+    edges: &'a [Vec<usize>],
+    index: Vec<Option<usize>>,
+    low: Vec<usize>,
+    stack: Vec<usize>,
+    on_stack: Vec<bool>,
+    counter: usize,
+    components: Vec<Vec<usize>>,
+}
+
+impl Components<'_> {
+    fn visit(&mut self, node: usize) {
+        self.index[node] = Some(self.counter);
+        self.low[node] = self.counter;
+        self.counter += 1;
+
+        self.stack.push(node);
+        self.on_stack[node] = true;
+
+        for &next in &self.edges[node] {
+            match self.index[next] {
+                None => {
+                    self.visit(next);
+                    self.low[node] = self.low[node].min(self.low[next]);
+                }
+                Some(index) if self.on_stack[next] => {
+                    self.low[node] = self.low[node].min(index);
+                }
+                Some(_) => (),
+            }
+        }
+
+        if Some(self.low[node]) == self.index[node] {
+            let mut component = Vec::new();
+
+            while let Some(top) = self.stack.pop() {
+                self.on_stack[top] = false;
+                component.push(top);
+
+                if top == node {
+                    break;
+                }
+            }
+
+            component.sort();
+            self.components.push(component);
+        }
+    }
+}
+
+fn components(edges: &[Vec<usize>]) -> Vec<Vec<usize>> {
+    let mut components = Components {
+        edges,
+        index: vec![None; edges.len()],
+        low: vec![0; edges.len()],
+        stack: Vec::new(),
+        on_stack: vec![false; edges.len()],
+        counter: 0,
+        components: Vec::new(),
+    };
+
+    for node in 0..edges.len() {
+        if components.index[node].is_none() {
+            components.visit(node);
+        }
+    }
+
+    components.components
+}
+
+fn is_function(binding: &LetBinding) -> bool {
+    matches!(binding.body.data, ExprKind::Lambda(_) | ExprKind::Cases(_))
+}
+
+/// The part of the elaborated group that binds a component.
+enum Part {
+    Let(Symbol, elaborated::Expr<Type<Real>>),
+    Rec(Vec<(Symbol, elaborated::Expr<Type<Real>>)>),
+}
+
+/// Infers a let group. The components that don't refer to themselves become lets and the other
+/// ones become recursive lets, in the order that they're inferred.
+pub fn infer_group(
+    ctx: &mut Context,
+    mut env: Env,
+    group: &LetGroupExpr,
+    span: Span,
+) -> (Type<Virtual>, Box<elaborated::ExprKind<Type<Real>>>) {
+    let names: HashMap<_, _> = group
+        .bindings
+        .iter()
+        .enumerate()
+        .map(|(i, binding)| (binding.name.clone(), i))
+        .collect();
+
+    let edges: Vec<_> = group
+        .bindings
+        .iter()
+        .map(|binding| {
+            let mut uses = Uses {
+                names: &names,
+                found: Vec::new(),
+            };
+
+            uses.visit_expr(&binding.body);
+            uses.found.sort();
+            uses.found.dedup();
+            uses.found
+        })
+        .collect();
+
+    let mut parts = Vec::new();
+
+    for component in components(&edges) {
+        let recursive = component.len() > 1 || edges[component[0]].contains(&component[0]);
+
+        if !recursive {
+            let binding = &group.bindings[component[0]];
+            let (typ, elab) = binding.body.infer((ctx, env.clone()));
+            env.add_var(binding.name.clone(), typ);
+            parts.push(Part::Let(binding.name.clone(), elab));
+            continue;
+        }
+
+        let holes: Vec<_> = component
+            .iter()
+            .map(|&i| {
+                let hole = ctx.hole::<Virtual>(&env, Type::typ());
+                env.add_var(group.bindings[i].name.clone(), hole.clone());
+                hole
+            })
+            .collect();
+
+        let mut bindings = Vec::new();
+
+        for (&i, hole) in component.iter().zip(holes) {
+            let binding = &group.bindings[i];
+
+            if !is_function(binding) {
+                env.set_current_span(binding.body.span.clone());
+                ctx.report(&env, TypeErrorKind::RecursiveValue(binding.name.clone()));
+            }
+
+            let (typ, elab) = binding.body.infer((ctx, env.clone()));
+
+            env.set_current_span(binding.body.span.clone());
+            ctx.subsumes(env.clone(), hole, typ);
+
+            bindings.push((binding.name.clone(), elab));
+        }
+
+        parts.push(Part::Rec(bindings));
+    }
+
+    let (typ, value) = group.value.infer((ctx, env.clone()));
+
+    let elab = parts.into_iter().rfold(value, |next, part| {
+        let kind = match part {
+            Part::Let(name, body) => elaborated::ExprKind::Let(elaborated::LetExpr {
+                pattern: Box::new(elaborated::PatternKind::Variable(name)),
+                body,
+                next,
+            }),
+            Part::Rec(bindings) => {
+                elaborated::ExprKind::LetRec(elaborated::LetRecExpr { bindings, next })
+            }
+        };
+
+        Spanned::new(Box::new(kind), span.clone())
+    });
+
+    (typ, elab.data)
+}
@@ -0,0 +1,281 @@
+//! Inference of effect handlers. A handler that is written with `cases` receives the requests of
+//! the computation that it handles, so the arms can match on operations using effect patterns
+//! like `{ Log.log e -> k }` or on the final value using ordinary patterns, that can be written
+//! after a `return` to make it clear.
+
+use std::collections::HashMap;
+
+use vulpi_intern::Symbol;
+use vulpi_syntax::{
+    elaborated,
+    r#abstract::{
+        Expr, ExprKind, OperationKind, PatEffect, Pattern, PatternArm, PatternKind, Qualified,
+        Resumption,
+    },
+};
+
+use crate::{
+    check::Check,
+    context::Context,
+    coverage,
+    errors::TypeErrorKind,
+    eval::{Eval, Quote},
+    module::Def,
+    r#virtual::Virtual,
+    real::Real,
+    Env, Type,
+};
+
+use super::{
+    resume::{self, Calls},
+    Infer,
+};
+
+/// Infers the arms of a handler that is written with `cases`. The `comp` type is the type of the
+/// computation that is being handled and `ret` is the type of the whole handler expression.
+pub fn infer_cases(
+    ctx: &mut Context,
+    env: Env,
+    arms: &[PatternArm],
+    comp: Type<Virtual>,
+    ret: Type<Virtual>,
+) -> Vec<elaborated::PatternArm<Type<Real>>> {
+    let mut elab_arms = Vec::new();
+    let mut forwards = false;
+
+    for arm in arms {
+        let mut env = env.clone();
+
+        if arm.patterns.len() != 1 {
+            ctx.report(&env, TypeErrorKind::WrongArity(1, arm.patterns.len()));
+            continue;
+        }
+
+        let pat = match &arm.patterns[0].data {
+            PatternKind::Return(pat) => pat,
+            _ => &arm.patterns[0],
+        };
+
+        let mut map = Default::default();
+
+        env.set_current_span(pat.span.clone());
+
+        let (elab_pat, body_ty) = match &pat.data {
+            PatternKind::Effect(eff) => {
+                effect_pattern(ctx, env.clone(), eff, ret.clone(), &mut map)
+            }
+            _ => {
+                // A guard can fail, so a guarded arm doesn't forward everything.
+                forwards |= arm.guard.is_none() && is_forwarding(pat);
+                let elab_pat = pat.check(comp.clone(), (ctx, &mut map, env.clone()));
+                (elab_pat, ret.clone())
+            }
+        };
+
+        for binding in map {
+            env.add_var(binding.0, binding.1);
+        }
+
+        let guard = arm.guard.as_ref().map(|g| g.infer((ctx, env.clone())));
+
+        let elab_guard = if let Some((typ, guard)) = guard {
+            let bool = ctx.find_prelude_type("Bool", env.clone());
+            ctx.subsumes(env.clone(), typ, bool);
+            Some(guard)
+        } else {
+            None
+        };
+
+        let elab_expr = arm.expr.check(body_ty, (ctx, env.clone()));
+
+        if let PatternKind::Effect(eff) = &pat.data {
+            env.set_current_span(pat.span.clone());
+            check_resumption(ctx, &env, eff, arm);
+        }
+
+        elab_arms.push(elaborated::PatternArm {
+            patterns: vec![elab_pat],
+            expr: elab_expr,
+            guard: elab_guard,
+        });
+    }
+
+    // Without a forwarding clause the final value of the computation goes through the handler
+    // untouched.
+    if !forwards {
+        ctx.subsumes(env.clone(), comp, ret);
+
+        for (effect, missing) in coverage::unhandled_operations(ctx, env.clone(), &elab_arms) {
+            let span = ctx
+                .modules
+                .get(&effect.path)
+                .effects
+                .get(&effect.name)
+                .cloned()
+                .unwrap_or_default();
+
+            let names = missing.into_iter().map(|x| x.name).collect();
+
+            ctx.report(&env, TypeErrorKind::UnhandledOperation(names, span));
+        }
+    }
+
+    elab_arms
+}
+
+/// Effects whose operations are matched by a handler. A named handler can only perform the
+/// operations of these effects.
+pub fn handled_effects(ctx: &mut Context, handler: &Expr) -> Vec<Qualified> {
+    let mut effects = Vec::new();
+
+    if let ExprKind::Cases(cases) = &handler.data {
+        for arm in &cases.arms {
+            for pat in &arm.patterns {
+                if let PatternKind::Effect(eff) = &pat.data {
+                    if let Some((_, _, effect, _)) = ctx.modules.operation(&eff.func) {
+                        if !effects.contains(&effect) {
+                            effects.push(effect);
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    effects
+}
+
+/// The operations of the effects that have a default implementation alongside the function that
+/// implements it. The handler falls back to these functions for requests that it does not match.
+pub fn default_operations(ctx: &mut Context, effects: &[Qualified]) -> Vec<(Qualified, Qualified)> {
+    let mut defaults = Vec::new();
+
+    for effect in effects {
+        let Def::Effect(operations) = ctx.modules.typ(effect).def else {
+            continue;
+        };
+
+        for operation in operations {
+            if let Some(default) = ctx.modules.operation_default(&operation) {
+                defaults.push((operation, default));
+            }
+        }
+    }
+
+    defaults
+}
+
+/// Finds the operation with the given name in one of the effects.
+pub fn find_operation(
+    ctx: &mut Context,
+    effects: &[Qualified],
+    name: &Symbol,
+) -> Option<Qualified> {
+    effects.iter().find_map(|effect| {
+        let Def::Effect(operations) = ctx.modules.typ(effect).def else {
+            return None;
+        };
+
+        operations.into_iter().find(|x| &x.name == name)
+    })
+}
+
+/// Reports the arm of an operation declared with `once` if it may not resume the continuation
+/// exactly once. An arm that doesn't bind the continuation never resumes it.
+fn check_resumption(ctx: &mut Context, env: &Env, eff: &PatEffect, arm: &PatternArm) {
+    let strict = match ctx.modules.operation_resumption(&eff.func) {
+        Resumption::Many => return,
+        Resumption::Once => false,
+        Resumption::Linear => true,
+    };
+
+    let calls = match &eff.cont {
+        Some(cont) => resume::calls(&arm.expr, cont),
+        None => Calls::ZERO,
+    };
+
+    if calls.more_than_once() {
+        let kind = TypeErrorKind::ResumedMoreThanOnce(eff.func.clone(), strict);
+        ctx.report(env, kind);
+    } else if calls.maybe_none() {
+        ctx.report(env, TypeErrorKind::NotResumed(eff.func.clone(), strict));
+    }
+}
+
+/// A forwarding clause is an arm without a guard whose pattern cannot fail, so everything that is
+/// not handled by the other arms goes to it.
+fn is_forwarding(pat: &Pattern) -> bool {
+    match &pat.data {
+        PatternKind::Wildcard | PatternKind::Variable(_) => true,
+        PatternKind::Ascription(asc) => is_forwarding(&asc.pat),
+        _ => false,
+    }
+}
+
+/// Checks an effect pattern and returns the type that the body of the arm must have. The body of a
+/// `fun` operation is the result of the operation, while the body of a `ctl` operation is the
+/// result of the whole handler.
+fn effect_pattern(
+    ctx: &mut Context,
+    env: Env,
+    eff: &PatEffect,
+    ret: Type<Virtual>,
+    map: &mut HashMap<Symbol, Type<Virtual>>,
+) -> (elaborated::Pattern, Type<Virtual>) {
+    let error = (Box::new(elaborated::PatternKind::Error), ret.clone());
+
+    let Some((typ, arity, effect, kind)) = ctx.modules.operation(&eff.func) else {
+        ctx.report(&env, TypeErrorKind::NotAnOperation(eff.func.clone()));
+        return error;
+    };
+
+    if arity != eff.args.len() {
+        ctx.report(&env, TypeErrorKind::WrongArity(arity, eff.args.len()));
+        return error;
+    }
+
+    let mut typ = ctx.instantiate_all(&env, &typ.eval(&env));
+    let mut args = Vec::new();
+
+    for arg in &eff.args {
+        let Some((param_ty, rest)) = ctx.as_function(&env, typ.clone()) else {
+            ctx.report(
+                &env,
+                TypeErrorKind::NotAFunction(env.clone(), typ.quote(env.level)),
+            );
+            return error;
+        };
+
+        args.push(arg.check(param_ty, (ctx, map, env.clone())));
+        typ = rest;
+    }
+
+    // The continuation receives the result of the operation and resumes the handled computation.
+    if let Some(cont) = &eff.cont {
+        map.insert(cont.clone(), Type::<Virtual>::function(vec![typ.clone()], ret.clone()));
+    }
+
+    let body_ty = match kind {
+        OperationKind::Fun => typ,
+        OperationKind::Ctl => ret,
+    };
+
+    // The operations of the primitive effects are run by the backends without looking for a
+    // handler. The binders of the pattern are still typed, so the body of the arm is checked.
+    if crate::context::primitive_effects().contains(&effect) {
+        ctx.report(&env, TypeErrorKind::PrimitiveOperation(eff.func.clone()));
+        return (Box::new(elaborated::PatternKind::Error), body_ty);
+    }
+
+    if kind == OperationKind::Fun && eff.cont.is_some() {
+        ctx.report(&env, TypeErrorKind::ContinuationInFun(eff.func.clone()));
+    }
+
+    let elab_pat = Box::new(elaborated::PatternKind::Effect(elaborated::PatEffect {
+        func: eff.func.clone(),
+        args,
+        cont: eff.cont.clone(),
+    }));
+
+    (elab_pat, body_ty)
+}
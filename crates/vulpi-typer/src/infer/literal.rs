@@ -1,9 +1,12 @@
 //! Inference of literals
 
+use vulpi_intern::Symbol;
 use vulpi_syntax::{elaborated, r#abstract::Literal, r#abstract::LiteralKind};
 
 use super::Infer;
-use crate::{context::Context, r#virtual::Virtual, Env, Type};
+use crate::{
+    context::Context, errors::TypeErrorKind, number::Number, r#virtual::Virtual, Env, Type,
+};
 
 impl Infer for Literal {
     type Return = (Type<Virtual>, elaborated::Literal);
@@ -18,14 +21,28 @@ impl Infer for Literal {
                 ctx.find_prelude_type("String", env),
                 Box::new(elaborated::LiteralKind::String(n.clone())),
             ),
-            LiteralKind::Integer(n) => (
-                ctx.find_prelude_type("Int", env),
-                Box::new(elaborated::LiteralKind::Integer(n.clone())),
-            ),
-            LiteralKind::Float(n) => (
-                ctx.find_prelude_type("Float", env),
-                Box::new(elaborated::LiteralKind::Float(n.clone())),
-            ),
+            LiteralKind::Integer(n) => {
+                if !Number::INT.fits(&n.get()) {
+                    let name = Symbol::intern("Int");
+                    ctx.report(&env, TypeErrorKind::LiteralOutOfRange(n.clone(), name));
+                }
+
+                (
+                    ctx.find_prelude_type("Int", env),
+                    Box::new(elaborated::LiteralKind::Integer(n.clone())),
+                )
+            }
+            LiteralKind::Float(n) => {
+                if !Number::FLOAT.fits(&n.get()) {
+                    let name = Symbol::intern("Float");
+                    ctx.report(&env, TypeErrorKind::LiteralOutOfRange(n.clone(), name));
+                }
+
+                (
+                    ctx.find_prelude_type("Float", env),
+                    Box::new(elaborated::LiteralKind::Float(n.clone())),
+                )
+            }
             LiteralKind::Char(n) => (
                 ctx.find_prelude_type("Char", env),
                 Box::new(elaborated::LiteralKind::Char(n.clone())),
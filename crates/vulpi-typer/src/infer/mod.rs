@@ -4,9 +4,12 @@
 use vulpi_location::Spanned;
 
 pub mod expr;
+pub mod group;
+pub mod handler;
 pub mod kind;
 pub mod literal;
 pub mod pat;
+pub mod resume;
 pub mod r#type;
 
 /// The inference trait. It descovers the type of an expression based on the context.
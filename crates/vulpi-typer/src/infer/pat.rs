@@ -11,6 +11,7 @@ use vulpi_syntax::{
 };
 
 use crate::{
+    check::Check,
     context::Context,
     errors::TypeErrorKind,
     real::Real,
@@ -125,6 +126,56 @@ impl Infer for Vec<PatternArm> {
     }
 }
 
+/// The pattern of a range, after its ends were checked against the type of the scrutinee.
+pub fn elaborate_range(
+    ctx: &mut Context,
+    env: Env,
+    start: elaborated::Literal,
+    end: elaborated::Literal,
+) -> elaborated::Pattern {
+    let ordinal = |literal: &elaborated::Literal| match &**literal {
+        elaborated::LiteralKind::Integer(_) | elaborated::LiteralKind::Char(_) => literal.ordinal(),
+        _ => None,
+    };
+
+    match (ordinal(&start), ordinal(&end)) {
+        (Some(lo), Some(hi)) if lo > hi => ctx.report(&env, TypeErrorKind::EmptyRange),
+        (Some(_), Some(_)) => return Box::new(elaborated::PatternKind::Range(start, end)),
+        _ => ctx.report(&env, TypeErrorKind::NotOrdinalRange),
+    }
+
+    Box::new(elaborated::PatternKind::Error)
+}
+
+/// The alternatives of an or-pattern, without the nested or-patterns.
+pub fn flatten_or<'a>(pattern: &'a Pattern, alternatives: &mut Vec<&'a Pattern>) {
+    match &pattern.data {
+        PatternKind::Or(or) => {
+            flatten_or(&or.left, alternatives);
+            flatten_or(&or.right, alternatives);
+        }
+        _ => alternatives.push(pattern),
+    }
+}
+
+/// Adds the variables of an alternative of an or-pattern to the ones of the first alternative,
+/// so each variable has the same type in all of them.
+pub fn merge_bindings(
+    ctx: &mut Context,
+    env: &Env,
+    map: &mut HashMap<Symbol, Type<Virtual>>,
+    bindings: HashMap<Symbol, Type<Virtual>>,
+) {
+    for (name, typ) in bindings {
+        match map.get(&name) {
+            Some(first) => ctx.subsumes(env.clone(), typ, first.clone()),
+            None => {
+                map.insert(name, typ);
+            }
+        }
+    }
+}
+
 impl Infer for Pattern {
     type Return = (Type<Virtual>, elaborated::Pattern);
 
@@ -171,6 +222,12 @@ impl Infer for Pattern {
                 let (typ, lit) = lit.infer((ctx, env));
                 (typ, Box::new(elaborated::PatternKind::Literal(lit)))
             }
+            PatternKind::Range(range) => {
+                let (typ, start) = range.start.infer((ctx, env.clone()));
+                let end = range.end.check(typ.clone(), (ctx, env.clone()));
+                env.set_current_span(self.span.clone());
+                (typ, elaborate_range(ctx, env, start, end))
+            }
             PatternKind::Ascription(ann) => {
                 let (typ, _) = ann.typ.infer((ctx, env.clone()));
                 let eval_typ = typ.eval(&env);
@@ -179,7 +236,38 @@ impl Infer for Pattern {
                 (eval_typ, pat)
             }
             PatternKind::Or(_) => {
-                unimplemented!("Or patterns are not yet implemented")
+                let mut alternatives = Vec::new();
+                flatten_or(self, &mut alternatives);
+
+                let (typ, first) = alternatives[0].infer((ctx, map, env.clone()));
+                let mut elab_alternatives = vec![first];
+
+                for alternative in &alternatives[1..] {
+                    let mut bindings = HashMap::new();
+                    let (alt_ty, elab) = alternative.infer((ctx, &mut bindings, env.clone()));
+                    ctx.subsumes(env.clone(), alt_ty, typ.clone());
+                    merge_bindings(ctx, &env, map, bindings);
+                    elab_alternatives.push(elab);
+                }
+
+                (
+                    typ,
+                    Box::new(elaborated::PatternKind::Or(elab_alternatives)),
+                )
+            }
+            PatternKind::As(as_) => {
+                let (typ, pat) = as_.pattern.infer((ctx, map, env.clone()));
+
+                if let Some(value) = map.get(&as_.name) {
+                    ctx.subsumes(env, value.clone(), typ.clone());
+                } else {
+                    map.insert(as_.name.clone(), typ.clone());
+                }
+
+                (
+                    typ,
+                    Box::new(elaborated::PatternKind::As(as_.name.clone(), pat)),
+                )
             }
             PatternKind::Application(app) => {
                 let (typ, arity, _) = ctx.modules.constructor(&app.func);
@@ -217,6 +305,17 @@ impl Infer for Pattern {
                     })),
                 )
             }
+            PatternKind::Return(pat) => {
+                ctx.report(&env, TypeErrorKind::PatternsNotAllowedHere);
+
+                // The variables of the pattern are still bound, so the body can use them.
+                let (typ, _) = pat.infer((ctx, map, env.clone()));
+                (typ, Box::new(elaborated::PatternKind::Error))
+            }
+            PatternKind::Effect(_) => {
+                ctx.report(&env, TypeErrorKind::PatternsNotAllowedHere);
+                (Type::error(), Box::new(elaborated::PatternKind::Error))
+            }
             PatternKind::Error => (Type::error(), Box::new(elaborated::PatternKind::Error)),
         }
     }
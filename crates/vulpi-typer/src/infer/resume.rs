@@ -0,0 +1,173 @@
+//! Counts how many times the arm of a handler may resume a continuation, so the handlers of the
+//! operations declared with `once` can be checked to resume it exactly once.
+
+use vulpi_intern::Symbol;
+use vulpi_syntax::r#abstract::{Block, Expr, ExprKind, Pattern, PatternArm, PatternKind, SttmKind};
+
+/// The least and the most number of times that an expression may call the continuation. The most
+/// is `None` when there's no bound, like for calls inside of a function.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Calls {
+    pub least: usize,
+    pub most: Option<usize>,
+}
+
+impl Calls {
+    pub const ZERO: Calls = Calls {
+        least: 0,
+        most: Some(0),
+    };
+
+    const ONE: Calls = Calls {
+        least: 1,
+        most: Some(1),
+    };
+
+    /// The calls of two expressions that run one after the other.
+    fn then(self, other: Calls) -> Calls {
+        Calls {
+            least: self.least + other.least,
+            most: self.most.zip(other.most).map(|(x, y)| x + y),
+        }
+    }
+
+    /// The calls of one of two expressions, when only one of them runs.
+    fn or(self, other: Calls) -> Calls {
+        Calls {
+            least: self.least.min(other.least),
+            most: self.most.zip(other.most).map(|(x, y)| x.max(y)),
+        }
+    }
+
+    /// The calls of a function that may be called any number of times.
+    fn repeated(self) -> Calls {
+        if self == Calls::ZERO {
+            self
+        } else {
+            Calls {
+                least: 0,
+                most: None,
+            }
+        }
+    }
+
+    pub fn more_than_once(&self) -> bool {
+        !matches!(self.most, Some(0 | 1))
+    }
+
+    pub fn maybe_none(&self) -> bool {
+        self.least == 0
+    }
+}
+
+/// Checks if the pattern binds the name again, so the continuation cannot be used after it.
+fn binds(pattern: &Pattern, name: &Symbol) -> bool {
+    match &pattern.data {
+        PatternKind::Variable(x) => x == name,
+        PatternKind::As(x) => &x.name == name || binds(&x.pattern, name),
+        PatternKind::Ascription(x) => binds(&x.pat, name),
+        PatternKind::Return(x) => binds(x, name),
+        PatternKind::Or(x) => binds(&x.left, name),
+        PatternKind::Tuple(x) => x.iter().any(|x| binds(x, name)),
+        PatternKind::Application(x) => x.args.iter().any(|x| binds(x, name)),
+        PatternKind::Effect(x) => {
+            x.cont.as_ref() == Some(name) || x.args.iter().any(|x| binds(x, name))
+        }
+        PatternKind::Wildcard
+        | PatternKind::Literal(_)
+        | PatternKind::Range(_)
+        | PatternKind::Error => false,
+    }
+}
+
+fn arm(arm: &PatternArm, name: &Symbol) -> Calls {
+    if arm.patterns.iter().any(|x| binds(x, name)) {
+        return Calls::ZERO;
+    }
+
+    let guard = arm.guard.as_ref().map_or(Calls::ZERO, |x| calls(x, name));
+    guard.then(calls(&arm.expr, name))
+}
+
+fn arms(arms: &[PatternArm], name: &Symbol) -> Calls {
+    arms.iter()
+        .map(|x| arm(x, name))
+        .reduce(Calls::or)
+        .unwrap_or(Calls::ZERO)
+}
+
+fn block(block: &Block, name: &Symbol) -> Calls {
+    let mut result = Calls::ZERO;
+
+    for sttm in &block.sttms {
+        match &sttm.data {
+            SttmKind::Let(sttm) => {
+                result = result.then(calls(&sttm.expr, name));
+
+                if binds(&sttm.pat, name) {
+                    break;
+                }
+            }
+            SttmKind::Expr(expr) => result = result.then(calls(expr, name)),
+            SttmKind::Error => (),
+        }
+    }
+
+    result
+}
+
+/// The number of times that the expression may call the continuation with the name. Using it
+/// without calling it, like giving it to a function, counts as a call.
+pub fn calls(expr: &Expr, name: &Symbol) -> Calls {
+    let all = |exprs: &mut dyn Iterator<Item = &Expr>| {
+        exprs.fold(Calls::ZERO, |acc, x| acc.then(calls(x, name)))
+    };
+
+    match &expr.data {
+        ExprKind::Variable(x) if x == name => Calls::ONE,
+        ExprKind::Application(app) => calls(&app.func, name).then(all(&mut app.args.iter())),
+        ExprKind::Perform(perform) => all(&mut perform.args.iter()),
+        ExprKind::Lambda(lambda) if binds(&lambda.param, name) => Calls::ZERO,
+        ExprKind::Lambda(lambda) => calls(&lambda.body, name).repeated(),
+        ExprKind::Projection(x) => calls(&x.expr, name),
+        ExprKind::Let(x) => {
+            let body = calls(&x.body, name);
+
+            if binds(&x.pattern, name) {
+                body
+            } else {
+                body.then(calls(&x.value, name))
+            }
+        }
+        ExprKind::LetGroup(x) => {
+            if x.bindings.iter().any(|x| &x.name == name) {
+                return Calls::ZERO;
+            }
+
+            let bindings = all(&mut x.bindings.iter().map(|x| &x.body)).repeated();
+            bindings.then(calls(&x.value, name))
+        }
+        ExprKind::When(x) => all(&mut x.scrutinee.iter()).then(arms(&x.arms, name)),
+        ExprKind::Cases(x) => arms(&x.arms, name).repeated(),
+        ExprKind::Handler(x) if x.name.as_ref() == Some(name) => {
+            calls(&x.handler, name).then(all(&mut x.finally.iter()))
+        }
+        ExprKind::Handler(x) => calls(&x.handler, name)
+            .then(calls(&x.expr, name))
+            .then(all(&mut x.finally.iter())),
+        ExprKind::Do(x) => block(x, name),
+        ExprKind::Mask(x) => calls(&x.expr, name),
+        ExprKind::Annotation(x) => calls(&x.expr, name),
+        ExprKind::RecordInstance(x) => all(&mut x.fields.iter().map(|x| &x.2)),
+        ExprKind::RecordUpdate(x) => {
+            calls(&x.expr, name).then(all(&mut x.fields.iter().map(|x| &x.2)))
+        }
+        ExprKind::Tuple(x) => all(&mut x.exprs.iter()),
+        ExprKind::Variable(_)
+        | ExprKind::Constructor(_)
+        | ExprKind::Function(_)
+        | ExprKind::Operation(_)
+        | ExprKind::Literal(_)
+        | ExprKind::Error => Calls::ZERO,
+    }
+}
@@ -17,14 +17,23 @@ mod context;
 mod coverage;
 mod eval;
 mod infer;
-mod module;
 mod unify;
 
 pub mod declare;
+pub mod module;
+pub mod number;
+pub mod pretty;
+pub mod serialize;
+pub mod store;
+pub mod typed;
 
-pub use context::Context;
+pub use context::{
+    array_effect, async_effect, async_operations, primitive_effects, primitive_operations,
+    ref_effect, task_type, Context,
+};
+pub use errors::ONE_SHOT;
 
-use std::{cell::RefCell, hash::Hash, rc::Rc};
+use std::{hash::Hash, rc::Rc};
 
 use r#virtual::Virtual;
 use vulpi_intern::Symbol;
@@ -96,7 +105,7 @@ pub enum TypeKind<S: State> {
     Forall(S::Forall),
 
     /// The type of holes.
-    Hole(Hole<Virtual>),
+    Hole(Hole),
 
     /// Type for types that are defined by the user.
     Variable(Qualified),
@@ -176,52 +185,37 @@ pub enum HoleInner<S: State> {
     Filled(Type<S>),
 }
 
-/// A hole is a type that is not yet known. It is used for type inference.
+/// A hole is a type that is not yet known. It is used for type inference. It's the index of its
+/// node in the arena of the [store::HoleStore] that made it.
 #[derive(Clone)]
-pub struct Hole<S: State>(pub Rc<RefCell<HoleInner<S>>>);
+pub struct Hole {
+    arena: store::Arena,
+    index: usize,
+}
 
-impl<S: State> Hash for Hole<S> {
+impl Hash for Hole {
     fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
-        let s = (self.0.as_ptr()) as usize;
-        s.hash(state);
+        (Rc::as_ptr(&self.arena) as usize).hash(state);
+        self.index.hash(state);
     }
 }
 
-impl<S: State> PartialEq for Hole<S> {
+impl PartialEq for Hole {
     fn eq(&self, other: &Self) -> bool {
-        Rc::ptr_eq(&self.0, &other.0)
-    }
-}
-
-impl<S: State> Eq for Hole<S> {}
-
-impl<S: State> Hole<S> {
-    pub fn is_empty(&self) -> bool {
-        matches!(&*self.0.borrow(), HoleInner::Empty(_, _, _))
+        Rc::ptr_eq(&self.arena, &other.arena) && self.index == other.index
     }
 }
 
-impl<S: State> Hole<S> {
-    pub fn new(hole_inner: HoleInner<S>) -> Self {
-        Self(Rc::new(RefCell::new(hole_inner)))
-    }
-
-    pub fn empty(name: Symbol, kind: Kind<S>, level: Level) -> Self {
-        Self(Rc::new(RefCell::new(HoleInner::Empty(name, kind, level))))
-    }
-
-    pub fn fill(&self, typ: Type<S>) {
-        *self.0.borrow_mut() = HoleInner::Filled(typ);
-    }
-}
+impl Eq for Hole {}
 
 pub mod r#virtual {
     use std::cell::RefCell;
 
     use vulpi_intern::Symbol;
     use vulpi_location::Span;
+    use vulpi_syntax::r#abstract::Qualified;
 
-    use super::{eval::Eval, real::Real, Hole, HoleInner, Kind, Level, State, Type, TypeKind};
+    use super::{eval::Eval, real::Real, HoleInner, Level, State, Type, TypeKind};
 
     /// The virtual state is used as label for the [State] trait as a way to express that the type
     /// contains closures and can be executed.
@@ -235,6 +229,7 @@ pub mod r#virtual {
         pub types: im_rc::Vector<Type<Virtual>>,
         pub kinds: im_rc::Vector<Type<Virtual>>,
         pub vars: im_rc::HashMap<Symbol, Type<Virtual>>,
+        pub instances: im_rc::HashMap<Symbol, Vec<Qualified>>,
         pub level: Level,
         pub span: RefCell<Span>,
     }
@@ -244,6 +239,11 @@ pub mod r#virtual {
             self.vars.insert(name, typ);
         }
 
+        /// Adds a named handler that handles the operations of the given effects.
+        pub fn add_instance(&mut self, name: Symbol, effects: Vec<Qualified>) {
+            self.instances.insert(name, effects);
+        }
+
         /// Sets the location of the environment. It is used for error reporting.
         pub fn set_current_span(&self, span: Span) {
             *self.span.borrow_mut() = span;
@@ -291,10 +291,6 @@ pub mod r#virtual {
             clone.level = clone.level.inc();
             clone
         }
-
-        pub fn hole<S: State>(&self, kind: Kind<Virtual>, label: Symbol) -> Type<S> {
-            Type::new(TypeKind::Hole(Hole::empty(label, kind, self.level)))
-        }
     }
 
     /// A simulation of a closure in a type. It contains the environment and the body of the closure.
@@ -370,7 +366,7 @@ pub mod r#virtual {
 
         pub fn deref(&self) -> Type<Virtual> {
             match self.as_ref() {
-                TypeKind::Hole(h) => match h.0.borrow().clone() {
+                TypeKind::Hole(h) => match h.get() {
                     HoleInner::Filled(typ) => typ.deref(),
                     _ => self.clone(),
                 },
@@ -384,6 +380,39 @@ pub mod r#virtual {
                 .fold(left, |acc, x| Type::new(TypeKind::Application(acc, x)))
         }
 
+        /// Checks if every bound variable of the type is below the level, so the type can be quoted
+        /// at it.
+        pub(crate) fn is_bound_below(&self, level: Level) -> bool {
+            match self.deref().as_ref() {
+                TypeKind::Bound(l) => *l < level,
+                TypeKind::Arrow(pi) => pi.typ.is_bound_below(level) && pi.body.is_bound_below(level),
+                TypeKind::Forall(forall) => forall
+                    .body
+                    .apply_local(None, Type::new(TypeKind::Bound(level)))
+                    .is_bound_below(level.inc()),
+                TypeKind::Tuple(types) => types.iter().all(|x| x.is_bound_below(level)),
+                TypeKind::Application(func, arg) | TypeKind::Qualified(func, arg) => {
+                    func.is_bound_below(level) && arg.is_bound_below(level)
+                }
+                _ => true,
+            }
+        }
+
+        /// Checks if the type contains the type of an error anywhere, so mismatches with it are
+        /// caused by an error that was already reported.
+        pub(crate) fn has_error(&self) -> bool {
+            match self.deref().as_ref() {
+                TypeKind::Error => true,
+                TypeKind::Arrow(pi) => pi.typ.has_error() || pi.body.has_error(),
+                TypeKind::Forall(forall) => forall.kind.has_error() || forall.body.body.has_error(),
+                TypeKind::Tuple(types) => types.iter().any(|x| x.has_error()),
+                TypeKind::Application(func, arg) | TypeKind::Qualified(func, arg) => {
+                    func.has_error() || arg.has_error()
+                }
+                _ => false,
+            }
+        }
+
         pub(crate) fn function(right: Vec<Self>, ret: Self) -> Self {
             right
                 .into_iter()
@@ -394,15 +423,10 @@ pub mod r#virtual {
 }
 
 pub mod real {
-    use std::fmt::Display;
-
-    use crate::Virtual;
     use vulpi_intern::Symbol;
     use vulpi_show::Show as OShow;
 
-    use super::{
-        eval::Quote, r#virtual::Env, Hole, HoleInner, Index, Level, State, Type, TypeKind,
-    };
+    use super::{eval::Quote, r#virtual::Env, HoleInner, Index, Level, State, Type, TypeKind};
 
     /// The real state is used as label for the [State] trait as a way to express that the type
     /// contains closures and can be executed.
@@ -429,19 +453,11 @@ pub mod real {
         type Bound = Index;
     }
 
-    /// Environment of names that is useful for pretty printing.
-    #[derive(Clone)]
-    struct NameEnv(im_rc::Vector<Option<Symbol>>);
-
-    impl From<Env> for NameEnv {
-        fn from(env: Env) -> Self {
-            Self(env.names)
-        }
-    }
-
     impl OShow for Type<Real> {
         fn show(&self) -> vulpi_show::TreeDisplay {
-            vulpi_show::TreeDisplay::label("Type")
+            // Types of the elaborated tree are shown outside of their binders, so the bound
+            // variables that have no names are shown by their indices.
+            vulpi_show::TreeDisplay::label(&format!("Type {}", self.show(&Env::default())))
         }
     }
 
@@ -472,6 +488,38 @@ pub mod real {
             (spine, current)
         }
 
+        /// Replaces a hole that was filled during type checking by its content. The depth is the
+        /// number of type binders that are in scope, holes that refer to variables outside of them
+        /// are kept.
+        pub fn force(&self, depth: usize) -> Self {
+            match self.as_ref() {
+                TypeKind::Hole(hole) => match hole.get() {
+                    HoleInner::Filled(typ) if typ.is_bound_below(Level(depth)) => {
+                        typ.quote(Level(depth))
+                    }
+                    _ => self.clone(),
+                },
+                _ => self.clone(),
+            }
+        }
+
+        pub(crate) fn has_error(&self) -> bool {
+            match self.as_ref() {
+                TypeKind::Error => true,
+                TypeKind::Hole(hole) => match hole.get() {
+                    HoleInner::Filled(typ) => typ.has_error(),
+                    HoleInner::Empty(..) => false,
+                },
+                TypeKind::Arrow(pi) => pi.typ.has_error() || pi.body.has_error(),
+                TypeKind::Forall(forall) => forall.kind.has_error() || forall.body.has_error(),
+                TypeKind::Tuple(types) => types.iter().any(|x| x.has_error()),
+                TypeKind::Application(func, arg) | TypeKind::Qualified(func, arg) => {
+                    func.has_error() || arg.has_error()
+                }
+                _ => false,
+            }
+        }
+
         pub fn arrow_spine(&self) -> Vec<Self> {
             let mut spine = Vec::new();
             let mut current = self.clone();
@@ -498,112 +546,4 @@ pub mod real {
             })
         }
     }
-
-    trait Formattable {
-        fn format(&self, env: &NameEnv, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result;
-    }
-
-    impl Formattable for Hole<Virtual> {
-        fn format(&self, env: &NameEnv, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-            match self.0.borrow().clone() {
-                HoleInner::Empty(s, _, _) => write!(f, "{}", s.get()),
-                HoleInner::Filled(forall) => forall.quote(Level(env.0.len())).format(env, f),
-            }
-        }
-    }
-
-    impl Formattable for Type<Real> {
-        fn format(&self, env: &NameEnv, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-            match self.as_ref() {
-                TypeKind::Constraint => write!(f, "Constraint"),
-                TypeKind::Type => write!(f, "Type"),
-                TypeKind::Arrow(pi) => {
-                    write!(f, "(")?;
-                    pi.typ.format(env, f)?;
-                    write!(f, " -> ")?;
-                    pi.body.format(env, f)?;
-                    write!(f, ")")
-                }
-                TypeKind::Forall(_) => {
-                    let mut env = env.clone();
-                    write!(f, "(forall ")?;
-
-                    let (binder, rest) = self.forall_spine();
-
-                    for (i, (name, kind)) in binder.iter().enumerate() {
-                        write!(f, "({}: ", name.get())?;
-                        kind.format(&env, f)?;
-                        write!(f, ")")?;
-                        if i != binder.len() - 1 {
-                            write!(f, " ")?;
-                        }
-                        env.0.push_front(Some(name.clone()))
-                    }
-
-                    write!(f, ". ")?;
-
-                    rest.format(&env, f)?;
-
-                    write!(f, ")")
-                }
-                TypeKind::Hole(hole) => hole.format(env, f),
-                TypeKind::Variable(n) => write!(f, "{}", n.name.get()),
-                TypeKind::Bound(n) => {
-                    write!(
-                        f,
-                        "{}~{}",
-                        env.0[n.0]
-                            .clone()
-                            .unwrap_or(Symbol::intern(&format!("_{}", n.0)))
-                            .get(),
-                        n.0
-                    )
-                }
-                TypeKind::Tuple(t) => {
-                    write!(f, "(")?;
-                    for (i, typ) in t.iter().enumerate() {
-                        typ.format(env, f)?;
-                        if i != t.len() - 1 {
-                            write!(f, ", ")?;
-                        }
-                    }
-                    write!(f, ")")
-                }
-                TypeKind::Application(_, _) => {
-                    let (p, args) = self.application_spine();
-                    write!(f, "(")?;
-                    p.format(env, f)?;
-                    for arg in args {
-                        write!(f, " ")?;
-                        arg.format(env, f)?;
-                    }
-                    write!(f, ")")
-                }
-                TypeKind::Error => write!(f, "<ERROR>"),
-                TypeKind::Qualified(from, to) => {
-                    write!(f, "(")?;
-                    from.format(env, f)?;
-                    write!(f, " => ")?;
-                    to.format(env, f)?;
-                    write!(f, ")")
-                }
-            }
-        }
-    }
-
-    impl Type<Real> {
-        /// Function that generates a [Show] object responsible for the pretty printing of the type.
-        pub fn show(&self, env: &Env) -> Show {
-            Show(self.clone(), env.clone().into())
-        }
-    }
-
-    /// A interface to show types with the correct names.
-    pub struct Show(Type<Real>, NameEnv);
-
-    impl Display for Show {
-        fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-            self.0.format(&self.1, f)
-        }
-    }
 }
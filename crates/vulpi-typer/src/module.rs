@@ -2,14 +2,16 @@
 //! module is the [Module] structure that is responsible for storing the types of the top level
 //! items.
 
-use std::collections::HashMap;
+use std::collections::BTreeMap;
 
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
 use vulpi_intern::Symbol;
-use vulpi_syntax::r#abstract::Qualified;
+use vulpi_location::Span;
+use vulpi_syntax::r#abstract::{OperationKind, Qualified, Resumption};
 
 use crate::{r#virtual::Virtual, real::Real, Type};
 
-#[derive(Clone)]
+#[derive(Clone, Serialize, Deserialize)]
 pub enum Def {
     Enum(Vec<Qualified>),
     Record(Vec<Qualified>),
@@ -18,15 +20,19 @@ pub enum Def {
     Constraint
 }
 
-#[derive(Clone)]
+#[derive(Clone, Serialize, Deserialize)]
 pub struct TypeData {
     pub kind: Type<Virtual>,
     pub binders: Vec<(Symbol, Type<Virtual>)>,
     pub module: Symbol,
     pub def: Def,
+
+    /// Set for the types declared with `#[opaque]`, whose definitions can only be used inside of
+    /// the modules that declare them.
+    pub opaque: bool,
 }
 
-#[derive(Clone)]
+#[derive(Clone, Serialize, Deserialize)]
 pub struct TraitData {
     pub kind: Type<Virtual>,
     pub binders: Vec<Type<Virtual>>,
@@ -44,28 +50,80 @@ pub struct LetDef {
     pub ret: Type<Virtual>,
 }
 
-#[derive(Default)]
+/// Only the type of a let declaration is stored, because the rest is used to define it and the
+/// modules that are loaded are already defined.
+impl Serialize for LetDef {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        self.typ.serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for LetDef {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let typ = Type::<Virtual>::deserialize(deserializer)?;
+
+        Ok(LetDef {
+            typ: typ.clone(),
+            unbound: Vec::new(),
+            args: Vec::new(),
+            ret: typ,
+        })
+    }
+}
+
+#[derive(Clone, Default, Serialize, Deserialize)]
 pub struct Interface {
     /// The types of the functions.
-    pub variables: HashMap<Symbol, LetDef>,
+    pub variables: BTreeMap<Symbol, LetDef>,
 
     /// The types of the functions.
-    pub constructors: HashMap<Symbol, (Type<Real>, usize, Qualified)>,
+    pub constructors: BTreeMap<Symbol, (Type<Real>, usize, Qualified)>,
 
     /// The types of the types.
-    pub types: HashMap<Symbol, TypeData>,
+    pub types: BTreeMap<Symbol, TypeData>,
 
     /// The fields of the records.
-    pub fields: HashMap<Symbol, Type<Real>>,
+    pub fields: BTreeMap<Symbol, Type<Real>>,
 
     /// Traits.
-    pub traits: HashMap<Symbol, TraitData>,
+    pub traits: BTreeMap<Symbol, TraitData>,
+
+    /// The types of the operations of the effects.
+    pub operations: BTreeMap<Symbol, (Type<Real>, usize, Qualified, OperationKind)>,
+
+    /// The synthesized functions that implement the default of the operations.
+    pub defaults: BTreeMap<Symbol, Qualified>,
+
+    /// The operations whose continuations must be resumed exactly once.
+    pub resumptions: BTreeMap<Symbol, Resumption>,
+
+    /// The location of the effect declarations.
+    pub effects: BTreeMap<Symbol, Span>,
+
+    /// The effects of the external functions that are not pure.
+    pub externals: BTreeMap<Symbol, Qualified>,
 }
 
-#[derive(Default)]
+impl Interface {
+    /// Adds the declarations of another interface of the same path.
+    pub fn merge(&mut self, other: Interface) {
+        self.variables.extend(other.variables);
+        self.constructors.extend(other.constructors);
+        self.types.extend(other.types);
+        self.fields.extend(other.fields);
+        self.traits.extend(other.traits);
+        self.operations.extend(other.operations);
+        self.defaults.extend(other.defaults);
+        self.resumptions.extend(other.resumptions);
+        self.effects.extend(other.effects);
+        self.externals.extend(other.externals);
+    }
+}
+
+#[derive(Default, Serialize, Deserialize)]
 pub struct Modules {
     /// The modules.
-    pub modules: HashMap<Symbol, Interface>,
+    pub modules: BTreeMap<Symbol, Interface>,
 }
 
 impl Modules {
@@ -85,6 +143,31 @@ impl Modules {
         module.constructors.get(&qualified.name).unwrap().clone()
     }
 
+    pub fn operation(
+        &mut self,
+        qualified: &Qualified,
+    ) -> Option<(Type<Real>, usize, Qualified, OperationKind)> {
+        let module = self.get(&qualified.path);
+        module.operations.get(&qualified.name).cloned()
+    }
+
+    pub fn operation_default(&mut self, qualified: &Qualified) -> Option<Qualified> {
+        let module = self.get(&qualified.path);
+        module.defaults.get(&qualified.name).cloned()
+    }
+
+    /// The effect of a function if it's an external one.
+    pub fn external_effect(&mut self, qualified: &Qualified) -> Option<Qualified> {
+        let module = self.get(&qualified.path);
+        module.externals.get(&qualified.name).cloned()
+    }
+
+    pub fn operation_resumption(&mut self, qualified: &Qualified) -> Resumption {
+        let module = self.get(&qualified.path);
+        let resumption = module.resumptions.get(&qualified.name);
+        resumption.copied().unwrap_or(Resumption::Many)
+    }
+
     pub fn let_decl(&mut self, qualified: &Qualified) -> &mut LetDef {
         let module = self.get(&qualified.path);
         module.variables.get_mut(&qualified.name).unwrap()
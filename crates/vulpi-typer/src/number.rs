@@ -0,0 +1,155 @@
+//! The numeric types of the prelude. `Int` and `Float` are 64 bit and the other ones are sized
+//! versions of them, so programs can use the values that C functions and binary formats expect.
+//! Every integer is kept in a 64 bit word that holds a value inside of the range of its type, and
+//! every float in a 64 bit float that holds a value of its precision, so the backends only need
+//! the sizes to pass them to foreign functions and to convert between them.
+
+/// A numeric type of the prelude with the layout of its values.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum Number {
+    /// An integer with its number of bits and whether it can be negative.
+    Int { bits: u32, signed: bool },
+
+    /// A float with its number of bits.
+    Float { bits: u32 },
+}
+
+impl Number {
+    pub const INT: Number = Number::Int {
+        bits: 64,
+        signed: true,
+    };
+
+    pub const FLOAT: Number = Number::Float { bits: 64 };
+
+    /// The numeric types by their names in the prelude.
+    pub const ALL: [(&'static str, Number); 10] = [
+        ("Int", Number::INT),
+        (
+            "Int8",
+            Number::Int {
+                bits: 8,
+                signed: true,
+            },
+        ),
+        (
+            "Int16",
+            Number::Int {
+                bits: 16,
+                signed: true,
+            },
+        ),
+        (
+            "Int32",
+            Number::Int {
+                bits: 32,
+                signed: true,
+            },
+        ),
+        (
+            "Int64",
+            Number::Int {
+                bits: 64,
+                signed: true,
+            },
+        ),
+        (
+            "UInt8",
+            Number::Int {
+                bits: 8,
+                signed: false,
+            },
+        ),
+        (
+            "UInt16",
+            Number::Int {
+                bits: 16,
+                signed: false,
+            },
+        ),
+        (
+            "UInt32",
+            Number::Int {
+                bits: 32,
+                signed: false,
+            },
+        ),
+        ("Float", Number::FLOAT),
+        ("Float32", Number::Float { bits: 32 }),
+    ];
+
+    /// The numeric type of a type of the prelude.
+    pub fn of(name: &str) -> Option<Number> {
+        Self::ALL
+            .iter()
+            .find(|(x, _)| *x == name)
+            .map(|(_, number)| *number)
+    }
+
+    pub fn bits(&self) -> u32 {
+        match self {
+            Number::Int { bits, .. } | Number::Float { bits } => *bits,
+        }
+    }
+
+    /// The number of bytes of a value of the type in memory.
+    pub fn size(&self) -> usize {
+        self.bits() as usize / 8
+    }
+
+    /// The smallest and the largest integers of the type.
+    pub fn range(&self) -> Option<(i64, i64)> {
+        match *self {
+            Number::Int { bits, signed: true } => {
+                let max = i64::MAX >> (64 - bits);
+                Some((-max - 1, max))
+            }
+            Number::Int {
+                bits,
+                signed: false,
+            } => Some((0, i64::MAX >> (63 - bits))),
+            Number::Float { .. } => None,
+        }
+    }
+
+    /// Checks if the text of a literal is a value of the type.
+    pub fn fits(&self, literal: &str) -> bool {
+        let literal = literal.replace('_', "");
+
+        match self {
+            Number::Int { .. } => {
+                let (min, max) = self.range().unwrap();
+
+                literal
+                    .parse::<i128>()
+                    .is_ok_and(|x| (min as i128..=max as i128).contains(&x))
+            }
+            Number::Float { bits: 32 } => literal.parse::<f32>().is_ok_and(f32::is_finite),
+            Number::Float { .. } => literal.parse::<f64>().is_ok_and(f64::is_finite),
+        }
+    }
+
+    /// The integer of the type with the same lower bits, like a cast of C.
+    pub fn wrap(&self, value: i64) -> i64 {
+        match *self {
+            Number::Int { bits: 64, .. } | Number::Float { .. } => value,
+            Number::Int { bits, signed: true } => (value << (64 - bits)) >> (64 - bits),
+            Number::Int { bits, .. } => value & (i64::MAX >> (63 - bits)),
+        }
+    }
+
+    /// The integer of the type that is closest to the float, truncating its fraction. Not a
+    /// number is zero.
+    pub fn saturate(&self, value: f64) -> i64 {
+        let (min, max) = self.range().unwrap_or((i64::MIN, i64::MAX));
+        (value as i64).clamp(min, max)
+    }
+
+    /// The float of the type that is closest to the value.
+    pub fn round(&self, value: f64) -> f64 {
+        match self {
+            Number::Float { bits: 32 } => value as f32 as f64,
+            _ => value,
+        }
+    }
+}
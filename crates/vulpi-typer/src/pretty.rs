@@ -0,0 +1,204 @@
+//! Printing of the types of the type checker as they're written in the source. The diagnostics,
+//! the details of the completions and the documentation print the types with it. Bound variables
+//! keep the names that the user wrote in their `forall`s, with a number after the ones that shadow
+//! a variable of the same name, and types are put inside of parenthesis only where they need to.
+
+use std::{fmt::Display, rc::Rc};
+
+use vulpi_intern::Symbol;
+use vulpi_pretty::Doc;
+use vulpi_syntax::r#abstract::Qualified;
+
+use crate::{
+    eval::Quote,
+    r#virtual::{Env, Virtual},
+    real::{Forall, Real},
+    HoleInner, Level, Type, TypeKind,
+};
+
+const INDENT: usize = 2;
+
+/// Writes the name of a type in the place of its qualified name.
+type Namer = Rc<dyn Fn(&Qualified) -> String>;
+
+/// The names of the bound variables that are in scope, from the innermost one. The names of the
+/// types can be written by a function instead.
+#[derive(Clone)]
+pub struct Names {
+    bound: im_rc::Vector<Option<Symbol>>,
+    namer: Option<Namer>,
+}
+
+impl Names {
+    /// The name of a variable that is bound inside of the names. A variable that has the name of
+    /// another one in scope is named with the first number that makes it different.
+    fn bind(&self, name: &Symbol) -> (Symbol, Names) {
+        let taken = |name: &Symbol| self.bound.iter().any(|x| x.as_ref() == Some(name));
+
+        let mut fresh = name.clone();
+        let mut counter = 1;
+
+        while taken(&fresh) {
+            fresh = Symbol::intern(&format!("{}{}", name.get(), counter));
+            counter += 1;
+        }
+
+        let mut names = self.clone();
+        names.bound.push_front(Some(fresh.clone()));
+        (fresh, names)
+    }
+}
+
+impl From<&Env> for Names {
+    fn from(env: &Env) -> Self {
+        Self {
+            bound: env.names.clone(),
+            namer: None,
+        }
+    }
+}
+
+/// How tightly a type is bound. A type in a place that binds tighter than it is put inside of
+/// parenthesis.
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum Prec {
+    Open,
+    Binary,
+    Application,
+    Atom,
+}
+
+/// The content of the filled holes at the head of a type.
+fn resolve(typ: &Type<Real>, names: &Names) -> Type<Real> {
+    match typ.as_ref() {
+        TypeKind::Hole(hole) => match hole.get() {
+            HoleInner::Filled(filled) => resolve(&filled.quote(Level(names.bound.len())), names),
+            HoleInner::Empty(..) => typ.clone(),
+        },
+        _ => typ.clone(),
+    }
+}
+
+fn prec(typ: &Type<Real>) -> Prec {
+    match typ.as_ref() {
+        TypeKind::Forall(_) | TypeKind::Qualified(_, _) => Prec::Open,
+        TypeKind::Arrow(_) => Prec::Binary,
+        TypeKind::Application(_, _) => Prec::Application,
+        _ => Prec::Atom,
+    }
+}
+
+fn parens(doc: Doc) -> Doc {
+    Doc::text("(") + doc + Doc::text(")")
+}
+
+fn typ(typ: &Type<Real>, names: &Names, at: Prec) -> Doc {
+    let typ = resolve(typ, names);
+
+    if prec(&typ) < at {
+        parens(doc(&typ, names))
+    } else {
+        doc(&typ, names)
+    }
+}
+
+fn binder(name: &Symbol, kind: &Type<Real>, names: &Names) -> Doc {
+    match kind.as_ref() {
+        TypeKind::Type => Doc::text(name.get()),
+        _ => parens(Doc::text(format!("{} : ", name.get())) + typ(kind, names, Prec::Open)),
+    }
+}
+
+fn doc(ty: &Type<Real>, names: &Names) -> Doc {
+    match ty.as_ref() {
+        TypeKind::Type => Doc::text("Type"),
+        TypeKind::Constraint => Doc::text("Constraint"),
+        TypeKind::Arrow(pi) => {
+            let left = typ(&pi.typ, names, Prec::Application);
+            let right = typ(&pi.body, names, Prec::Binary);
+            (left + Doc::text(" ->") + Doc::line() + right).group()
+        }
+        TypeKind::Forall(_) => {
+            let mut names = names.clone();
+            let mut binders = Vec::new();
+            let mut current = ty.clone();
+
+            while let TypeKind::Forall(Forall { name, kind, body }) = current.as_ref() {
+                let (fresh, inner) = names.bind(name);
+                binders.push(binder(&fresh, kind, &names));
+                names = inner;
+                current = resolve(body, &names);
+            }
+
+            let binders = Doc::join(binders, Doc::text(" "));
+            let body = (Doc::line() + typ(&current, &names, Prec::Open)).nest(INDENT);
+            (Doc::text("forall ") + binders + Doc::text(".") + body).group()
+        }
+        TypeKind::Hole(hole) => match hole.get() {
+            HoleInner::Empty(name, ..) => Doc::text(name.get()),
+            HoleInner::Filled(_) => typ(ty, names, Prec::Open),
+        },
+        TypeKind::Variable(name) => match &names.namer {
+            Some(namer) => Doc::text(namer(name)),
+            None => Doc::text(name.name.get()),
+        },
+        TypeKind::Bound(index) => match names.bound.get(index.0).cloned().flatten() {
+            Some(name) => Doc::text(name.get()),
+            None => Doc::text(format!("_{}", index.0)),
+        },
+        TypeKind::Tuple(types) => {
+            let types = types.iter().map(|x| typ(x, names, Prec::Open));
+            let types = Doc::join(types, Doc::text(",") + Doc::line());
+            (Doc::text("(") + types.nest(INDENT) + Doc::text(")")).group()
+        }
+        TypeKind::Application(_, _) => {
+            let (func, args) = ty.application_spine();
+            let args = args.iter().map(|x| Doc::line() + typ(x, names, Prec::Atom));
+
+            (typ(&func, names, Prec::Application) + Doc::concat(args).nest(INDENT)).group()
+        }
+        TypeKind::Qualified(from, to) => {
+            let from = typ(from, names, Prec::Application);
+            let to = typ(to, names, Prec::Open);
+            (from + Doc::text(" =>") + Doc::line() + to).group()
+        }
+        TypeKind::Error => Doc::text("<ERROR>"),
+    }
+}
+
+impl Type<Real> {
+    /// The document of the type, with the names of the bound variables of the environment.
+    pub fn pretty(&self, env: &Env) -> Doc {
+        typ(self, &env.into(), Prec::Open)
+    }
+
+    /// Function that generates a [Show] object responsible for the pretty printing of the type.
+    pub fn show(&self, env: &Env) -> Show {
+        Show(self.clone(), env.into())
+    }
+
+    /// Shows the type with the names of the types written by a function, like the links to
+    /// their declarations in the documentation.
+    pub fn show_with(&self, env: &Env, name: impl Fn(&Qualified) -> String + 'static) -> Show {
+        let mut names = Names::from(env);
+        names.namer = Some(Rc::new(name));
+        Show(self.clone(), names)
+    }
+}
+
+impl Type<Virtual> {
+    /// The document of the type, that is quoted in the environment first.
+    pub fn pretty(&self, env: &Env) -> Doc {
+        self.quote(env.level).pretty(env)
+    }
+}
+
+/// A interface to show types with the correct names. They're shown in a single line.
+pub struct Show(Type<Real>, Names);
+
+impl Display for Show {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let doc = typ(&self.0, &self.1, Prec::Open);
+        write!(f, "{}", doc.render(usize::MAX))
+    }
+}
@@ -0,0 +1,110 @@
+//! Serialization of types, so the interfaces of modules can be stored and loaded again. Types are
+//! stored without holes: the filled ones are replaced by their contents and the empty ones become
+//! errors, like they do when they're lowered. Virtual types are quoted before they're stored and
+//! evaluated when they're loaded, so only closed ones can be stored.
+
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use vulpi_intern::Symbol;
+use vulpi_syntax::r#abstract::Qualified;
+
+use crate::{
+    eval::{Eval, Quote},
+    r#virtual::{Env, Virtual},
+    real::{self, Real},
+    Index, Level, Type, TypeKind,
+};
+
+#[derive(Serialize, Deserialize)]
+enum Stored {
+    Type,
+    Constraint,
+    Arrow(Box<Stored>, Box<Stored>),
+    Forall(Symbol, Box<Stored>, Box<Stored>),
+    Variable(Qualified),
+    Bound(usize),
+    Tuple(Vec<Stored>),
+    Application(Box<Stored>, Box<Stored>),
+    Qualified(Box<Stored>, Box<Stored>),
+    Error,
+}
+
+impl Stored {
+    /// The depth is the number of type binders that are in scope.
+    fn new(typ: &Type<Real>, depth: usize) -> Self {
+        let typ = typ.force(depth);
+
+        match typ.as_ref() {
+            TypeKind::Type => Stored::Type,
+            TypeKind::Constraint => Stored::Constraint,
+            TypeKind::Arrow(arrow) => Stored::Arrow(
+                Box::new(Stored::new(&arrow.typ, depth)),
+                Box::new(Stored::new(&arrow.body, depth)),
+            ),
+            TypeKind::Forall(forall) => Stored::Forall(
+                forall.name.clone(),
+                Box::new(Stored::new(&forall.kind, depth)),
+                Box::new(Stored::new(&forall.body, depth + 1)),
+            ),
+            TypeKind::Variable(name) => Stored::Variable(name.clone()),
+            TypeKind::Bound(index) => Stored::Bound(index.0),
+            TypeKind::Tuple(types) => {
+                Stored::Tuple(types.iter().map(|x| Stored::new(x, depth)).collect())
+            }
+            TypeKind::Application(func, arg) => Stored::Application(
+                Box::new(Stored::new(func, depth)),
+                Box::new(Stored::new(arg, depth)),
+            ),
+            TypeKind::Qualified(from, to) => Stored::Qualified(
+                Box::new(Stored::new(from, depth)),
+                Box::new(Stored::new(to, depth)),
+            ),
+            TypeKind::Hole(_) | TypeKind::Error => Stored::Error,
+        }
+    }
+
+    fn load(self) -> Type<Real> {
+        Type::new(match self {
+            Stored::Type => TypeKind::Type,
+            Stored::Constraint => TypeKind::Constraint,
+            Stored::Arrow(typ, body) => TypeKind::Arrow(real::Arrow {
+                typ: typ.load(),
+                body: body.load(),
+            }),
+            Stored::Forall(name, kind, body) => TypeKind::Forall(real::Forall {
+                name,
+                kind: kind.load(),
+                body: body.load(),
+            }),
+            Stored::Variable(name) => TypeKind::Variable(name),
+            Stored::Bound(index) => TypeKind::Bound(Index(index)),
+            Stored::Tuple(types) => TypeKind::Tuple(types.into_iter().map(Stored::load).collect()),
+            Stored::Application(func, arg) => TypeKind::Application(func.load(), arg.load()),
+            Stored::Qualified(from, to) => TypeKind::Qualified(from.load(), to.load()),
+            Stored::Error => TypeKind::Error,
+        })
+    }
+}
+
+impl Serialize for Type<Real> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        Stored::new(self, 0).serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for Type<Real> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        Ok(Stored::deserialize(deserializer)?.load())
+    }
+}
+
+impl Serialize for Type<Virtual> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        self.quote(Level(0)).serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for Type<Virtual> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        Ok(Type::<Real>::deserialize(deserializer)?.eval(&Env::default()))
+    }
+}
@@ -0,0 +1,327 @@
+//! The store of the holes of the type checker. Holes live in an arena and are referred to by their
+//! indices. Holes that are unified with each other are linked in a union-find structure: every hole
+//! points to the hole that represents its class, the representative is the only one that has
+//! contents, and the paths to it are compressed when they are followed. The writes to the holes
+//! are recorded while a snapshot is open, so unifications that are only tried can be undone.
+
+use std::{cell::RefCell, rc::Rc};
+
+use vulpi_intern::Symbol;
+
+use crate::{r#virtual::Virtual, Hole, HoleInner, Kind, Level, Type, TypeKind};
+
+/// The nodes of the holes, indexed by the holes.
+pub(crate) type Arena = Rc<RefCell<Vec<Node>>>;
+
+/// A node of the union-find structure.
+#[derive(Clone)]
+pub(crate) enum Node {
+    /// A hole that was unified with the hole of the index, that is closer to the representative.
+    Link(usize),
+
+    /// The representative of a class with its contents and its rank, that bounds the length of the
+    /// paths to it.
+    Root(HoleInner<Virtual>, usize),
+}
+
+impl Hole {
+    fn node(&self) -> Node {
+        self.arena.borrow()[self.index].clone()
+    }
+
+    fn at(&self, index: usize) -> Hole {
+        Hole {
+            arena: self.arena.clone(),
+            index,
+        }
+    }
+
+    /// The representative of the class of the hole, without compressing the path to it.
+    pub(crate) fn root(&self) -> Hole {
+        let mut current = self.clone();
+
+        while let Node::Link(next) = current.node() {
+            current = current.at(next);
+        }
+
+        current
+    }
+
+    /// The contents of the class of the hole.
+    pub fn get(&self) -> HoleInner<Virtual> {
+        match self.root().node() {
+            Node::Root(inner, _) => inner,
+            Node::Link(_) => unreachable!(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        matches!(self.get(), HoleInner::Empty(..))
+    }
+}
+
+/// A point of the unification that the holes can go back to.
+#[must_use]
+pub struct Snapshot(usize);
+
+/// The union-find store of the holes.
+#[derive(Default)]
+pub struct HoleStore {
+    /// The holes that were made by the store.
+    arena: Arena,
+
+    /// The previous nodes of the holes that were written while a snapshot was open.
+    trail: Vec<(Hole, Node)>,
+
+    /// The number of snapshots that are open.
+    open: usize,
+}
+
+impl HoleStore {
+    /// Makes a hole that is not unified with any other one.
+    pub fn empty(&mut self, name: Symbol, kind: Kind<Virtual>, level: Level) -> Hole {
+        let mut arena = self.arena.borrow_mut();
+        arena.push(Node::Root(HoleInner::Empty(name, kind, level), 0));
+
+        Hole {
+            arena: self.arena.clone(),
+            index: arena.len() - 1,
+        }
+    }
+
+    fn set(&mut self, hole: &Hole, node: Node) {
+        let old = std::mem::replace(&mut hole.arena.borrow_mut()[hole.index], node);
+
+        if self.open > 0 {
+            self.trail.push((hole.clone(), old));
+        }
+    }
+
+    /// Finds the representative of the class of a hole, and makes every hole in the way point to
+    /// it.
+    pub fn find(&mut self, hole: &Hole) -> Hole {
+        let root = hole.root();
+        let mut current = hole.clone();
+
+        while current != root {
+            let Node::Link(next) = current.node() else {
+                unreachable!()
+            };
+
+            if next != root.index {
+                self.set(&current, Node::Link(root.index));
+            }
+
+            current = current.at(next);
+        }
+
+        root
+    }
+
+    /// Follows the holes at the head of a type until a type that is not a filled hole.
+    pub fn deref(&mut self, typ: &Type<Virtual>) -> Type<Virtual> {
+        match typ.as_ref() {
+            TypeKind::Hole(hole) => {
+                let root = self.find(hole);
+                match root.get() {
+                    HoleInner::Filled(typ) => typ,
+                    HoleInner::Empty(..) => Type::new(TypeKind::Hole(root)),
+                }
+            }
+            _ => typ.clone(),
+        }
+    }
+
+    /// Fills the class of a hole with a type that is not a hole.
+    pub fn fill(&mut self, hole: &Hole, typ: Type<Virtual>) {
+        let root = self.find(hole);
+
+        let Node::Root(_, rank) = root.node() else {
+            unreachable!()
+        };
+
+        self.set(&root, Node::Root(HoleInner::Filled(typ), rank));
+    }
+
+    /// Joins the classes of two empty holes. The representative with the smaller rank points to
+    /// the other one, that keeps the smaller level of both so no variable escapes through it.
+    pub fn union(&mut self, left: &Hole, right: &Hole) {
+        let (left, right) = (self.find(left), self.find(right));
+
+        if left == right {
+            return;
+        }
+
+        let Node::Root(HoleInner::Empty(l_name, l_kind, l_level), l_rank) = left.node() else {
+            unreachable!()
+        };
+
+        let Node::Root(HoleInner::Empty(r_name, r_kind, r_level), r_rank) = right.node() else {
+            unreachable!()
+        };
+
+        let level = l_level.min(r_level);
+
+        if l_rank < r_rank {
+            self.set(&left, Node::Link(right.index));
+            let inner = HoleInner::Empty(r_name, r_kind, level);
+            self.set(&right, Node::Root(inner, r_rank));
+        } else {
+            let rank = if l_rank == r_rank { l_rank + 1 } else { l_rank };
+            self.set(&right, Node::Link(left.index));
+            let inner = HoleInner::Empty(l_name, l_kind, level);
+            self.set(&left, Node::Root(inner, rank));
+        }
+    }
+
+    /// Opens a snapshot. Every write to the holes from now on can be undone until the snapshot is
+    /// rolled back or committed.
+    pub fn snapshot(&mut self) -> Snapshot {
+        self.open += 1;
+        Snapshot(self.trail.len())
+    }
+
+    /// Undoes every write to the holes since the snapshot was opened, and closes it.
+    pub fn rollback(&mut self, snapshot: Snapshot) {
+        while self.trail.len() > snapshot.0 {
+            let (hole, old) = self.trail.pop().unwrap();
+            hole.arena.borrow_mut()[hole.index] = old;
+        }
+
+        self.close();
+    }
+
+    /// Keeps the writes to the holes since the snapshot was opened, and closes it.
+    pub fn commit(&mut self, _: Snapshot) {
+        self.close();
+    }
+
+    fn close(&mut self) {
+        self.open -= 1;
+
+        // Without snapshots nothing can be undone, so the trail is not needed anymore.
+        if self.open == 0 {
+            self.trail.clear();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hole(store: &mut HoleStore, level: usize) -> Hole {
+        let name = Symbol::intern("t");
+        store.empty(name, Type::typ(), Level(level))
+    }
+
+    fn rank(hole: &Hole) -> usize {
+        match hole.node() {
+            Node::Root(_, rank) => rank,
+            Node::Link(_) => panic!("the hole is not a representative"),
+        }
+    }
+
+    fn level(hole: &Hole) -> Level {
+        match hole.get() {
+            HoleInner::Empty(_, _, level) => level,
+            HoleInner::Filled(_) => panic!("the hole is filled"),
+        }
+    }
+
+    fn unit() -> Type<Virtual> {
+        Type::tuple(vec![])
+    }
+
+    #[test]
+    fn find_compresses_paths() {
+        let mut store = HoleStore::default();
+        let holes: Vec<_> = (0..4).map(|_| hole(&mut store, 0)).collect();
+
+        // Holes of the same rank make a chain of links when the representative is unified again.
+        store.union(&holes[1], &holes[0]);
+        store.union(&holes[3], &holes[2]);
+        store.union(&holes[3], &holes[1]);
+
+        assert!(matches!(holes[0].node(), Node::Link(1)));
+        assert!(matches!(holes[1].node(), Node::Link(3)));
+
+        let root = store.find(&holes[0]);
+
+        assert!(root == holes[3]);
+        assert!(matches!(holes[0].node(), Node::Link(3)));
+    }
+
+    #[test]
+    fn union_points_the_smaller_rank_to_the_larger() {
+        let mut store = HoleStore::default();
+        let (a, b, c) = (
+            hole(&mut store, 0),
+            hole(&mut store, 0),
+            hole(&mut store, 0),
+        );
+
+        store.union(&a, &b);
+        assert_eq!(rank(&a), 1);
+        assert!(b.root() == a);
+
+        // The representative of the larger class stays, whatever the side that it's on.
+        store.union(&c, &a);
+        assert_eq!(rank(&a), 1);
+        assert!(c.root() == a);
+    }
+
+    #[test]
+    fn union_keeps_the_smaller_level() {
+        let mut store = HoleStore::default();
+        let (a, b) = (hole(&mut store, 3), hole(&mut store, 1));
+
+        store.union(&a, &b);
+
+        assert_eq!(level(&a.root()), Level(1));
+        assert_eq!(level(&b), Level(1));
+    }
+
+    #[test]
+    fn nested_snapshots_roll_back_their_own_writes() {
+        let mut store = HoleStore::default();
+        let (a, b, c) = (
+            hole(&mut store, 0),
+            hole(&mut store, 0),
+            hole(&mut store, 0),
+        );
+
+        let outer = store.snapshot();
+        store.union(&a, &b);
+
+        let inner = store.snapshot();
+        store.union(&c, &a);
+        store.fill(&b, unit());
+        assert!(!a.is_empty() && !c.is_empty());
+
+        store.rollback(inner);
+        assert!(a.is_empty() && c.is_empty());
+        assert!(a.root() == b.root());
+        assert!(c.root() == c);
+
+        store.rollback(outer);
+        assert!(a.root() == a && b.root() == b);
+        assert_eq!(rank(&a), 0);
+        assert!(store.trail.is_empty());
+    }
+
+    #[test]
+    fn committed_snapshots_are_undone_by_the_outer_ones() {
+        let mut store = HoleStore::default();
+        let a = hole(&mut store, 0);
+
+        let outer = store.snapshot();
+        let inner = store.snapshot();
+        store.fill(&a, unit());
+        store.commit(inner);
+        assert!(!a.is_empty());
+
+        store.rollback(outer);
+        assert!(a.is_empty());
+    }
+}
@@ -0,0 +1,81 @@
+//! The typed tree that the type checker gives to the backends. It's the elaborated tree after
+//! zonking: the holes that were filled during type checking are replaced by their contents, so the
+//! types have no mutable state left, and the holes that were never filled are defaulted. Nothing
+//! observes the type of a value whose type was never decided, so the empty holes of kind `Type`
+//! become the unit type and the other ones become errors.
+
+use vulpi_syntax::elaborated::{self, TypesMut};
+
+use crate::{
+    real::{Arrow, Forall, Real},
+    HoleInner, Type, TypeKind,
+};
+
+/// A typed module with its inline modules. Its types have no holes.
+pub type Module = elaborated::Program<Type<Real>>;
+
+/// Replaces the holes of the types of an elaborated program.
+pub fn zonk(mut program: elaborated::Program<Type<Real>>) -> Module {
+    for decl in program.lets.values_mut() {
+        // The types inside of the declaration are under the binders of its type.
+        let depth = decl.typ.forall_spine().0.len();
+
+        decl.typ = zonk_type(&decl.typ, 0);
+
+        for (_, typ) in &mut decl.binders {
+            *typ = zonk_type(typ, depth);
+        }
+
+        for arm in &mut decl.body {
+            arm.types_mut(&mut |typ| *typ = zonk_type(typ, depth));
+        }
+    }
+
+    for external in program.externals.values_mut() {
+        external.typ = zonk_type(&external.typ, 0);
+    }
+
+    for test in &mut program.tests {
+        test.typ = zonk_type(&test.typ, 0);
+        test.body.types_mut(&mut |typ| *typ = zonk_type(typ, 0));
+    }
+
+    program.modules = std::mem::take(&mut program.modules)
+        .into_iter()
+        .map(|(name, module)| (name, zonk(module)))
+        .collect();
+
+    program
+}
+
+/// Replaces the holes of a type. The depth is the number of type binders that are in scope.
+fn zonk_type(typ: &Type<Real>, depth: usize) -> Type<Real> {
+    let typ = typ.force(depth);
+
+    match typ.as_ref() {
+        TypeKind::Hole(hole) => match hole.get() {
+            HoleInner::Empty(_, kind, ..) if matches!(kind.deref().as_ref(), TypeKind::Type) => {
+                Type::tuple(vec![])
+            }
+            _ => Type::error(),
+        },
+        TypeKind::Arrow(arrow) => Type::new(TypeKind::Arrow(Arrow {
+            typ: zonk_type(&arrow.typ, depth),
+            body: zonk_type(&arrow.body, depth),
+        })),
+        TypeKind::Forall(forall) => Type::forall(Forall {
+            name: forall.name.clone(),
+            kind: zonk_type(&forall.kind, depth),
+            body: zonk_type(&forall.body, depth + 1),
+        }),
+        TypeKind::Tuple(types) => Type::tuple(types.iter().map(|x| zonk_type(x, depth)).collect()),
+        TypeKind::Application(func, arg) => Type::new(TypeKind::Application(
+            zonk_type(func, depth),
+            zonk_type(arg, depth),
+        )),
+        TypeKind::Qualified(from, to) => {
+            Type::qualified(zonk_type(from, depth), zonk_type(to, depth))
+        }
+        _ => typ.clone(),
+    }
+}
@@ -11,13 +11,14 @@ use super::{
     Hole, HoleInner, Level, Type, TypeKind,
 };
 
-type Result<T = ()> = std::result::Result<T, TypeErrorKind>;
+/// The errors of unification are boxed because they carry the environment where they happened.
+type Result<T = ()> = std::result::Result<T, Box<TypeErrorKind>>;
 
 impl Context {
     pub fn subsumes(&mut self, env: Env, left: Type<Virtual>, right: Type<Virtual>) {
         fn go(ctx: &mut Context, env: Env, left: Type<Virtual>, right: Type<Virtual>) -> Result {
-            let l = left.deref();
-            let r = right.deref();
+            let l = ctx.holes.deref(&left);
+            let r = ctx.holes.deref(&right);
 
             match (l.as_ref(), r.as_ref()) {
                 (TypeKind::Hole(n), _) if n.is_empty() => {
@@ -51,7 +52,11 @@ impl Context {
         let result = go(self, env.clone(), left.clone(), right.clone());
 
         if let Err(kind) = result {
-            match kind {
+            match *kind {
+                // The error was reported where the type of the error was made, like a name that
+                // could not be resolved.
+                TypeErrorKind::TypeMismatch(_, _, _) | TypeErrorKind::KindMismatch(_, _, _)
+                    if left.has_error() || right.has_error() => {}
                 TypeErrorKind::TypeMismatch(_, _, _) => self.report(
                     &env,
                     TypeErrorKind::TypeMismatch(
@@ -60,13 +65,13 @@ impl Context {
                         right.quote(env.level),
                     ),
                 ),
-                _ => self.report(&env, kind),
+                kind => self.report(&env, kind),
             }
         }
     }
 
-    fn sub_hole_type(&mut self, env: Env, left: Hole<Virtual>, right: Type<Virtual>) -> Result {
-        match right.deref().as_ref() {
+    fn sub_hole_type(&mut self, env: Env, left: Hole, right: Type<Virtual>) -> Result {
+        match self.holes.deref(&right).as_ref() {
             TypeKind::Forall(forall) => {
                 let lvl_ty = Type::new(TypeKind::Bound(env.level));
                 self.sub_hole_type(
@@ -76,17 +81,19 @@ impl Context {
                 )
             }
             TypeKind::Arrow(pi) => {
-                let HoleInner::Empty(_, kind, _) = left.0.borrow().clone() else {
+                let HoleInner::Empty(_, kind, ..) = left.get() else {
                     unreachable!()
                 };
 
                 let hole_a = self.hole(&env, kind.clone());
                 let hole_b = self.hole(&env, kind);
 
-                left.fill(Type::new(TypeKind::Arrow(Pi {
+                let arrow = Type::new(TypeKind::Arrow(Pi {
                     typ: hole_a.clone(),
                     body: hole_b.clone(),
-                })));
+                }));
+
+                self.holes.fill(&left, arrow);
 
                 let a = pi.typ.clone();
                 let b = pi.body.clone();
@@ -105,25 +112,27 @@ impl Context {
         }
     }
 
-    fn sub_type_hole(&mut self, env: Env, left: Type<Virtual>, right: Hole<Virtual>) -> Result {
-        let deref = &left.deref();
+    fn sub_type_hole(&mut self, env: Env, left: Type<Virtual>, right: Hole) -> Result {
+        let deref = &self.holes.deref(&left);
         match deref.as_ref() {
             TypeKind::Forall(_) => {
                 let left = self.instantiate(&env, deref);
                 self.sub_type_hole(env, left, right)
             }
             TypeKind::Arrow(pi) => {
-                let HoleInner::Empty(_, kind, _) = right.0.borrow().clone() else {
+                let HoleInner::Empty(_, kind, ..) = right.get() else {
                     unreachable!()
                 };
 
                 let hole_a = self.hole(&env, kind.clone());
                 let hole_b = self.hole(&env, kind);
 
-                right.fill(Type::new(TypeKind::Arrow(Pi {
+                let arrow = Type::new(TypeKind::Arrow(Pi {
                     typ: hole_a.clone(),
                     body: hole_b.clone(),
-                })));
+                }));
+
+                self.holes.fill(&right, arrow);
 
                 let a = pi.typ.clone();
                 let b = pi.body.clone();
@@ -142,14 +151,18 @@ impl Context {
         }
     }
 
-    pub fn overlaps(&mut self, env: Env, left: Type<Virtual>, right: Type<Virtual>) -> bool {
-        let result = self.unify(env, left, right);
+    /// Tries a unification and leaves the holes as they were before, to pick between alternatives
+    /// like the records that have a field or the defaults of a number.
+    pub fn attempt(&mut self, unify: impl FnOnce(&mut Self) -> Result) -> bool {
+        let snapshot = self.holes.snapshot();
+        let result = unify(self);
+        self.holes.rollback(snapshot);
         result.is_ok()
     }
 
     pub fn unify(&mut self, env: Env, left: Type<Virtual>, right: Type<Virtual>) -> Result {
-        let l = left.deref();
-        let r = right.deref();
+        let l = self.holes.deref(&left);
+        let r = self.holes.deref(&right);
         match (l.as_ref(), r.as_ref()) {
             (TypeKind::Tuple(x), TypeKind::Tuple(y)) if x.len() == y.len() => x
                 .iter()
@@ -171,16 +184,22 @@ impl Context {
             (TypeKind::Type, TypeKind::Type) => Ok(()),
             (TypeKind::Constraint, TypeKind::Constraint) => Ok(()),
             (TypeKind::Error, _) | (_, TypeKind::Error) => Ok(()),
-            (_, _) => Err(TypeErrorKind::TypeMismatch(
+            (_, _) => Err(Box::new(TypeErrorKind::TypeMismatch(
                 env.clone(),
                 left.quote(env.level),
                 right.quote(env.level),
-            )),
+            ))),
         }
     }
 
-    fn occurs(&self, env: Env, scope: &Level, hole: Hole<Virtual>, typ: Type<Virtual>) -> Result {
-        match typ.deref().as_ref() {
+    fn occurs(
+        &mut self,
+        env: Env,
+        scope: &Level,
+        hole: Hole,
+        typ: Type<Virtual>,
+    ) -> Result {
+        match self.holes.deref(&typ).as_ref() {
             TypeKind::Arrow(pi) => {
                 self.occurs(env.clone(), scope, hole.clone(), pi.typ.clone())?;
                 self.occurs(env, scope, hole, pi.body.clone())
@@ -189,8 +208,8 @@ impl Context {
                 let lvl_ty = Type::new(TypeKind::Bound(env.level));
                 self.occurs(env, scope, hole, forall.body.apply_local(None, lvl_ty))
             }
-            TypeKind::Hole(h) if h.clone() == hole => Err(TypeErrorKind::InfiniteType),
-            TypeKind::Bound(l) if l >= scope => Err(TypeErrorKind::EscapingScope),
+            TypeKind::Hole(h) if h.clone() == hole => Err(Box::new(TypeErrorKind::InfiniteType)),
+            TypeKind::Bound(l) if l >= scope => Err(Box::new(TypeErrorKind::EscapingScope)),
             TypeKind::Tuple(t) => t
                 .iter()
                 .try_for_each(|t| self.occurs(env.clone(), scope, hole.clone(), t.clone())),
@@ -202,14 +221,18 @@ impl Context {
         }
     }
 
-    fn unify_hole(&mut self, env: Env, hole: Hole<Virtual>, right: Type<Virtual>) -> Result {
-        let borrow = hole.0.borrow().clone();
-        match borrow {
-            HoleInner::Empty(_, _, lvl) => match right.deref().as_ref() {
+    fn unify_hole(&mut self, env: Env, hole: Hole, right: Type<Virtual>) -> Result {
+        let hole = self.holes.find(&hole);
+        match hole.get() {
+            HoleInner::Empty(_, _, lvl) => match self.holes.deref(&right).as_ref() {
                 TypeKind::Hole(hole1) if hole == hole1.clone() => Ok(()),
+                TypeKind::Hole(hole1) => {
+                    self.holes.union(&hole, hole1);
+                    Ok(())
+                }
                 _ => {
                     self.occurs(env, &lvl, hole.clone(), right.clone())?;
-                    hole.fill(right);
+                    self.holes.fill(&hole, right);
                     Ok(())
                 }
             },
@@ -3,9 +3,10 @@ use std::{
     path::PathBuf,
 };
 
+use serde::{Deserialize, Serialize};
 use vulpi_intern::Symbol;
 
-#[derive(Clone, PartialEq, Eq, Hash)]
+#[derive(Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
 pub struct Path {
     pub segments: Vec<Symbol>,
 }
@@ -65,7 +66,7 @@ impl Display for Path {
     }
 }
 
-#[derive(Clone, PartialEq, Eq, Hash)]
+#[derive(Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub struct Qualified {
     pub path: Path,
     pub name: Symbol,
@@ -0,0 +1,241 @@
+//! The bytecode format. Each function has a fixed number of local slots that start with the
+//! captured variables followed by the parameters, and the instructions move values between the
+//! slots and an operand stack.
+
+use vulpi_core::primitive::Primitive;
+use vulpi_intern::Symbol;
+use vulpi_location::Span;
+use vulpi_show::{Show, TreeDisplay};
+use vulpi_syntax::r#abstract::{OperationKind, Qualified};
+
+use crate::sync::Builtin;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Instruction {
+    /// Pushes the value of a local slot.
+    Load(u32),
+
+    /// Pops a value into a local slot.
+    Store(u32),
+
+    /// Pushes the value of a global.
+    Global(u32),
+
+    /// Pushes a value from the constant pool.
+    Constant(u32),
+
+    /// Pops the captured variables and pushes a closure of the function.
+    Closure(u32, u32),
+
+    /// Pops the function and then the arguments, pushing the result of the call.
+    Call(u32),
+
+    /// Pops the function and then the arguments, replacing the frame of the current call by the
+    /// one of the new call.
+    TailCall(u32),
+
+    /// Pops the fields and pushes the constructor with the index.
+    Construct(u32, u32),
+
+    /// Pushes the constructor with the index, for constructors that are stored as their tags.
+    Tag(u32),
+
+    /// Pops a constructor and pushes one of its fields.
+    Field(u32),
+
+    /// Pops a value and jumps to the target of the switch table that matches it.
+    Switch(u32),
+
+    Jump(u32),
+    Return,
+
+    /// Pops the arguments and performs the operation with the index.
+    Perform(u32, u32),
+
+    /// Pops a handler instance and the arguments, performing the operation on that handler.
+    PerformAt(u32, u32),
+
+    /// Pops the return clause, the operation clauses and the thunk, running the thunk with the
+    /// handler installed.
+    Handle(u32),
+
+    /// Pops the thunk and runs it with the operations of the mask with the index masked.
+    Mask(u32),
+
+    Unreachable,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Constant {
+    Int(i64),
+    Float(f64),
+    String(Symbol),
+    Char(char),
+    Unit,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Key {
+    Constructor(u32),
+    Constant(u32),
+
+    /// The constants at the start and at the end of an inclusive range of integers or characters.
+    Range(u32, u32),
+}
+
+/// The targets of a [Instruction::Switch]. Values that do not match any key go to the default.
+#[derive(Clone, Debug, Default)]
+pub struct Table {
+    pub cases: Vec<(Key, u32)>,
+    pub default: Option<u32>,
+}
+
+#[derive(Clone, Debug)]
+pub struct Function {
+    pub name: Symbol,
+    pub arity: u32,
+    pub captures: u32,
+    pub locals: u32,
+    pub code: Vec<Instruction>,
+    pub tables: Vec<Table>,
+
+    /// The let declaration that the function is, or that it's in for the functions of lambdas,
+    /// and the span of its name. They name and locate the function in stack traces.
+    pub declaration: Qualified,
+    pub location: Span,
+
+    /// The spans of the sources of the calls, the performs and the unreachable instructions, by
+    /// the positions of the instructions in increasing order.
+    pub spans: Vec<(u32, Span)>,
+}
+
+impl Function {
+    /// The span of the closest instruction with a span at or before the position.
+    pub fn span(&self, position: usize) -> Option<&Span> {
+        let index = self
+            .spans
+            .partition_point(|(at, _)| *at as usize <= position);
+
+        index.checked_sub(1).map(|x| &self.spans[x].1)
+    }
+}
+
+/// The operations that a handler has clauses for, in the order that the clauses are pushed. A
+/// named handler passes its instance to the thunk, and a handler with a `finally` has its thunk
+/// pushed after the return clause.
+#[derive(Clone, Debug)]
+pub struct Handler {
+    pub operations: Vec<(u32, OperationKind)>,
+    pub named: bool,
+    pub finally: bool,
+}
+
+#[derive(Clone, Debug)]
+pub enum Global {
+    /// A let declaration with parameters.
+    Function(u32),
+
+    /// A let declaration without parameters. It's computed by the function the first time that
+    /// it's used.
+    Value(u32),
+
+    External(Primitive),
+
+    /// An external of a channel or a mutex, that is run by the machine.
+    Builtin(Builtin),
+
+    /// An external that is not a primitive. It calls the host function that is registered with
+    /// its binding, and fails when used if there's none.
+    Host(Symbol),
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct Module {
+    pub functions: Vec<Function>,
+    pub globals: Vec<(Qualified, Global)>,
+    pub constants: Vec<Constant>,
+    pub constructors: Vec<Qualified>,
+    pub operations: Vec<Qualified>,
+    pub handlers: Vec<Handler>,
+
+    /// The operations of the masks, so the ones of the same effect are masked together.
+    pub masks: Vec<Vec<u32>>,
+
+    /// The constructors `Prelude.Bool.False` and `Prelude.Bool.True`, used by primitives that
+    /// return booleans. They have no fields, so they are stored as tags.
+    pub booleans: (u32, u32),
+
+    /// The operations `spawn`, `await` and `yield` of `Prelude.Async`, that the scheduler of the
+    /// machine runs when no handler handles them.
+    pub tasks: [u32; 3],
+}
+
+impl Module {
+    pub fn global(&self, name: &Qualified) -> Option<u32> {
+        self.globals
+            .iter()
+            .position(|(global, _)| global == name)
+            .map(|x| x as u32)
+    }
+}
+
+/// Shows the module as a listing of its pools and of the instructions of its functions, numbered
+/// by their positions so the targets of the jumps can be followed.
+impl Show for Module {
+    fn show(&self) -> TreeDisplay {
+        fn listing<T>(label: &str, items: &[T], show: impl Fn(&T) -> String) -> TreeDisplay {
+            items
+                .iter()
+                .enumerate()
+                .fold(TreeDisplay::label(label), |tree, (index, item)| {
+                    tree.with(TreeDisplay::label(&format!("{}: {}", index, show(item))))
+                })
+        }
+
+        let functions = self.functions.iter().enumerate().fold(
+            TreeDisplay::label("Functions"),
+            |tree, (index, function)| {
+                let label = format!(
+                    "{}: {} (arity {}, captures {}, locals {})",
+                    index,
+                    function.name.get(),
+                    function.arity,
+                    function.captures,
+                    function.locals
+                );
+
+                let code = listing("Code", &function.code, |x| format!("{:?}", x));
+                let tables = listing("Tables", &function.tables, |x| format!("{:?}", x));
+                let spans = listing("Spans", &function.spans, |(at, span)| {
+                    format!("{} at {:?}", at, span)
+                });
+
+                tree.with(
+                    TreeDisplay::label(&label)
+                        .with(code)
+                        .with(tables)
+                        .with(spans),
+                )
+            },
+        );
+
+        let globals = listing("Globals", &self.globals, |(name, global)| {
+            format!("{} = {:?}", name.to_string(), global)
+        });
+
+        let constants = listing("Constants", &self.constants, |x| format!("{:?}", x));
+        let constructors = listing("Constructors", &self.constructors, Qualified::to_string);
+        let operations = listing("Operations", &self.operations, Qualified::to_string);
+        let handlers = listing("Handlers", &self.handlers, |x| format!("{:?}", x));
+        let masks = listing("Masks", &self.masks, |x| format!("{:?}", x));
+
+        TreeDisplay::label("Module")
+            .with(globals)
+            .with(constants)
+            .with(constructors)
+            .with(operations)
+            .with(handlers)
+            .with(masks)
+            .with(functions)
+    }
+}
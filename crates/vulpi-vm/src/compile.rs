@@ -0,0 +1,482 @@
+//! Compiler from the core language to bytecode. Every let declaration and lambda becomes a
+//! [Function], join points become labels inside of the function that they're declared in and
+//! matches become switch tables. Constructors follow their [Layout], so unboxed constructors do not
+//! exist at runtime and the ones of types without fields do not allocate.
+
+use std::collections::HashMap;
+
+use vulpi_intern::Symbol;
+use vulpi_location::Span;
+use vulpi_syntax::{elaborated::LiteralKind, r#abstract::Qualified};
+
+use vulpi_core::{
+    layout::{Layout, Layouts},
+    primitive::Primitive,
+    syntax::{self as core, free_variables, Atom, Case, TermKind, Value},
+};
+
+use crate::{
+    bytecode::{Constant, Function, Global, Handler, Instruction, Key, Module, Table},
+    sync::Builtin,
+};
+
+/// The function that is being compiled.
+#[derive(Default)]
+struct Builder {
+    code: Vec<Instruction>,
+    tables: Vec<Table>,
+    slots: HashMap<Symbol, u32>,
+    locals: u32,
+    labels: HashMap<Symbol, (u32, Vec<u32>)>,
+    patches: Vec<(usize, Symbol)>,
+    arities: HashMap<Symbol, usize>,
+    spans: Vec<(u32, Span)>,
+}
+
+impl Builder {
+    fn slot(&mut self, name: &Symbol) -> u32 {
+        let slot = self.locals;
+        self.locals += 1;
+        self.slots.insert(name.clone(), slot);
+        slot
+    }
+
+    fn emit(&mut self, instruction: Instruction) -> usize {
+        self.code.push(instruction);
+        self.code.len() - 1
+    }
+
+    /// Emits an instruction that comes from the span of the sources.
+    fn emit_at(&mut self, instruction: Instruction, span: &Span) -> usize {
+        let index = self.emit(instruction);
+        self.spans.push((index as u32, span.clone()));
+        index
+    }
+
+    fn position(&self) -> u32 {
+        self.code.len() as u32
+    }
+
+    fn finish(
+        mut self,
+        name: Symbol,
+        declaration: (Qualified, Span),
+        arity: usize,
+        captures: usize,
+    ) -> Function {
+        for (index, label) in std::mem::take(&mut self.patches) {
+            self.code[index] = Instruction::Jump(self.labels[&label].0);
+        }
+
+        Function {
+            name,
+            arity: arity as u32,
+            captures: captures as u32,
+            locals: self.locals,
+            code: self.code,
+            tables: self.tables,
+            declaration: declaration.0,
+            location: declaration.1,
+            spans: self.spans,
+        }
+    }
+}
+
+fn literal(literal: &LiteralKind) -> Constant {
+    match literal {
+        LiteralKind::String(x) => Constant::String(x.clone()),
+        LiteralKind::Integer(x) => Constant::Int(x.get().replace('_', "").parse().unwrap_or(0)),
+        LiteralKind::Float(x) => Constant::Float(x.get().replace('_', "").parse().unwrap_or(0.0)),
+        LiteralKind::Char(x) => Constant::Char(x.get().chars().next().unwrap_or_default()),
+        LiteralKind::Unit => Constant::Unit,
+    }
+}
+
+#[derive(Default)]
+pub struct Compiler {
+    module: Module,
+    globals: HashMap<Qualified, u32>,
+    constructors: HashMap<Qualified, u32>,
+    operations: HashMap<Qualified, u32>,
+    layouts: Layouts,
+
+    /// The let declaration whose functions are being compiled.
+    declaration: Option<(Qualified, Span)>,
+}
+
+impl Compiler {
+    fn constructor(&mut self, name: &Qualified) -> u32 {
+        if let Some(index) = self.constructors.get(name) {
+            return *index;
+        }
+
+        let index = self.module.constructors.len() as u32;
+        self.module.constructors.push(name.clone());
+        self.constructors.insert(name.clone(), index);
+        index
+    }
+
+    fn operation(&mut self, name: &Qualified) -> u32 {
+        if let Some(index) = self.operations.get(name) {
+            return *index;
+        }
+
+        let index = self.module.operations.len() as u32;
+        self.module.operations.push(name.clone());
+        self.operations.insert(name.clone(), index);
+        index
+    }
+
+    fn constant(&mut self, constant: Constant) -> u32 {
+        self.module.constants.push(constant);
+        self.module.constants.len() as u32 - 1
+    }
+
+    fn atom(&mut self, builder: &mut Builder, atom: &Atom) {
+        let instruction = match atom {
+            Atom::Variable(name) => match builder.slots.get(name) {
+                Some(slot) => Instruction::Load(*slot),
+                None => Instruction::Unreachable,
+            },
+            Atom::Function(name, _) => match self.globals.get(name) {
+                Some(global) => Instruction::Global(*global),
+                None => Instruction::Unreachable,
+            },
+            Atom::Literal(lit) => Instruction::Constant(self.constant(literal(lit))),
+        };
+
+        builder.emit(instruction);
+    }
+
+    fn atoms(&mut self, builder: &mut Builder, atoms: &[Atom]) {
+        for atom in atoms {
+            self.atom(builder, atom);
+        }
+    }
+
+    fn function(
+        &mut self,
+        name: Symbol,
+        captures: &[Symbol],
+        params: &[core::Binder],
+        body: &TermKind,
+    ) -> u32 {
+        let index = self.module.functions.len();
+
+        let declaration = self
+            .declaration
+            .clone()
+            .expect("functions are compiled inside of declarations");
+
+        // Reserves the index, so nested functions come after their parent.
+        self.module.functions.push(Function {
+            name: name.clone(),
+            arity: 0,
+            captures: 0,
+            locals: 0,
+            code: vec![],
+            tables: vec![],
+            declaration: declaration.0.clone(),
+            location: declaration.1.clone(),
+            spans: vec![],
+        });
+
+        let mut builder = Builder::default();
+
+        for capture in captures {
+            builder.slot(capture);
+        }
+
+        for param in params {
+            builder.slot(&param.name);
+        }
+
+        self.term(&mut builder, body);
+
+        self.module.functions[index] =
+            builder.finish(name, declaration, params.len(), captures.len());
+        index as u32
+    }
+
+    fn value(&mut self, builder: &mut Builder, name: &Symbol, value: &Value) {
+        match value {
+            Value::Atom(atom) => self.atom(builder, atom),
+            Value::Lambda(params, body) => {
+                let captures = free_variables(params, body);
+                let captures: Vec<_> = captures
+                    .into_iter()
+                    .filter(|x| builder.slots.contains_key(x))
+                    .collect();
+
+                let function = self.function(name.clone(), &captures, params, body);
+
+                for capture in &captures {
+                    builder.emit(Instruction::Load(builder.slots[capture]));
+                }
+
+                builder.arities.insert(name.clone(), params.len());
+                builder.emit(Instruction::Closure(function, captures.len() as u32));
+            }
+            Value::Application(func, args, span) => {
+                self.atoms(builder, args);
+                self.atom(builder, func);
+                builder.emit_at(Instruction::Call(args.len() as u32), span);
+            }
+            Value::Constructor(constructor, args) => {
+                self.atoms(builder, args);
+                let index = self.constructor(constructor);
+
+                match self.layouts.get(constructor) {
+                    Layout::Boxed(_) => {
+                        builder.emit(Instruction::Construct(index, args.len() as u32));
+                    }
+                    Layout::Tag(_) => {
+                        builder.emit(Instruction::Tag(index));
+                    }
+                    Layout::Unboxed => (),
+                }
+            }
+            Value::Field(constructor, atom, field) => {
+                self.atom(builder, atom);
+
+                if self.layouts.get(constructor) != Layout::Unboxed {
+                    builder.emit(Instruction::Field(*field as u32));
+                }
+            }
+            Value::Perform(instance, operation, args, span) => {
+                self.atoms(builder, args);
+                let index = self.operation(operation);
+
+                match instance {
+                    Some(instance) => {
+                        self.atom(builder, instance);
+                        builder.emit_at(Instruction::PerformAt(index, args.len() as u32), span);
+                    }
+                    None => {
+                        builder.emit_at(Instruction::Perform(index, args.len() as u32), span);
+                    }
+                }
+            }
+            Value::Handle(thunk, clauses, ret, finally) => {
+                self.atom(builder, thunk);
+
+                let mut operations = Vec::new();
+
+                for (operation, kind, clause) in clauses {
+                    self.atom(builder, clause);
+                    operations.push((self.operation(operation), *kind));
+                }
+
+                self.atom(builder, ret);
+
+                if let Some(finally) = finally {
+                    self.atom(builder, finally);
+                }
+
+                let named = match thunk {
+                    Atom::Variable(name) => builder.arities.get(name) == Some(&1),
+                    _ => false,
+                };
+
+                self.module.handlers.push(Handler {
+                    operations,
+                    named,
+                    finally: finally.is_some(),
+                });
+                let handler = self.module.handlers.len() as u32 - 1;
+
+                builder.emit(Instruction::Handle(handler));
+            }
+            Value::Mask(operations, thunk) => {
+                self.atom(builder, thunk);
+
+                let operations = operations.iter().map(|x| self.operation(x)).collect();
+                self.module.masks.push(operations);
+                let mask = self.module.masks.len() as u32 - 1;
+
+                builder.emit(Instruction::Mask(mask));
+            }
+        }
+    }
+
+    fn term(&mut self, builder: &mut Builder, term: &TermKind) {
+        match term {
+            TermKind::Let(binder, value, rest) => {
+                self.value(builder, &binder.name, value);
+                let slot = builder.slot(&binder.name);
+                builder.emit(Instruction::Store(slot));
+                self.term(builder, rest);
+            }
+            TermKind::Join(label, params, body, rest) => {
+                let params: Vec<_> = params.iter().map(|x| builder.slot(&x.name)).collect();
+                builder.labels.insert(label.clone(), (0, params));
+
+                self.term(builder, rest);
+
+                let position = builder.position();
+                builder.labels.get_mut(label).unwrap().0 = position;
+
+                self.term(builder, body);
+            }
+            TermKind::Jump(label, args) => {
+                self.atoms(builder, args);
+
+                let params = builder.labels[label].1.clone();
+
+                for slot in params.iter().rev() {
+                    builder.emit(Instruction::Store(*slot));
+                }
+
+                let index = builder.emit(Instruction::Jump(0));
+                builder.patches.push((index, label.clone()));
+            }
+            TermKind::Tail(func, args, span) => {
+                self.atoms(builder, args);
+                self.atom(builder, func);
+                builder.emit_at(Instruction::TailCall(args.len() as u32), span);
+            }
+            TermKind::Match(atom, alts, default) => {
+                // The only constructor of an unboxed type always matches and its field is the
+                // value itself.
+                if let Some(alt) = alts.first() {
+                    if let Case::Constructor(name) = &alt.case {
+                        if self.layouts.get(name) == Layout::Unboxed {
+                            for binder in &alt.binders {
+                                self.atom(builder, atom);
+                                let slot = builder.slot(&binder.name);
+                                builder.emit(Instruction::Store(slot));
+                            }
+
+                            return self.term(builder, &alt.body);
+                        }
+                    }
+                }
+
+                self.atom(builder, atom);
+
+                let table = builder.tables.len();
+                builder.tables.push(Table::default());
+                builder.emit(Instruction::Switch(table as u32));
+
+                for alt in alts {
+                    let key = match &alt.case {
+                        Case::Constructor(name) => Key::Constructor(self.constructor(name)),
+                        Case::Literal(lit) => Key::Constant(self.constant(literal(lit))),
+                        Case::Range(start, end) => {
+                            Key::Range(self.constant(literal(start)), self.constant(literal(end)))
+                        }
+                    };
+
+                    let position = builder.position();
+                    builder.tables[table].cases.push((key, position));
+
+                    for (i, binder) in alt.binders.iter().enumerate() {
+                        self.atom(builder, atom);
+                        builder.emit(Instruction::Field(i as u32));
+                        let slot = builder.slot(&binder.name);
+                        builder.emit(Instruction::Store(slot));
+                    }
+
+                    self.term(builder, &alt.body);
+                }
+
+                if let Some(default) = default {
+                    builder.tables[table].default = Some(builder.position());
+                    self.term(builder, default);
+                }
+            }
+            TermKind::Return(atom) => {
+                self.atom(builder, atom);
+                builder.emit(Instruction::Return);
+            }
+            TermKind::Unreachable => {
+                // Matches that fail are located at their declaration.
+                match &self.declaration {
+                    Some((_, span)) => builder.emit_at(Instruction::Unreachable, span),
+                    None => builder.emit(Instruction::Unreachable),
+                };
+            }
+        }
+    }
+}
+
+/// Compiles a core program into a module of bytecode.
+pub fn compile(program: &core::Program) -> Module {
+    let mut ctx = Compiler {
+        layouts: Layouts::new(program),
+        ..Default::default()
+    };
+
+    for typ in &program.types {
+        for (constructor, _) in &typ.constructors {
+            ctx.constructor(constructor);
+        }
+    }
+
+    for effect in &program.effects {
+        for (operation, _, _) in &effect.operations {
+            ctx.operation(operation);
+        }
+    }
+
+    let bool = |name: &str| Qualified {
+        path: Symbol::intern("Prelude.Bool"),
+        name: Symbol::intern(name),
+    };
+
+    ctx.module.booleans = (
+        ctx.constructor(&bool("False")),
+        ctx.constructor(&bool("True")),
+    );
+
+    let task = |name: &str| Qualified {
+        path: Symbol::intern("Prelude.Async"),
+        name: Symbol::intern(name),
+    };
+
+    ctx.module.tasks = [
+        ctx.operation(&task("spawn")),
+        ctx.operation(&task("await")),
+        ctx.operation(&task("yield")),
+    ];
+
+    // Globals are declared before compiling any function so they can refer to each other.
+    for external in &program.externals {
+        let global = match Primitive::from_binding(&external.binding.get()) {
+            Some(primitive) => Global::External(primitive),
+            None => match Builtin::from_binding(external.binding.get().trim()) {
+                Some(builtin) => Global::Builtin(builtin),
+                None => Global::Host(Symbol::intern(external.binding.get().trim())),
+            },
+        };
+
+        ctx.globals
+            .insert(external.name.clone(), ctx.module.globals.len() as u32);
+        ctx.module.globals.push((external.name.clone(), global));
+    }
+
+    for decl in &program.lets {
+        ctx.globals
+            .insert(decl.name.clone(), ctx.module.globals.len() as u32);
+        ctx.module
+            .globals
+            .push((decl.name.clone(), Global::Function(0)));
+    }
+
+    for decl in &program.lets {
+        let name = Symbol::intern(&decl.name.to_string());
+        ctx.declaration = Some((decl.name.clone(), decl.span.clone()));
+        let function = ctx.function(name, &[], &decl.params, &decl.body);
+
+        let global = if decl.params.is_empty() {
+            Global::Value(function)
+        } else {
+            Global::Function(function)
+        };
+
+        let index = ctx.globals[&decl.name] as usize;
+        ctx.module.globals[index].1 = global;
+    }
+
+    ctx.module
+}
@@ -0,0 +1,181 @@
+//! Functions of the host that externals can be bound to. An external that is not a primitive
+//! calls the host function registered with its binding, so a program that uses C functions in
+//! the native backend can run in the virtual machine if the application registers Rust versions
+//! of them.
+//!
+//! Typed host functions receive and return Rust types that are marshalled by [Marshal]: integers
+//! are `i64`, floats are `f64`, characters are `char`, strings are `String` and the unit is `()`.
+
+use std::{collections::HashMap, rc::Rc};
+
+use vulpi_intern::Symbol;
+
+use crate::value::Value;
+
+/// A Rust type that has a representation as a value of the virtual machine.
+pub trait Marshal: Sized {
+    fn from_value(value: &Value) -> Option<Self>;
+    fn into_value(self) -> Value;
+}
+
+impl Marshal for i64 {
+    fn from_value(value: &Value) -> Option<Self> {
+        match value {
+            Value::Int(x) => Some(*x),
+            _ => None,
+        }
+    }
+
+    fn into_value(self) -> Value {
+        Value::Int(self)
+    }
+}
+
+impl Marshal for f64 {
+    fn from_value(value: &Value) -> Option<Self> {
+        match value {
+            Value::Float(x) => Some(*x),
+            _ => None,
+        }
+    }
+
+    fn into_value(self) -> Value {
+        Value::Float(self)
+    }
+}
+
+impl Marshal for char {
+    fn from_value(value: &Value) -> Option<Self> {
+        match value {
+            Value::Char(x) => Some(*x),
+            _ => None,
+        }
+    }
+
+    fn into_value(self) -> Value {
+        Value::Char(self)
+    }
+}
+
+impl Marshal for String {
+    fn from_value(value: &Value) -> Option<Self> {
+        match value {
+            Value::String(x) => Some(x.to_string()),
+            _ => None,
+        }
+    }
+
+    fn into_value(self) -> Value {
+        Value::String(self.into())
+    }
+}
+
+impl Marshal for () {
+    fn from_value(value: &Value) -> Option<Self> {
+        match value {
+            Value::Unit => Some(()),
+            _ => None,
+        }
+    }
+
+    fn into_value(self) -> Value {
+        Value::Unit
+    }
+}
+
+/// The reasons that a host function can fail with.
+#[derive(Debug)]
+pub enum HostError {
+    /// An argument doesn't have the type that the function expects.
+    InvalidArguments,
+
+    Failed(String),
+}
+
+type Function = dyn Fn(Vec<Value>) -> Result<Value, HostError>;
+
+/// A host function with the number of arguments that it receives.
+#[derive(Clone)]
+pub struct HostFn {
+    pub arity: usize,
+    function: Rc<Function>,
+}
+
+impl HostFn {
+    pub fn new(
+        arity: usize,
+        function: impl Fn(Vec<Value>) -> Result<Value, HostError> + 'static,
+    ) -> Self {
+        Self {
+            arity,
+            function: Rc::new(function),
+        }
+    }
+
+    pub fn call(&self, args: Vec<Value>) -> Result<Value, HostError> {
+        (self.function)(args)
+    }
+}
+
+fn marshal<T: Marshal>(value: &Value) -> Result<T, HostError> {
+    T::from_value(value).ok_or(HostError::InvalidArguments)
+}
+
+/// The host functions by the bindings of the externals that call them.
+#[derive(Clone, Default)]
+pub struct Host {
+    functions: HashMap<Symbol, HostFn>,
+}
+
+impl Host {
+    pub fn get(&self, binding: &Symbol) -> Option<&HostFn> {
+        self.functions.get(binding)
+    }
+
+    pub fn register(&mut self, binding: &str, function: HostFn) {
+        self.functions.insert(Symbol::intern(binding), function);
+    }
+
+    /// Registers a function of one argument. Externals of functions without parameters take the
+    /// unit, so they're registered with `()` as the argument.
+    pub fn register1<A, R>(&mut self, binding: &str, function: impl Fn(A) -> R + 'static)
+    where
+        A: Marshal,
+        R: Marshal,
+    {
+        let function = HostFn::new(1, move |args| Ok(function(marshal(&args[0])?).into_value()));
+
+        self.register(binding, function);
+    }
+
+    pub fn register2<A, B, R>(&mut self, binding: &str, function: impl Fn(A, B) -> R + 'static)
+    where
+        A: Marshal,
+        B: Marshal,
+        R: Marshal,
+    {
+        let function = HostFn::new(2, move |args| {
+            Ok(function(marshal(&args[0])?, marshal(&args[1])?).into_value())
+        });
+
+        self.register(binding, function);
+    }
+
+    pub fn register3<A, B, C, R>(
+        &mut self,
+        binding: &str,
+        function: impl Fn(A, B, C) -> R + 'static,
+    ) where
+        A: Marshal,
+        B: Marshal,
+        C: Marshal,
+        R: Marshal,
+    {
+        let function = HostFn::new(3, move |args| {
+            let result = function(marshal(&args[0])?, marshal(&args[1])?, marshal(&args[2])?);
+            Ok(result.into_value())
+        });
+
+        self.register(binding, function);
+    }
+}
@@ -0,0 +1,11 @@
+//! A virtual machine that runs programs without any native toolchain. The core language is
+//! compiled into a compact bytecode that is run by a stack based interpreter.
+
+pub mod bytecode;
+pub mod compile;
+pub mod host;
+pub mod machine;
+pub mod scheduler;
+pub mod sync;
+pub mod trace;
+pub mod value;
@@ -0,0 +1,1355 @@
+//! The interpreter of the bytecode. It keeps the local slots and the operands of every call in a
+//! single value stack and the calls in a stack of frames.
+//!
+//! Installing a handler pushes a handler frame. Performing an operation looks for the closest
+//! handler frame that handles it, cuts the frames and values above it into a [Continuation] and
+//! calls the clause of the operation. Resuming the continuation copies them back on top of the
+//! stack, so continuations can be resumed more than once.
+//!
+//! A handler with a `finally` pushes a frame below its handler frame that runs the thunk of the
+//! `finally` when a value reaches it, so it runs after the return clause and after a clause that
+//! doesn't resume. When a `ctl` clause of an outer handler gets the continuation, the frames of
+//! the `finally`s inside of it run when the clause returns without having resumed it. A
+//! continuation that is stored and resumed after its clause returned has already run them.
+//!
+//! A mask pushes a frame like a handler frame. Searching for the handler of an operation skips one
+//! handler of it for each mask of it that is above that handler.
+//!
+//! The operations of `Prelude.Async` that no handler handles are run by the [Scheduler], that
+//! switches between the tasks by cutting all of the frames into a continuation. The builtins of
+//! channels and mutexes make the tasks wait in the same way.
+//!
+//! Each perform remembers the handler that it found, together with the shape of the handlers that
+//! were installed then. While the shape is the same, performing it again goes straight to that
+//! handler instead of searching the frames, which makes the effects of hot loops cheap.
+
+use std::{cell::Cell, collections::HashMap, fmt, io::Write, rc::Rc};
+
+use vulpi_core::{
+    primitive::{Number, Primitive},
+    syntax::tuple,
+};
+use vulpi_intern::Symbol;
+use vulpi_syntax::r#abstract::{OperationKind, Qualified};
+
+use crate::{
+    bytecode::{Constant, Global, Instruction, Key, Module},
+    host::{Host, HostError},
+    scheduler::{Ready, Scheduler, Task},
+    sync::{Builtin, Channel, Mutex},
+    trace::Location,
+    value::{Closure, Code, Handle, Value},
+};
+
+pub enum RuntimeError {
+    UnhandledOperation(Qualified),
+    UnknownExternal(Symbol),
+    Host(Symbol, HostError),
+    UnknownGlobal(Qualified),
+    NotAFunction,
+    NotAConstructor,
+    InvalidArguments(Primitive),
+    InvalidBuiltinArguments(Builtin),
+    DivisionByZero,
+    Overflow,
+    IndexOutOfBounds(i64),
+    SplitCharacter(i64),
+    InvalidCharacter(i64),
+    ArrayIndex(i64, usize),
+    NegativeLength(i64),
+    NegativeCapacity(i64),
+    NotLocked,
+    Unreachable,
+    Deadlock,
+    Io(std::io::Error),
+}
+
+impl fmt::Display for RuntimeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RuntimeError::UnhandledOperation(name) => {
+                write!(f, "unhandled operation '{}'", name.to_string())
+            }
+            RuntimeError::UnknownExternal(binding) => {
+                write!(f, "the external '{}' is not available", binding.get())
+            }
+            RuntimeError::Host(binding, HostError::InvalidArguments) => {
+                write!(f, "invalid arguments for the external '{}'", binding.get())
+            }
+            RuntimeError::Host(binding, HostError::Failed(message)) => {
+                write!(f, "the external '{}' failed: {}", binding.get(), message)
+            }
+            RuntimeError::UnknownGlobal(name) => write!(f, "cannot find '{}'", name.to_string()),
+            RuntimeError::NotAFunction => write!(f, "called a value that is not a function"),
+            RuntimeError::NotAConstructor => write!(f, "accessed a field of a non constructor"),
+            RuntimeError::InvalidArguments(primitive) => {
+                write!(f, "invalid arguments for the primitive {:?}", primitive)
+            }
+            RuntimeError::InvalidBuiltinArguments(builtin) => {
+                write!(f, "invalid arguments for the builtin {:?}", builtin)
+            }
+            RuntimeError::DivisionByZero => write!(f, "division by zero"),
+            RuntimeError::Overflow => write!(f, "integer overflow"),
+            RuntimeError::IndexOutOfBounds(index) => {
+                write!(f, "the index {} is outside of the string", index)
+            }
+            RuntimeError::SplitCharacter(offset) => {
+                write!(f, "the offset {} is inside of a character", offset)
+            }
+            RuntimeError::InvalidCharacter(code) => {
+                write!(f, "{} is not the code point of a character", code)
+            }
+            RuntimeError::ArrayIndex(index, length) => {
+                write!(
+                    f,
+                    "the index {} is outside of an array of length {}",
+                    index, length
+                )
+            }
+            RuntimeError::NegativeLength(length) => {
+                write!(f, "the length {} of an array is negative", length)
+            }
+            RuntimeError::NegativeCapacity(capacity) => {
+                write!(f, "the capacity {} of a channel is negative", capacity)
+            }
+            RuntimeError::NotLocked => write!(f, "unlocked a mutex that is not locked"),
+            RuntimeError::Unreachable => write!(f, "reached code that should be unreachable"),
+            RuntimeError::Deadlock => write!(f, "every task is waiting for another one"),
+            RuntimeError::Io(err) => write!(f, "{}", err),
+        }
+    }
+}
+
+type Result<T> = std::result::Result<T, RuntimeError>;
+
+#[derive(Clone)]
+enum Frame {
+    Call {
+        function: u32,
+        pc: usize,
+        base: usize,
+    },
+
+    /// A handler that is installed. The base is the size of the stack when it was installed.
+    Handler {
+        id: usize,
+        handler: u32,
+        shape: Rc<Shape>,
+        clauses: Rc<[Value]>,
+        ret: Value,
+        base: usize,
+    },
+
+    /// The operations of a mask, that skip the closest handler below it. It has a shape like the
+    /// handlers, so the performs don't remember the handlers that they found across it.
+    Mask { mask: u32, shape: Rc<Shape> },
+
+    /// Resumes the continuation with the result of the clause of a `fun` operation.
+    Resume(Rc<Continuation>),
+
+    /// Runs the thunk of a `finally` when the handler below it gives its result.
+    Finally(Value),
+
+    /// Gives the value instead of the result, that is the one of a `finally`.
+    Discard(Value),
+
+    /// Runs the `finally`s inside of the continuation that a `ctl` clause got, unless the clause
+    /// resumed it.
+    Unwind(Rc<Continuation>),
+
+    /// Applies the result to the arguments that were left by a call with too many arguments.
+    Apply(Vec<Value>),
+
+    /// Stores the result as the value of the global.
+    Global(u32),
+
+    /// Finishes the task whose function gives its result. It's the bottom frame of the task.
+    Task(Rc<Task>),
+}
+
+impl Frame {
+    fn rebase(&mut self, from: usize, to: usize) {
+        match self {
+            Frame::Call { base, .. } | Frame::Handler { base, .. } => *base = *base - from + to,
+            _ => (),
+        }
+    }
+}
+
+/// The handlers that are installed. Each handler frame has a shape whose parent is the one of the
+/// handler below it, so two handler frames have the same shape only if the handlers below them are
+/// the same, and the shape of the innermost handler identifies all of them.
+struct Shape {
+    parent: Option<Rc<Shape>>,
+}
+
+/// The shapes are dropped with a loop, so dropping the ones of deeply nested handlers does not
+/// overflow the stack.
+impl Drop for Shape {
+    fn drop(&mut self) {
+        let mut parent = self.parent.take();
+
+        while let Some(shape) = parent {
+            parent = match Rc::try_unwrap(shape) {
+                Ok(mut shape) => shape.parent.take(),
+                Err(_) => None,
+            };
+        }
+    }
+}
+
+/// The position of an offset of a string that is the start of a character or the end of it.
+fn boundary(string: &str, offset: i64) -> Result<usize> {
+    let position = usize::try_from(offset)
+        .ok()
+        .filter(|x| *x <= string.len())
+        .ok_or(RuntimeError::IndexOutOfBounds(offset))?;
+
+    if string.is_char_boundary(position) {
+        Ok(position)
+    } else {
+        Err(RuntimeError::SplitCharacter(offset))
+    }
+}
+
+/// Checks if an integer or a character is inside of an inclusive range.
+fn within(start: &Value, value: &Value, end: &Value) -> bool {
+    match (start, value, end) {
+        (Value::Int(start), Value::Int(x), Value::Int(end)) => start <= x && x <= end,
+        (Value::Char(start), Value::Char(x), Value::Char(end)) => start <= x && x <= end,
+        _ => false,
+    }
+}
+
+/// The position of an index of an array with the length.
+fn element(length: usize, index: i64) -> Result<usize> {
+    usize::try_from(index)
+        .ok()
+        .filter(|x| *x < length)
+        .ok_or(RuntimeError::ArrayIndex(index, length))
+}
+
+/// The object of a handle of the runtime.
+fn object<T: 'static>(value: &Value) -> Option<&T> {
+    match value {
+        Value::Handle(handle) => handle.downcast_ref(),
+        _ => None,
+    }
+}
+
+fn same(l: &Option<Rc<Shape>>, r: &Option<Rc<Shape>>) -> bool {
+    match (l, r) {
+        (Some(l), Some(r)) => Rc::ptr_eq(l, r),
+        (None, None) => true,
+        _ => false,
+    }
+}
+
+/// The handler that a perform found the last time that it ran.
+struct Site {
+    /// The shape of the handlers when it ran.
+    shape: Option<Rc<Shape>>,
+
+    /// The shape of the handler frame that it found, with its position and the clause.
+    target: Rc<Shape>,
+    index: usize,
+    clause: usize,
+    instance: Option<usize>,
+}
+
+/// The part of the computation between a perform and its handler. Programs get it in a handle.
+pub struct Continuation {
+    frames: Vec<Frame>,
+    stack: Vec<Value>,
+    base: usize,
+    resumed: Cell<bool>,
+}
+
+/// The thunks of the `finally`s that are left when the frames are thrown away, from the outermost
+/// one. The ones of the continuations that are not done yet are included.
+fn finalizers(frames: &[Frame], found: &mut Vec<Value>) {
+    for frame in frames {
+        match frame {
+            Frame::Finally(thunk) => found.push(thunk.clone()),
+            Frame::Resume(cont) => finalizers(&cont.frames, found),
+            Frame::Unwind(cont) if !cont.resumed.get() => finalizers(&cont.frames, found),
+            _ => (),
+        }
+    }
+}
+
+/// The instance of a named handler that programs get in a handle. It's the unique number of the
+/// handler that is installed.
+struct Instance(usize);
+
+pub struct Machine<'a> {
+    module: &'a Module,
+    stack: Vec<Value>,
+    frames: Vec<Frame>,
+    globals: Vec<Option<Value>>,
+    handlers: usize,
+
+    /// The shape of the innermost handler that is installed.
+    shape: Option<Rc<Shape>>,
+
+    /// The handlers found by the performs, by the function and the position of their instructions.
+    sites: HashMap<(u32, usize), Site>,
+    result: Option<Value>,
+    output: Box<dyn Write + 'a>,
+    host: Host,
+    trace: Vec<Location>,
+    scheduler: Scheduler,
+}
+
+impl<'a> Machine<'a> {
+    pub fn new(module: &'a Module) -> Self {
+        Self::with_output(module, Box::new(std::io::stdout()))
+    }
+
+    pub fn with_output(module: &'a Module, output: Box<dyn Write + 'a>) -> Self {
+        Self {
+            module,
+            stack: Vec::new(),
+            frames: Vec::new(),
+            globals: vec![None; module.globals.len()],
+            handlers: 0,
+            shape: None,
+            sites: HashMap::new(),
+            result: None,
+            output,
+            host: Host::default(),
+            trace: Vec::new(),
+            scheduler: Scheduler::default(),
+        }
+    }
+
+    /// The host functions that the externals of the program can call.
+    pub fn host(&mut self) -> &mut Host {
+        &mut self.host
+    }
+
+    /// Computes the value of a global. Functions without parameters are called with unit.
+    pub fn run(&mut self, name: &Qualified) -> Result<Value> {
+        let global = self
+            .module
+            .global(name)
+            .ok_or_else(|| RuntimeError::UnknownGlobal(name.clone()))?;
+
+        let value = self.execute(|this| this.global(global))?;
+
+        match &value {
+            Value::Closure(closure)
+                if matches!(closure.code, Code::Function(function)
+                    if self.module.functions[function as usize].arity == 1) =>
+            {
+                self.execute(|this| this.call(value, vec![Value::Unit]))
+            }
+            _ => Ok(value),
+        }
+    }
+
+    /// Computes the values of the let declarations without parameters in the order of the module,
+    /// except the entry point, so they're initialized before it runs.
+    pub fn initialize(&mut self, entry: &Qualified) -> Result<()> {
+        for (index, (name, global)) in self.module.globals.iter().enumerate() {
+            if name == entry || !matches!(global, Global::Value(_)) {
+                continue;
+            }
+
+            if self.globals[index].is_none() {
+                self.execute(|this| this.global(index as u32))?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Applies a global to the arguments. A function that receives less arguments than its arity
+    /// returns a partial application.
+    pub fn apply(&mut self, name: &Qualified, args: Vec<Value>) -> Result<Value> {
+        let global = self
+            .module
+            .global(name)
+            .ok_or_else(|| RuntimeError::UnknownGlobal(name.clone()))?;
+
+        let value = self.execute(|this| this.global(global))?;
+
+        if args.is_empty() {
+            Ok(value)
+        } else {
+            self.execute(|this| this.call(value, args))
+        }
+    }
+
+    /// The calls that were running when the last error happened, from the innermost one.
+    pub fn trace(&self) -> &[Location] {
+        &self.trace
+    }
+
+    fn execute(&mut self, start: impl FnOnce(&mut Self) -> Result<()>) -> Result<Value> {
+        self.stack.clear();
+        self.frames.clear();
+        self.shape = None;
+        self.trace.clear();
+        self.result = None;
+        self.scheduler = Scheduler::default();
+
+        let result = self.drive(start);
+
+        if result.is_err() {
+            self.trace = self.locations();
+        }
+
+        result
+    }
+
+    fn drive(&mut self, start: impl FnOnce(&mut Self) -> Result<()>) -> Result<Value> {
+        start(self)?;
+
+        loop {
+            if let Some(value) = self.result.take() {
+                return Ok(value);
+            }
+
+            self.step()?;
+        }
+    }
+
+    /// The locations of the calls of the stack. The position of a call is the one after the
+    /// instruction that it's running.
+    fn locations(&self) -> Vec<Location> {
+        self.frames
+            .iter()
+            .rev()
+            .filter_map(|frame| match frame {
+                Frame::Call { function, pc, .. } => {
+                    let function = &self.module.functions[*function as usize];
+                    let span = function.span(pc.saturating_sub(1));
+
+                    Some(Location {
+                        function: function.declaration.clone(),
+                        span: span.unwrap_or(&function.location).clone(),
+                    })
+                }
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// Gives a value to the frame at the top of the stack.
+    fn give(&mut self, value: Value) -> Result<()> {
+        match self.frames.last() {
+            // The main computation finished, but the tasks that are ready still run before it
+            // gives its result.
+            None if !self.scheduler.is_idle() => {
+                self.scheduler.finished = Some(value);
+                self.switch()
+            }
+            None => {
+                self.result = Some(value);
+                Ok(())
+            }
+            Some(Frame::Call { .. }) => {
+                self.stack.push(value);
+                Ok(())
+            }
+            Some(Frame::Handler { .. }) => {
+                let Some(Frame::Handler {
+                    ret, base, shape, ..
+                }) = self.frames.pop()
+                else {
+                    unreachable!()
+                };
+
+                self.shape = shape.parent.clone();
+
+                self.stack.truncate(base);
+                self.call(ret, vec![value])
+            }
+            Some(Frame::Mask { .. }) => {
+                let Some(Frame::Mask { shape, .. }) = self.frames.pop() else {
+                    unreachable!()
+                };
+
+                self.shape = shape.parent.clone();
+                self.give(value)
+            }
+            Some(Frame::Resume(_)) => {
+                let Some(Frame::Resume(cont)) = self.frames.pop() else {
+                    unreachable!()
+                };
+
+                self.resume(&cont, value)
+            }
+            Some(Frame::Finally(_)) => {
+                let Some(Frame::Finally(thunk)) = self.frames.pop() else {
+                    unreachable!()
+                };
+
+                self.frames.push(Frame::Discard(value));
+                self.call(thunk, vec![])
+            }
+            Some(Frame::Discard(_)) => {
+                let Some(Frame::Discard(value)) = self.frames.pop() else {
+                    unreachable!()
+                };
+
+                self.give(value)
+            }
+            Some(Frame::Unwind(_)) => {
+                let Some(Frame::Unwind(cont)) = self.frames.pop() else {
+                    unreachable!()
+                };
+
+                let mut thunks = Vec::new();
+
+                if !cont.resumed.get() {
+                    finalizers(&cont.frames, &mut thunks);
+                }
+
+                if thunks.is_empty() {
+                    return self.give(value);
+                }
+
+                // The innermost `finally` is at the top, so it runs first.
+                self.frames.push(Frame::Discard(value));
+                self.frames.extend(thunks.into_iter().map(Frame::Finally));
+                self.give(Value::Unit)
+            }
+            Some(Frame::Apply(_)) => {
+                let Some(Frame::Apply(args)) = self.frames.pop() else {
+                    unreachable!()
+                };
+
+                self.call(value, args)
+            }
+            Some(Frame::Global(_)) => {
+                let Some(Frame::Global(global)) = self.frames.pop() else {
+                    unreachable!()
+                };
+
+                self.globals[global as usize] = Some(value.clone());
+                self.give(value)
+            }
+            Some(Frame::Task(_)) => {
+                let Some(Frame::Task(task)) = self.frames.pop() else {
+                    unreachable!()
+                };
+
+                for cont in task.finish(value.clone()) {
+                    self.scheduler.push(Ready::Resume(cont, value.clone()));
+                }
+
+                self.switch()
+            }
+        }
+    }
+
+    fn enter(&mut self, function: u32, captures: &[Value], args: Vec<Value>) {
+        let base = self.stack.len();
+        let locals = self.module.functions[function as usize].locals as usize;
+
+        self.stack.extend(captures.iter().cloned());
+        self.stack.extend(args);
+        self.stack.resize(base + locals, Value::Unit);
+
+        self.frames.push(Frame::Call {
+            function,
+            pc: 0,
+            base,
+        });
+    }
+
+    /// The primitive that a global binds.
+    fn primitive_of(&self, global: u32) -> Primitive {
+        match &self.module.globals[global as usize].1 {
+            Global::External(primitive) => *primitive,
+            _ => unreachable!("the global of a primitive closure must bind a primitive"),
+        }
+    }
+
+    fn call(&mut self, func: Value, args: Vec<Value>) -> Result<()> {
+        match &func {
+            Value::Closure(closure) => {
+                let arity = match &closure.code {
+                    Code::Function(function) => {
+                        self.module.functions[*function as usize].arity as usize
+                    }
+                    Code::Primitive(global) => match &self.module.globals[*global as usize].1 {
+                        Global::Builtin(builtin) => builtin.arity(),
+                        _ => self.primitive_of(*global).arity(),
+                    },
+                    Code::Host(binding) => {
+                        self.host
+                            .get(binding)
+                            .ok_or_else(|| RuntimeError::UnknownExternal(binding.clone()))?
+                            .arity
+                    }
+                };
+
+                let mut all = closure.args.clone();
+                all.extend(args);
+
+                if all.len() < arity {
+                    return self.give(Value::Closure(Rc::new(Closure {
+                        code: closure.code.clone(),
+                        captures: closure.captures.clone(),
+                        args: all,
+                    })));
+                }
+
+                let rest = all.split_off(arity);
+
+                let result = match &closure.code {
+                    Code::Function(function) => {
+                        if !rest.is_empty() {
+                            self.frames.push(Frame::Apply(rest));
+                        }
+
+                        self.enter(*function, &closure.captures, all);
+                        return Ok(());
+                    }
+                    Code::Primitive(global) => {
+                        if let Global::Builtin(builtin) = self.module.globals[*global as usize].1 {
+                            if !rest.is_empty() {
+                                self.frames.push(Frame::Apply(rest));
+                            }
+
+                            return self.builtin(builtin, all);
+                        }
+
+                        self.primitive(self.primitive_of(*global), all)?
+                    }
+                    Code::Host(binding) => {
+                        let function = self.host.get(binding).cloned().unwrap();
+
+                        function
+                            .call(all)
+                            .map_err(|err| RuntimeError::Host(binding.clone(), err))?
+                    }
+                };
+
+                if rest.is_empty() {
+                    self.give(result)
+                } else {
+                    self.call(result, rest)
+                }
+            }
+            Value::Handle(handle) if !args.is_empty() => {
+                let Some(cont) = handle.downcast_ref::<Continuation>() else {
+                    return Err(RuntimeError::NotAFunction);
+                };
+
+                let mut args = args.into_iter();
+                let value = args.next().unwrap();
+                let rest: Vec<_> = args.collect();
+
+                if !rest.is_empty() {
+                    self.frames.push(Frame::Apply(rest));
+                }
+
+                self.resume(cont, value)
+            }
+            _ => Err(RuntimeError::NotAFunction),
+        }
+    }
+
+    fn resume(&mut self, cont: &Continuation, value: Value) -> Result<()> {
+        cont.resumed.set(true);
+
+        let base = self.stack.len();
+        self.stack.extend(cont.stack.iter().cloned());
+
+        for frame in &cont.frames {
+            let mut frame = frame.clone();
+            frame.rebase(cont.base, base);
+
+            // The handlers keep their shapes when they're resumed on top of the same ones.
+            if let Frame::Handler { shape, .. } | Frame::Mask { shape, .. } = &mut frame {
+                if !same(&shape.parent, &self.shape) {
+                    *shape = Rc::new(Shape {
+                        parent: self.shape.clone(),
+                    });
+                }
+
+                self.shape = Some(shape.clone());
+            }
+
+            self.frames.push(frame);
+        }
+
+        self.give(value)
+    }
+
+    /// The handler frame and the clause that a perform found the last time, if the handlers are
+    /// still the same.
+    fn cached(&self, site: (u32, usize), instance: Option<usize>) -> Option<(usize, usize)> {
+        let cached = self.sites.get(&site)?;
+
+        if cached.instance != instance || !same(&cached.shape, &self.shape) {
+            return None;
+        }
+
+        match self.frames.get(cached.index) {
+            Some(Frame::Handler { shape, .. }) if Rc::ptr_eq(shape, &cached.target) => {
+                Some((cached.index, cached.clause))
+            }
+            _ => None,
+        }
+    }
+
+    /// The closest handler frame of the operation and the clause of it. The masks don't change
+    /// the handler of an operation that is performed on an instance.
+    fn find(&self, operation: u32, instance: Option<usize>) -> Option<(usize, usize)> {
+        let mut masked = 0;
+
+        self.frames.iter().enumerate().rev().find_map(|(i, frame)| {
+            let (id, handler) = match frame {
+                Frame::Handler { id, handler, .. } => (id, handler),
+                Frame::Mask { mask, .. } if instance.is_none() => {
+                    if self.module.masks[*mask as usize].contains(&operation) {
+                        masked += 1;
+                    }
+
+                    return None;
+                }
+                _ => return None,
+            };
+
+            if instance.is_some_and(|instance| instance != *id) {
+                return None;
+            }
+
+            let clause = self.module.handlers[*handler as usize]
+                .operations
+                .iter()
+                .position(|(op, _)| *op == operation)?;
+
+            if masked > 0 {
+                masked -= 1;
+                return None;
+            }
+
+            Some((i, clause))
+        })
+    }
+
+    fn perform(
+        &mut self,
+        site: (u32, usize),
+        operation: u32,
+        args: Vec<Value>,
+        instance: Option<usize>,
+    ) -> Result<()> {
+        let cached = self.cached(site, instance);
+        let found = cached.or_else(|| self.find(operation, instance));
+
+        let Some((index, clause)) = found else {
+            if instance.is_none() && self.module.tasks.contains(&operation) {
+                return self.schedule(operation, args);
+            }
+
+            let name = self.module.operations[operation as usize].clone();
+            return Err(RuntimeError::UnhandledOperation(name));
+        };
+
+        let Frame::Handler {
+            handler,
+            clauses,
+            base,
+            shape,
+            ..
+        } = self.frames[index].clone()
+        else {
+            unreachable!()
+        };
+
+        if cached.is_none() {
+            let remembered = Site {
+                shape: self.shape.clone(),
+                target: shape.clone(),
+                index,
+                clause,
+                instance,
+            };
+
+            self.sites.insert(site, remembered);
+        }
+
+        self.shape = shape.parent.clone();
+
+        let cont = Rc::new(Continuation {
+            frames: self.frames.split_off(index),
+            stack: self.stack.split_off(base),
+            base,
+            resumed: Cell::new(false),
+        });
+
+        let clause_value = clauses[clause].clone();
+
+        match self.module.handlers[handler as usize].operations[clause].1 {
+            OperationKind::Ctl => {
+                let mut thunks = Vec::new();
+                finalizers(&cont.frames, &mut thunks);
+
+                if !thunks.is_empty() {
+                    self.frames.push(Frame::Unwind(cont.clone()));
+                }
+
+                let mut args = args;
+                args.push(Value::Handle(cont.into()));
+                self.call(clause_value, args)
+            }
+            OperationKind::Fun => {
+                self.frames.push(Frame::Resume(cont));
+                self.call(clause_value, args)
+            }
+        }
+    }
+
+    /// Runs an operation of `Prelude.Async` that no handler handles. Spawning a task doesn't
+    /// stop the one that spawned it.
+    fn schedule(&mut self, operation: u32, mut args: Vec<Value>) -> Result<()> {
+        let [spawn, wait, _] = self.module.tasks;
+        let arg = args.pop().unwrap_or(Value::Unit);
+
+        if operation == spawn {
+            let task = Rc::new(Task::default());
+            self.scheduler.push(Ready::Start(task.clone(), arg));
+            return self.give(Value::Handle(task.into()));
+        }
+
+        if operation == wait {
+            let Some(task) = object::<Task>(&arg) else {
+                unreachable!("the argument of await must be a task")
+            };
+
+            if let Some(value) = task.result() {
+                return self.give(value);
+            }
+
+            task.wait(self.suspend());
+            return self.switch();
+        }
+
+        let cont = self.suspend();
+        self.scheduler.push(Ready::Resume(cont, Value::Unit));
+        self.switch()
+    }
+
+    /// Cuts all of the frames and values of the task that is running into a continuation.
+    fn suspend(&mut self) -> Rc<Continuation> {
+        self.shape = None;
+
+        Rc::new(Continuation {
+            frames: std::mem::take(&mut self.frames),
+            stack: std::mem::take(&mut self.stack),
+            base: 0,
+            resumed: Cell::new(false),
+        })
+    }
+
+    /// Runs the next task that is ready on the empty machine. When none is ready, the result of
+    /// the main computation is given if it finished, and the tasks that are left wait forever.
+    fn switch(&mut self) -> Result<()> {
+        match self.scheduler.pop() {
+            Some(Ready::Start(task, func)) => {
+                self.frames.push(Frame::Task(task));
+                self.call(func, vec![Value::Unit])
+            }
+            Some(Ready::Resume(cont, value)) => self.resume(&cont, value),
+            None => match self.scheduler.finished.take() {
+                Some(value) => {
+                    self.result = Some(value);
+                    Ok(())
+                }
+                None => Err(RuntimeError::Deadlock),
+            },
+        }
+    }
+
+    /// Runs a builtin of channels and mutexes. The task that calls it waits in the channel or the
+    /// mutex if it can't go on.
+    fn builtin(&mut self, builtin: Builtin, args: Vec<Value>) -> Result<()> {
+        let invalid = || RuntimeError::InvalidBuiltinArguments(builtin);
+
+        match builtin {
+            Builtin::NewChannel => {
+                let Value::Int(capacity) = args[0] else {
+                    return Err(invalid());
+                };
+
+                let capacity = usize::try_from(capacity)
+                    .map_err(|_| RuntimeError::NegativeCapacity(capacity))?;
+
+                let channel = Rc::new(Channel::new(capacity));
+                self.give(Value::Handle(channel.into()))
+            }
+            Builtin::Send => {
+                let channel = object::<Channel>(&args[0]).ok_or_else(invalid)?;
+
+                match channel.send(args[1].clone()) {
+                    Ok(receiver) => {
+                        if let Some((cont, value)) = receiver {
+                            self.scheduler.push(Ready::Resume(cont, value));
+                        }
+
+                        self.give(Value::Unit)
+                    }
+                    Err(value) => {
+                        channel.wait_to_send(self.suspend(), value);
+                        self.switch()
+                    }
+                }
+            }
+            Builtin::Receive => {
+                let channel = object::<Channel>(&args[0]).ok_or_else(invalid)?;
+
+                match channel.receive() {
+                    Some((value, sender)) => {
+                        if let Some(cont) = sender {
+                            self.scheduler.push(Ready::Resume(cont, Value::Unit));
+                        }
+
+                        self.give(value)
+                    }
+                    None => {
+                        channel.wait_to_receive(self.suspend());
+                        self.switch()
+                    }
+                }
+            }
+            Builtin::NewMutex => self.give(Value::Handle(Rc::new(Mutex::default()).into())),
+            Builtin::Lock => {
+                let mutex = object::<Mutex>(&args[0]).ok_or_else(invalid)?;
+
+                if mutex.lock() {
+                    return self.give(Value::Unit);
+                }
+
+                mutex.wait(self.suspend());
+                self.switch()
+            }
+            Builtin::Unlock => {
+                let mutex = object::<Mutex>(&args[0]).ok_or_else(invalid)?;
+
+                if !mutex.is_locked() {
+                    return Err(RuntimeError::NotLocked);
+                }
+
+                if let Some(cont) = mutex.unlock() {
+                    self.scheduler.push(Ready::Resume(cont, Value::Unit));
+                }
+
+                self.give(Value::Unit)
+            }
+        }
+    }
+
+    fn global(&mut self, global: u32) -> Result<()> {
+        if let Some(value) = &self.globals[global as usize] {
+            return self.give(value.clone());
+        }
+
+        match &self.module.globals[global as usize].1 {
+            Global::Function(function) => {
+                let value = Value::Closure(Rc::new(Closure::new(Code::Function(*function))));
+
+                self.globals[global as usize] = Some(value.clone());
+                self.give(value)
+            }
+            Global::Value(function) => {
+                self.frames.push(Frame::Global(global));
+                self.enter(*function, &[], vec![]);
+                Ok(())
+            }
+            Global::External(_) | Global::Builtin(_) => self.give(Value::Closure(Rc::new(
+                Closure::new(Code::Primitive(global)),
+            ))),
+            Global::Host(binding) if self.host.get(binding).is_some() => {
+                let code = Code::Host(binding.clone());
+                self.give(Value::Closure(Rc::new(Closure::new(code))))
+            }
+            Global::Host(binding) => Err(RuntimeError::UnknownExternal(binding.clone())),
+        }
+    }
+
+    fn constant(&self, constant: u32) -> Value {
+        match &self.module.constants[constant as usize] {
+            Constant::Int(x) => Value::Int(*x),
+            Constant::Float(x) => Value::Float(*x),
+            Constant::String(x) => Value::String(x.get().into()),
+            Constant::Char(x) => Value::Char(*x),
+            Constant::Unit => Value::Unit,
+        }
+    }
+
+    fn pop(&mut self, count: usize) -> Vec<Value> {
+        self.stack.split_off(self.stack.len() - count)
+    }
+
+    fn step(&mut self) -> Result<()> {
+        let Some(Frame::Call { function, pc, base }) = self.frames.last_mut() else {
+            unreachable!("the frame at the top must be a call while running")
+        };
+
+        let (function, base) = (*function, *base);
+        let code = &self.module.functions[function as usize];
+        let position = *pc;
+        let instruction = code.code[position];
+        *pc += 1;
+
+        match instruction {
+            Instruction::Load(slot) => {
+                let value = self.stack[base + slot as usize].clone();
+                self.stack.push(value);
+            }
+            Instruction::Store(slot) => {
+                let value = self.stack.pop().unwrap();
+                self.stack[base + slot as usize] = value;
+            }
+            Instruction::Global(global) => self.global(global)?,
+            Instruction::Constant(constant) => {
+                let value = self.constant(constant);
+                self.stack.push(value);
+            }
+            Instruction::Closure(function, captures) => {
+                let captures = self.pop(captures as usize);
+
+                self.stack.push(Value::Closure(Rc::new(Closure {
+                    code: Code::Function(function),
+                    captures: captures.into(),
+                    args: vec![],
+                })));
+            }
+            Instruction::Call(count) => {
+                let func = self.stack.pop().unwrap();
+                let args = self.pop(count as usize);
+                self.call(func, args)?;
+            }
+            Instruction::TailCall(count) => {
+                let func = self.stack.pop().unwrap();
+                let args = self.pop(count as usize);
+                self.stack.truncate(base);
+                self.frames.pop();
+                self.call(func, args)?;
+            }
+            Instruction::Construct(constructor, count) => {
+                let fields = self.pop(count as usize);
+                self.stack.push(Value::constructor(constructor, fields));
+            }
+            Instruction::Tag(constructor) => {
+                self.stack.push(Value::constructor(constructor, vec![]))
+            }
+            Instruction::Field(field) => match &self.stack.pop().unwrap() {
+                Value::Constructor { fields, .. } => {
+                    self.stack.push(fields[field as usize].clone())
+                }
+                _ => return Err(RuntimeError::NotAConstructor),
+            },
+            Instruction::Switch(table) => {
+                let value = self.stack.pop().unwrap();
+                let table = &code.tables[table as usize];
+
+                let target = table
+                    .cases
+                    .iter()
+                    .find(|(key, _)| match (key, &value) {
+                        (Key::Constructor(l), Value::Constructor { tag, .. }) => l == tag,
+                        (Key::Constant(constant), value) => self.constant(*constant) == *value,
+                        (Key::Range(start, end), value) => {
+                            within(&self.constant(*start), value, &self.constant(*end))
+                        }
+                        _ => false,
+                    })
+                    .map(|(_, target)| *target)
+                    .or(table.default)
+                    .ok_or(RuntimeError::Unreachable)?;
+
+                self.jump(target);
+            }
+            Instruction::Jump(target) => self.jump(target),
+            Instruction::Return => {
+                let value = self.stack.pop().unwrap();
+                self.stack.truncate(base);
+                self.frames.pop();
+                self.give(value)?;
+            }
+            Instruction::Perform(operation, count) => {
+                let args = self.pop(count as usize);
+                self.perform((function, position), operation, args, None)?;
+            }
+            Instruction::PerformAt(operation, count) => {
+                let instance = match &self.stack.pop().unwrap() {
+                    Value::Handle(handle) => match handle.downcast_ref::<Instance>() {
+                        Some(Instance(id)) => *id,
+                        None => return Err(RuntimeError::Unreachable),
+                    },
+                    _ => return Err(RuntimeError::Unreachable),
+                };
+
+                let args = self.pop(count as usize);
+                self.perform((function, position), operation, args, Some(instance))?;
+            }
+            Instruction::Handle(handler) => {
+                let info = &self.module.handlers[handler as usize];
+
+                let finally = info.finally.then(|| self.stack.pop().unwrap());
+                let ret = self.stack.pop().unwrap();
+                let clauses = self.pop(info.operations.len());
+                let thunk = self.stack.pop().unwrap();
+
+                if let Some(finally) = finally {
+                    self.frames.push(Frame::Finally(finally));
+                }
+
+                let id = self.handlers;
+                self.handlers += 1;
+
+                let shape = Rc::new(Shape {
+                    parent: self.shape.clone(),
+                });
+
+                self.shape = Some(shape.clone());
+
+                self.frames.push(Frame::Handler {
+                    id,
+                    handler,
+                    shape,
+                    clauses: clauses.into(),
+                    ret,
+                    base: self.stack.len(),
+                });
+
+                let args = if info.named {
+                    vec![Value::Handle(Handle::new(Instance(id)))]
+                } else {
+                    vec![]
+                };
+
+                self.call(thunk, args)?;
+            }
+            Instruction::Mask(mask) => {
+                let thunk = self.stack.pop().unwrap();
+
+                let shape = Rc::new(Shape {
+                    parent: self.shape.clone(),
+                });
+
+                self.shape = Some(shape.clone());
+                self.frames.push(Frame::Mask { mask, shape });
+
+                self.call(thunk, vec![])?;
+            }
+            Instruction::Unreachable => return Err(RuntimeError::Unreachable),
+        }
+
+        Ok(())
+    }
+
+    fn jump(&mut self, target: u32) {
+        if let Some(Frame::Call { pc, .. }) = self.frames.last_mut() {
+            *pc = target as usize;
+        }
+    }
+
+    fn boolean(&self, value: bool) -> Value {
+        let (false_, true_) = self.module.booleans;
+        Value::constructor(if value { true_ } else { false_ }, vec![])
+    }
+
+    fn primitive(&mut self, primitive: Primitive, args: Vec<Value>) -> Result<Value> {
+        let invalid = || RuntimeError::InvalidArguments(primitive);
+
+        let value = match (primitive, args.as_slice()) {
+            (Primitive::Identity, [x]) => x.clone(),
+            (Primitive::Show, [Value::Char(x)]) => Value::String(x.to_string().into()),
+            (Primitive::Show, [x]) => Value::String(self.show(x).into()),
+            (Primitive::Print, [x]) => {
+                writeln!(self.output, "{}", self.show(x)).map_err(RuntimeError::Io)?;
+                Value::Unit
+            }
+            (Primitive::PrintError, [x]) => {
+                eprintln!("{}", self.show(x));
+                Value::Unit
+            }
+            (Primitive::Eq, [l, r]) => self.boolean(l == r),
+            (Primitive::Neq, [l, r]) => self.boolean(l != r),
+            (Primitive::Concat, [Value::String(l), Value::String(r)]) => {
+                Value::String(format!("{}{}", l, r).into())
+            }
+            (Primitive::Length, [Value::String(x)]) => Value::Int(x.chars().count() as i64),
+            (Primitive::Index, [Value::String(x), Value::Int(index)]) => usize::try_from(*index)
+                .ok()
+                .and_then(|i| x.chars().nth(i))
+                .map(Value::Char)
+                .ok_or(RuntimeError::IndexOutOfBounds(*index))?,
+            (Primitive::ByteLength, [Value::String(x)]) => Value::Int(x.len() as i64),
+            (Primitive::Slice, [Value::String(x), Value::Int(start), Value::Int(end)]) => {
+                let (from, to) = (boundary(x, *start)?, boundary(x, *end)?);
+
+                if to < from {
+                    return Err(RuntimeError::IndexOutOfBounds(*end));
+                }
+
+                Value::String(x[from..to].into())
+            }
+            (Primitive::CharAt, [Value::String(x), Value::Int(offset)]) => {
+                let rest = &x[boundary(x, *offset)?..];
+                let char = rest.chars().next();
+                char.map(Value::Char)
+                    .ok_or(RuntimeError::IndexOutOfBounds(*offset))?
+            }
+            (Primitive::CharWidth, [Value::Char(x)]) => Value::Int(x.len_utf8() as i64),
+            (Primitive::RefNew, [x]) => Value::cell(x.clone()),
+            (Primitive::RefGet, [cell]) => cell.as_cell().ok_or_else(invalid)?.borrow().clone(),
+            (Primitive::RefSet, [cell, x]) => {
+                *cell.as_cell().ok_or_else(invalid)?.borrow_mut() = x.clone();
+                Value::Unit
+            }
+            (Primitive::ArrayEmpty, [_]) => Value::array(vec![]),
+            (Primitive::ArrayMake, [Value::Int(length), x]) => {
+                let length =
+                    usize::try_from(*length).map_err(|_| RuntimeError::NegativeLength(*length))?;
+                Value::array(vec![x.clone(); length])
+            }
+            (Primitive::ArrayGet, [array, Value::Int(index)]) => {
+                let array = array.as_array().ok_or_else(invalid)?.borrow();
+                array[element(array.len(), *index)?].clone()
+            }
+            (Primitive::ArraySet, [array, Value::Int(index), x]) => {
+                let mut array = array.as_array().ok_or_else(invalid)?.borrow_mut();
+                let position = element(array.len(), *index)?;
+                array[position] = x.clone();
+                Value::Unit
+            }
+            (Primitive::ArrayLength, [array]) => {
+                Value::Int(array.as_array().ok_or_else(invalid)?.borrow().len() as i64)
+            }
+            (Primitive::ArrayPush, [array, x]) => {
+                let mut array = array.as_array().ok_or_else(invalid)?.borrow_mut();
+                array.push(x.clone());
+                Value::Unit
+            }
+            (Primitive::Convert(number), [Value::Int(x)]) => match number {
+                Number::Int { .. } => Value::Int(number.wrap(*x)),
+                Number::Float { .. } => Value::Float(number.round(*x as f64)),
+            },
+            (Primitive::Convert(number), [Value::Float(x)]) => match number {
+                Number::Int { .. } => Value::Int(number.saturate(*x)),
+                Number::Float { .. } => Value::Float(number.round(*x)),
+            },
+            (Primitive::CharToInt, [Value::Char(x)]) => Value::Int(*x as i64),
+            (Primitive::IntToChar, [Value::Int(x)]) => u32::try_from(*x)
+                .ok()
+                .and_then(char::from_u32)
+                .map(Value::Char)
+                .ok_or(RuntimeError::InvalidCharacter(*x))?,
+            (Primitive::Div | Primitive::Rem, [Value::Int(_), Value::Int(0)]) => {
+                return Err(RuntimeError::DivisionByZero)
+            }
+            (_, [Value::Int(l), Value::Int(r)]) => match primitive {
+                Primitive::Add => Value::Int(l.wrapping_add(*r)),
+                Primitive::Sub => Value::Int(l.wrapping_sub(*r)),
+                Primitive::Mul => Value::Int(l.wrapping_mul(*r)),
+                Primitive::CheckedAdd => {
+                    Value::Int(l.checked_add(*r).ok_or(RuntimeError::Overflow)?)
+                }
+                Primitive::CheckedSub => {
+                    Value::Int(l.checked_sub(*r).ok_or(RuntimeError::Overflow)?)
+                }
+                Primitive::CheckedMul => {
+                    Value::Int(l.checked_mul(*r).ok_or(RuntimeError::Overflow)?)
+                }
+                Primitive::SaturatingAdd => Value::Int(l.saturating_add(*r)),
+                Primitive::SaturatingSub => Value::Int(l.saturating_sub(*r)),
+                Primitive::SaturatingMul => Value::Int(l.saturating_mul(*r)),
+                Primitive::Div => Value::Int(l.wrapping_div(*r)),
+                Primitive::Rem => Value::Int(l.wrapping_rem(*r)),
+                Primitive::Lt => self.boolean(l < r),
+                Primitive::Gt => self.boolean(l > r),
+                Primitive::Le => self.boolean(l <= r),
+                Primitive::Ge => self.boolean(l >= r),
+                _ => return Err(invalid()),
+            },
+            (_, [Value::Float(l), Value::Float(r)]) => match primitive {
+                Primitive::Add => Value::Float(l + r),
+                Primitive::Sub => Value::Float(l - r),
+                Primitive::Mul => Value::Float(l * r),
+                Primitive::Div => Value::Float(l / r),
+                Primitive::Rem => Value::Float(l % r),
+                Primitive::Lt => self.boolean(l < r),
+                Primitive::Gt => self.boolean(l > r),
+                Primitive::Le => self.boolean(l <= r),
+                Primitive::Ge => self.boolean(l >= r),
+                _ => return Err(invalid()),
+            },
+            (_, [Value::String(l), Value::String(r)]) => self.compare(primitive, l, r)?,
+            (_, [Value::Char(l), Value::Char(r)]) => self.compare(primitive, l, r)?,
+            _ => return Err(invalid()),
+        };
+
+        Ok(value)
+    }
+
+    fn compare<T: PartialOrd>(&self, primitive: Primitive, l: T, r: T) -> Result<Value> {
+        match primitive {
+            Primitive::Lt => Ok(self.boolean(l < r)),
+            Primitive::Gt => Ok(self.boolean(l > r)),
+            Primitive::Le => Ok(self.boolean(l <= r)),
+            Primitive::Ge => Ok(self.boolean(l >= r)),
+            _ => Err(RuntimeError::InvalidArguments(primitive)),
+        }
+    }
+
+    /// Checks if a value is the `True` of the prelude.
+    pub fn is_true(&self, value: &Value) -> bool {
+        *value == self.boolean(true)
+    }
+
+    /// Shows a value the way that the print primitive writes it.
+    pub fn show(&self, value: &Value) -> String {
+        match value {
+            Value::String(x) => x.to_string(),
+            value => self.inspect(value),
+        }
+    }
+
+    /// Shows a value the way that it's written in the source, with the strings quoted.
+    pub fn inspect(&self, value: &Value) -> String {
+        match value {
+            Value::Int(x) => x.to_string(),
+            Value::Float(x) => x.to_string(),
+            Value::Char(x) => format!("{:?}", x),
+            Value::String(x) => format!("{:?}", x),
+            Value::Unit => "()".to_string(),
+            Value::Constructor { tag, fields } => {
+                let constructor = &self.module.constructors[*tag as usize];
+                let name = constructor.name.get();
+
+                if *constructor == tuple(fields.len()) {
+                    let fields: Vec<_> = fields.iter().map(|x| self.inspect(x)).collect();
+                    format!("({})", fields.join(", "))
+                } else if fields.is_empty() {
+                    name
+                } else {
+                    let fields: Vec<_> = fields.iter().map(|x| self.inspect(x)).collect();
+                    format!("({} {})", name, fields.join(" "))
+                }
+            }
+            Value::Closure(_) => "<function>".to_string(),
+            Value::Handle(handle) => {
+                if let Some(cell) = value.as_cell() {
+                    format!("(Ref {})", self.inspect(&cell.borrow()))
+                } else if let Some(array) = value.as_array() {
+                    let elements: Vec<_> = array.borrow().iter().map(|x| self.inspect(x)).collect();
+                    format!("#[{}]", elements.join(", "))
+                } else if handle.downcast_ref::<Continuation>().is_some() {
+                    "<continuation>".to_string()
+                } else {
+                    "<handler>".to_string()
+                }
+            }
+        }
+    }
+}
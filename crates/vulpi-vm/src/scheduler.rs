@@ -0,0 +1,88 @@
+//! The scheduler of the tasks of the `Async` effect when no handler handles its operations. Tasks
+//! are green threads: they run one at a time on the frames of the machine and only switch when
+//! they yield, wait for another task or finish.
+//!
+//! A task that stops running takes all of the frames and values of the machine with it in a
+//! continuation, so the next one starts on an empty machine. The frames of the handlers that were
+//! installed by a task go with it, and a spawned task starts without any handler.
+
+use std::{cell::RefCell, collections::VecDeque, rc::Rc};
+
+use crate::{machine::Continuation, value::Value};
+
+/// A task that was spawned. Programs get it in a handle.
+#[derive(Default)]
+pub struct Task {
+    state: RefCell<State>,
+}
+
+enum State {
+    /// The task is running or ready to run, and the continuations are waiting for its result.
+    Running(Vec<Rc<Continuation>>),
+    Done(Value),
+}
+
+impl Default for State {
+    fn default() -> Self {
+        State::Running(Vec::new())
+    }
+}
+
+impl Task {
+    /// The result of the task, if it finished.
+    pub fn result(&self) -> Option<Value> {
+        match &*self.state.borrow() {
+            State::Done(value) => Some(value.clone()),
+            State::Running(_) => None,
+        }
+    }
+
+    /// Makes a continuation wait for the result of the task, that must be running.
+    pub fn wait(&self, cont: Rc<Continuation>) {
+        if let State::Running(waiting) = &mut *self.state.borrow_mut() {
+            waiting.push(cont);
+        }
+    }
+
+    /// Stores the result of the task and gives back the continuations that were waiting for it.
+    pub fn finish(&self, value: Value) -> Vec<Rc<Continuation>> {
+        match self.state.replace(State::Done(value)) {
+            State::Running(waiting) => waiting,
+            State::Done(_) => Vec::new(),
+        }
+    }
+}
+
+/// Something that the scheduler can run.
+pub enum Ready {
+    /// A task that was spawned, with the function that computes its result.
+    Start(Rc<Task>, Value),
+
+    /// A task that stopped, with the value that it gets when it runs again.
+    Resume(Rc<Continuation>, Value),
+}
+
+/// The tasks that are ready to run, in the order that they became ready.
+#[derive(Default)]
+pub struct Scheduler {
+    ready: VecDeque<Ready>,
+
+    /// The result of the main computation, that waits for the tasks that are still running before
+    /// it's given to the caller of the machine.
+    pub finished: Option<Value>,
+}
+
+impl Scheduler {
+    pub fn push(&mut self, ready: Ready) {
+        self.ready.push_back(ready);
+    }
+
+    pub fn pop(&mut self) -> Option<Ready> {
+        self.ready.pop_front()
+    }
+
+    /// Checks if no task is ready to run.
+    pub fn is_idle(&self) -> bool {
+        self.ready.is_empty()
+    }
+}
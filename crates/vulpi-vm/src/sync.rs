@@ -0,0 +1,143 @@
+//! Channels and mutexes that the tasks of the scheduler share. Their externals are bound to
+//! builtins of the machine instead of host functions, because a task can wait in them: a task that
+//! receives from an empty channel, sends to a full one or locks a mutex that another task holds is
+//! cut into a continuation, like a task that awaits another one, and the task that lets it go on
+//! makes it ready again.
+
+use std::{
+    cell::{Cell, RefCell},
+    collections::VecDeque,
+    rc::Rc,
+};
+
+use crate::{machine::Continuation, value::Value};
+
+/// The functions of the machine that the externals of channels and mutexes are bound to.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Builtin {
+    NewChannel,
+    Send,
+    Receive,
+    NewMutex,
+    Lock,
+    Unlock,
+}
+
+impl Builtin {
+    pub fn from_binding(binding: &str) -> Option<Self> {
+        match binding {
+            "channel_new" => Some(Builtin::NewChannel),
+            "channel_send" => Some(Builtin::Send),
+            "channel_receive" => Some(Builtin::Receive),
+            "mutex_new" => Some(Builtin::NewMutex),
+            "mutex_lock" => Some(Builtin::Lock),
+            "mutex_unlock" => Some(Builtin::Unlock),
+            _ => None,
+        }
+    }
+
+    pub fn arity(&self) -> usize {
+        match self {
+            Builtin::Send => 2,
+            _ => 1,
+        }
+    }
+}
+
+/// A queue of values with a capacity. A channel without capacity gives each value straight from
+/// the task that sends it to the one that receives it.
+pub struct Channel {
+    capacity: usize,
+    buffer: RefCell<VecDeque<Value>>,
+
+    /// The tasks that wait for room in the channel, with the values that they send.
+    senders: RefCell<VecDeque<(Rc<Continuation>, Value)>>,
+
+    /// The tasks that wait for a value. There are only receivers while the buffer is empty.
+    receivers: RefCell<VecDeque<Rc<Continuation>>>,
+}
+
+impl Channel {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            buffer: RefCell::default(),
+            senders: RefCell::default(),
+            receivers: RefCell::default(),
+        }
+    }
+
+    /// Sends a value without waiting. It gives back the receiver that was waiting for the value
+    /// together with it, or the value if the channel is full.
+    pub fn send(&self, value: Value) -> Result<Option<(Rc<Continuation>, Value)>, Value> {
+        if let Some(receiver) = self.receivers.borrow_mut().pop_front() {
+            return Ok(Some((receiver, value)));
+        }
+
+        let mut buffer = self.buffer.borrow_mut();
+
+        if buffer.len() < self.capacity {
+            buffer.push_back(value);
+            Ok(None)
+        } else {
+            Err(value)
+        }
+    }
+
+    /// Receives a value without waiting, together with the sender that was waiting for room and
+    /// can go on now. It's none if the channel is empty and no task is sending to it.
+    pub fn receive(&self) -> Option<(Value, Option<Rc<Continuation>>)> {
+        let mut buffer = self.buffer.borrow_mut();
+        let sender = self.senders.borrow_mut().pop_front();
+
+        match buffer.pop_front() {
+            Some(value) => {
+                let sender = sender.map(|(cont, sent)| {
+                    buffer.push_back(sent);
+                    cont
+                });
+
+                Some((value, sender))
+            }
+            None => sender.map(|(cont, sent)| (sent, Some(cont))),
+        }
+    }
+
+    pub fn wait_to_send(&self, cont: Rc<Continuation>, value: Value) {
+        self.senders.borrow_mut().push_back((cont, value));
+    }
+
+    pub fn wait_to_receive(&self, cont: Rc<Continuation>) {
+        self.receivers.borrow_mut().push_back(cont);
+    }
+}
+
+/// A lock that one task holds at a time. The tasks that wait for it get it in the order that they
+/// tried to lock it.
+#[derive(Default)]
+pub struct Mutex {
+    locked: Cell<bool>,
+    waiting: RefCell<VecDeque<Rc<Continuation>>>,
+}
+
+impl Mutex {
+    /// Locks the mutex if it's unlocked. It's false if a task holds it.
+    pub fn lock(&self) -> bool {
+        !self.locked.replace(true)
+    }
+
+    pub fn wait(&self, cont: Rc<Continuation>) {
+        self.waiting.borrow_mut().push_back(cont);
+    }
+
+    pub fn is_locked(&self) -> bool {
+        self.locked.get()
+    }
+
+    /// Unlocks the mutex, or gives it to the next task that waits for it, which is given back.
+    pub fn unlock(&self) -> Option<Rc<Continuation>> {
+        let next = self.waiting.borrow_mut().pop_front();
+        self.locked.set(next.is_some());
+        next
+    }
+}
@@ -0,0 +1,37 @@
+//! Stack traces of the errors of the [Machine](crate::machine::Machine). Each call that is running
+//! when an error happens is named by the let declaration of its function and located at the
+//! instruction that it's running, using the spans that the bytecode keeps.
+
+use vulpi_location::{Encoding, Sources, Span};
+use vulpi_syntax::r#abstract::Qualified;
+
+/// A call that was running when an error happened.
+#[derive(Clone, Debug)]
+pub struct Location {
+    pub function: Qualified,
+    pub span: Span,
+}
+
+/// Renders the calls from the innermost one, each in a line like `at Main.double (Main.vp:3:29)`.
+/// The positions of the spans whose files are not in the sources are left out.
+pub fn render(trace: &[Location], sources: &Sources) -> String {
+    let mut output = String::new();
+
+    for location in trace {
+        output.push_str("  at ");
+        output.push_str(&location.function.to_string());
+
+        if let Some((source, position)) = sources.locate(&location.span, Encoding::Utf32) {
+            output.push_str(&format!(
+                " ({}:{}:{})",
+                source.path,
+                position.line + 1,
+                position.column + 1
+            ));
+        }
+
+        output.push('\n');
+    }
+
+    output
+}
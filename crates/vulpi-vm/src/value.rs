@@ -0,0 +1,4 @@
+//! Values that the virtual machine works with. They're the values of the runtime, so the host
+//! functions and the applications that embed the machine receive them as they are.
+
+pub use vulpi_runtime::value::{Closure, Code, Fields, Handle, Value};
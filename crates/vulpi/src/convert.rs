@@ -0,0 +1,88 @@
+//! Conversions between Rust types and the values of the virtual machine. Booleans are
+//! constructors of `Prelude.Bool`, so the conversions receive the module that numbers them.
+
+use vulpi_vm::{bytecode::Module, host::Marshal, value::Value};
+
+/// A Rust type that the host functions of an engine receive and return. Booleans are not one of
+/// them, because the host functions don't know the module that numbers their constructors.
+pub trait HostValue: Marshal {}
+
+/// A Rust type that can be given to a function of a module.
+pub trait IntoValue {
+    fn into_value(self, module: &Module) -> Value;
+}
+
+/// A Rust type that can be read from a value returned by a function of a module.
+pub trait FromValue: Sized {
+    fn from_value(value: &Value, module: &Module) -> Option<Self>;
+}
+
+macro_rules! marshal {
+    ($($typ:ty),*) => {
+        $(
+            impl IntoValue for $typ {
+                fn into_value(self, _: &Module) -> Value {
+                    Marshal::into_value(self)
+                }
+            }
+
+            impl FromValue for $typ {
+                fn from_value(value: &Value, _: &Module) -> Option<Self> {
+                    Marshal::from_value(value)
+                }
+            }
+
+            impl HostValue for $typ {}
+        )*
+    };
+}
+
+marshal!(i64, f64, char, String, ());
+
+impl IntoValue for &str {
+    fn into_value(self, _: &Module) -> Value {
+        Value::String(self.into())
+    }
+}
+
+impl IntoValue for bool {
+    fn into_value(self, module: &Module) -> Value {
+        let (false_, true_) = module.booleans;
+        Value::constructor(if self { true_ } else { false_ }, vec![])
+    }
+}
+
+impl FromValue for bool {
+    fn from_value(value: &Value, module: &Module) -> Option<Self> {
+        match value {
+            Value::Constructor { tag, .. } if *tag == module.booleans.0 => Some(false),
+            Value::Constructor { tag, .. } if *tag == module.booleans.1 => Some(true),
+            _ => None,
+        }
+    }
+}
+
+/// The arguments of a call. Tuples give each of their elements as an argument and the empty tuple
+/// gives none, so it returns the global itself.
+pub trait Args {
+    fn into_values(self, module: &Module) -> Vec<Value>;
+}
+
+macro_rules! tuple {
+    ($($name:ident),*) => {
+        impl<$($name: IntoValue),*> Args for ($($name,)*) {
+            #[allow(non_snake_case, unused_variables)]
+            fn into_values(self, module: &Module) -> Vec<Value> {
+                let ($($name,)*) = self;
+                vec![$($name.into_value(module)),*]
+            }
+        }
+    };
+}
+
+tuple!();
+tuple!(A);
+tuple!(A, B);
+tuple!(A, B, C);
+tuple!(A, B, C, D);
+tuple!(A, B, C, D, E);
@@ -0,0 +1,290 @@
+//! The engine that compiles scripts and the modules that it produces.
+
+use std::{fmt, path::PathBuf};
+
+use vulpi_build::{cfg::Target, memory::MemoryFileSystem, ProjectCompiler};
+use vulpi_intern::Symbol;
+use vulpi_report::renderer::{classic::Classic, Renderer};
+use vulpi_syntax::r#abstract::Qualified;
+use vulpi_vfs::{path::Path, FileSystem};
+use vulpi_vm::{
+    bytecode,
+    host::Host,
+    machine::Machine,
+};
+
+use crate::convert::{Args, FromValue, HostValue};
+
+/// What the operators of integer arithmetic do when the result doesn't fit in 64 bits.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Overflow {
+    /// Keeps the lower 64 bits of the result.
+    #[default]
+    Wrap,
+
+    /// Stops the call with a runtime error.
+    Check,
+
+    /// Gives the minimum or the maximum integer.
+    Saturate,
+}
+
+impl From<Overflow> for vulpi_build::Overflow {
+    fn from(overflow: Overflow) -> Self {
+        match overflow {
+            Overflow::Wrap => vulpi_build::Overflow::Wrap,
+            Overflow::Check => vulpi_build::Overflow::Check,
+            Overflow::Saturate => vulpi_build::Overflow::Saturate,
+        }
+    }
+}
+
+pub enum Error {
+    /// The script has errors. It contains the rendered diagnostics.
+    Compile(String),
+
+    /// The module has no public function with the name.
+    UnknownFunction(String),
+
+    /// The function failed while it ran. It contains the reason.
+    Runtime(String),
+
+    /// The result of the function cannot be converted to the Rust type.
+    Conversion,
+
+    /// The project of a workspace cannot be loaded. It contains the reason.
+    Load(String),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Compile(diagnostics) => write!(f, "the script has errors\n{}", diagnostics),
+            Error::UnknownFunction(name) => write!(f, "cannot find the function '{}'", name),
+            Error::Runtime(reason) => write!(f, "{}", reason),
+            Error::Conversion => write!(f, "the result has a different type"),
+            Error::Load(reason) => write!(f, "{}", reason),
+        }
+    }
+}
+
+impl fmt::Debug for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Display::fmt(self, f)
+    }
+}
+
+impl std::error::Error for Error {}
+
+/// Compiles scripts to modules. The modules added to the engine can be imported by every script
+/// and the host functions registered in it are available to the externals of all of them.
+#[derive(Default)]
+pub struct Engine {
+    modules: Vec<(Path, String)>,
+    host: Host,
+
+    /// The level of the optimizer of the core language. Level 0 disables it.
+    pub optimization: usize,
+
+    /// What the operators of integer arithmetic do when the result doesn't fit in 64 bits.
+    pub overflow: Overflow,
+}
+
+impl Engine {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Registers a host function of one argument for the externals whose binding is `binding`.
+    /// Externals of functions without parameters take the unit, so they're registered with `()`
+    /// as the argument.
+    pub fn register1<A, R>(&mut self, binding: &str, function: impl Fn(A) -> R + 'static)
+    where
+        A: HostValue,
+        R: HostValue,
+    {
+        self.host.register1(binding, function);
+    }
+
+    /// Registers a host function of two arguments for the externals whose binding is `binding`.
+    pub fn register2<A, B, R>(&mut self, binding: &str, function: impl Fn(A, B) -> R + 'static)
+    where
+        A: HostValue,
+        B: HostValue,
+        R: HostValue,
+    {
+        self.host.register2(binding, function);
+    }
+
+    /// Registers a host function of three arguments for the externals whose binding is `binding`.
+    pub fn register3<A, B, C, R>(
+        &mut self,
+        binding: &str,
+        function: impl Fn(A, B, C) -> R + 'static,
+    ) where
+        A: HostValue,
+        B: HostValue,
+        C: HostValue,
+        R: HostValue,
+    {
+        self.host.register3(binding, function);
+    }
+
+    /// Adds a module that scripts can import by its path, like `Prelude` or `Data.List`.
+    pub fn add_module(&mut self, path: &str, source: &str) {
+        let path = Path {
+            segments: path.split('.').map(Symbol::intern).collect(),
+        };
+
+        self.modules.push((path, source.to_string()));
+    }
+
+    /// Compiles a script. The name is the one of the crate that it's the root of, so the other
+    /// modules of the crate are imported as `Name.Module`.
+    pub fn compile(&self, name: &str, source: &str) -> Result<Module, Error> {
+        let name = Symbol::intern(name);
+        let root = PathBuf::from("Main.vp");
+
+        let mut fs = MemoryFileSystem::new(name.clone());
+
+        for (path, source) in &self.modules {
+            fs.insert(fs.from_src_path(path.clone()), source.clone());
+        }
+
+        fs.insert(root.clone(), source.to_string());
+
+        let mut compiler = ProjectCompiler::new(name.clone(), fs);
+        compiler.optimization = self.optimization;
+        compiler.overflow = self.overflow.into();
+        compiler.target = Target::Vm;
+
+        match compiler.bytecode(name.clone(), root) {
+            Some(bytecode) => Ok(Module {
+                path: Path {
+                    segments: vec![name, Symbol::intern("Main")],
+                }
+                .symbol(),
+                bytecode,
+                host: self.host.clone(),
+            }),
+            None => {
+                let ctx = Classic::new(&compiler.fs, PathBuf::new());
+                let mut rendered = Vec::new();
+
+                for diagnostic in compiler.reporter.all_diagnostics().iter().rev() {
+                    diagnostic.render(&ctx, &mut rendered).unwrap();
+                }
+
+                Err(Error::Compile(
+                    String::from_utf8_lossy(&rendered).to_string(),
+                ))
+            }
+        }
+    }
+}
+
+/// A compiled script. Every call runs in a new machine, so the values of the globals are computed
+/// again by each one.
+pub struct Module {
+    path: Symbol,
+    bytecode: bytecode::Module,
+    host: Host,
+}
+
+impl Module {
+    /// Calls a public function of the module. The name is qualified by the path of its module,
+    /// like `Data.List.map`, or it's just the name of a function of the script.
+    pub fn call<R: FromValue>(&self, name: &str, args: impl Args) -> Result<R, Error> {
+        let qualified = match name.rsplit_once('.') {
+            Some((path, function)) => Qualified {
+                path: Symbol::intern(path),
+                name: Symbol::intern(function),
+            },
+            None => Qualified {
+                path: self.path.clone(),
+                name: Symbol::intern(name),
+            },
+        };
+
+        if self.bytecode.global(&qualified).is_none() {
+            return Err(Error::UnknownFunction(name.to_string()));
+        }
+
+        let args = args.into_values(&self.bytecode);
+
+        let mut machine = Machine::new(&self.bytecode);
+        *machine.host() = self.host.clone();
+
+        let result = machine.apply(&qualified, args).map_err(|err| Error::Runtime(err.to_string()))?;
+
+        R::from_value(&result, &self.bytecode).ok_or(Error::Conversion)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PRELUDE: &str = "
+pub type Int
+pub type String
+pub type Unit = | Unit
+pub type Bool = | False | True
+
+pub external add : Int -> Int -> Int = \"add\"
+pub external eq : forall a. a -> a -> Bool = \"eq\"
+pub external concat : String -> String -> String = \"concat\"
+";
+
+    #[test]
+    fn calls_functions_of_scripts() {
+        let mut engine = Engine::new();
+        engine.add_module("Prelude", PRELUDE);
+        engine.register1("square", |x: i64| x * x);
+
+        let module = engine
+            .compile(
+                "Script",
+                "use Prelude
+
+external square : Int -> Int = \"square\"
+
+pub let double (x: Int) : Int = x + x
+
+pub let isZero (x: Int) : Bool = x == 0
+
+pub let greet (name: String) : String = \"hello \" ++ name
+
+pub let area (x: Int) : Int = square x
+",
+            )
+            .unwrap();
+
+        assert_eq!(module.call::<i64>("double", (21,)).unwrap(), 42);
+        assert!(module.call::<bool>("isZero", (0,)).unwrap());
+        assert!(!module.call::<bool>("Script.Main.isZero", (1,)).unwrap());
+        assert_eq!(
+            module.call::<String>("greet", ("vulpi",)).unwrap(),
+            "hello vulpi"
+        );
+        assert_eq!(module.call::<i64>("area", (7,)).unwrap(), 49);
+        assert_eq!(module.call::<i64>("Prelude.add", (1, 2)).unwrap(), 3);
+        assert!(matches!(
+            module.call::<i64>("missing", ()),
+            Err(Error::UnknownFunction(_))
+        ));
+        assert!(matches!(
+            module.call::<String>("double", (1,)),
+            Err(Error::Conversion)
+        ));
+    }
+
+    #[test]
+    fn reports_errors_of_scripts() {
+        let mut engine = Engine::new();
+        engine.add_module("Prelude", PRELUDE);
+
+        let result = engine.compile("Script", "use Prelude\n\npub let wrong : Int = \"a\"\n");
+        assert!(matches!(result, Err(Error::Compile(_))));
+    }
+}
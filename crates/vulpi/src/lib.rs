@@ -0,0 +1,20 @@
+//! Vulpi as a library. An [Engine] compiles the source of a script to a [Module] that runs in the
+//! virtual machine, and the functions of the module can be called with Rust values that are
+//! converted by [IntoValue] and [FromValue]. A [Workspace] checks a project on disk for the tools
+//! that only read the code.
+//!
+//! ```ignore
+//! let mut engine = Engine::new();
+//! engine.add_module("Prelude", prelude);
+//!
+//! let module = engine.compile("Script", "use Prelude\npub let double (x: Int) : Int = x + x")?;
+//! let result: i64 = module.call("double", (21,))?;
+//! ```
+
+pub mod convert;
+pub mod engine;
+pub mod workspace;
+
+pub use convert::{Args, FromValue, HostValue, IntoValue};
+pub use engine::{Engine, Error, Module, Overflow};
+pub use workspace::{Declaration, Diagnostic, Severity, TypedModule, Workspace};
@@ -0,0 +1,212 @@
+//! Checking of the projects on disk, for tools that read Vulpi code without running it, like
+//! linters and documentation sites. A [Workspace] keeps the results of the compiler between calls,
+//! and what it returns is made of plain strings and positions, so the tools don't depend on the
+//! internals of the compiler.
+
+use std::path::{Path as FilePath, PathBuf};
+
+use vulpi_build::{cfg::Target, query::Database, real::RealFileSystem};
+use vulpi_intern::Symbol;
+use vulpi_location::{Encoding, LineIndex};
+use vulpi_report::{IntoDiagnostic, Severity as ReportSeverity};
+use vulpi_typer::Env;
+use vulpi_vfs::{path::Path, FileSystem};
+
+use crate::engine::Error;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Severity {
+    Error,
+    Warning,
+    Note,
+    Help,
+}
+
+/// A diagnostic of the compiler with the position where it starts. Lines and columns start at one
+/// and columns count characters.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    pub code: Option<usize>,
+    pub message: String,
+    pub file: PathBuf,
+    pub line: usize,
+    pub column: usize,
+}
+
+/// A let declaration of a typed module with its type as it's written in the source.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Declaration {
+    pub name: String,
+    pub typ: String,
+}
+
+/// A module of the workspace after it's type checked.
+#[derive(Clone, Debug)]
+pub struct TypedModule {
+    pub path: String,
+
+    /// The let declarations of the module.
+    pub declarations: Vec<Declaration>,
+}
+
+/// A project whose modules are read from the disk.
+pub struct Workspace {
+    db: Database<RealFileSystem>,
+}
+
+impl Workspace {
+    /// Loads the project of a directory, whose root module is `src/Main.vp`, or of a single file,
+    /// whose directory has the other modules. The name of the crate is the one of the directory or
+    /// of the file.
+    pub fn load(path: impl AsRef<FilePath>) -> Result<Self, Error> {
+        let path = path.as_ref();
+
+        let path = path
+            .canonicalize()
+            .map_err(|_| Error::Load(format!("cannot find '{}'", path.display())))?;
+
+        let (directory, sources, root) = if path.is_dir() {
+            (path.clone(), path.join("src"), PathBuf::from("Main.vp"))
+        } else {
+            let directory = path.parent().map(FilePath::to_path_buf).unwrap_or_default();
+            let root = PathBuf::from(path.file_name().unwrap());
+            (directory.clone(), directory, root)
+        };
+
+        if !sources.join(&root).is_file() {
+            let root = sources.join(&root);
+            let message = format!("cannot find the root module '{}'", root.display());
+            return Err(Error::Load(message));
+        }
+
+        let stem = if path.is_dir() {
+            path.file_name()
+        } else {
+            path.file_stem()
+        };
+
+        // Paths are made of upper case identifiers, so the name of the crate starts with one.
+        let mut chars = stem.and_then(|x| x.to_str()).unwrap_or("Main").chars();
+        let name = chars
+            .next()
+            .map_or_else(String::new, |x| x.to_uppercase().chain(chars).collect());
+
+        let name = Symbol::intern(&name);
+        let fs = RealFileSystem::new(name.clone(), sources.clone(), directory.join("build"));
+
+        Ok(Self {
+            db: Database::new(fs, name, sources.join(root), Target::default()),
+        })
+    }
+
+    /// Type checks the modules of the project. It's true when none of them has errors.
+    pub fn check(&mut self) -> bool {
+        self.diagnostics()
+            .iter()
+            .all(|x| x.severity != Severity::Error)
+    }
+
+    /// The diagnostics of the modules of the project, sorted by their positions. Modules that
+    /// didn't change since the last call are not checked again.
+    pub fn diagnostics(&mut self) -> Vec<Diagnostic> {
+        let mut diagnostics: Vec<_> = self
+            .db
+            .diagnostics()
+            .iter()
+            .map(|diagnostic| {
+                let span = diagnostic.location();
+                let file = self.db.fs.path(span.file).cloned().unwrap_or_default();
+                let source = self.db.fs.read(span.file).unwrap_or_default();
+
+                let (line, column) = LineIndex::new(&source)
+                    .line_col(&span.start, Encoding::Utf32)
+                    .map(|x| (x.line + 1, x.column + 1))
+                    .unwrap_or_default();
+
+                let severity = match diagnostic.severity() {
+                    ReportSeverity::Error => Severity::Error,
+                    ReportSeverity::Warning => Severity::Warning,
+                    ReportSeverity::Note => Severity::Note,
+                    ReportSeverity::Help => Severity::Help,
+                };
+
+                Diagnostic {
+                    severity,
+                    code: diagnostic.code(),
+                    message: diagnostic.message().plain(),
+                    file,
+                    line,
+                    column,
+                }
+            })
+            .collect();
+
+        diagnostics.sort_by(|x, y| (&x.file, x.line, x.column).cmp(&(&y.file, y.line, y.column)));
+        diagnostics
+    }
+
+    /// A module of the project after it's type checked, by its path inside of the crate, like
+    /// `Main` or `Data.List`. It's none when the project has no file for it.
+    pub fn typed_module(&mut self, path: &str) -> Option<TypedModule> {
+        let mut segments = vec![self.db.name.clone()];
+        segments.extend(path.split('.').map(Symbol::intern));
+
+        let module = Path { segments };
+
+        if !self.db.modules().contains(&module) {
+            return None;
+        }
+
+        let typed = self.db.typed(&module);
+
+        let declarations = typed
+            .lets
+            .iter()
+            .map(|(name, decl)| Declaration {
+                name: name.name.get(),
+                typ: decl.typ.show(&Env::default()).to_string(),
+            })
+            .collect();
+
+        Some(TypedModule {
+            path: path.to_string(),
+            declarations,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn checks_projects_on_disk() {
+        let directory = std::env::temp_dir().join(format!("vulpi-{}", std::process::id()));
+        let sources = directory.join("src");
+        std::fs::create_dir_all(&sources).unwrap();
+
+        std::fs::write(
+            sources.join("Main.vp"),
+            "use Prelude\n\npub let double (x : Int) : Int = add x x\n\nlet wrong : Int = \"no\"\n",
+        )
+        .unwrap();
+
+        let mut workspace = Workspace::load(&directory).unwrap();
+
+        assert!(!workspace.check());
+
+        let diagnostics = workspace.diagnostics();
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(diagnostics[0].severity, Severity::Error);
+        assert_eq!((diagnostics[0].line, diagnostics[0].column), (5, 19));
+
+        let module = workspace.typed_module("Main").unwrap();
+        let double = module.declarations.iter().find(|x| x.name == "double");
+        assert_eq!(double.map(|x| x.typ.as_str()), Some("Int -> Int"));
+
+        assert!(workspace.typed_module("Missing").is_none());
+
+        std::fs::remove_dir_all(&directory).unwrap();
+    }
+}